@@ -0,0 +1,260 @@
+//! Account management for the sandbox servers.
+//!
+//! The `ws_admin` binary works on the database that the servers are configured
+//! with: it creates accounts and changes their password, computing the SRP6a
+//! verifiers of `ws_auth`, bans and unbans them, and lists the sessions of the
+//! world servers. Sessions are closed over the `ws_cluster` bus, which the
+//! tool joins as [`ws_cluster::Node::Admin`].
+
+use std::fmt;
+use std::time::Duration;
+
+use uuid::Uuid;
+use ws_auth::{normalize_login, Srp6Verifier};
+use ws_cluster::{ClusterError, ClusterMessage, ClusterNode, Target};
+use ws_db::{Account, Database, DbError, Session};
+
+/// How long the world servers are given to answer a [`close_session`].
+pub const CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+pub enum AdminError {
+    Io(std::io::Error),
+    Db(DbError),
+    Cluster(ClusterError),
+    /// Sessions can't be closed without a cluster to reach the world servers.
+    NoCluster,
+    /// An account name is empty or has whitespace.
+    InvalidName(String),
+    EmptyPassword,
+    AccountExists(String),
+    AccountNotFound(String),
+}
+
+impl fmt::Display for AdminError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Db(error) => write!(f, "database error: {error}"),
+            Self::Cluster(error) => write!(f, "cluster error: {error}"),
+            Self::NoCluster => write!(f, "no cluster is configured"),
+            Self::InvalidName(name) => write!(f, "invalid account name {name:?}"),
+            Self::EmptyPassword => write!(f, "the password is empty"),
+            Self::AccountExists(name) => write!(f, "account {name} already exists"),
+            Self::AccountNotFound(name) => write!(f, "account {name} doesn't exist"),
+        }
+    }
+}
+
+impl std::error::Error for AdminError {}
+
+impl From<std::io::Error> for AdminError {
+    fn from(error: std::io::Error) -> Self {
+        AdminError::Io(error)
+    }
+}
+
+impl From<DbError> for AdminError {
+    fn from(error: DbError) -> Self {
+        AdminError::Db(error)
+    }
+}
+
+impl From<ClusterError> for AdminError {
+    fn from(error: ClusterError) -> Self {
+        AdminError::Cluster(error)
+    }
+}
+
+pub type AdminResult<T = ()> = Result<T, AdminError>;
+
+/// Creates an account with the verifier of its password. The name is stored
+/// normalised, see [`normalize_login`].
+pub async fn create_account(
+    database: &Database,
+    name: &str,
+    password: &str,
+) -> AdminResult<Account> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(AdminError::InvalidName(name.to_string()));
+    }
+    if password.is_empty() {
+        return Err(AdminError::EmptyPassword);
+    }
+    let accounts = database.accounts();
+    // names are unique regardless of case
+    if accounts.find_by_name(name).await?.is_some() {
+        return Err(AdminError::AccountExists(name.to_string()));
+    }
+    // the verifier is computed from the name as stored
+    let name = normalize_login(name);
+    let verifier = Srp6Verifier::generate(&name, password);
+    Ok(accounts
+        .create(&name, &verifier.salt, &verifier.verifier)
+        .await?)
+}
+
+/// Replaces the verifier of an account with one for a new password, with a
+/// new salt.
+pub async fn set_password(database: &Database, name: &str, password: &str) -> AdminResult<Account> {
+    if password.is_empty() {
+        return Err(AdminError::EmptyPassword);
+    }
+    let account = find_account(database, name).await?;
+    let verifier = Srp6Verifier::generate(&account.name, password);
+    database
+        .accounts()
+        .set_password(account.id, &verifier.salt, &verifier.verifier)
+        .await?;
+    Ok(Account {
+        salt: verifier.salt,
+        verifier: verifier.verifier,
+        ..account
+    })
+}
+
+/// Bans or unbans an account. The sessions it already has aren't closed, see
+/// [`close_session`].
+pub async fn set_banned(database: &Database, name: &str, banned: bool) -> AdminResult<Account> {
+    let account = find_account(database, name).await?;
+    database.accounts().set_banned(account.id, banned).await?;
+    Ok(Account { banned, ..account })
+}
+
+/// Lists the sessions of the world servers, with their account, the oldest
+/// first.
+pub async fn list_sessions(database: &Database) -> AdminResult<Vec<(Session, Account)>> {
+    let mut sessions = Vec::new();
+    for session in database.sessions().list().await? {
+        // the account can be deleted in between
+        if let Some(account) = database.accounts().find(session.account_id).await? {
+            sessions.push((session, account));
+        }
+    }
+    Ok(sessions)
+}
+
+/// Asks the world servers to close a session, returning whether one of them
+/// did before the timeout.
+pub async fn close_session(
+    node: &mut ClusterNode,
+    key: Uuid,
+    timeout: Duration,
+) -> AdminResult<bool> {
+    node.send(Target::Broadcast, ClusterMessage::CloseSession { key })?;
+    let closed = async {
+        while let Some(envelope) = node.recv().await {
+            if envelope.message == (ClusterMessage::SessionClosed { key }) {
+                return Ok(true);
+            }
+        }
+        Err(AdminError::Cluster(ClusterError::Closed))
+    };
+    tokio::time::timeout(timeout, closed)
+        .await
+        .unwrap_or(Ok(false))
+}
+
+async fn find_account(database: &Database, name: &str) -> AdminResult<Account> {
+    database
+        .accounts()
+        .find_by_name(name)
+        .await?
+        .ok_or_else(|| AdminError::AccountNotFound(name.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_auth::{Srp6Client, Srp6Server};
+    use ws_cluster::{ClusterHub, Node};
+
+    use super::*;
+
+    /// Runs the SRP6a exchange against the verifier of an account, with the
+    /// client normalising the login.
+    fn log_in(account: &Account, login: &str, password: &str) -> bool {
+        let verifier = Srp6Verifier {
+            salt: account.salt.clone(),
+            verifier: account.verifier.clone(),
+        };
+        let server = Srp6Server::new(&account.name, &verifier);
+        let client = Srp6Client::new();
+        let (proof, _) = client
+            .process(
+                &normalize_login(login),
+                password,
+                server.salt(),
+                &server.public_key(),
+            )
+            .unwrap();
+        server.verify(&client.public_key(), &proof).is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_accounts() {
+        let database = Database::in_memory().await.unwrap();
+        let account = create_account(&database, "Alice", "hunter2").await.unwrap();
+        assert_eq!(account.name, "alice");
+        assert!(log_in(&account, "alice", "hunter2"));
+        assert!(!log_in(&account, "alice", "hunter3"));
+        assert!(matches!(
+            create_account(&database, "ALICE", "other").await,
+            Err(AdminError::AccountExists(_))
+        ));
+        assert!(matches!(
+            create_account(&database, "Bob Smith", "other").await,
+            Err(AdminError::InvalidName(_))
+        ));
+        assert!(matches!(
+            create_account(&database, "Bob", "").await,
+            Err(AdminError::EmptyPassword)
+        ));
+
+        let changed = set_password(&database, "alice", "hunter3").await.unwrap();
+        assert_ne!(changed.salt, account.salt);
+        let found = database.accounts().find(account.id).await.unwrap().unwrap();
+        assert_eq!(found, changed);
+        assert!(log_in(&found, "Alice", "hunter3"));
+        assert!(!log_in(&found, "Alice", "hunter2"));
+
+        assert!(set_banned(&database, "alice", true).await.unwrap().banned);
+        let found = database.accounts().find(account.id).await.unwrap().unwrap();
+        assert!(found.banned);
+        assert!(!set_banned(&database, "alice", false).await.unwrap().banned);
+        assert!(matches!(
+            set_banned(&database, "Bob", true).await,
+            Err(AdminError::AccountNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sessions() {
+        let database = Database::in_memory().await.unwrap();
+        let account = create_account(&database, "Alice", "hunter2").await.unwrap();
+        let session = database.sessions().create(account.id).await.unwrap();
+        let sessions = list_sessions(&database).await.unwrap();
+        assert_eq!(sessions, [(session.clone(), account)]);
+
+        // a world server holding the session answers
+        let hub = ClusterHub::bind("127.0.0.1:0").await.unwrap();
+        let mut world = hub.join(Node::World(1));
+        let mut admin = hub.join(Node::Admin);
+        tokio::spawn(async move {
+            while let Some(envelope) = world.recv().await {
+                if let ClusterMessage::CloseSession { key } = envelope.message {
+                    if key == session.key {
+                        let closed = ClusterMessage::SessionClosed { key };
+                        world.send_to(envelope.from, closed).unwrap();
+                    }
+                }
+            }
+        });
+        let timeout = Duration::from_millis(200);
+        assert!(close_session(&mut admin, session.key, timeout)
+            .await
+            .unwrap());
+        assert!(!close_session(&mut admin, Uuid::nil(), timeout)
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,187 @@
+use std::io::BufRead;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use uuid::Uuid;
+use ws_admin::*;
+use ws_cluster::{ClusterNode, Node};
+use ws_config::Config;
+use ws_db::Database;
+
+const USAGE: &str = "Usage:
+  ws_admin [--config <sandbox.toml>] <command>
+
+Commands:
+  create <account> [password]
+  set-password <account> [password]
+  ban <account>
+  unban <account>
+  sessions
+  disconnect <session key>
+
+The password is read from the standard input when it isn't given.";
+
+#[derive(Debug)]
+enum Command {
+    Create {
+        name: String,
+        password: Option<String>,
+    },
+    SetPassword {
+        name: String,
+        password: Option<String>,
+    },
+    Ban {
+        name: String,
+        banned: bool,
+    },
+    Sessions,
+    Disconnect {
+        key: Uuid,
+    },
+}
+
+/// Parses the arguments after the program name, returning the path of the
+/// configuration, if any, and the command.
+fn parse_args(args: impl Iterator<Item = String>) -> Option<(Option<PathBuf>, Command)> {
+    let mut args = args.peekable();
+    let mut config = None;
+    if args.peek().map(String::as_str) == Some("--config") {
+        args.next();
+        config = Some(PathBuf::from(args.next()?));
+    }
+    let command = match args.next()?.as_str() {
+        "create" => Command::Create {
+            name: args.next()?,
+            password: args.next(),
+        },
+        "set-password" => Command::SetPassword {
+            name: args.next()?,
+            password: args.next(),
+        },
+        "ban" => Command::Ban {
+            name: args.next()?,
+            banned: true,
+        },
+        "unban" => Command::Ban {
+            name: args.next()?,
+            banned: false,
+        },
+        "sessions" => Command::Sessions,
+        "disconnect" => Command::Disconnect {
+            key: args.next()?.parse().ok()?,
+        },
+        _ => return None,
+    };
+    // every argument has to be used
+    match args.next() {
+        Some(_) => None,
+        None => Some((config, command)),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> ExitCode {
+    let (path, command) = match parse_args(std::env::args().skip(1)) {
+        Some(args) => args,
+        None => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let config = match Config::load(path.as_deref()) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("Failed to load the configuration: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match run(&config, command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run(config: &Config, command: Command) -> AdminResult {
+    let database = Database::connect(&config.database_url).await?;
+    match command {
+        Command::Create { name, password } => {
+            let password = password.map_or_else(read_password, Ok)?;
+            let account = create_account(&database, &name, &password).await?;
+            println!("Created account {} ({})", account.name, account.id);
+        }
+        Command::SetPassword { name, password } => {
+            let password = password.map_or_else(read_password, Ok)?;
+            let account = ws_admin::set_password(&database, &name, &password).await?;
+            println!("Changed the password of {}", account.name);
+        }
+        Command::Ban { name, banned: true } => {
+            let account = set_banned(&database, &name, true).await?;
+            println!("Banned {}", account.name);
+            let sessions = database.sessions().list_by_account(account.id).await?;
+            if sessions.is_empty() {
+                return Ok(());
+            }
+            let mut node = match connect(config).await {
+                Ok(node) => node,
+                Err(AdminError::NoCluster) => {
+                    println!("No cluster is configured, its sessions stay open");
+                    return Ok(());
+                }
+                Err(error) => return Err(error),
+            };
+            for session in sessions {
+                disconnect(&mut node, session.key).await?;
+            }
+        }
+        Command::Ban {
+            name,
+            banned: false,
+        } => {
+            let account = set_banned(&database, &name, false).await?;
+            println!("Unbanned {}", account.name);
+        }
+        Command::Sessions => {
+            println!(
+                "{:<36}  {:>10}  {:<20}  created at",
+                "key", "account", "name"
+            );
+            for (session, account) in list_sessions(&database).await? {
+                println!(
+                    "{:<36}  {:>10}  {:<20}  {}",
+                    session.key, account.id, account.name, session.created_at
+                );
+            }
+        }
+        Command::Disconnect { key } => {
+            let mut node = connect(config).await?;
+            disconnect(&mut node, key).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn connect(config: &Config) -> AdminResult<ClusterNode> {
+    if config.cluster.addr.is_empty() {
+        return Err(AdminError::NoCluster);
+    }
+    Ok(ClusterNode::connect(&config.cluster.addr, Node::Admin).await?)
+}
+
+async fn disconnect(node: &mut ClusterNode, key: Uuid) -> AdminResult {
+    match close_session(node, key, CLOSE_TIMEOUT).await? {
+        true => println!("Closed session {key}"),
+        false => println!("No world server closed session {key}"),
+    }
+    Ok(())
+}
+
+/// Reads the password from the first line of the standard input.
+fn read_password() -> AdminResult<String> {
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    Ok(password.trim_end_matches(['\r', '\n']).to_string())
+}
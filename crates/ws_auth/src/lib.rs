@@ -0,0 +1,45 @@
+//! The SRP6a password verifiers that accounts are authenticated with.
+//!
+//! The server never sees the passwords: an account keeps a random salt and a
+//! verifier computed from it, and the client proves that it knows the password
+//! with an exchange that also agrees on a session key. The variant used by
+//! WildStar is SRP6a with SHA-256 as the hash and the 1024-bit group of RFC
+//! 5054 (`g = 2`), where:
+//!
+//! - `k = H(N | PAD(g))` and `x = H(s | H(I | ":" | P))`
+//! - `K = H(S)`, the session key
+//! - `M1 = H(H(N) xor H(g) | H(I) | s | A | B | K)`, the client proof
+//! - `M2 = H(A | M1 | K)`, the server proof
+//!
+//! The identity `I` is the login exactly as given. Account names are
+//! normalised with [`normalize_login`] when the accounts are created, and the
+//! server runs the exchange with the name as stored, so a verifier only has to
+//! match the name it was computed from.
+
+mod srp;
+
+pub use srp::*;
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// The public key sent by the other side is a multiple of N.
+    InvalidPublicKey,
+    /// The proof sent by the other side doesn't match, usually because of a
+    /// wrong password.
+    InvalidProof,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPublicKey => write!(f, "invalid SRP6 public key"),
+            Self::InvalidProof => write!(f, "invalid SRP6 proof"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+pub type AuthResult<T = ()> = Result<T, AuthError>;
@@ -0,0 +1,380 @@
+use num_bigint::BigUint;
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use crate::{AuthError, AuthResult};
+
+/// The 1024-bit group from RFC 5054, appendix A.
+///
+/// The exchange uses this group with SHA-256 for every hash (k, u, x, K, M1 and
+/// M2), as laid out in RFC 5054 apart from the hash. [`Srp6Client`] makes the
+/// same choices, but they haven't been checked against the real WildStar
+/// client yet: a recorded `/Auth/LoginStart` and `/Auth/KeyData` exchange for a
+/// known password would give a test vector to confirm them.
+const N_HEX: &[u8] = b"EEAF0AB9ADB38DD69C33F80AFA8FC5E86072618775FF3C0B9EA2314C\
+    9C256576D674DF7496EA81D3383B4813D692C6E0E0D5D8E250B98BE4\
+    8E495C1D6089DAD15DC7D7B46154D6B6CE8EF4AD69B15D4982559B29\
+    7BCF1885C529F566660E57EC68EDBC3C05726CC02FD4CBF4976EAA9A\
+    FD5138FE8376435B9FC61D2FC0EB06E3";
+const G: u32 = 2;
+
+/// The length of the salts generated by [`Srp6Verifier::generate`].
+pub const SALT_LENGTH: usize = 16;
+
+/// The SRP6a parameters: a safe prime N and a generator g.
+#[derive(Debug, Clone)]
+struct Group {
+    n: BigUint,
+    g: BigUint,
+    /// The multiplier parameter, `H(N | PAD(g))`.
+    k: BigUint,
+}
+
+impl Group {
+    fn get() -> Self {
+        let n = BigUint::parse_bytes(N_HEX, 16).expect("Invalid SRP6 prime");
+        let g = BigUint::from(G);
+        let k = BigUint::from_bytes_be(&hash(&[&n.to_bytes_be(), &pad(&g, &n)]));
+        Self { n, g, k }
+    }
+
+    fn is_valid_public(&self, key: &BigUint) -> bool {
+        key % &self.n != BigUint::default()
+    }
+}
+
+fn hash(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+/// Returns the big-endian bytes of `value`, left-padded to the length of N.
+fn pad(value: &BigUint, n: &BigUint) -> Vec<u8> {
+    let length = n.to_bytes_be().len();
+    let bytes = value.to_bytes_be();
+    let mut padded = vec![0; length.saturating_sub(bytes.len())];
+    padded.extend_from_slice(&bytes);
+    padded
+}
+
+fn random_secret() -> BigUint {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BigUint::from_bytes_be(&bytes)
+}
+
+/// Returns the name that an account is created with for a login, in
+/// lowercase, which is also the identity I of the key exchange.
+///
+/// Account names are normalised once, when the account is created, so that
+/// the verifier is computed from the name as stored. The key exchange then
+/// uses the login exactly as given: the server passes the stored name, and
+/// the client has to normalise what the player typed the same way.
+pub fn normalize_login(login: &str) -> String {
+    login.to_lowercase()
+}
+
+/// `x = H(s | H(I | ":" | P))`
+fn private_key(login: &str, password: &str, salt: &[u8]) -> BigUint {
+    let identity = hash(&[login.as_bytes(), b":", password.as_bytes()]);
+    BigUint::from_bytes_be(&hash(&[salt, &identity]))
+}
+
+/// `u = H(PAD(A) | PAD(B))`
+fn scrambler(group: &Group, a: &BigUint, b: &BigUint) -> BigUint {
+    BigUint::from_bytes_be(&hash(&[&pad(a, &group.n), &pad(b, &group.n)]))
+}
+
+/// `M1 = H(H(N) xor H(g) | H(I) | s | A | B | K)`
+fn client_proof(
+    group: &Group,
+    login: &str,
+    salt: &[u8],
+    a: &BigUint,
+    b: &BigUint,
+    key: &[u8],
+) -> Vec<u8> {
+    let hash_n = hash(&[&group.n.to_bytes_be()]);
+    let hash_g = hash(&[&group.g.to_bytes_be()]);
+    let xor = hash_n
+        .iter()
+        .zip(&hash_g)
+        .map(|(n, g)| n ^ g)
+        .collect::<Vec<_>>();
+    let hash_login = hash(&[login.as_bytes()]);
+    hash(&[
+        &xor,
+        &hash_login,
+        salt,
+        &a.to_bytes_be(),
+        &b.to_bytes_be(),
+        key,
+    ])
+}
+
+/// `M2 = H(A | M1 | K)`
+fn server_proof(a: &BigUint, client_proof: &[u8], key: &[u8]) -> Vec<u8> {
+    hash(&[&a.to_bytes_be(), client_proof, key])
+}
+
+/// What the server stores instead of an account's password.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Srp6Verifier {
+    pub salt: Vec<u8>,
+    /// The password verifier `v = g^x`, in big-endian bytes.
+    pub verifier: Vec<u8>,
+}
+
+impl Srp6Verifier {
+    /// Creates a verifier with a random salt, such as when an account is created.
+    pub fn generate(login: &str, password: &str) -> Self {
+        let mut salt = vec![0u8; SALT_LENGTH];
+        rand::thread_rng().fill_bytes(&mut salt);
+        Self::with_salt(login, password, salt)
+    }
+
+    pub fn with_salt(login: &str, password: &str, salt: Vec<u8>) -> Self {
+        let group = Group::get();
+        let x = private_key(login, password, &salt);
+        let verifier = group.g.modpow(&x, &group.n).to_bytes_be();
+        Self { salt, verifier }
+    }
+
+    /// Creates a verifier for a login without an account, so that the
+    /// challenge sent for it looks like the one of an account. The salt is
+    /// derived from the login and a server secret, which keeps it the same
+    /// at every attempt, and no password matches the verifier.
+    pub fn fake(login: &str, secret: &[u8]) -> Self {
+        let group = Group::get();
+        let salt = hash(&[secret, b":salt:", login.as_bytes()])[..SALT_LENGTH].to_vec();
+        let x = BigUint::from_bytes_be(&hash(&[secret, b":verifier:", login.as_bytes()]));
+        let verifier = group.g.modpow(&x, &group.n).to_bytes_be();
+        Self { salt, verifier }
+    }
+}
+
+/// The server side of the key exchange.
+///
+/// The server sends the salt and [`public_key`](Self::public_key) to the
+/// client, such as in response to `/Auth/LoginStart`, then checks the client's
+/// public key and proof, such as sent with `/Auth/KeyData`, using
+/// [`verify`](Self::verify).
+#[derive(Debug)]
+pub struct Srp6Server {
+    group: Group,
+    login: String,
+    salt: Vec<u8>,
+    verifier: BigUint,
+    secret: BigUint,
+    public: BigUint,
+}
+
+impl Srp6Server {
+    pub fn new(login: &str, verifier: &Srp6Verifier) -> Self {
+        let group = Group::get();
+        let secret = random_secret();
+        let v = BigUint::from_bytes_be(&verifier.verifier);
+        // B = k*v + g^b
+        let public = (&group.k * &v + group.g.modpow(&secret, &group.n)) % &group.n;
+        Self {
+            group,
+            login: login.to_string(),
+            salt: verifier.salt.clone(),
+            verifier: v,
+            secret,
+            public,
+        }
+    }
+
+    pub fn salt(&self) -> &[u8] {
+        &self.salt
+    }
+
+    /// Returns the server's public key B, in big-endian bytes.
+    pub fn public_key(&self) -> Vec<u8> {
+        self.public.to_bytes_be()
+    }
+
+    /// Checks the client's public key A and proof M1.
+    ///
+    /// On success, returns the session key along with the server proof M2 that
+    /// is sent back to the client.
+    pub fn verify(&self, client_public: &[u8], proof: &[u8]) -> AuthResult<SessionKeys> {
+        let group = &self.group;
+        let a = BigUint::from_bytes_be(client_public);
+        if !group.is_valid_public(&a) {
+            return Err(AuthError::InvalidPublicKey);
+        }
+
+        // S = (A * v^u)^b
+        let u = scrambler(group, &a, &self.public);
+        let base = (&a * self.verifier.modpow(&u, &group.n)) % &group.n;
+        let shared = base.modpow(&self.secret, &group.n);
+        let session_key = hash(&[&shared.to_bytes_be()]);
+
+        let expected = client_proof(
+            group,
+            &self.login,
+            &self.salt,
+            &a,
+            &self.public,
+            &session_key,
+        );
+        // compared in constant time so that the time taken doesn't tell how
+        // much of a guessed proof is right
+        if !bool::from(expected.ct_eq(proof)) {
+            return Err(AuthError::InvalidProof);
+        }
+
+        Ok(SessionKeys {
+            server_proof: server_proof(&a, proof, &session_key),
+            session_key,
+        })
+    }
+}
+
+/// The result of a successful key exchange.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionKeys {
+    /// The key shared by both sides, used to key the game connection.
+    pub session_key: Vec<u8>,
+    /// The proof that the server knows the verifier, M2.
+    pub server_proof: Vec<u8>,
+}
+
+/// The client side of the key exchange, for tools and tests.
+#[derive(Debug)]
+pub struct Srp6Client {
+    group: Group,
+    secret: BigUint,
+    public: BigUint,
+}
+
+impl Srp6Client {
+    pub fn new() -> Self {
+        let group = Group::get();
+        let secret = random_secret();
+        let public = group.g.modpow(&secret, &group.n);
+        Self {
+            group,
+            secret,
+            public,
+        }
+    }
+
+    /// Returns the client's public key A, in big-endian bytes.
+    pub fn public_key(&self) -> Vec<u8> {
+        self.public.to_bytes_be()
+    }
+
+    /// Computes the session key and client proof M1 from the server's salt and
+    /// public key B.
+    ///
+    /// The returned [`SessionKeys`] holds the server proof that the client
+    /// should expect in return.
+    pub fn process(
+        &self,
+        login: &str,
+        password: &str,
+        salt: &[u8],
+        server_public: &[u8],
+    ) -> AuthResult<(Vec<u8>, SessionKeys)> {
+        let group = &self.group;
+        let b = BigUint::from_bytes_be(server_public);
+        if !group.is_valid_public(&b) {
+            return Err(AuthError::InvalidPublicKey);
+        }
+
+        // S = (B - k*g^x)^(a + u*x)
+        let u = scrambler(group, &self.public, &b);
+        let x = private_key(login, password, salt);
+        let kgx = (&group.k * group.g.modpow(&x, &group.n)) % &group.n;
+        let base = (&b + &group.n - kgx) % &group.n;
+        let shared = base.modpow(&(&self.secret + &u * &x), &group.n);
+        let session_key = hash(&[&shared.to_bytes_be()]);
+
+        let proof = client_proof(group, login, salt, &self.public, &b, &session_key);
+        let keys = SessionKeys {
+            server_proof: server_proof(&self.public, &proof, &session_key),
+            session_key,
+        };
+        Ok((proof, keys))
+    }
+}
+
+impl Default for Srp6Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verifier() {
+        let group = Group::get();
+        assert_eq!(group.n.bits(), 1024);
+
+        let verifier = Srp6Verifier::with_salt("alice", "password123", vec![1; 16]);
+        assert!(BigUint::from_bytes_be(&verifier.verifier) < group.n);
+        let other = Srp6Verifier::with_salt("alice", "password123", vec![1; 16]);
+        assert_eq!(verifier, other);
+        // the login is used as given, the account names are normalised
+        let other = Srp6Verifier::with_salt("Alice", "password123", vec![1; 16]);
+        assert_ne!(verifier, other);
+        assert_eq!(normalize_login("Alice"), "alice");
+        let other = Srp6Verifier::with_salt("alice", "Password123", vec![1; 16]);
+        assert_ne!(verifier, other);
+        let other = Srp6Verifier::with_salt("alice", "password123", vec![2; 16]);
+        assert_ne!(verifier.verifier, other.verifier);
+    }
+
+    #[test]
+    fn test_fake_verifier() {
+        let fake = Srp6Verifier::fake("nobody", b"secret");
+        assert_eq!(fake.salt.len(), SALT_LENGTH);
+        assert_eq!(fake, Srp6Verifier::fake("nobody", b"secret"));
+        assert_ne!(fake.salt, Srp6Verifier::fake("somebody", b"secret").salt);
+        assert_ne!(fake.salt, Srp6Verifier::fake("nobody", b"other").salt);
+
+        // no password is accepted
+        let server = Srp6Server::new("nobody", &fake);
+        let client = Srp6Client::new();
+        let (proof, _) = client
+            .process("nobody", "", server.salt(), &server.public_key())
+            .unwrap();
+        assert!(server.verify(&client.public_key(), &proof).is_err());
+    }
+
+    #[test]
+    fn test_key_exchange() {
+        let verifier = Srp6Verifier::generate("alice", "password123");
+        let server = Srp6Server::new("alice", &verifier);
+        let client = Srp6Client::new();
+
+        let (proof, client_keys) = client
+            .process("alice", "password123", server.salt(), &server.public_key())
+            .unwrap();
+        let server_keys = server.verify(&client.public_key(), &proof).unwrap();
+        assert_eq!(server_keys, client_keys);
+        assert_eq!(server_keys.session_key.len(), 32);
+
+        let (proof, _) = client
+            .process("alice", "wrong", server.salt(), &server.public_key())
+            .unwrap();
+        assert!(matches!(
+            server.verify(&client.public_key(), &proof),
+            Err(AuthError::InvalidProof)
+        ));
+        assert!(matches!(
+            server.verify(&[0], &proof),
+            Err(AuthError::InvalidPublicKey)
+        ));
+    }
+}
@@ -0,0 +1,109 @@
+//! Benchmarks of the reader and writer hot paths.
+//!
+//! Run with `cargo bench -p ws_bitpack`, and compare against a baseline with
+//! `--save-baseline` and `--baseline` when changing the reader or writer.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ws_bitpack::*;
+
+/// How many values each iteration reads or writes, so that timings aren't
+/// dominated by setting up the reader or writer.
+const VALUES: usize = 1024;
+
+const WIDTHS: [usize; 6] = [1, 5, 8, 13, 32, 64];
+
+fn mask(bits: usize) -> u64 {
+    match bits {
+        64 => u64::MAX,
+        _ => (1 << bits) - 1,
+    }
+}
+
+fn bench_u64(c: &mut Criterion) {
+    let mut group = c.benchmark_group("u64");
+    for bits in WIDTHS {
+        let values: Vec<u64> = (0..VALUES as u64)
+            .map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15) & mask(bits))
+            .collect();
+        let mut buffer = vec![0u8; (VALUES * bits).div_ceil(8)];
+        group.throughput(Throughput::Bytes(buffer.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("write", bits), &values, |b, values| {
+            b.iter(|| {
+                let mut writer = BitPackWriter::new(&mut buffer);
+                for value in values {
+                    writer.write_u64(*value, bits).unwrap();
+                }
+            })
+        });
+
+        let data = buffer.clone();
+        group.bench_with_input(BenchmarkId::new("read", bits), &data, |b, data| {
+            b.iter(|| {
+                let mut reader = BitPackReader::new(data);
+                for _ in 0..VALUES {
+                    black_box(reader.read_u64(bits).unwrap());
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_bytes(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bytes");
+    let bytes = vec![0xa5u8; 4096];
+    let mut buffer = vec![0u8; bytes.len() + 1];
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    // the reader copies whole bytes when aligned, and goes bit by bit otherwise
+    for offset in [0, 3] {
+        let name = match offset {
+            0 => "aligned",
+            _ => "unaligned",
+        };
+        group.bench_function(BenchmarkId::new("write", name), |b| {
+            b.iter(|| {
+                let mut writer = BitPackWriter::with_position(&mut buffer, offset);
+                writer.write_bytes(&bytes).unwrap();
+            })
+        });
+
+        let data = buffer.clone();
+        let mut read = vec![0u8; bytes.len()];
+        group.bench_function(BenchmarkId::new("read", name), |b| {
+            b.iter(|| {
+                let mut reader = BitPackReader::with_position(&data, offset);
+                reader.read_bytes(&mut read).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_strings(c: &mut Criterion) {
+    let mut group = c.benchmark_group("strings");
+    for length in [16, 1000] {
+        let string: String = "WildStar ".chars().cycle().take(length).collect();
+        let mut writer = BitPackWriter::growable();
+        writer.write(&string).unwrap();
+        let data = writer.finish().unwrap();
+        group.throughput(Throughput::Bytes(data.len() as u64));
+
+        group.bench_with_input(BenchmarkId::new("write", length), &string, |b, string| {
+            let mut buffer = vec![0u8; data.len()];
+            b.iter(|| {
+                let mut writer = BitPackWriter::new(&mut buffer);
+                writer.write(string).unwrap();
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("read", length), &data, |b, data| {
+            b.iter(|| black_box(BitPackReader::new(data).read::<String>().unwrap()))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_u64, bench_bytes, bench_strings);
+criterion_main!(benches);
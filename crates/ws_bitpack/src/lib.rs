@@ -1,15 +1,230 @@
 mod reader;
-mod writer;
+mod stream;
+mod trace;
 mod values;
+mod writer;
 
 pub use reader::*;
-pub use writer::*;
+pub use stream::*;
+pub use trace::*;
 pub use values::*;
+pub use writer::*;
+
+use std::fmt;
 
 #[derive(Debug)]
 pub enum BitPackError {
+    /// A wide string isn't valid UTF-16.
     FromUtf16(std::string::FromUtf16Error),
-    OutOfBounds,
+    /// A character can't be represented as a single UTF-16 code unit.
+    InvalidChar(u32),
+    /// A length prefix is larger than the maximum allowed length.
+    LengthTooLarge { length: usize, max: usize },
+    /// Values are nested deeper than the reader allows.
+    DepthLimitExceeded { max: usize },
+    /// Data was left unread after a value that should span the whole buffer.
+    TrailingData { bits: usize },
+    /// An entry of a map has the same key as an earlier one.
+    DuplicateKey {
+        /// The index of the entry in the map.
+        index: usize,
+    },
+    /// A union selector doesn't match any variant of the union.
+    InvalidUnionVariant {
+        type_name: &'static str,
+        variant: usize,
+    },
+    /// An integer doesn't match any variant of a message enum.
+    InvalidEnumValue {
+        type_name: &'static str,
+        value: i128,
+    },
+    /// A flags value has bits that don't correspond to any of its flags.
+    InvalidFlags {
+        type_name: &'static str,
+        /// The bits that aren't defined.
+        unknown: u64,
+    },
+    /// A value doesn't fit in the number of bits it is written on.
+    ValueTooLarge { bits: usize, value: i128 },
+    /// A value is read or written on more than the 64 bits an integer has.
+    TooManyBits { bits: usize },
+    /// The source of a stream reader failed.
+    Io(std::io::Error),
+    /// A compressed block couldn't be inflated or deflated.
+    Compression(std::io::Error),
+    /// A value goes past the end of the buffer.
+    OutOfBounds {
+        /// The position of the reader or writer, in bits.
+        position: usize,
+        /// How many bits the value needed.
+        needed: usize,
+        /// How many bits were left in the buffer.
+        available: usize,
+    },
+    /// A field of a derived value failed to read.
+    Context {
+        /// The type of the outermost value, like `Message02EE`.
+        type_name: &'static str,
+        /// The fields from that value down to the one that failed.
+        path: Vec<&'static str>,
+        /// The position of the reader when the field failed, in bits.
+        position: usize,
+        error: Box<BitPackError>,
+    },
+}
+
+impl BitPackError {
+    /// Records that this error happened while reading `field` of `type_name`,
+    /// with the reader at `position`.
+    ///
+    /// Adding context to an error that already has some prepends the field to
+    /// its path, so that it goes from the outermost value to the failing field.
+    /// The position stays the one of the innermost field.
+    pub fn with_context(
+        self,
+        type_name: &'static str,
+        field: &'static str,
+        position: usize,
+    ) -> Self {
+        match self {
+            Self::Context {
+                mut path,
+                position,
+                error,
+                ..
+            } => {
+                path.insert(0, field);
+                Self::Context {
+                    type_name,
+                    path,
+                    position,
+                    error,
+                }
+            }
+            error => Self::Context {
+                type_name,
+                path: vec![field],
+                position,
+                error: Box::new(error),
+            },
+        }
+    }
+
+    /// Returns the error without its context.
+    pub fn root(&self) -> &BitPackError {
+        match self {
+            Self::Context { error, .. } => error,
+            error => error,
+        }
+    }
+
+    /// Returns the error without its context.
+    pub fn into_root(self) -> BitPackError {
+        match self {
+            Self::Context { error, .. } => *error,
+            error => error,
+        }
+    }
+
+    /// Returns true if this error shows that the data ends before a value that
+    /// was read from `start`: reading it went out of bounds, and it started in
+    /// the padding of the last byte.
+    ///
+    /// A value written there would either fit in the padding, and then read
+    /// fine, or have needed another byte. The padding alone can't tell whether a
+    /// value was written in it, so values that may be missing are read first and
+    /// only defaulted on this error.
+    pub fn is_end_from(&self, start: usize) -> bool {
+        match self.root() {
+            Self::OutOfBounds {
+                position,
+                available,
+                ..
+            } => (position + available).saturating_sub(start) < 8,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for BitPackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FromUtf16(_) => write!(f, "invalid UTF-16 string"),
+            Self::InvalidChar(c) => write!(f, "invalid character {c:#x}"),
+            Self::LengthTooLarge { length, max } => {
+                write!(f, "length {length} is larger than the maximum of {max}")
+            }
+            Self::DepthLimitExceeded { max } => {
+                write!(f, "values are nested deeper than the maximum of {max}")
+            }
+            Self::TrailingData { bits } => write!(f, "{bits} bits of trailing data"),
+            Self::DuplicateKey { index } => {
+                write!(f, "map entry {index} has the same key as an earlier one")
+            }
+            Self::InvalidUnionVariant { type_name, variant } => {
+                write!(f, "invalid variant {variant} for union {type_name}")
+            }
+            Self::InvalidEnumValue { type_name, value } => {
+                write!(f, "invalid value {value} for enum {type_name}")
+            }
+            Self::InvalidFlags { type_name, unknown } => {
+                write!(f, "undefined bits {unknown:#x} for flags {type_name}")
+            }
+            Self::ValueTooLarge { bits, value } => {
+                write!(f, "value {value} doesn't fit in {bits} bits")
+            }
+            Self::TooManyBits { bits } => write!(f, "{bits} bits is more than 64"),
+            Self::Io(_) => write!(f, "failed to read from the source"),
+            Self::Compression(_) => write!(f, "failed to inflate or deflate a compressed block"),
+            Self::OutOfBounds {
+                position,
+                needed,
+                available,
+            } => write!(
+                f,
+                "out of bounds at bit {position}, {needed} bits needed but {available} available"
+            ),
+            Self::Context {
+                type_name,
+                path,
+                position,
+                error,
+            } => write!(
+                f,
+                "{type_name}.{} @ bit {position}: {error}",
+                path.join(".")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BitPackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::FromUtf16(error) => Some(error),
+            Self::Io(error) | Self::Compression(error) => Some(error),
+            Self::Context { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::string::FromUtf16Error> for BitPackError {
+    fn from(error: std::string::FromUtf16Error) -> Self {
+        Self::FromUtf16(error)
+    }
+}
+
+/// Lets codecs that work with I/O errors return bitpack errors. Errors of the
+/// source are returned as is, the others as [`std::io::ErrorKind::InvalidData`].
+impl From<BitPackError> for std::io::Error {
+    fn from(error: BitPackError) -> Self {
+        match error.into_root() {
+            BitPackError::Io(error) => error,
+            error => std::io::Error::new(std::io::ErrorKind::InvalidData, error),
+        }
+    }
 }
 
 pub type BitPackResult<T = ()> = Result<T, BitPackError>;
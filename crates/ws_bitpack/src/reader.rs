@@ -1,5 +1,6 @@
 use crate::{
     BitPackError, BitPackResult, ReadArrayValue, ReadPackedArrayValue, ReadPackedValue, ReadValue,
+    Trace,
 };
 
 /// A BitPack reader that can be used to read game packets.
@@ -8,24 +9,194 @@ use crate::{
 /// its functions returns an error because the bit stream may be corrupted at that
 /// point.
 ///
-/// This implementation is very inefficient and could be optimized.
+/// Values are read a word at a time and aligned byte runs are copied directly.
 pub struct BitPackReader<'a> {
     /// The buffer from which the reader is reading.
     buffer: &'a [u8],
     /// Represents the position of the reader in bits.
     position: usize,
+    /// How many nested values are currently being read.
+    depth: usize,
+    /// The maximum nesting depth allowed before reads fail.
+    max_depth: usize,
+    /// The maximum length allowed for arrays and strings.
+    max_length: usize,
+    /// Whether missing trailing fields should be tolerated.
+    lenient: bool,
+    /// Whether the data may continue past the end of the buffer.
+    partial: bool,
+    /// The spans read so far, when tracing.
+    #[cfg(feature = "trace")]
+    trace: Option<Trace>,
 }
 
 impl<'a> BitPackReader<'a> {
+    /// The default maximum nesting depth of a reader.
+    pub const DEFAULT_MAX_DEPTH: usize = 32;
+    /// The default maximum length of arrays and strings read by a reader.
+    pub const DEFAULT_MAX_LENGTH: usize = 0x10000;
+
     pub fn new(buffer: &'a [u8]) -> Self {
+        Self::with_position(buffer, 0)
+    }
+
+    pub fn with_position(buffer: &'a [u8], position: usize) -> Self {
         Self {
+            buffer,
+            position,
+            depth: 0,
+            max_depth: Self::DEFAULT_MAX_DEPTH,
+            max_length: Self::DEFAULT_MAX_LENGTH,
+            lenient: false,
+            partial: false,
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// Enables or disables lenient decoding.
+    ///
+    /// In lenient mode, fields marked as `#[trailing]` in derived messages fall
+    /// back to their default value when the data ends right before them. This
+    /// helps with captures from older client builds which sent shorter messages.
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.lenient
+    }
+
+    /// Marks the buffer as the part of the data received so far.
+    ///
+    /// Running out of a partial buffer doesn't mean the data ended, so fields
+    /// marked as `#[default_on_eof]` or `#[trailing]` in derived messages aren't
+    /// defaulted and the read fails instead, to be retried once more data came.
+    pub fn set_partial(&mut self, partial: bool) {
+        self.partial = partial;
+    }
+
+    pub fn is_partial(&self) -> bool {
+        self.partial
+    }
+
+    /// Sets the maximum nesting depth allowed while reading.
+    ///
+    /// Reading a value nested deeper than this fails with
+    /// [`BitPackError::DepthLimitExceeded`] instead of exhausting the stack.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Returns the current nesting depth of this reader.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Sets the maximum length allowed for arrays and strings.
+    ///
+    /// Lengths usually come from the data, so reading a longer array fails with
+    /// [`BitPackError::LengthTooLarge`] instead of trusting them. Derived fields
+    /// can use their own limit with `#[length(count, max = 1024)]`.
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = max_length;
+    }
+
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// Returns `length` if it is at most `max_length`, or fails with
+    /// [`BitPackError::LengthTooLarge`].
+    pub fn check_length(&self, length: usize, max_length: usize) -> BitPackResult<usize> {
+        match length <= max_length {
+            true => Ok(length),
+            false => Err(BitPackError::LengthTooLarge {
+                length,
+                max: max_length,
+            }),
+        }
+    }
+
+    /// Runs `f` one nesting level deeper, failing if that exceeds the maximum depth.
+    ///
+    /// Composite values (such as derived messages) should read their content
+    /// through this.
+    pub fn nested<T, F>(&mut self, f: F) -> BitPackResult<T>
+    where
+        F: FnOnce(&mut Self) -> BitPackResult<T>,
+    {
+        if self.depth >= self.max_depth {
+            return Err(BitPackError::DepthLimitExceeded {
+                max: self.max_depth,
+            });
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        result
+    }
+
+    /// Creates a reader over another buffer, such as a decompressed block, with
+    /// the same settings and nesting depth as this one.
+    pub fn sub_reader<'b>(&self, buffer: &'b [u8]) -> BitPackReader<'b> {
+        BitPackReader {
             buffer,
             position: 0,
+            depth: self.depth,
+            max_depth: self.max_depth,
+            max_length: self.max_length,
+            lenient: self.lenient,
+            // the other buffer is read in full
+            partial: false,
+            // positions in another buffer would be meaningless in the trace
+            #[cfg(feature = "trace")]
+            trace: None,
         }
     }
 
-    pub fn with_position(buffer: &'a [u8], position: usize) -> Self {
-        Self { buffer, position }
+    /// Starts recording the spans of the labeled values read from now on.
+    ///
+    /// This does nothing unless the `trace` feature is enabled.
+    pub fn start_trace(&mut self) {
+        #[cfg(feature = "trace")]
+        {
+            self.trace = Some(Trace::new());
+        }
+    }
+
+    /// Stops recording and returns the spans recorded since
+    /// [`BitPackReader::start_trace`]. Always returns `None` without the `trace`
+    /// feature.
+    ///
+    /// Spans of values that failed to read are left unfinished.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        #[cfg(feature = "trace")]
+        return self.trace.take();
+        #[cfg(not(feature = "trace"))]
+        None
+    }
+
+    /// Marks the start of a labeled value when tracing. Does nothing otherwise.
+    #[inline]
+    pub fn trace_enter(&mut self, label: &'static str) {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            trace.enter(label, self.position);
+        }
+        #[cfg(not(feature = "trace"))]
+        let _ = label;
+    }
+
+    /// Marks the end of the last labeled value when tracing. Does nothing
+    /// otherwise.
+    #[inline]
+    pub fn trace_exit(&mut self) {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            trace.exit(self.position);
+        }
     }
 
     /// Returns the current position of this reader, in bits.
@@ -36,13 +207,66 @@ impl<'a> BitPackReader<'a> {
     /// Advances the reader to the next full byte ((pos % 8) == 0).
     /// If the reader is already aligned, this does nothing.
     pub fn align(&mut self) -> BitPackResult {
-        while self.position % 8 != 0 {
+        while !self.position.is_multiple_of(8) {
             self.read_bit()?;
         }
 
         Ok(())
     }
 
+    /// Returns how many bits are left to read in the buffer.
+    pub fn remaining_bits(&self) -> usize {
+        (self.buffer.len() * 8).saturating_sub(self.position)
+    }
+
+    fn out_of_bounds(&self, needed: usize) -> BitPackError {
+        BitPackError::OutOfBounds {
+            position: self.position,
+            needed,
+            available: self.remaining_bits(),
+        }
+    }
+
+    /// Fails with [`BitPackError::OutOfBounds`] unless `bits` bits can be read
+    /// from the current position, which may itself be past the end of the buffer.
+    fn check_remaining(&self, bits: usize) -> BitPackResult {
+        match self.position.saturating_add(bits) <= self.buffer.len() * 8 {
+            true => Ok(()),
+            false => Err(self.out_of_bounds(bits)),
+        }
+    }
+
+    /// Returns true if nothing but the padding of the current byte is left to read.
+    pub fn is_at_end(&self) -> bool {
+        self.position.div_ceil(8) >= self.buffer.len()
+    }
+
+    /// Checks that the whole buffer was consumed, ignoring the padding of the
+    /// current byte.
+    ///
+    /// Fails with [`BitPackError::TrailingData`] otherwise, which usually means
+    /// that a message definition is missing fields.
+    pub fn finish(&mut self) -> BitPackResult {
+        self.align()?;
+        match self.remaining_bits() {
+            0 => Ok(()),
+            bits => Err(BitPackError::TrailingData { bits }),
+        }
+    }
+
+    /// Reads a value that must span the rest of the buffer.
+    ///
+    /// This is a strict version of [`read`](Self::read) that fails when any data
+    /// beyond the final byte's padding is left unread.
+    pub fn read_to_end<T>(&mut self) -> BitPackResult<T>
+    where
+        T: ReadValue,
+    {
+        let value = self.read()?;
+        self.finish()?;
+        Ok(value)
+    }
+
     pub fn read_bit(&mut self) -> BitPackResult<bool> {
         let pos_in_buffer = self.position / 8;
         let pos_in_byte = self.position % 8;
@@ -54,7 +278,7 @@ impl<'a> BitPackReader<'a> {
 
                 Ok(value)
             }
-            None => Err(BitPackError::OutOfBounds),
+            None => Err(self.out_of_bounds(1)),
         }
     }
 
@@ -62,27 +286,81 @@ impl<'a> BitPackReader<'a> {
         self.read_u64(32).map(|v| f32::from_bits(v as u32))
     }
 
-    pub fn read_u64(&mut self, bits: usize) -> BitPackResult<u64> {
-        let mut value = 0;
+    pub fn read_f64(&mut self) -> BitPackResult<f64> {
+        self.read_u64(64).map(f64::from_bits)
+    }
 
-        for i in 0..bits {
-            if self.read_bit()? {
-                value |= 1 << i;
-            }
+    /// Reads an unsigned value of `bits` bits, which fails with
+    /// [`BitPackError::TooManyBits`] past 64.
+    ///
+    /// The bytes covering the value are loaded as a single little-endian word, so
+    /// only the unaligned head needs shifting and the tail masking.
+    pub fn read_u64(&mut self, bits: usize) -> BitPackResult<u64> {
+        if bits > 64 {
+            return Err(BitPackError::TooManyBits { bits });
         }
+        self.check_remaining(bits)?;
 
-        Ok(value)
+        let start = self.position / 8;
+        let end = (self.position + bits).div_ceil(8);
+        let mut word = [0u8; 16];
+        word[..end - start].copy_from_slice(&self.buffer[start..end]);
+        let value = (u128::from_le_bytes(word) >> (self.position % 8)) as u64;
+        self.position += bits;
+
+        match bits {
+            64 => Ok(value),
+            _ => Ok(value & ((1 << bits) - 1)),
+        }
     }
 
     // todo: move this to support read<&mut [u8]>
     pub fn read_bytes(&mut self, buf: &mut [u8]) -> BitPackResult {
-        for byte in buf.iter_mut() {
-            *byte = self.read_u64(8)? as u8;
+        self.check_remaining(buf.len() * 8)?;
+
+        if self.position.is_multiple_of(8) {
+            let start = self.position / 8;
+            buf.copy_from_slice(&self.buffer[start..start + buf.len()]);
+            self.position += buf.len() * 8;
+        } else {
+            for byte in buf.iter_mut() {
+                *byte = self.read_u64(8)? as u8;
+            }
         }
 
         Ok(())
     }
 
+    /// Reads a `length_bits`-bit byte count followed by that many raw bytes.
+    ///
+    /// Fails with [`BitPackError::LengthTooLarge`] before allocating anything if
+    /// the length is larger than `max_length`.
+    pub fn read_blob(&mut self, length_bits: usize, max_length: usize) -> BitPackResult<Vec<u8>> {
+        let length = self.read_u64(length_bits)? as usize;
+        if length > max_length {
+            return Err(BitPackError::LengthTooLarge {
+                length,
+                max: max_length,
+            });
+        }
+
+        self.read_byte_vec(length)
+    }
+
+    /// Reads `length` bytes into a new vector.
+    ///
+    /// The length is checked against the remaining data before allocating, so it
+    /// can safely come from the data itself.
+    pub fn read_byte_vec(&mut self, length: usize) -> BitPackResult<Vec<u8>> {
+        if length.saturating_mul(8) > self.remaining_bits() {
+            return Err(self.out_of_bounds(length.saturating_mul(8)));
+        }
+
+        let mut bytes = vec![0; length];
+        self.read_bytes(&mut bytes)?;
+        Ok(bytes)
+    }
+
     pub fn read<T>(&mut self) -> BitPackResult<T>
     where
         T: ReadValue,
@@ -97,17 +375,22 @@ impl<'a> BitPackReader<'a> {
         ReadPackedValue::read_packed(self, bits)
     }
 
+    /// Reads `length` items, failing if that is more than the maximum length.
     pub fn read_array<T>(&mut self, length: usize) -> BitPackResult<T>
     where
         T: ReadArrayValue,
     {
+        let length = self.check_length(length, self.max_length)?;
         ReadArrayValue::read_array(self, length)
     }
 
+    /// Reads `length` items packed on `bits` bits, failing if that is more than
+    /// the maximum length.
     pub fn read_packed_array<T>(&mut self, length: usize, bits: usize) -> BitPackResult<T>
     where
         T: ReadPackedArrayValue,
     {
+        let length = self.check_length(length, self.max_length)?;
         ReadPackedArrayValue::read_packed_array(self, length, bits)
     }
 }
@@ -142,6 +425,152 @@ mod tests {
         assert_eq!(reader.position(), 9);
     }
 
+    #[test]
+    fn test_nested_depth_limit() {
+        fn read_nested(reader: &mut BitPackReader, levels: usize) -> BitPackResult {
+            match levels {
+                0 => Ok(()),
+                _ => reader.nested(|reader| read_nested(reader, levels - 1)),
+            }
+        }
+
+        let mut reader = BitPackReader::new(&[]);
+        reader.set_max_depth(4);
+        assert!(read_nested(&mut reader, 4).is_ok());
+        assert_eq!(reader.depth(), 0);
+        assert!(matches!(
+            read_nested(&mut reader, 5),
+            Err(BitPackError::DepthLimitExceeded { max: 4 })
+        ));
+        assert_eq!(reader.depth(), 0);
+    }
+
+    #[test]
+    fn test_max_length() {
+        let data = [0u8; 16];
+        let mut reader = BitPackReader::new(&data);
+        reader.set_max_length(4);
+        assert!(reader.read_array::<Vec<u8>>(4).is_ok());
+        assert!(matches!(
+            reader.read_array::<Vec<u8>>(5),
+            Err(BitPackError::LengthTooLarge { length: 5, max: 4 })
+        ));
+        assert!(matches!(
+            reader.read_packed_array::<Vec<u8>>(usize::MAX, 1),
+            Err(BitPackError::LengthTooLarge { max: 4, .. })
+        ));
+
+        // a string prefix with the extended flag and a length of 0x80
+        let data = hex::decode("0101").unwrap();
+        let mut reader = BitPackReader::new(&data);
+        reader.set_max_length(0x7f);
+        assert!(matches!(
+            reader.read::<String>(),
+            Err(BitPackError::LengthTooLarge {
+                length: 0x80,
+                max: 0x7f
+            })
+        ));
+    }
+
+    #[test]
+    fn test_read_u64_matches_bit_reads() {
+        let data = hex::decode("8f3a17c2e45b96d0017fa3b4c5d6e7f8091a2b3c").unwrap();
+
+        for offset in 0..16 {
+            for bits in 0..=64 {
+                let mut expected = 0u64;
+                let mut bit_reader = BitPackReader::with_position(&data, offset);
+                for i in 0..bits {
+                    if bit_reader.read_bit().unwrap() {
+                        expected |= 1 << i;
+                    }
+                }
+
+                let mut reader = BitPackReader::with_position(&data, offset);
+                assert_eq!(reader.read_u64(bits).unwrap(), expected);
+                assert_eq!(reader.position(), offset + bits);
+            }
+        }
+
+        let mut reader = BitPackReader::new(&data);
+        assert!(matches!(
+            reader.read_u64(65),
+            Err(BitPackError::TooManyBits { bits: 65 })
+        ));
+        assert_eq!(reader.position(), 0);
+    }
+
+    #[test]
+    fn test_read_past_the_end() {
+        let data = hex::decode("8f3a").unwrap();
+
+        let mut reader = BitPackReader::with_position(&data, 40);
+        assert!(matches!(
+            reader.read_u64(0),
+            Err(BitPackError::OutOfBounds {
+                position: 40,
+                needed: 0,
+                available: 0
+            })
+        ));
+        assert!(matches!(
+            reader.read_bytes(&mut []),
+            Err(BitPackError::OutOfBounds { position: 40, .. })
+        ));
+        assert!(reader.read_bit().is_err());
+
+        // reading nothing right at the end is fine
+        let mut reader = BitPackReader::with_position(&data, 16);
+        assert_eq!(reader.read_u64(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_read_bytes() {
+        let data = hex::decode("8f3a17c2e4").unwrap();
+
+        let mut buf = [0u8; 4];
+        let mut reader = BitPackReader::new(&data);
+        reader.read_bytes(&mut buf).unwrap();
+        assert_eq!(hex::encode(buf), "8f3a17c2");
+
+        let mut reader = BitPackReader::with_position(&data, 4);
+        reader.read_bytes(&mut buf).unwrap();
+        assert_eq!(hex::encode(buf), "a873214c");
+
+        let mut reader = BitPackReader::with_position(&data, 9);
+        let error = reader.read_bytes(&mut buf).unwrap_err();
+        assert!(matches!(
+            error,
+            BitPackError::OutOfBounds {
+                position: 9,
+                needed: 32,
+                available: 31
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "out of bounds at bit 9, 32 bits needed but 31 available"
+        );
+    }
+
+    #[test]
+    fn test_read_to_end() {
+        let data = hex::decode("ffff7f").unwrap();
+
+        // padding of the last byte is ignored
+        let mut reader = BitPackReader::new(&data);
+        reader.read_u64(16).unwrap();
+        assert!(reader.read_to_end::<bool>().is_ok());
+
+        // unread bytes are reported
+        let mut reader = BitPackReader::new(&data);
+        assert!(matches!(
+            reader.read_to_end::<u8>(),
+            Err(BitPackError::TrailingData { bits: 16 })
+        ));
+    }
+
     #[test]
     fn test_simple_message() {
         let data = "2f00000240c00000000000008800000000000000000000\
@@ -0,0 +1,306 @@
+use std::io::{ErrorKind, Read};
+
+use crate::*;
+
+/// A BitPack reader that pulls its data from an [`io::Read`](Read) source, such
+/// as a socket, as it is needed.
+///
+/// Bytes are fetched into an internal buffer in chunks and dropped from it once
+/// fully read. Values are decoded with a [`BitPackReader`] over that buffer: when
+/// one runs out of data, more is fetched until the bits it was missing arrive,
+/// and the value is decoded again from its start. The buffer is read as
+/// [partial](BitPackReader::set_partial) until the source reports its end, so
+/// optional trailing fields are only defaulted once no more data can come.
+/// Reaching the end of the source in any other value fails with
+/// [`BitPackError::OutOfBounds`].
+pub struct BitPackStreamReader<R> {
+    source: R,
+    /// The bytes fetched from the source that weren't fully read yet.
+    buffer: Vec<u8>,
+    /// The position of the reader in the buffer, in bits.
+    position: usize,
+    /// The number of bits dropped from the front of the buffer.
+    consumed: usize,
+    max_depth: usize,
+    max_length: usize,
+    lenient: bool,
+    /// Whether the source reported its end.
+    ended: bool,
+}
+
+impl<R: Read> BitPackStreamReader<R> {
+    /// The number of bytes requested from the source at once.
+    const CHUNK_SIZE: usize = 4096;
+
+    pub fn new(source: R) -> Self {
+        Self {
+            source,
+            buffer: Vec::new(),
+            position: 0,
+            consumed: 0,
+            max_depth: BitPackReader::DEFAULT_MAX_DEPTH,
+            max_length: BitPackReader::DEFAULT_MAX_LENGTH,
+            lenient: false,
+            ended: false,
+        }
+    }
+
+    /// Sets the maximum nesting depth allowed while reading.
+    ///
+    /// See [`BitPackReader::set_max_depth`].
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Sets the maximum length allowed for arrays and strings.
+    ///
+    /// See [`BitPackReader::set_max_length`].
+    pub fn set_max_length(&mut self, max_length: usize) {
+        self.max_length = max_length;
+    }
+
+    /// Enables or disables lenient decoding.
+    ///
+    /// See [`BitPackReader::set_lenient`].
+    pub fn set_lenient(&mut self, lenient: bool) {
+        self.lenient = lenient;
+    }
+
+    /// Returns the number of bits read since the reader was created.
+    pub fn position(&self) -> usize {
+        self.consumed + self.position
+    }
+
+    /// Returns the bytes fetched from the source that weren't fully read yet.
+    pub fn buffered(&self) -> &[u8] {
+        &self.buffer[self.position / 8..]
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.source
+    }
+
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.source
+    }
+
+    /// Runs `f` on a reader over the buffered data, fetching more data and trying
+    /// again for as long as it runs out of it.
+    fn read_with<T, F>(&mut self, mut f: F) -> BitPackResult<T>
+    where
+        F: FnMut(&mut BitPackReader) -> BitPackResult<T>,
+    {
+        loop {
+            let mut reader = BitPackReader::with_position(&self.buffer, self.position);
+            reader.set_max_depth(self.max_depth);
+            reader.set_max_length(self.max_length);
+            reader.set_lenient(self.lenient);
+            reader.set_partial(!self.ended);
+            let error = match f(&mut reader) {
+                Ok(value) => {
+                    self.position = reader.position();
+                    self.compact();
+                    return Ok(value);
+                }
+                Err(error) => error,
+            };
+
+            // errors from derived values wrap the one from the reader
+            let BitPackError::OutOfBounds {
+                position, needed, ..
+            } = *error.root()
+            else {
+                return Err(error);
+            };
+            if self.ended {
+                return Err(error);
+            }
+            // decoding again before the missing bits arrive would fail at the
+            // same place, so fetch until they do. Out of bounds errors of a
+            // sub-reader are about another buffer, which gets at least one fetch.
+            // Once the source ended, the value is decoded one last time with
+            // its missing trailing fields defaulted
+            let end = position.saturating_add(needed);
+            loop {
+                if !self.fetch()? {
+                    self.ended = true;
+                    break;
+                }
+                if self.buffer.len() * 8 >= end {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Reads the next chunk of data from the source. Returns false at its end.
+    fn fetch(&mut self) -> BitPackResult<bool> {
+        let length = self.buffer.len();
+        self.buffer.resize(length + Self::CHUNK_SIZE, 0);
+        loop {
+            match self.source.read(&mut self.buffer[length..]) {
+                Ok(read) => {
+                    self.buffer.truncate(length + read);
+                    return Ok(read > 0);
+                }
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.buffer.truncate(length);
+                    return Err(BitPackError::Io(error));
+                }
+            }
+        }
+    }
+
+    /// Drops the bytes that were fully read from the buffer.
+    fn compact(&mut self) {
+        let bytes = self.position / 8;
+        if bytes > 0 {
+            self.buffer.drain(..bytes);
+            self.position -= bytes * 8;
+            self.consumed += bytes * 8;
+        }
+    }
+
+    /// Advances the reader to the next full byte.
+    pub fn align(&mut self) -> BitPackResult {
+        self.read_with(|reader| reader.align())
+    }
+
+    pub fn read_bit(&mut self) -> BitPackResult<bool> {
+        self.read_with(|reader| reader.read_bit())
+    }
+
+    pub fn read_u64(&mut self, bits: usize) -> BitPackResult<u64> {
+        self.read_with(|reader| reader.read_u64(bits))
+    }
+
+    pub fn read_bytes(&mut self, buf: &mut [u8]) -> BitPackResult {
+        self.read_with(|reader| reader.read_bytes(buf))
+    }
+
+    pub fn read<T>(&mut self) -> BitPackResult<T>
+    where
+        T: ReadValue,
+    {
+        self.read_with(|reader| reader.read())
+    }
+
+    pub fn read_packed<T>(&mut self, bits: usize) -> BitPackResult<T>
+    where
+        T: ReadPackedValue,
+    {
+        self.read_with(|reader| reader.read_packed(bits))
+    }
+
+    pub fn read_array<T>(&mut self, length: usize) -> BitPackResult<T>
+    where
+        T: ReadArrayValue,
+    {
+        self.read_with(|reader| reader.read_array(length))
+    }
+
+    pub fn read_packed_array<T>(&mut self, length: usize, bits: usize) -> BitPackResult<T>
+    where
+        T: ReadPackedArrayValue,
+    {
+        self.read_with(|reader| reader.read_packed_array(length, bits))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A source that returns a single byte per read, like a slow socket.
+    struct ByteByByte<'a>(&'a [u8]);
+
+    impl Read for ByteByByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match (self.0.split_first(), buf.first_mut()) {
+                (Some((byte, rest)), Some(out)) => {
+                    *out = *byte;
+                    self.0 = rest;
+                    Ok(1)
+                }
+                _ => Ok(0),
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_simple_message() {
+        let data = "2f00000240c00000000000008800000000000000000000\
+            00000000000000489208b89c000000000000000000000000";
+        let data = hex::decode(data).unwrap();
+        let stream = [data.as_slice(), data.as_slice()].concat();
+        let mut reader = BitPackStreamReader::new(ByteByByte(&stream));
+
+        for _ in 0..2 {
+            assert_eq!(reader.read_u64(24).unwrap(), 47);
+            assert_eq!(reader.read_u64(11).unwrap(), 2);
+            assert_eq!(
+                reader.read::<(u32, u32, u32, u32)>().unwrap(),
+                (6152, 0, 17, 0)
+            );
+            assert_eq!(reader.read::<(u64, u16)>().unwrap(), (0, 0));
+            assert_eq!(reader.read_packed::<u8>(5).unwrap(), 9);
+            assert_eq!(
+                reader.read::<(u32, u32, u64)>().unwrap(),
+                (2629306514, 0, 0)
+            );
+            reader.align().unwrap();
+        }
+
+        assert_eq!(reader.position(), 94 * 8);
+        assert!(reader.buffered().is_empty());
+        assert!(matches!(
+            reader.read_bit(),
+            Err(BitPackError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_stream_retries_once_data_arrived() {
+        let data = [0xab; 64];
+        let mut reader = BitPackStreamReader::new(ByteByByte(&data));
+
+        let mut attempts = 0;
+        let mut bytes = [0; 64];
+        reader
+            .read_with(|reader| {
+                attempts += 1;
+                reader.read_bytes(&mut bytes)
+            })
+            .unwrap();
+        assert_eq!(attempts, 2);
+        assert_eq!(bytes, data);
+    }
+
+    #[test]
+    fn test_stream_partial_until_end() {
+        let data = [0xab; 4];
+        let mut reader = BitPackStreamReader::new(ByteByByte(&data));
+
+        let mut partial = vec![];
+        let result = reader.read_with(|reader| {
+            partial.push(reader.is_partial());
+            reader.read_u64(40)
+        });
+        assert!(matches!(result, Err(BitPackError::OutOfBounds { .. })));
+        assert_eq!(partial.pop(), Some(false));
+        assert!(partial.iter().all(|&partial| partial));
+    }
+
+    #[test]
+    fn test_stream_settings() {
+        let data = [0; 16];
+        let mut reader = BitPackStreamReader::new(ByteByByte(&data));
+        reader.set_max_length(4);
+        assert!(reader.read_array::<Vec<u8>>(4).is_ok());
+        assert!(matches!(
+            reader.read_array::<Vec<u8>>(5),
+            Err(BitPackError::LengthTooLarge { length: 5, max: 4 })
+        ));
+    }
+}
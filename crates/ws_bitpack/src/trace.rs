@@ -0,0 +1,84 @@
+/// A range of bits that a labeled value was read from or written to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceSpan {
+    /// The label of the value, usually a field name.
+    pub label: &'static str,
+    /// The position of the first bit of the value.
+    pub offset: usize,
+    /// The position right after the last bit of the value, or `None` if reading
+    /// or writing the value failed.
+    pub end: Option<usize>,
+    /// How many spans this span is nested in.
+    pub depth: usize,
+}
+
+impl TraceSpan {
+    /// Returns true if the bit at `offset` belongs to this span. Unfinished spans
+    /// contain everything after their start.
+    pub fn contains(&self, offset: usize) -> bool {
+        offset >= self.offset && self.end.is_none_or(|end| offset < end)
+    }
+}
+
+/// The spans recorded while reading or writing values, in the order they
+/// started in.
+///
+/// Recording is only available with the `trace` feature. Derived messages label
+/// each of their fields with its name, which lets tools show which bits of a
+/// packet belong to which field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    spans: Vec<TraceSpan>,
+    /// The indices of the spans that aren't finished yet.
+    open: Vec<usize>,
+}
+
+impl Trace {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enter(&mut self, label: &'static str, offset: usize) {
+        self.open.push(self.spans.len());
+        self.spans.push(TraceSpan {
+            label,
+            offset,
+            end: None,
+            depth: self.open.len() - 1,
+        });
+    }
+
+    pub fn exit(&mut self, offset: usize) {
+        if let Some(index) = self.open.pop() {
+            self.spans[index].end = Some(offset);
+        }
+    }
+
+    pub fn spans(&self) -> &[TraceSpan] {
+        &self.spans
+    }
+
+    /// Returns the spans containing the bit at `offset`, outermost first.
+    pub fn spans_at(&self, offset: usize) -> Vec<&TraceSpan> {
+        let mut path: Vec<&TraceSpan> = Vec::new();
+        for span in self.spans.iter().filter(|span| span.contains(offset)) {
+            // spans of a previous value at the same depth don't contain it anymore
+            path.truncate(span.depth);
+            if path.len() == span.depth {
+                path.push(span);
+            }
+        }
+        path
+    }
+
+    /// Returns the labels of the spans containing the bit at `offset`, joined
+    /// with dots, like `characters.name`.
+    pub fn path_at(&self, offset: usize) -> Option<String> {
+        let spans = self.spans_at(offset);
+        if spans.is_empty() {
+            return None;
+        }
+        let labels = spans.iter().map(|span| span.label).collect::<Vec<_>>();
+        Some(labels.join("."))
+    }
+}
@@ -5,7 +5,9 @@ where
     Item: ReadValue,
 {
     fn read_array(reader: &mut BitPackReader, length: usize) -> BitPackResult<Self> {
-        let mut vec = Vec::with_capacity(length);
+        // the length comes from the data, so don't trust it further than the
+        // number of bits left, otherwise a bad length would allocate gigabytes
+        let mut vec = Vec::with_capacity(length.min(reader.remaining_bits()));
         while vec.len() < length {
             vec.push(ReadValue::read(reader)?);
         }
@@ -23,8 +25,14 @@ where
     }
 
     fn bits_array(&self) -> usize {
-        self.iter()
-            .fold(0, |bits, item| bits + WriteValue::bits(item))
+        self.bits_array_at(0)
+    }
+
+    fn bits_array_at(&self, position: usize) -> usize {
+        let end = self.iter().fold(position, |bits, item| {
+            bits + WriteValue::bits_at(item, bits)
+        });
+        end - position
     }
 }
 
@@ -37,7 +45,7 @@ where
         length: usize,
         bits: usize,
     ) -> BitPackResult<Self> {
-        let mut vec = Vec::with_capacity(length);
+        let mut vec = Vec::with_capacity(length.min(reader.remaining_bits() / bits.max(1)));
         while vec.len() < length {
             vec.push(ReadPackedValue::read_packed(reader, bits)?);
         }
@@ -58,3 +66,26 @@ where
         self.len() * bits
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hostile_length() {
+        // the reader rejects it first, but the arrays shouldn't allocate it
+        // even without that check
+        let buffer = [0xff; 4];
+        let mut reader = BitPackReader::new(&buffer);
+        assert!(matches!(
+            <Vec<u64>>::read_array(&mut reader, usize::MAX),
+            Err(BitPackError::OutOfBounds { .. })
+        ));
+
+        let mut reader = BitPackReader::new(&buffer);
+        assert!(matches!(
+            <Vec<u32>>::read_packed_array(&mut reader, usize::MAX, 3),
+            Err(BitPackError::OutOfBounds { .. })
+        ));
+    }
+}
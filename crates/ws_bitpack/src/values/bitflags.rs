@@ -0,0 +1,118 @@
+use ::bitflags::Flags;
+
+use crate::*;
+
+impl BitPackReader<'_> {
+    /// Reads a `bitflags!` type from its mask on `bits` bits.
+    ///
+    /// Bits that don't correspond to any flag fail with
+    /// [`BitPackError::InvalidFlags`], unless `retain` is set to keep them.
+    pub fn read_flags<T>(&mut self, bits: usize, retain: bool) -> BitPackResult<T>
+    where
+        T: Flags,
+        T::Bits: TryFrom<u64> + Into<u64>,
+    {
+        let raw = self.read_u64(bits)?;
+        let value = T::Bits::try_from(raw).map_err(|_| BitPackError::ValueTooLarge {
+            bits,
+            value: raw as i128,
+        })?;
+        let value = T::from_bits_retain(value);
+        check_flags(&value, retain)?;
+        Ok(value)
+    }
+}
+
+impl BitPackWriter<'_> {
+    /// Writes the mask of a `bitflags!` type on `bits` bits.
+    ///
+    /// Bits that don't correspond to any flag fail with
+    /// [`BitPackError::InvalidFlags`], unless `retain` is set to keep them.
+    pub fn write_flags<T>(&mut self, value: &T, bits: usize, retain: bool) -> BitPackResult
+    where
+        T: Flags,
+        T::Bits: Into<u64>,
+    {
+        check_flags(value, retain)?;
+        self.write_u64(value.bits().into(), bits)
+    }
+}
+
+fn check_flags<T>(value: &T, retain: bool) -> BitPackResult
+where
+    T: Flags,
+    T::Bits: Into<u64>,
+{
+    let unknown = value.bits().into() & !T::all().bits().into();
+    match unknown {
+        0 => Ok(()),
+        _ if retain => Ok(()),
+        unknown => Err(BitPackError::InvalidFlags {
+            type_name: std::any::type_name::<T>(),
+            unknown,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    ::bitflags::bitflags! {
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        struct UnitFlags: u16 {
+            const HOSTILE = 1 << 0;
+            const TARGETABLE = 1 << 3;
+            const DEAD = 1 << 6;
+        }
+    }
+
+    #[test]
+    fn test_flags_write_read() {
+        let flags = UnitFlags::HOSTILE | UnitFlags::DEAD;
+        let mut writer = BitPackWriter::growable();
+        writer.write_flags(&flags, 7, false).unwrap();
+        assert_eq!(writer.position(), 7);
+        let buffer = writer.finish().unwrap();
+        assert_eq!(buffer, [0b1000001]);
+
+        let mut reader = BitPackReader::new(&buffer);
+        assert_eq!(reader.read_flags::<UnitFlags>(7, false).unwrap(), flags);
+    }
+
+    #[test]
+    fn test_unknown_flags() {
+        let buffer = [0b1001_0001];
+        let mut reader = BitPackReader::new(&buffer);
+        assert!(matches!(
+            reader.read_flags::<UnitFlags>(8, false),
+            Err(BitPackError::InvalidFlags { unknown: 0x90, .. })
+        ));
+
+        let mut reader = BitPackReader::new(&buffer);
+        let flags: UnitFlags = reader.read_flags(8, true).unwrap();
+        assert_eq!(flags.bits(), 0b1001_0001);
+
+        let mut writer = BitPackWriter::growable();
+        assert!(matches!(
+            writer.write_flags(&flags, 8, false),
+            Err(BitPackError::InvalidFlags { unknown: 0x90, .. })
+        ));
+        writer.write_flags(&flags, 8, true).unwrap();
+        assert_eq!(writer.finish().unwrap(), buffer);
+
+        // flags past the width of the mask don't fit in it
+        let mut writer = BitPackWriter::growable();
+        assert!(matches!(
+            writer.write_flags(&UnitFlags::DEAD, 6, false),
+            Err(BitPackError::ValueTooLarge { bits: 6, value: 64 })
+        ));
+
+        // masks wider than the type don't fit
+        let mut reader = BitPackReader::new(&[0, 0, 1]);
+        assert!(matches!(
+            reader.read_flags::<UnitFlags>(24, true),
+            Err(BitPackError::ValueTooLarge { bits: 24, .. })
+        ));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::*;
+
+/// Raw bytes prefixed by their length on `LENGTH_BITS` bits.
+///
+/// Reads fail with [`BitPackError::LengthTooLarge`] when the length prefix is
+/// larger than `MAX_LENGTH`, which avoids allocating attacker-controlled sizes.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Blob<const LENGTH_BITS: usize, const MAX_LENGTH: usize = { usize::MAX }>(pub Vec<u8>);
+
+impl<const LENGTH_BITS: usize, const MAX_LENGTH: usize> Blob<LENGTH_BITS, MAX_LENGTH> {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl<const LENGTH_BITS: usize, const MAX_LENGTH: usize> From<Vec<u8>>
+    for Blob<LENGTH_BITS, MAX_LENGTH>
+{
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl<const LENGTH_BITS: usize, const MAX_LENGTH: usize> std::ops::Deref
+    for Blob<LENGTH_BITS, MAX_LENGTH>
+{
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<const LENGTH_BITS: usize, const MAX_LENGTH: usize> ReadValue
+    for Blob<LENGTH_BITS, MAX_LENGTH>
+{
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        reader.read_blob(LENGTH_BITS, MAX_LENGTH).map(Self)
+    }
+}
+
+impl<const LENGTH_BITS: usize, const MAX_LENGTH: usize> WriteValue
+    for Blob<LENGTH_BITS, MAX_LENGTH>
+{
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        if self.0.len() > MAX_LENGTH {
+            return Err(BitPackError::LengthTooLarge {
+                length: self.0.len(),
+                max: MAX_LENGTH,
+            });
+        }
+        writer.write_blob(&self.0, LENGTH_BITS)
+    }
+
+    fn bits(&self) -> usize {
+        LENGTH_BITS + self.0.len() * 8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_blob_write_read() {
+        let blob: Blob<12> = Blob(vec![0xde, 0xad, 0xbe, 0xef]);
+
+        let mut buffer = vec![0; 8];
+        let mut writer = BitPackWriter::new(&mut buffer);
+        writer.write(&blob).unwrap();
+        assert_eq!(writer.position(), blob.bits());
+
+        let mut reader = BitPackReader::new(&buffer);
+        let result: Blob<12> = reader.read().unwrap();
+        assert_eq!(result, blob);
+    }
+
+    #[test]
+    fn test_blob_limits() {
+        let mut buffer = vec![0; 8];
+        let mut writer = BitPackWriter::new(&mut buffer);
+        let result = writer.write_blob(&[0; 4], 2);
+        assert!(matches!(
+            result,
+            Err(BitPackError::LengthTooLarge { length: 4, max: 3 })
+        ));
+
+        let blob: Blob<8> = Blob(vec![0; 4]);
+        let mut writer = BitPackWriter::new(&mut buffer);
+        writer.write(&blob).unwrap();
+        let mut reader = BitPackReader::new(&buffer);
+        let result = reader.read::<Blob<8, 2>>();
+        assert!(matches!(
+            result,
+            Err(BitPackError::LengthTooLarge { length: 4, max: 2 })
+        ));
+    }
+}
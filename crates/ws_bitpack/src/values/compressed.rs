@@ -0,0 +1,166 @@
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::*;
+
+/// A value bit-packed into its own buffer and then compressed with zlib.
+///
+/// The compressed bytes are prefixed by their length on `LENGTH_BITS` bits.
+/// Reads fail with [`BitPackError::LengthTooLarge`] when the compressed or the
+/// inflated data would be larger than `MAX_LENGTH` bytes or than the
+/// [maximum length](BitPackReader::set_max_length) of the reader, which guards
+/// against compression bombs.
+///
+/// Computing [`WriteValue::bits`] compresses the value, so it is as expensive as
+/// writing it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Compressed<T, const LENGTH_BITS: usize, const MAX_LENGTH: usize = { usize::MAX }>(pub T);
+
+impl<T, const LENGTH_BITS: usize, const MAX_LENGTH: usize> Compressed<T, LENGTH_BITS, MAX_LENGTH> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, const LENGTH_BITS: usize, const MAX_LENGTH: usize> From<T>
+    for Compressed<T, LENGTH_BITS, MAX_LENGTH>
+{
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T, const LENGTH_BITS: usize, const MAX_LENGTH: usize> std::ops::Deref
+    for Compressed<T, LENGTH_BITS, MAX_LENGTH>
+{
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: ReadValue, const LENGTH_BITS: usize, const MAX_LENGTH: usize> ReadValue
+    for Compressed<T, LENGTH_BITS, MAX_LENGTH>
+{
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        let max_length = MAX_LENGTH.min(reader.max_length());
+        let compressed = reader.read_blob(LENGTH_BITS, max_length)?;
+
+        // read one byte past the limit to tell whether it was exceeded
+        let limit = max_length.saturating_add(1) as u64;
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(compressed.as_slice())
+            .take(limit)
+            .read_to_end(&mut inflated)
+            .map_err(BitPackError::Compression)?;
+        if inflated.len() > max_length {
+            return Err(BitPackError::LengthTooLarge {
+                length: inflated.len(),
+                max: max_length,
+            });
+        }
+
+        reader.sub_reader(&inflated).read_to_end().map(Self)
+    }
+}
+
+impl<T: WriteValue, const LENGTH_BITS: usize, const MAX_LENGTH: usize>
+    Compressed<T, LENGTH_BITS, MAX_LENGTH>
+{
+    fn deflate(&self) -> BitPackResult<Vec<u8>> {
+        let mut writer = BitPackWriter::with_capacity(self.0.bits().div_ceil(8));
+        writer.write(&self.0)?;
+        let inflated = writer.finish()?;
+        if inflated.len() > MAX_LENGTH {
+            return Err(BitPackError::LengthTooLarge {
+                length: inflated.len(),
+                max: MAX_LENGTH,
+            });
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(&inflated)
+            .map_err(BitPackError::Compression)?;
+        encoder.finish().map_err(BitPackError::Compression)
+    }
+}
+
+impl<T: WriteValue, const LENGTH_BITS: usize, const MAX_LENGTH: usize> WriteValue
+    for Compressed<T, LENGTH_BITS, MAX_LENGTH>
+{
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.write_blob(&self.deflate()?, LENGTH_BITS)
+    }
+
+    fn bits(&self) -> usize {
+        // an error here will also be returned when writing
+        self.deflate()
+            .map_or(LENGTH_BITS, |compressed| LENGTH_BITS + compressed.len() * 8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_compressed_write_read() {
+        let value: Compressed<(u32, Blob<16>), 16> = Compressed((6152, Blob(vec![7; 256])));
+
+        let mut writer = BitPackWriter::growable();
+        writer.write_bit(true).unwrap();
+        writer.write(&value).unwrap();
+        assert_eq!(writer.position(), 1 + value.bits());
+        // the repeated bytes compress well below their own size
+        assert!(value.bits() < 256 * 8);
+        let buffer = writer.finish().unwrap();
+
+        let mut reader = BitPackReader::new(&buffer);
+        assert!(reader.read_bit().unwrap());
+        assert_eq!(
+            reader.read::<Compressed<(u32, Blob<16>), 16>>().unwrap(),
+            value
+        );
+    }
+
+    #[test]
+    fn test_compressed_limits() {
+        let mut writer = BitPackWriter::growable();
+        writer
+            .write(&Compressed::<Blob<16>, 16>(Blob(vec![0; 256])))
+            .unwrap();
+        let buffer = writer.finish().unwrap();
+
+        let result = BitPackReader::new(&buffer).read::<Compressed<Blob<16>, 16, 128>>();
+        assert!(matches!(
+            result,
+            Err(BitPackError::LengthTooLarge {
+                length: 129,
+                max: 128
+            })
+        ));
+
+        // without its own limit, the one of the reader applies
+        let mut reader = BitPackReader::new(&buffer);
+        reader.set_max_length(64);
+        assert!(matches!(
+            reader.read::<Compressed<Blob<16>, 16>>(),
+            Err(BitPackError::LengthTooLarge {
+                length: 65,
+                max: 64
+            })
+        ));
+
+        let result = BitPackReader::new(&[3, 0, 1, 2]).read::<Compressed<u8, 8>>();
+        assert!(matches!(result, Err(BitPackError::Compression(_))));
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+use crate::*;
+
+/// Reading a map fails with [`BitPackError::DuplicateKey`] when two entries have
+/// the same key, rather than keeping only one of them.
+impl<K, V> ReadArrayValue for BTreeMap<K, V>
+where
+    K: ReadValue + Ord,
+    V: ReadValue,
+{
+    fn read_array(reader: &mut BitPackReader, length: usize) -> BitPackResult<Self> {
+        let mut map = BTreeMap::new();
+        for index in 0..length {
+            let (key, value) = ReadValue::read(reader)?;
+            if map.insert(key, value).is_some() {
+                return Err(BitPackError::DuplicateKey { index });
+            }
+        }
+        Ok(map)
+    }
+}
+
+impl<K, V> WriteArrayValue for BTreeMap<K, V>
+where
+    K: WriteValue,
+    V: WriteValue,
+{
+    fn write_array(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        self.iter().try_for_each(|(key, value)| {
+            writer.write(key)?;
+            writer.write(value)
+        })
+    }
+
+    fn bits_array(&self) -> usize {
+        self.bits_array_at(0)
+    }
+
+    fn bits_array_at(&self, position: usize) -> usize {
+        let end = self.iter().fold(position, |bits, (key, value)| {
+            let bits = bits + key.bits_at(bits);
+            bits + value.bits_at(bits)
+        });
+        end - position
+    }
+}
+
+/// Duplicate keys fail to read like for [`BTreeMap`].
+impl<K, V> ReadArrayValue for HashMap<K, V>
+where
+    K: ReadValue + Eq + Hash,
+    V: ReadValue,
+{
+    fn read_array(reader: &mut BitPackReader, length: usize) -> BitPackResult<Self> {
+        let mut map = HashMap::with_capacity(length.min(reader.remaining_bits()));
+        for index in 0..length {
+            let (key, value) = ReadValue::read(reader)?;
+            if map.insert(key, value).is_some() {
+                return Err(BitPackError::DuplicateKey { index });
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Entries are written sorted by key so that the output is deterministic.
+impl<K, V> WriteArrayValue for HashMap<K, V>
+where
+    K: WriteValue + Ord,
+    V: WriteValue,
+{
+    fn write_array(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        let mut entries = self.iter().collect::<Vec<_>>();
+        entries.sort_by_key(|(key, _)| *key);
+        entries.into_iter().try_for_each(|(key, value)| {
+            writer.write(key)?;
+            writer.write(value)
+        })
+    }
+
+    fn bits_array(&self) -> usize {
+        self.bits_array_at(0)
+    }
+
+    fn bits_array_at(&self, position: usize) -> usize {
+        let end = self.iter().fold(position, |bits, (key, value)| {
+            let bits = bits + key.bits_at(bits);
+            bits + value.bits_at(bits)
+        });
+        end - position
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashMap};
+
+    use crate::*;
+
+    #[test]
+    fn test_map_write_read() {
+        let map = HashMap::from([(3u8, 30u16), (1, 10), (2, 20)]);
+
+        let mut writer = BitPackWriter::growable();
+        writer.write_array(&map).unwrap();
+        let buffer = writer.finish().unwrap();
+        // entries are sorted by key
+        assert_eq!(buffer, [1, 10, 0, 2, 20, 0, 3, 30, 0]);
+
+        let mut reader = BitPackReader::new(&buffer);
+        assert_eq!(reader.read_array::<HashMap<u8, u16>>(3).unwrap(), map);
+        let mut reader = BitPackReader::new(&buffer);
+        assert_eq!(
+            reader.read_array::<BTreeMap<u8, u16>>(3).unwrap(),
+            map.into_iter().collect()
+        );
+    }
+
+    #[test]
+    fn test_map_duplicate_key() {
+        let buffer = [1, 10, 0, 2, 20, 0, 1, 30, 0];
+        assert!(matches!(
+            BitPackReader::new(&buffer).read_array::<HashMap<u8, u16>>(3),
+            Err(BitPackError::DuplicateKey { index: 2 })
+        ));
+        assert!(matches!(
+            BitPackReader::new(&buffer).read_array::<BTreeMap<u8, u16>>(3),
+            Err(BitPackError::DuplicateKey { index: 2 })
+        ));
+    }
+}
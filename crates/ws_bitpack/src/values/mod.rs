@@ -1,6 +1,23 @@
 mod arrays;
+#[cfg(feature = "bitflags")]
+mod bitflags;
+mod blobs;
+mod compressed;
+mod maps;
+mod packed;
 mod primitives;
-mod traits;
 mod strings;
+mod traits;
+mod tuples;
+mod unions;
+mod uuids;
+mod wide_strings;
+mod wrappers;
 
+pub use blobs::*;
+pub use compressed::*;
+pub use packed::*;
+pub use strings::*;
 pub use traits::*;
+pub use unions::*;
+pub use wide_strings::*;
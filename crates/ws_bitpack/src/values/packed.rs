@@ -0,0 +1,84 @@
+use std::mem::size_of;
+
+use crate::*;
+
+/// An integer value that is always packed to `BITS` bits.
+///
+/// This is an alternative to the `#[packed(n)]` attribute where the width is part
+/// of the type. Widths of 0 or larger than the integer type itself fail to compile
+/// as soon as the value is read or written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Packed<T, const BITS: usize>(pub T);
+
+impl<T, const BITS: usize> Packed<T, BITS> {
+    const VALID_WIDTH: () = assert!(
+        BITS > 0 && BITS <= size_of::<T>() * 8,
+        "Invalid bit width for packed value"
+    );
+
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T, const BITS: usize> From<T> for Packed<T, BITS> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+impl<T, const BITS: usize> std::ops::Deref for Packed<T, BITS> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T, const BITS: usize> ReadValue for Packed<T, BITS>
+where
+    T: ReadPackedValue,
+{
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        let () = Self::VALID_WIDTH;
+        reader.read_packed(BITS).map(Self)
+    }
+}
+
+impl<T, const BITS: usize> WriteValue for Packed<T, BITS>
+where
+    T: WritePackedValue,
+{
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        let () = Self::VALID_WIDTH;
+        writer.write_packed(&self.0, BITS)
+    }
+
+    fn bits(&self) -> usize {
+        self.0.bits_packed(BITS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_packed_write_read() {
+        let value: (Packed<u32, 5>, Packed<u8, 3>) = (Packed(9), Packed(5));
+        assert_eq!(value.bits(), 8);
+
+        let mut buffer = vec![0; 1];
+        let mut writer = BitPackWriter::new(&mut buffer);
+        writer.write(&value).unwrap();
+        assert_eq!(buffer, [0b101_01001]);
+
+        let mut reader = BitPackReader::new(&buffer);
+        let result: (Packed<u32, 5>, Packed<u8, 3>) = reader.read().unwrap();
+        assert_eq!(result, value);
+    }
+}
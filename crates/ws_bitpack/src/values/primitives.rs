@@ -32,6 +32,45 @@ impl WriteValue for f32 {
     }
 }
 
+impl ReadValue for f64 {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        reader.read_f64()
+    }
+}
+
+impl WriteValue for f64 {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.write_f64(*self)
+    }
+
+    fn bits(&self) -> usize {
+        64
+    }
+}
+
+/// Characters are sent as a single UTF-16 code unit, which means that only
+/// characters from the basic multilingual plane can be represented.
+impl ReadValue for char {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        let unit: u16 = reader.read()?;
+        char::from_u32(unit as u32).ok_or(BitPackError::InvalidChar(unit as u32))
+    }
+}
+
+impl WriteValue for char {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        let mut units = [0u16; 2];
+        match self.encode_utf16(&mut units) {
+            [unit] => writer.write(unit),
+            _ => Err(BitPackError::InvalidChar(*self as u32)),
+        }
+    }
+
+    fn bits(&self) -> usize {
+        16
+    }
+}
+
 macro_rules! impl_int_readers {
     ( $($t: ident)* ) => {$(
         impl ReadValue for $t {
@@ -42,14 +81,21 @@ macro_rules! impl_int_readers {
 
         impl WriteValue for $t {
             fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
-                writer.write_u64(*self as u64, $t::BITS as usize)
+                // signed values are sign-extended by the cast, keep their own bits only
+                writer.write_u64(*self as u64 & mask($t::BITS as usize), $t::BITS as usize)
             }
 
             fn bits(&self) -> usize {
                 $t::BITS as usize
             }
         }
+    )+};
+}
+
+impl_int_readers!(u8 i8 u16 i16 u32 i32 u64 i64 usize isize);
 
+macro_rules! impl_unsigned_packed {
+    ( $($t: ident)* ) => {$(
         impl ReadPackedValue for $t {
             fn read_packed(reader: &mut BitPackReader, bits: usize) -> BitPackResult<$t> {
                 reader.read_u64(bits).map(|v| v as $t)
@@ -64,4 +110,124 @@ macro_rules! impl_int_readers {
     )+};
 }
 
-impl_int_readers!(u8 i8 u16 i16 u32 i32 u64 i64 usize isize);
+impl_unsigned_packed!(u8 u16 u32 u64 usize);
+
+/// Packed signed integers are stored in two's complement on the given number of
+/// bits, so the sign bit is the highest packed bit rather than the type's.
+macro_rules! impl_signed_packed {
+    ( $($t: ident)* ) => {$(
+        impl ReadPackedValue for $t {
+            fn read_packed(reader: &mut BitPackReader, bits: usize) -> BitPackResult<$t> {
+                reader.read_u64(bits).map(|v| sign_extend(v, bits) as $t)
+            }
+        }
+
+        impl WritePackedValue for $t {
+            fn write_packed(&self, writer: &mut BitPackWriter, bits: usize) -> BitPackResult {
+                let value = *self as i64;
+                let truncated = value as u64 & mask(bits);
+                if !writer.is_truncating() && sign_extend(truncated, bits) != value {
+                    return Err(BitPackError::ValueTooLarge {
+                        bits,
+                        value: value as i128,
+                    });
+                }
+                writer.write_u64(truncated, bits)
+            }
+        }
+    )+};
+}
+
+impl_signed_packed!(i8 i16 i32 i64 isize);
+
+fn mask(bits: usize) -> u64 {
+    match bits {
+        0 => 0,
+        64.. => u64::MAX,
+        _ => (1 << bits) - 1,
+    }
+}
+
+fn sign_extend(value: u64, bits: usize) -> i64 {
+    match bits {
+        0 => 0,
+        64.. => value as i64,
+        _ => {
+            let shift = 64 - bits;
+            ((value << shift) as i64) >> shift
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_signed_packed() {
+        for bits in [1, 2, 5, 8, 13, 32, 63, 64] {
+            let min = if bits == 64 {
+                i64::MIN
+            } else {
+                -(1 << (bits - 1))
+            };
+            let max = if bits == 64 {
+                i64::MAX
+            } else {
+                (1 << (bits - 1)) - 1
+            };
+
+            for value in [min, -1, 0, max] {
+                let mut writer = BitPackWriter::growable();
+                writer.write_packed(&value, bits).unwrap();
+                writer.write_bit(true).unwrap();
+                let buffer = writer.finish().unwrap();
+
+                let mut reader = BitPackReader::new(&buffer);
+                assert_eq!(reader.read_packed::<i64>(bits).unwrap(), value);
+                assert!(reader.read_bit().unwrap());
+            }
+        }
+
+        let mut buffer = vec![0; 1];
+        BitPackWriter::new(&mut buffer)
+            .write_packed(&-1i8, 5)
+            .unwrap();
+        assert_eq!(buffer, [0b11111]);
+        let mut reader = BitPackReader::new(&buffer);
+        assert_eq!(reader.read_packed::<i8>(5).unwrap(), -1);
+        assert_eq!(
+            BitPackReader::new(&buffer).read_packed::<u8>(5).unwrap(),
+            31
+        );
+    }
+
+    #[test]
+    fn test_packed_overflow() {
+        let mut writer = BitPackWriter::growable();
+        assert!(matches!(
+            writer.write_packed(&32u8, 5),
+            Err(BitPackError::ValueTooLarge { bits: 5, value: 32 })
+        ));
+        assert!(matches!(
+            writer.write_packed(&-17i32, 5),
+            Err(BitPackError::ValueTooLarge {
+                bits: 5,
+                value: -17
+            })
+        ));
+        assert!(matches!(
+            writer.write_packed(&16i32, 5),
+            Err(BitPackError::ValueTooLarge { bits: 5, value: 16 })
+        ));
+        assert_eq!(writer.position(), 0);
+
+        writer.set_truncating(true);
+        writer.write_packed(&0x2au8, 5).unwrap();
+        writer.write_packed(&-17i32, 5).unwrap();
+        let buffer = writer.finish().unwrap();
+        let mut reader = BitPackReader::new(&buffer);
+        assert_eq!(reader.read_packed::<u8>(5).unwrap(), 0x0a);
+        assert_eq!(reader.read_packed::<i32>(5).unwrap(), 15);
+    }
+}
@@ -2,9 +2,7 @@ use crate::*;
 
 impl ReadValue for String {
     fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
-        let extended: bool = reader.read()?;
-        let length_bits = if extended { 15 } else { 7 };
-        let length: usize = reader.read_packed(length_bits)?;
+        let length = read_string_length(reader)?;
         let vec: Vec<u16> = reader.read_array(length)?;
         String::from_utf16(&vec).map_err(BitPackError::FromUtf16)
     }
@@ -20,23 +18,192 @@ impl WriteValue for String {
     }
 }
 
+/// The length prefix counts UTF-16 code units, so characters outside of the
+/// basic multilingual plane count twice.
 impl WriteValue for str {
     fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
-        debug_assert!(self.len() < 32768);
-        let extended = self.len() > 127;
-        let length_bits = if extended { 15 } else { 7 };
-        extended.write(writer)?;
-        self.len().write_packed(writer, length_bits)?;
+        write_string_length(writer, self.encode_utf16().count())?;
         self.encode_utf16()
             .try_for_each(|part| part.write(writer))?;
         Ok(())
     }
 
     fn bits(&self) -> usize {
-        debug_assert!(self.len() < 32768);
-        let extended = self.len() > 127;
-        let length_bits = if extended { 15 } else { 7 };
-        let content_bits = 16 * self.encode_utf16().count();
-        length_bits + content_bits
+        let units = self.encode_utf16().count();
+        string_length_bits(units) + 16 * units
+    }
+}
+
+/// A string sent with one byte per character instead of UTF-16 code units.
+///
+/// It uses the same length prefix as wide strings. Only ASCII characters are
+/// allowed, anything else fails with [`BitPackError::InvalidChar`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct AsciiString(pub String);
+
+impl AsciiString {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl From<String> for AsciiString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for AsciiString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl From<AsciiString> for String {
+    fn from(value: AsciiString) -> Self {
+        value.0
+    }
+}
+
+impl std::ops::Deref for AsciiString {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ReadValue for AsciiString {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        reader.read_ascii().map(Self)
+    }
+}
+
+impl WriteValue for AsciiString {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.write_ascii(&self.0)
+    }
+
+    fn bits(&self) -> usize {
+        ascii_bits(&self.0)
+    }
+}
+
+impl BitPackReader<'_> {
+    /// Reads a length-prefixed string with one byte per character.
+    pub fn read_ascii(&mut self) -> BitPackResult<String> {
+        let length = read_string_length(self)?;
+        let bytes = self.read_byte_vec(length)?;
+        match bytes.iter().find(|byte| !byte.is_ascii()) {
+            Some(byte) => Err(BitPackError::InvalidChar(*byte as u32)),
+            None => Ok(bytes.into_iter().map(char::from).collect()),
+        }
+    }
+}
+
+impl BitPackWriter<'_> {
+    /// Writes a length-prefixed string with one byte per character.
+    pub fn write_ascii(&mut self, value: &str) -> BitPackResult {
+        if let Some(c) = value.chars().find(|c| !c.is_ascii()) {
+            return Err(BitPackError::InvalidChar(c as u32));
+        }
+        write_string_length(self, value.len())?;
+        self.write_bytes(value.as_bytes())
+    }
+}
+
+/// Returns the size of `value` written with [`BitPackWriter::write_ascii`], in bits.
+pub fn ascii_bits(value: &str) -> usize {
+    string_length_bits(value.len()) + value.len() * 8
+}
+
+/// The longest string that can be written, limited by the 15-bit length prefix.
+const MAX_STRING_LENGTH: usize = 0x7fff;
+
+fn read_string_length(reader: &mut BitPackReader) -> BitPackResult<usize> {
+    let extended: bool = reader.read()?;
+    let length_bits = if extended { 15 } else { 7 };
+    let length = reader.read_packed(length_bits)?;
+    reader.check_length(length, reader.max_length())
+}
+
+fn write_string_length(writer: &mut BitPackWriter, length: usize) -> BitPackResult {
+    if length > MAX_STRING_LENGTH {
+        return Err(BitPackError::LengthTooLarge {
+            length,
+            max: MAX_STRING_LENGTH,
+        });
+    }
+    let extended = length > 127;
+    let length_bits = if extended { 15 } else { 7 };
+    extended.write(writer)?;
+    length.write_packed(writer, length_bits)
+}
+
+/// Returns the size of a string length prefix, including the extended flag.
+fn string_length_bits(length: usize) -> usize {
+    if length > 127 {
+        16
+    } else {
+        8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn write_and_read(value: &str) -> String {
+        let mut writer = BitPackWriter::growable();
+        value.write(&mut writer).unwrap();
+        assert_eq!(writer.position(), value.bits());
+        let buffer = writer.finish().unwrap();
+        BitPackReader::new(&buffer).read().unwrap()
+    }
+
+    #[test]
+    fn test_string_write_read() {
+        for name in [
+            "",
+            "Drusera",
+            "Éléonore",
+            "Zoë-Ångström",
+            "鈴木",
+            "キャラクター",
+            "𝒜𝒷",
+        ] {
+            assert_eq!(write_and_read(name), name);
+        }
+
+        // 64 characters but 192 bytes, which used to get an extended length
+        let name = "世".repeat(64);
+        assert_eq!(name.bits(), 8 + 64 * 16);
+        assert_eq!(write_and_read(&name), name);
+
+        // surrogate pairs count as two code units
+        let name = "😀".repeat(64);
+        assert_eq!(name.bits(), 16 + 128 * 16);
+        assert_eq!(write_and_read(&name), name);
+    }
+
+    #[test]
+    fn test_string_too_long() {
+        let mut writer = BitPackWriter::growable();
+        let result = "a".repeat(0x8000).write(&mut writer);
+        assert!(matches!(
+            result,
+            Err(BitPackError::LengthTooLarge {
+                length: 0x8000,
+                max: 0x7fff
+            })
+        ));
     }
 }
@@ -10,6 +10,15 @@ where
 pub trait WriteValue {
     fn write(&self, writer: &mut BitPackWriter) -> BitPackResult;
     fn bits(&self) -> usize;
+
+    /// Returns the bits written when starting at `position`, which differs
+    /// from [`WriteValue::bits`] for values that align themselves and the ones
+    /// holding them. Those holding other values pass the position of each one
+    /// on, so that the padding is counted wherever it ends up.
+    fn bits_at(&self, position: usize) -> usize {
+        let _ = position;
+        self.bits()
+    }
 }
 
 pub trait ReadPackedValue
@@ -37,6 +46,13 @@ where
 pub trait WriteArrayValue {
     fn write_array(&self, writer: &mut BitPackWriter) -> BitPackResult;
     fn bits_array(&self) -> usize;
+
+    /// Returns the bits written when starting at `position`, like
+    /// [`WriteValue::bits_at`].
+    fn bits_array_at(&self, position: usize) -> usize {
+        let _ = position;
+        self.bits_array()
+    }
 }
 
 pub trait ReadPackedArrayValue
@@ -0,0 +1,65 @@
+use crate::*;
+
+macro_rules! impl_tuple_values {
+    ( $( ( $($t: ident $i: tt),+ ) )* ) => {$(
+        impl<$($t),+> ReadValue for ($($t,)+)
+        where
+            $($t: ReadValue,)+
+        {
+            fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+                Ok(($(reader.read::<$t>()?,)+))
+            }
+        }
+
+        impl<$($t),+> WriteValue for ($($t,)+)
+        where
+            $($t: WriteValue,)+
+        {
+            fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+                $(writer.write(&self.$i)?;)+
+                Ok(())
+            }
+
+            fn bits(&self) -> usize {
+                self.bits_at(0)
+            }
+
+            fn bits_at(&self, position: usize) -> usize {
+                let mut bits = position;
+                $(bits += self.$i.bits_at(bits);)+
+                bits - position
+            }
+        }
+    )*};
+}
+
+impl_tuple_values! {
+    (A 0)
+    (A 0, B 1)
+    (A 0, B 1, C 2)
+    (A 0, B 1, C 2, D 3)
+    (A 0, B 1, C 2, D 3, E 4)
+    (A 0, B 1, C 2, D 3, E 4, F 5)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6)
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_tuple_write_read() {
+        let value: (u32, u16, bool, f32) = (6152, 17, true, 1.5);
+
+        let mut buffer = vec![0; 16];
+        let mut writer = BitPackWriter::new(&mut buffer);
+        writer.write(&value).unwrap();
+        assert_eq!(writer.position(), value.bits());
+
+        let mut reader = BitPackReader::new(&buffer);
+        let result: (u32, u16, bool, f32) = reader.read().unwrap();
+        assert_eq!(result, value);
+        assert_eq!(reader.position(), value.bits());
+    }
+}
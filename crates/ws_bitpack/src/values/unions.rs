@@ -0,0 +1,138 @@
+use crate::*;
+
+impl BitPackReader<'_> {
+    /// Reads a union preceded by its variant on `bits` bits.
+    pub fn read_inline_union<T>(&mut self, bits: usize) -> BitPackResult<T>
+    where
+        T: ReadUnionValue,
+    {
+        let variant = self.read_u64(bits)? as usize;
+        T::read_union(self, variant)
+    }
+
+    /// Reads `length` unions, each preceded by its variant on `bits` bits.
+    pub fn read_inline_union_array<T>(
+        &mut self,
+        length: usize,
+        bits: usize,
+    ) -> BitPackResult<Vec<T>>
+    where
+        T: ReadUnionValue,
+    {
+        // every item takes at least its variant
+        let mut vec = Vec::with_capacity(length.min(self.remaining_bits() / bits.max(1)));
+        while vec.len() < length {
+            vec.push(self.read_inline_union(bits)?);
+        }
+        Ok(vec)
+    }
+}
+
+impl BitPackWriter<'_> {
+    /// Writes the variant of a union on `bits` bits, followed by its value.
+    pub fn write_inline_union<T>(&mut self, value: &T, bits: usize) -> BitPackResult
+    where
+        T: WriteValue + UnionVariant,
+    {
+        self.write_u64(value.variant() as u64, bits)?;
+        value.write(self)
+    }
+
+    /// Writes unions with [`BitPackWriter::write_inline_union`], without their
+    /// count.
+    pub fn write_inline_union_array<T>(&mut self, values: &[T], bits: usize) -> BitPackResult
+    where
+        T: WriteValue + UnionVariant,
+    {
+        values
+            .iter()
+            .try_for_each(|value| self.write_inline_union(value, bits))
+    }
+}
+
+/// Returns the size of unions written with
+/// [`BitPackWriter::write_inline_union_array`] from `position`, in bits.
+pub fn inline_union_array_bits<T>(values: &[T], bits: usize, position: usize) -> usize
+where
+    T: WriteValue,
+{
+    let end = values.iter().fold(position, |end, value| {
+        end + bits + value.bits_at(end + bits)
+    });
+    end - position
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[derive(Debug, PartialEq)]
+    enum Shape {
+        Point,
+        Circle(u8),
+    }
+
+    impl ReadUnionValue for Shape {
+        fn read_union(reader: &mut BitPackReader, variant: usize) -> BitPackResult<Self> {
+            match variant {
+                0 => Ok(Self::Point),
+                3 => Ok(Self::Circle(reader.read()?)),
+                variant => Err(BitPackError::InvalidUnionVariant {
+                    type_name: "Shape",
+                    variant,
+                }),
+            }
+        }
+    }
+
+    impl UnionVariant for Shape {
+        fn variant(&self) -> usize {
+            match self {
+                Self::Point => 0,
+                Self::Circle(_) => 3,
+            }
+        }
+    }
+
+    impl WriteValue for Shape {
+        fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+            match self {
+                Self::Point => Ok(()),
+                Self::Circle(radius) => writer.write(radius),
+            }
+        }
+
+        fn bits(&self) -> usize {
+            match self {
+                Self::Point => 0,
+                Self::Circle(_) => 8,
+            }
+        }
+    }
+
+    #[test]
+    fn test_inline_union_write_read() {
+        let shapes = [Shape::Circle(0x55), Shape::Point, Shape::Circle(1)];
+        let mut writer = BitPackWriter::growable();
+        writer.write_inline_union_array(&shapes, 2).unwrap();
+        assert_eq!(writer.position(), inline_union_array_bits(&shapes, 2, 0));
+        assert_eq!(writer.position(), 2 + 8 + 2 + 2 + 8);
+        let buffer = writer.finish().unwrap();
+
+        let mut reader = BitPackReader::new(&buffer);
+        let read: Vec<Shape> = reader.read_inline_union_array(3, 2).unwrap();
+        assert_eq!(read, shapes);
+
+        let mut writer = BitPackWriter::growable();
+        assert!(matches!(
+            writer.write_inline_union(&Shape::Circle(1), 1),
+            Err(BitPackError::ValueTooLarge { bits: 1, .. })
+        ));
+
+        let mut reader = BitPackReader::new(&[0b01]);
+        assert!(matches!(
+            reader.read_inline_union::<Shape>(2),
+            Err(BitPackError::InvalidUnionVariant { variant: 1, .. })
+        ));
+    }
+}
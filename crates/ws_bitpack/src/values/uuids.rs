@@ -0,0 +1,87 @@
+use uuid::Uuid;
+
+use crate::*;
+
+/// GUIDs are sent as 16 aligned bytes using the mixed-endian layout of .NET's
+/// `Guid.ToByteArray()`. They align themselves, so their fields don't need
+/// `#[aligned]`.
+impl ReadValue for Uuid {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        reader.align()?;
+        let mut bytes = [0u8; 16];
+        reader.read_bytes(&mut bytes)?;
+        Ok(Uuid::from_bytes_le(bytes))
+    }
+}
+
+impl WriteValue for Uuid {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.align()?;
+        writer.write_bytes(&self.to_bytes_le())
+    }
+
+    /// The bits of a GUID written at an aligned position.
+    fn bits(&self) -> usize {
+        128
+    }
+
+    fn bits_at(&self, position: usize) -> usize {
+        position.next_multiple_of(8) - position + 128
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use crate::*;
+
+    #[test]
+    fn test_uuid_write_read() {
+        let guid = Uuid::parse_str("52a475ba-a2f8-491b-b0d8-86ed0d9e58a8").unwrap();
+        assert_eq!(guid.bits_at(0), 128);
+        assert_eq!(guid.bits_at(3), 5 + 128);
+
+        let mut writer = BitPackWriter::with_capacity(17);
+        writer.write_u64(0b101, 3).unwrap();
+        writer.write(&guid).unwrap();
+        assert_eq!(writer.position(), 3 + guid.bits_at(3));
+        let buffer = writer.finish().unwrap();
+        assert_eq!(buffer[0], 0b101);
+        assert_eq!(buffer[1..5], [0xba, 0x75, 0xa4, 0x52]);
+
+        let mut reader = BitPackReader::new(&buffer);
+        assert_eq!(reader.read_u64(3).unwrap(), 0b101);
+        assert_eq!(reader.read::<Uuid>().unwrap(), guid);
+        assert!(reader.is_at_end());
+    }
+
+    #[test]
+    fn test_uuid_in_compound_bits() {
+        let guid = Uuid::parse_str("52a475ba-a2f8-491b-b0d8-86ed0d9e58a8").unwrap();
+
+        fn written_bits<T: WriteValue>(value: &T, position: usize) -> usize {
+            let mut writer = BitPackWriter::growable();
+            writer.write_u64(0, position).unwrap();
+            writer.write(value).unwrap();
+            writer.position() - position
+        }
+
+        let tuple = (true, guid);
+        assert_eq!(tuple.bits(), 136);
+        assert_eq!(tuple.bits(), written_bits(&tuple, 0));
+        assert_eq!(tuple.bits_at(35), written_bits(&tuple, 35));
+
+        let option = (true, Some(Box::new(guid)));
+        assert_eq!(option.bits(), 136);
+        assert_eq!(option.bits_at(6), 2 + 128);
+        assert_eq!(option.bits_at(6), written_bits(&option, 6));
+
+        let items = vec![(false, guid), (true, guid)];
+        let mut writer = BitPackWriter::growable();
+        writer.write_u64(0, 3).unwrap();
+        writer.write_array(&items).unwrap();
+        assert_eq!(writer.position(), 3 + items.bits_array_at(3));
+        assert_eq!(items.bits_array(), 2 * 136);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::*;
+
+/// A UTF-16 string that always takes `N` code units, padded with zeros.
+///
+/// Reading stops the string at the first zero unit. Writing fails with
+/// [`BitPackError::LengthTooLarge`] if the string doesn't fit. Fields of type
+/// `String` can use the same format with `#[wide_fixed(N)]`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct WideStringFixed<const N: usize>(pub String);
+
+impl<const N: usize> WideStringFixed<N> {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl<const N: usize> From<String> for WideStringFixed<N> {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl<const N: usize> From<&str> for WideStringFixed<N> {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl<const N: usize> std::ops::Deref for WideStringFixed<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl<const N: usize> ReadValue for WideStringFixed<N> {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        reader.read_wide_fixed(N).map(Self)
+    }
+}
+
+impl<const N: usize> WriteValue for WideStringFixed<N> {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.write_wide_fixed(&self.0, N)
+    }
+
+    fn bits(&self) -> usize {
+        wide_fixed_bits(N)
+    }
+}
+
+/// A UTF-16 string terminated by a zero code unit.
+///
+/// Reading fails with [`BitPackError::LengthTooLarge`] if no terminator is
+/// found within the reader's maximum length, and writing fails with
+/// [`BitPackError::InvalidChar`] if the string contains a null character.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct CStringWide(pub String);
+
+impl CStringWide {
+    pub fn new(value: impl Into<String>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> String {
+        self.0
+    }
+}
+
+impl From<String> for CStringWide {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for CStringWide {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl std::ops::Deref for CStringWide {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl ReadValue for CStringWide {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        let mut units = Vec::new();
+        loop {
+            let unit: u16 = reader.read()?;
+            if unit == 0 {
+                break;
+            }
+            reader.check_length(units.len() + 1, reader.max_length())?;
+            units.push(unit);
+        }
+        String::from_utf16(&units)
+            .map(Self)
+            .map_err(BitPackError::FromUtf16)
+    }
+}
+
+impl WriteValue for CStringWide {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        if self.0.contains('\0') {
+            return Err(BitPackError::InvalidChar(0));
+        }
+        self.0
+            .encode_utf16()
+            .chain(std::iter::once(0))
+            .try_for_each(|unit| unit.write(writer))
+    }
+
+    fn bits(&self) -> usize {
+        (self.0.encode_utf16().count() + 1) * 16
+    }
+}
+
+impl BitPackReader<'_> {
+    /// Reads a UTF-16 string taking `units` code units, stopping it at the
+    /// first zero unit.
+    pub fn read_wide_fixed(&mut self, units: usize) -> BitPackResult<String> {
+        let mut buffer = (0..units)
+            .map(|_| self.read::<u16>())
+            .collect::<BitPackResult<Vec<_>>>()?;
+        if let Some(end) = buffer.iter().position(|unit| *unit == 0) {
+            buffer.truncate(end);
+        }
+        String::from_utf16(&buffer).map_err(BitPackError::FromUtf16)
+    }
+}
+
+impl BitPackWriter<'_> {
+    /// Writes a UTF-16 string on exactly `units` code units, padded with zeros.
+    pub fn write_wide_fixed(&mut self, value: &str, units: usize) -> BitPackResult {
+        let length = value.encode_utf16().count();
+        if length > units {
+            return Err(BitPackError::LengthTooLarge { length, max: units });
+        }
+        value
+            .encode_utf16()
+            .chain(std::iter::repeat_n(0, units - length))
+            .try_for_each(|unit| unit.write(self))
+    }
+}
+
+/// Returns the size of a string written with
+/// [`BitPackWriter::write_wide_fixed`], in bits.
+pub fn wide_fixed_bits(units: usize) -> usize {
+    units * 16
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_wide_fixed_write_read() {
+        let name: WideStringFixed<8> = "Zoë".into();
+        let mut writer = BitPackWriter::growable();
+        writer.write(&name).unwrap();
+        assert_eq!(writer.position(), 128);
+        let buffer = writer.finish().unwrap();
+        assert_eq!(&buffer[..8], [0x5a, 0, 0x6f, 0, 0xeb, 0, 0, 0]);
+        assert_eq!(
+            BitPackReader::new(&buffer)
+                .read::<WideStringFixed<8>>()
+                .unwrap(),
+            name
+        );
+
+        // a full string has no terminator
+        let full: WideStringFixed<3> = "abc".into();
+        let mut writer = BitPackWriter::growable();
+        writer.write(&full).unwrap();
+        let buffer = writer.finish().unwrap();
+        assert_eq!(
+            BitPackReader::new(&buffer)
+                .read::<WideStringFixed<3>>()
+                .unwrap(),
+            full
+        );
+
+        let mut writer = BitPackWriter::growable();
+        assert!(matches!(
+            writer.write(&WideStringFixed::<2>::from("abc")),
+            Err(BitPackError::LengthTooLarge { length: 3, max: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_cstring_wide_write_read() {
+        let name = CStringWide::from("鈴木");
+        let mut writer = BitPackWriter::growable();
+        writer.write(&name).unwrap();
+        assert_eq!(writer.position(), name.bits());
+        let buffer = writer.finish().unwrap();
+        assert_eq!(buffer.len(), 6);
+        assert_eq!(
+            BitPackReader::new(&buffer).read::<CStringWide>().unwrap(),
+            name
+        );
+
+        let mut writer = BitPackWriter::growable();
+        assert!(matches!(
+            writer.write(&CStringWide::from("a\0b")),
+            Err(BitPackError::InvalidChar(0))
+        ));
+
+        // the terminator is missing
+        let mut reader = BitPackReader::new(&[0x61, 0, 0x62, 0]);
+        assert!(matches!(
+            reader
+                .read::<CStringWide>()
+                .map_err(BitPackError::into_root),
+            Err(BitPackError::OutOfBounds { .. })
+        ));
+        let mut reader = BitPackReader::new(&[0x61, 0, 0x62, 0, 0, 0]);
+        reader.set_max_length(1);
+        assert!(matches!(
+            reader.read::<CStringWide>(),
+            Err(BitPackError::LengthTooLarge { length: 2, max: 1 })
+        ));
+    }
+}
@@ -0,0 +1,180 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::*;
+
+/// Smart pointers are read and written as the value they point to, which lets
+/// derived types hold themselves, like trees of quest objectives.
+macro_rules! impl_pointer_values {
+    ( $( $pointer: ident ),* ) => {$(
+        impl<T> ReadValue for $pointer<T>
+        where
+            T: ReadValue,
+        {
+            fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+                T::read(reader).map($pointer::new)
+            }
+        }
+
+        impl<T> WriteValue for $pointer<T>
+        where
+            T: WriteValue + ?Sized,
+        {
+            fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+                (**self).write(writer)
+            }
+
+            fn bits(&self) -> usize {
+                (**self).bits()
+            }
+
+            fn bits_at(&self, position: usize) -> usize {
+                (**self).bits_at(position)
+            }
+        }
+
+        impl<T> ReadPackedValue for $pointer<T>
+        where
+            T: ReadPackedValue,
+        {
+            fn read_packed(reader: &mut BitPackReader, bits: usize) -> BitPackResult<Self> {
+                T::read_packed(reader, bits).map($pointer::new)
+            }
+        }
+
+        impl<T> WritePackedValue for $pointer<T>
+        where
+            T: WritePackedValue + ?Sized,
+        {
+            fn write_packed(&self, writer: &mut BitPackWriter, bits: usize) -> BitPackResult {
+                (**self).write_packed(writer, bits)
+            }
+
+            fn bits_packed(&self, bits: usize) -> usize {
+                (**self).bits_packed(bits)
+            }
+        }
+
+        impl<T> ReadUnionValue for $pointer<T>
+        where
+            T: ReadUnionValue,
+        {
+            fn read_union(reader: &mut BitPackReader, variant: usize) -> BitPackResult<Self> {
+                T::read_union(reader, variant).map($pointer::new)
+            }
+        }
+
+        impl<T> UnionVariant for $pointer<T>
+        where
+            T: UnionVariant,
+        {
+            fn variant(&self) -> usize {
+                (**self).variant()
+            }
+        }
+    )*};
+}
+
+impl_pointer_values!(Box, Rc, Arc);
+
+/// An optional value preceded by a bit telling whether it is present.
+///
+/// Derived fields with `#[present(...)]` read and write the value itself
+/// instead, so that their condition decides.
+impl<T> ReadValue for Option<T>
+where
+    T: ReadValue,
+{
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        match reader.read_bit()? {
+            true => T::read(reader).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+impl<T> WriteValue for Option<T>
+where
+    T: WriteValue,
+{
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.write_bit(self.is_some())?;
+        match self {
+            Some(value) => value.write(writer),
+            None => Ok(()),
+        }
+    }
+
+    fn bits(&self) -> usize {
+        self.bits_at(0)
+    }
+
+    fn bits_at(&self, position: usize) -> usize {
+        1 + self.as_ref().map_or(0, |value| value.bits_at(position + 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::*;
+
+    #[test]
+    fn test_pointer_write_read() {
+        let value: (Box<u16>, Arc<String>) = (Box::new(0x1234), Arc::new("abc".to_string()));
+        let mut writer = BitPackWriter::growable();
+        writer.write(&value).unwrap();
+        assert_eq!(writer.position(), 16 + "abc".to_string().bits());
+        let mut packed = BitPackWriter::growable();
+        packed.write_packed(&Box::new(5u8), 3).unwrap();
+        assert_eq!(packed.position(), 3);
+
+        let buffer = writer.finish().unwrap();
+        let mut reader = BitPackReader::new(&buffer);
+        let result: (Box<u16>, Arc<String>) = reader.read().unwrap();
+        assert_eq!(result, value);
+    }
+
+    #[test]
+    fn test_option_write_read() {
+        let value: (u8, Option<u16>) = (7, Some(0x0102));
+        let mut writer = BitPackWriter::growable();
+        writer.write(&value).unwrap();
+        assert_eq!(writer.position(), value.bits());
+        let buffer = writer.finish().unwrap();
+        assert_eq!(
+            BitPackReader::new(&buffer)
+                .read::<(u8, Option<u16>)>()
+                .unwrap(),
+            value
+        );
+
+        let value: (u8, Option<u16>) = (7, None);
+        let mut writer = BitPackWriter::growable();
+        writer.write(&value).unwrap();
+        assert_eq!(value.bits(), 9);
+        let buffer = writer.finish().unwrap();
+        assert_eq!(buffer, [7, 0]);
+        assert_eq!(
+            BitPackReader::new(&buffer)
+                .read::<(u8, Option<u16>)>()
+                .unwrap(),
+            value
+        );
+
+        // values in the padding of the last byte are present
+        for value in [(true, Some(true)), (true, Some(false)), (true, None)] {
+            let mut writer = BitPackWriter::growable();
+            writer.write(&value).unwrap();
+            let buffer = writer.finish().unwrap();
+            assert_eq!(buffer.len(), 1);
+            assert_eq!(
+                BitPackReader::new(&buffer)
+                    .read::<(bool, Option<bool>)>()
+                    .unwrap(),
+                value
+            );
+        }
+    }
+}
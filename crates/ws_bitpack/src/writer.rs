@@ -1,7 +1,14 @@
-use crate::{BitPackError, BitPackResult, WriteArrayValue, WritePackedValue, WriteValue, WritePackedArrayValue};
+use crate::Trace;
+use crate::{
+    BitPackError, BitPackResult, WriteArrayValue, WritePackedArrayValue, WritePackedValue,
+    WriteValue,
+};
 
 /// A BitPack writer that can be used to write game packets.
 ///
+/// The writer either writes into a borrowed fixed-size buffer, or into an owned
+/// buffer that grows on demand (see [`BitPackWriter::growable`]).
+///
 /// While this guarantees not to panic, it should not be used anymore after any of
 /// its functions returns an error because the bit stream may be corrupted at that
 /// point.
@@ -9,33 +16,159 @@ use crate::{BitPackError, BitPackResult, WriteArrayValue, WritePackedValue, Writ
 /// This implementation is very simple and could be optimized.
 pub struct BitPackWriter<'a> {
     /// The buffer to which this is writing.
-    buffer: &'a mut [u8],
+    buffer: WriterBuffer<'a>,
     /// Represents the position of the writer in bits.
     position: usize,
+    /// Whether values wider than their bit count should be silently truncated.
+    truncating: bool,
+    /// The spans written so far, when tracing.
+    #[cfg(feature = "trace")]
+    trace: Option<Trace>,
+}
+
+/// Bits reserved by [`BitPackWriter::reserve`], to be written later with
+/// [`BitPackWriter::write_at`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[must_use = "reserved bits are zeros until written with write_at"]
+pub struct Placeholder {
+    position: usize,
+    bits: usize,
+}
+
+impl Placeholder {
+    /// Returns the position of the first reserved bit.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn bits(&self) -> usize {
+        self.bits
+    }
+}
+
+enum WriterBuffer<'a> {
+    /// A fixed-size buffer, writing past its end is an error.
+    Borrowed(&'a mut [u8]),
+    /// A buffer that grows as bytes are written.
+    Owned(Vec<u8>),
 }
 
 impl<'a> BitPackWriter<'a> {
     pub fn new(buffer: &'a mut [u8]) -> Self {
+        Self::with_position(buffer, 0)
+    }
+
+    pub fn with_position(buffer: &'a mut [u8], position: usize) -> Self {
         Self {
-            buffer,
+            buffer: WriterBuffer::Borrowed(buffer),
+            position,
+            truncating: false,
+            #[cfg(feature = "trace")]
+            trace: None,
+        }
+    }
+
+    /// Creates a writer that writes into its own buffer, growing it as needed.
+    pub fn growable() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates a growable writer with room for `bytes` bytes before reallocating.
+    pub fn with_capacity(bytes: usize) -> Self {
+        Self {
+            buffer: WriterBuffer::Owned(Vec::with_capacity(bytes)),
             position: 0,
+            truncating: false,
+            #[cfg(feature = "trace")]
+            trace: None,
         }
     }
 
-    pub fn with_position(buffer: &'a mut [u8], position: usize) -> Self {
-        Self { buffer, position }
+    /// Enables or disables truncation of values that don't fit in their bit count.
+    ///
+    /// By default, such writes fail with [`BitPackError::ValueTooLarge`]. When
+    /// truncating, only the lowest bits of the value are written instead.
+    pub fn set_truncating(&mut self, truncating: bool) {
+        self.truncating = truncating;
+    }
+
+    pub fn is_truncating(&self) -> bool {
+        self.truncating
+    }
+
+    /// Starts recording the spans of the labeled values written from now on.
+    ///
+    /// This does nothing unless the `trace` feature is enabled.
+    pub fn start_trace(&mut self) {
+        #[cfg(feature = "trace")]
+        {
+            self.trace = Some(Trace::new());
+        }
+    }
+
+    /// Stops recording and returns the spans recorded since
+    /// [`BitPackWriter::start_trace`]. Always returns `None` without the `trace`
+    /// feature.
+    pub fn take_trace(&mut self) -> Option<Trace> {
+        #[cfg(feature = "trace")]
+        return self.trace.take();
+        #[cfg(not(feature = "trace"))]
+        None
+    }
+
+    /// Marks the start of a labeled value when tracing. Does nothing otherwise.
+    #[inline]
+    pub fn trace_enter(&mut self, label: &'static str) {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            trace.enter(label, self.position);
+        }
+        #[cfg(not(feature = "trace"))]
+        let _ = label;
+    }
+
+    /// Marks the end of the last labeled value when tracing. Does nothing
+    /// otherwise.
+    #[inline]
+    pub fn trace_exit(&mut self) {
+        #[cfg(feature = "trace")]
+        if let Some(trace) = &mut self.trace {
+            trace.exit(self.position);
+        }
     }
 
     pub fn position(&self) -> usize {
         self.position
     }
 
+    /// Returns the number of bytes touched by the writer so far, including the
+    /// partially written last byte.
+    pub fn len_bytes(&self) -> usize {
+        self.position.div_ceil(8)
+    }
+
+    /// Aligns the writer and returns the bytes that were written.
+    ///
+    /// For a growable writer, this returns its buffer without copying. For a
+    /// borrowed buffer, the written part is copied.
+    pub fn finish(mut self) -> BitPackResult<Vec<u8>> {
+        self.align()?;
+        let length = self.len_bytes();
+        match self.buffer {
+            WriterBuffer::Borrowed(buffer) => Ok(buffer[..length].to_vec()),
+            WriterBuffer::Owned(mut buffer) => {
+                buffer.truncate(length);
+                Ok(buffer)
+            }
+        }
+    }
+
     /// Aligns the writer's position to the next byte by finishing the current byte
     /// with 0's.
     ///
     /// If the writer is already aligned, this does nothing.
     pub fn align(&mut self) -> BitPackResult {
-        while self.position % 8 != 0 {
+        while !self.position.is_multiple_of(8) {
             self.write_bit(false)?;
         }
 
@@ -46,7 +179,7 @@ impl<'a> BitPackWriter<'a> {
         let pos_in_buffer = self.position / 8;
         let pos_in_byte = self.position % 8;
 
-        match self.buffer.get_mut(pos_in_buffer) {
+        match self.byte_mut(pos_in_buffer) {
             Some(byte) => {
                 let rhs = 1 << pos_in_byte;
                 if bit {
@@ -58,11 +191,43 @@ impl<'a> BitPackWriter<'a> {
 
                 Ok(())
             }
-            None => Err(BitPackError::OutOfBounds),
+            None => Err(BitPackError::OutOfBounds {
+                position: self.position,
+                needed: 1,
+                available: 0,
+            }),
+        }
+    }
+
+    /// Returns the byte at `index`, growing an owned buffer up to it if needed.
+    fn byte_mut(&mut self, index: usize) -> Option<&mut u8> {
+        match &mut self.buffer {
+            WriterBuffer::Borrowed(buffer) => buffer.get_mut(index),
+            WriterBuffer::Owned(buffer) => {
+                if index >= buffer.len() {
+                    buffer.resize(index + 1, 0);
+                }
+                buffer.get_mut(index)
+            }
         }
     }
 
+    /// Writes the lowest `bits` bits of `value`.
+    ///
+    /// Unless the writer is truncating, this fails with
+    /// [`BitPackError::ValueTooLarge`] if `value` doesn't fit in `bits` bits.
+    /// It always fails with [`BitPackError::TooManyBits`] past 64 bits.
     pub fn write_u64(&mut self, value: u64, bits: usize) -> BitPackResult {
+        if bits > 64 {
+            return Err(BitPackError::TooManyBits { bits });
+        }
+        if !self.truncating && bits < 64 && value >> bits != 0 {
+            return Err(BitPackError::ValueTooLarge {
+                bits,
+                value: value as i128,
+            });
+        }
+
         for i in 0..bits {
             self.write_bit(((value >> i) & 1) != 0)?;
         }
@@ -74,6 +239,10 @@ impl<'a> BitPackWriter<'a> {
         self.write_u64(value.to_bits() as u64, 32)
     }
 
+    pub fn write_f64(&mut self, value: f64) -> BitPackResult {
+        self.write_u64(value.to_bits(), 64)
+    }
+
     pub fn write_bytes(&mut self, bytes: &[u8]) -> BitPackResult {
         for byte in bytes {
             self.write_u64(*byte as u64, 8)?;
@@ -82,6 +251,42 @@ impl<'a> BitPackWriter<'a> {
         Ok(())
     }
 
+    /// Writes the length of `bytes` on `length_bits` bits followed by the raw bytes.
+    pub fn write_blob(&mut self, bytes: &[u8], length_bits: usize) -> BitPackResult {
+        let max = max_for_bits(length_bits);
+        if bytes.len() > max {
+            return Err(BitPackError::LengthTooLarge {
+                length: bytes.len(),
+                max,
+            });
+        }
+
+        self.write_u64(bytes.len() as u64, length_bits)?;
+        self.write_bytes(bytes)
+    }
+
+    /// Skips `bits` bits, writing zeros, so that a value only known later, like
+    /// the size of what follows, can be written there with
+    /// [`BitPackWriter::write_at`].
+    pub fn reserve(&mut self, bits: usize) -> BitPackResult<Placeholder> {
+        let position = self.position;
+        self.write_u64(0, bits)?;
+        Ok(Placeholder { position, bits })
+    }
+
+    /// Writes the lowest bits of `value` in bits reserved earlier, without
+    /// moving the writer.
+    ///
+    /// Like [`BitPackWriter::write_u64`], this fails with
+    /// [`BitPackError::ValueTooLarge`] if `value` doesn't fit in the reserved
+    /// bits, unless the writer is truncating.
+    pub fn write_at(&mut self, placeholder: Placeholder, value: u64) -> BitPackResult {
+        let position = std::mem::replace(&mut self.position, placeholder.position);
+        let result = self.write_u64(value, placeholder.bits);
+        self.position = position;
+        result
+    }
+
     pub fn write<T>(&mut self, value: &T) -> BitPackResult
     where
         T: WriteValue,
@@ -111,6 +316,15 @@ impl<'a> BitPackWriter<'a> {
     }
 }
 
+/// Returns the largest value that fits in `bits` bits.
+pub(crate) fn max_for_bits(bits: usize) -> usize {
+    if bits >= usize::BITS as usize {
+        usize::MAX
+    } else {
+        (1 << bits) - 1
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,6 +357,11 @@ mod tests {
         let mut writer = BitPackWriter::new(&mut buffer);
         assert!(writer.write_u64(0, 9).is_ok());
         assert_eq!(writer.position(), 9);
+        assert!(matches!(
+            writer.write_u64(0, 65),
+            Err(BitPackError::TooManyBits { bits: 65 })
+        ));
+        assert_eq!(writer.position(), 9);
     }
 
     #[test]
@@ -154,6 +373,38 @@ mod tests {
         writer.write_u64(0, 32).unwrap();
     }
 
+    #[test]
+    fn test_growable_writer() {
+        let mut writer = BitPackWriter::growable();
+        assert!(writer.write_u64(47, 24).is_ok());
+        assert!(writer.write_u64(2, 11).is_ok());
+        assert_eq!(writer.len_bytes(), 5);
+        assert_eq!(hex::encode(writer.finish().unwrap()), "2f00000200");
+
+        let mut buffer = vec![0; 10];
+        let mut writer = BitPackWriter::new(&mut buffer);
+        assert!(writer.write_u64(0x1ff, 9).is_ok());
+        assert_eq!(writer.finish().unwrap(), [0xff, 0x01]);
+    }
+
+    #[test]
+    fn test_reserve_and_write_at() {
+        let mut writer = BitPackWriter::growable();
+        writer.write_u64(0b101, 3).unwrap();
+        let size = writer.reserve(10).unwrap();
+        assert_eq!((size.position(), size.bits()), (3, 10));
+        writer.write_u64(0xff, 8).unwrap();
+
+        writer.write_at(size, 0x3ff).unwrap();
+        assert_eq!(writer.position(), 21);
+        assert!(matches!(
+            writer.write_at(size, 0x400),
+            Err(BitPackError::ValueTooLarge { bits: 10, .. })
+        ));
+        assert_eq!(writer.position(), 21);
+        assert_eq!(writer.finish().unwrap(), [0xfd, 0xff, 0x1f]);
+    }
+
     #[test]
     fn test_simple_message() {
         let mut buffer = vec![0; 47];
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::*;
+
+#[derive(Debug)]
+struct Route {
+    /// Tells the connections of a node apart, when one replaces another.
+    connection: u64,
+    sender: UnboundedSender<Envelope>,
+}
+
+#[derive(Debug, Default)]
+struct Routes {
+    nodes: Mutex<HashMap<Node, Route>>,
+    last_connection: AtomicU64,
+}
+
+impl Routes {
+    /// Adds a node, replacing the connection it had, and returns the id of
+    /// its connection and the envelopes routed to it.
+    fn join(&self, node: Node) -> (u64, UnboundedReceiver<Envelope>) {
+        let connection = self.last_connection.fetch_add(1, Ordering::Relaxed) + 1;
+        let (sender, receiver) = unbounded_channel();
+        let route = Route { connection, sender };
+        if self
+            .nodes
+            .lock()
+            .unwrap()
+            .insert(node.clone(), route)
+            .is_some()
+        {
+            tracing::warn!("Cluster node {node} reconnected, dropping its old connection");
+        }
+        (connection, receiver)
+    }
+
+    /// Removes a node, unless a newer connection replaced it.
+    fn leave(&self, node: &Node, connection: u64) {
+        let mut nodes = self.nodes.lock().unwrap();
+        if nodes
+            .get(node)
+            .is_some_and(|route| route.connection == connection)
+        {
+            nodes.remove(node);
+        }
+    }
+
+    fn route(&self, envelope: Envelope) {
+        let nodes = self.nodes.lock().unwrap();
+        match &envelope.to {
+            Target::Node(node) => match nodes.get(node) {
+                Some(route) => {
+                    let _ = route.sender.send(envelope);
+                }
+                None => tracing::debug!(
+                    "Dropped a message from {} to {node}, which isn't connected",
+                    envelope.from
+                ),
+            },
+            Target::Broadcast => {
+                for (node, route) in nodes.iter() {
+                    if *node != envelope.from {
+                        let _ = route.sender.send(envelope.clone());
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// The hub of the cluster, which the nodes connect to and which routes their
+/// envelopes.
+///
+/// The hub keeps no envelope: those sent to a node that isn't connected are
+/// dropped.
+pub struct ClusterHub {
+    listener: TcpListener,
+    routes: Arc<Routes>,
+}
+
+impl ClusterHub {
+    pub async fn bind<A: ToSocketAddrs>(addr: A) -> ClusterResult<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            routes: Default::default(),
+        })
+    }
+
+    pub fn local_addr(&self) -> ClusterResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Adds a node of the process running the hub, which doesn't need to
+    /// connect to it.
+    pub fn join(&self, node: Node) -> ClusterNode {
+        let (connection, incoming) = self.routes.join(node.clone());
+        let (outgoing, mut to_route) = unbounded_channel();
+        let routes = self.routes.clone();
+        let local = node.clone();
+        tokio::spawn(async move {
+            while let Some(envelope) = to_route.recv().await {
+                routes.route(envelope);
+            }
+            routes.leave(&local, connection);
+        });
+        ClusterNode::new(node, outgoing, incoming)
+    }
+
+    /// Accepts nodes forever, serving each of them in its own task.
+    pub async fn run(self) -> ClusterResult {
+        loop {
+            let (stream, addr) = self.listener.accept().await?;
+            let routes = self.routes.clone();
+            tokio::spawn(async move {
+                if let Err(error) = serve(stream, routes).await {
+                    tracing::warn!("Cluster connection from {addr} failed: {error}");
+                }
+            });
+        }
+    }
+}
+
+async fn serve(stream: TcpStream, routes: Arc<Routes>) -> ClusterResult {
+    stream.set_nodelay(true)?;
+    let (mut reader, mut writer) = stream.into_split();
+    let Hello { node } = read_frame(&mut reader).await?;
+    tracing::info!("Cluster node {node} connected");
+    let (connection, mut incoming) = routes.join(node.clone());
+    let writing = tokio::spawn(async move {
+        while let Some(envelope) = incoming.recv().await {
+            if write_frame(&mut writer, &envelope).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let result = loop {
+        match read_frame::<_, Envelope>(&mut reader).await {
+            Ok(mut envelope) => {
+                envelope.from = node.clone();
+                routes.route(envelope);
+            }
+            Err(ClusterError::Closed) => break Ok(()),
+            Err(error) => break Err(error),
+        }
+    };
+    routes.leave(&node, connection);
+    writing.abort();
+    tracing::info!("Cluster node {node} disconnected");
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    fn released(character_id: u64) -> ClusterMessage {
+        ClusterMessage::CharacterReleased {
+            character_id,
+            to_realm: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_routing() {
+        let hub = ClusterHub::bind("127.0.0.1:0").await.unwrap();
+        let addr = hub.local_addr().unwrap();
+        let mut realm = hub.join(Node::Realm);
+        tokio::spawn(hub.run());
+
+        // a broadcast reaching the realm tells that the node joined
+        let mut first = ClusterNode::connect(addr, Node::World(1)).await.unwrap();
+        first.send(Target::Broadcast, released(1)).unwrap();
+        let envelope = realm.recv().await.unwrap();
+        assert_eq!(envelope.from, Node::World(1));
+        assert_eq!(envelope.message, released(1));
+
+        let mut second = ClusterNode::connect(addr, Node::World(2)).await.unwrap();
+        second.send(Target::Broadcast, released(2)).unwrap();
+        assert_eq!(realm.recv().await.unwrap().from, Node::World(2));
+        assert_eq!(first.recv().await.unwrap().from, Node::World(2));
+
+        let ticket = ClusterMessage::SessionTicket {
+            ticket: Uuid::from_u128(7),
+            realm_id: 1,
+        };
+        realm.send_to(Node::World(1), ticket.clone()).unwrap();
+        realm.send_to(Node::World(2), released(3)).unwrap();
+        let envelope = first.recv().await.unwrap();
+        assert_eq!(envelope.from, Node::Realm);
+        assert_eq!(envelope.message, ticket);
+        // the second world only got what was sent to it
+        assert_eq!(second.recv().await.unwrap().message, released(3));
+    }
+}
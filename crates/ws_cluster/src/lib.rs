@@ -0,0 +1,66 @@
+//! The internal bus between the server processes of the sandbox.
+//!
+//! The realm server runs a [`ClusterHub`] that the world servers connect to
+//! as [`ClusterNode`]s, so that the realm can hand them the tickets it issues
+//! and the characters moving between realms, without the servers having to
+//! run in one process. The hub routes the [`Envelope`]s sent by a node to the
+//! node they are addressed to, or to every other node.
+//!
+//! Frames are a little-endian `u32` length followed by that much JSON, which
+//! keeps the protocol easy to inspect. It is meant for a private network:
+//! nodes aren't authenticated.
+
+mod hub;
+mod node;
+mod protocol;
+
+pub use hub::*;
+pub use node::*;
+pub use protocol::*;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ClusterError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// A frame was longer than [`MAX_FRAME_LENGTH`].
+    FrameTooLong(usize),
+    /// The connection to the hub was closed.
+    Closed,
+}
+
+impl fmt::Display for ClusterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Json(error) => write!(f, "invalid cluster frame: {error}"),
+            Self::FrameTooLong(length) => write!(f, "cluster frame of {length} bytes is too long"),
+            Self::Closed => write!(f, "the cluster connection is closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClusterError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Json(error) => Some(error),
+            Self::FrameTooLong(_) | Self::Closed => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ClusterError {
+    fn from(error: std::io::Error) -> Self {
+        ClusterError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ClusterError {
+    fn from(error: serde_json::Error) -> Self {
+        ClusterError::Json(error)
+    }
+}
+
+pub type ClusterResult<T = ()> = Result<T, ClusterError>;
@@ -0,0 +1,115 @@
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::*;
+
+/// Sends envelopes from a node, and is cheap to clone.
+#[derive(Debug, Clone)]
+pub struct ClusterSender {
+    node: Node,
+    outgoing: UnboundedSender<Envelope>,
+}
+
+impl ClusterSender {
+    pub fn node(&self) -> &Node {
+        &self.node
+    }
+
+    /// Queues a message for the hub to route, which fails once the node is
+    /// disconnected.
+    pub fn send(&self, to: Target, message: ClusterMessage) -> ClusterResult {
+        let envelope = Envelope {
+            from: self.node.clone(),
+            to,
+            message,
+        };
+        self.outgoing
+            .send(envelope)
+            .map_err(|_| ClusterError::Closed)
+    }
+
+    pub fn send_to(&self, node: Node, message: ClusterMessage) -> ClusterResult {
+        self.send(Target::Node(node), message)
+    }
+}
+
+/// A process connected to the [`ClusterHub`], sending envelopes to the other
+/// nodes and receiving theirs.
+#[derive(Debug)]
+pub struct ClusterNode {
+    sender: ClusterSender,
+    incoming: UnboundedReceiver<Envelope>,
+}
+
+impl ClusterNode {
+    pub(crate) fn new(
+        node: Node,
+        outgoing: UnboundedSender<Envelope>,
+        incoming: UnboundedReceiver<Envelope>,
+    ) -> Self {
+        Self {
+            sender: ClusterSender { node, outgoing },
+            incoming,
+        }
+    }
+
+    /// Connects to the hub listening on `addr`, as `node`. A node connecting
+    /// with the name of another takes its place.
+    pub async fn connect<A: ToSocketAddrs>(addr: A, node: Node) -> ClusterResult<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        stream.set_nodelay(true)?;
+        let (mut reader, mut writer) = stream.into_split();
+        write_frame(&mut writer, &Hello { node: node.clone() }).await?;
+
+        let (outgoing, mut to_write) = unbounded_channel::<Envelope>();
+        tokio::spawn(async move {
+            while let Some(envelope) = to_write.recv().await {
+                if let Err(error) = write_frame(&mut writer, &envelope).await {
+                    tracing::warn!("Failed to send to the cluster hub: {error}");
+                    break;
+                }
+            }
+        });
+        let (received, incoming) = unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                match read_frame::<_, Envelope>(&mut reader).await {
+                    Ok(envelope) => {
+                        if received.send(envelope).is_err() {
+                            break;
+                        }
+                    }
+                    Err(ClusterError::Closed) => break,
+                    Err(error) => {
+                        tracing::warn!("Failed to receive from the cluster hub: {error}");
+                        break;
+                    }
+                }
+            }
+        });
+        Ok(Self::new(node, outgoing, incoming))
+    }
+
+    pub fn node(&self) -> &Node {
+        self.sender.node()
+    }
+
+    /// Returns a sender of the node, to send from other tasks.
+    pub fn sender(&self) -> ClusterSender {
+        self.sender.clone()
+    }
+
+    pub fn send(&self, to: Target, message: ClusterMessage) -> ClusterResult {
+        self.sender.send(to, message)
+    }
+
+    pub fn send_to(&self, node: Node, message: ClusterMessage) -> ClusterResult {
+        self.sender.send_to(node, message)
+    }
+
+    /// Waits for the next envelope sent to the node, or returns `None` once
+    /// it is disconnected from the hub.
+    pub async fn recv(&mut self) -> Option<Envelope> {
+        self.incoming.recv().await
+    }
+}
@@ -0,0 +1,146 @@
+use std::fmt;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::{ClusterError, ClusterResult};
+
+/// The longest frame read or written, far more than any message needs.
+pub const MAX_FRAME_LENGTH: usize = 1 << 20;
+
+/// A server process connected to the cluster.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Node {
+    /// The realm server, which runs the hub.
+    Realm,
+    /// The world server of a realm.
+    World(u32),
+    /// An administration tool, such as `ws_admin`.
+    Admin,
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Realm => write!(f, "realm"),
+            Self::World(realm_id) => write!(f, "world-{realm_id}"),
+            Self::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+/// The first frame sent by a node, naming itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hello {
+    pub node: Node,
+}
+
+/// Who an [`Envelope`] is delivered to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Target {
+    Node(Node),
+    /// Every node but the sender.
+    Broadcast,
+}
+
+/// A message routed by the hub. Its sender is set by the hub, whatever the
+/// node claimed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope {
+    pub from: Node,
+    pub to: Target,
+    pub message: ClusterMessage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClusterMessage {
+    /// A ticket was issued for a realm, the client presents it to the world
+    /// server of the realm next.
+    SessionTicket { ticket: Uuid, realm_id: u32 },
+    /// Asks the world server a character is on to let it go, so that it can
+    /// move to another realm.
+    CharacterTransfer { character_id: u64, to_realm: u32 },
+    /// The character of a transfer isn't on the world server anymore.
+    CharacterReleased { character_id: u64, to_realm: u32 },
+    /// Asks the world servers to disconnect the session with this key in the
+    /// database, which only the one holding it answers.
+    CloseSession { key: Uuid },
+    /// The session of a [`ClusterMessage::CloseSession`] was closed.
+    SessionClosed { key: Uuid },
+}
+
+/// Reads a frame and decodes its JSON.
+pub async fn read_frame<R, T>(reader: &mut R) -> ClusterResult<T>
+where
+    R: AsyncRead + Unpin,
+    T: DeserializeOwned,
+{
+    let length = match reader.read_u32_le().await {
+        Ok(length) => length as usize,
+        Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => {
+            return Err(ClusterError::Closed)
+        }
+        Err(error) => return Err(error.into()),
+    };
+    if length > MAX_FRAME_LENGTH {
+        return Err(ClusterError::FrameTooLong(length));
+    }
+    let mut json = vec![0; length];
+    reader.read_exact(&mut json).await?;
+    Ok(serde_json::from_slice(&json)?)
+}
+
+/// Encodes a value as JSON and writes it in a frame.
+pub async fn write_frame<W, T>(writer: &mut W, value: &T) -> ClusterResult
+where
+    W: AsyncWrite + Unpin,
+    T: Serialize,
+{
+    let json = serde_json::to_vec(value)?;
+    if json.len() > MAX_FRAME_LENGTH {
+        return Err(ClusterError::FrameTooLong(json.len()));
+    }
+    let mut frame = Vec::with_capacity(4 + json.len());
+    frame.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    frame.extend_from_slice(&json);
+    writer.write_all(&frame).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frames() {
+        let envelope = Envelope {
+            from: Node::Realm,
+            to: Target::Node(Node::World(1)),
+            message: ClusterMessage::SessionTicket {
+                ticket: Uuid::nil(),
+                realm_id: 1,
+            },
+        };
+        let mut frame = Vec::new();
+        write_frame(&mut frame, &envelope).await.unwrap();
+        let json = br#"{"from":"realm","to":{"node":{"world":1}},"message":{"type":"session_ticket","ticket":"00000000-0000-0000-0000-000000000000","realm_id":1}}"#;
+        assert_eq!(frame[..4], (json.len() as u32).to_le_bytes());
+        assert_eq!(frame[4..], json[..]);
+
+        let read: Envelope = read_frame(&mut &frame[..]).await.unwrap();
+        assert_eq!(read, envelope);
+        assert!(matches!(
+            read_frame::<_, Envelope>(&mut &frame[..10]).await,
+            Err(ClusterError::Io(_))
+        ));
+        assert!(matches!(
+            read_frame::<_, Envelope>(&mut &[][..]).await,
+            Err(ClusterError::Closed)
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::*;
+
+/// Generates the Rust code of every type of a schema.
+///
+/// The code expects `ws_bitpack::*` and `ws_messages::*` to be in scope, which
+/// [`generate_file`] takes care of.
+pub fn generate(schema: &Schema) -> String {
+    let mut out = String::new();
+    for def in &schema.enums {
+        write_enum(&mut out, def);
+    }
+    for def in &schema.structs {
+        write_struct(&mut out, def, false);
+    }
+    for def in &schema.unions {
+        write_union(&mut out, def);
+    }
+    for def in &schema.messages {
+        write_struct(&mut out, def, true);
+    }
+    out
+}
+
+/// Reads a YAML schema and writes the generated code to `out_path`, with a
+/// header saying where it comes from.
+pub fn generate_file(schema_path: impl AsRef<Path>, out_path: impl AsRef<Path>) -> CodegenResult {
+    let schema_path = schema_path.as_ref();
+    let schema = Schema::from_yaml(&std::fs::read_to_string(schema_path)?)?;
+    let name = schema_path.file_name().map_or_else(
+        || schema_path.display().to_string(),
+        |name| name.to_string_lossy().into(),
+    );
+    let code = format!(
+        "// Generated by ws_codegen from {name}, do not edit.\n\n\
+         use ws_bitpack::*;\n\
+         use ws_messages::*;\n\n{}",
+        generate(&schema)
+    );
+    std::fs::write(out_path, code)?;
+    Ok(())
+}
+
+fn write_doc(out: &mut String, doc: &Option<String>, indent: &str) {
+    if let Some(doc) = doc {
+        for line in doc.trim().lines() {
+            let line = line.trim_end();
+            match line.is_empty() {
+                true => writeln!(out, "{indent}///").unwrap(),
+                false => writeln!(out, "{indent}/// {line}").unwrap(),
+            }
+        }
+    }
+}
+
+fn write_enum(out: &mut String, def: &EnumDef) {
+    write_doc(out, &def.doc, "");
+    writeln!(
+        out,
+        "#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq)]"
+    )
+    .unwrap();
+    writeln!(out, "#[repr({})]", def.repr).unwrap();
+    writeln!(out, "pub enum {} {{", def.name).unwrap();
+    for variant in &def.variants {
+        writeln!(out, "    {} = {},", variant.name, variant.value).unwrap();
+    }
+    writeln!(out, "}}\n").unwrap();
+}
+
+fn write_struct(out: &mut String, def: &StructDef, is_message: bool) {
+    write_doc(out, &def.doc, "");
+    match is_message {
+        true => {
+            writeln!(
+                out,
+                "#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]"
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "#[message_id({:#06x})]",
+                def.opcode.unwrap_or_default()
+            )
+            .unwrap();
+        }
+        false => writeln!(out, "#[derive(MessageStruct, Debug, Clone, PartialEq)]").unwrap(),
+    }
+    match def.fields.is_empty() {
+        true => writeln!(out, "pub struct {} {{}}\n", def.name).unwrap(),
+        false => {
+            writeln!(out, "pub struct {} {{", def.name).unwrap();
+            write_fields(out, &def.fields, "    ", "pub ");
+            writeln!(out, "}}\n").unwrap();
+        }
+    }
+}
+
+fn write_union(out: &mut String, def: &UnionDef) {
+    write_doc(out, &def.doc, "");
+    writeln!(out, "#[derive(MessageUnion, Debug, Clone, PartialEq)]").unwrap();
+    writeln!(out, "pub enum {} {{", def.name).unwrap();
+    for variant in &def.variants {
+        match variant.fields.is_empty() {
+            true => writeln!(out, "    {} {{}},", variant.name).unwrap(),
+            false => {
+                writeln!(out, "    {} {{", variant.name).unwrap();
+                write_fields(out, &variant.fields, "        ", "");
+                writeln!(out, "    }},").unwrap();
+            }
+        }
+    }
+    writeln!(out, "}}\n").unwrap();
+}
+
+fn write_fields(out: &mut String, fields: &[FieldDef], indent: &str, visibility: &str) {
+    for field in fields {
+        write_doc(out, &field.doc, indent);
+        let mut attrs = Vec::new();
+        if field.aligned {
+            attrs.push("aligned".to_string());
+        }
+        if let Some(bits) = field.packed {
+            attrs.push(format!("packed({bits})"));
+        }
+        if let Some(length) = &field.length {
+            attrs.push(format!("length({length})"));
+        }
+        if let Some(items) = &field.length_of {
+            attrs.push(format!("length_of({items})"));
+        }
+        if let Some(variant) = &field.variant {
+            attrs.push(format!("variant({variant})"));
+        }
+        if field.ascii {
+            attrs.push("ascii".to_string());
+        }
+        if field.bytes {
+            attrs.push("bytes".to_string());
+        }
+        if let Some(present) = &field.present {
+            attrs.push(format!("present({present})"));
+        }
+        if field.trailing {
+            attrs.push("trailing".to_string());
+        }
+        for attr in attrs {
+            writeln!(out, "{indent}#[{attr}]").unwrap();
+        }
+        writeln!(out, "{indent}{visibility}{}: {},", field.name, field.ty).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCHEMA: &str = r#"
+enums:
+  - name: Faction
+    repr: u16
+    variants:
+      - { name: Exile, value: 166 }
+      - { name: Dominion, value: 167 }
+unions:
+  - name: Target
+    variants:
+      - name: None
+      - name: Unit
+        fields:
+          - { name: guid, type: u64 }
+messages:
+  - name: ServerCharacterList
+    opcode: 0x0117
+    doc: The characters of the account.
+    fields:
+      - { name: count, type: u32, length_of: characters }
+      - { name: characters, type: "Vec<Faction>", packed: 14, length: count }
+      - { name: kind, type: u8, packed: 2 }
+      - { name: target, type: Target, variant: kind }
+"#;
+
+    #[test]
+    fn test_generate() {
+        let schema = Schema::from_yaml(SCHEMA).unwrap();
+        assert_eq!(
+            generate(&schema),
+            r#"#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u16)]
+pub enum Faction {
+    Exile = 166,
+    Dominion = 167,
+}
+
+#[derive(MessageUnion, Debug, Clone, PartialEq)]
+pub enum Target {
+    None {},
+    Unit {
+        guid: u64,
+    },
+}
+
+/// The characters of the account.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0117)]
+pub struct ServerCharacterList {
+    #[length_of(characters)]
+    pub count: u32,
+    #[packed(14)]
+    #[length(count)]
+    pub characters: Vec<Faction>,
+    #[packed(2)]
+    pub kind: u8,
+    #[variant(kind)]
+    pub target: Target,
+}
+
+"#
+        );
+    }
+
+    #[test]
+    fn test_invalid_schema() {
+        let invalid = |yaml: &str| match Schema::from_yaml(yaml) {
+            Err(CodegenError::Invalid(message)) => message,
+            result => panic!("Unexpected result {result:?}"),
+        };
+        assert_eq!(
+            invalid("messages: [{ name: Hello, fields: [] }]"),
+            "Message Hello has no opcode"
+        );
+        assert_eq!(
+            invalid("structs: [{ name: A, fields: [{ name: b, type: u8, variant: c }] }]"),
+            "A.b takes its variant from c, which isn't a previous field"
+        );
+        assert_eq!(
+            invalid("structs: [{ name: A, fields: [{ name: b, type: String, ascii: true, packed: 3 }] }]"),
+            "A.b has an invalid combination of attributes"
+        );
+        let error = Schema::from_yaml("structs: [{ name: A, size: 3 }]").unwrap_err();
+        assert!(matches!(error, CodegenError::Yaml(_)));
+        assert!(error.to_string().starts_with("invalid YAML: "), "{error}");
+    }
+}
@@ -0,0 +1,65 @@
+//! Generates message definitions from a schema.
+//!
+//! Message layouts are described in YAML, and turned into Rust types with the
+//! attributes of the `ws_messages` derive macros. This can run from a build
+//! script:
+//!
+//! ```no_run
+//! let out_dir = std::env::var("OUT_DIR").unwrap();
+//! ws_codegen::generate_file("messages.yaml", format!("{out_dir}/messages.rs")).unwrap();
+//! println!("cargo:rerun-if-changed=messages.yaml");
+//! ```
+//!
+//! with the generated file brought in with
+//! `include!(concat!(env!("OUT_DIR"), "/messages.rs"));`, or from the
+//! `ws_codegen` binary to check the generated code in.
+
+mod generate;
+mod schema;
+
+pub use generate::*;
+pub use schema::*;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum CodegenError {
+    Io(std::io::Error),
+    Yaml(serde_yaml::Error),
+    /// The schema parsed but describes something that can't be generated.
+    Invalid(String),
+}
+
+impl fmt::Display for CodegenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Yaml(error) => write!(f, "invalid YAML: {error}"),
+            Self::Invalid(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for CodegenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::Yaml(error) => Some(error),
+            Self::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for CodegenError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+impl From<serde_yaml::Error> for CodegenError {
+    fn from(error: serde_yaml::Error) -> Self {
+        Self::Yaml(error)
+    }
+}
+
+pub type CodegenResult<T = ()> = Result<T, CodegenError>;
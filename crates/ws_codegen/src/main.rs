@@ -0,0 +1,19 @@
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: ws_codegen <schema.yaml> <output.rs>";
+
+fn main() -> ExitCode {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let [schema, output] = args.as_slice() else {
+        eprintln!("{USAGE}");
+        return ExitCode::FAILURE;
+    };
+
+    match ws_codegen::generate_file(schema, output) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Failed to generate {output} from {schema}: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use serde::Deserialize;
+
+use crate::{CodegenError, CodegenResult};
+
+/// The types to generate, in the order they are generated in.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Schema {
+    #[serde(default)]
+    pub enums: Vec<EnumDef>,
+    /// Structs that are part of messages.
+    #[serde(default)]
+    pub structs: Vec<StructDef>,
+    #[serde(default)]
+    pub unions: Vec<UnionDef>,
+    /// Structs with an opcode.
+    #[serde(default)]
+    pub messages: Vec<StructDef>,
+}
+
+impl Schema {
+    pub fn from_yaml(yaml: &str) -> CodegenResult<Self> {
+        let schema: Self = serde_yaml::from_str(yaml)?;
+        schema.validate()?;
+        Ok(schema)
+    }
+
+    /// Checks what the derive macros would reject, to report it against the
+    /// schema instead of the generated code.
+    pub fn validate(&self) -> CodegenResult {
+        for def in &self.enums {
+            check_ident(&def.name)?;
+            for variant in &def.variants {
+                check_ident(&variant.name)?;
+            }
+        }
+        for def in self.structs.iter().chain(&self.messages) {
+            check_ident(&def.name)?;
+            check_fields(&def.name, &def.fields)?;
+        }
+        for def in &self.messages {
+            if def.opcode.is_none() {
+                return Err(invalid(format!("Message {} has no opcode", def.name)));
+            }
+        }
+        for def in &self.unions {
+            check_ident(&def.name)?;
+            for variant in &def.variants {
+                check_ident(&variant.name)?;
+                check_fields(&format!("{}::{}", def.name, variant.name), &variant.fields)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnumDef {
+    pub name: String,
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// The integer type of the values, `u32` by default.
+    #[serde(default = "default_repr")]
+    pub repr: String,
+    pub variants: Vec<EnumVariant>,
+}
+
+fn default_repr() -> String {
+    "u32".to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnumVariant {
+    pub name: String,
+    pub value: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StructDef {
+    pub name: String,
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// The opcode of a message.
+    #[serde(default)]
+    pub opcode: Option<u32>,
+    #[serde(default)]
+    pub fields: Vec<FieldDef>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnionDef {
+    pub name: String,
+    #[serde(default)]
+    pub doc: Option<String>,
+    /// The variants, in the order of their index.
+    pub variants: Vec<UnionVariant>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UnionVariant {
+    pub name: String,
+    #[serde(default)]
+    pub fields: Vec<FieldDef>,
+}
+
+/// A field, with the attributes of the derive macros it takes.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldDef {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    #[serde(default)]
+    pub doc: Option<String>,
+    #[serde(default)]
+    pub packed: Option<usize>,
+    /// An expression using previous fields.
+    #[serde(default)]
+    pub length: Option<String>,
+    #[serde(default)]
+    pub length_of: Option<String>,
+    /// The previous field selecting the variant of a union.
+    #[serde(default)]
+    pub variant: Option<String>,
+    #[serde(default)]
+    pub aligned: bool,
+    #[serde(default)]
+    pub ascii: bool,
+    #[serde(default)]
+    pub bytes: bool,
+    #[serde(default)]
+    pub trailing: bool,
+    /// An expression using previous fields, for `Option` fields.
+    #[serde(default)]
+    pub present: Option<String>,
+}
+
+fn invalid(message: String) -> CodegenError {
+    CodegenError::Invalid(message)
+}
+
+fn check_ident(name: &str) -> CodegenResult {
+    syn::parse_str::<syn::Ident>(name)
+        .map(|_| ())
+        .map_err(|_| invalid(format!("{name:?} is not a valid identifier")))
+}
+
+fn check_fields(owner: &str, fields: &[FieldDef]) -> CodegenResult {
+    for (index, field) in fields.iter().enumerate() {
+        let name = format!("{owner}.{}", field.name);
+        check_ident(&field.name)?;
+        syn::parse_str::<syn::Type>(&field.ty)
+            .map_err(|_| invalid(format!("{name} has an invalid type {:?}", field.ty)))?;
+        for expr in [&field.length, &field.present].into_iter().flatten() {
+            syn::parse_str::<syn::Expr>(expr)
+                .map_err(|_| invalid(format!("{name} has an invalid expression {expr:?}")))?;
+        }
+
+        let previous = &fields[..index];
+        if let Some(variant) = &field.variant {
+            if !previous.iter().any(|field| field.name == *variant) {
+                return Err(invalid(format!(
+                    "{name} takes its variant from {variant}, which isn't a previous field"
+                )));
+            }
+        }
+        if let Some(items) = &field.length_of {
+            if !fields[index + 1..].iter().any(|field| field.name == *items) {
+                return Err(invalid(format!(
+                    "{name} is the length of {items}, which isn't a following field"
+                )));
+            }
+        }
+
+        // the combinations accepted by the derive macros
+        let valid = matches!(
+            (
+                field.packed.is_some(),
+                field.length.is_some(),
+                field.variant.is_some(),
+                field.ascii,
+                field.bytes,
+            ),
+            (_, _, false, false, false)
+                | (false, false, true, false, false)
+                | (false, false, false, true, false)
+                | (false, true, false, false, true)
+        );
+        if !valid {
+            return Err(invalid(format!(
+                "{name} has an invalid combination of attributes"
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,312 @@
+//! The configuration of the server binaries, shared by the realm and world
+//! servers.
+//!
+//! The configuration is read from a TOML file, where every key is optional:
+//!
+//! ```toml
+//! database_url = "sqlite://sandbox.db"
+//! encryption = true
+//! ticket_key = "000102030405060708090a0b0c0d0e0f"
+//! motd = "Welcome to the sandbox!"
+//!
+//! [realm]
+//! listen = "0.0.0.0:23115"
+//! id = 1
+//! name = "Sandbox"
+//!
+//! [world]
+//! listen = "0.0.0.0:24000"
+//! public_addr = "127.0.0.1:24000"
+//! data_dir = "data"
+//!
+//! [cluster]
+//! addr = "127.0.0.1:23200"
+//! ```
+//!
+//! Each key can then be overridden by an environment variable named after its
+//! path, like `WS_DATABASE_URL` or `WS_REALM_NAME`.
+
+use std::fmt;
+use std::net::SocketAddrV4;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+/// The settings of both servers.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub database_url: String,
+    /// Whether the world server encrypts the frames of logged in sessions.
+    pub encryption: bool,
+    /// The key in hex that the realm server signs the tickets it issues with,
+    /// and that the world server checks them with. When empty, the realm
+    /// server picks a random key and the world server lets any account in.
+    pub ticket_key: String,
+    /// Shown in the chat box when entering the world, unless empty.
+    pub motd: String,
+    pub realm: RealmConfig,
+    pub world: WorldConfig,
+    pub cluster: ClusterConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RealmConfig {
+    /// The address the realm server listens on.
+    pub listen: String,
+    /// The address of the realm server's `/metrics` endpoint.
+    pub metrics: String,
+    pub id: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldConfig {
+    /// The address the world server listens on.
+    pub listen: String,
+    /// The address of the world server's `/metrics` endpoint.
+    pub metrics: String,
+    /// The address clients connect to, as given by the realm server.
+    pub public_addr: SocketAddrV4,
+    /// The directory of the spawn points, item templates and map terrain.
+    pub data_dir: PathBuf,
+    /// The address of the WebSocket bridge of the debugging tools, unless
+    /// empty. It streams the messages of the sessions at `/packets` and the
+    /// world snapshots at `/world`. Anyone connecting to it can inject
+    /// messages in the sessions, so it should only listen locally.
+    pub bridge: String,
+    /// The file the messages of the clients and the ticks of the world are
+    /// recorded to, to replay them with `ws_world replay`, unless empty.
+    pub record: String,
+    /// How many threads the instances of the maps tick on, one per core if 0.
+    pub tick_threads: usize,
+    /// The locale of the texts of the players who didn't choose one, whose
+    /// translations are in `<data_dir>/text/<locale>.csv`.
+    pub locale: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClusterConfig {
+    /// The address of the cluster hub, which the realm server listens on and
+    /// the world server connects to, unless empty.
+    pub addr: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            database_url: "sqlite://sandbox.db".to_string(),
+            encryption: false,
+            ticket_key: String::new(),
+            motd: String::new(),
+            realm: RealmConfig::default(),
+            world: WorldConfig::default(),
+            cluster: ClusterConfig::default(),
+        }
+    }
+}
+
+impl Default for RealmConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:23115".to_string(),
+            metrics: "127.0.0.1:9923".to_string(),
+            id: 1,
+            name: "Sandbox".to_string(),
+        }
+    }
+}
+
+impl Default for WorldConfig {
+    fn default() -> Self {
+        Self {
+            listen: "0.0.0.0:24000".to_string(),
+            metrics: "127.0.0.1:9924".to_string(),
+            public_addr: SocketAddrV4::new([127, 0, 0, 1].into(), 24000),
+            data_dir: PathBuf::from("data"),
+            bridge: String::new(),
+            record: String::new(),
+            tick_threads: 0,
+            locale: "en-US".to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// The file loaded when no path is given, if it exists.
+    pub const DEFAULT_PATH: &'static str = "sandbox.toml";
+
+    /// Loads the configuration from `path`, or from [`Config::DEFAULT_PATH`]
+    /// if it exists when no path is given, then applies the environment
+    /// variables.
+    pub fn load(path: Option<&Path>) -> ConfigResult<Self> {
+        let path = match path {
+            Some(path) => Some(path),
+            None => Some(Path::new(Self::DEFAULT_PATH)).filter(|path| path.exists()),
+        };
+        let mut config = match path {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).map_err(|error| ConfigError::Io {
+                    path: path.to_path_buf(),
+                    error,
+                })?;
+                Self::from_toml(&text)?
+            }
+            None => Self::default(),
+        };
+        config.apply_env(|name| std::env::var(name).ok())?;
+        Ok(config)
+    }
+
+    /// Parses a configuration, without applying the environment variables.
+    pub fn from_toml(text: &str) -> ConfigResult<Self> {
+        Ok(toml::from_str(text)?)
+    }
+
+    /// Overrides the settings with the variables returned by `var`, usually
+    /// the environment variables.
+    pub fn apply_env<F>(&mut self, var: F) -> ConfigResult
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        override_with(&var, "WS_DATABASE_URL", &mut self.database_url)?;
+        override_with(&var, "WS_ENCRYPTION", &mut self.encryption)?;
+        override_with(&var, "WS_TICKET_KEY", &mut self.ticket_key)?;
+        override_with(&var, "WS_MOTD", &mut self.motd)?;
+        override_with(&var, "WS_REALM_LISTEN", &mut self.realm.listen)?;
+        override_with(&var, "WS_REALM_METRICS", &mut self.realm.metrics)?;
+        override_with(&var, "WS_REALM_ID", &mut self.realm.id)?;
+        override_with(&var, "WS_REALM_NAME", &mut self.realm.name)?;
+        override_with(&var, "WS_WORLD_LISTEN", &mut self.world.listen)?;
+        override_with(&var, "WS_WORLD_METRICS", &mut self.world.metrics)?;
+        override_with(&var, "WS_WORLD_PUBLIC_ADDR", &mut self.world.public_addr)?;
+        override_with(&var, "WS_WORLD_DATA_DIR", &mut self.world.data_dir)?;
+        override_with(&var, "WS_WORLD_BRIDGE", &mut self.world.bridge)?;
+        override_with(&var, "WS_WORLD_RECORD", &mut self.world.record)?;
+        override_with(&var, "WS_WORLD_TICK_THREADS", &mut self.world.tick_threads)?;
+        override_with(&var, "WS_WORLD_LOCALE", &mut self.world.locale)?;
+        override_with(&var, "WS_CLUSTER_ADDR", &mut self.cluster.addr)?;
+        Ok(())
+    }
+}
+
+fn override_with<F, T>(var: &F, name: &'static str, setting: &mut T) -> ConfigResult
+where
+    F: Fn(&str) -> Option<String>,
+    T: FromStr,
+{
+    if let Some(value) = var(name) {
+        *setting = value
+            .parse()
+            .map_err(|_| ConfigError::InvalidEnv { name, value })?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Toml(toml::de::Error),
+    /// An environment variable couldn't be parsed as the setting it overrides.
+    InvalidEnv {
+        name: &'static str,
+        value: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "failed to read {}: {error}", path.display()),
+            Self::Toml(error) => error.fmt(f),
+            Self::InvalidEnv { name, value } => write!(f, "invalid {name} {value:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::Toml(error) => Some(error),
+            Self::InvalidEnv { .. } => None,
+        }
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> Self {
+        ConfigError::Toml(error)
+    }
+}
+
+pub type ConfigResult<T = ()> = Result<T, ConfigError>;
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    #[test]
+    fn test_from_toml() {
+        let config = Config::from_toml(
+            r#"
+            encryption = true
+            motd = "Hello"
+
+            [realm]
+            id = 3
+            name = "Test"
+
+            [world]
+            public_addr = "10.0.0.2:24001"
+            "#,
+        )
+        .unwrap();
+        assert!(config.encryption);
+        assert_eq!(config.motd, "Hello");
+        assert_eq!(config.realm.id, 3);
+        assert_eq!(config.realm.name, "Test");
+        // missing keys keep their default
+        assert_eq!(config.realm.listen, "0.0.0.0:23115");
+        assert_eq!(config.database_url, "sqlite://sandbox.db");
+        assert_eq!(config.world.public_addr, "10.0.0.2:24001".parse().unwrap());
+
+        assert!(matches!(
+            Config::from_toml("[realm]\nport = 1"),
+            Err(ConfigError::Toml(_))
+        ));
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = HashMap::from([
+            ("WS_REALM_ID", "7"),
+            ("WS_ENCRYPTION", "true"),
+            ("WS_WORLD_LISTEN", "0.0.0.0:24100"),
+            ("WS_CLUSTER_ADDR", "10.0.0.1:23200"),
+        ]);
+        let var = |name: &str| vars.get(name).map(|value| value.to_string());
+        let mut config = Config::default();
+        config.apply_env(var).unwrap();
+        assert_eq!(config.realm.id, 7);
+        assert!(config.encryption);
+        assert_eq!(config.world.listen, "0.0.0.0:24100");
+        assert_eq!(config.cluster.addr, "10.0.0.1:23200");
+        assert_eq!(config.realm.name, "Sandbox");
+
+        let mut config = Config::default();
+        let error = config
+            .apply_env(|name| (name == "WS_REALM_ID").then(|| "one".to_string()))
+            .unwrap_err();
+        assert_eq!(error.to_string(), "invalid WS_REALM_ID \"one\"");
+    }
+}
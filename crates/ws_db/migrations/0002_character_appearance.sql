@@ -0,0 +1,5 @@
+ALTER TABLE characters ADD COLUMN sex INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE characters ADD COLUMN path INTEGER NOT NULL DEFAULT 0;
+-- the customizations chosen in the character creator, bit-packed like in the
+-- messages
+ALTER TABLE characters ADD COLUMN customization BLOB NOT NULL DEFAULT x'';
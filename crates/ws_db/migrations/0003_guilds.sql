@@ -0,0 +1,24 @@
+CREATE TABLE guilds (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+-- rank 0 is the guild master, the ranks below it are numbered from 1
+CREATE TABLE guild_ranks (
+    guild_id INTEGER NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    rank INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    permissions INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (guild_id, rank)
+);
+
+-- a character is in one guild at most
+CREATE TABLE guild_members (
+    character_id INTEGER PRIMARY KEY REFERENCES characters (id) ON DELETE CASCADE,
+    guild_id INTEGER NOT NULL REFERENCES guilds (id) ON DELETE CASCADE,
+    rank INTEGER NOT NULL,
+    joined_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+CREATE INDEX guild_members_guild_id ON guild_members (guild_id);
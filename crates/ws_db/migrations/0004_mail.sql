@@ -0,0 +1,23 @@
+CREATE TABLE mail (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    -- the sender may have been deleted since
+    sender_id INTEGER REFERENCES characters (id) ON DELETE SET NULL,
+    sender_name TEXT NOT NULL,
+    recipient_id INTEGER NOT NULL REFERENCES characters (id) ON DELETE CASCADE,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    is_read INTEGER NOT NULL DEFAULT 0,
+    sent_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    expires_at INTEGER NOT NULL
+);
+
+CREATE INDEX mail_recipient_id ON mail (recipient_id);
+CREATE INDEX mail_expires_at ON mail (expires_at);
+
+CREATE TABLE mail_attachments (
+    mail_id INTEGER NOT NULL REFERENCES mail (id) ON DELETE CASCADE,
+    slot INTEGER NOT NULL,
+    template_id INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (mail_id, slot)
+);
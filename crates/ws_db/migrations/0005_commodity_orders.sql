@@ -0,0 +1,14 @@
+-- the open orders of the commodity exchange, removed once filled or cancelled
+CREATE TABLE commodity_orders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    character_id INTEGER NOT NULL REFERENCES characters (id) ON DELETE CASCADE,
+    -- 0 to buy, 1 to sell
+    side INTEGER NOT NULL,
+    template_id INTEGER NOT NULL,
+    -- what is left to buy or sell
+    count INTEGER NOT NULL,
+    unit_price INTEGER NOT NULL,
+    created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+);
+
+CREATE INDEX commodity_orders_character_id ON commodity_orders (character_id);
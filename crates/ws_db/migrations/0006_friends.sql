@@ -0,0 +1,10 @@
+-- friends are kept per account, every character of the account shares them
+CREATE TABLE friends (
+    account_id INTEGER NOT NULL REFERENCES accounts (id) ON DELETE CASCADE,
+    character_id INTEGER NOT NULL REFERENCES characters (id) ON DELETE CASCADE,
+    note TEXT NOT NULL DEFAULT '',
+    added_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+    PRIMARY KEY (account_id, character_id)
+);
+
+CREATE INDEX friends_character_id ON friends (character_id);
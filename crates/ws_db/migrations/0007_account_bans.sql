@@ -0,0 +1,2 @@
+-- banned accounts can't log in anymore, but keep their characters
+ALTER TABLE accounts ADD COLUMN banned INTEGER NOT NULL DEFAULT 0;
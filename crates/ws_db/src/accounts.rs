@@ -0,0 +1,121 @@
+use sqlx::SqlitePool;
+
+use crate::DbResult;
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Account {
+    pub id: i64,
+    pub name: String,
+    /// The SRP6 salt of the account's password.
+    pub salt: Vec<u8>,
+    /// The SRP6 verifier of the account's password.
+    pub verifier: Vec<u8>,
+    pub created_at: i64,
+    /// Whether the account is refused at login.
+    pub banned: bool,
+}
+
+/// The accounts repository.
+#[derive(Debug, Clone, Copy)]
+pub struct Accounts<'a> {
+    pub(crate) pool: &'a SqlitePool,
+}
+
+impl Accounts<'_> {
+    /// Creates an account, failing if the name is taken.
+    pub async fn create(&self, name: &str, salt: &[u8], verifier: &[u8]) -> DbResult<Account> {
+        let account = sqlx::query_as(
+            "INSERT INTO accounts (name, salt, verifier) VALUES (?, ?, ?) RETURNING *",
+        )
+        .bind(name)
+        .bind(salt)
+        .bind(verifier)
+        .fetch_one(self.pool)
+        .await?;
+        Ok(account)
+    }
+
+    pub async fn find(&self, id: i64) -> DbResult<Option<Account>> {
+        let account = sqlx::query_as("SELECT * FROM accounts WHERE id = ?")
+            .bind(id)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(account)
+    }
+
+    /// Finds an account by name, ignoring case.
+    pub async fn find_by_name(&self, name: &str) -> DbResult<Option<Account>> {
+        let account = sqlx::query_as("SELECT * FROM accounts WHERE name = ?")
+            .bind(name)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(account)
+    }
+
+    /// Replaces the SRP6 salt and verifier of an account, after its password
+    /// changed. Returns false if it didn't exist.
+    pub async fn set_password(&self, id: i64, salt: &[u8], verifier: &[u8]) -> DbResult<bool> {
+        let result = sqlx::query("UPDATE accounts SET salt = ?, verifier = ? WHERE id = ?")
+            .bind(salt)
+            .bind(verifier)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Bans or unbans an account. Returns false if it didn't exist.
+    pub async fn set_banned(&self, id: i64, banned: bool) -> DbResult<bool> {
+        let result = sqlx::query("UPDATE accounts SET banned = ? WHERE id = ?")
+            .bind(banned)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes an account along with its sessions and characters. Returns false
+    /// if it didn't exist.
+    pub async fn delete(&self, id: i64) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM accounts WHERE id = ?")
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_accounts() {
+        let db = Database::in_memory().await.unwrap();
+        let accounts = db.accounts();
+        let account = accounts.create("Alice", &[1; 16], &[2; 128]).await.unwrap();
+        assert!(!account.banned);
+        assert!(accounts.create("alice", &[1; 16], &[2; 128]).await.is_err());
+        assert_eq!(
+            accounts.find_by_name("ALICE").await.unwrap(),
+            Some(account.clone())
+        );
+
+        assert!(accounts.set_banned(account.id, true).await.unwrap());
+        assert!(accounts
+            .set_password(account.id, &[3; 16], &[4; 128])
+            .await
+            .unwrap());
+        let found = accounts.find(account.id).await.unwrap().unwrap();
+        assert!(found.banned);
+        assert_eq!((found.salt, found.verifier), (vec![3; 16], vec![4; 128]));
+        assert!(accounts.set_banned(account.id, false).await.unwrap());
+        assert!(!accounts.find(account.id).await.unwrap().unwrap().banned);
+
+        assert!(!accounts.set_banned(account.id + 1, true).await.unwrap());
+        assert!(!accounts
+            .set_password(account.id + 1, &[], &[])
+            .await
+            .unwrap());
+    }
+}
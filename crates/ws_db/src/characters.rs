@@ -0,0 +1,225 @@
+use sqlx::SqlitePool;
+
+use crate::DbResult;
+
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Character {
+    pub id: i64,
+    pub account_id: i64,
+    pub name: String,
+    pub faction: u16,
+    pub race: u8,
+    pub class: u8,
+    pub level: u32,
+    pub world_id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub yaw: f32,
+    pub created_at: i64,
+    pub sex: u8,
+    pub path: u8,
+    pub customization: Vec<u8>,
+}
+
+/// The values needed to create a character.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewCharacter {
+    pub account_id: i64,
+    pub name: String,
+    pub faction: u16,
+    pub race: u8,
+    pub class: u8,
+    pub world_id: u32,
+    pub position: (f32, f32, f32),
+    pub yaw: f32,
+    pub sex: u8,
+    pub path: u8,
+    pub customization: Vec<u8>,
+}
+
+/// The characters repository.
+#[derive(Debug, Clone, Copy)]
+pub struct Characters<'a> {
+    pub(crate) pool: &'a SqlitePool,
+}
+
+impl Characters<'_> {
+    /// Creates a character, failing if the name is taken.
+    pub async fn create(&self, character: &NewCharacter) -> DbResult<Character> {
+        let (x, y, z) = character.position;
+        let character = sqlx::query_as(
+            "INSERT INTO characters \
+            (account_id, name, faction, race, class, world_id, x, y, z, yaw, sex, path, customization) \
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
+        )
+        .bind(character.account_id)
+        .bind(&character.name)
+        .bind(character.faction)
+        .bind(character.race)
+        .bind(character.class)
+        .bind(character.world_id)
+        .bind(x)
+        .bind(y)
+        .bind(z)
+        .bind(character.yaw)
+        .bind(character.sex)
+        .bind(character.path)
+        .bind(&character.customization)
+        .fetch_one(self.pool)
+        .await?;
+        Ok(character)
+    }
+
+    /// Lists the characters of an account, in creation order.
+    pub async fn list(&self, account_id: i64) -> DbResult<Vec<Character>> {
+        let characters =
+            sqlx::query_as("SELECT * FROM characters WHERE account_id = ? ORDER BY id")
+                .bind(account_id)
+                .fetch_all(self.pool)
+                .await?;
+        Ok(characters)
+    }
+
+    /// Finds a character of an account.
+    pub async fn find(&self, account_id: i64, id: i64) -> DbResult<Option<Character>> {
+        let character = sqlx::query_as("SELECT * FROM characters WHERE account_id = ? AND id = ?")
+            .bind(account_id)
+            .bind(id)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(character)
+    }
+
+    /// Finds a character by name, ignoring case.
+    pub async fn find_by_name(&self, name: &str) -> DbResult<Option<Character>> {
+        let character = sqlx::query_as("SELECT * FROM characters WHERE name = ?")
+            .bind(name)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(character)
+    }
+
+    /// Returns the number of characters of an account.
+    pub async fn count(&self, account_id: i64) -> DbResult<u32> {
+        let (count,): (u32,) =
+            sqlx::query_as("SELECT COUNT(*) FROM characters WHERE account_id = ?")
+                .bind(account_id)
+                .fetch_one(self.pool)
+                .await?;
+        Ok(count)
+    }
+
+    /// Deletes a character of an account. Returns false if the account has no
+    /// such character.
+    pub async fn delete(&self, account_id: i64, id: i64) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM characters WHERE account_id = ? AND id = ?")
+            .bind(account_id)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn new_character(account_id: i64, name: &str) -> NewCharacter {
+        NewCharacter {
+            account_id,
+            name: name.to_string(),
+            faction: 166,
+            race: 1,
+            class: 1,
+            world_id: 870,
+            position: (1.0, 2.0, 3.0),
+            yaw: 0.5,
+            sex: 1,
+            path: 2,
+            customization: vec![0x12, 0x34],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_characters() {
+        let db = Database::in_memory().await.unwrap();
+        let account = db
+            .accounts()
+            .create("clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let other = db
+            .accounts()
+            .create("other", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+
+        let first = db
+            .characters()
+            .create(&new_character(account.id, "First"))
+            .await
+            .unwrap();
+        assert_eq!(first.level, 1);
+        assert_eq!((first.x, first.y, first.z), (1.0, 2.0, 3.0));
+        assert_eq!(first.customization, [0x12, 0x34]);
+        let second = db
+            .characters()
+            .create(&new_character(account.id, "Second"))
+            .await
+            .unwrap();
+        db.characters()
+            .create(&new_character(other.id, "Third"))
+            .await
+            .unwrap();
+
+        // names are unique regardless of case
+        let result = db
+            .characters()
+            .create(&new_character(other.id, "first"))
+            .await;
+        assert!(matches!(result, Err(DbError::Sqlx(_))));
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("UNIQUE constraint failed"), "{error}");
+
+        assert_eq!(
+            db.characters().find_by_name("SECOND").await.unwrap(),
+            Some(second.clone())
+        );
+        assert_eq!(db.characters().count(account.id).await.unwrap(), 2);
+
+        let characters = db.characters().list(account.id).await.unwrap();
+        assert_eq!(characters, [first.clone(), second.clone()]);
+
+        // characters can only be deleted by their account
+        assert!(!db.characters().delete(other.id, first.id).await.unwrap());
+        assert!(db.characters().delete(account.id, first.id).await.unwrap());
+        assert_eq!(db.characters().list(account.id).await.unwrap(), [second]);
+
+        // deleting the account deletes its characters
+        assert!(db.accounts().delete(other.id).await.unwrap());
+        assert!(db.characters().list(other.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_accounts_and_sessions() {
+        let db = Database::in_memory().await.unwrap();
+        let account = db
+            .accounts()
+            .create("Clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let found = db.accounts().find_by_name("clamoune").await.unwrap();
+        assert_eq!(found, Some(account.clone()));
+
+        let session = db.sessions().create(account.id).await.unwrap();
+        assert_eq!(
+            db.sessions().find(&session.key).await.unwrap(),
+            Some(session.clone())
+        );
+        assert_eq!(db.sessions().delete_expired(60).await.unwrap(), 0);
+        assert!(db.sessions().delete(&session.key).await.unwrap());
+        assert_eq!(db.sessions().find(&session.key).await.unwrap(), None);
+    }
+}
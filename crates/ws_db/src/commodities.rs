@@ -0,0 +1,185 @@
+use sqlx::SqlitePool;
+
+use crate::mail::insert_mail;
+use crate::{DbResult, Mail, NewMail};
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct CommodityOrder {
+    pub id: i64,
+    pub character_id: i64,
+    /// 0 to buy, 1 to sell.
+    pub side: u8,
+    pub template_id: u32,
+    /// The items left to buy or sell.
+    pub count: u32,
+    pub unit_price: i64,
+    pub created_at: i64,
+}
+
+/// The values needed to place an order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewCommodityOrder {
+    pub character_id: i64,
+    pub side: u8,
+    pub template_id: u32,
+    pub count: u32,
+    pub unit_price: i64,
+}
+
+/// The commodity orders repository.
+#[derive(Debug, Clone, Copy)]
+pub struct CommodityOrders<'a> {
+    pub(crate) pool: &'a SqlitePool,
+}
+
+impl CommodityOrders<'_> {
+    pub async fn create(&self, order: &NewCommodityOrder) -> DbResult<CommodityOrder> {
+        let order = sqlx::query_as(
+            "INSERT INTO commodity_orders (character_id, side, template_id, count, unit_price) \
+            VALUES (?, ?, ?, ?, ?) RETURNING *",
+        )
+        .bind(order.character_id)
+        .bind(order.side)
+        .bind(order.template_id)
+        .bind(order.count)
+        .bind(order.unit_price)
+        .fetch_one(self.pool)
+        .await?;
+        Ok(order)
+    }
+
+    /// Lists the open orders, in the order they were placed.
+    pub async fn list(&self) -> DbResult<Vec<CommodityOrder>> {
+        let orders = sqlx::query_as("SELECT * FROM commodity_orders ORDER BY id")
+            .fetch_all(self.pool)
+            .await?;
+        Ok(orders)
+    }
+
+    /// Changes what is left of an order once it was partly filled. Returns
+    /// false if there is no such order.
+    pub async fn set_count(&self, id: i64, count: u32) -> DbResult<bool> {
+        let result = sqlx::query("UPDATE commodity_orders SET count = ? WHERE id = ?")
+            .bind(count)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Keeps what is left of the orders that were matched, deleting the ones
+    /// filled, and sends the mail that tells the traders. Either all of it
+    /// is done or none of it.
+    pub async fn settle(
+        &self,
+        orders: &[(i64, Option<u32>)],
+        mail: &[NewMail],
+    ) -> DbResult<Vec<Mail>> {
+        let mut transaction = self.pool.begin().await?;
+        for (id, count) in orders {
+            let query = match count {
+                Some(count) => {
+                    sqlx::query("UPDATE commodity_orders SET count = ? WHERE id = ?").bind(count)
+                }
+                None => sqlx::query("DELETE FROM commodity_orders WHERE id = ?"),
+            };
+            query.bind(id).execute(&mut *transaction).await?;
+        }
+        let mut sent = Vec::with_capacity(mail.len());
+        for mail in mail {
+            sent.push(insert_mail(&mut transaction, mail).await?);
+        }
+        transaction.commit().await?;
+        Ok(sent)
+    }
+
+    /// Deletes an order once it was filled or cancelled. Returns false if
+    /// there is no such order.
+    pub async fn delete(&self, id: i64) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM commodity_orders WHERE id = ?")
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_commodity_orders() {
+        let db = Database::in_memory().await.unwrap();
+        let account = db
+            .accounts()
+            .create("clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let character = NewCharacter {
+            account_id: account.id,
+            name: "Trader".to_string(),
+            faction: 166,
+            race: 1,
+            class: 1,
+            world_id: 870,
+            position: (0.0, 0.0, 0.0),
+            yaw: 0.0,
+            sex: 0,
+            path: 0,
+            customization: Vec::new(),
+        };
+        let character = db.characters().create(&character).await.unwrap();
+
+        let mut new_order = NewCommodityOrder {
+            character_id: character.id,
+            side: 1,
+            template_id: 30,
+            count: 20,
+            unit_price: 150,
+        };
+        let sell = db.commodity_orders().create(&new_order).await.unwrap();
+        new_order.side = 0;
+        new_order.unit_price = 120;
+        let buy = db.commodity_orders().create(&new_order).await.unwrap();
+        assert_eq!(
+            db.commodity_orders().list().await.unwrap(),
+            [sell.clone(), buy.clone()]
+        );
+
+        assert!(db.commodity_orders().set_count(sell.id, 5).await.unwrap());
+        assert!(db.commodity_orders().delete(buy.id).await.unwrap());
+        assert!(!db.commodity_orders().delete(buy.id).await.unwrap());
+        let orders = db.commodity_orders().list().await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].count, 5);
+
+        // orders are settled along with their mail, or not at all
+        let mut mail = NewMail {
+            sender_id: None,
+            sender_name: "Commodity Exchange".to_string(),
+            recipient_id: character.id + 1,
+            subject: "Order filled".to_string(),
+            body: String::new(),
+            attachments: vec![(30, 2)],
+            expires_at: 0,
+        };
+        let orders = db.commodity_orders();
+        let settle = [(sell.id, Some(3))];
+        assert!(orders.settle(&settle, &[mail.clone()]).await.is_err());
+        assert_eq!(orders.list().await.unwrap()[0].count, 5);
+        assert!(db.mail().list(character.id).await.unwrap().is_empty());
+        mail.recipient_id = character.id;
+        let sent = orders.settle(&settle, &[mail]).await.unwrap();
+        assert_eq!(orders.list().await.unwrap()[0].count, 3);
+        assert_eq!(db.mail().list(character.id).await.unwrap(), sent);
+        assert_eq!(db.mail().attachments(sent[0].id).await.unwrap().len(), 1);
+
+        // the orders of deleted characters are gone with them
+        db.characters()
+            .delete(account.id, character.id)
+            .await
+            .unwrap();
+        assert!(db.commodity_orders().list().await.unwrap().is_empty());
+    }
+}
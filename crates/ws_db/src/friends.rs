@@ -0,0 +1,144 @@
+use sqlx::SqlitePool;
+
+use crate::DbResult;
+
+/// A friend of an account, along with the character it is.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Friend {
+    pub account_id: i64,
+    pub character_id: i64,
+    pub note: String,
+    pub added_at: i64,
+    pub name: String,
+    pub class: u8,
+    pub level: u32,
+}
+
+const SELECT_FRIENDS: &str = "SELECT friends.*, characters.name, characters.class, \
+    characters.level FROM friends \
+    JOIN characters ON characters.id = friends.character_id";
+
+/// The friends repository.
+#[derive(Debug, Clone, Copy)]
+pub struct Friends<'a> {
+    pub(crate) pool: &'a SqlitePool,
+}
+
+impl Friends<'_> {
+    /// Lists the friends of an account, in the order they were added.
+    pub async fn list(&self, account_id: i64) -> DbResult<Vec<Friend>> {
+        let friends = sqlx::query_as(&format!(
+            "{SELECT_FRIENDS} WHERE friends.account_id = ? \
+            ORDER BY friends.added_at, friends.character_id"
+        ))
+        .bind(account_id)
+        .fetch_all(self.pool)
+        .await?;
+        Ok(friends)
+    }
+
+    pub async fn find(&self, account_id: i64, character_id: i64) -> DbResult<Option<Friend>> {
+        let friend = sqlx::query_as(&format!(
+            "{SELECT_FRIENDS} WHERE friends.account_id = ? AND friends.character_id = ?"
+        ))
+        .bind(account_id)
+        .bind(character_id)
+        .fetch_optional(self.pool)
+        .await?;
+        Ok(friend)
+    }
+
+    pub async fn count(&self, account_id: i64) -> DbResult<u32> {
+        let (count,) = sqlx::query_as("SELECT COUNT(*) FROM friends WHERE account_id = ?")
+            .bind(account_id)
+            .fetch_one(self.pool)
+            .await?;
+        Ok(count)
+    }
+
+    /// Adds a character to the friends of an account. Returns false if it
+    /// already is one.
+    pub async fn add(&self, account_id: i64, character_id: i64) -> DbResult<bool> {
+        let result =
+            sqlx::query("INSERT OR IGNORE INTO friends (account_id, character_id) VALUES (?, ?)")
+                .bind(account_id)
+                .bind(character_id)
+                .execute(self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Changes the note of a friend. Returns false if there is no such friend.
+    pub async fn set_note(&self, account_id: i64, character_id: i64, note: &str) -> DbResult<bool> {
+        let result =
+            sqlx::query("UPDATE friends SET note = ? WHERE account_id = ? AND character_id = ?")
+                .bind(note)
+                .bind(account_id)
+                .bind(character_id)
+                .execute(self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Removes a friend. Returns false if there is no such friend.
+    pub async fn remove(&self, account_id: i64, character_id: i64) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM friends WHERE account_id = ? AND character_id = ?")
+            .bind(account_id)
+            .bind(character_id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_friends() {
+        let db = Database::in_memory().await.unwrap();
+        let mut ids = Vec::new();
+        for name in ["Alice", "Bob"] {
+            let account = db
+                .accounts()
+                .create(name, &[1; 16], &[2; 128])
+                .await
+                .unwrap();
+            let character = NewCharacter {
+                account_id: account.id,
+                name: name.to_string(),
+                faction: 166,
+                race: 1,
+                class: 1,
+                world_id: 870,
+                position: (0.0, 0.0, 0.0),
+                yaw: 0.0,
+                sex: 0,
+                path: 0,
+                customization: Vec::new(),
+            };
+            let character = db.characters().create(&character).await.unwrap();
+            ids.push((account.id, character.id));
+        }
+        let (alice, bob) = (ids[0], ids[1]);
+
+        let friends = db.friends();
+        assert!(friends.add(alice.0, bob.1).await.unwrap());
+        assert!(!friends.add(alice.0, bob.1).await.unwrap());
+        assert!(friends.set_note(alice.0, bob.1, "Tank").await.unwrap());
+        assert!(!friends.set_note(bob.0, alice.1, "Healer").await.unwrap());
+        let list = friends.list(alice.0).await.unwrap();
+        assert_eq!(list.len(), 1);
+        assert_eq!(
+            (list[0].name.as_str(), list[0].note.as_str()),
+            ("Bob", "Tank")
+        );
+        assert_eq!(friends.count(bob.0).await.unwrap(), 0);
+
+        // characters deleted leave the friend lists
+        db.characters().delete(bob.0, bob.1).await.unwrap();
+        assert_eq!(friends.find(alice.0, bob.1).await.unwrap(), None);
+        assert!(!friends.remove(alice.0, bob.1).await.unwrap());
+    }
+}
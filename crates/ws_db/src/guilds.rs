@@ -0,0 +1,282 @@
+use sqlx::SqlitePool;
+
+use crate::DbResult;
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Guild {
+    pub id: i64,
+    pub name: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct GuildRank {
+    pub guild_id: i64,
+    /// 0 for the guild master, higher for the lower ranks.
+    pub rank: u8,
+    pub name: String,
+    /// What the members of the rank may do, as bits defined by the world
+    /// server.
+    pub permissions: u32,
+}
+
+/// A member of a guild, along with the character it is.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct GuildMember {
+    pub guild_id: i64,
+    pub character_id: i64,
+    pub rank: u8,
+    pub joined_at: i64,
+    pub name: String,
+    pub class: u8,
+    pub level: u32,
+}
+
+const SELECT_MEMBERS: &str = "SELECT guild_members.*, characters.name, characters.class, \
+    characters.level FROM guild_members \
+    JOIN characters ON characters.id = guild_members.character_id";
+
+/// The guilds repository.
+#[derive(Debug, Clone, Copy)]
+pub struct Guilds<'a> {
+    pub(crate) pool: &'a SqlitePool,
+}
+
+impl Guilds<'_> {
+    /// Creates a guild with its ranks, the first one being the guild master's,
+    /// and its master. Fails if the name is taken or the master is already in
+    /// a guild.
+    pub async fn create(
+        &self,
+        name: &str,
+        master_id: i64,
+        ranks: &[(&str, u32)],
+    ) -> DbResult<Guild> {
+        let mut transaction = self.pool.begin().await?;
+        let guild: Guild = sqlx::query_as("INSERT INTO guilds (name) VALUES (?) RETURNING *")
+            .bind(name)
+            .fetch_one(&mut *transaction)
+            .await?;
+        for (rank, (rank_name, permissions)) in ranks.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO guild_ranks (guild_id, rank, name, permissions) VALUES (?, ?, ?, ?)",
+            )
+            .bind(guild.id)
+            .bind(rank as u8)
+            .bind(rank_name)
+            .bind(permissions)
+            .execute(&mut *transaction)
+            .await?;
+        }
+        sqlx::query("INSERT INTO guild_members (character_id, guild_id, rank) VALUES (?, ?, 0)")
+            .bind(master_id)
+            .bind(guild.id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(guild)
+    }
+
+    pub async fn find(&self, id: i64) -> DbResult<Option<Guild>> {
+        let guild = sqlx::query_as("SELECT * FROM guilds WHERE id = ?")
+            .bind(id)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(guild)
+    }
+
+    /// Finds a guild by name, ignoring case.
+    pub async fn find_by_name(&self, name: &str) -> DbResult<Option<Guild>> {
+        let guild = sqlx::query_as("SELECT * FROM guilds WHERE name = ?")
+            .bind(name)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(guild)
+    }
+
+    /// Returns the guild membership of a character, if it is in one.
+    pub async fn member(&self, character_id: i64) -> DbResult<Option<GuildMember>> {
+        let member = sqlx::query_as(&format!(
+            "{SELECT_MEMBERS} WHERE guild_members.character_id = ?"
+        ))
+        .bind(character_id)
+        .fetch_optional(self.pool)
+        .await?;
+        Ok(member)
+    }
+
+    /// Lists the members of a guild, by rank and then in joining order.
+    pub async fn members(&self, guild_id: i64) -> DbResult<Vec<GuildMember>> {
+        let members = sqlx::query_as(&format!(
+            "{SELECT_MEMBERS} WHERE guild_members.guild_id = ? \
+            ORDER BY guild_members.rank, guild_members.joined_at, guild_members.character_id"
+        ))
+        .bind(guild_id)
+        .fetch_all(self.pool)
+        .await?;
+        Ok(members)
+    }
+
+    /// Lists the ranks of a guild, from the guild master's down.
+    pub async fn ranks(&self, guild_id: i64) -> DbResult<Vec<GuildRank>> {
+        let ranks = sqlx::query_as("SELECT * FROM guild_ranks WHERE guild_id = ? ORDER BY rank")
+            .bind(guild_id)
+            .fetch_all(self.pool)
+            .await?;
+        Ok(ranks)
+    }
+
+    /// Adds a character to a guild, failing if it is already in one.
+    pub async fn add_member(&self, guild_id: i64, character_id: i64, rank: u8) -> DbResult {
+        sqlx::query("INSERT INTO guild_members (character_id, guild_id, rank) VALUES (?, ?, ?)")
+            .bind(character_id)
+            .bind(guild_id)
+            .bind(rank)
+            .execute(self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Changes the rank of a member. Returns false if the character isn't in
+    /// the guild.
+    pub async fn set_rank(&self, guild_id: i64, character_id: i64, rank: u8) -> DbResult<bool> {
+        let result = sqlx::query(
+            "UPDATE guild_members SET rank = ? WHERE guild_id = ? AND character_id = ?",
+        )
+        .bind(rank)
+        .bind(guild_id)
+        .bind(character_id)
+        .execute(self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Renames a rank. Returns false if the guild has no such rank.
+    pub async fn rename_rank(&self, guild_id: i64, rank: u8, name: &str) -> DbResult<bool> {
+        let result = sqlx::query("UPDATE guild_ranks SET name = ? WHERE guild_id = ? AND rank = ?")
+            .bind(name)
+            .bind(guild_id)
+            .bind(rank)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Removes a member from a guild. Returns false if the character isn't in
+    /// the guild.
+    pub async fn remove_member(&self, guild_id: i64, character_id: i64) -> DbResult<bool> {
+        let result =
+            sqlx::query("DELETE FROM guild_members WHERE guild_id = ? AND character_id = ?")
+                .bind(guild_id)
+                .bind(character_id)
+                .execute(self.pool)
+                .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes a guild along with its ranks and memberships.
+    pub async fn delete(&self, id: i64) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM guilds WHERE id = ?")
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    async fn create_character(db: &Database, account_id: i64, name: &str) -> Character {
+        let character = NewCharacter {
+            account_id,
+            name: name.to_string(),
+            faction: 166,
+            race: 1,
+            class: 3,
+            world_id: 870,
+            position: (0.0, 0.0, 0.0),
+            yaw: 0.0,
+            sex: 0,
+            path: 0,
+            customization: Vec::new(),
+        };
+        db.characters().create(&character).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_guilds() {
+        let db = Database::in_memory().await.unwrap();
+        let account = db
+            .accounts()
+            .create("clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let master = create_character(&db, account.id, "Master").await;
+        let member = create_character(&db, account.id, "Member").await;
+
+        let ranks = [("Guild Master", 0xff), ("Member", 0)];
+        let guild = db
+            .guilds()
+            .create("Sandbox Testers", master.id, &ranks)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.guilds().find_by_name("sandbox testers").await.unwrap(),
+            Some(guild.clone())
+        );
+        // names are unique regardless of case, and the master is taken
+        assert!(db
+            .guilds()
+            .create("SANDBOX TESTERS", member.id, &ranks)
+            .await
+            .is_err());
+        assert!(db
+            .guilds()
+            .create("Other", master.id, &ranks)
+            .await
+            .is_err());
+        assert_eq!(db.guilds().find_by_name("Other").await.unwrap(), None);
+
+        db.guilds()
+            .add_member(guild.id, member.id, 1)
+            .await
+            .unwrap();
+        let members = db.guilds().members(guild.id).await.unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!((members[0].name.as_str(), members[0].rank), ("Master", 0));
+        assert_eq!((members[1].name.as_str(), members[1].class), ("Member", 3));
+
+        assert!(db
+            .guilds()
+            .rename_rank(guild.id, 1, "Recruit")
+            .await
+            .unwrap());
+        assert!(!db
+            .guilds()
+            .rename_rank(guild.id, 2, "Nobody")
+            .await
+            .unwrap());
+        let ranks = db.guilds().ranks(guild.id).await.unwrap();
+        assert_eq!(ranks[1].name, "Recruit");
+        assert_eq!(ranks[0].permissions, 0xff);
+
+        assert!(db.guilds().set_rank(guild.id, member.id, 0).await.unwrap());
+        assert_eq!(
+            db.guilds().member(member.id).await.unwrap().unwrap().rank,
+            0
+        );
+        assert!(db
+            .guilds()
+            .remove_member(guild.id, member.id)
+            .await
+            .unwrap());
+        assert_eq!(db.guilds().member(member.id).await.unwrap(), None);
+
+        // deleting the guild deletes its memberships
+        assert!(db.guilds().delete(guild.id).await.unwrap());
+        assert_eq!(db.guilds().member(master.id).await.unwrap(), None);
+        assert!(db.guilds().ranks(guild.id).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,134 @@
+//! Persistence for the sandbox servers, on top of SQLite.
+//!
+//! Migrations are embedded in the crate and applied when connecting.
+
+mod accounts;
+mod characters;
+mod commodities;
+mod friends;
+mod guilds;
+mod mail;
+mod sessions;
+
+pub use accounts::*;
+pub use characters::*;
+pub use commodities::*;
+pub use friends::*;
+pub use guilds::*;
+pub use mail::*;
+pub use sessions::*;
+
+use std::fmt;
+use std::str::FromStr;
+
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+
+#[derive(Debug)]
+pub enum DbError {
+    Sqlx(sqlx::Error),
+    Migrate(sqlx::migrate::MigrateError),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Sqlx(error) => error.fmt(f),
+            Self::Migrate(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DbError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Sqlx(error) => error.source(),
+            Self::Migrate(error) => error.source(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(error: sqlx::Error) -> Self {
+        DbError::Sqlx(error)
+    }
+}
+
+impl From<sqlx::migrate::MigrateError> for DbError {
+    fn from(error: sqlx::migrate::MigrateError) -> Self {
+        DbError::Migrate(error)
+    }
+}
+
+pub type DbResult<T = ()> = Result<T, DbError>;
+
+/// A pool of connections to the database.
+///
+/// Cloning a database is cheap and shares the pool.
+#[derive(Debug, Clone)]
+pub struct Database {
+    pool: SqlitePool,
+}
+
+impl Database {
+    /// The database used when none is configured.
+    pub const DEFAULT_URL: &'static str = "sqlite://sandbox.db";
+
+    /// Opens the database at `url`, creating it if needed, and applies the
+    /// migrations.
+    pub async fn connect(url: &str) -> DbResult<Self> {
+        let options = SqliteConnectOptions::from_str(url)?
+            .create_if_missing(true)
+            .foreign_keys(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await?;
+        Self::with_pool(pool).await
+    }
+
+    /// Opens a new empty database that only lives in memory, for tests.
+    pub async fn in_memory() -> DbResult<Self> {
+        let options = SqliteConnectOptions::from_str("sqlite::memory:")?.foreign_keys(true);
+        // every connection to an in-memory database gets its own database
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect_with(options)
+            .await?;
+        Self::with_pool(pool).await
+    }
+
+    async fn with_pool(pool: SqlitePool) -> DbResult<Self> {
+        sqlx::migrate!().run(&pool).await?;
+        Ok(Self { pool })
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    pub fn accounts(&self) -> Accounts<'_> {
+        Accounts { pool: &self.pool }
+    }
+
+    pub fn sessions(&self) -> Sessions<'_> {
+        Sessions { pool: &self.pool }
+    }
+
+    pub fn characters(&self) -> Characters<'_> {
+        Characters { pool: &self.pool }
+    }
+
+    pub fn guilds(&self) -> Guilds<'_> {
+        Guilds { pool: &self.pool }
+    }
+
+    pub fn mail(&self) -> Mailboxes<'_> {
+        Mailboxes { pool: &self.pool }
+    }
+
+    pub fn commodity_orders(&self) -> CommodityOrders<'_> {
+        CommodityOrders { pool: &self.pool }
+    }
+
+    pub fn friends(&self) -> Friends<'_> {
+        Friends { pool: &self.pool }
+    }
+}
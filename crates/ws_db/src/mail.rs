@@ -0,0 +1,231 @@
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::DbResult;
+
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Mail {
+    pub id: i64,
+    pub sender_id: Option<i64>,
+    pub sender_name: String,
+    pub recipient_id: i64,
+    pub subject: String,
+    pub body: String,
+    pub is_read: bool,
+    pub sent_at: i64,
+    /// When the mail is deleted, in seconds since the Unix epoch.
+    pub expires_at: i64,
+}
+
+/// Items sent along with a mail, kept until the recipient takes them.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct MailAttachment {
+    pub mail_id: i64,
+    pub slot: u8,
+    pub template_id: u32,
+    pub count: u32,
+}
+
+/// The values needed to send a mail.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewMail {
+    pub sender_id: Option<i64>,
+    pub sender_name: String,
+    pub recipient_id: i64,
+    pub subject: String,
+    pub body: String,
+    /// The template and count of each attached item.
+    pub attachments: Vec<(u32, u32)>,
+    pub expires_at: i64,
+}
+
+/// The mail repository.
+#[derive(Debug, Clone, Copy)]
+pub struct Mailboxes<'a> {
+    pub(crate) pool: &'a SqlitePool,
+}
+
+impl Mailboxes<'_> {
+    /// Sends a mail along with its attachments.
+    pub async fn send(&self, mail: &NewMail) -> DbResult<Mail> {
+        let mut transaction = self.pool.begin().await?;
+        let sent = insert_mail(&mut transaction, mail).await?;
+        transaction.commit().await?;
+        Ok(sent)
+    }
+
+    /// Lists the mail of a character, the most recent first.
+    pub async fn list(&self, recipient_id: i64) -> DbResult<Vec<Mail>> {
+        let mail = sqlx::query_as(
+            "SELECT * FROM mail WHERE recipient_id = ? ORDER BY sent_at DESC, id DESC",
+        )
+        .bind(recipient_id)
+        .fetch_all(self.pool)
+        .await?;
+        Ok(mail)
+    }
+
+    /// Finds a mail received by a character.
+    pub async fn find(&self, recipient_id: i64, id: i64) -> DbResult<Option<Mail>> {
+        let mail = sqlx::query_as("SELECT * FROM mail WHERE recipient_id = ? AND id = ?")
+            .bind(recipient_id)
+            .bind(id)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(mail)
+    }
+
+    /// Lists the attachments of a mail, in the order they were attached.
+    pub async fn attachments(&self, mail_id: i64) -> DbResult<Vec<MailAttachment>> {
+        let attachments =
+            sqlx::query_as("SELECT * FROM mail_attachments WHERE mail_id = ? ORDER BY slot")
+                .bind(mail_id)
+                .fetch_all(self.pool)
+                .await?;
+        Ok(attachments)
+    }
+
+    /// Deletes the attachments of a mail once they were taken, returning how
+    /// many there were.
+    pub async fn remove_attachments(&self, mail_id: i64) -> DbResult<u64> {
+        let result = sqlx::query("DELETE FROM mail_attachments WHERE mail_id = ?")
+            .bind(mail_id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Marks a mail of a character as read. Returns false if it has no such
+    /// mail.
+    pub async fn mark_read(&self, recipient_id: i64, id: i64) -> DbResult<bool> {
+        let result = sqlx::query("UPDATE mail SET is_read = 1 WHERE recipient_id = ? AND id = ?")
+            .bind(recipient_id)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes a mail of a character along with its attachments. Returns false
+    /// if it has no such mail.
+    pub async fn delete(&self, recipient_id: i64, id: i64) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM mail WHERE recipient_id = ? AND id = ?")
+            .bind(recipient_id)
+            .bind(id)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes the mail expiring at `now` or before, in seconds since the Unix
+    /// epoch, and returns it.
+    pub async fn delete_expired(&self, now: i64) -> DbResult<Vec<Mail>> {
+        let mail = sqlx::query_as("DELETE FROM mail WHERE expires_at <= ? RETURNING *")
+            .bind(now)
+            .fetch_all(self.pool)
+            .await?;
+        Ok(mail)
+    }
+}
+
+/// Inserts a mail along with its attachments, as part of a transaction.
+pub(crate) async fn insert_mail(
+    connection: &mut SqliteConnection,
+    mail: &NewMail,
+) -> DbResult<Mail> {
+    let sent: Mail = sqlx::query_as(
+        "INSERT INTO mail \
+        (sender_id, sender_name, recipient_id, subject, body, expires_at) \
+        VALUES (?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(mail.sender_id)
+    .bind(&mail.sender_name)
+    .bind(mail.recipient_id)
+    .bind(&mail.subject)
+    .bind(&mail.body)
+    .bind(mail.expires_at)
+    .fetch_one(&mut *connection)
+    .await?;
+    for (slot, (template_id, count)) in mail.attachments.iter().enumerate() {
+        sqlx::query(
+            "INSERT INTO mail_attachments (mail_id, slot, template_id, count) \
+            VALUES (?, ?, ?, ?)",
+        )
+        .bind(sent.id)
+        .bind(slot as u8)
+        .bind(template_id)
+        .bind(count)
+        .execute(&mut *connection)
+        .await?;
+    }
+    Ok(sent)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_mail() {
+        let db = Database::in_memory().await.unwrap();
+        let account = db
+            .accounts()
+            .create("clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let mut ids = Vec::new();
+        for name in ["Sender", "Recipient"] {
+            let character = NewCharacter {
+                account_id: account.id,
+                name: name.to_string(),
+                faction: 166,
+                race: 1,
+                class: 1,
+                world_id: 870,
+                position: (0.0, 0.0, 0.0),
+                yaw: 0.0,
+                sex: 0,
+                path: 0,
+                customization: Vec::new(),
+            };
+            ids.push(db.characters().create(&character).await.unwrap().id);
+        }
+
+        let mut new_mail = NewMail {
+            sender_id: Some(ids[0]),
+            sender_name: "Sender".to_string(),
+            recipient_id: ids[1],
+            subject: "Potions".to_string(),
+            body: "For the raid".to_string(),
+            attachments: vec![(1, 20), (2, 1)],
+            expires_at: 1000,
+        };
+        let first = db.mail().send(&new_mail).await.unwrap();
+        new_mail.attachments.clear();
+        new_mail.expires_at = 2000;
+        let second = db.mail().send(&new_mail).await.unwrap();
+        assert!(!first.is_read);
+
+        let mail = db.mail().list(ids[1]).await.unwrap();
+        assert_eq!(mail, [second.clone(), first.clone()]);
+        assert!(db.mail().list(ids[0]).await.unwrap().is_empty());
+        let attachments = db.mail().attachments(first.id).await.unwrap();
+        let attachments: Vec<_> = attachments
+            .iter()
+            .map(|attachment| (attachment.slot, attachment.template_id, attachment.count))
+            .collect();
+        assert_eq!(attachments, [(0, 1, 20), (1, 2, 1)]);
+
+        // only the recipient reads its mail
+        assert!(!db.mail().mark_read(ids[0], first.id).await.unwrap());
+        assert!(db.mail().mark_read(ids[1], first.id).await.unwrap());
+        let found = db.mail().find(ids[1], first.id).await.unwrap().unwrap();
+        assert!(found.is_read);
+        assert_eq!(db.mail().remove_attachments(first.id).await.unwrap(), 2);
+
+        let expired = db.mail().delete_expired(1500).await.unwrap();
+        assert_eq!(expired, [found]);
+        assert!(db.mail().attachments(first.id).await.unwrap().is_empty());
+        assert!(db.mail().delete(ids[1], second.id).await.unwrap());
+        assert!(db.mail().list(ids[1]).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+use sqlx::SqlitePool;
+use uuid::Uuid;
+
+use crate::DbResult;
+
+/// A session key given to a client after it logged in, which it presents when
+/// connecting to the game servers.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct Session {
+    pub key: Uuid,
+    pub account_id: i64,
+    pub created_at: i64,
+}
+
+/// The sessions repository.
+#[derive(Debug, Clone, Copy)]
+pub struct Sessions<'a> {
+    pub(crate) pool: &'a SqlitePool,
+}
+
+impl Sessions<'_> {
+    /// Creates a session with a new random key.
+    pub async fn create(&self, account_id: i64) -> DbResult<Session> {
+        let session =
+            sqlx::query_as("INSERT INTO sessions (key, account_id) VALUES (?, ?) RETURNING *")
+                .bind(Uuid::new_v4())
+                .bind(account_id)
+                .fetch_one(self.pool)
+                .await?;
+        Ok(session)
+    }
+
+    pub async fn find(&self, key: &Uuid) -> DbResult<Option<Session>> {
+        let session = sqlx::query_as("SELECT * FROM sessions WHERE key = ?")
+            .bind(key)
+            .fetch_optional(self.pool)
+            .await?;
+        Ok(session)
+    }
+
+    /// Lists every session, the oldest first.
+    pub async fn list(&self) -> DbResult<Vec<Session>> {
+        let sessions = sqlx::query_as("SELECT * FROM sessions ORDER BY created_at, rowid")
+            .fetch_all(self.pool)
+            .await?;
+        Ok(sessions)
+    }
+
+    /// Lists the sessions of an account, the oldest first.
+    pub async fn list_by_account(&self, account_id: i64) -> DbResult<Vec<Session>> {
+        let sessions = sqlx::query_as(
+            "SELECT * FROM sessions WHERE account_id = ? ORDER BY created_at, rowid",
+        )
+        .bind(account_id)
+        .fetch_all(self.pool)
+        .await?;
+        Ok(sessions)
+    }
+
+    /// Deletes a session. Returns false if it didn't exist.
+    pub async fn delete(&self, key: &Uuid) -> DbResult<bool> {
+        let result = sqlx::query("DELETE FROM sessions WHERE key = ?")
+            .bind(key)
+            .execute(self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Deletes the sessions created more than `max_age` seconds ago, returning
+    /// how many were deleted.
+    pub async fn delete_expired(&self, max_age: i64) -> DbResult<u64> {
+        let result =
+            sqlx::query("DELETE FROM sessions WHERE created_at < strftime('%s', 'now') - ?")
+                .bind(max_age)
+                .execute(self.pool)
+                .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[tokio::test]
+    async fn test_sessions() {
+        let db = Database::in_memory().await.unwrap();
+        let accounts = db.accounts();
+        let alice = accounts.create("Alice", &[1; 16], &[2; 128]).await.unwrap();
+        let bob = accounts.create("Bob", &[1; 16], &[2; 128]).await.unwrap();
+
+        let sessions = db.sessions();
+        let first = sessions.create(alice.id).await.unwrap();
+        let second = sessions.create(bob.id).await.unwrap();
+        let third = sessions.create(alice.id).await.unwrap();
+        assert_eq!(
+            sessions.list().await.unwrap(),
+            [first.clone(), second.clone(), third.clone()]
+        );
+        assert_eq!(
+            sessions.list_by_account(alice.id).await.unwrap(),
+            [first.clone(), third]
+        );
+        assert_eq!(sessions.find(&second.key).await.unwrap(), Some(second));
+
+        assert!(sessions.delete(&first.key).await.unwrap());
+        assert!(!sessions.delete(&first.key).await.unwrap());
+        assert_eq!(sessions.list_by_account(alice.id).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,65 @@
+//! Decodes single packets with the message definitions of the servers.
+//!
+//! Nothing here depends on tokio or the network, so this builds for
+//! `wasm32-unknown-unknown`. With the `wasm` feature, `decodePacket` is exported
+//! to JavaScript and returns the same JSON as [`decode_packet_json`], which lets
+//! a browser capture viewer use the exact message definitions of the servers.
+
+// messages are registered by the crates that define them, so they have to be
+// linked even though nothing is used from them directly
+extern crate ws_realm;
+extern crate ws_world;
+
+mod packet;
+#[cfg(feature = "wasm")]
+mod wasm;
+
+pub use packet::*;
+
+use std::fmt;
+
+use ws_bitpack::BitPackError;
+
+#[derive(Debug)]
+pub enum DecoderError {
+    /// The data is too short to hold a packet header.
+    TooShort(usize),
+    /// The size in the header doesn't match the size of the data.
+    SizeMismatch { size: usize, actual: usize },
+    /// No message is registered for the opcode.
+    UnknownOpcode(u16),
+    /// The message body couldn't be decoded.
+    Read {
+        name: &'static str,
+        error: BitPackError,
+    },
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort(size) => write!(f, "{size} bytes is too short for a packet"),
+            Self::SizeMismatch { size, actual } => {
+                write!(
+                    f,
+                    "the header says {size} bytes but the packet has {actual}"
+                )
+            }
+            Self::UnknownOpcode(opcode) => {
+                write!(f, "no message is registered for {opcode:#06x}")
+            }
+            Self::Read { name, error } => write!(f, "failed to decode {name}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for DecoderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Read { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+pub type DecoderResult<T = ()> = Result<T, DecoderError>;
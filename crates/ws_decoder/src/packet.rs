@@ -0,0 +1,160 @@
+use std::sync::OnceLock;
+
+use serde::Serialize;
+use ws_bitpack::BitPackReader;
+use ws_messages::MessageRegistry;
+use ws_protocol::PacketHeader;
+
+use crate::{DecoderError, DecoderResult};
+
+/// A packet decoded into its message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecodedMessage {
+    pub opcode: u16,
+    /// The name of the message type.
+    pub name: &'static str,
+    /// The size of the packet, in bytes.
+    pub size: usize,
+    /// The message formatted with its `Debug` impl.
+    pub message: String,
+    /// The fields read, in the order they were read in.
+    pub fields: Vec<DecodedField>,
+    /// The bits left after the message, not counting the padding of the last
+    /// byte. Anything but 0 usually means that fields are missing from the
+    /// message definition.
+    pub trailing_bits: usize,
+}
+
+/// The bits of the packet that a field was read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecodedField {
+    /// The name of the field.
+    pub label: &'static str,
+    /// How many fields this field is nested in.
+    pub depth: usize,
+    /// The position of the first bit of the field in the packet.
+    pub start: usize,
+    /// The position right after the last bit of the field.
+    pub end: usize,
+}
+
+/// Returns the registry of every message linked in.
+pub fn registry() -> &'static MessageRegistry {
+    static REGISTRY: OnceLock<MessageRegistry> = OnceLock::new();
+    REGISTRY.get_or_init(MessageRegistry::with_registered)
+}
+
+/// Decodes a plaintext packet, header included, with the messages of every
+/// server.
+pub fn decode_packet(bytes: &[u8]) -> DecoderResult<DecodedMessage> {
+    decode_packet_with(registry(), bytes)
+}
+
+/// Decodes a plaintext packet, header included, with the messages of `registry`.
+pub fn decode_packet_with(
+    registry: &MessageRegistry,
+    bytes: &[u8],
+) -> DecoderResult<DecodedMessage> {
+    if bytes.len() < PacketHeader::BYTES {
+        return Err(DecoderError::TooShort(bytes.len()));
+    }
+    let mut reader = BitPackReader::new(bytes);
+    let header: PacketHeader = reader
+        .read()
+        .map_err(|_| DecoderError::TooShort(bytes.len()))?;
+    if header.size as usize != bytes.len() {
+        return Err(DecoderError::SizeMismatch {
+            size: header.size as usize,
+            actual: bytes.len(),
+        });
+    }
+
+    let registration = registry
+        .get(header.opcode as u32)
+        .ok_or(DecoderError::UnknownOpcode(header.opcode))?;
+    reader.start_trace();
+    let message = (registration.read)(&mut reader).map_err(|error| DecoderError::Read {
+        name: registration.name,
+        error,
+    })?;
+    let fields = reader
+        .take_trace()
+        .map(|trace| {
+            trace
+                .spans()
+                .iter()
+                .map(|span| DecodedField {
+                    label: span.label,
+                    depth: span.depth,
+                    start: span.offset,
+                    // every span is finished once the message was read
+                    end: span.end.unwrap_or(span.offset),
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+
+    Ok(DecodedMessage {
+        opcode: header.opcode,
+        name: registration.name,
+        size: bytes.len(),
+        message: format!("{:#?}", registration.debug_message(&*message)),
+        fields,
+        trailing_bits: match reader.remaining_bits() {
+            bits if bits < 8 => 0,
+            bits => bits,
+        },
+    })
+}
+
+/// Decodes a packet like [`decode_packet`] and returns the result as JSON.
+///
+/// This is the [`DecodedMessage`] on success, and an object with an `error`
+/// string otherwise.
+pub fn decode_packet_json(bytes: &[u8]) -> String {
+    let json = match decode_packet(bytes) {
+        Ok(message) => serde_json::to_value(message),
+        Err(error) => Ok(serde_json::json!({ "error": error.to_string() })),
+    };
+    json.map(|json| json.to_string())
+        .unwrap_or_else(|error| serde_json::json!({ "error": error.to_string() }).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_protocol::FrameEncoder;
+    use ws_world::ServerAuthAccepted;
+
+    use super::*;
+
+    #[test]
+    fn test_decode_packet() {
+        let accepted = ServerAuthAccepted { account_id: 7 };
+        let data = FrameEncoder::new().encode_value(0x0591, &accepted).unwrap();
+
+        let decoded = decode_packet(&data).unwrap();
+        assert_eq!(decoded.name, "ServerAuthAccepted");
+        assert_eq!(decoded.size, data.len());
+        assert_eq!(
+            decoded.fields,
+            [DecodedField {
+                label: "account_id",
+                depth: 0,
+                start: PacketHeader::BITS,
+                end: PacketHeader::BITS + 32,
+            }]
+        );
+        assert_eq!(decoded.trailing_bits, 0);
+
+        let json: serde_json::Value = serde_json::from_str(&decode_packet_json(&data)).unwrap();
+        assert_eq!(json["opcode"], 0x0591);
+        assert_eq!(json["fields"][0]["label"], "account_id");
+
+        assert!(matches!(
+            decode_packet(&data[..data.len() - 1]),
+            Err(DecoderError::SizeMismatch { .. })
+        ));
+        let json: serde_json::Value = serde_json::from_str(&decode_packet_json(&[1])).unwrap();
+        assert_eq!(json["error"], "1 bytes is too short for a packet");
+    }
+}
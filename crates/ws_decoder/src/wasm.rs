@@ -0,0 +1,9 @@
+use wasm_bindgen::prelude::*;
+
+/// Decodes a plaintext packet, header included, into JSON.
+///
+/// See [`crate::decode_packet_json`] for the format.
+#[wasm_bindgen(js_name = decodePacket)]
+pub fn decode_packet(bytes: &[u8]) -> String {
+    crate::decode_packet_json(bytes)
+}
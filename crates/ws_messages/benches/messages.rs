@@ -0,0 +1,131 @@
+//! Benchmarks of derived messages, read and written as a whole.
+//!
+//! Run with `cargo bench -p ws_messages`.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use ws_bitpack::*;
+use ws_messages::*;
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+enum Class {
+    Warrior = 1,
+    Esper = 3,
+}
+
+#[derive(MessageStruct, Debug, Clone, PartialEq)]
+struct Position {
+    x: f32,
+    y: f32,
+    z: f32,
+}
+
+#[derive(MessageUnion, Debug, Clone, PartialEq)]
+enum Appearance {
+    Default {},
+    Custom {
+        #[packed(5)]
+        count: u8,
+        #[length(count)]
+        #[packed(10)]
+        bones: Vec<u16>,
+    },
+}
+
+#[derive(MessageStruct, Debug, Clone, PartialEq)]
+struct Entry {
+    id: u64,
+    name: String,
+    #[packed(5)]
+    class: Class,
+    #[packed(7)]
+    level: u8,
+    position: Position,
+    has_appearance: bool,
+    #[variant(has_appearance)]
+    appearance: Appearance,
+    #[aligned]
+    #[length(8)]
+    #[bytes]
+    checksum: Vec<u8>,
+}
+
+/// A message about the size of a large character list or world update.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0001)]
+struct LargeMessage {
+    #[length_of(entries)]
+    count: u16,
+    #[length(count)]
+    entries: Vec<Entry>,
+}
+
+fn large_message() -> LargeMessage {
+    let entries: Vec<Entry> = (0..200)
+        .map(|i| Entry {
+            id: 0x0100_0000_0000_0000 + i,
+            name: format!("Character {i}"),
+            class: if i % 2 == 0 {
+                Class::Warrior
+            } else {
+                Class::Esper
+            },
+            level: (i % 50) as u8 + 1,
+            position: Position {
+                x: i as f32 * 1.5,
+                y: -20.0,
+                z: i as f32 * -0.5,
+            },
+            has_appearance: i % 3 == 0,
+            appearance: match i % 3 {
+                0 => Appearance::Custom {
+                    count: 12,
+                    bones: (0..12).map(|bone| bone * 17).collect(),
+                },
+                _ => Appearance::Default {},
+            },
+            checksum: vec![i as u8; 8],
+        })
+        .collect();
+    LargeMessage {
+        count: entries.len() as u16,
+        entries,
+    }
+}
+
+fn bench_round_trip(c: &mut Criterion) {
+    let message = large_message();
+    let mut writer = BitPackWriter::growable();
+    writer.write(&message).unwrap();
+    let data = writer.finish().unwrap();
+    assert_eq!(
+        BitPackReader::new(&data).read::<LargeMessage>().unwrap(),
+        message
+    );
+
+    let mut group = c.benchmark_group("large_message");
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function("bits", |b| b.iter(|| black_box(message.bits())));
+    group.bench_function("write", |b| {
+        let mut buffer = vec![0u8; data.len()];
+        b.iter(|| {
+            let mut writer = BitPackWriter::new(&mut buffer);
+            writer.write(&message).unwrap();
+        })
+    });
+    group.bench_function("read", |b| {
+        b.iter(|| black_box(BitPackReader::new(&data).read::<LargeMessage>().unwrap()))
+    });
+    group.bench_function("round_trip", |b| {
+        b.iter(|| {
+            let mut writer = BitPackWriter::with_capacity(data.len());
+            writer.write(&message).unwrap();
+            let data = writer.finish().unwrap();
+            black_box(BitPackReader::new(&data).read::<LargeMessage>().unwrap())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_round_trip);
+criterion_main!(benches);
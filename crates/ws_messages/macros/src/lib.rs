@@ -4,70 +4,178 @@ use syn::{parse_macro_input, DeriveInput, Field, Type};
 
 #[proc_macro_derive(Message, attributes(message_id))]
 pub fn derive_message(input: TokenStream) -> TokenStream {
-    let _ast = parse_macro_input!(input as DeriveInput);
+    let ast = parse_macro_input!(input as DeriveInput);
+
+    let ident = &ast.ident;
+    let id = match get_message_id(&ast) {
+        Ok(id) => id,
+        Err(error) => return error.to_compile_error().into(),
+    };
 
-    let expanded = quote! {};
+    let expanded = quote! {
+        impl ws_messages::Message for #ident {
+            fn id() -> u32 {
+                #id
+            }
+        }
+
+        const _: fn() = || {
+            fn assert_message_struct<T: ws_messages::MessageStruct>() {}
+            assert_message_struct::<#ident>();
+        };
+
+        ws_messages::inventory::submit! {
+            ws_messages::MessageRegistration {
+                id: #id,
+                name: stringify!(#ident),
+                read: ws_messages::read_any::<#ident>,
+                debug: ws_messages::debug_any::<#ident>,
+            }
+        }
+    };
 
     TokenStream::from(expanded)
 }
 
-#[proc_macro_derive(MessageStruct, attributes(aligned, packed, length, variant, ascii))]
+fn get_message_id(ast: &DeriveInput) -> syn::Result<u32> {
+    let attr = ast
+        .attrs
+        .iter()
+        .find(|a| a.path.is_ident("message_id"))
+        .ok_or_else(|| {
+            syn::Error::new_spanned(
+                &ast.ident,
+                "Deriving Message requires a #[message_id(...)] attribute",
+            )
+        })?;
+    attr.parse_args::<syn::LitInt>()?.base10_parse()
+}
+
+#[proc_macro_derive(
+    MessageStruct,
+    attributes(
+        aligned,
+        packed,
+        length,
+        length_of,
+        variant,
+        ascii,
+        bytes,
+        wide_fixed,
+        flags,
+        trailing,
+        default_on_eof,
+        present,
+        read_if,
+        skip_if
+    )
+)]
 pub fn derive_message_struct(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
     let data_struct = if let syn::Data::Struct(s) = ast.data {
         s
     } else {
-        return TokenStream::from(quote!(compile_error!(
-            "Deriving MessageStruct is only valid on a struct."
-        )));
+        return syn::Error::new_spanned(
+            &ast.ident,
+            "Deriving MessageStruct is only valid on a struct",
+        )
+        .to_compile_error()
+        .into();
+    };
+    let fields = match parse_fields(&data_struct.fields) {
+        Ok(fields) => fields,
+        Err(error) => return error.to_compile_error().into(),
     };
 
     let ident = &ast.ident;
-    let field_idents = data_struct
-        .fields
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let read_generics = add_trait_bounds(&ast.generics, quote!(ws_bitpack::ReadValue));
+    let (read_impl_generics, _, read_where_clause) = read_generics.split_for_impl();
+    let write_generics = add_trait_bounds(&ast.generics, quote!(ws_bitpack::WriteValue));
+    let (write_impl_generics, _, write_where_clause) = write_generics.split_for_impl();
+    let field_idents = fields.iter().map(|field| &field.ident).collect::<Vec<_>>();
+    let field_types = fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let type_name = ident.to_string();
+    let field_reads = fields
         .iter()
-        .filter_map(|field| field.ident.as_ref())
+        .map(|field| with_field_context(&type_name, field, get_field_read(field)))
         .collect::<Vec<_>>();
-    let field_reads = data_struct
-        .fields
-        .iter()
-        .map(get_field_read)
-        .collect::<Vec<_>>();
-    let field_writes = data_struct
-        .fields
+    let field_labels = fields.iter().map(|field| &field.name).collect::<Vec<_>>();
+    let field_descriptors = fields.iter().map(get_field_descriptor).collect::<Vec<_>>();
+    let construct = match &data_struct.fields {
+        syn::Fields::Named(_) => quote!(#ident { #(#field_idents,)* }),
+        syn::Fields::Unnamed(_) => quote!(#ident(#(#field_idents,)*)),
+        syn::Fields::Unit => quote!(#ident),
+    };
+    // positional fields are only reachable once `self` is destructured
+    let (destructure, access) = match &data_struct.fields {
+        syn::Fields::Named(_) => (quote!(), FieldAccess::AsField),
+        _ => (quote!(let #construct = self;), FieldAccess::AsVar),
+    };
+    let field_writes = fields
         .iter()
-        .map(|field| get_field_write(field, FieldAccess::AsField))
+        .map(|field| get_field_write(field, access))
         .collect::<Vec<_>>();
-    let field_bits = data_struct
-        .fields
+    let field_bits = fields
         .iter()
-        .map(|field| get_field_bits(field, FieldAccess::AsField))
+        .map(|field| get_field_bits(field, access))
         .collect::<Vec<_>>();
+    let schema = register_schema(
+        &ast.generics,
+        quote! {
+            ws_messages::TypeSchema {
+                name: #type_name,
+                shape: ws_messages::TypeShape::Struct(
+                    <#ident as ws_messages::MessageSchema>::FIELDS
+                ),
+            }
+        },
+    );
 
     let expanded = quote! {
-        impl MessageStruct for #ident {}
+        impl #impl_generics MessageStruct for #ident #ty_generics #where_clause {}
 
-        impl ws_bitpack::ReadValue for #ident {
+        impl #impl_generics ws_messages::MessageSchema for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #type_name;
+            const FIELDS: &'static [ws_messages::FieldDescriptor] = &[#(#field_descriptors,)*];
+        }
+
+        #schema
+
+        impl #read_impl_generics ws_bitpack::ReadValue for #ident #ty_generics #read_where_clause {
             fn read(reader_: &mut ws_bitpack::BitPackReader) -> ws_bitpack::BitPackResult<Self> {
                 use ws_bitpack::*;
-                #(let #field_idents = #field_reads;)*
-                Ok(#ident {
-                    #(#field_idents,)*
+                reader_.nested(|reader_| {
+                    #(
+                        reader_.trace_enter(#field_labels);
+                        let #field_idents: #field_types = #field_reads;
+                        reader_.trace_exit();
+                    )*
+                    Ok(#construct)
                 })
             }
         }
 
-        impl ws_bitpack::WriteValue for #ident {
+        impl #write_impl_generics ws_bitpack::WriteValue for #ident #ty_generics #write_where_clause {
             fn write(&self, writer_: &mut ws_bitpack::BitPackWriter) -> ws_bitpack::BitPackResult {
                 use ws_bitpack::*;
-                #(#field_writes;)*
+                #destructure
+                #(
+                    writer_.trace_enter(#field_labels);
+                    #field_writes;
+                    writer_.trace_exit();
+                )*
                 Ok(())
             }
             fn bits(&self) -> usize {
-                let mut bits_: usize = 0;
+                self.bits_at(0)
+            }
+            fn bits_at(&self, position_: usize) -> usize {
+                let mut bits_: usize = position_;
+                #destructure
                 #(#field_bits;)*
-                bits_
+                bits_ - position_
             }
         }
     };
@@ -75,76 +183,143 @@ pub fn derive_message_struct(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
-#[proc_macro_derive(MessageUnion)]
+#[proc_macro_derive(
+    MessageUnion,
+    attributes(
+        aligned,
+        packed,
+        length,
+        length_of,
+        variant,
+        ascii,
+        bytes,
+        wide_fixed,
+        flags,
+        trailing,
+        default_on_eof,
+        present,
+        read_if,
+        skip_if,
+        variant_index,
+        selector
+    )
+)]
 pub fn derive_message_union(input: TokenStream) -> TokenStream {
     let ast = parse_macro_input!(input as DeriveInput);
 
     let data_enum = match ast.data {
         syn::Data::Enum(e) => e,
         _ => {
-            return TokenStream::from(quote!(compile_error!(
-                "Deriving MessageStruct is only valid on a struct."
-            )))
+            return syn::Error::new_spanned(
+                &ast.ident,
+                "Deriving MessageUnion is only valid on an enum",
+            )
+            .to_compile_error()
+            .into()
         }
     };
+    let variants = match parse_variants(&data_enum) {
+        Ok(variants) => variants,
+        Err(error) => return error.to_compile_error().into(),
+    };
 
     let ident = &ast.ident;
-    let variant_indices = data_enum
-        .variants
+    let (impl_generics, ty_generics, where_clause) = ast.generics.split_for_impl();
+    let read_generics = add_trait_bounds(&ast.generics, quote!(ws_bitpack::ReadValue));
+    let (read_impl_generics, _, read_where_clause) = read_generics.split_for_impl();
+    let write_generics = add_trait_bounds(&ast.generics, quote!(ws_bitpack::WriteValue));
+    let (write_impl_generics, _, write_where_clause) = write_generics.split_for_impl();
+    let variant_values = variants
         .iter()
-        .enumerate()
-        .map(|(index, _)| index)
+        .map(|variant| &variant.selectors[0])
         .collect::<Vec<_>>();
-    let variant_idents = data_enum
-        .variants
+    let variant_idents = variants
         .iter()
-        .map(|variant| &variant.ident)
+        .map(|variant| &variant.variant.ident)
         .collect::<Vec<_>>();
-    let variants_with_fields = data_enum
-        .variants
+    let variant_matches = variants
+        .iter()
+        .map(|variant| {
+            let selectors = &variant.selectors;
+            quote!(_ if #(variant_ == #selectors)||*)
+        })
+        .collect::<Vec<_>>();
+    let variant_reads = variants
         .iter()
-        .map(|variant| match &variant.fields {
-            syn::Fields::Named(fields) => {
-                let fields = fields.named.iter().collect::<Vec<_>>();
-                (variant, fields)
-            }
-            _ => panic!("Only named fields are supported for unions."),
-        });
-    let variant_reads = variants_with_fields
-        .clone()
-        .map(|(variant, fields)| {
-            let variant_ident = &variant.ident;
+        .map(|variant| {
+            let fields = &variant.fields;
             let field_idents = fields.iter().map(|field| &field.ident).collect::<Vec<_>>();
+            let field_types = fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+            let type_name = format!("{ident}::{}", variant.variant.ident);
             let field_reads = fields
                 .iter()
-                .map(|field| get_field_read(*field))
+                .map(|field| with_field_context(&type_name, field, get_field_read(field)))
                 .collect::<Vec<_>>();
+            let construct = variant.pattern(ident);
             quote! {{
-                #(let #field_idents = #field_reads;)*
-                #ident::#variant_ident {
-                    #(#field_idents,)*
-                }
+                #(let #field_idents: #field_types = #field_reads;)*
+                #construct
             }}
         })
         .collect::<Vec<_>>();
-    let variant_writes = variants_with_fields
-        .map(|(variant, fields)| {
-            let variant_ident = &variant.ident;
-            let field_idents = fields
+    let variant_descriptors = variants
+        .iter()
+        .map(|variant| {
+            let name = variant.variant.ident.to_string();
+            let index = &variant.selectors[0];
+            let selectors = &variant.selectors;
+            let field_descriptors = variant
+                .fields
                 .iter()
-                .map(|field| {
-                    field
-                        .ident
-                        .as_ref()
-                        .expect("Unexpected field with no ident")
-                })
+                .map(get_field_descriptor)
+                .collect::<Vec<_>>();
+            quote! {
+                ws_messages::VariantDescriptor {
+                    name: #name,
+                    index: #index,
+                    selectors: &[#(#selectors,)*],
+                    fields: &[#(#field_descriptors,)*],
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let type_name = ident.to_string();
+    let schema = register_schema(
+        &ast.generics,
+        quote! {
+            ws_messages::TypeSchema {
+                name: #type_name,
+                shape: ws_messages::TypeShape::Union(&[#(#variant_descriptors,)*]),
+            }
+        },
+    );
+    let variant_bits = variants
+        .iter()
+        .map(|variant| {
+            let pattern = variant.pattern(ident);
+            let field_bits = variant
+                .fields
+                .iter()
+                .map(|field| get_field_bits(field, FieldAccess::AsVar))
                 .collect::<Vec<_>>();
-            let field_writes = fields
+            quote! {
+                #pattern => {
+                    #(#field_bits;)*
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    let variant_writes = variants
+        .iter()
+        .map(|variant| {
+            let pattern = variant.pattern(ident);
+            let field_writes = variant
+                .fields
                 .iter()
-                .map(|field| get_field_write(*field, FieldAccess::AsVar))
+                .map(|field| get_field_write(field, FieldAccess::AsVar))
                 .collect::<Vec<_>>();
             quote! {
-                #ident::#variant_ident { #(#field_idents,)* } => {
+                #pattern => {
                     #(#field_writes;)*
                 }
             }
@@ -152,29 +327,37 @@ pub fn derive_message_union(input: TokenStream) -> TokenStream {
         .collect::<Vec<_>>();
 
     let expanded = quote! {
-        impl ws_bitpack::UnionVariant for #ident {
+        #schema
+
+        impl #impl_generics ws_bitpack::UnionVariant for #ident #ty_generics #where_clause {
             fn variant(&self) -> usize {
                 match self {
-                    #(#ident::#variant_idents { .. } => #variant_indices,)*
+                    #(#ident::#variant_idents { .. } => #variant_values,)*
                 }
             }
         }
 
-        impl ws_bitpack::ReadUnionValue for #ident {
+        impl #read_impl_generics ws_bitpack::ReadUnionValue for #ident #ty_generics #read_where_clause {
             fn read_union(
                 reader_: &mut BitPackReader,
                 variant_: usize,
             ) -> ws_bitpack::BitPackResult<Self> {
                 use ws_bitpack::*;
-                Ok(match variant_ {
-                    #(#variant_indices => #variant_reads,)*
-                    // TODO: use an error instead
-                    _ => panic!("Invalid union variant {}", variant_)
+                reader_.nested(|reader_| {
+                    Ok(match variant_ {
+                        #(#variant_matches => #variant_reads,)*
+                        _ => {
+                            return Err(BitPackError::InvalidUnionVariant {
+                                type_name: stringify!(#ident),
+                                variant: variant_,
+                            })
+                        }
+                    })
                 })
             }
         }
 
-        impl ws_bitpack::WriteValue for #ident {
+        impl #write_impl_generics ws_bitpack::WriteValue for #ident #ty_generics #write_where_clause {
             fn write(
                 &self,
                 writer_: &mut BitPackWriter,
@@ -185,7 +368,14 @@ pub fn derive_message_union(input: TokenStream) -> TokenStream {
                 })
             }
             fn bits(&self) -> usize {
-                0 // TODO
+                self.bits_at(0)
+            }
+            fn bits_at(&self, position_: usize) -> usize {
+                let mut bits_: usize = position_;
+                match self {
+                    #(#variant_bits,)*
+                }
+                bits_ - position_
             }
         }
 
@@ -193,7 +383,7 @@ pub fn derive_message_union(input: TokenStream) -> TokenStream {
         impl ws_messages::writer::UnionVariant<#ident> for ws_messages::writer::MessageWriter<#ident> {
             fn variant(value: &#ident) -> usize {
                 match value {
-                    #(#ident::#variant_idents { .. } => #variant_indices,)*
+                    #(#ident::#variant_idents { .. } => #variant_values,)*
                 }
             }
         }
@@ -203,49 +393,782 @@ pub fn derive_message_union(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
-fn get_field_read(field: &Field) -> proc_macro2::TokenStream {
-    let field_metadata = get_field_metadata(field, FieldAccess::AsVar);
-    let align_expr = match get_field_aligned(field) {
+/// A variant of a derived union, with the selector value that reads it.
+struct MessageVariant<'a> {
+    variant: &'a syn::Variant,
+    /// The selector values reading this variant as `usize` expressions, the
+    /// first one being the value written for it.
+    selectors: Vec<proc_macro2::TokenStream>,
+    fields: Vec<MessageField>,
+}
+
+impl MessageVariant<'_> {
+    /// Returns the pattern binding the fields of the variant, which also builds
+    /// it once they are read.
+    fn pattern(&self, ident: &syn::Ident) -> proc_macro2::TokenStream {
+        let variant_ident = &self.variant.ident;
+        let field_idents = self.fields.iter().map(|field| &field.ident);
+        match &self.variant.fields {
+            syn::Fields::Named(_) => quote!(#ident::#variant_ident { #(#field_idents,)* }),
+            syn::Fields::Unnamed(_) => quote!(#ident::#variant_ident(#(#field_idents,)*)),
+            syn::Fields::Unit => quote!(#ident::#variant_ident),
+        }
+    }
+}
+
+/// How the selector value of a variant is given.
+enum VariantSelector {
+    /// `#[variant_index(n)]`
+    Index(usize),
+    /// `#[selector(a, b, ...)]`: values of the selector field, like enum
+    /// variants or constants.
+    Values(Vec<syn::Expr>),
+}
+
+/// Parses the variants of a union. Like enum discriminants, variants without a
+/// `#[variant_index(...)]` or `#[selector(...)]` attribute take the index
+/// after the previous one, which must then be known.
+fn parse_variants(data_enum: &syn::DataEnum) -> syn::Result<Vec<MessageVariant<'_>>> {
+    let mut variants: Vec<MessageVariant> = Vec::new();
+    let mut indices: Vec<(usize, &syn::Ident)> = Vec::new();
+    let mut errors = Errors::default();
+    let mut next_index = Some(0);
+    for variant in &data_enum.variants {
+        let selector = match parse_variant_selector(variant) {
+            Ok(Some(selector)) => selector,
+            Ok(None) => match next_index {
+                Some(index) => VariantSelector::Index(index),
+                None => {
+                    errors.push(syn::Error::new_spanned(
+                        &variant.ident,
+                        "Variants after one with #[selector(...)] need a #[variant_index(...)] or #[selector(...)] attribute",
+                    ));
+                    continue;
+                }
+            },
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        let selectors = match selector {
+            VariantSelector::Index(index) => {
+                next_index = Some(index + 1);
+                if let Some((_, other)) = indices.iter().find(|(other, _)| *other == index) {
+                    errors.push(syn::Error::new_spanned(
+                        &variant.ident,
+                        format!("Variant index {index} is already used by {other}"),
+                    ));
+                }
+                indices.push((index, &variant.ident));
+                vec![quote!(#index)]
+            }
+            VariantSelector::Values(values) => {
+                next_index = None;
+                values
+                    .iter()
+                    .map(|value| quote!((#value) as usize))
+                    .collect()
+            }
+        };
+        match parse_fields(&variant.fields) {
+            Ok(fields) => variants.push(MessageVariant {
+                variant,
+                selectors,
+                fields,
+            }),
+            Err(error) => errors.push(error),
+        }
+    }
+    errors.finish(variants)
+}
+
+fn parse_variant_selector(variant: &syn::Variant) -> syn::Result<Option<VariantSelector>> {
+    let mut attrs = variant
+        .attrs
+        .iter()
+        .filter(|a| a.path.is_ident("variant_index") || a.path.is_ident("selector"));
+    let selector = match attrs.next() {
+        Some(attr) if attr.path.is_ident("variant_index") => {
+            VariantSelector::Index(attr.parse_args::<syn::LitInt>()?.base10_parse()?)
+        }
+        Some(attr) => {
+            let values = attr.parse_args_with(
+                syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated,
+            )?;
+            if values.is_empty() {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "#[selector(...)] requires at least one value",
+                ));
+            }
+            VariantSelector::Values(values.into_iter().collect())
+        }
+        None => return Ok(None),
+    };
+    match attrs.next() {
+        Some(attr) => Err(syn::Error::new_spanned(
+            attr,
+            "Only one #[variant_index] or #[selector] attribute is allowed",
+        )),
+        None => Ok(Some(selector)),
+    }
+}
+
+#[proc_macro_derive(MessageEnum)]
+pub fn derive_message_enum(input: TokenStream) -> TokenStream {
+    let ast = parse_macro_input!(input as DeriveInput);
+    let repr = get_enum_repr(&ast);
+
+    let data_enum = match ast.data {
+        syn::Data::Enum(e) => e,
+        _ => {
+            return syn::Error::new_spanned(
+                &ast.ident,
+                "Deriving MessageEnum is only valid on an enum",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+    if let Some(variant) = data_enum
+        .variants
+        .iter()
+        .find(|variant| !matches!(variant.fields, syn::Fields::Unit))
+    {
+        return syn::Error::new_spanned(
+            &variant.fields,
+            "Deriving MessageEnum is only valid on an enum without fields",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let ident = &ast.ident;
+    let variant_idents = data_enum
+        .variants
+        .iter()
+        .map(|variant| &variant.ident)
+        .collect::<Vec<_>>();
+    let type_name = ident.to_string();
+    let repr_name = repr.to_string();
+    let variant_names = variant_idents
+        .iter()
+        .map(|ident| ident.to_string())
+        .collect::<Vec<_>>();
+    let schema = register_schema(
+        &ast.generics,
+        quote! {
+            ws_messages::TypeSchema {
+                name: #type_name,
+                shape: ws_messages::TypeShape::Enum {
+                    repr: #repr_name,
+                    values: &[#((#variant_names, #ident::#variant_idents as i64),)*],
+                },
+            }
+        },
+    );
+
+    let expanded = quote! {
+        #schema
+
+        impl #ident {
+            fn from_repr_(value_: #repr) -> ws_bitpack::BitPackResult<Self> {
+                #(
+                    if value_ == #ident::#variant_idents as #repr {
+                        return Ok(#ident::#variant_idents);
+                    }
+                )*
+                Err(ws_bitpack::BitPackError::InvalidEnumValue {
+                    type_name: stringify!(#ident),
+                    value: value_ as i128,
+                })
+            }
+
+            fn to_repr_(&self) -> #repr {
+                match self {
+                    #(#ident::#variant_idents => #ident::#variant_idents as #repr,)*
+                }
+            }
+        }
+
+        impl ws_bitpack::ReadValue for #ident {
+            fn read(reader_: &mut ws_bitpack::BitPackReader) -> ws_bitpack::BitPackResult<Self> {
+                Self::from_repr_(reader_.read()?)
+            }
+        }
+
+        impl ws_bitpack::WriteValue for #ident {
+            fn write(&self, writer_: &mut ws_bitpack::BitPackWriter) -> ws_bitpack::BitPackResult {
+                writer_.write(&self.to_repr_())
+            }
+            fn bits(&self) -> usize {
+                #repr::BITS as usize
+            }
+        }
+
+        impl ws_bitpack::ReadPackedValue for #ident {
+            fn read_packed(
+                reader_: &mut ws_bitpack::BitPackReader,
+                bits_: usize,
+            ) -> ws_bitpack::BitPackResult<Self> {
+                Self::from_repr_(reader_.read_packed(bits_)?)
+            }
+        }
+
+        impl ws_bitpack::WritePackedValue for #ident {
+            fn write_packed(
+                &self,
+                writer_: &mut ws_bitpack::BitPackWriter,
+                bits_: usize,
+            ) -> ws_bitpack::BitPackResult {
+                writer_.write_packed(&self.to_repr_(), bits_)
+            }
+        }
+    };
+
+    TokenStream::from(expanded)
+}
+
+/// Registers the schema of a derived type, unless it is generic.
+fn register_schema(
+    generics: &syn::Generics,
+    schema: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    match generics.params.is_empty() {
+        true => quote! {
+            ws_messages::inventory::submit! { #schema }
+        },
+        false => quote!(),
+    }
+}
+
+/// Returns the integer type from the `#[repr(...)]` attribute of an enum, which
+/// defaults to `u32` like most protocol enums.
+fn get_enum_repr(ast: &DeriveInput) -> proc_macro2::TokenStream {
+    ast.attrs
+        .iter()
+        .filter(|a| a.path.is_ident("repr"))
+        .filter_map(|attr| attr.parse_args::<syn::Ident>().ok())
+        .find(|ident| {
+            ["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64"].contains(&&*ident.to_string())
+        })
+        .map(|ident| quote!(#ident))
+        .unwrap_or_else(|| quote!(u32))
+}
+
+/// Adds a bound to every type parameter of the generics, so that a generic message
+/// can only be read or written if its type parameters can.
+fn add_trait_bounds(generics: &syn::Generics, bound: proc_macro2::TokenStream) -> syn::Generics {
+    let mut generics = generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(syn::parse_quote!(#bound));
+    }
+    generics
+}
+
+/// Collects errors so that all of them are reported at once.
+#[derive(Default)]
+struct Errors(Option<syn::Error>);
+
+impl Errors {
+    fn push(&mut self, error: syn::Error) {
+        match &mut self.0 {
+            Some(errors) => errors.combine(error),
+            None => self.0 = Some(error),
+        }
+    }
+
+    fn finish<T>(self, value: T) -> syn::Result<T> {
+        match self.0 {
+            Some(errors) => Err(errors),
+            None => Ok(value),
+        }
+    }
+}
+
+/// Parses the fields of a struct or union variant, reporting the errors of all
+/// of them.
+fn parse_fields(fields: &syn::Fields) -> syn::Result<Vec<MessageField>> {
+    let mut parsed = Vec::new();
+    let mut errors = Errors::default();
+    let mut default_on_eof = false;
+    for (index, field) in fields.iter().enumerate() {
+        match MessageField::parse(field, index) {
+            Ok(mut field) => {
+                // the fields after the first one marked can be missing too
+                default_on_eof |= field.default_on_eof;
+                field.default_on_eof = default_on_eof;
+                parsed.push(field);
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+    errors.finish(parsed)
+}
+
+/// A field of a derived struct or union variant, with its attributes parsed.
+struct MessageField {
+    /// The variable the field is read into, `_0`, `_1`, etc. for positional
+    /// fields.
+    ident: syn::Ident,
+    /// The name of the field in schemas and errors, its index for positional
+    /// fields.
+    name: String,
+    ty: Type,
+    metadata: FieldMetadata,
+    aligned: bool,
+    trailing: bool,
+    /// Whether the field falls back to its default value when the data ends
+    /// right before it, lenient reader or not. `#[default_on_eof]` applies to
+    /// the field it's on and all the following ones.
+    default_on_eof: bool,
+    /// The field named by a `#[length_of(...)]` attribute. The length of that
+    /// field is written in place of this field's value, so that they can't get
+    /// out of sync.
+    length_of: Option<syn::Ident>,
+    /// The condition of a `#[present(...)]` attribute, which decides whether an
+    /// `Option` field is read. It can be any expression using previous fields.
+    /// `#[read_if(...)]` is the same attribute, and `#[skip_if(...)]` takes the
+    /// opposite condition.
+    ///
+    /// When writing, the value is written only if it is `Some`, so keeping the
+    /// condition in sync is up to the user.
+    present: Option<proc_macro2::TokenStream>,
+}
+
+/// Attributes that decide how a value is encoded, which mostly exclude each other.
+const ENCODING_ATTRIBUTES: [&str; 7] = [
+    "packed",
+    "length",
+    "variant",
+    "ascii",
+    "bytes",
+    "wide_fixed",
+    "flags",
+];
+
+/// Returns whether two encoding attributes can be used on the same field.
+fn encodings_compatible(a: &str, b: &str) -> bool {
+    matches!(
+        (a, b),
+        ("packed", "length")
+            | ("length", "packed")
+            | ("bytes", "length")
+            | ("length", "bytes")
+            | ("variant", "length")
+            | ("length", "variant")
+    )
+}
+
+impl MessageField {
+    fn parse(field: &Field, index: usize) -> syn::Result<Self> {
+        let (ident, name) = match &field.ident {
+            Some(ident) => (ident.clone(), ident.to_string()),
+            None => (quote::format_ident!("_{}", index), index.to_string()),
+        };
+
+        let mut aligned = false;
+        let mut trailing = false;
+        let mut default_on_eof = false;
+        let mut ascii = false;
+        let mut bytes = None;
+        let mut packed = None;
+        let mut wide_fixed = None;
+        let mut flags = None;
+        let mut length = None;
+        let mut length_of = None;
+        let mut variant = None;
+        let mut present = None;
+        let mut seen: Vec<(String, &syn::Attribute)> = Vec::new();
+        for attr in &field.attrs {
+            let attr_name = match attr.path.get_ident() {
+                Some(ident) => ident.to_string(),
+                None => continue,
+            };
+            match attr_name.as_str() {
+                "aligned" => aligned = parse_marker(attr, &attr_name)?,
+                "trailing" => trailing = parse_marker(attr, &attr_name)?,
+                "default_on_eof" => default_on_eof = parse_marker(attr, &attr_name)?,
+                "ascii" => ascii = parse_marker(attr, &attr_name)?,
+                "bytes" => {
+                    parse_marker(attr, &attr_name)?;
+                    bytes = Some(attr);
+                }
+                "packed" => {
+                    let bits = attr.parse_args_with(parse_bits)?;
+                    if is_float_type(&field.ty) {
+                        return Err(syn::Error::new_spanned(
+                            attr,
+                            "Floating point values can't be packed",
+                        ));
+                    }
+                    packed = Some(bits);
+                }
+                "wide_fixed" => {
+                    wide_fixed = Some(attr.parse_args::<syn::LitInt>()?.base10_parse()?)
+                }
+                "flags" => flags = Some(attr.parse_args_with(parse_flags_args)?),
+                "length" => length = Some(attr.parse_args_with(parse_length_args)?),
+                "length_of" => length_of = Some(attr.parse_args::<syn::Ident>()?),
+                "variant" => variant = Some(attr.parse_args_with(parse_variant_args)?),
+                "present" | "read_if" => {
+                    present = Some(attr.parse_args::<syn::Expr>()?.to_token_stream())
+                }
+                "skip_if" => {
+                    let condition = attr.parse_args::<syn::Expr>()?;
+                    present = Some(quote!(!(#condition)));
+                }
+                _ => continue,
+            }
+
+            let group = match attr_name.as_str() {
+                "read_if" | "skip_if" => "present",
+                name => name,
+            };
+            if let Some((other, _)) = seen.iter().find(|(other, _)| {
+                let other = match other.as_str() {
+                    "read_if" | "skip_if" => "present",
+                    other => other,
+                };
+                other == group
+            }) {
+                let error = match other == &attr_name {
+                    true => format!("Duplicate #[{attr_name}] attribute"),
+                    false => format!("#[{attr_name}] can't be combined with #[{other}]"),
+                };
+                return Err(syn::Error::new_spanned(attr, error));
+            }
+            if ENCODING_ATTRIBUTES.contains(&group) {
+                let conflict = seen.iter().find(|(other, _)| {
+                    ENCODING_ATTRIBUTES.contains(&other.as_str())
+                        && !encodings_compatible(group, other)
+                });
+                if let Some((other, _)) = conflict {
+                    return Err(syn::Error::new_spanned(
+                        attr,
+                        format!("#[{attr_name}] can't be combined with #[{other}]"),
+                    ));
+                }
+            }
+            seen.push((attr_name, attr));
+        }
+        check_field_type(&field.ty)?;
+
+        let metadata = match (packed, length, variant, ascii, bytes, wide_fixed, flags) {
+            (None, None, None, false, None, None, None) => FieldMetadata::Simple,
+            (Some(bits), None, None, false, None, None, None) => FieldMetadata::Packed { bits },
+            (None, Some(length), None, false, None, None, None) => FieldMetadata::Array { length },
+            (Some(bits), Some(length), None, false, None, None, None) => {
+                FieldMetadata::PackedArray { bits, length }
+            }
+            (None, None, Some(VariantSource::Field(variant)), false, None, None, None) => {
+                FieldMetadata::Union { variant }
+            }
+            (None, None, Some(VariantSource::Inline { bits }), false, None, None, None) => {
+                FieldMetadata::InlineUnion { bits }
+            }
+            (None, Some(length), Some(VariantSource::Inline { bits }), false, None, None, None) => {
+                FieldMetadata::InlineUnionArray { bits, length }
+            }
+            (None, None, None, true, None, None, None) => FieldMetadata::Ascii,
+            (None, Some(length), None, false, Some(_), None, None) => {
+                FieldMetadata::Bytes { length }
+            }
+            (None, None, None, false, None, Some(units), None) => {
+                FieldMetadata::WideFixed { units }
+            }
+            (None, None, None, false, None, None, Some((bits, retain))) => {
+                FieldMetadata::Flags { bits, retain }
+            }
+            (None, Some(_), Some(VariantSource::Field(variant)), false, None, None, None) => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "Arrays of unions need their variants inline, with #[variant(inline, bits = ...)]",
+                ))
+            }
+            (_, None, _, _, Some(attr), _, _) => {
+                return Err(syn::Error::new_spanned(
+                    attr,
+                    "#[bytes] requires a #[length(...)] attribute",
+                ))
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "Invalid combination of attributes",
+                ))
+            }
+        };
+
+        Ok(Self {
+            ident,
+            name,
+            ty: field.ty.clone(),
+            metadata,
+            aligned,
+            trailing,
+            default_on_eof,
+            length_of,
+            present,
+        })
+    }
+}
+
+/// Checks an attribute without arguments, like `#[aligned]`.
+fn parse_marker(attr: &syn::Attribute, name: &str) -> syn::Result<bool> {
+    match attr.tokens.is_empty() {
+        true => Ok(true),
+        false => Err(syn::Error::new_spanned(
+            &attr.tokens,
+            format!("#[{name}] takes no arguments"),
+        )),
+    }
+}
+
+/// Parses a number of bits, which must fit in the 64 bits values are read on.
+fn parse_bits(input: syn::parse::ParseStream) -> syn::Result<usize> {
+    let lit: syn::LitInt = input.parse()?;
+    match lit.base10_parse()? {
+        bits @ 1..=64 => Ok(bits),
+        _ => Err(syn::Error::new(
+            lit.span(),
+            "The number of bits must be between 1 and 64",
+        )),
+    }
+}
+
+/// Parses the arguments of a `#[flags(...)]` attribute: the number of bits,
+/// optionally followed by `retain` to keep undefined bits instead of failing.
+fn parse_flags_args(input: syn::parse::ParseStream) -> syn::Result<(usize, bool)> {
+    let bits = parse_bits(input)?;
+    if input.is_empty() {
+        return Ok((bits, false));
+    }
+
+    input.parse::<syn::Token![,]>()?;
+    let name: syn::Ident = input.parse()?;
+    if name != "retain" {
+        return Err(syn::Error::new(name.span(), "Expected `retain`"));
+    }
+    Ok((bits, true))
+}
+
+/// Where the variant of a union field comes from.
+enum VariantSource {
+    /// `#[variant(field)]`: a previous field.
+    Field(syn::Ident),
+    /// `#[variant(inline, bits = N)]`: read on `bits` bits right before the
+    /// value, or before each item of arrays.
+    Inline { bits: usize },
+}
+
+/// Parses the arguments of a `#[variant(...)]` attribute: the selector field,
+/// or `inline, bits = N`.
+fn parse_variant_args(input: syn::parse::ParseStream) -> syn::Result<VariantSource> {
+    let ident: syn::Ident = input.parse()?;
+    if ident != "inline" {
+        return Ok(VariantSource::Field(ident));
+    }
+
+    if input.is_empty() {
+        return Err(syn::Error::new(
+            ident.span(),
+            "#[variant(inline)] requires `bits = ...`",
+        ));
+    }
+    input.parse::<syn::Token![,]>()?;
+    let name: syn::Ident = input.parse()?;
+    if name != "bits" {
+        return Err(syn::Error::new(name.span(), "Expected `bits = ...`"));
+    }
+    input.parse::<syn::Token![=]>()?;
+    let bits = parse_bits(input)?;
+    Ok(VariantSource::Inline { bits })
+}
+
+/// Parses the arguments of a `#[length(...)]` attribute: the length expression,
+/// optionally followed by `max = ...` to use instead of the reader's maximum length.
+fn parse_length_args(input: syn::parse::ParseStream) -> syn::Result<Length> {
+    let expr = input.parse()?;
+    if input.is_empty() {
+        return Ok(Length { expr, max: None });
+    }
+
+    input.parse::<syn::Token![,]>()?;
+    let name: syn::Ident = input.parse()?;
+    if name != "max" {
+        return Err(syn::Error::new(name.span(), "Expected `max = ...`"));
+    }
+    input.parse::<syn::Token![=]>()?;
+    let max = input.parse()?;
+    Ok(Length {
+        expr,
+        max: Some(max),
+    })
+}
+
+/// Checks that a field is a path type or an array of them, the types that can
+/// be read and written.
+fn check_field_type(ty: &Type) -> syn::Result<()> {
+    match ty {
+        Type::Path(_) => Ok(()),
+        Type::Array(a) => match *a.elem {
+            Type::Path(_) => Ok(()),
+            _ => Err(syn::Error::new_spanned(
+                &a.elem,
+                "Unsupported array element type",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(ty, "Unsupported field type")),
+    }
+}
+
+fn get_field_read(field: &MessageField) -> proc_macro2::TokenStream {
+    let align_expr = match field.aligned {
         true => quote!(reader_.align()?),
         false => quote!(),
     };
+    let read_expr = get_read_expr(&field.metadata);
 
-    match &field.ty {
-        syn::Type::Path(_) => {
-            let read_expr = get_read_expr(&field_metadata);
-            quote! {{ #align_expr; #read_expr }}
-        }
+    let read = match &field.ty {
         Type::Array(a) => {
             let len = &a.len;
-            match *a.elem {
-                syn::Type::Path(_) => {
-                    let read_expr = get_read_expr(&field_metadata);
-                    quote! {{
-                        #align_expr;
-                        let mut result = [Default::default(); #len];
-                        for item in &mut result {
-                            *item = #read_expr
-                        }
-                        result
-                    }}
+            // collecting into a vector first means that items don't need to
+            // be Copy or Default
+            quote! {{
+                #align_expr;
+                let mut items_ = Vec::with_capacity(#len);
+                for _ in 0..#len {
+                    items_.push(#read_expr);
                 }
-                _ => {
-                    let t = a.elem.to_token_stream().to_string();
-                    let n = get_field_name(field);
-                    let error = format!("Unsupported array element type: {t} for field: {n}");
-                    quote!(compile_error!(#error))
+                match <[_; #len]>::try_from(items_) {
+                    Ok(result) => result,
+                    Err(_) => unreachable!(),
                 }
-            }
+            }}
         }
-        _ => {
-            let t = field.ty.to_token_stream().to_string();
-            let n = get_field_name(field);
-            let error = format!("Unsupported field type: {t} for field: {n}");
-            quote!(compile_error!(#error))
+        _ => match &field.present {
+            Some(present) => quote! {
+                if #present {
+                    #align_expr;
+                    Some(#read_expr)
+                } else {
+                    None
+                }
+            },
+            None => quote! {{ #align_expr; #read_expr }},
+        },
+    };
+
+    // a missing field can only be told from one written in the padding of the
+    // last byte by trying to read it, so it is defaulted only if that fails
+    // from the padding
+    let start = match field.aligned {
+        true => quote!(reader_.position().next_multiple_of(8)),
+        false => quote!(reader_.position()),
+    };
+    let read_or_default = |condition: proc_macro2::TokenStream| {
+        quote! {{
+            let start_ = #start;
+            let read_ = |reader_: &mut ws_bitpack::BitPackReader| -> ws_bitpack::BitPackResult<_> {
+                Ok(#read)
+            };
+            match read_(reader_) {
+                Ok(value_) => value_,
+                Err(error_) if !reader_.is_partial() && #condition error_.is_end_from(start_) => {
+                    Default::default()
+                }
+                Err(error_) => return Err(error_),
+            }
+        }}
+    };
+
+    match (field.default_on_eof, field.trailing) {
+        (true, _) => read_or_default(quote!()),
+        (false, true) => read_or_default(quote!(reader_.is_lenient() &&)),
+        (false, false) => read,
+    }
+}
+
+/// Wraps the read of a field so that its errors tell which field failed and
+/// where, like `Message02EE.session_guid @ bit 56`.
+fn with_field_context(
+    type_name: &str,
+    field: &MessageField,
+    read: proc_macro2::TokenStream,
+) -> proc_macro2::TokenStream {
+    let ty = &field.ty;
+    let name = &field.name;
+    // the closure gives `?` in the read somewhere to return to
+    quote! {
+        (|reader_: &mut ws_bitpack::BitPackReader| -> ws_bitpack::BitPackResult<#ty> {
+            Ok(#read)
+        })(reader_)
+        .map_err(|error_| error_.with_context(#type_name, #name, reader_.position()))?
+    }
+}
+
+/// Returns a `ws_messages::FieldDescriptor` describing a field for tools.
+fn get_field_descriptor(field: &MessageField) -> proc_macro2::TokenStream {
+    let name = &field.name;
+    let ty = field.ty.to_token_stream().to_string().replace(' ', "");
+    let kind = match &field.metadata {
+        FieldMetadata::Simple => quote!(Value),
+        FieldMetadata::Packed { bits } => quote!(Packed { bits: #bits }),
+        FieldMetadata::Array { .. } => quote!(Array),
+        FieldMetadata::PackedArray { bits, .. } => quote!(PackedArray { bits: #bits }),
+        FieldMetadata::Union { .. } => quote!(Union),
+        FieldMetadata::InlineUnion { bits } => quote!(InlineUnion { bits: #bits }),
+        FieldMetadata::InlineUnionArray { bits, .. } => quote!(InlineUnionArray { bits: #bits }),
+        FieldMetadata::Ascii => quote!(Ascii),
+        FieldMetadata::Bytes { .. } => quote!(Bytes),
+        FieldMetadata::WideFixed { units } => quote!(WideFixed { units: #units }),
+        FieldMetadata::Flags { bits, retain } => quote!(Flags { bits: #bits, retain: #retain }),
+    };
+    let aligned = field.aligned;
+    let length = optional_str(
+        field
+            .metadata
+            .length()
+            .map(|length| expr_string(&length.expr)),
+    );
+    let length_of = optional_str(field.length_of.as_ref().map(|field| field.to_string()));
+    let variant = optional_str(field.metadata.variant().map(|variant| variant.to_string()));
+    let present = optional_str(field.present.as_ref().map(expr_string));
+    let trailing = field.trailing || field.default_on_eof;
+    quote! {
+        ws_messages::FieldDescriptor {
+            name: #name,
+            ty: #ty,
+            kind: ws_messages::FieldKind::#kind,
+            aligned: #aligned,
+            length: #length,
+            length_of: #length_of,
+            variant: #variant,
+            present: #present,
+            trailing: #trailing,
         }
     }
 }
 
+/// Formats an expression from an attribute for humans, like `header.count`.
+fn expr_string(expr: impl ToTokens) -> String {
+    expr.to_token_stream()
+        .to_string()
+        .replace(" . ", ".")
+        .replace("( ", "(")
+        .replace(" )", ")")
+        .replace("! ", "!")
+}
+
+fn optional_str(value: Option<String>) -> proc_macro2::TokenStream {
+    match value {
+        Some(value) => quote!(Some(#value)),
+        None => quote!(None),
+    }
+}
+
 fn get_read_expr(field_metadata: &FieldMetadata) -> proc_macro2::TokenStream {
     match field_metadata {
         FieldMetadata::Simple => quote!(ws_bitpack::ReadValue::read(reader_)?),
@@ -253,103 +1176,120 @@ fn get_read_expr(field_metadata: &FieldMetadata) -> proc_macro2::TokenStream {
             quote!(ws_bitpack::ReadPackedValue::read_packed(reader_, #bits)?)
         }
         FieldMetadata::Array { length } => {
+            let length = length.read_expr();
             quote!(ws_bitpack::ReadArrayValue::read_array(reader_, #length)?)
         }
         FieldMetadata::PackedArray { bits, length } => {
+            let length = length.read_expr();
             quote!(ws_bitpack::ReadPackedArrayValue::read_packed_array(reader_, #length, #bits)?)
         }
-        // todo: handle ascii?
-        //FieldMetadata::Ascii => quote!(MessageReader::read_ascii(reader_)?),
-        FieldMetadata::Ascii => quote!(ws_bitpack::ReadValue::read(reader_)?),
+        FieldMetadata::Ascii => quote!(reader_.read_ascii()?),
+        FieldMetadata::WideFixed { units } => quote!(reader_.read_wide_fixed(#units)?),
+        FieldMetadata::Flags { bits, retain } => quote!(reader_.read_flags(#bits, #retain)?),
+        FieldMetadata::Bytes { length } => {
+            let length = length.read_expr();
+            quote!(reader_.read_byte_vec(#length)?)
+        }
         FieldMetadata::Union { variant } => {
             // TODO: Verify this. Our trait for it is unfinished.
-            quote!(ws_bitpack::ReadUnionValue::read_union(reader_, #variant)?)
+            quote!(ws_bitpack::ReadUnionValue::read_union(reader_, #variant as usize)?)
+        }
+        FieldMetadata::InlineUnion { bits } => quote!(reader_.read_inline_union(#bits)?),
+        FieldMetadata::InlineUnionArray { bits, length } => {
+            let length = length.read_expr();
+            quote!(reader_.read_inline_union_array(#length, #bits)?)
         }
     }
 }
 
-fn get_field_write(field: &Field, access: FieldAccess) -> proc_macro2::TokenStream {
-    let ident = field.ident.as_ref().unwrap();
-    let field_metadata = get_field_metadata(field, FieldAccess::AsField);
+fn get_field_write(field: &MessageField, access: FieldAccess) -> proc_macro2::TokenStream {
+    let ident = &field.ident;
     let field_access = match access {
         FieldAccess::AsVar => quote!(#ident),
         FieldAccess::AsField => quote!(&self.#ident),
     };
-    let align_expr = match get_field_aligned(field) {
+    let align_expr = match field.aligned {
         true => quote!(writer_.align()?),
         false => quote!(),
     };
 
-    match &field.ty {
-        syn::Type::Path(_) => {
-            let write_expr = get_write_expr(&field_metadata, field_access);
-            quote!({ #align_expr; #write_expr })
+    if let Some(items) = &field.length_of {
+        let ty = &field.ty;
+        let items_access = match access {
+            FieldAccess::AsVar => quote!(#items),
+            FieldAccess::AsField => quote!(self.#items),
+        };
+        let write_expr = get_write_expr(&field.metadata, quote!(&length_));
+        return quote! {{
+            #align_expr;
+            let length_ = #items_access.len();
+            let length_ = <#ty>::try_from(length_).map_err(|_| BitPackError::LengthTooLarge {
+                length: length_,
+                max: <#ty>::MAX as usize,
+            })?;
+            #write_expr;
+        }};
+    }
+
+    match (&field.ty, &field.present) {
+        (Type::Array(_), _) => {
+            let write_expr = get_write_expr(&field.metadata, quote!(item));
+            quote! {
+                #align_expr;
+                for item in #field_access {
+                    #write_expr
+                }
+            }
         }
-        Type::Array(a) => match *a.elem {
-            syn::Type::Path(_) => {
-                let write_expr = get_write_expr(&field_metadata, quote!(item));
-                quote! {
+        (_, Some(_)) => {
+            let write_expr = get_write_expr(&field.metadata, quote!(value_));
+            quote! {
+                if let Some(value_) = #field_access {
                     #align_expr;
-                    for item in #field_access {
-                        #write_expr
-                    }
+                    #write_expr;
                 }
             }
-            _ => {
-                let t = a.elem.to_token_stream().to_string();
-                let n = get_field_name(field);
-                let error = format!("Unsupported array element type: {t} for field: {n}");
-                quote!(compile_error!(#error))
-            }
-        },
-        _ => {
-            let t = field.ty.to_token_stream().to_string();
-            let n = get_field_name(field);
-            let error = format!("Unsupported field type: {t} for field: {n}");
-            quote!(compile_error!(#error))
+        }
+        (_, None) => {
+            let write_expr = get_write_expr(&field.metadata, field_access);
+            quote!({ #align_expr; #write_expr })
         }
     }
 }
 
-fn get_field_bits(field: &Field, access: FieldAccess) -> proc_macro2::TokenStream {
-    let ident = field.ident.as_ref().unwrap();
-    let field_metadata = get_field_metadata(field, FieldAccess::AsField);
+fn get_field_bits(field: &MessageField, access: FieldAccess) -> proc_macro2::TokenStream {
+    let ident = &field.ident;
     let field_access = match access {
         FieldAccess::AsVar => quote!(#ident),
         FieldAccess::AsField => quote!(&self.#ident),
     };
-    let align_expr = match get_field_aligned(field) {
-        true => quote!(bits_ += 8 - (bits_ % 8)),
+    let align_expr = match field.aligned {
+        true => quote!(bits_ = bits_.next_multiple_of(8)),
         false => quote!(),
     };
 
-    match &field.ty {
-        syn::Type::Path(_) => {
-            let write_expr = get_bits_expr(&field_metadata, field_access);
-            quote!({ #align_expr; #write_expr; })
+    match (&field.ty, &field.present) {
+        (Type::Array(_), _) => {
+            let bits_expr = get_bits_expr(&field.metadata, quote!(item));
+            quote! {
+                #align_expr;
+                for item in #field_access {
+                    #bits_expr;
+                }
+            }
         }
-        Type::Array(a) => match *a.elem {
-            syn::Type::Path(_) => {
-                let bits_expr = get_bits_expr(&field_metadata, quote!(item));
-                quote! {
+        (_, Some(_)) => {
+            let bits_expr = get_bits_expr(&field.metadata, quote!(value_));
+            quote! {
+                if let Some(value_) = #field_access {
                     #align_expr;
-                    for item in #field_access {
-                        #bits_expr;
-                    }
+                    #bits_expr;
                 }
             }
-            _ => {
-                let t = a.elem.to_token_stream().to_string();
-                let n = get_field_name(field);
-                let error = format!("Unsupported array element type: {t} for field: {n}");
-                quote!(compile_error!(#error))
-            }
-        },
-        _ => {
-            let t = field.ty.to_token_stream().to_string();
-            let n = get_field_name(field);
-            let error = format!("Unsupported field type: {t} for field: {n}");
-            quote!(compile_error!(#error))
+        }
+        (_, None) => {
+            let bits_expr = get_bits_expr(&field.metadata, field_access);
+            quote!({ #align_expr; #bits_expr; })
         }
     }
 }
@@ -365,8 +1305,17 @@ fn get_write_expr(
         FieldMetadata::PackedArray { bits, .. } => {
             quote!(writer_.write_packed_array(#value, #bits)?)
         }
-        FieldMetadata::Ascii => quote!(MessageWriter::write_ascii(writer_, #value)?),
+        FieldMetadata::Ascii => quote!(writer_.write_ascii(#value)?),
+        FieldMetadata::WideFixed { units } => quote!(writer_.write_wide_fixed(#value, #units)?),
+        FieldMetadata::Flags { bits, retain } => {
+            quote!(writer_.write_flags(#value, #bits, #retain)?)
+        }
+        FieldMetadata::Bytes { .. } => quote!(writer_.write_bytes(#value)?),
         FieldMetadata::Union { .. } => quote!(writer_.write(#value)?),
+        FieldMetadata::InlineUnion { bits } => quote!(writer_.write_inline_union(#value, #bits)?),
+        FieldMetadata::InlineUnionArray { bits, .. } => {
+            quote!(writer_.write_inline_union_array(#value, #bits)?)
+        }
     }
 }
 
@@ -375,37 +1324,83 @@ fn get_bits_expr(
     value: proc_macro2::TokenStream,
 ) -> proc_macro2::TokenStream {
     match field_metadata {
-        FieldMetadata::Simple => quote!(bits_ += ws_bitpack::WriteValue::bits(#value)),
+        FieldMetadata::Simple => quote!(bits_ += ws_bitpack::WriteValue::bits_at(#value, bits_)),
         FieldMetadata::Packed { bits } => {
             quote!(bits_ += ws_bitpack::WritePackedValue::bits_packed(#value, #bits))
         }
         FieldMetadata::Array { .. } => {
-            quote!(bits_ += ws_bitpack::WriteArrayValue::bits_array(#value))
+            quote!(bits_ += ws_bitpack::WriteArrayValue::bits_array_at(#value, bits_))
         }
         FieldMetadata::PackedArray { bits, .. } => {
             quote!(bits_ += ws_bitpack::WritePackedArrayValue::bits_packed_array(#value, #bits))
         }
-        FieldMetadata::Ascii => todo!(),
-        FieldMetadata::Union { .. } => quote!(bits_ += ws_bitpack::WriteValue::bits(#value)),
+        FieldMetadata::Ascii => quote!(bits_ += ws_bitpack::ascii_bits(#value)),
+        FieldMetadata::WideFixed { units } => quote!(bits_ += ws_bitpack::wide_fixed_bits(#units)),
+        FieldMetadata::Flags { bits, .. } => quote!(bits_ += #bits),
+        FieldMetadata::Bytes { .. } => quote!(bits_ += #value.len() * 8),
+        FieldMetadata::Union { .. } => {
+            quote!(bits_ += ws_bitpack::WriteValue::bits_at(#value, bits_))
+        }
+        FieldMetadata::InlineUnion { bits } => {
+            quote!(bits_ += #bits + ws_bitpack::WriteValue::bits_at(#value, bits_ + #bits))
+        }
+        FieldMetadata::InlineUnionArray { bits, .. } => {
+            quote!(bits_ += ws_bitpack::inline_union_array_bits(#value, #bits, bits_))
+        }
     }
 }
 
-fn get_field_name(field: &Field) -> String {
-    field
-        .ident
-        .as_ref()
-        .map(|i| i.to_token_stream().to_string())
-        .unwrap_or_else(|| "?".to_string())
+/// Returns true if the type is a float, or a path type with a float generic
+/// argument (such as `Vec<f32>`).
+fn is_float_type(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) => p.path.segments.last().is_some_and(|segment| {
+            if segment.ident == "f32" || segment.ident == "f64" {
+                return true;
+            }
+            match &segment.arguments {
+                syn::PathArguments::AngleBracketed(args) => args
+                    .args
+                    .iter()
+                    .any(|arg| matches!(arg, syn::GenericArgument::Type(t) if is_float_type(t))),
+                _ => false,
+            }
+        }),
+        Type::Array(a) => is_float_type(&a.elem),
+        _ => false,
+    }
 }
 
 /// Indicates how the fields should be accessed.
+#[derive(Clone, Copy)]
 enum FieldAccess {
     /// Access as a variable with the same ident as the field itself.
     AsVar,
-    /// Access as a field of a local variable named `value_`.
+    /// Access as a field of `self`.
     AsField,
 }
 
+/// The length of an array from a `#[length(...)]` attribute, and its maximum
+/// if any.
+struct Length {
+    expr: syn::Expr,
+    max: Option<syn::Expr>,
+}
+
+impl Length {
+    /// Returns the length checked against its maximum. Lengths are only needed
+    /// when reading, where previous fields are variables, so they can be any
+    /// expression using them.
+    fn read_expr(&self) -> proc_macro2::TokenStream {
+        let expr = &self.expr;
+        let max = match &self.max {
+            Some(max) => quote!((#max) as usize),
+            None => quote!(reader_.max_length()),
+        };
+        quote!(reader_.check_length((#expr) as usize, #max)?)
+    }
+}
+
 /// Extra field metadata generated from attributes.
 enum FieldMetadata {
     Simple,
@@ -413,92 +1408,58 @@ enum FieldMetadata {
         bits: usize,
     },
     Array {
-        length: proc_macro2::TokenStream,
+        length: Length,
     },
     PackedArray {
         bits: usize,
-        length: proc_macro2::TokenStream,
+        length: Length,
     },
+    /// A union whose variant is selected by the field named by `#[variant(...)]`.
     Union {
-        variant: proc_macro2::TokenStream,
+        variant: syn::Ident,
+    },
+    /// A union preceded by its variant, from `#[variant(inline, bits = N)]`.
+    InlineUnion {
+        bits: usize,
+    },
+    /// A `Vec` of unions each preceded by its variant, with its length from
+    /// another field.
+    InlineUnionArray {
+        bits: usize,
+        length: Length,
     },
     Ascii,
+    /// A `Vec<u8>` read and written as whole bytes, with its length from another
+    /// field.
+    Bytes {
+        length: Length,
+    },
+    /// A `String` taking a fixed number of UTF-16 code units, padded with zeros.
+    WideFixed {
+        units: usize,
+    },
+    /// A `bitflags!` type written as its mask on `bits` bits.
+    Flags {
+        bits: usize,
+        retain: bool,
+    },
 }
 
-fn get_field_aligned(field: &Field) -> bool {
-    field.attrs.iter().any(|a| a.path.is_ident("aligned"))
-}
-
-fn get_field_metadata(field: &Field, access: FieldAccess) -> FieldMetadata {
-    let packed_bits = field
-        .attrs
-        .iter()
-        .find(|a| a.path.is_ident("packed"))
-        .and_then(|attr| attr.parse_meta().ok())
-        .and_then(|meta| {
-            if let syn::Meta::List(list) = meta {
-                if let Some(syn::NestedMeta::Lit(syn::Lit::Int(i))) = list.nested.first() {
-                    let bits = i.base10_parse().expect("Invalid number of bits");
-                    Some(bits)
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        });
-
-    let length_expr = field
-        .attrs
-        .iter()
-        .find(|a| a.path.is_ident("length"))
-        .and_then(|attr| attr.parse_meta().ok())
-        .and_then(|meta| {
-            if let syn::Meta::List(list) = meta {
-                if let Some(syn::NestedMeta::Meta(syn::Meta::Path(p))) = list.nested.first() {
-                    p.get_ident().cloned()
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        })
-        .map(|length| match access {
-            FieldAccess::AsVar => quote!(#length as usize),
-            FieldAccess::AsField => quote!(self.#length as usize),
-        });
+impl FieldMetadata {
+    fn length(&self) -> Option<&Length> {
+        match self {
+            Self::Array { length }
+            | Self::PackedArray { length, .. }
+            | Self::Bytes { length }
+            | Self::InlineUnionArray { length, .. } => Some(length),
+            _ => None,
+        }
+    }
 
-    let variant_expr = field
-        .attrs
-        .iter()
-        .find(|a| a.path.is_ident("variant"))
-        .and_then(|attr| attr.parse_meta().ok())
-        .and_then(|meta| {
-            if let syn::Meta::List(list) = meta {
-                if let Some(syn::NestedMeta::Meta(syn::Meta::Path(p))) = list.nested.first() {
-                    p.get_ident().cloned()
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        })
-        .map(|variant| match access {
-            FieldAccess::AsVar => quote!(#variant as usize),
-            FieldAccess::AsField => quote!(self.#variant as usize),
-        });
-
-    let is_ascii = field.attrs.iter().any(|a| a.path.is_ident("ascii"));
-
-    match (packed_bits, length_expr, variant_expr, is_ascii) {
-        (None, None, None, false) => FieldMetadata::Simple,
-        (Some(bits), None, None, false) => FieldMetadata::Packed { bits },
-        (None, Some(length), None, false) => FieldMetadata::Array { length },
-        (Some(bits), Some(length), None, false) => FieldMetadata::PackedArray { bits, length },
-        (None, None, Some(variant), false) => FieldMetadata::Union { variant },
-        (None, None, None, true) => FieldMetadata::Ascii,
-        _ => panic!("invalid attributes combination"),
+    fn variant(&self) -> Option<&syn::Ident> {
+        match self {
+            Self::Union { variant } => Some(variant),
+            _ => None,
+        }
     }
 }
@@ -0,0 +1,310 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use ws_bitpack::{BitPackReader, BitPackWriter, ReadValue, WriteValue};
+
+use crate::Message;
+
+/// Converts the body of a message between its binary and JSON forms.
+#[derive(Debug, Clone, Copy)]
+struct JsonCodec {
+    name: &'static str,
+    read: fn(&mut BitPackReader) -> Result<Value, String>,
+    encode: fn(Value) -> Result<Vec<u8>, String>,
+}
+
+impl JsonCodec {
+    fn decode(&self, data: &[u8]) -> Result<Value, String> {
+        let mut reader = BitPackReader::new(data);
+        let message = (self.read)(&mut reader)?;
+        reader
+            .finish()
+            .map_err(|error| format!("decoding failed: {error}"))?;
+        Ok(message)
+    }
+}
+
+fn read_json<T: ReadValue + Serialize>(reader: &mut BitPackReader) -> Result<Value, String> {
+    let message: T = reader
+        .read()
+        .map_err(|error| format!("decoding failed: {error}"))?;
+    serde_json::to_value(message).map_err(|error| format!("serializing failed: {error}"))
+}
+
+fn encode_json<T: WriteValue + DeserializeOwned>(value: Value) -> Result<Vec<u8>, String> {
+    let message: T =
+        serde_json::from_value(value).map_err(|error| format!("deserializing failed: {error}"))?;
+    let mut writer = BitPackWriter::growable();
+    writer
+        .write(&message)
+        .and_then(|_| writer.finish())
+        .map_err(|error| format!("encoding failed: {error}"))
+}
+
+/// Converts the bodies of messages between their binary and JSON forms, with
+/// the type registered for their opcode.
+#[derive(Debug, Clone, Default)]
+pub struct JsonCodecs {
+    codecs: HashMap<u32, JsonCodec>,
+}
+
+impl JsonCodecs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register<T>(&mut self) -> &mut Self
+    where
+        T: Message + ReadValue + WriteValue + Serialize + DeserializeOwned,
+    {
+        self.codecs.insert(
+            T::id(),
+            JsonCodec {
+                name: crate::short_type_name::<T>(),
+                read: read_json::<T>,
+                encode: encode_json::<T>,
+            },
+        );
+        self
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.codecs.contains_key(&id)
+    }
+
+    /// Decodes the body of the message with opcode `id` to JSON, failing if
+    /// anything is left after it.
+    ///
+    /// Returns `None` if no message is registered for that opcode.
+    pub fn decode(&self, id: u32, data: &[u8]) -> Option<Result<Value, String>> {
+        self.codecs.get(&id).map(|codec| codec.decode(data))
+    }
+
+    /// Reads the message with opcode `id` as JSON, leaving the reader after
+    /// it.
+    ///
+    /// Returns `None` if no message is registered for that opcode.
+    pub fn read(&self, id: u32, reader: &mut BitPackReader) -> Option<Result<Value, String>> {
+        self.codecs.get(&id).map(|codec| (codec.read)(reader))
+    }
+
+    /// Encodes the message with opcode `id` from its JSON form.
+    ///
+    /// Returns `None` if no message is registered for that opcode.
+    pub fn encode(&self, id: u32, message: Value) -> Option<Result<Vec<u8>, String>> {
+        self.codecs.get(&id).map(|codec| (codec.encode)(message))
+    }
+}
+
+/// A fixture that didn't match the message it describes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GoldenFailure {
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Checks messages against golden files holding a message body in hex and the
+/// same message in JSON.
+///
+/// Each `.json` file of the fixture directory is a case like:
+///
+/// ```json
+/// { "opcode": "0x0591", "hex": "07000000", "message": { "account_id": 7 } }
+/// ```
+///
+/// The hex body must decode to the JSON message, and the JSON message must
+/// encode to the hex body. Messages are checked with the type registered for
+/// their opcode, and every registered message needs at least one fixture.
+#[derive(Debug, Default)]
+pub struct GoldenTests {
+    codecs: JsonCodecs,
+}
+
+impl GoldenTests {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks fixtures with the messages registered in `codecs`.
+    pub fn with_codecs(codecs: JsonCodecs) -> Self {
+        Self { codecs }
+    }
+
+    pub fn register<T>(&mut self) -> &mut Self
+    where
+        T: Message + ReadValue + WriteValue + Serialize + DeserializeOwned,
+    {
+        self.codecs.register::<T>();
+        self
+    }
+
+    /// Checks every fixture of a directory and returns the ones that failed,
+    /// followed by a failure for each registered message without a fixture,
+    /// with the path of the directory.
+    pub fn run(&self, dir: impl AsRef<Path>) -> std::io::Result<Vec<GoldenFailure>> {
+        let dir = dir.as_ref();
+        let mut paths = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+        paths.sort();
+
+        let mut failures = Vec::new();
+        let mut covered = HashSet::new();
+        for path in paths {
+            let fixture = std::fs::read_to_string(&path)?;
+            let opcode = serde_json::from_str::<Value>(&fixture)
+                .ok()
+                .and_then(|fixture| fixture_opcode(&fixture));
+            covered.extend(opcode);
+            if let Err(reason) = self.check(&fixture) {
+                failures.push(GoldenFailure { path, reason });
+            }
+        }
+
+        let mut missing = self
+            .codecs
+            .codecs
+            .iter()
+            .filter(|(opcode, _)| !covered.contains(*opcode))
+            .collect::<Vec<_>>();
+        missing.sort_by_key(|(opcode, _)| **opcode);
+        failures.extend(missing.into_iter().map(|(opcode, codec)| GoldenFailure {
+            path: dir.to_path_buf(),
+            reason: format!("no fixture for {} ({opcode:#06x})", codec.name),
+        }));
+        Ok(failures)
+    }
+
+    /// Checks every fixture of a directory, and panics with the list of the ones
+    /// that failed.
+    #[track_caller]
+    pub fn assert_fixtures(&self, dir: impl AsRef<Path>) {
+        let dir = dir.as_ref();
+        let failures = self.run(dir).unwrap_or_else(|error| {
+            panic!("Failed to read fixtures in {}: {error}", dir.display())
+        });
+        if !failures.is_empty() {
+            let failures = failures
+                .iter()
+                .map(|failure| format!("{}: {}", failure.path.display(), failure.reason))
+                .collect::<Vec<_>>();
+            panic!(
+                "{} fixtures failed:\n{}",
+                failures.len(),
+                failures.join("\n")
+            );
+        }
+    }
+
+    /// Checks a single fixture.
+    pub fn check(&self, fixture: &str) -> Result<(), String> {
+        let fixture: Value =
+            serde_json::from_str(fixture).map_err(|error| format!("invalid fixture: {error}"))?;
+        let opcode = fixture_opcode(&fixture).ok_or("missing or invalid opcode")?;
+        let hex = fixture["hex"].as_str().ok_or("missing hex body")?;
+        let data = parse_hex(hex).ok_or("invalid hex body")?;
+        let expected = fixture.get("message").ok_or("missing message")?;
+
+        let codec = self
+            .codecs
+            .codecs
+            .get(&opcode)
+            .ok_or_else(|| format!("no message is registered for {opcode:#06x}"))?;
+        let decoded = codec
+            .decode(&data)
+            .map_err(|error| format!("{}: {error}", codec.name))?;
+        if decoded != *expected {
+            return Err(format!(
+                "{} decoded to {decoded}, expected {expected}",
+                codec.name
+            ));
+        }
+        let encoded =
+            (codec.encode)(expected.clone()).map_err(|error| format!("{}: {error}", codec.name))?;
+        if encoded != data {
+            return Err(format!(
+                "{} encoded to {}, expected {}",
+                codec.name,
+                to_hex(&encoded),
+                to_hex(&data)
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Returns the opcode of a fixture, written as a number or as a hex string.
+fn fixture_opcode(fixture: &Value) -> Option<u32> {
+    match &fixture["opcode"] {
+        Value::Number(number) => number.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(string) => string
+            .strip_prefix("0x")
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok()),
+        _ => None,
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    let digits = hex.split_whitespace().collect::<String>();
+    if digits.len() % 2 != 0 {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::*;
+
+    #[derive(MessageStruct, Message, Debug, PartialEq, Serialize, Deserialize)]
+    #[message_id(0x07fb)]
+    struct Message07FB {
+        id: u32,
+        #[packed(3)]
+        kind: u8,
+        name: String,
+    }
+
+    #[derive(MessageStruct, Message, Debug, PartialEq, Serialize, Deserialize)]
+    #[message_id(0x07fa)]
+    struct Message07FA {}
+
+    #[test]
+    fn test_golden_fixtures() {
+        let mut tests = GoldenTests::new();
+        tests.register::<Message07FB>();
+        tests.assert_fixtures(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+        let fixture = r#"{ "opcode": 2043, "hex": "2a000000", "message": { "id": 42 } }"#;
+        assert!(tests
+            .check(fixture)
+            .unwrap_err()
+            .contains("decoding failed"));
+        tests.register::<Message07FA>();
+        let failures = tests
+            .run(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"))
+            .unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].reason, "no fixture for Message07FA (0x07fa)");
+
+        let fixture = r#"{ "opcode": "0x07f9", "hex": "", "message": {} }"#;
+        assert_eq!(
+            tests.check(fixture),
+            Err("no message is registered for 0x07f9".to_string())
+        );
+    }
+}
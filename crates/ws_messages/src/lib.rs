@@ -1,10 +1,112 @@
+// Lets the derive macros refer to this crate as `ws_messages` from within it.
+extern crate self as ws_messages;
+
+// the golden tests run with the dev-dependencies even without the feature
+#[cfg(any(feature = "json", test))]
+mod golden;
 mod macros;
+mod registry;
+mod roundtrip;
+mod schema;
+#[cfg(any(feature = "json", test))]
+pub use golden::*;
 pub use macros::*;
+pub use registry::*;
+pub use roundtrip::*;
+pub use schema::*;
+
+#[doc(hidden)]
+pub use inventory;
+
+use std::any::Any;
+
+use ws_bitpack::{BitPackResult, BitPackWriter, WriteValue};
 
 pub trait Message {
     fn id() -> u32;
 }
 
+/// A type-erased message, as returned by the [`MessageRegistry`].
+///
+/// This is implemented for every [`Message`] and can be downcast back to the
+/// concrete message type.
+pub trait AnyMessage: Any + Send {
+    /// Returns the opcode of this message.
+    fn message_id(&self) -> u32;
+
+    /// Returns the name of the message type, without its path like in the
+    /// [`MessageRegistry`].
+    fn message_name(&self) -> &'static str;
+
+    /// Writes the message body.
+    fn write_message(&self, writer: &mut BitPackWriter) -> BitPackResult;
+
+    /// Returns the size of the message body, in bits.
+    fn message_bits(&self) -> usize;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any>;
+}
+
+impl<T> AnyMessage for T
+where
+    T: Message + WriteValue + Any + Send,
+{
+    fn message_id(&self) -> u32 {
+        T::id()
+    }
+
+    fn message_name(&self) -> &'static str {
+        short_type_name::<T>()
+    }
+
+    fn write_message(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        self.write(writer)
+    }
+
+    fn message_bits(&self) -> usize {
+        self.bits()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn into_any(self: Box<Self>) -> Box<dyn Any> {
+        self
+    }
+}
+
+impl std::fmt::Debug for dyn AnyMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AnyMessage")
+            .field("id", &self.message_id())
+            .field("name", &self.message_name())
+            .finish()
+    }
+}
+
+impl dyn AnyMessage {
+    pub fn is<T: AnyMessage>(&self) -> bool {
+        self.as_any().is::<T>()
+    }
+
+    pub fn downcast_ref<T: AnyMessage>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    /// Converts the message back into its concrete type, or returns it unchanged
+    /// if it is of another type.
+    pub fn downcast<T: AnyMessage>(self: Box<Self>) -> Result<Box<T>, Box<dyn AnyMessage>> {
+        if self.is::<T>() {
+            Ok(self.into_any().downcast().expect("type was just checked"))
+        } else {
+            Err(self)
+        }
+    }
+}
+
 pub trait MessageStruct
 where
     Self: Sized,
@@ -13,7 +115,7 @@ where
 
 pub trait MessageUnion
 where
-    Self: Sized
+    Self: Sized,
 {
     /// Returns the 0-based variant index for that union value.
     fn variant(&self) -> usize;
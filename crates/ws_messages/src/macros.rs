@@ -2,8 +2,8 @@ pub use ws_messages_macros::*;
 
 #[cfg(test)]
 mod tests {
-    use ws_bitpack::*;
     use crate::*;
+    use ws_bitpack::*;
 
     fn write_and_read<T>(input: &T) -> T
     where
@@ -35,6 +35,162 @@ mod tests {
         assert_eq!(in_value.items, out_value.items);
     }
 
+    #[test]
+    fn test_bytes_write_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            flag: bool,
+            count: u8,
+            #[bytes]
+            #[length(count)]
+            digest: Vec<u8>,
+        }
+        let in_value = Struct {
+            flag: true,
+            count: 4,
+            digest: vec![0xde, 0xad, 0xbe, 0xef],
+        };
+        assert_eq!(in_value.bits(), 1 + 8 + 32);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(in_value.digest, out_value.digest);
+
+        // a length larger than the data fails before allocating
+        let data = [0xff, 0xff];
+        let result = BitPackReader::new(&data).read::<Struct>();
+        assert!(matches!(
+            result.map_err(BitPackError::into_root),
+            Err(BitPackError::OutOfBounds { .. })
+        ));
+    }
+
+    #[test]
+    fn test_length_expr_write_read() {
+        #[derive(MessageStruct)]
+        struct Header {
+            count: u8,
+        }
+
+        #[derive(MessageStruct)]
+        struct Struct {
+            header: Header,
+            #[length(header.count)]
+            items: Vec<u16>,
+            extra_count: u8,
+            #[length(extra_count as usize + 1)]
+            extra: Vec<u8>,
+        }
+        let in_value = Struct {
+            header: Header { count: 2 },
+            items: vec![1, 2],
+            extra_count: 2,
+            extra: vec![3, 4, 5],
+        };
+        let out_value = write_and_read(&in_value);
+        assert_eq!(in_value.items, out_value.items);
+        assert_eq!(in_value.extra, out_value.extra);
+    }
+
+    #[test]
+    fn test_length_max() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            count: u8,
+            #[length(count, max = 2)]
+            items: Vec<u8>,
+        }
+        let in_value = Struct {
+            count: 2,
+            items: vec![1, 2],
+        };
+        assert_eq!(write_and_read(&in_value).items, [1, 2]);
+
+        let data = [3, 1, 2, 3];
+        let result = BitPackReader::new(&data).read::<Struct>();
+        assert!(matches!(
+            result.map_err(BitPackError::into_root),
+            Err(BitPackError::LengthTooLarge { length: 3, max: 2 })
+        ));
+
+        // without a max, the limit of the reader applies
+        #[derive(MessageStruct)]
+        struct Unlimited {
+            count: u8,
+            #[length(count)]
+            items: Vec<u8>,
+        }
+        let mut reader = BitPackReader::new(&data);
+        reader.set_max_length(1);
+        assert!(matches!(
+            reader.read::<Unlimited>().map_err(BitPackError::into_root),
+            Err(BitPackError::LengthTooLarge { length: 3, max: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_length_of_write_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            #[packed(4)]
+            #[length_of(items)]
+            count: u8,
+            #[length(count)]
+            items: Vec<u32>,
+        }
+        let in_value = Struct {
+            count: 0,
+            items: vec![1, 2, 3],
+        };
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.count, 3);
+        assert_eq!(out_value.items, [1, 2, 3]);
+
+        let in_value = Struct {
+            count: 0,
+            items: vec![0; 16],
+        };
+        let mut writer = BitPackWriter::growable();
+        assert!(matches!(
+            writer.write(&in_value),
+            Err(BitPackError::ValueTooLarge { bits: 4, value: 16 })
+        ));
+    }
+
+    #[test]
+    fn test_generic_write_read() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Entry {
+            id: u32,
+            #[packed(3)]
+            rank: u8,
+        }
+
+        #[derive(MessageStruct)]
+        struct Paged<T> {
+            page: u16,
+            #[length_of(items)]
+            count: u8,
+            #[length(count)]
+            items: Vec<T>,
+        }
+
+        let in_value = Paged {
+            page: 2,
+            count: 0,
+            items: vec![Entry { id: 7, rank: 1 }, Entry { id: 9, rank: 5 }],
+        };
+        assert_eq!(in_value.bits(), 16 + 8 + 2 * 35);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.page, 2);
+        assert_eq!(in_value.items, out_value.items);
+
+        let in_value = Paged::<u64> {
+            page: 0,
+            count: 0,
+            items: vec![1],
+        };
+        assert_eq!(write_and_read(&in_value).items, [1]);
+    }
+
     #[test]
     fn test_packed_write_read() {
         #[derive(MessageStruct)]
@@ -66,7 +222,621 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Invalid union variant 2")]
+    fn test_float_write_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            x: f32,
+            y: f64,
+        }
+        let in_value = Struct {
+            x: -1234.5,
+            y: std::f64::consts::PI,
+        };
+        assert_eq!(in_value.bits(), 96);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.x, -1234.5);
+        assert_eq!(out_value.y, std::f64::consts::PI);
+    }
+
+    #[test]
+    fn test_ascii_write_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            #[ascii]
+            account_name: String,
+            realm_name: AsciiString,
+        }
+        let in_value = Struct {
+            account_name: "clamoune".to_string(),
+            realm_name: AsciiString::new("Nexus"),
+        };
+        assert_eq!(in_value.bits(), (8 + 8 * 8) + (8 + 5 * 8));
+
+        let mut buf = [0u8; 32];
+        let mut writer = BitPackWriter::new(&mut buf);
+        writer.write(&in_value).unwrap();
+        assert_eq!(writer.position(), in_value.bits());
+        assert_eq!(hex::encode(&buf[..9]), "10636c616d6f756e65");
+
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.account_name, "clamoune");
+        assert_eq!(out_value.realm_name.as_str(), "Nexus");
+
+        let mut writer = BitPackWriter::new(&mut buf);
+        assert!(matches!(
+            writer.write_ascii("clamoūne"),
+            Err(BitPackError::InvalidChar(0x16b))
+        ));
+    }
+
+    #[test]
+    fn test_wide_string_write_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            #[wide_fixed(6)]
+            name: String,
+            guild: WideStringFixed<4>,
+            title: CStringWide,
+            level: u8,
+        }
+        let in_value = Struct {
+            name: "Zoë".to_string(),
+            guild: "Ex".into(),
+            title: "Hero".into(),
+            level: 50,
+        };
+        assert_eq!(in_value.bits(), 6 * 16 + 4 * 16 + 5 * 16 + 8);
+
+        let mut buf = [0u8; 32];
+        let mut writer = BitPackWriter::new(&mut buf);
+        writer.write(&in_value).unwrap();
+        assert_eq!(writer.position(), in_value.bits());
+        assert_eq!(hex::encode(&buf[..12]), "5a006f00eb00000000000000");
+
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.name, "Zoë");
+        assert_eq!(out_value.guild.as_str(), "Ex");
+        assert_eq!(out_value.title.as_str(), "Hero");
+        assert_eq!(out_value.level, 50);
+
+        let in_value = Struct {
+            name: "Too long".to_string(),
+            ..in_value
+        };
+        let mut writer = BitPackWriter::new(&mut buf);
+        assert!(matches!(
+            writer.write(&in_value).map_err(BitPackError::into_root),
+            Err(BitPackError::LengthTooLarge { length: 8, max: 6 })
+        ));
+    }
+
+    #[test]
+    fn test_tuple_struct_write_read() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Id(u64);
+
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Slot(Id, #[packed(4)] u8, #[ascii] String);
+
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Marker;
+
+        let in_value = Slot(Id(0x1234), 9, "bag".to_string());
+        assert_eq!(in_value.bits(), 64 + 4 + 8 + 3 * 8);
+        assert_eq!(write_and_read(&in_value), in_value);
+        assert_eq!(write_and_read(&Marker), Marker);
+        assert_eq!(Marker.bits(), 0);
+
+        let names: Vec<_> = Slot::FIELDS.iter().map(|field| field.name).collect();
+        assert_eq!(names, ["0", "1", "2"]);
+        assert_eq!(Slot::FIELDS[1].kind, FieldKind::Packed { bits: 4 });
+
+        // errors name positional fields by their index
+        let result = BitPackReader::new(&[0; 8]).read::<Slot>();
+        match result {
+            Err(BitPackError::Context { path, .. }) => assert_eq!(path, ["1"]),
+            result => panic!("unexpected result {result:?}"),
+        }
+    }
+
+    #[test]
+    fn test_flags_write_read() {
+        bitflags::bitflags! {
+            #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+            struct UnitFlags: u32 {
+                const HOSTILE = 1 << 0;
+                const TARGETABLE = 1 << 3;
+            }
+        }
+
+        #[derive(MessageStruct)]
+        struct Struct {
+            #[flags(5)]
+            flags: UnitFlags,
+            #[flags(5, retain)]
+            raw_flags: UnitFlags,
+        }
+        let in_value = Struct {
+            flags: UnitFlags::HOSTILE | UnitFlags::TARGETABLE,
+            raw_flags: UnitFlags::from_bits_retain(0b10001),
+        };
+        assert_eq!(in_value.bits(), 10);
+
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.flags, in_value.flags);
+        assert_eq!(out_value.raw_flags.bits(), 0b10001);
+
+        // undefined bits fail unless the field retains them
+        let mut reader = BitPackReader::new(&[0b10001, 0]);
+        assert!(matches!(
+            reader.read::<Struct>().map_err(BitPackError::into_root),
+            Err(BitPackError::InvalidFlags {
+                unknown: 0b10000,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_struct_array_write_read() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Item {
+            name: String,
+            #[packed(4)]
+            slot: u8,
+        }
+
+        #[derive(MessageStruct)]
+        struct Struct {
+            items: [Item; 2],
+            names: [String; 3],
+        }
+        let in_value = Struct {
+            items: [
+                Item {
+                    name: "Sword".to_string(),
+                    slot: 3,
+                },
+                Item {
+                    name: "Shield".to_string(),
+                    slot: 4,
+                },
+            ],
+            names: ["a".to_string(), "bc".to_string(), String::new()],
+        };
+        let out_value = write_and_read(&in_value);
+        assert_eq!(in_value.items, out_value.items);
+        assert_eq!(in_value.names, out_value.names);
+    }
+
+    #[test]
+    fn test_enum_write_read() {
+        #[derive(MessageEnum, Debug, Clone, Copy, PartialEq)]
+        #[repr(u8)]
+        enum Faction {
+            Exile = 1,
+            Dominion = 2,
+            Neutral = 5,
+        }
+
+        #[derive(MessageStruct)]
+        struct Struct {
+            faction: Faction,
+            #[packed(3)]
+            packed_faction: Faction,
+        }
+        let in_value = Struct {
+            faction: Faction::Dominion,
+            packed_faction: Faction::Neutral,
+        };
+        assert_eq!(in_value.bits(), 11);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.faction, Faction::Dominion);
+        assert_eq!(out_value.packed_faction, Faction::Neutral);
+
+        let result = BitPackReader::new(&[3]).read::<Faction>();
+        assert!(matches!(
+            result,
+            Err(BitPackError::InvalidEnumValue {
+                type_name: "Faction",
+                value: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn test_char_write_read() {
+        assert_eq!(write_and_read(&'a'), 'a');
+        assert_eq!(write_and_read(&'é'), 'é');
+        assert_eq!(write_and_read(&'名'), '名');
+
+        let mut buf = [0u8; 4];
+        let mut writer = BitPackWriter::new(&mut buf);
+        assert!(matches!(
+            writer.write(&'🦀'),
+            Err(BitPackError::InvalidChar(0x1f980))
+        ));
+    }
+
+    #[test]
+    fn test_map_write_read() {
+        use std::collections::HashMap;
+
+        #[derive(MessageStruct)]
+        struct Struct {
+            count: u32,
+            #[length(count)]
+            pairs: Vec<(u32, u16)>,
+            #[length(count)]
+            map: HashMap<u32, u16>,
+        }
+        let in_value = Struct {
+            count: 3,
+            pairs: vec![(3, 30), (1, 10), (2, 20)],
+            map: HashMap::from([(3, 30), (1, 10), (2, 20)]),
+        };
+        let out_value = write_and_read(&in_value);
+        assert_eq!(in_value.pairs, out_value.pairs);
+        assert_eq!(in_value.map, out_value.map);
+        assert_eq!(in_value.bits(), 32 + 2 * 3 * 48);
+    }
+
+    #[test]
+    fn test_present_write_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            has_value: bool,
+            #[present(has_value)]
+            value: Option<u32>,
+            flags: u8,
+            #[packed(5)]
+            #[present(flags & 0x4 != 0)]
+            packed: Option<u8>,
+        }
+
+        let in_value = Struct {
+            has_value: true,
+            value: Some(42),
+            flags: 0x4,
+            packed: Some(17),
+        };
+        assert_eq!(in_value.bits(), 1 + 32 + 8 + 5);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.value, Some(42));
+        assert_eq!(out_value.packed, Some(17));
+
+        let in_value = Struct {
+            has_value: false,
+            value: None,
+            flags: 0,
+            packed: None,
+        };
+        assert_eq!(in_value.bits(), 1 + 8);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.value, None);
+        assert_eq!(out_value.packed, None);
+    }
+
+    #[test]
+    fn test_read_if_skip_if_write_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            flags: u8,
+            #[read_if(flags & 0x1 != 0)]
+            guild: Option<u32>,
+            #[skip_if(flags & 0x2 != 0)]
+            #[packed(4)]
+            level: Option<u8>,
+        }
+
+        let in_value = Struct {
+            flags: 0x1,
+            guild: Some(7),
+            level: Some(9),
+        };
+        assert_eq!(in_value.bits(), 8 + 32 + 4);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.guild, Some(7));
+        assert_eq!(out_value.level, Some(9));
+
+        let in_value = Struct {
+            flags: 0x2,
+            guild: None,
+            level: None,
+        };
+        assert_eq!(in_value.bits(), 8);
+        let out_value = write_and_read(&in_value);
+        assert_eq!(out_value.guild, None);
+        assert_eq!(out_value.level, None);
+    }
+
+    #[test]
+    fn test_union_bits_with_attributes() {
+        #[derive(MessageUnion)]
+        enum Union {
+            Empty {},
+            Items {
+                #[packed(3)]
+                count: u8,
+                #[packed(5)]
+                #[length(count)]
+                items: Vec<u8>,
+                #[ascii]
+                name: String,
+            },
+        }
+
+        assert_eq!(Union::Empty {}.bits(), 0);
+        let value = Union::Items {
+            count: 2,
+            items: vec![1, 2],
+            name: "abc".to_string(),
+        };
+        assert_eq!(value.bits(), 3 + 2 * 5 + 8 + 3 * 8);
+    }
+
+    #[test]
+    fn test_nested_depth_limit() {
+        #[derive(MessageStruct)]
+        struct Inner {
+            value: u8,
+        }
+        #[derive(MessageStruct)]
+        struct Outer {
+            inner: Inner,
+        }
+
+        let data = [0u8; 1];
+        let mut reader = BitPackReader::new(&data);
+        reader.set_max_depth(2);
+        assert!(reader.read::<Outer>().is_ok());
+
+        let mut reader = BitPackReader::new(&data);
+        reader.set_max_depth(1);
+        assert!(matches!(
+            reader.read::<Outer>().map_err(BitPackError::into_root),
+            Err(BitPackError::DepthLimitExceeded { max: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_error_context() {
+        #[derive(MessageStruct, Debug)]
+        struct Header {
+            flags: u8,
+            count: u8,
+        }
+
+        #[derive(MessageStruct, Debug)]
+        struct Struct {
+            id: u32,
+            header: Header,
+        }
+
+        let data = hex::decode("2a00000001").unwrap();
+        let error = BitPackReader::new(&data).read::<Struct>().unwrap_err();
+        assert!(matches!(
+            &error,
+            BitPackError::Context {
+                type_name: "Struct",
+                position: 40,
+                ..
+            }
+        ));
+        assert!(matches!(error.root(), BitPackError::OutOfBounds { .. }));
+        assert_eq!(
+            error.to_string(),
+            "Struct.header.count @ bit 40: out of bounds at bit 40, 8 bits needed but 0 available"
+        );
+    }
+
+    #[test]
+    fn test_trailing_lenient_read() {
+        #[derive(MessageStruct)]
+        struct Struct {
+            id: u32,
+            #[trailing]
+            extra: u16,
+        }
+
+        let data = hex::decode("2a000000").unwrap();
+        let mut reader = BitPackReader::new(&data);
+        assert!(matches!(
+            reader.read::<Struct>().map_err(BitPackError::into_root),
+            Err(BitPackError::OutOfBounds { .. })
+        ));
+
+        let mut reader = BitPackReader::new(&data);
+        reader.set_lenient(true);
+        let result: Struct = reader.read().unwrap();
+        assert_eq!(result.id, 42);
+        assert_eq!(result.extra, 0);
+
+        let data = hex::decode("2a0000000700").unwrap();
+        let mut reader = BitPackReader::new(&data);
+        reader.set_lenient(true);
+        let result: Struct = reader.read().unwrap();
+        assert_eq!(result.extra, 7);
+    }
+
+    #[test]
+    fn test_trailing_lenient_read_in_last_byte() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Struct {
+            id: u32,
+            #[packed(4)]
+            flags: u8,
+            #[trailing]
+            #[packed(3)]
+            extra: u8,
+        }
+
+        // a trailing field written in the padding of the last byte is read back
+        let in_value = Struct {
+            id: 42,
+            flags: 1,
+            extra: 5,
+        };
+        let mut writer = BitPackWriter::growable();
+        writer.write(&in_value).unwrap();
+        let buffer = writer.finish().unwrap();
+        assert_eq!(buffer.len(), 5);
+        let mut reader = BitPackReader::new(&buffer);
+        reader.set_lenient(true);
+        assert_eq!(reader.read::<Struct>().unwrap(), in_value);
+
+        // one that doesn't fit in the padding is missing
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Longer {
+            id: u32,
+            #[packed(4)]
+            flags: u8,
+            #[trailing]
+            extra: u8,
+        }
+
+        let mut reader = BitPackReader::new(&buffer);
+        reader.set_lenient(true);
+        let result: Longer = reader.read().unwrap();
+        assert_eq!((result.flags, result.extra), (1, 0));
+    }
+
+    #[test]
+    fn test_default_on_eof_read() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Struct {
+            id: u32,
+            #[default_on_eof]
+            level: u8,
+            #[packed(4)]
+            flags: u8,
+            #[ascii]
+            title: String,
+        }
+
+        // every field after the marked one can be missing, lenient or not
+        let data = hex::decode("2a000000").unwrap();
+        let result: Struct = BitPackReader::new(&data).read().unwrap();
+        assert_eq!(
+            result,
+            Struct {
+                id: 42,
+                level: 0,
+                flags: 0,
+                title: String::new(),
+            }
+        );
+        let data = hex::decode("2a00000032").unwrap();
+        let result: Struct = BitPackReader::new(&data).read().unwrap();
+        assert_eq!(result.level, 50);
+        assert_eq!(result.flags, 0);
+
+        // the padding of the last byte isn't a missing field
+        let data = hex::decode("2a0000003205").unwrap();
+        let result: Struct = BitPackReader::new(&data).read().unwrap();
+        assert_eq!(result.flags, 5);
+        assert_eq!(result.title, "");
+
+        // data ending inside a field is still an error
+        let data = hex::decode("2a0000").unwrap();
+        assert!(matches!(
+            BitPackReader::new(&data)
+                .read::<Struct>()
+                .map_err(BitPackError::into_root),
+            Err(BitPackError::OutOfBounds { .. })
+        ));
+        assert!(Struct::FIELDS[1..].iter().all(|field| field.trailing));
+        assert!(!Struct::FIELDS[0].trailing);
+    }
+
+    #[test]
+    fn test_default_on_eof_in_last_byte() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Struct {
+            id: u32,
+            #[default_on_eof]
+            a: bool,
+            b: bool,
+        }
+
+        // fields written in the padding of the last byte are read back
+        let in_value = Struct {
+            id: 1,
+            a: true,
+            b: true,
+        };
+        let mut writer = BitPackWriter::growable();
+        writer.write(&in_value).unwrap();
+        let buffer = writer.finish().unwrap();
+        assert_eq!(buffer, [1, 0, 0, 0, 3]);
+        let result: Struct = BitPackReader::new(&buffer).read().unwrap();
+        assert_eq!(result, in_value);
+
+        let data = hex::decode("01000000").unwrap();
+        let result: Struct = BitPackReader::new(&data).read().unwrap();
+        assert!(!result.a && !result.b);
+    }
+
+    #[test]
+    fn test_default_on_eof_stream() {
+        use std::io::Read;
+
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Struct {
+            id: u32,
+            #[default_on_eof]
+            level: u8,
+        }
+
+        // the first chunk ends right before the field, which arrives later
+        let data = [0x2a, 0, 0, 0, 0x32];
+        let mut reader = BitPackStreamReader::new((&data[..4]).chain(&data[4..]));
+        let result: Struct = reader.read().unwrap();
+        assert_eq!(result.level, 50);
+
+        // it is only defaulted once the source ended
+        let mut reader = BitPackStreamReader::new((&data[..4]).chain(&[][..]));
+        let result: Struct = reader.read().unwrap();
+        assert_eq!(result.level, 0);
+    }
+
+    #[test]
+    fn test_recursive_struct() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Objective {
+            id: u16,
+            #[packed(1)]
+            has_next: u8,
+            #[present(has_next != 0)]
+            next: Option<Box<Objective>>,
+            #[length_of(children)]
+            child_count: u8,
+            #[length(child_count)]
+            children: Vec<Objective>,
+        }
+
+        let leaf = |id| Objective {
+            id,
+            has_next: 0,
+            next: None,
+            child_count: 0,
+            children: vec![],
+        };
+        let in_value = Objective {
+            id: 1,
+            has_next: 1,
+            next: Some(Box::new(Objective {
+                children: vec![leaf(3)],
+                child_count: 1,
+                ..leaf(2)
+            })),
+            child_count: 2,
+            children: vec![leaf(4), leaf(5)],
+        };
+        assert_eq!(write_and_read(&in_value), in_value);
+    }
+
+    #[test]
     fn test_union() {
         #[derive(MessageUnion)]
         enum Union {
@@ -94,6 +864,8 @@ mod tests {
         };
         assert_eq!(out_union_value, Some(123456789123456789));
         assert_eq!(out_union_value.unwrap().bits(), 64);
+        assert_eq!(in_value.union.bits(), 64);
+        assert_eq!(in_value.bits(), 32 + 64);
 
         // test second variant
         let in_value = Struct {
@@ -107,16 +879,211 @@ mod tests {
         };
         assert_eq!(out_union_value, Some(-12349));
         assert_eq!(out_union_value.unwrap().bits(), 16);
+        assert_eq!(in_value.bits(), 32 + 16);
 
-        // test invalid variant (should panic during read with above message)
+        // test invalid variant (should fail during read)
         let in_value = Struct {
             id: 2,
             union: Union::Signed16 { value: 0 },
         };
-        write_and_read(&in_value);
+        let mut buf = [0u8; 8];
+        let mut writer = BitPackWriter::new(&mut buf);
+        writer.write(&in_value).unwrap();
+        let mut reader = BitPackReader::new(&buf);
+        assert!(matches!(
+            reader.read::<Struct>().map_err(BitPackError::into_root),
+            Err(BitPackError::InvalidUnionVariant {
+                type_name: "Union",
+                variant: 2
+            })
+        ));
     }
 
-    #[derive(MessageStruct)]
+    #[test]
+    fn test_union_variant_kinds() {
+        #[derive(MessageUnion, Debug, PartialEq)]
+        enum SparseUnion {
+            None,
+            #[variant_index(4)]
+            Guid(u64),
+            Position(#[packed(10)] u16, #[packed(10)] u16),
+            #[variant_index(9)]
+            Named {
+                #[ascii]
+                name: String,
+            },
+        }
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Struct {
+            #[packed(4)]
+            kind: u8,
+            #[variant(kind)]
+            union: SparseUnion,
+        }
+
+        assert_eq!(SparseUnion::None.variant(), 0);
+        assert_eq!(SparseUnion::Position(1, 2).variant(), 5);
+        for (kind, union) in [
+            (0, SparseUnion::None),
+            (4, SparseUnion::Guid(0x1234)),
+            (5, SparseUnion::Position(3, 1000)),
+            (
+                9,
+                SparseUnion::Named {
+                    name: "Nexus".to_string(),
+                },
+            ),
+        ] {
+            let in_value = Struct { kind, union };
+            assert_eq!(write_and_read(&in_value), in_value);
+        }
+        assert_eq!(SparseUnion::Position(3, 1000).bits(), 20);
+
+        let indices: Vec<_> = match find_schema("SparseUnion").unwrap().shape {
+            TypeShape::Union(variants) => variants.iter().map(|variant| variant.index).collect(),
+            shape => panic!("unexpected shape {shape:?}"),
+        };
+        assert_eq!(indices, [0, 4, 5, 9]);
+
+        let mut reader = BitPackReader::new(&[1]);
+        assert!(matches!(
+            reader.read::<Struct>().map_err(BitPackError::into_root),
+            Err(BitPackError::InvalidUnionVariant { variant: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_union_selector_values() {
+        #[derive(MessageEnum, Debug, Clone, Copy, PartialEq)]
+        #[repr(u8)]
+        enum ItemKind {
+            Weapon = 2,
+            Armor = 3,
+            Shield = 7,
+            Bag = 255,
+        }
+        const NO_ITEM: u8 = 0;
+        #[derive(MessageUnion, Debug, PartialEq)]
+        enum SelectedItem {
+            #[selector(NO_ITEM)]
+            Empty,
+            #[selector(ItemKind::Weapon)]
+            Weapon { damage: u16 },
+            #[selector(ItemKind::Armor, ItemKind::Shield)]
+            Armor { armor: u16 },
+            #[selector(ItemKind::Bag)]
+            Bag(#[packed(6)] u8),
+        }
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Struct {
+            kind: u8,
+            #[variant(kind)]
+            item: SelectedItem,
+        }
+
+        assert_eq!(SelectedItem::Empty.variant(), 0);
+        assert_eq!(SelectedItem::Armor { armor: 1 }.variant(), 3);
+        assert_eq!(SelectedItem::Bag(16).variant(), 255);
+        for (kind, item) in [
+            (0, SelectedItem::Empty),
+            (2, SelectedItem::Weapon { damage: 120 }),
+            (3, SelectedItem::Armor { armor: 40 }),
+            (7, SelectedItem::Armor { armor: 25 }),
+            (255, SelectedItem::Bag(16)),
+        ] {
+            let in_value = Struct { kind, item };
+            assert_eq!(write_and_read(&in_value), in_value);
+        }
+
+        let variants = match find_schema("SelectedItem").unwrap().shape {
+            TypeShape::Union(variants) => variants,
+            shape => panic!("unexpected shape {shape:?}"),
+        };
+        assert_eq!(variants[2].index, 3);
+        assert_eq!(variants[2].selectors, [3, 7]);
+        assert_eq!(variants[3].selectors, [255]);
+
+        let mut reader = BitPackReader::new(&[1]);
+        assert!(matches!(
+            reader.read::<Struct>().map_err(BitPackError::into_root),
+            Err(BitPackError::InvalidUnionVariant { variant: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_inline_unions() {
+        #[derive(MessageUnion, Debug, PartialEq)]
+        enum PropertyValue {
+            Int(u32),
+            Float(f32),
+            Flag(bool),
+        }
+        #[derive(MessageUnion, Debug, PartialEq)]
+        enum Property {
+            Health(u32),
+            #[variant_index(3)]
+            Custom {
+                #[packed(2)]
+                kind: u8,
+                #[variant(kind)]
+                value: PropertyValue,
+            },
+        }
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct PropertyUpdate {
+            #[packed(5)]
+            count: u8,
+            #[length(count)]
+            #[variant(inline, bits = 3)]
+            properties: Vec<Property>,
+            #[variant(inline, bits = 2)]
+            pair: [PropertyValue; 2],
+            #[variant(inline, bits = 2)]
+            last: PropertyValue,
+        }
+
+        let in_value = PropertyUpdate {
+            count: 3,
+            properties: vec![
+                Property::Health(250),
+                Property::Custom {
+                    kind: 2,
+                    value: PropertyValue::Flag(true),
+                },
+                Property::Custom {
+                    kind: 1,
+                    value: PropertyValue::Float(0.5),
+                },
+            ],
+            pair: [PropertyValue::Int(7), PropertyValue::Flag(false)],
+            last: PropertyValue::Float(1.5),
+        };
+        assert_eq!(
+            in_value.bits(),
+            5 + (3 + 32) + (3 + 2 + 1) + (3 + 2 + 32) + 34 + 3 + 34
+        );
+        assert_eq!(write_and_read(&in_value), in_value);
+
+        let fields = PropertyUpdate::FIELDS;
+        assert_eq!(fields[1].kind, FieldKind::InlineUnionArray { bits: 3 });
+        assert_eq!(fields[1].length, Some("count"));
+        assert_eq!(fields[2].kind, FieldKind::InlineUnion { bits: 2 });
+
+        // the variant of each item is checked
+        let mut reader = BitPackReader::new(&[0x21, 0, 0, 0, 0, 0]);
+        assert!(matches!(
+            reader
+                .read::<PropertyUpdate>()
+                .map_err(BitPackError::into_root),
+            Err(BitPackError::InvalidUnionVariant {
+                type_name: "Property",
+                variant: 1
+            })
+        ));
+    }
+
+    #[derive(MessageStruct, Message, Debug)]
+    #[message_id(0x0002)]
     struct Message0002 {
         build_number: u32,
         realm_id: u32,
@@ -131,6 +1098,15 @@ mod tests {
         process_creation_time: u64,
     }
 
+    #[test]
+    fn test_message_id() {
+        assert_eq!(<Message0002 as crate::Message>::id(), 0x0002);
+
+        let registration = find_message(0x0002).unwrap();
+        assert_eq!(registration.name, "Message0002");
+        assert!(find_message(0x0001).is_none());
+    }
+
     #[test]
     fn test_simple_read() {
         let data = "2f00000240c00000000000008800000000000000000000\
@@ -180,7 +1156,7 @@ mod tests {
 
         // check final buffer
         assert_eq!(
-            hex::encode(&buf),
+            hex::encode(buf),
             "2f00000240c00000000000008800000000000000000000\
             00000000000000489208b89c000000000000000000000000"
         );
@@ -207,8 +1183,8 @@ mod tests {
     #[derive(MessageStruct)]
     struct Message02EE {
         account_id: u32,
-        #[aligned]
-        session_guid: [u8; 16],
+        // aligned on its own
+        session_guid: uuid::Uuid,
         account_name: String,
     }
 
@@ -220,7 +1196,7 @@ mod tests {
         )
         .unwrap();
 
-        let guid_data = hex::decode("ba75a452f8a21b49b0d886ed0d9e58a8").unwrap();
+        let guid = uuid::Uuid::parse_str("52a475ba-a2f8-491b-b0d8-86ed0d9e58a8").unwrap();
         let mut reader = BitPackReader::new(&data);
 
         // header
@@ -229,7 +1205,53 @@ mod tests {
 
         let result: Message02EE = reader.read().unwrap();
         assert_eq!(result.account_id, 13761);
-        assert_eq!(result.session_guid, guid_data.as_slice());
+        assert_eq!(result.session_guid, guid);
         assert_eq!(result.account_name, "clamoune");
+
+        // the body starts after the header, which moves the padding of the guid
+        let mut writer = BitPackWriter::growable();
+        writer.write_u64(0, 35).unwrap();
+        writer.write(&result).unwrap();
+        assert_eq!(writer.position(), 35 + result.bits_at(35));
+        assert_eq!(result.bits(), 32 + 128 + result.account_name.bits());
+        assert_eq!(result.bits_at(35), result.bits() + 5);
+    }
+
+    #[test]
+    fn test_nested_alignment_bits() {
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Inner {
+            flag: bool,
+            guid: uuid::Uuid,
+        }
+
+        #[derive(MessageStruct, Debug, PartialEq)]
+        struct Outer {
+            #[packed(3)]
+            kind: u8,
+            inner: Inner,
+            #[aligned]
+            id: u16,
+        }
+
+        let inner = Inner {
+            flag: true,
+            guid: uuid::Uuid::parse_str("52a475ba-a2f8-491b-b0d8-86ed0d9e58a8").unwrap(),
+        };
+        assert_eq!(inner.bits(), 136);
+
+        let in_value = Outer {
+            kind: 5,
+            inner,
+            id: 7,
+        };
+        assert_eq!(in_value.bits(), 8 + 128 + 16);
+        for position in [0, 3, 35] {
+            let mut writer = BitPackWriter::growable();
+            writer.write_u64(0, position).unwrap();
+            writer.write(&in_value).unwrap();
+            assert_eq!(writer.position(), position + in_value.bits_at(position));
+        }
+        assert_eq!(write_and_read(&in_value), in_value);
     }
 }
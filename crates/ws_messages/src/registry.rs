@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use ws_bitpack::{BitPackReader, BitPackResult, ReadValue};
+
+use crate::{AnyMessage, Message};
+
+/// Decodes a message body into a type-erased message.
+pub type ReadMessageFn = fn(&mut BitPackReader) -> BitPackResult<Box<dyn AnyMessage>>;
+
+/// Formats a type-erased message with the `Debug` impl of its concrete type.
+pub type DebugMessageFn = fn(&dyn AnyMessage, &mut fmt::Formatter) -> fmt::Result;
+
+/// Returns the name of a type without its path, which is what
+/// `#[derive(Message)]` registers messages as. The parameters of generic types
+/// keep their path.
+pub fn short_type_name<T: ?Sized>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    let end = name.find('<').unwrap_or(name.len());
+    let start = name[..end].rfind("::").map_or(0, |index| index + 2);
+    &name[start..]
+}
+
+/// A message type registered by `#[derive(Message)]`.
+#[derive(Debug, Clone, Copy)]
+pub struct MessageRegistration {
+    /// The opcode of the message.
+    pub id: u32,
+    /// The name of the message type, without its path.
+    pub name: &'static str,
+    /// Decodes the message body.
+    pub read: ReadMessageFn,
+    /// Formats a decoded message.
+    pub debug: DebugMessageFn,
+}
+
+impl MessageRegistration {
+    /// Creates the registration of a message type that wasn't derived.
+    pub fn of<T>() -> Self
+    where
+        T: Message + AnyMessage + ReadValue + fmt::Debug,
+    {
+        Self {
+            id: T::id(),
+            name: short_type_name::<T>(),
+            read: read_any::<T>,
+            debug: debug_any::<T>,
+        }
+    }
+
+    /// Wraps a message decoded by this registration so that it is formatted
+    /// with the `Debug` impl of its concrete type.
+    pub fn debug_message<'a>(&self, message: &'a dyn AnyMessage) -> impl fmt::Debug + 'a {
+        DebugMessage {
+            debug: self.debug,
+            message,
+        }
+    }
+}
+
+struct DebugMessage<'a> {
+    debug: DebugMessageFn,
+    message: &'a dyn AnyMessage,
+}
+
+impl fmt::Debug for DebugMessage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (self.debug)(self.message, f)
+    }
+}
+
+inventory::collect!(MessageRegistration);
+
+/// Returns all messages registered through `#[derive(Message)]`, in no particular
+/// order.
+pub fn registered_messages() -> impl Iterator<Item = &'static MessageRegistration> {
+    inventory::iter::<MessageRegistration>.into_iter()
+}
+
+/// Finds the registered message with the given opcode.
+pub fn find_message(id: u32) -> Option<&'static MessageRegistration> {
+    registered_messages().find(|registration| registration.id == id)
+}
+
+/// Formats a message that is a `T`, or its name and id if it isn't.
+#[doc(hidden)]
+pub fn debug_any<T>(message: &dyn AnyMessage, f: &mut fmt::Formatter) -> fmt::Result
+where
+    T: AnyMessage + fmt::Debug,
+{
+    match message.downcast_ref::<T>() {
+        Some(message) => fmt::Debug::fmt(message, f),
+        None => fmt::Debug::fmt(message, f),
+    }
+}
+
+/// Reads a `T` and boxes it as an [`AnyMessage`].
+#[doc(hidden)]
+pub fn read_any<T>(reader: &mut BitPackReader) -> BitPackResult<Box<dyn AnyMessage>>
+where
+    T: AnyMessage + ReadValue,
+{
+    Ok(Box::new(T::read(reader)?))
+}
+
+/// Maps opcodes to the functions decoding their message.
+///
+/// This lets a server decode any known message from the opcode of its header.
+#[derive(Debug, Clone, Default)]
+pub struct MessageRegistry {
+    messages: HashMap<u32, MessageRegistration>,
+}
+
+impl MessageRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a registry with all messages registered through `#[derive(Message)]`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if two registered messages share the same opcode.
+    pub fn with_registered() -> Self {
+        let mut registry = Self::new();
+        for registration in registered_messages() {
+            if let Some(previous) = registry.insert(*registration) {
+                panic!(
+                    "Messages {} and {} share the same id {:#06x}",
+                    previous.name, registration.name, registration.id
+                );
+            }
+        }
+        registry
+    }
+
+    /// Registers a message type, replacing any message with the same opcode.
+    pub fn register<T>(&mut self) -> Option<MessageRegistration>
+    where
+        T: Message + AnyMessage + ReadValue + fmt::Debug,
+    {
+        self.insert(MessageRegistration::of::<T>())
+    }
+
+    /// Adds a registration, returning the one it replaced if any.
+    pub fn insert(&mut self, registration: MessageRegistration) -> Option<MessageRegistration> {
+        self.messages.insert(registration.id, registration)
+    }
+
+    pub fn get(&self, id: u32) -> Option<&MessageRegistration> {
+        self.messages.get(&id)
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.messages.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MessageRegistration> {
+        self.messages.values()
+    }
+
+    /// Decodes the body of the message with opcode `id`.
+    ///
+    /// Returns `None` if no message is registered for that opcode.
+    pub fn decode(
+        &self,
+        id: u32,
+        reader: &mut BitPackReader,
+    ) -> Option<BitPackResult<Box<dyn AnyMessage>>> {
+        self.get(id).map(|registration| (registration.read)(reader))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_bitpack::*;
+
+    use crate::*;
+
+    #[derive(MessageStruct, Message, Debug, PartialEq)]
+    #[message_id(0x07ff)]
+    struct Message07FF {
+        value: u32,
+    }
+
+    #[test]
+    fn test_registry_decode() {
+        let registry = MessageRegistry::with_registered();
+        assert!(registry.contains(0x07ff));
+        assert_eq!(registry.get(0x07ff).unwrap().name, "Message07FF");
+
+        let data = hex::decode("2a000000").unwrap();
+        let mut reader = BitPackReader::new(&data);
+        let message = registry.decode(0x07ff, &mut reader).unwrap().unwrap();
+        assert_eq!(message.message_id(), 0x07ff);
+        // every registration goes by the same name
+        assert_eq!(message.message_name(), "Message07FF");
+        let registration = MessageRegistration::of::<Message07FF>();
+        assert_eq!(registration.name, "Message07FF");
+        assert_eq!(message.message_bits(), 32);
+        let registration = registry.get(0x07ff).unwrap();
+        assert_eq!(
+            format!("{:?}", registration.debug_message(&*message)),
+            "Message07FF { value: 42 }"
+        );
+        assert_eq!(
+            message.downcast::<Message07FF>().unwrap(),
+            Box::new(Message07FF { value: 42 })
+        );
+
+        let mut reader = BitPackReader::new(&data);
+        assert!(registry.decode(0x07fe, &mut reader).is_none());
+    }
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name::<Message07FF>(), "Message07FF");
+        assert_eq!(short_type_name::<u32>(), "u32");
+        assert_eq!(
+            short_type_name::<Vec<std::string::String>>(),
+            "Vec<alloc::string::String>"
+        );
+    }
+}
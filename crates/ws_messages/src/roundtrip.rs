@@ -0,0 +1,265 @@
+use std::fmt;
+
+use ws_bitpack::{BitPackError, BitPackReader, BitPackWriter};
+
+use crate::MessageRegistry;
+
+/// Why a message didn't survive being decoded and encoded again.
+#[derive(Debug)]
+pub enum RoundTripError {
+    InvalidHex(String),
+    /// No message is registered for the opcode.
+    UnknownMessage(u32),
+    Read(BitPackError),
+    Write(BitPackError),
+    Mismatch(RoundTripMismatch),
+}
+
+impl fmt::Display for RoundTripError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidHex(hex) => write!(f, "invalid hex string {hex:?}"),
+            Self::UnknownMessage(id) => write!(f, "no message is registered for {id:#06x}"),
+            Self::Read(error) => write!(f, "decoding failed: {error}"),
+            Self::Write(error) => write!(f, "encoding failed: {error}"),
+            Self::Mismatch(mismatch) => mismatch.fmt(f),
+        }
+    }
+}
+
+/// Where the encoded message first differs from the original data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoundTripMismatch {
+    pub name: &'static str,
+    /// The offset of the first bit that differs.
+    pub bit: usize,
+    /// The path of the field written at that offset, like `characters.name`.
+    ///
+    /// This is only known when the `trace` feature of `ws_bitpack` is enabled.
+    pub field: Option<String>,
+    pub expected: Vec<u8>,
+    pub actual: Vec<u8>,
+}
+
+impl fmt::Display for RoundTripMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} differs at bit {} (byte {}, bit {})",
+            self.name,
+            self.bit,
+            self.bit / 8,
+            self.bit % 8
+        )?;
+        match &self.field {
+            Some(field) => writeln!(f, ", while writing `{field}`")?,
+            None => writeln!(f)?,
+        }
+        if self.expected.len() != self.actual.len() {
+            writeln!(
+                f,
+                "expected {} bytes, got {}",
+                self.expected.len(),
+                self.actual.len()
+            )?;
+        }
+
+        // show the bytes around the first difference
+        let byte = self.bit / 8;
+        let start = byte.saturating_sub(8);
+        let hex = |bytes: &[u8]| {
+            let end = bytes.len().min(byte + 9);
+            let bytes = bytes.get(start..end).unwrap_or_default();
+            bytes
+                .iter()
+                .map(|b| format!("{b:02x}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        writeln!(f, "expected: {}", hex(&self.expected))?;
+        writeln!(f, "actual:   {}", hex(&self.actual))?;
+        writeln!(f, "          {}^^", "   ".repeat(byte - start))?;
+
+        // bits are written from the least significant one
+        let bits = |bytes: &[u8]| match bytes.get(byte) {
+            Some(b) => format!("{:08b}", b.reverse_bits()),
+            None => "--------".to_string(),
+        };
+        let prefix = format!("expected bits of byte {byte}: ");
+        writeln!(f, "{prefix}{}", bits(&self.expected))?;
+        writeln!(
+            f,
+            "{:1$}{2}",
+            "actual bits:",
+            prefix.len(),
+            bits(&self.actual)
+        )?;
+        write!(f, "{}^", " ".repeat(prefix.len() + self.bit % 8))
+    }
+}
+
+/// Decodes `data` as the message with opcode `id`, encodes it again, and
+/// checks that the result is the same as `data`.
+pub fn round_trip(registry: &MessageRegistry, id: u32, data: &[u8]) -> Result<(), RoundTripError> {
+    let registration = registry.get(id).ok_or(RoundTripError::UnknownMessage(id))?;
+    let mut reader = BitPackReader::new(data);
+    let message = (registration.read)(&mut reader).map_err(RoundTripError::Read)?;
+    reader.finish().map_err(RoundTripError::Read)?;
+
+    let mut writer = BitPackWriter::with_capacity(data.len());
+    writer.start_trace();
+    let result = message.write_message(&mut writer);
+    let trace = writer.take_trace();
+    result.map_err(RoundTripError::Write)?;
+    let actual = writer.finish().map_err(RoundTripError::Write)?;
+
+    let bit = match first_different_bit(data, &actual) {
+        Some(bit) => bit,
+        None => return Ok(()),
+    };
+    let field = trace.and_then(|trace| trace.path_at(bit));
+    Err(RoundTripError::Mismatch(RoundTripMismatch {
+        name: registration.name,
+        bit,
+        field,
+        expected: data.to_vec(),
+        actual,
+    }))
+}
+
+/// Checks that a captured message body, given in hex, is encoded back to the
+/// same bytes after being decoded. Panics with a description of the first
+/// difference otherwise.
+///
+/// The message is looked up among the messages registered with
+/// `#[derive(Message)]`. Enable the `trace` feature to also get the name of the
+/// field that was written where the data differs.
+#[track_caller]
+pub fn assert_roundtrip(id: u32, hex: &str) {
+    let registry = MessageRegistry::with_registered();
+    let result = parse_hex(hex).and_then(|data| round_trip(&registry, id, &data));
+    if let Err(error) = result {
+        panic!("Round trip of message {id:#06x} failed: {error}");
+    }
+}
+
+/// Parses a hex string, ignoring whitespace.
+fn parse_hex(hex: &str) -> Result<Vec<u8>, RoundTripError> {
+    let digits = hex.split_whitespace().collect::<String>();
+    let invalid = || RoundTripError::InvalidHex(hex.to_string());
+    if digits.len() % 2 != 0 {
+        return Err(invalid());
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(digits.get(i..i + 2).ok_or_else(invalid)?, 16).map_err(|_| invalid())
+        })
+        .collect()
+}
+
+fn first_different_bit(expected: &[u8], actual: &[u8]) -> Option<usize> {
+    let length = expected.len().max(actual.len());
+    (0..length).find_map(|i| {
+        let diff = expected.get(i).copied().unwrap_or(0) ^ actual.get(i).copied().unwrap_or(0);
+        match diff {
+            0 if expected.len() != actual.len() && i >= expected.len().min(actual.len()) => {
+                Some(i * 8)
+            }
+            0 => None,
+            diff => Some(i * 8 + diff.trailing_zeros() as usize),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_bitpack::*;
+
+    use super::*;
+    use crate::*;
+
+    /// A byte that is written back with one of its bits flipped.
+    #[derive(Debug, PartialEq)]
+    struct Flipped(u8);
+
+    impl ReadValue for Flipped {
+        fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+            Ok(Self(reader.read()?))
+        }
+    }
+
+    impl WriteValue for Flipped {
+        fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+            writer.write(&(self.0 ^ 0x10))
+        }
+
+        fn bits(&self) -> usize {
+            8
+        }
+    }
+
+    #[derive(MessageStruct, Debug, PartialEq)]
+    struct Inner {
+        #[packed(4)]
+        value: u8,
+        flipped: Flipped,
+    }
+
+    #[derive(MessageStruct, Message, Debug, PartialEq)]
+    #[message_id(0x07fd)]
+    struct Message07FD {
+        id: u8,
+        #[packed(3)]
+        small: u8,
+    }
+
+    #[derive(MessageStruct, Message, Debug, PartialEq)]
+    #[message_id(0x07fc)]
+    struct Message07FC {
+        id: u8,
+        inner: Inner,
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_roundtrip(0x07fd, "2a 07");
+
+        let mut registry = MessageRegistry::new();
+        registry.register::<Message07FD>();
+        registry.register::<Message07FC>();
+        assert!(matches!(
+            round_trip(&registry, 0x07fe, &[]),
+            Err(RoundTripError::UnknownMessage(0x07fe))
+        ));
+        assert!(matches!(
+            round_trip(&registry, 0x07fd, &[0x2a, 0x07, 0xff]),
+            Err(RoundTripError::Read(BitPackError::TrailingData { bits: 8 }))
+        ));
+
+        // padding bits are written back as 0
+        let mismatch = match round_trip(&registry, 0x07fd, &[0x2a, 0x0f]) {
+            Err(RoundTripError::Mismatch(mismatch)) => mismatch,
+            result => panic!("Unexpected result {result:?}"),
+        };
+        assert_eq!(mismatch.bit, 11);
+        assert_eq!(mismatch.field, None);
+        assert_eq!(mismatch.actual, [0x2a, 0x07]);
+
+        let mismatch = match round_trip(&registry, 0x07fc, &[0x2a, 0x00, 0x00]) {
+            Err(RoundTripError::Mismatch(mismatch)) => mismatch,
+            result => panic!("Unexpected result {result:?}"),
+        };
+        assert_eq!(mismatch.bit, 16);
+        assert_eq!(mismatch.field.as_deref(), Some("inner.flipped"));
+        assert!(mismatch.to_string().contains(
+            "Message07FC differs at bit 16 (byte 2, bit 0), while writing `inner.flipped`"
+        ));
+    }
+    #[test]
+    fn test_first_different_bit() {
+        assert_eq!(first_different_bit(&[1, 2], &[1, 2]), None);
+        assert_eq!(first_different_bit(&[1, 2], &[1, 6]), Some(10));
+        assert_eq!(first_different_bit(&[1], &[1, 0]), Some(8));
+    }
+}
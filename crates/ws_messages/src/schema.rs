@@ -0,0 +1,243 @@
+/// How a field of a derived type is encoded, from its attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    /// Read with the `ReadValue` impl of its type.
+    Value,
+    /// `#[packed(bits)]`: an integer or enum on `bits` bits.
+    Packed { bits: usize },
+    /// `#[length(...)]`: a number of items given by the length expression.
+    Array,
+    /// `#[packed(bits)]` with `#[length(...)]`: items packed on `bits` bits.
+    PackedArray { bits: usize },
+    /// `#[variant(...)]`: a union whose variant is selected by another field.
+    Union,
+    /// `#[variant(inline, bits = N)]`: a union preceded by its variant on
+    /// `bits` bits.
+    InlineUnion { bits: usize },
+    /// `#[variant(inline, bits = N)]` with `#[length(...)]`: unions each
+    /// preceded by their variant.
+    InlineUnionArray { bits: usize },
+    /// `#[ascii]`: a length-prefixed string with one byte per character.
+    Ascii,
+    /// `#[bytes]` with `#[length(...)]`: whole bytes.
+    Bytes,
+    /// `#[wide_fixed(units)]`: a string on `units` UTF-16 code units.
+    WideFixed { units: usize },
+    /// `#[flags(bits)]`: a `bitflags!` type written as its mask on `bits` bits,
+    /// `retain` keeping undefined bits instead of failing.
+    Flags { bits: usize, retain: bool },
+}
+
+impl FieldKind {
+    /// Returns the number of bits of packed values, or of each packed item.
+    pub fn bits(self) -> Option<usize> {
+        match self {
+            Self::Packed { bits } | Self::PackedArray { bits } | Self::Flags { bits, .. } => {
+                Some(bits)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// A field of a derived struct or union variant, as written in its definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldDescriptor {
+    pub name: &'static str,
+    /// The type of the field without spaces, like `Vec<CharacterEntry>`.
+    pub ty: &'static str,
+    pub kind: FieldKind,
+    /// Whether the field starts at the next full byte.
+    pub aligned: bool,
+    /// The expression giving the number of items of arrays, like `count`.
+    pub length: Option<&'static str>,
+    /// `#[length_of(...)]`: the array whose length is written in this field.
+    pub length_of: Option<&'static str>,
+    /// The field selecting the variant of unions.
+    pub variant: Option<&'static str>,
+    /// The condition for `Option` fields to be present, like `flags & 1 != 0`.
+    pub present: Option<&'static str>,
+    /// Whether the field can be missing at the end of the data, from
+    /// `#[trailing]` or `#[default_on_eof]`.
+    pub trailing: bool,
+}
+
+/// A variant of a derived union, with the selector values reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VariantDescriptor {
+    pub name: &'static str,
+    /// The selector value written for the variant.
+    pub index: usize,
+    /// Every selector value reading the variant, `index` first.
+    pub selectors: &'static [usize],
+    pub fields: &'static [FieldDescriptor],
+}
+
+/// The shape of a derived type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypeShape {
+    Struct(&'static [FieldDescriptor]),
+    Union(&'static [VariantDescriptor]),
+    /// An enum read as its `repr` integer, with the value of each variant.
+    Enum {
+        repr: &'static str,
+        values: &'static [(&'static str, i64)],
+    },
+}
+
+/// Implemented by `MessageStruct` to describe the fields of a struct, generic
+/// structs included.
+pub trait MessageSchema {
+    /// The name of the type, without its module path or generics.
+    const NAME: &'static str;
+    /// The fields, in the order they are read and written.
+    const FIELDS: &'static [FieldDescriptor];
+
+    fn schema() -> TypeSchema {
+        TypeSchema {
+            name: Self::NAME,
+            shape: TypeShape::Struct(Self::FIELDS),
+        }
+    }
+}
+
+/// The layout of a type deriving `MessageStruct`, `MessageUnion` or
+/// `MessageEnum`, registered so that tools can describe messages without
+/// parsing their source.
+///
+/// Generic types aren't registered, their layout depends on their parameters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeSchema {
+    /// The name of the type, without its module path.
+    pub name: &'static str,
+    pub shape: TypeShape,
+}
+
+inventory::collect!(TypeSchema);
+
+/// Returns the schemas of every derived type linked in.
+pub fn registered_schemas() -> impl Iterator<Item = &'static TypeSchema> {
+    inventory::iter::<TypeSchema>.into_iter()
+}
+
+/// Returns the schema of the derived type named `name`, if any.
+///
+/// Types with the same name in different modules can't be told apart, the first
+/// one found is returned.
+pub fn find_schema(name: &str) -> Option<&'static TypeSchema> {
+    registered_schemas().find(|schema| schema.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_bitpack::*;
+
+    use crate::*;
+
+    #[derive(MessageEnum, Debug, Clone, Copy, PartialEq)]
+    #[repr(u8)]
+    enum SchemaKind {
+        Small = 0,
+        Large = 1,
+    }
+
+    #[derive(MessageUnion, Debug)]
+    enum SchemaUnion {
+        Small { value: u8 },
+        Large { value: u32 },
+    }
+
+    #[derive(MessageStruct, Debug)]
+    struct SchemaStruct {
+        #[packed(3)]
+        kind: SchemaKind,
+        count: u8,
+        #[aligned]
+        #[length(count)]
+        items: Vec<u16>,
+        #[variant(kind)]
+        value: SchemaUnion,
+    }
+
+    #[test]
+    fn test_registered_schemas() {
+        let schema = find_schema("SchemaStruct").unwrap();
+        let fields = match schema.shape {
+            TypeShape::Struct(fields) => fields,
+            _ => panic!("SchemaStruct isn't a struct"),
+        };
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0].kind, FieldKind::Packed { bits: 3 });
+        assert_eq!(fields[0].ty, "SchemaKind");
+        assert_eq!(
+            fields[2],
+            FieldDescriptor {
+                name: "items",
+                ty: "Vec<u16>",
+                kind: FieldKind::Array,
+                aligned: true,
+                length: Some("count"),
+                length_of: None,
+                variant: None,
+                present: None,
+                trailing: false,
+            }
+        );
+        assert_eq!(fields[3].variant, Some("kind"));
+
+        assert_eq!(
+            find_schema("SchemaKind").unwrap().shape,
+            TypeShape::Enum {
+                repr: "u8",
+                values: &[("Small", 0), ("Large", 1)],
+            }
+        );
+        match find_schema("SchemaUnion").unwrap().shape {
+            TypeShape::Union(variants) => {
+                assert_eq!(variants[1].name, "Large");
+                assert_eq!(variants[1].index, 1);
+                assert_eq!(variants[1].selectors, [1]);
+                assert_eq!(variants[1].fields[0].ty, "u32");
+            }
+            _ => panic!("SchemaUnion isn't a union"),
+        }
+
+        // the schema describes what is actually read
+        let data = [0x09, 0x00, 0x34, 0x12, 0x78, 0x56, 0x34, 0x12];
+        let value: SchemaStruct = BitPackReader::new(&data).read().unwrap();
+        assert_eq!(value.kind, SchemaKind::Large);
+        assert_eq!(value.items, [0x1234]);
+        assert!(matches!(
+            value.value,
+            SchemaUnion::Large { value: 0x12345678 }
+        ));
+    }
+
+    #[derive(MessageStruct, Debug)]
+    struct GenericSchema<T> {
+        #[length_of(items)]
+        count: u8,
+        #[length(count)]
+        #[packed(4)]
+        items: Vec<u8>,
+        value: T,
+    }
+
+    #[test]
+    fn test_message_schema() {
+        type Schema = GenericSchema<u32>;
+        assert_eq!(Schema::NAME, "GenericSchema");
+        assert_eq!(Schema::FIELDS.len(), 3);
+        assert_eq!(Schema::FIELDS[0].length_of, Some("items"));
+        assert_eq!(Schema::FIELDS[1].kind.bits(), Some(4));
+        assert_eq!(Schema::FIELDS[2].ty, "T");
+        assert_eq!(Schema::schema().shape, TypeShape::Struct(Schema::FIELDS));
+        // generic types aren't registered
+        assert!(find_schema("GenericSchema").is_none());
+
+        let data = [0x02, 0x21, 0x78, 0x56, 0x34, 0x12];
+        let value: Schema = BitPackReader::new(&data).read().unwrap();
+        assert_eq!(value.items, [1, 2]);
+        assert_eq!(value.value, 0x12345678);
+    }
+}
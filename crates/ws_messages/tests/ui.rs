@@ -0,0 +1,9 @@
+//! Checks that invalid uses of the derives fail with errors pointing at the
+//! offending attribute. Run with `TRYBUILD=overwrite` to update the expected
+//! errors after changing them.
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,26 @@
+use ws_messages::*;
+
+#[derive(MessageStruct)]
+struct Struct {
+    #[packed(0)]
+    empty: u8,
+    #[packed(65)]
+    wide: u64,
+    #[packed(bits)]
+    named: u8,
+    #[aligned(1)]
+    aligned: u8,
+    count: u8,
+    #[length(count, min = 1)]
+    items: Vec<u8>,
+    #[flags(8, keep)]
+    flags: u8,
+    #[variant(1 + 1)]
+    selected: u8,
+    #[variant(inline)]
+    inline: u8,
+    #[variant(inline, bytes = 2)]
+    inline_bytes: u8,
+}
+
+fn main() {}
@@ -0,0 +1,25 @@
+use ws_messages::*;
+
+#[derive(MessageStruct)]
+struct Struct {
+    #[packed(3)]
+    #[ascii]
+    name: String,
+    count: u8,
+    #[bytes]
+    digest: Vec<u8>,
+    #[length(count)]
+    #[wide_fixed(8)]
+    title: String,
+    #[packed(3)]
+    #[packed(4)]
+    level: u8,
+    #[present(count > 0)]
+    #[skip_if(count == 0)]
+    extra: Option<u8>,
+    #[variant(count)]
+    #[length(count)]
+    values: Vec<u8>,
+}
+
+fn main() {}
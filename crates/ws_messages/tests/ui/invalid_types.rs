@@ -0,0 +1,23 @@
+use ws_messages::*;
+
+#[derive(MessageStruct)]
+struct Struct {
+    #[packed(10)]
+    speed: f32,
+    pair: (u8, u8),
+    names: [&'static str; 2],
+}
+
+#[derive(MessageStruct)]
+enum NotAStruct {}
+
+#[derive(MessageEnum)]
+enum WithFields {
+    Unit,
+    Tuple(u8),
+}
+
+#[derive(MessageStruct, Message, Debug)]
+struct MissingId {}
+
+fn main() {}
@@ -0,0 +1,26 @@
+use ws_messages::*;
+
+#[derive(MessageUnion)]
+enum Union {
+    #[variant_index(2)]
+    First,
+    #[variant_index(1)]
+    Second(u8),
+    Third { value: u16 },
+    #[variant_index(first)]
+    Fourth,
+}
+
+#[derive(MessageUnion)]
+enum SelectedUnion {
+    #[selector(1)]
+    #[variant_index(1)]
+    First,
+    #[selector(2, 3)]
+    Second(u8),
+    Third { value: u16 },
+    #[selector()]
+    Fourth,
+}
+
+fn main() {}
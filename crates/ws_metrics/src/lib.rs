@@ -0,0 +1,209 @@
+//! Prometheus metrics of the servers, and the HTTP endpoint serving them.
+//!
+//! Metrics are recorded on the process-wide [`Metrics`] returned by
+//! [`metrics`], so that the network code and the world simulation can record
+//! them without passing a handle around. [`MetricsServer`] serves them in the
+//! Prometheus text format at `/metrics`.
+
+mod http;
+
+pub use http::*;
+
+use std::fmt::{self, Write};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue, LabelValueEncoder};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+
+/// An opcode, labeled in hex like in the packet logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Opcode(pub u32);
+
+impl EncodeLabelValue for Opcode {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> fmt::Result {
+        write!(encoder, "0x{:04x}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, EncodeLabelSet)]
+struct OpcodeLabels {
+    opcode: Opcode,
+}
+
+/// The metrics of a server process.
+pub struct Metrics {
+    registry: Registry,
+    packets_in: Family<OpcodeLabels, Counter>,
+    packets_out: Family<OpcodeLabels, Counter>,
+    decode_failures: Family<OpcodeLabels, Counter>,
+    sessions: Gauge,
+    tick_duration: Histogram,
+    tick_overruns: Counter,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let mut metrics = Self {
+            registry: Registry::with_prefix("ws"),
+            packets_in: Family::default(),
+            packets_out: Family::default(),
+            decode_failures: Family::default(),
+            sessions: Gauge::default(),
+            // from 1ms to about 1s
+            tick_duration: Histogram::new(exponential_buckets(0.001, 2.0, 11)),
+            tick_overruns: Counter::default(),
+        };
+        let registry = &mut metrics.registry;
+        registry.register(
+            "packets_in",
+            "Packets received, by opcode",
+            metrics.packets_in.clone(),
+        );
+        registry.register(
+            "packets_out",
+            "Packets sent, by opcode",
+            metrics.packets_out.clone(),
+        );
+        registry.register(
+            "decode_failures",
+            "Packets whose message couldn't be decoded, by opcode",
+            metrics.decode_failures.clone(),
+        );
+        registry.register("sessions", "Connected sessions", metrics.sessions.clone());
+        registry.register(
+            "tick_duration_seconds",
+            "How long world ticks took",
+            metrics.tick_duration.clone(),
+        );
+        registry.register(
+            "tick_overruns",
+            "World ticks that took longer than the tick duration",
+            metrics.tick_overruns.clone(),
+        );
+        metrics
+    }
+
+    pub fn packet_in(&self, opcode: u32) {
+        self.packets_in.get_or_create(&opcode_labels(opcode)).inc();
+    }
+
+    pub fn packet_out(&self, opcode: u32) {
+        self.packets_out.get_or_create(&opcode_labels(opcode)).inc();
+    }
+
+    pub fn decode_failure(&self, opcode: u32) {
+        self.decode_failures
+            .get_or_create(&opcode_labels(opcode))
+            .inc();
+    }
+
+    pub fn session_opened(&self) {
+        self.sessions.inc();
+    }
+
+    pub fn session_closed(&self) {
+        self.sessions.dec();
+    }
+
+    pub fn observe_tick(&self, duration: Duration) {
+        self.tick_duration.observe(duration.as_secs_f64());
+    }
+
+    pub fn tick_overrun(&self) {
+        self.tick_overruns.inc();
+    }
+
+    /// Returns the metrics in the Prometheus text format.
+    pub fn encode(&self) -> String {
+        let mut text = String::new();
+        prometheus_client::encoding::text::encode(&mut text, &self.registry)
+            .expect("writing to a String can't fail");
+        text
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Metrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metrics").finish_non_exhaustive()
+    }
+}
+
+fn opcode_labels(opcode: u32) -> OpcodeLabels {
+    OpcodeLabels {
+        opcode: Opcode(opcode),
+    }
+}
+
+/// Returns the metrics of this process.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+#[derive(Debug)]
+pub enum MetricsError {
+    Io(std::io::Error),
+}
+
+impl fmt::Display for MetricsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MetricsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+        }
+    }
+}
+
+impl From<std::io::Error> for MetricsError {
+    fn from(error: std::io::Error) -> Self {
+        MetricsError::Io(error)
+    }
+}
+
+pub type MetricsResult<T = ()> = Result<T, MetricsError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_metrics() {
+        let metrics = Metrics::new();
+        metrics.packet_in(0x0123);
+        metrics.packet_in(0x0123);
+        metrics.packet_out(0x0640);
+        metrics.decode_failure(0x0123);
+        metrics.session_opened();
+        metrics.session_opened();
+        metrics.session_closed();
+        metrics.observe_tick(Duration::from_millis(3));
+        metrics.tick_overrun();
+
+        let text = metrics.encode();
+        assert!(text.contains("ws_packets_in_total{opcode=\"0x0123\"} 2\n"));
+        assert!(text.contains("ws_packets_out_total{opcode=\"0x0640\"} 1\n"));
+        assert!(text.contains("ws_decode_failures_total{opcode=\"0x0123\"} 1\n"));
+        assert!(text.contains("ws_sessions 1\n"));
+        assert!(text.contains("ws_tick_duration_seconds_count 1\n"));
+        assert!(text.contains("ws_tick_overruns_total 1\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
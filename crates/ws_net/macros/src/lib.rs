@@ -0,0 +1,57 @@
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, FnArg, ItemFn};
+
+/// Turns an `async fn(session: &Session, message: SomeMessage) -> NetResult` into a
+/// `MessageHandler` constant with the same name, which can be added to a
+/// `HandlerMap`.
+#[proc_macro_attribute]
+pub fn message_handler(_args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as ItemFn);
+
+    if item.sig.asyncness.is_none() {
+        return TokenStream::from(quote!(compile_error!(
+            "A message handler must be an async function."
+        )));
+    }
+
+    let message_type = match item.sig.inputs.iter().nth(1) {
+        Some(FnArg::Typed(arg)) if item.sig.inputs.len() == 2 => &arg.ty,
+        _ => {
+            return TokenStream::from(quote!(compile_error!(
+                "A message handler must take a session and a message as arguments."
+            )))
+        }
+    };
+
+    let vis = &item.vis;
+    let ident = &item.sig.ident;
+    let inner_ident = format_ident!("{}_", ident);
+    let mut inner = item.clone();
+    inner.vis = syn::Visibility::Inherited;
+    inner.sig.ident = inner_ident.clone();
+
+    let expanded = quote! {
+        #[allow(non_upper_case_globals)]
+        #vis const #ident: ws_net::MessageHandler = {
+            #inner
+
+            fn handle_(
+                session_: ws_net::Session,
+                message_: Box<dyn ws_net::ws_messages::AnyMessage>,
+            ) -> ws_net::HandlerFuture {
+                Box::pin(async move {
+                    let message_ = ws_net::downcast_message::<#message_type>(message_)?;
+                    #inner_ident(&session_, message_).await
+                })
+            }
+
+            ws_net::MessageHandler {
+                id: <#message_type as ws_net::ws_messages::Message>::id,
+                handle: handle_,
+            }
+        };
+    };
+
+    TokenStream::from(expanded)
+}
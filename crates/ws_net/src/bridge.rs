@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use serde_json::{json, Value};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::watch;
+use ws_bitpack::BitPackReader;
+use ws_messages::{JsonCodecs, MessageRegistry};
+
+use crate::{NetResult, PacketTap, TappedPacket, WebSocket};
+
+/// The path of the WebSocket endpoint of the bridge.
+pub const BRIDGE_PATH: &str = "/packets";
+
+/// Serves the messages of the sessions of a server to browser-based tools
+/// over a WebSocket, and lets them inject crafted messages in a session.
+///
+/// Once connected, a client gets the open sessions:
+///
+/// ```json
+/// { "sessions": [{ "id": 1, "peer_addr": "127.0.0.1:50412", "state": "in world" }] }
+/// ```
+///
+/// Then every message received or sent by a session, in its JSON form when
+/// its type is in the codecs of the bridge, and always with the plaintext
+/// packet in hex:
+///
+/// ```json
+/// { "session": 1, "direction": "in", "opcode": "0x07e0", "message": {}, "packet": "..." }
+/// ```
+///
+/// A client injects a message by sending the same object without the packet.
+/// Messages going `in` are handled as if the client of the session had sent
+/// them, messages going `out` are sent to it. Each command is answered with
+/// `{ "ok": true }` or `{ "error": "..." }`, and `{ "lagged": n }` tells a
+/// client that it missed `n` messages by reading too slowly.
+///
+/// Other paths can serve feeds, see [`DebugBridge::add_feed`].
+pub struct DebugBridge {
+    listener: TcpListener,
+    tap: PacketTap,
+    registry: Arc<MessageRegistry>,
+    codecs: Arc<JsonCodecs>,
+    feeds: HashMap<&'static str, watch::Receiver<String>>,
+}
+
+impl DebugBridge {
+    /// Binds a bridge to the sessions of `tap`, converting messages with
+    /// `codecs` and decoding the injected ones with `registry`.
+    pub async fn bind<A: ToSocketAddrs>(
+        addr: A,
+        tap: PacketTap,
+        registry: MessageRegistry,
+        codecs: JsonCodecs,
+    ) -> NetResult<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            tap,
+            registry: Arc::new(registry),
+            codecs: Arc::new(codecs),
+            feeds: HashMap::new(),
+        })
+    }
+
+    /// Sends the messages of `feed` to the clients connecting to `path`: the
+    /// current one, unless empty, then every new one. Messages the clients
+    /// send are ignored.
+    ///
+    /// This is meant for the state of a server that tools show live, like the
+    /// world snapshots of a dashboard.
+    pub fn add_feed(&mut self, path: &'static str, feed: watch::Receiver<String>) {
+        self.feeds.insert(path, feed);
+    }
+
+    pub fn local_addr(&self) -> NetResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accepts clients forever, serving each of them in its own task.
+    pub async fn run(self) -> NetResult {
+        let feeds = Arc::new(self.feeds);
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let client = BridgeClient {
+                tap: self.tap.clone(),
+                registry: self.registry.clone(),
+                codecs: self.codecs.clone(),
+                feeds: feeds.clone(),
+            };
+            tokio::spawn(async move {
+                if let Err(error) = client.serve(stream).await {
+                    tracing::debug!("Bridge client stopped: {error}");
+                }
+            });
+        }
+    }
+}
+
+/// What the task of a bridge client woke up for.
+enum BridgeEvent {
+    Packet(Result<TappedPacket, RecvError>),
+    Command(Option<String>),
+}
+
+struct BridgeClient {
+    tap: PacketTap,
+    registry: Arc<MessageRegistry>,
+    codecs: Arc<JsonCodecs>,
+    feeds: Arc<HashMap<&'static str, watch::Receiver<String>>>,
+}
+
+impl BridgeClient {
+    async fn serve(&self, stream: tokio::net::TcpStream) -> NetResult {
+        let mut paths = vec![BRIDGE_PATH];
+        paths.extend(self.feeds.keys());
+        let (path, socket) = WebSocket::accept(stream, &paths).await?;
+        match self.feeds.get(path.as_str()) {
+            Some(feed) => serve_feed(socket, feed.clone()).await,
+            None => self.serve_packets(socket).await,
+        }
+    }
+
+    async fn serve_packets(&self, mut socket: WebSocket) -> NetResult {
+        let mut packets = self.tap.subscribe();
+        let sessions: Vec<Value> = self
+            .tap
+            .sessions()
+            .iter()
+            .map(|session| {
+                json!({
+                    "id": session.id(),
+                    "peer_addr": session.peer_addr().to_string(),
+                    "state": session.state().to_string(),
+                })
+            })
+            .collect();
+        socket
+            .send(&json!({ "sessions": sessions }).to_string())
+            .await?;
+
+        loop {
+            let event = tokio::select! {
+                packet = packets.recv() => BridgeEvent::Packet(packet),
+                command = socket.recv() => BridgeEvent::Command(command?),
+            };
+            let reply = match event {
+                BridgeEvent::Packet(Ok(packet)) => self.packet_json(&packet),
+                BridgeEvent::Packet(Err(RecvError::Lagged(missed))) => json!({ "lagged": missed }),
+                BridgeEvent::Packet(Err(RecvError::Closed)) => return Ok(()),
+                BridgeEvent::Command(Some(command)) => match self.inject(&command) {
+                    Ok(()) => json!({ "ok": true }),
+                    Err(error) => json!({ "error": error }),
+                },
+                BridgeEvent::Command(None) => return Ok(()),
+            };
+            socket.send(&reply.to_string()).await?;
+        }
+    }
+
+    fn packet_json(&self, packet: &TappedPacket) -> Value {
+        let mut json = json!({
+            "session": packet.session,
+            "direction": packet.direction.to_string(),
+            "opcode": format!("0x{:04x}", packet.opcode),
+            "packet": hex(&packet.packet),
+        });
+        match self.codecs.read(packet.opcode, &mut packet.reader()) {
+            Some(Ok(message)) => json["message"] = message,
+            Some(Err(error)) => json["error"] = error.into(),
+            None => (),
+        }
+        if let Some(registration) = self.registry.get(packet.opcode) {
+            json["name"] = registration.name.into();
+        }
+        json
+    }
+
+    fn inject(&self, command: &str) -> Result<(), String> {
+        let command: Value =
+            serde_json::from_str(command).map_err(|error| format!("invalid command: {error}"))?;
+        let session = command["session"]
+            .as_u64()
+            .and_then(|id| self.tap.session(id))
+            .ok_or("missing or unknown session")?;
+        let opcode = parse_opcode(&command["opcode"]).ok_or("missing or invalid opcode")?;
+        let message = command.get("message").ok_or("missing message")?.clone();
+        let body = self
+            .codecs
+            .encode(opcode, message)
+            .ok_or_else(|| format!("no JSON form is registered for 0x{opcode:04x}"))??;
+
+        match command["direction"].as_str() {
+            Some("in") => {
+                let registration = self
+                    .registry
+                    .get(opcode)
+                    .ok_or_else(|| format!("no message is registered for 0x{opcode:04x}"))?;
+                let message = (registration.read)(&mut BitPackReader::new(&body))
+                    .map_err(|error| format!("decoding failed: {error}"))?;
+                session.inject(message)
+            }
+            Some("out") => session.send_body(opcode, &body),
+            _ => return Err("the direction must be in or out".to_string()),
+        }
+        .map_err(|error| error.to_string())
+    }
+}
+
+async fn serve_feed(mut socket: WebSocket, mut feed: watch::Receiver<String>) -> NetResult {
+    feed.mark_changed();
+    loop {
+        let changed = tokio::select! {
+            changed = feed.changed() => changed.is_ok(),
+            message = socket.recv() => match message? {
+                Some(_) => continue,
+                None => return Ok(()),
+            },
+        };
+        if !changed {
+            return Ok(());
+        }
+        let message = feed.borrow_and_update().clone();
+        if !message.is_empty() {
+            socket.send(&message).await?;
+        }
+    }
+}
+
+/// Parses an opcode given as a number or as a hex string like `"0x07e0"`.
+fn parse_opcode(value: &Value) -> Option<u32> {
+    match value {
+        Value::Number(number) => number.as_u64().and_then(|n| u32::try_from(n).ok()),
+        Value::String(string) => u32::from_str_radix(string.strip_prefix("0x")?, 16).ok(),
+        _ => None,
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use ws_messages::*;
+    use ws_protocol::FrameDecoder;
+
+    use super::*;
+    use crate::{Handler, Server, Session};
+
+    #[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+    #[message_id(0x0131)]
+    struct Counter {
+        value: u32,
+    }
+
+    struct CounterHandler;
+
+    impl Handler for CounterHandler {
+        async fn message(&self, session: &Session, message: Box<dyn AnyMessage>) -> NetResult {
+            let counter = message.downcast::<Counter>().unwrap();
+            session.send(&Counter {
+                value: counter.value + 1,
+            })
+        }
+    }
+
+    /// The browser side of the bridge, which masks what it sends.
+    struct Client {
+        stream: TcpStream,
+    }
+
+    impl Client {
+        async fn connect(addr: SocketAddr, path: &str) -> Self {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            let request = format!(
+                "GET {path} HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n"
+            );
+            stream.write_all(request.as_bytes()).await.unwrap();
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                response.push(stream.read_u8().await.unwrap());
+            }
+            let response = String::from_utf8(response).unwrap();
+            assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+            // the example of RFC 6455
+            assert!(response.contains("Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+            Self { stream }
+        }
+
+        async fn send(&mut self, message: Value) {
+            let payload = message.to_string().into_bytes();
+            let mask = [1, 2, 3, 4];
+            let mut frame = vec![0x81, 0x80 | payload.len() as u8];
+            frame.extend_from_slice(&mask);
+            frame.extend(payload.iter().enumerate().map(|(i, b)| b ^ mask[i % 4]));
+            self.stream.write_all(&frame).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> Value {
+            let header = [
+                self.stream.read_u8().await.unwrap(),
+                self.stream.read_u8().await.unwrap(),
+            ];
+            assert_eq!(header[0], 0x81);
+            let length = match header[1] {
+                126 => self.stream.read_u16().await.unwrap() as usize,
+                length => length as usize,
+            };
+            let mut payload = vec![0; length];
+            self.stream.read_exact(&mut payload).await.unwrap();
+            serde_json::from_slice(&payload).unwrap()
+        }
+    }
+
+    async fn read_counter(stream: &mut TcpStream, decoder: &mut FrameDecoder) -> Counter {
+        loop {
+            if let Some(frame) = decoder.next_frame().unwrap() {
+                return frame.reader().read().unwrap();
+            }
+            let mut buf = [0u8; 64];
+            let read = stream.read(&mut buf).await.unwrap();
+            decoder.extend(&buf[..read]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bridge() {
+        let tap = PacketTap::new(16);
+        let mut server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        server.set_packet_tap(tap.clone());
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run(std::sync::Arc::new(CounterHandler)));
+        let mut codecs = JsonCodecs::new();
+        codecs.register::<Counter>();
+        let bridge = DebugBridge::bind(
+            "127.0.0.1:0",
+            tap.clone(),
+            MessageRegistry::with_registered(),
+            codecs,
+        )
+        .await
+        .unwrap();
+        let bridge_addr = bridge.local_addr().unwrap();
+        tokio::spawn(bridge.run());
+
+        let mut game = TcpStream::connect(addr).await.unwrap();
+        while tap.sessions().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let mut client = Client::connect(bridge_addr, BRIDGE_PATH).await;
+        let sessions = client.recv().await;
+        assert_eq!(sessions["sessions"][0]["id"], 1);
+        assert_eq!(sessions["sessions"][0]["state"], "connected");
+
+        // messages are streamed both ways
+        let frame = ws_protocol::encode_message(&Counter { value: 41 }).unwrap();
+        game.write_all(&frame).await.unwrap();
+        let packet = client.recv().await;
+        assert_eq!(packet["direction"], "in");
+        assert_eq!(packet["opcode"], "0x0131");
+        assert_eq!(packet["message"], json!({ "value": 41 }));
+        assert_eq!(packet["packet"], hex(&frame));
+        let packet = client.recv().await;
+        assert_eq!(packet["direction"], "out");
+        assert_eq!(packet["message"], json!({ "value": 42 }));
+        let mut decoder = FrameDecoder::new();
+        assert_eq!(read_counter(&mut game, &mut decoder).await.value, 42);
+
+        // crafted messages are sent to the client, or handled as if it sent them
+        let command = json!({ "session": 1, "direction": "out", "opcode": "0x0131", "message": { "value": 7 } });
+        client.send(command).await;
+        assert_eq!(client.recv().await, json!({ "ok": true }));
+        assert_eq!(client.recv().await["message"], json!({ "value": 7 }));
+        assert_eq!(read_counter(&mut game, &mut decoder).await.value, 7);
+        let command =
+            json!({ "session": 1, "direction": "in", "opcode": 0x0131, "message": { "value": 9 } });
+        client.send(command).await;
+        assert_eq!(client.recv().await, json!({ "ok": true }));
+        let packet = client.recv().await;
+        assert_eq!(packet["direction"], "in");
+        assert_eq!(packet["message"], json!({ "value": 9 }));
+        assert_eq!(client.recv().await["message"], json!({ "value": 10 }));
+        assert_eq!(read_counter(&mut game, &mut decoder).await.value, 10);
+
+        let command = json!({ "session": 2, "direction": "in", "opcode": 0x0131, "message": {} });
+        client.send(command).await;
+        assert_eq!(
+            client.recv().await,
+            json!({ "error": "missing or unknown session" })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_feed() {
+        let (feed, receiver) = watch::channel(String::new());
+        let registry = MessageRegistry::new();
+        let mut bridge = DebugBridge::bind(
+            "127.0.0.1:0",
+            PacketTap::new(1),
+            registry,
+            JsonCodecs::new(),
+        )
+        .await
+        .unwrap();
+        bridge.add_feed("/state", receiver);
+        let addr = bridge.local_addr().unwrap();
+        tokio::spawn(bridge.run());
+
+        // nothing is sent until the first message
+        let mut client = Client::connect(addr, "/state").await;
+        feed.send_replace(json!({ "tick": 1 }).to_string());
+        assert_eq!(client.recv().await, json!({ "tick": 1 }));
+        client.send(json!({ "ignored": true })).await;
+        feed.send_replace(json!({ "tick": 2 }).to_string());
+        assert_eq!(client.recv().await, json!({ "tick": 2 }));
+
+        // late clients get the current one
+        let mut client = Client::connect(addr, "/state").await;
+        assert_eq!(client.recv().await, json!({ "tick": 2 }));
+
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(b"GET /other HTTP/1.1\r\nSec-WebSocket-Key: a\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,60 @@
+use std::future::Future;
+
+use ws_messages::AnyMessage;
+use ws_protocol::Frame;
+
+use crate::{LimitAction, NetError, NetResult, Session};
+
+/// Receives the events of every session accepted by a [`Server`](crate::Server).
+///
+/// Events of a single session are delivered in order, one at a time. Returning an
+/// error from a handler closes the session.
+pub trait Handler: Send + Sync + 'static {
+    /// Called when a new session is accepted, before any message is read.
+    fn connected(&self, session: &Session) -> impl Future<Output = NetResult> + Send {
+        let _ = session;
+        async { Ok(()) }
+    }
+
+    /// Called for every message decoded from the session.
+    fn message(
+        &self,
+        session: &Session,
+        message: Box<dyn AnyMessage>,
+    ) -> impl Future<Output = NetResult> + Send;
+
+    /// Called for frames whose opcode isn't in the registry. These are ignored by
+    /// default.
+    fn unknown_message(
+        &self,
+        session: &Session,
+        frame: Frame,
+    ) -> impl Future<Output = NetResult> + Send {
+        let _ = (session, frame);
+        async { Ok(()) }
+    }
+
+    /// Called for messages exceeding a rate limit, before they are dropped or
+    /// the session is closed depending on `action`. This is meant for metrics
+    /// and logs.
+    fn rate_limited(
+        &self,
+        session: &Session,
+        opcode: u32,
+        action: LimitAction,
+    ) -> impl Future<Output = ()> + Send {
+        let _ = (session, opcode, action);
+        async {}
+    }
+
+    /// Called once the session is closed. `error` is the reason it was closed, if
+    /// it wasn't closed cleanly.
+    fn disconnected(
+        &self,
+        session: &Session,
+        error: Option<NetError>,
+    ) -> impl Future<Output = ()> + Send {
+        let _ = (session, error);
+        async {}
+    }
+}
@@ -0,0 +1,134 @@
+// Lets the macros refer to this crate as `ws_net` from within it.
+extern crate self as ws_net;
+
+#[cfg(feature = "bridge")]
+mod bridge;
+mod handler;
+mod limits;
+mod routing;
+mod server;
+mod session;
+mod state;
+mod tap;
+mod traffic;
+#[cfg(feature = "bridge")]
+mod websocket;
+
+#[cfg(feature = "bridge")]
+pub use bridge::*;
+pub use handler::*;
+pub use limits::*;
+pub use routing::*;
+pub use server::*;
+pub use session::*;
+pub use state::*;
+pub use tap::*;
+pub use traffic::*;
+#[cfg(feature = "bridge")]
+pub use websocket::*;
+pub use ws_net_macros::*;
+
+#[doc(hidden)]
+pub use ws_messages;
+
+use std::fmt;
+
+use ws_bitpack::BitPackError;
+use ws_protocol::ProtocolError;
+
+#[derive(Debug)]
+pub enum NetError {
+    Io(std::io::Error),
+    Protocol(ProtocolError),
+    BitPack(BitPackError),
+    /// The session was closed, so nothing can be sent to it anymore.
+    SessionClosed,
+    /// A handler received a message of another type than the one it handles.
+    UnexpectedMessage(&'static str),
+    /// A session didn't send anything for longer than the idle timeout.
+    IdleTimeout,
+    /// A session sent the message with this opcode while over a rate limit
+    /// closing it.
+    RateLimited {
+        opcode: u32,
+    },
+    /// A frame wasn't sent because it doesn't fit in the queue of its session,
+    /// see [`SendLimits`].
+    QueueFull,
+    /// A session had more queued than its client reads for too long, see
+    /// [`SendLimits`].
+    Saturated,
+    /// A message can't be sent with this opcode, which doesn't fit in the
+    /// header.
+    InvalidOpcode(u32),
+    /// A WebSocket client broke the protocol, or asked for something else.
+    WebSocket(&'static str),
+    /// A session sent a message that isn't allowed in its current state.
+    MessageNotAllowed {
+        opcode: u32,
+        state: SessionState,
+    },
+    /// A session was moved out of `expected` by another handle while a
+    /// [`SessionIn`] still held it.
+    StateChanged {
+        expected: SessionState,
+        actual: SessionState,
+    },
+}
+
+impl fmt::Display for NetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::Protocol(error) => error.fmt(f),
+            Self::BitPack(error) => error.fmt(f),
+            Self::SessionClosed => write!(f, "the session is closed"),
+            Self::UnexpectedMessage(name) => write!(f, "unexpected message {name}"),
+            Self::QueueFull => write!(f, "the send queue of the session is full"),
+            Self::Saturated => write!(f, "the client doesn't read what is sent fast enough"),
+            Self::InvalidOpcode(opcode) => write!(f, "invalid opcode 0x{opcode:04x}"),
+            Self::WebSocket(error) => write!(f, "WebSocket error: {error}"),
+            Self::IdleTimeout => write!(f, "the session was idle for too long"),
+            Self::RateLimited { opcode } => {
+                write!(f, "message 0x{opcode:04x} exceeded a rate limit")
+            }
+            Self::MessageNotAllowed { opcode, state } => {
+                write!(f, "message 0x{opcode:04x} isn't allowed while {state}")
+            }
+            Self::StateChanged { expected, actual } => {
+                write!(f, "the session is {actual} instead of {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            Self::Protocol(error) => error.source(),
+            Self::BitPack(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for NetError {
+    fn from(error: std::io::Error) -> Self {
+        NetError::Io(error)
+    }
+}
+
+impl From<ProtocolError> for NetError {
+    fn from(error: ProtocolError) -> Self {
+        NetError::Protocol(error)
+    }
+}
+
+impl From<BitPackError> for NetError {
+    fn from(error: BitPackError) -> Self {
+        NetError::BitPack(error)
+    }
+}
+
+pub type NetResult<T = ()> = Result<T, NetError>;
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use ws_messages::Message;
+
+/// How many bytes can be queued for a client that reads them slower than
+/// they are sent.
+///
+/// Past `backed_up_bytes`, [`Session::is_backed_up`](crate::Session::is_backed_up)
+/// tells the senders to hold back the updates that later ones supersede.
+/// Nothing is queued past `saturated_bytes`: frames that don't fit are refused
+/// with [`NetError::QueueFull`](crate::NetError::QueueFull), and sessions that
+/// keep refusing them for `saturated_timeout` are closed with
+/// [`NetError::Saturated`](crate::NetError::Saturated), without sending what is
+/// queued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SendLimits {
+    pub backed_up_bytes: usize,
+    pub saturated_bytes: usize,
+    pub saturated_timeout: Duration,
+}
+
+impl SendLimits {
+    /// Never backs up or saturates, for sessions whose frames nobody reads.
+    pub const UNLIMITED: Self = Self {
+        backed_up_bytes: usize::MAX,
+        saturated_bytes: usize::MAX,
+        saturated_timeout: Duration::MAX,
+    };
+}
+
+impl Default for SendLimits {
+    fn default() -> Self {
+        Self {
+            backed_up_bytes: 64 * 1024,
+            saturated_bytes: 1024 * 1024,
+            saturated_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+/// What happens to a message exceeding a rate limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LimitAction {
+    /// The message is ignored.
+    Drop,
+    /// The session is closed with [`NetError::RateLimited`](crate::NetError::RateLimited).
+    Disconnect,
+}
+
+/// A token bucket: sessions can send `burst` messages at once, then
+/// `per_second` messages every second.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub burst: u32,
+    pub per_second: f64,
+    pub action: LimitAction,
+}
+
+impl RateLimit {
+    /// A limit dropping the messages that exceed it.
+    pub fn new(burst: u32, per_second: f64) -> Self {
+        Self {
+            burst,
+            per_second,
+            action: LimitAction::Drop,
+        }
+    }
+
+    /// Closes the sessions exceeding this limit instead of dropping their
+    /// messages.
+    pub fn disconnect(mut self) -> Self {
+        self.action = LimitAction::Disconnect;
+        self
+    }
+}
+
+/// The rate limits of every session of a [`Server`](crate::Server), for all
+/// messages and for messages with specific opcodes.
+///
+/// A message counts towards both the global limit and the limit of its opcode,
+/// and is only accepted if neither is exceeded. Pings count too.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    global: Option<RateLimit>,
+    opcodes: HashMap<u32, RateLimit>,
+}
+
+impl RateLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits all the messages of a session.
+    pub fn global(&mut self, limit: RateLimit) -> &mut Self {
+        self.global = Some(limit);
+        self
+    }
+
+    /// Limits the messages of type `T`.
+    pub fn message<T: Message>(&mut self, limit: RateLimit) -> &mut Self {
+        self.opcode(T::id(), limit)
+    }
+
+    /// Limits the messages with opcode `id`.
+    pub fn opcode(&mut self, id: u32, limit: RateLimit) -> &mut Self {
+        self.opcodes.insert(id, limit);
+        self
+    }
+
+    pub(crate) fn limiter(&self) -> RateLimiter<'_> {
+        RateLimiter {
+            limits: self,
+            global: self.global.map(TokenBucket::new),
+            opcodes: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Option<Instant>,
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated: None,
+        }
+    }
+
+    /// Adds the tokens earned since the last update, and returns whether one is
+    /// available.
+    fn refill(&mut self, now: Instant) -> bool {
+        if let Some(updated) = self.updated {
+            let earned =
+                now.saturating_duration_since(updated).as_secs_f64() * self.limit.per_second;
+            self.tokens = (self.tokens + earned).min(self.limit.burst as f64);
+        }
+        self.updated = Some(now);
+        self.tokens >= 1.0
+    }
+}
+
+/// The buckets of a single session.
+pub(crate) struct RateLimiter<'a> {
+    limits: &'a RateLimits,
+    global: Option<TokenBucket>,
+    opcodes: HashMap<u32, TokenBucket>,
+}
+
+impl RateLimiter<'_> {
+    /// Counts a message, returning what to do with it if it exceeds a limit.
+    pub(crate) fn check(&mut self, opcode: u32) -> Option<LimitAction> {
+        self.check_at(opcode, Instant::now())
+    }
+
+    fn check_at(&mut self, opcode: u32, now: Instant) -> Option<LimitAction> {
+        let opcode_bucket = match self.limits.opcodes.get(&opcode) {
+            Some(limit) => Some(
+                self.opcodes
+                    .entry(opcode)
+                    .or_insert_with(|| TokenBucket::new(*limit)),
+            ),
+            None => None,
+        };
+        let buckets = [self.global.as_mut(), opcode_bucket];
+        let mut buckets: Vec<_> = buckets.into_iter().flatten().collect();
+
+        // the strictest action wins when both limits are exceeded
+        let exceeded = buckets
+            .iter_mut()
+            .filter_map(|bucket| match bucket.refill(now) {
+                true => None,
+                false => Some(bucket.limit.action),
+            })
+            .max();
+        if exceeded.is_none() {
+            for bucket in buckets {
+                bucket.tokens -= 1.0;
+            }
+        }
+        exceeded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_token_bucket() {
+        let mut limits = RateLimits::new();
+        limits.opcode(0x01c3, RateLimit::new(2, 1.0));
+        let mut limiter = limits.limiter();
+        let start = Instant::now();
+
+        assert_eq!(limiter.check_at(0x01c3, start), None);
+        assert_eq!(limiter.check_at(0x01c3, start), None);
+        assert_eq!(limiter.check_at(0x01c3, start), Some(LimitAction::Drop));
+        // other opcodes aren't limited
+        assert_eq!(limiter.check_at(0x01c4, start), None);
+
+        let later = start + Duration::from_millis(1500);
+        assert_eq!(limiter.check_at(0x01c3, later), None);
+        assert_eq!(limiter.check_at(0x01c3, later), Some(LimitAction::Drop));
+        // the bucket never holds more than the burst
+        let much_later = later + Duration::from_secs(60);
+        assert_eq!(limiter.check_at(0x01c3, much_later), None);
+        assert_eq!(limiter.check_at(0x01c3, much_later), None);
+        assert_eq!(
+            limiter.check_at(0x01c3, much_later),
+            Some(LimitAction::Drop)
+        );
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let mut limits = RateLimits::new();
+        limits
+            .global(RateLimit::new(3, 10.0).disconnect())
+            .opcode(0x01c3, RateLimit::new(1, 1.0));
+        let mut limiter = limits.limiter();
+        let start = Instant::now();
+
+        assert_eq!(limiter.check_at(0x01c3, start), None);
+        // dropped messages don't count towards the global limit
+        assert_eq!(limiter.check_at(0x01c3, start), Some(LimitAction::Drop));
+        assert_eq!(limiter.check_at(0x0001, start), None);
+        assert_eq!(limiter.check_at(0x0002, start), None);
+        assert_eq!(
+            limiter.check_at(0x0001, start),
+            Some(LimitAction::Disconnect)
+        );
+        assert_eq!(
+            limiter.check_at(0x01c3, start),
+            Some(LimitAction::Disconnect)
+        );
+    }
+}
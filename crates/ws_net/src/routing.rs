@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+
+use ws_messages::{AnyMessage, Message};
+
+use crate::{Handler, NetError, NetResult, Session};
+
+/// The future returned by a type-erased message handler.
+pub type HandlerFuture = Pin<Box<dyn Future<Output = NetResult> + Send>>;
+
+type BoxedHandler = Box<dyn Fn(Session, Box<dyn AnyMessage>) -> HandlerFuture + Send + Sync>;
+
+/// A handler for a single message type, as generated by `#[message_handler]`.
+#[derive(Clone, Copy)]
+pub struct MessageHandler {
+    /// Returns the opcode of the handled message.
+    pub id: fn() -> u32,
+    /// Handles a message, which must be of the handled type.
+    pub handle: fn(Session, Box<dyn AnyMessage>) -> HandlerFuture,
+}
+
+/// Routes decoded messages to the handler registered for their type.
+///
+/// Messages without a handler are ignored. A `HandlerMap` implements [`Handler`]
+/// so it can be given directly to a [`Server`](crate::Server).
+#[derive(Default)]
+pub struct HandlerMap {
+    handlers: HashMap<u32, BoxedHandler>,
+}
+
+impl HandlerMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an async function or closure handling messages of type `T`,
+    /// replacing any previous handler for that message.
+    pub fn register<T, F, Fut>(&mut self, handler: F) -> &mut Self
+    where
+        T: Message + AnyMessage,
+        F: Fn(Session, T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = NetResult> + Send + 'static,
+    {
+        let handler = move |session, message| -> HandlerFuture {
+            match downcast_message::<T>(message) {
+                Ok(message) => Box::pin(handler(session, message)),
+                Err(error) => Box::pin(async { Err(error) }),
+            }
+        };
+        self.handlers.insert(T::id(), Box::new(handler));
+        self
+    }
+
+    /// Adds a handler generated by `#[message_handler]`.
+    pub fn add(&mut self, handler: MessageHandler) -> &mut Self {
+        self.handlers
+            .insert((handler.id)(), Box::new(handler.handle));
+        self
+    }
+
+    pub fn contains(&self, id: u32) -> bool {
+        self.handlers.contains_key(&id)
+    }
+
+    /// Calls the handler of the message, if there is one.
+    pub async fn dispatch(&self, session: &Session, message: Box<dyn AnyMessage>) -> NetResult {
+        match self.handlers.get(&message.message_id()) {
+            Some(handler) => handler(session.clone(), message).await,
+            None => Ok(()),
+        }
+    }
+}
+
+impl Handler for HandlerMap {
+    async fn message(&self, session: &Session, message: Box<dyn AnyMessage>) -> NetResult {
+        self.dispatch(session, message).await
+    }
+}
+
+/// Converts a decoded message back to its concrete type.
+pub fn downcast_message<T: AnyMessage>(message: Box<dyn AnyMessage>) -> NetResult<T> {
+    message
+        .downcast::<T>()
+        .map(|message| *message)
+        .map_err(|message| NetError::UnexpectedMessage(message.message_name()))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::mpsc;
+    use ws_messages::*;
+    use ws_protocol::FrameDecoder;
+
+    use super::*;
+    use crate::message_handler;
+
+    #[derive(MessageStruct, Message, Debug, PartialEq)]
+    #[message_id(0x0124)]
+    struct Hello {
+        value: u32,
+    }
+
+    #[derive(MessageStruct, Message, Debug, PartialEq)]
+    #[message_id(0x0125)]
+    struct Goodbye {
+        value: u32,
+    }
+
+    #[message_handler]
+    async fn on_hello(session: &Session, message: Hello) -> NetResult {
+        session.send(&Goodbye {
+            value: message.value * 2,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let (outgoing, mut frames) = mpsc::unbounded_channel();
+        let session = Session::new(
+            1,
+            "127.0.0.1:1".parse().unwrap(),
+            outgoing,
+            Default::default(),
+            Default::default(),
+            None,
+        );
+
+        let mut handlers = HandlerMap::new();
+        handlers
+            .add(on_hello)
+            .register(|session: Session, message: Goodbye| async move {
+                session.send(&Hello {
+                    value: message.value + 1,
+                })
+            });
+        assert!(handlers.contains(0x0124));
+        assert!(handlers.contains(0x0125));
+
+        handlers
+            .dispatch(&session, Box::new(Hello { value: 21 }))
+            .await
+            .unwrap();
+        handlers
+            .dispatch(&session, Box::new(Goodbye { value: 1 }))
+            .await
+            .unwrap();
+
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&frames.recv().await.unwrap());
+        decoder.extend(&frames.recv().await.unwrap());
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame.reader().read::<Goodbye>().unwrap().value, 42);
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame.reader().read::<Hello>().unwrap().value, 2);
+    }
+}
@@ -0,0 +1,519 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::mpsc;
+use tracing::Instrument;
+use ws_messages::{short_type_name, AnyMessage, Message, MessageRegistry};
+use ws_metrics::metrics;
+use ws_protocol::{ClientPing, FrameDecoder, FrameEncoder};
+
+use crate::traffic::record_message;
+use crate::{
+    Direction, Handler, LimitAction, NetError, NetResult, PacketTap, RateLimits, SendLimits,
+    Session, SessionPolicy, TrafficFilter,
+};
+
+/// Accepts client connections and runs a read and a write task for each of them.
+pub struct Server {
+    listener: TcpListener,
+    registry: Arc<MessageRegistry>,
+    options: SessionOptions,
+    next_session_id: AtomicU64,
+}
+
+/// How the sessions of a server are run.
+#[derive(Default)]
+struct SessionOptions {
+    policy: Option<SessionPolicy>,
+    idle_timeout: Option<Duration>,
+    limits: Option<RateLimits>,
+    send_limits: SendLimits,
+    traffic: Arc<TrafficFilter>,
+    tap: Option<PacketTap>,
+}
+
+impl Server {
+    /// Binds a server that decodes messages using `registry`.
+    pub async fn bind<A: ToSocketAddrs>(addr: A, registry: MessageRegistry) -> NetResult<Self> {
+        Ok(Self {
+            listener: TcpListener::bind(addr).await?,
+            registry: Arc::new(registry),
+            options: SessionOptions::default(),
+            next_session_id: AtomicU64::new(1),
+        })
+    }
+
+    /// Closes sessions sending messages that `policy` doesn't allow in their
+    /// state. Without a policy, every message is handled.
+    pub fn set_policy(&mut self, policy: SessionPolicy) {
+        self.options.policy = Some(policy);
+    }
+
+    /// Closes sessions that send nothing for `timeout`, with
+    /// [`NetError::IdleTimeout`]. Clients keep their session alive with
+    /// [`ClientPing`], which the server answers on its own.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.options.idle_timeout = Some(timeout);
+    }
+
+    /// Drops the messages of sessions exceeding `limits`, or closes them with
+    /// [`NetError::RateLimited`], depending on the limit.
+    pub fn set_rate_limits(&mut self, limits: RateLimits) {
+        self.options.limits = Some(limits);
+    }
+
+    /// Replaces the default limits of what can be queued for a client that
+    /// doesn't keep up.
+    pub fn set_send_limits(&mut self, limits: SendLimits) {
+        self.options.send_limits = limits;
+    }
+
+    /// Mutes the spans of the messages that `filter` mutes, in both
+    /// directions. Without a filter, every message gets one.
+    pub fn set_traffic_filter(&mut self, filter: TrafficFilter) {
+        self.options.traffic = Arc::new(filter);
+    }
+
+    /// Copies the messages of every session to `tap`, and lets messages be
+    /// injected in the sessions through it.
+    pub fn set_packet_tap(&mut self, tap: PacketTap) {
+        self.options.tap = Some(tap);
+    }
+
+    pub fn local_addr(&self) -> NetResult<std::net::SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accepts connections forever, spawning the tasks of each session on the
+    /// current tokio runtime.
+    pub async fn run<H: Handler>(self, handler: Arc<H>) -> NetResult {
+        let options = Arc::new(self.options);
+        loop {
+            let (stream, _) = self.listener.accept().await?;
+            let id = self.next_session_id.fetch_add(1, Ordering::Relaxed);
+            tokio::spawn(run_session(
+                id,
+                stream,
+                self.registry.clone(),
+                options.clone(),
+                handler.clone(),
+            ));
+        }
+    }
+}
+
+async fn run_session<H: Handler>(
+    id: u64,
+    stream: TcpStream,
+    registry: Arc<MessageRegistry>,
+    options: Arc<SessionOptions>,
+    handler: Arc<H>,
+) {
+    let peer_addr = match stream.peer_addr() {
+        Ok(addr) => addr,
+        Err(_) => return,
+    };
+    let (read_half, write_half) = stream.into_split();
+    let (outgoing, outgoing_receiver) = mpsc::unbounded_channel();
+    let session = Session::new(
+        id,
+        peer_addr,
+        outgoing,
+        options.send_limits,
+        options.traffic.clone(),
+        options.tap.clone(),
+    );
+    if let Some(tap) = &options.tap {
+        tap.opened(&session);
+    }
+
+    let writer = tokio::spawn(write_frames(write_half, outgoing_receiver, session.clone()));
+
+    metrics().session_opened();
+    let result = match handler.connected(&session).await {
+        Ok(()) => read_frames(read_half, &session, &registry, &options, handler.as_ref()).await,
+        Err(error) => Err(error),
+    };
+
+    session.close();
+    if let Some(tap) = &options.tap {
+        tap.closed(&session);
+    }
+    // the writer may be stuck on a client that stopped reading
+    if session.is_saturated() {
+        writer.abort();
+    }
+    let _ = writer.await;
+    let result = match session.is_saturated() {
+        true => Err(NetError::Saturated),
+        false => result,
+    };
+    handler.disconnected(&session, result.err()).await;
+    metrics().session_closed();
+}
+
+/// What the read task of a session woke up for.
+enum ReadEvent {
+    Read(usize),
+    Injected(Box<dyn AnyMessage>),
+}
+
+async fn read_frames<H: Handler>(
+    mut stream: OwnedReadHalf,
+    session: &Session,
+    registry: &MessageRegistry,
+    options: &SessionOptions,
+    handler: &H,
+) -> NetResult {
+    let mut decoder = FrameDecoder::new();
+    let mut closed = session.closed_receiver();
+    let mut buf = vec![0u8; 4096];
+    let mut limiter = options.limits.as_ref().map(RateLimits::limiter);
+    let mut injected = session
+        .take_injected()
+        .expect("the session is already read");
+
+    loop {
+        let idle = async {
+            match options.idle_timeout {
+                Some(timeout) => {
+                    let deadline = session.last_seen() + timeout;
+                    tokio::time::sleep_until(deadline.into()).await
+                }
+                None => std::future::pending().await,
+            }
+        };
+        let event = tokio::select! {
+            read = stream.read(&mut buf) => ReadEvent::Read(read?),
+            Some(message) = injected.recv() => ReadEvent::Injected(message),
+            _ = closed.wait_for(|closed| *closed) => return Ok(()),
+            _ = idle => return Err(NetError::IdleTimeout),
+        };
+        let read = match event {
+            ReadEvent::Read(read) => read,
+            ReadEvent::Injected(message) => {
+                handle_injected(session, registry, options, handler, message).await?;
+                continue;
+            }
+        };
+        if read == 0 {
+            return Ok(());
+        }
+        session.touch();
+        decoder.extend(&buf[..read]);
+
+        loop {
+            // a handler may have enabled encryption for the frames that follow
+            if let Some(encryption) = session.take_incoming_encryption() {
+                decoder.set_encryption(encryption);
+            }
+            let Some(frame) = decoder.next_frame()? else {
+                break;
+            };
+            let opcode = frame.opcode as u32;
+            metrics().packet_in(opcode);
+            if let Some(tap) = session.tap().filter(|tap| tap.is_watched()) {
+                tap.record(session.id(), Direction::In, opcode, frame.data.as_slice());
+            }
+            let span = options
+                .traffic
+                .span(Direction::In, session.id(), opcode, frame.data.len());
+            if let Some(action) = limiter.as_mut().and_then(|l| l.check(opcode)) {
+                span.in_scope(|| tracing::debug!(?action, "rate limited"));
+                handler
+                    .rate_limited(session, opcode, action)
+                    .instrument(span)
+                    .await;
+                match action {
+                    LimitAction::Drop => continue,
+                    LimitAction::Disconnect => return Err(NetError::RateLimited { opcode }),
+                }
+            }
+            // pings are allowed in every state
+            if opcode == ClientPing::id() {
+                let _entered = span.enter();
+                let ping: ClientPing = frame
+                    .reader()
+                    .read()
+                    .inspect_err(|_| metrics().decode_failure(opcode))?;
+                record_message(&span, short_type_name::<ClientPing>(), &ping, || {
+                    frame.data.clone()
+                });
+                tracing::debug!("received");
+                session.send(&ping.pong())?;
+                continue;
+            }
+            if let Some(policy) = &options.policy {
+                let state = session.state();
+                if !policy.is_allowed(state, opcode) {
+                    return Err(NetError::MessageNotAllowed { opcode, state });
+                }
+            }
+            let Some(registration) = registry.get(opcode) else {
+                span.in_scope(|| tracing::debug!("received an unknown message"));
+                handler
+                    .unknown_message(session, frame)
+                    .instrument(span)
+                    .await?;
+                continue;
+            };
+            let message = span
+                .in_scope(|| (registration.read)(&mut frame.reader()))
+                .inspect_err(|_| metrics().decode_failure(opcode))?;
+            record_message(
+                &span,
+                registration.name,
+                registration.debug_message(&*message),
+                || frame.data.clone(),
+            );
+            span.in_scope(|| tracing::debug!("received"));
+            handler.message(session, message).instrument(span).await?;
+        }
+    }
+}
+
+async fn handle_injected<H: Handler>(
+    session: &Session,
+    registry: &MessageRegistry,
+    options: &SessionOptions,
+    handler: &H,
+    message: Box<dyn AnyMessage>,
+) -> NetResult {
+    let opcode = message.message_id();
+    let span = options.traffic.span(Direction::In, session.id(), opcode, 0);
+    if let Some(policy) = &options.policy {
+        if !policy.is_allowed(session.state(), opcode) {
+            span.in_scope(|| tracing::debug!("dropped an injected message"));
+            return Ok(());
+        }
+    }
+    match registry.get(opcode) {
+        Some(registration) => record_message(
+            &span,
+            registration.name,
+            registration.debug_message(&*message),
+            Vec::new,
+        ),
+        None => record_message(&span, message.message_name(), &*message, Vec::new),
+    }
+    span.in_scope(|| tracing::debug!("injected"));
+    if let Some(tap) = session.tap().filter(|tap| tap.is_watched()) {
+        let packet = FrameEncoder::new().encode(opcode as u16, |w| message.write_message(w))?;
+        tap.record(session.id(), Direction::In, opcode, packet);
+    }
+    handler.message(session, message).instrument(span).await
+}
+
+async fn write_frames(
+    mut stream: OwnedWriteHalf,
+    mut frames: mpsc::UnboundedReceiver<Vec<u8>>,
+    session: Session,
+) -> NetResult {
+    let mut closed = session.closed_receiver();
+    loop {
+        let frame = tokio::select! {
+            biased;
+            frame = frames.recv() => frame,
+            _ = closed.wait_for(|closed| *closed) => None,
+        };
+        match frame {
+            Some(frame) => {
+                stream.write_all(&frame).await?;
+                session.written(frame.len());
+            }
+            None => break,
+        }
+    }
+
+    // send whatever was queued before the session was closed, unless the
+    // client doesn't read it anyway
+    while let Ok(frame) = frames.try_recv() {
+        if session.is_saturated() {
+            break;
+        }
+        stream.write_all(&frame).await?;
+        session.written(frame.len());
+    }
+
+    stream.shutdown().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use ws_messages::*;
+    use ws_protocol::FrameEncoder;
+
+    use super::*;
+    use crate::{phase, SessionState};
+
+    #[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+    #[message_id(0x0123)]
+    struct Echo {
+        value: u32,
+    }
+
+    #[derive(Default)]
+    struct EchoHandler {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl Handler for EchoHandler {
+        async fn connected(&self, session: &Session) -> NetResult {
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("connected {}", session.id()));
+            Ok(())
+        }
+
+        async fn message(&self, session: &Session, message: Box<dyn AnyMessage>) -> NetResult {
+            let echo = message.downcast::<Echo>().unwrap();
+            session.send(&Echo {
+                value: echo.value + 1,
+            })
+        }
+
+        async fn disconnected(&self, session: &Session, error: Option<NetError>) {
+            assert!(error.is_none());
+            let event = format!("disconnected {}", session.id());
+            self.events.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_echo_session() {
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let handler = Arc::new(EchoHandler::default());
+        tokio::spawn(server.run(handler.clone()));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let frame = FrameEncoder::new()
+            .encode_value(0x0123, &Echo { value: 41 })
+            .unwrap();
+        // send the frame in two parts to exercise the decoder buffering
+        client.write_all(&frame[..3]).await.unwrap();
+        client.flush().await.unwrap();
+        client.write_all(&frame[3..]).await.unwrap();
+
+        let mut response = vec![0u8; frame.len()];
+        client.read_exact(&mut response).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&response);
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame.opcode, 0x0123);
+        assert_eq!(frame.reader().read::<Echo>().unwrap(), Echo { value: 42 });
+
+        drop(client);
+        while handler.events.lock().unwrap().len() < 2 {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            *handler.events.lock().unwrap(),
+            ["connected 1", "disconnected 1"]
+        );
+    }
+
+    /// Authenticates sessions on their first message and records why they
+    /// were closed.
+    #[derive(Default)]
+    struct LoginHandler {
+        errors: Mutex<Vec<String>>,
+    }
+
+    impl Handler for LoginHandler {
+        async fn message(&self, session: &Session, _message: Box<dyn AnyMessage>) -> NetResult {
+            match session.in_state::<phase::Connected>() {
+                Some(session) => {
+                    session.authenticate()?;
+                    Ok(())
+                }
+                None => session.send(&Echo { value: 0 }),
+            }
+        }
+
+        async fn disconnected(&self, _session: &Session, error: Option<NetError>) {
+            let error = error.map_or_else(String::new, |error| error.to_string());
+            self.errors.lock().unwrap().push(error);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keep_alive() {
+        let mut server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        server.set_idle_timeout(Duration::from_millis(150));
+        let addr = server.local_addr().unwrap();
+        let handler = Arc::new(LoginHandler::default());
+        tokio::spawn(server.run(handler.clone()));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        for timestamp in [1000, 1060] {
+            tokio::time::sleep(Duration::from_millis(90)).await;
+            let frame = ws_protocol::encode_message(&ClientPing { timestamp }).unwrap();
+            client.write_all(&frame).await.unwrap();
+
+            let mut buf = [0u8; 16];
+            let read = client.read(&mut buf).await.unwrap();
+            decoder.extend(&buf[..read]);
+            let frame = decoder.next_frame().unwrap().unwrap();
+            let pong: ws_protocol::ServerPong = frame.reader().read().unwrap();
+            assert_eq!(pong.timestamp, timestamp);
+        }
+        assert!(handler.errors.lock().unwrap().is_empty());
+
+        // the session is closed once the client stops pinging
+        let mut buf = [0u8; 16];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+        while handler.errors.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            *handler.errors.lock().unwrap(),
+            ["the session was idle for too long"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_session_policy() {
+        let mut server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let mut policy = SessionPolicy::new();
+        policy.allow::<Echo>(&[SessionState::Connected]);
+        server.set_policy(policy);
+        let addr = server.local_addr().unwrap();
+        let handler = Arc::new(LoginHandler::default());
+        tokio::spawn(server.run(handler.clone()));
+
+        // the first echo authenticates, and echoes aren't allowed afterwards
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut encoder = FrameEncoder::new();
+        for _ in 0..2 {
+            let frame = encoder.encode_value(0x0123, &Echo { value: 1 }).unwrap();
+            client.write_all(&frame).await.unwrap();
+        }
+        let mut buf = [0u8; 16];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+
+        while handler.errors.lock().unwrap().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        assert_eq!(
+            *handler.errors.lock().unwrap(),
+            ["message 0x0123 isn't allowed while authenticated"]
+        );
+    }
+}
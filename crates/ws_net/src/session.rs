@@ -0,0 +1,516 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use tokio::sync::{mpsc, watch};
+use ws_bitpack::{BitPackWriter, WriteValue};
+use ws_messages::{short_type_name, AnyMessage, Message};
+use ws_metrics::metrics;
+use ws_protocol::{Encryption, FrameEncoder};
+
+use crate::phase::{Authed, BeforeAuth, Connected, Encrypted, InWorld, Phase};
+use crate::traffic::record_message;
+use crate::{Direction, NetError, NetResult, PacketTap, SendLimits, SessionState, TrafficFilter};
+
+/// Identifies a session for the lifetime of a server.
+pub type SessionId = u64;
+
+/// A handle to a connected client.
+///
+/// Handles are cheap to clone and can be kept around to send messages to the
+/// client from anywhere, until the session is closed.
+#[derive(Clone)]
+pub struct Session {
+    inner: Arc<SessionInner>,
+}
+
+struct SessionInner {
+    id: SessionId,
+    peer_addr: SocketAddr,
+    outgoing: mpsc::UnboundedSender<Vec<u8>>,
+    send_limits: SendLimits,
+    /// The bytes of the frames queued and not written yet.
+    queued: AtomicUsize,
+    /// When the queue went over the saturation limit, while it is.
+    saturated_since: Mutex<Option<Instant>>,
+    /// Set when the session is closed for being saturated.
+    saturated: AtomicBool,
+    closed: watch::Sender<bool>,
+    state: Mutex<SessionState>,
+    traffic: Arc<TrafficFilter>,
+    tap: Option<PacketTap>,
+    /// Messages handled as if the client had sent them, see
+    /// [`Session::inject`].
+    injected: mpsc::UnboundedSender<Box<dyn AnyMessage>>,
+    /// Taken by the read task.
+    injected_receiver: Mutex<Option<mpsc::UnboundedReceiver<Box<dyn AnyMessage>>>>,
+    /// When data was last received from the client.
+    last_seen: Mutex<Instant>,
+    /// Locked while a frame is encoded and queued so that frames are queued in
+    /// the order they were encrypted in.
+    encoder: Mutex<FrameEncoder>,
+    /// Encryption waiting to be picked up by the read task.
+    incoming_encryption: Mutex<Option<Box<dyn Encryption>>>,
+}
+
+impl Session {
+    pub(crate) fn new(
+        id: SessionId,
+        peer_addr: SocketAddr,
+        outgoing: mpsc::UnboundedSender<Vec<u8>>,
+        send_limits: SendLimits,
+        traffic: Arc<TrafficFilter>,
+        tap: Option<PacketTap>,
+    ) -> Self {
+        let (closed, _) = watch::channel(false);
+        let (injected, injected_receiver) = mpsc::unbounded_channel();
+        Self {
+            inner: Arc::new(SessionInner {
+                id,
+                peer_addr,
+                outgoing,
+                send_limits,
+                queued: AtomicUsize::new(0),
+                saturated_since: Mutex::new(None),
+                saturated: AtomicBool::new(false),
+                closed,
+                state: Mutex::new(SessionState::Connected),
+                traffic,
+                tap,
+                injected,
+                injected_receiver: Mutex::new(Some(injected_receiver)),
+                last_seen: Mutex::new(Instant::now()),
+                encoder: Mutex::new(FrameEncoder::new()),
+                incoming_encryption: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Creates a session that no client is connected to, whose frames are
+    /// returned by the receiver instead of being sent. This lets a handler be
+    /// driven without a socket, like when replaying the messages of a
+    /// recording.
+    pub fn detached(
+        id: SessionId,
+        peer_addr: SocketAddr,
+    ) -> (Self, mpsc::UnboundedReceiver<Vec<u8>>) {
+        let (outgoing, frames) = mpsc::unbounded_channel();
+        let limits = SendLimits::UNLIMITED;
+        let session = Self::new(id, peer_addr, outgoing, limits, Arc::default(), None);
+        (session, frames)
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.inner.id
+    }
+
+    pub fn peer_addr(&self) -> SocketAddr {
+        self.inner.peer_addr
+    }
+
+    /// Returns when data was last received from the client, or when it
+    /// connected if it sent nothing yet.
+    pub fn last_seen(&self) -> Instant {
+        *self.inner.last_seen.lock().unwrap()
+    }
+
+    pub(crate) fn touch(&self) {
+        *self.inner.last_seen.lock().unwrap() = Instant::now();
+    }
+
+    /// Encodes a message in a frame and queues it to be sent.
+    ///
+    /// The message gets a span unless the [`TrafficFilter`] of the server
+    /// mutes it.
+    pub fn send<T>(&self, message: &T) -> NetResult
+    where
+        T: Message + WriteValue + fmt::Debug,
+    {
+        let mut encoder = self.inner.encoder.lock().unwrap();
+        let frame = encoder.encode_message(message)?;
+        let span = self
+            .inner
+            .traffic
+            .span(Direction::Out, self.id(), T::id(), frame.len());
+        let _entered = span.enter();
+        record_message(&span, short_type_name::<T>(), message, || {
+            // the frame may be encrypted already
+            ws_protocol::encode_message(message).unwrap_or_default()
+        });
+        tracing::debug!("sent");
+        self.send_frame(frame)?;
+        metrics().packet_out(T::id());
+        if let Some(tap) = self.inner.tap.as_ref().filter(|tap| tap.is_watched()) {
+            // the frame may be encrypted already
+            if let Ok(packet) = ws_protocol::encode_message(message) {
+                tap.record(self.id(), Direction::Out, T::id(), packet);
+            }
+        }
+        Ok(())
+    }
+
+    /// Encodes a message body that was already written, like one crafted from
+    /// a debugging tool, in a frame and queues it to be sent.
+    pub fn send_body(&self, opcode: u32, body: &[u8]) -> NetResult {
+        let opcode = u16::try_from(opcode).map_err(|_| NetError::InvalidOpcode(opcode))?;
+        let write_body = |writer: &mut BitPackWriter| writer.write_bytes(body);
+        let mut encoder = self.inner.encoder.lock().unwrap();
+        let frame = encoder.encode(opcode, write_body)?;
+        let opcode = opcode as u32;
+        let span = self
+            .inner
+            .traffic
+            .span(Direction::Out, self.id(), opcode, frame.len());
+        span.in_scope(|| tracing::debug!("sent a crafted message"));
+        self.send_frame(frame)?;
+        metrics().packet_out(opcode);
+        if let Some(tap) = self.inner.tap.as_ref().filter(|tap| tap.is_watched()) {
+            let packet = FrameEncoder::new().encode(opcode as u16, write_body)?;
+            tap.record(self.id(), Direction::Out, opcode, packet);
+        }
+        Ok(())
+    }
+
+    /// Hands a message to the handler of the session as if the client had
+    /// sent it, after the messages being handled. The session policy still
+    /// applies, and messages it doesn't allow are dropped.
+    pub fn inject(&self, message: Box<dyn AnyMessage>) -> NetResult {
+        if self.is_closed() {
+            return Err(NetError::SessionClosed);
+        }
+        self.inner
+            .injected
+            .send(message)
+            .map_err(|_| NetError::SessionClosed)
+    }
+
+    pub(crate) fn take_injected(&self) -> Option<mpsc::UnboundedReceiver<Box<dyn AnyMessage>>> {
+        self.inner.injected_receiver.lock().unwrap().take()
+    }
+
+    pub(crate) fn tap(&self) -> Option<&PacketTap> {
+        self.inner.tap.as_ref()
+    }
+
+    /// Queues an already encoded frame to be sent as is, without encrypting it.
+    /// Frames sent this way get no span.
+    ///
+    /// Refuses the frame with [`NetError::QueueFull`] if it would take the
+    /// queue past its saturation limit, and closes the session with
+    /// [`NetError::Saturated`] instead if it kept refusing frames for too long.
+    pub fn send_frame(&self, frame: Vec<u8>) -> NetResult {
+        if self.is_closed() {
+            return Err(NetError::SessionClosed);
+        }
+        let len = frame.len();
+        self.reserve(len)?;
+        self.inner.outgoing.send(frame).map_err(|_| {
+            self.inner.queued.fetch_sub(len, Ordering::Relaxed);
+            NetError::SessionClosed
+        })
+    }
+
+    /// Returns the bytes queued for the client and not written yet.
+    pub fn queued_bytes(&self) -> usize {
+        self.inner.queued.load(Ordering::Relaxed)
+    }
+
+    /// Returns true while more is queued than the backed up limit of the
+    /// [`SendLimits`], so that updates superseded by later ones can be held
+    /// back and merged instead of queued.
+    pub fn is_backed_up(&self) -> bool {
+        self.queued_bytes() >= self.inner.send_limits.backed_up_bytes
+    }
+
+    /// Returns true if the session was closed for being saturated.
+    pub fn is_saturated(&self) -> bool {
+        self.inner.saturated.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn written(&self, bytes: usize) {
+        self.inner.queued.fetch_sub(bytes, Ordering::Relaxed);
+    }
+
+    /// Counts `len` more bytes as queued, unless that would take the queue
+    /// past the saturation limit.
+    fn reserve(&self, len: usize) -> NetResult {
+        let limits = &self.inner.send_limits;
+        // checked and added at once, so that concurrent senders can't go over
+        let fits = |queued: usize| {
+            queued
+                .checked_add(len)
+                .filter(|queued| *queued <= limits.saturated_bytes)
+        };
+        let reserved = self
+            .inner
+            .queued
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, fits);
+        let mut since = self.inner.saturated_since.lock().unwrap();
+        let queued = match reserved {
+            Ok(_) => {
+                *since = None;
+                return Ok(());
+            }
+            Err(queued) => queued,
+        };
+        let since = *since.get_or_insert_with(Instant::now);
+        if since.elapsed() < limits.saturated_timeout {
+            return Err(NetError::QueueFull);
+        }
+        tracing::warn!(
+            session = self.id(),
+            "Closing a session whose client doesn't keep up, {queued} bytes are queued"
+        );
+        self.inner.saturated.store(true, Ordering::Relaxed);
+        self.close();
+        Err(NetError::Saturated)
+    }
+
+    pub fn state(&self) -> SessionState {
+        *self.inner.state.lock().unwrap()
+    }
+
+    /// Returns a handle to move the session out of `S`, if it is in `S`.
+    pub fn in_state<S: Phase>(&self) -> Option<SessionIn<S>> {
+        (self.state() == S::STATE).then(|| SessionIn {
+            session: self.clone(),
+            state: PhantomData,
+        })
+    }
+
+    pub(crate) fn take_incoming_encryption(&self) -> Option<Box<dyn Encryption>> {
+        self.inner.incoming_encryption.lock().unwrap().take()
+    }
+
+    /// Closes the session once the frames queued so far are sent, unless it
+    /// is saturated.
+    pub fn close(&self) {
+        self.inner.closed.send_replace(true);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        *self.inner.closed.borrow()
+    }
+
+    pub(crate) fn closed_receiver(&self) -> watch::Receiver<bool> {
+        self.inner.closed.subscribe()
+    }
+}
+
+/// A handle to a session in the state `S`, which is the only way to change
+/// the state of a session.
+///
+/// Every transition consumes the handle and returns the one of the next
+/// state, so a session can't be moved to a state it can't go to:
+///
+/// ```compile_fail
+/// use ws_net::phase::Connected;
+/// use ws_net::SessionIn;
+///
+/// fn select_character(session: SessionIn<Connected>) {
+///     session.enter_world();
+/// }
+/// ```
+///
+/// Handles are taken with [`Session::in_state`], and deref to the session.
+/// Another handle may move the session while this one is held, so the
+/// transitions check that it is still in `S` and fail with
+/// [`NetError::StateChanged`] otherwise.
+#[derive(Debug)]
+pub struct SessionIn<S: Phase> {
+    session: Session,
+    state: PhantomData<S>,
+}
+
+impl<S: Phase> SessionIn<S> {
+    fn moved<T: Phase>(self) -> NetResult<SessionIn<T>> {
+        self.moved_with(|_| ())
+    }
+
+    /// Runs `f` and moves the session to `T`, with the state locked so that
+    /// nothing else moves it in between.
+    fn moved_with<T: Phase>(self, f: impl FnOnce(&Session)) -> NetResult<SessionIn<T>> {
+        let mut state = self.session.inner.state.lock().unwrap();
+        if *state != S::STATE {
+            return Err(NetError::StateChanged {
+                expected: S::STATE,
+                actual: *state,
+            });
+        }
+        f(&self.session);
+        *state = T::STATE;
+        drop(state);
+        Ok(SessionIn {
+            session: self.session,
+            state: PhantomData,
+        })
+    }
+}
+
+impl SessionIn<Connected> {
+    /// Encrypts the frames sent and decrypts the frames received from now on.
+    ///
+    /// This is meant to be called from a message handler once the handshake
+    /// is done. Frames received after the one being handled are decrypted,
+    /// and messages sent after this call are encrypted. Both directions start
+    /// with their own copy of `encryption`.
+    pub fn set_encryption<E>(self, encryption: E) -> NetResult<SessionIn<Encrypted>>
+    where
+        E: Encryption + Clone,
+    {
+        self.moved_with(|session| {
+            let mut encoder = session.inner.encoder.lock().unwrap();
+            encoder.set_encryption(encryption.clone());
+            *session.inner.incoming_encryption.lock().unwrap() = Some(Box::new(encryption));
+        })
+    }
+}
+
+impl<S: BeforeAuth> SessionIn<S> {
+    /// Marks the session as logged in.
+    pub fn authenticate(self) -> NetResult<SessionIn<Authed>> {
+        self.moved()
+    }
+}
+
+impl SessionIn<Authed> {
+    pub fn enter_world(self) -> NetResult<SessionIn<InWorld>> {
+        self.moved()
+    }
+}
+
+impl SessionIn<InWorld> {
+    /// Moves the session back from the world, like when going back to the
+    /// character selection.
+    pub fn leave_world(self) -> NetResult<SessionIn<Authed>> {
+        self.moved()
+    }
+}
+
+impl<S: Phase> Deref for SessionIn<S> {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("id", &self.inner.id)
+            .field("peer_addr", &self.inner.peer_addr)
+            .field("state", &self.state())
+            .field("closed", &self.is_closed())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_state_transitions() {
+        let (outgoing, _frames) = mpsc::unbounded_channel();
+        let session = Session::new(
+            1,
+            "127.0.0.1:1".parse().unwrap(),
+            outgoing,
+            SendLimits::default(),
+            Arc::default(),
+            None,
+        );
+        assert_eq!(session.state(), SessionState::Connected);
+        assert!(session.in_state::<Authed>().is_none());
+
+        let session = session.in_state::<Connected>().unwrap();
+        let session = session
+            .set_encryption(ws_protocol::Arc4Encryption::new(b"key"))
+            .unwrap();
+        assert_eq!(session.state(), SessionState::Encrypted);
+        assert!(session.take_incoming_encryption().is_some());
+        let session = session.authenticate().unwrap();
+        assert_eq!(session.state(), SessionState::Authed);
+        assert!(session.in_state::<Connected>().is_none());
+        let session = session.enter_world().unwrap();
+        assert_eq!(session.state(), SessionState::InWorld);
+        let session = session.leave_world().unwrap();
+        assert_eq!(session.state(), SessionState::Authed);
+
+        // a handle doesn't move a session that another one moved since
+        let stale = session.in_state::<Authed>().unwrap();
+        session.enter_world().unwrap();
+        assert!(matches!(
+            stale.enter_world(),
+            Err(NetError::StateChanged {
+                expected: SessionState::Authed,
+                actual: SessionState::InWorld,
+            })
+        ));
+    }
+
+    #[test]
+    fn test_send_limits() {
+        let (outgoing, mut frames) = mpsc::unbounded_channel();
+        let limits = SendLimits {
+            backed_up_bytes: 8,
+            saturated_bytes: 16,
+            saturated_timeout: Duration::ZERO,
+        };
+        let peer_addr = "127.0.0.1:1".parse().unwrap();
+        let session = Session::new(1, peer_addr, outgoing, limits, Arc::default(), None);
+
+        session.send_frame(vec![0; 6]).unwrap();
+        assert!(!session.is_backed_up());
+        session.send_frame(vec![0; 6]).unwrap();
+        assert_eq!(session.queued_bytes(), 12);
+        assert!(session.is_backed_up());
+        session.written(frames.try_recv().unwrap().len());
+        assert!(!session.is_backed_up());
+
+        session.send_frame(vec![0; 10]).unwrap();
+        assert!(!session.is_saturated());
+        assert!(matches!(
+            session.send_frame(vec![0; 6]),
+            Err(NetError::Saturated)
+        ));
+        assert!(session.is_saturated());
+        assert!(session.is_closed());
+    }
+
+    #[test]
+    fn test_send_queue_ceiling() {
+        let (outgoing, mut frames) = mpsc::unbounded_channel();
+        let limits = SendLimits {
+            backed_up_bytes: 8,
+            saturated_bytes: 16,
+            saturated_timeout: Duration::from_secs(60),
+        };
+        let peer_addr = "127.0.0.1:1".parse().unwrap();
+        let session = Session::new(1, peer_addr, outgoing, limits, Arc::default(), None);
+
+        // frames that don't fit are refused until the client reads
+        session.send_frame(vec![0; 10]).unwrap();
+        for _ in 0..100 {
+            assert!(matches!(
+                session.send_frame(vec![0; 7]),
+                Err(NetError::QueueFull)
+            ));
+        }
+        session.send_frame(vec![0; 6]).unwrap();
+        assert_eq!(session.queued_bytes(), 16);
+        assert!(!session.is_closed());
+
+        session.written(frames.try_recv().unwrap().len());
+        session.send_frame(vec![0; 7]).unwrap();
+        assert_eq!(session.queued_bytes(), 13);
+        assert!(!session.is_saturated());
+    }
+}
@@ -0,0 +1,160 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use ws_messages::Message;
+
+/// The phases a session goes through, in order.
+///
+/// Sessions start `Connected` and only move through the methods of
+/// [`SessionIn`](crate::SessionIn), whose state is a type of the [`phase`]
+/// module, so that moving from a state to one it can't go to doesn't compile.
+/// Encryption is optional, authenticating straight from `Connected` is
+/// allowed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum SessionState {
+    /// Nothing happened yet.
+    Connected,
+    /// The frames are encrypted.
+    Encrypted,
+    /// The client logged in with an account.
+    Authed,
+    /// The client entered the world with a character.
+    InWorld,
+}
+
+impl SessionState {
+    pub const ALL: [SessionState; 4] = [
+        SessionState::Connected,
+        SessionState::Encrypted,
+        SessionState::Authed,
+        SessionState::InWorld,
+    ];
+}
+
+impl fmt::Display for SessionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Connected => "connected",
+            Self::Encrypted => "encrypted",
+            Self::Authed => "authenticated",
+            Self::InWorld => "in world",
+        };
+        f.write_str(name)
+    }
+}
+
+/// The states of [`SessionState`] as types, for
+/// [`SessionIn`](crate::SessionIn).
+pub mod phase {
+    use super::SessionState;
+
+    mod sealed {
+        pub trait Sealed {}
+    }
+
+    /// A state that a [`SessionIn`](crate::SessionIn) can be in.
+    pub trait Phase: sealed::Sealed {
+        const STATE: SessionState;
+    }
+
+    /// The states that a session can authenticate from.
+    pub trait BeforeAuth: Phase {}
+
+    #[derive(Debug)]
+    pub struct Connected;
+
+    #[derive(Debug)]
+    pub struct Encrypted;
+
+    #[derive(Debug)]
+    pub struct Authed;
+
+    #[derive(Debug)]
+    pub struct InWorld;
+
+    impl sealed::Sealed for Connected {}
+    impl sealed::Sealed for Encrypted {}
+    impl sealed::Sealed for Authed {}
+    impl sealed::Sealed for InWorld {}
+
+    impl Phase for Connected {
+        const STATE: SessionState = SessionState::Connected;
+    }
+
+    impl Phase for Encrypted {
+        const STATE: SessionState = SessionState::Encrypted;
+    }
+
+    impl Phase for Authed {
+        const STATE: SessionState = SessionState::Authed;
+    }
+
+    impl Phase for InWorld {
+        const STATE: SessionState = SessionState::InWorld;
+    }
+
+    impl BeforeAuth for Connected {}
+    impl BeforeAuth for Encrypted {}
+}
+
+/// The messages that sessions may send in each state.
+///
+/// A [`Server`](crate::Server) given a policy closes sessions that send
+/// anything else, before the message is even decoded. This keeps world
+/// messages from being handled before the client logged in, whatever the
+/// handlers check.
+#[derive(Debug, Clone, Default)]
+pub struct SessionPolicy {
+    allowed: HashMap<SessionState, HashSet<u32>>,
+}
+
+impl SessionPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows messages of type `T` in the given states.
+    pub fn allow<T: Message>(&mut self, states: &[SessionState]) -> &mut Self {
+        self.allow_id(T::id(), states)
+    }
+
+    /// Allows messages with opcode `id` in the given states.
+    pub fn allow_id(&mut self, id: u32, states: &[SessionState]) -> &mut Self {
+        for state in states {
+            self.allowed.entry(*state).or_default().insert(id);
+        }
+        self
+    }
+
+    pub fn is_allowed(&self, state: SessionState, id: u32) -> bool {
+        self.allowed
+            .get(&state)
+            .is_some_and(|allowed| allowed.contains(&id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_messages::*;
+
+    use super::*;
+
+    #[derive(MessageStruct, Message, Debug)]
+    #[message_id(0x0130)]
+    struct Login {}
+
+    #[test]
+    fn test_session_policy() {
+        let mut policy = SessionPolicy::new();
+        policy
+            .allow::<Login>(&[SessionState::Connected, SessionState::Encrypted])
+            .allow_id(0x0131, &[SessionState::InWorld]);
+
+        assert!(policy.is_allowed(SessionState::Connected, 0x0130));
+        assert!(policy.is_allowed(SessionState::Encrypted, 0x0130));
+        assert!(!policy.is_allowed(SessionState::Authed, 0x0130));
+        assert!(!policy.is_allowed(SessionState::Connected, 0x0131));
+        assert!(policy.is_allowed(SessionState::InWorld, 0x0131));
+        assert!(!SessionPolicy::new().is_allowed(SessionState::Connected, 0x0130));
+    }
+}
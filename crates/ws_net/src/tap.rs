@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::broadcast;
+use ws_bitpack::BitPackReader;
+use ws_protocol::PacketHeader;
+
+use crate::{Direction, Session, SessionId};
+
+/// A message received or sent by a session, as seen by a [`PacketTap`].
+#[derive(Debug, Clone)]
+pub struct TappedPacket {
+    pub session: SessionId,
+    pub direction: Direction,
+    pub opcode: u32,
+    /// The plaintext packet, header included.
+    pub packet: Arc<[u8]>,
+}
+
+impl TappedPacket {
+    /// Returns a reader positioned at the start of the message body.
+    pub fn reader(&self) -> BitPackReader<'_> {
+        BitPackReader::with_position(&self.packet, PacketHeader::BITS)
+    }
+}
+
+/// Copies the messages of the sessions of a [`Server`](crate::Server) to its
+/// subscribers, and keeps the open sessions so that messages can be injected
+/// in them.
+///
+/// Handles are cheap to clone. Subscribers that fall behind miss the oldest
+/// packets instead of slowing the sessions down.
+#[derive(Debug, Clone)]
+pub struct PacketTap {
+    packets: broadcast::Sender<TappedPacket>,
+    sessions: Arc<Mutex<HashMap<SessionId, Session>>>,
+}
+
+impl PacketTap {
+    /// Creates a tap keeping up to `capacity` packets for each subscriber.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            packets: broadcast::channel(capacity).0,
+            sessions: Arc::default(),
+        }
+    }
+
+    /// Receives the packets tapped from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<TappedPacket> {
+        self.packets.subscribe()
+    }
+
+    /// Returns the open session with this id.
+    pub fn session(&self, id: SessionId) -> Option<Session> {
+        self.sessions.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Returns the open sessions, in no particular order.
+    pub fn sessions(&self) -> Vec<Session> {
+        self.sessions.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Returns whether anyone would receive a packet, which lets the sessions
+    /// skip copying their bodies otherwise.
+    pub(crate) fn is_watched(&self) -> bool {
+        self.packets.receiver_count() > 0
+    }
+
+    pub(crate) fn record(
+        &self,
+        session: SessionId,
+        direction: Direction,
+        opcode: u32,
+        packet: impl Into<Arc<[u8]>>,
+    ) {
+        // nobody may be subscribed
+        let _ = self.packets.send(TappedPacket {
+            session,
+            direction,
+            opcode,
+            packet: packet.into(),
+        });
+    }
+
+    pub(crate) fn opened(&self, session: &Session) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(session.id(), session.clone());
+    }
+
+    pub(crate) fn closed(&self, session: &Session) {
+        self.sessions.lock().unwrap().remove(&session.id());
+    }
+}
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use tracing::field::Empty;
+use tracing::Span;
+use ws_messages::Message;
+
+use crate::SessionId;
+
+/// Which messages get a span when they are received or sent.
+///
+/// Every message gets a `DEBUG` span named `message` with its direction,
+/// opcode, size and session, except the muted ones. This keeps frequent
+/// messages like movement updates from drowning the others.
+#[derive(Debug, Clone, Default)]
+pub struct TrafficFilter {
+    muted: HashSet<u32>,
+}
+
+impl TrafficFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Mutes the messages of type `T`.
+    pub fn mute<T: Message>(&mut self) -> &mut Self {
+        self.mute_id(T::id())
+    }
+
+    /// Mutes the messages with opcode `id`.
+    pub fn mute_id(&mut self, id: u32) -> &mut Self {
+        self.muted.insert(id);
+        self
+    }
+
+    pub fn is_muted(&self, id: u32) -> bool {
+        self.muted.contains(&id)
+    }
+
+    /// Returns the span of a message, which is disabled if the message is
+    /// muted.
+    ///
+    /// The `name` and `body` fields are empty until the message is decoded.
+    pub(crate) fn span(
+        &self,
+        direction: Direction,
+        session: SessionId,
+        opcode: u32,
+        size: usize,
+    ) -> Span {
+        if self.is_muted(opcode) {
+            return Span::none();
+        }
+        tracing::debug_span!(
+            "message",
+            %direction,
+            session,
+            opcode = format_args!("0x{opcode:04x}"),
+            size,
+            name = Empty,
+            body = Empty,
+        )
+    }
+}
+
+/// Whether a message was received or sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::In => "in",
+            Self::Out => "out",
+        })
+    }
+}
+
+/// Records the name of a message in its span, and its body as JSON with the
+/// `json-bodies` feature.
+///
+/// `packet` returns the plaintext packet, header included, and is only called
+/// for the JSON.
+pub(crate) fn record_message<F>(span: &Span, name: &str, body: impl fmt::Debug, packet: F)
+where
+    F: FnOnce() -> Vec<u8>,
+{
+    if span.is_disabled() {
+        return;
+    }
+    span.record("name", name);
+    #[cfg(feature = "json-bodies")]
+    span.record("body", body_json(body, &packet()).as_str());
+    #[cfg(not(feature = "json-bodies"))]
+    let _ = (body, packet);
+}
+
+/// Formats a message body as a JSON object with the message formatted by its
+/// `Debug` impl and its packet in hex.
+#[cfg(feature = "json-bodies")]
+fn body_json(body: impl fmt::Debug, packet: &[u8]) -> String {
+    let packet: String = packet.iter().map(|byte| format!("{byte:02x}")).collect();
+    serde_json::json!({
+        "message": format!("{body:?}"),
+        "packet": packet,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_filter() {
+        let mut filter = TrafficFilter::new();
+        filter.mute::<ws_protocol::ClientPing>().mute_id(0x0640);
+
+        assert!(filter.is_muted(0x0241));
+        assert!(filter.is_muted(0x0640));
+        assert!(!filter.is_muted(0x0242));
+        assert!(filter.span(Direction::In, 1, 0x0640, 12).is_disabled());
+    }
+
+    #[cfg(feature = "json-bodies")]
+    #[test]
+    fn test_body_json() {
+        let json = body_json(ws_protocol::ServerPong { timestamp: 3 }, &[0x03, 0xff]);
+        assert_eq!(
+            json,
+            r#"{"message":"ServerPong { timestamp: 3 }","packet":"03ff"}"#
+        );
+    }
+}
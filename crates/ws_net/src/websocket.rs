@@ -0,0 +1,200 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use sha1::{Digest, Sha1};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::{NetError, NetResult};
+
+/// The longest request head read before upgrading the connection.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// The largest message a client may send, fragments included.
+const MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+
+/// Appended to the key of the client to get the accept key of the server.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_CONTINUATION: u8 = 0x0;
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_BINARY: u8 = 0x2;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xa;
+
+/// The server side of a WebSocket connection, enough for the debugging tools
+/// to talk to a server from a browser.
+///
+/// Only text messages are sent. Binary messages received are returned as
+/// text when they are valid UTF-8, pings are answered, and extensions aren't
+/// supported.
+pub struct WebSocket {
+    stream: TcpStream,
+    /// Bytes received that aren't part of a complete frame yet.
+    buffer: Vec<u8>,
+    /// The fragments of the message being received.
+    message: Vec<u8>,
+}
+
+impl WebSocket {
+    /// Reads the HTTP request of a client and upgrades the connection if it
+    /// asks for a WebSocket on one of `paths`, answering with an error
+    /// otherwise. Returns the path requested along with the socket.
+    pub async fn accept(mut stream: TcpStream, paths: &[&str]) -> NetResult<(String, Self)> {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        let head_end = loop {
+            if let Some(end) = request.windows(4).position(|end| end == b"\r\n\r\n") {
+                break end + 4;
+            }
+            let read = stream.read(&mut buf).await?;
+            if read == 0 || request.len() + read > MAX_REQUEST_BYTES {
+                return Err(NetError::WebSocket("incomplete upgrade request"));
+            }
+            request.extend_from_slice(&buf[..read]);
+        };
+
+        let head = String::from_utf8_lossy(&request[..head_end]);
+        let mut lines = head.split("\r\n");
+        let mut request_line = lines.next().unwrap_or_default().split(' ');
+        let (method, path) = (request_line.next(), request_line.next().unwrap_or_default());
+        let key = lines.find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("sec-websocket-key")
+                .then(|| value.trim().to_string())
+        });
+
+        let (status, error) = match (method, key) {
+            (Some("GET"), _) if !paths.contains(&path) => ("404 Not Found", "unknown path"),
+            (Some("GET"), Some(key)) => {
+                let accept = STANDARD.encode(Sha1::digest(format!("{key}{ACCEPT_GUID}")));
+                let response = format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+                );
+                stream.write_all(response.as_bytes()).await?;
+                let socket = Self {
+                    stream,
+                    // a client may send frames right after its request
+                    buffer: request[head_end..].to_vec(),
+                    message: Vec::new(),
+                };
+                return Ok((path.to_string(), socket));
+            }
+            (Some("GET"), None) => ("400 Bad Request", "not a WebSocket upgrade"),
+            _ => ("405 Method Not Allowed", "not a WebSocket upgrade"),
+        };
+        let response =
+            format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await?;
+        Err(NetError::WebSocket(error))
+    }
+
+    /// Sends a text message.
+    pub async fn send(&mut self, text: &str) -> NetResult {
+        self.send_frame(OPCODE_TEXT, text.as_bytes()).await
+    }
+
+    /// Receives the next text message, or `None` once the client closed the
+    /// connection.
+    ///
+    /// This is cancel safe: a partially received message is kept for the next
+    /// call.
+    pub async fn recv(&mut self) -> NetResult<Option<String>> {
+        loop {
+            while let Some((fin, opcode, payload)) = self.next_frame()? {
+                match opcode {
+                    OPCODE_CONTINUATION | OPCODE_TEXT | OPCODE_BINARY => {
+                        if self.message.len() + payload.len() > MAX_MESSAGE_BYTES {
+                            return Err(NetError::WebSocket("message too large"));
+                        }
+                        self.message.extend_from_slice(&payload);
+                        if fin {
+                            let message = std::mem::take(&mut self.message);
+                            return match String::from_utf8(message) {
+                                Ok(text) => Ok(Some(text)),
+                                Err(_) => Err(NetError::WebSocket("message isn't UTF-8")),
+                            };
+                        }
+                    }
+                    OPCODE_PING => self.send_frame(OPCODE_PONG, &payload).await?,
+                    OPCODE_PONG => (),
+                    OPCODE_CLOSE => {
+                        // the client may be gone already
+                        let _ = self.send_frame(OPCODE_CLOSE, &[]).await;
+                        return Ok(None);
+                    }
+                    _ => return Err(NetError::WebSocket("unknown opcode")),
+                }
+            }
+            let mut buf = [0u8; 4096];
+            let read = self.stream.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(None);
+            }
+            self.buffer.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    /// Takes the next complete frame out of the buffer, unmasked.
+    fn next_frame(&mut self) -> NetResult<Option<(bool, u8, Vec<u8>)>> {
+        let buffer = &self.buffer;
+        if buffer.len() < 2 {
+            return Ok(None);
+        }
+        let fin = buffer[0] & 0x80 != 0;
+        let opcode = buffer[0] & 0x0f;
+        if buffer[1] & 0x80 == 0 {
+            return Err(NetError::WebSocket("client frames must be masked"));
+        }
+        let (length, mut offset) = match buffer[1] & 0x7f {
+            126 if buffer.len() >= 4 => (u16::from_be_bytes([buffer[2], buffer[3]]) as u64, 4),
+            127 if buffer.len() >= 10 => {
+                let length = u64::from_be_bytes(buffer[2..10].try_into().unwrap());
+                (length, 10)
+            }
+            126 | 127 => return Ok(None),
+            length => (length as u64, 2),
+        };
+        if length > MAX_MESSAGE_BYTES as u64 {
+            return Err(NetError::WebSocket("message too large"));
+        }
+        let end = offset + 4 + length as usize;
+        if buffer.len() < end {
+            return Ok(None);
+        }
+        let mask = [
+            buffer[offset],
+            buffer[offset + 1],
+            buffer[offset + 2],
+            buffer[offset + 3],
+        ];
+        offset += 4;
+        let payload = buffer[offset..end]
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect();
+        self.buffer.drain(..end);
+        Ok(Some((fin, opcode, payload)))
+    }
+
+    async fn send_frame(&mut self, opcode: u8, payload: &[u8]) -> NetResult {
+        let mut frame = Vec::with_capacity(payload.len() + 10);
+        frame.push(0x80 | opcode);
+        match payload.len() {
+            length @ 0..=125 => frame.push(length as u8),
+            length @ 126..=0xffff => {
+                frame.push(126);
+                frame.extend_from_slice(&(length as u16).to_be_bytes());
+            }
+            length => {
+                frame.push(127);
+                frame.extend_from_slice(&(length as u64).to_be_bytes());
+            }
+        }
+        frame.extend_from_slice(payload);
+        self.stream.write_all(&frame).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+/// Encrypts and decrypts frame payloads, everything after the size field.
+///
+/// Implementations are stateful stream ciphers, so each direction of a session
+/// must go through the same instance in order.
+pub trait Encryption: Send + 'static {
+    fn encrypt_outgoing(&mut self, payload: &mut [u8]);
+    fn decrypt_incoming(&mut self, payload: &mut [u8]);
+}
+
+impl<E: Encryption + ?Sized> Encryption for Box<E> {
+    fn encrypt_outgoing(&mut self, payload: &mut [u8]) {
+        (**self).encrypt_outgoing(payload);
+    }
+
+    fn decrypt_incoming(&mut self, payload: &mut [u8]) {
+        (**self).decrypt_incoming(payload);
+    }
+}
+
+/// Leaves payloads untouched, for streams that aren't encrypted.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Plaintext;
+
+impl Encryption for Plaintext {
+    fn encrypt_outgoing(&mut self, _payload: &mut [u8]) {}
+    fn decrypt_incoming(&mut self, _payload: &mut [u8]) {}
+}
+
+/// The ARC4 stream cipher.
+#[derive(Clone)]
+pub struct Arc4 {
+    state: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Arc4 {
+    /// Creates a cipher from its key, which must be between 1 and 256 bytes.
+    pub fn new(key: &[u8]) -> Self {
+        assert!(
+            !key.is_empty() && key.len() <= 256,
+            "Invalid ARC4 key length"
+        );
+
+        let mut state = [0u8; 256];
+        for (i, value) in state.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+
+        let mut j = 0u8;
+        for i in 0..256 {
+            j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+            state.swap(i, j as usize);
+        }
+
+        Self { state, i: 0, j: 0 }
+    }
+
+    /// XORs the data with the next bytes of the key stream.
+    pub fn apply_keystream(&mut self, data: &mut [u8]) {
+        for byte in data {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.state[self.i as usize]);
+            self.state.swap(self.i as usize, self.j as usize);
+            let index = self.state[self.i as usize].wrapping_add(self.state[self.j as usize]);
+            *byte ^= self.state[index as usize];
+        }
+    }
+}
+
+impl std::fmt::Debug for Arc4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the cipher state
+        f.debug_struct("Arc4").finish_non_exhaustive()
+    }
+}
+
+/// ARC4 encryption keyed from the session key, with one key stream per direction.
+#[derive(Debug, Clone)]
+pub struct Arc4Encryption {
+    outgoing: Arc4,
+    incoming: Arc4,
+}
+
+impl Arc4Encryption {
+    pub fn new(session_key: &[u8]) -> Self {
+        Self {
+            outgoing: Arc4::new(session_key),
+            incoming: Arc4::new(session_key),
+        }
+    }
+}
+
+impl Encryption for Arc4Encryption {
+    fn encrypt_outgoing(&mut self, payload: &mut [u8]) {
+        self.outgoing.apply_keystream(payload);
+    }
+
+    fn decrypt_incoming(&mut self, payload: &mut [u8]) {
+        self.incoming.apply_keystream(payload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arc4_vectors() {
+        let mut data = b"Plaintext".to_vec();
+        Arc4::new(b"Key").apply_keystream(&mut data);
+        assert_eq!(hex::encode(&data), "bbf316e8d940af0ad3");
+
+        let mut data = b"Attack at dawn".to_vec();
+        Arc4::new(b"Secret").apply_keystream(&mut data);
+        assert_eq!(hex::encode(&data), "45a01f645fc35b383552544b9bf5");
+    }
+
+    #[test]
+    fn test_arc4_encryption_round_trip() {
+        let mut client = Arc4Encryption::new(b"session key");
+        let mut server = Arc4Encryption::new(b"session key");
+
+        for message in [&b"first"[..], b"second message", b"third"] {
+            let mut payload = message.to_vec();
+            client.encrypt_outgoing(&mut payload);
+            assert_ne!(payload, message);
+            server.decrypt_incoming(&mut payload);
+            assert_eq!(payload, message);
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use ws_bitpack::*;
+use ws_messages::Message;
+
+use crate::{Encryption, PacketHeader, Plaintext, ProtocolError, ProtocolResult};
+
+/// A complete packet extracted from a byte stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub opcode: u16,
+    /// The raw bytes of the whole packet, header included.
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    /// Returns a reader positioned at the start of the message body, right after
+    /// the header.
+    pub fn reader(&self) -> BitPackReader<'_> {
+        BitPackReader::with_position(&self.data, PacketHeader::BITS)
+    }
+}
+
+/// Splits a byte stream into frames using the size found in their header.
+///
+/// Bytes can be fed in chunks of any size, such as the result of individual TCP
+/// reads. Incomplete frames are kept until the rest of their data arrives.
+///
+/// Returned frames are decrypted: their `data` always holds the plaintext packet.
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+    max_frame_size: usize,
+    encryption: Box<dyn Encryption>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> Self {
+        Self::with_max_frame_size(PacketHeader::MAX_SIZE)
+    }
+
+    /// Creates a decoder that rejects frames larger than `max_frame_size` bytes.
+    pub fn with_max_frame_size(max_frame_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_frame_size,
+            encryption: Box::new(Plaintext),
+        }
+    }
+
+    /// Decrypts the frames extracted from now on with `encryption`.
+    ///
+    /// Buffered bytes are only decrypted when their frame is extracted, so this
+    /// can be called between two frames received in the same chunk.
+    pub fn set_encryption(&mut self, encryption: impl Encryption) {
+        self.encryption = Box::new(encryption);
+    }
+
+    /// Appends received bytes to the stream.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the number of buffered bytes that aren't part of a returned frame.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Extracts the next complete frame, or returns `None` if more data is needed.
+    ///
+    /// An error means that the stream is corrupted and that the connection should
+    /// be closed.
+    pub fn next_frame(&mut self) -> ProtocolResult<Option<Frame>> {
+        if self.buffer.len() < PacketHeader::BYTES {
+            return Ok(None);
+        }
+
+        // the size field is never encrypted
+        let size = BitPackReader::new(&self.buffer).read_u64(PacketHeader::SIZE_BITS)? as usize;
+        if size < PacketHeader::BYTES {
+            return Err(ProtocolError::InvalidFrameSize(size));
+        }
+        if size > self.max_frame_size {
+            return Err(ProtocolError::FrameTooLarge {
+                size,
+                max: self.max_frame_size,
+            });
+        }
+        if self.buffer.len() < size {
+            return Ok(None);
+        }
+
+        let rest = self.buffer.split_off(size);
+        let mut data = std::mem::replace(&mut self.buffer, rest);
+        self.encryption
+            .decrypt_incoming(&mut data[PacketHeader::SIZE_BYTES..]);
+        let header: PacketHeader = BitPackReader::new(&data).read()?;
+        Ok(Some(Frame {
+            opcode: header.opcode,
+            data,
+        }))
+    }
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for FrameDecoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameDecoder")
+            .field("buffered", &self.buffer.len())
+            .field("max_frame_size", &self.max_frame_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Iterator for FrameDecoder {
+    type Item = ProtocolResult<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+/// Builds frames by writing the header in front of a message body.
+///
+/// Frames are encrypted as they are encoded, so they must be sent in the order
+/// they were encoded in.
+pub struct FrameEncoder {
+    encryption: Box<dyn Encryption>,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Self {
+        Self::with_encryption(Plaintext)
+    }
+
+    pub fn with_encryption(encryption: impl Encryption) -> Self {
+        Self {
+            encryption: Box::new(encryption),
+        }
+    }
+
+    /// Encrypts the frames encoded from now on with `encryption`.
+    pub fn set_encryption(&mut self, encryption: impl Encryption) {
+        self.encryption = Box::new(encryption);
+    }
+
+    /// Encodes a frame with the body written by `write_body`.
+    ///
+    /// The size field of the header is filled in once the body is written.
+    pub fn encode<F>(&mut self, opcode: u16, write_body: F) -> ProtocolResult<Vec<u8>>
+    where
+        F: FnOnce(&mut BitPackWriter) -> BitPackResult,
+    {
+        self.encode_frame(opcode as u32, 0, write_body)
+    }
+
+    /// Encodes a frame containing `body`.
+    pub fn encode_value<T>(&mut self, opcode: u16, body: &T) -> ProtocolResult<Vec<u8>>
+    where
+        T: WriteValue,
+    {
+        self.encode(opcode, |writer| writer.write(body))
+    }
+
+    /// Encodes a frame containing `message`, with its opcode.
+    ///
+    /// The buffer is allocated once, using the size of the message.
+    pub fn encode_message<T>(&mut self, message: &T) -> ProtocolResult<Vec<u8>>
+    where
+        T: Message + WriteValue,
+    {
+        let bits = PacketHeader::BITS + message.bits_at(PacketHeader::BITS);
+        self.encode_frame(T::id(), bits.div_ceil(8), |writer| writer.write(message))
+    }
+
+    /// Fails with [`BitPackError::ValueTooLarge`] if the opcode doesn't fit in
+    /// the header.
+    fn encode_frame<F>(
+        &mut self,
+        opcode: u32,
+        capacity: usize,
+        write_body: F,
+    ) -> ProtocolResult<Vec<u8>>
+    where
+        F: FnOnce(&mut BitPackWriter) -> BitPackResult,
+    {
+        let mut writer = BitPackWriter::with_capacity(capacity);
+        let size_placeholder = writer.reserve(PacketHeader::SIZE_BITS)?;
+        writer.write_u64(opcode as u64, PacketHeader::OPCODE_BITS)?;
+        write_body(&mut writer)?;
+
+        let size = writer.len_bytes();
+        if size > PacketHeader::MAX_SIZE {
+            return Err(ProtocolError::FrameTooLarge {
+                size,
+                max: PacketHeader::MAX_SIZE,
+            });
+        }
+        writer.write_at(size_placeholder, size as u64)?;
+        let mut data = writer.finish()?;
+        self.encryption
+            .encrypt_outgoing(&mut data[PacketHeader::SIZE_BYTES..]);
+        Ok(data)
+    }
+}
+
+/// Encodes a plaintext frame containing `message`, header included.
+pub fn encode_message<T>(message: &T) -> ProtocolResult<Vec<u8>>
+where
+    T: Message + WriteValue,
+{
+    FrameEncoder::new().encode_message(message)
+}
+
+impl Default for FrameEncoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for FrameEncoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameEncoder").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACKET: &str = "2f00000240c00000000000008800000000000000000000\
+        00000000000000489208b89c000000000000000000000000";
+
+    #[test]
+    fn test_decode_partial_frames() {
+        let packet = hex::decode(PACKET).unwrap();
+        let stream = [packet.as_slice(), packet.as_slice()].concat();
+
+        for chunk_size in [1, 3, 5, 46, 47, 48, 94] {
+            let mut decoder = FrameDecoder::new();
+            let mut frames = Vec::new();
+            for chunk in stream.chunks(chunk_size) {
+                decoder.extend(chunk);
+                while let Some(frame) = decoder.next_frame().unwrap() {
+                    frames.push(frame);
+                }
+            }
+
+            assert_eq!(frames.len(), 2);
+            assert_eq!(decoder.buffered(), 0);
+            for frame in frames {
+                assert_eq!(frame.opcode, 2);
+                assert_eq!(frame.data, packet);
+                assert_eq!(frame.reader().read_u64(32).unwrap(), 6152);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_sizes() {
+        let mut decoder = FrameDecoder::new();
+        decoder.extend(&hex::decode("0200000240").unwrap());
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(ProtocolError::InvalidFrameSize(2))
+        ));
+
+        let mut decoder = FrameDecoder::with_max_frame_size(16);
+        decoder.extend(&hex::decode(PACKET).unwrap());
+        assert!(matches!(
+            decoder.next_frame(),
+            Err(ProtocolError::FrameTooLarge { size: 47, max: 16 })
+        ));
+    }
+
+    #[test]
+    fn test_encode_frame() {
+        let mut encoder = FrameEncoder::new();
+        let data = encoder
+            .encode(2, |writer| {
+                writer.write(&6152u32)?;
+                writer.write(&0u32)?;
+                writer.write(&17u32)?;
+                writer.write(&0u32)?;
+                writer.write(&0u64)?;
+                writer.write(&0u16)?;
+                writer.write_packed(&9u8, 5)?;
+                writer.write(&2629306514u32)?;
+                writer.write(&0u32)?;
+                writer.write(&0u64)
+            })
+            .unwrap();
+        assert_eq!(hex::encode(data), PACKET);
+    }
+
+    #[test]
+    fn test_encode_message() {
+        use ws_messages::{Message, MessageStruct};
+
+        #[derive(MessageStruct, Message, Debug)]
+        #[message_id(0x0002)]
+        struct ClientHello {
+            build_number: u32,
+            realm_id: u32,
+            realm_group_id: u32,
+            realm_group_enum: u32,
+            startup_time: u64,
+            listen_port: u16,
+            #[packed(5)]
+            connection_type: u8,
+            network_message_crc: u32,
+            process_id: u32,
+            process_creation_time: u64,
+        }
+
+        let hello = ClientHello {
+            build_number: 6152,
+            realm_id: 0,
+            realm_group_id: 17,
+            realm_group_enum: 0,
+            startup_time: 0,
+            listen_port: 0,
+            connection_type: 9,
+            network_message_crc: 2629306514,
+            process_id: 0,
+            process_creation_time: 0,
+        };
+        assert_eq!(hex::encode(encode_message(&hello).unwrap()), PACKET);
+
+        #[derive(MessageStruct, Message, Debug)]
+        #[message_id(0x0800)]
+        struct TooLarge {}
+
+        assert!(matches!(
+            encode_message(&TooLarge {}),
+            Err(ProtocolError::BitPack(BitPackError::ValueTooLarge {
+                bits: 11,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_encrypted_frames() {
+        let key = b"session key";
+        let mut encoder = FrameEncoder::with_encryption(crate::Arc4Encryption::new(key));
+        let first = encoder.encode_value(0x0123, &6152u32).unwrap();
+        let second = encoder.encode_value(0x0456, &17u64).unwrap();
+        assert_ne!(
+            first,
+            FrameEncoder::new().encode_value(0x0123, &6152u32).unwrap()
+        );
+        assert_eq!(first[..PacketHeader::SIZE_BYTES], [9, 0, 0]);
+
+        let mut decoder = FrameDecoder::new();
+        decoder.set_encryption(crate::Arc4Encryption::new(key));
+        decoder.extend(&[first, second].concat());
+
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame.opcode, 0x0123);
+        assert_eq!(frame.reader().read::<u32>().unwrap(), 6152);
+        let frame = decoder.next_frame().unwrap().unwrap();
+        assert_eq!(frame.opcode, 0x0456);
+        assert_eq!(frame.reader().read::<u64>().unwrap(), 17);
+    }
+}
@@ -0,0 +1,71 @@
+use ws_bitpack::*;
+
+/// The header found at the start of every packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHeader {
+    /// The size of the whole packet in bytes, including this header.
+    pub size: u32,
+    /// The opcode of the message contained in the packet.
+    pub opcode: u16,
+}
+
+impl PacketHeader {
+    pub const SIZE_BITS: usize = 24;
+    pub const OPCODE_BITS: usize = 11;
+    /// The size of the header in bits. Note that the message body that follows it
+    /// is not aligned.
+    pub const BITS: usize = Self::SIZE_BITS + Self::OPCODE_BITS;
+    /// The number of bytes touched by the header.
+    pub const BYTES: usize = Self::BITS.div_ceil(8);
+    /// The number of bytes taken by the size field. Everything after it is the
+    /// encrypted part of the packet.
+    pub const SIZE_BYTES: usize = Self::SIZE_BITS / 8;
+    /// The largest possible packet size.
+    pub const MAX_SIZE: usize = (1 << Self::SIZE_BITS) - 1;
+    /// The largest possible opcode.
+    pub const MAX_OPCODE: u16 = (1 << Self::OPCODE_BITS) - 1;
+}
+
+impl ReadValue for PacketHeader {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        Ok(Self {
+            size: reader.read_packed(Self::SIZE_BITS)?,
+            opcode: reader.read_packed(Self::OPCODE_BITS)?,
+        })
+    }
+}
+
+impl WriteValue for PacketHeader {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.write_packed(&self.size, Self::SIZE_BITS)?;
+        writer.write_packed(&self.opcode, Self::OPCODE_BITS)
+    }
+
+    fn bits(&self) -> usize {
+        Self::BITS
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_write_read() {
+        let data = hex::decode("2f00000240").unwrap();
+        let mut reader = BitPackReader::new(&data);
+        let header: PacketHeader = reader.read().unwrap();
+        assert_eq!(
+            header,
+            PacketHeader {
+                size: 47,
+                opcode: 2
+            }
+        );
+        assert_eq!(reader.position(), PacketHeader::BITS);
+
+        let mut writer = BitPackWriter::growable();
+        writer.write(&header).unwrap();
+        assert_eq!(hex::encode(writer.finish().unwrap()), "2f00000200");
+    }
+}
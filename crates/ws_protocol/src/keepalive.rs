@@ -0,0 +1,26 @@
+use ws_messages::*;
+
+/// Sent by clients every few seconds to keep their connection alive.
+///
+/// Servers answer it with a [`ServerPong`] on their own, handlers never see it.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0241)]
+pub struct ClientPing {
+    /// The client's clock in milliseconds, echoed back to measure latency.
+    pub timestamp: u32,
+}
+
+/// The answer to a [`ClientPing`].
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0242)]
+pub struct ServerPong {
+    pub timestamp: u32,
+}
+
+impl ClientPing {
+    pub fn pong(&self) -> ServerPong {
+        ServerPong {
+            timestamp: self.timestamp,
+        }
+    }
+}
@@ -0,0 +1,56 @@
+mod encryption;
+mod framing;
+mod header;
+mod keepalive;
+mod tickets;
+
+pub use encryption::*;
+pub use framing::*;
+pub use header::*;
+pub use keepalive::*;
+pub use tickets::*;
+
+use std::fmt;
+
+use ws_bitpack::BitPackError;
+
+#[derive(Debug)]
+pub enum ProtocolError {
+    BitPack(BitPackError),
+    /// A frame's size is smaller than its own header.
+    InvalidFrameSize(usize),
+    /// A frame is larger than the maximum allowed size.
+    FrameTooLarge {
+        size: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BitPack(error) => error.fmt(f),
+            Self::InvalidFrameSize(size) => write!(f, "invalid frame size {size}"),
+            Self::FrameTooLarge { size, max } => {
+                write!(f, "frame of {size} bytes is larger than {max}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::BitPack(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<BitPackError> for ProtocolError {
+    fn from(error: BitPackError) -> Self {
+        ProtocolError::BitPack(error)
+    }
+}
+
+pub type ProtocolResult<T = ()> = Result<T, ProtocolError>;
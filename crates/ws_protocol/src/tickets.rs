@@ -0,0 +1,171 @@
+use std::fmt;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// The bytes of the HMAC kept in a ticket, after its expiry.
+const SIGNATURE_LENGTH: usize = 12;
+
+/// What a ticket vouches for: the account it was issued to, and the realm
+/// whose world server it can be presented to until it expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TicketClaims {
+    pub account_id: u32,
+    pub realm_id: u32,
+    /// In seconds since the Unix epoch.
+    pub expires_at: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TicketError {
+    /// The ticket wasn't signed with the key for the account and realm.
+    InvalidSignature,
+    Expired,
+    /// The ticket was already presented once.
+    Redeemed,
+}
+
+impl fmt::Display for TicketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSignature => write!(f, "invalid ticket signature"),
+            Self::Expired => write!(f, "the ticket expired"),
+            Self::Redeemed => write!(f, "the ticket was already redeemed"),
+        }
+    }
+}
+
+impl std::error::Error for TicketError {}
+
+/// The key that the realm server signs tickets with, and that the world
+/// servers check them with.
+///
+/// A ticket takes the place of the session GUID given to the client, so it
+/// has to fit its 16 bytes: the expiry as a little-endian `u32`, followed by
+/// the start of the HMAC-SHA256 of the claims. The account and the realm
+/// aren't in the ticket, the client tells the world server its account and
+/// the world server knows its realm.
+#[derive(Clone)]
+pub struct TicketKey {
+    key: Vec<u8>,
+}
+
+impl TicketKey {
+    /// The shortest key accepted, in bytes.
+    pub const MIN_LENGTH: usize = 16;
+
+    /// Creates a key from its bytes, or returns `None` if it is shorter than
+    /// [`TicketKey::MIN_LENGTH`].
+    pub fn new(key: &[u8]) -> Option<Self> {
+        (key.len() >= Self::MIN_LENGTH).then(|| Self { key: key.to_vec() })
+    }
+
+    /// Creates a key from its bytes in hex, like in the configuration.
+    pub fn from_hex(key: &str) -> Option<Self> {
+        Self::new(&hex::decode(key.trim()).ok()?)
+    }
+
+    pub fn issue(&self, claims: &TicketClaims) -> Uuid {
+        let mut ticket = [0u8; 16];
+        ticket[..4].copy_from_slice(&claims.expires_at.to_le_bytes());
+        let signature = self.mac(claims).finalize().into_bytes();
+        ticket[4..].copy_from_slice(&signature[..SIGNATURE_LENGTH]);
+        Uuid::from_bytes(ticket)
+    }
+
+    /// Checks that a ticket was issued to an account for a realm, and that
+    /// it didn't expire at `now`, in seconds since the Unix epoch.
+    pub fn verify(
+        &self,
+        ticket: &Uuid,
+        account_id: u32,
+        realm_id: u32,
+        now: u64,
+    ) -> Result<TicketClaims, TicketError> {
+        let ticket = ticket.as_bytes();
+        let claims = TicketClaims {
+            account_id,
+            realm_id,
+            expires_at: u32::from_le_bytes(ticket[..4].try_into().unwrap()),
+        };
+        self.mac(&claims)
+            .verify_truncated_left(&ticket[4..])
+            .map_err(|_| TicketError::InvalidSignature)?;
+        if u64::from(claims.expires_at) <= now {
+            return Err(TicketError::Expired);
+        }
+        Ok(claims)
+    }
+
+    /// Derives a secret for another use from the key, so that the servers
+    /// only have one secret configured. Different labels give unrelated
+    /// secrets, none of which reveal the key.
+    pub fn derive(&self, label: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("any key length works");
+        mac.update(b"derive:");
+        mac.update(label);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn mac(&self, claims: &TicketClaims) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).expect("any key length works");
+        mac.update(&claims.account_id.to_le_bytes());
+        mac.update(&claims.realm_id.to_le_bytes());
+        mac.update(&claims.expires_at.to_le_bytes());
+        mac
+    }
+}
+
+impl fmt::Debug for TicketKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // keeps the key out of the logs
+        f.debug_struct("TicketKey").finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tickets() {
+        assert!(TicketKey::from_hex("00112233").is_none());
+        assert!(TicketKey::from_hex("not hex").is_none());
+        let key = TicketKey::from_hex("000102030405060708090a0b0c0d0e0f").unwrap();
+        let claims = TicketClaims {
+            account_id: 7,
+            realm_id: 1,
+            expires_at: 1000,
+        };
+        let ticket = key.issue(&claims);
+        assert_eq!(ticket.as_bytes()[..4], 1000u32.to_le_bytes());
+        assert_eq!(key.verify(&ticket, 7, 1, 999), Ok(claims));
+
+        assert_eq!(key.verify(&ticket, 7, 1, 1000), Err(TicketError::Expired));
+        assert_eq!(
+            key.verify(&ticket, 8, 1, 999),
+            Err(TicketError::InvalidSignature)
+        );
+        assert_eq!(
+            key.verify(&ticket, 7, 2, 999),
+            Err(TicketError::InvalidSignature)
+        );
+        // pushing the expiry back breaks the signature
+        let mut forged = *ticket.as_bytes();
+        forged[..4].copy_from_slice(&2000u32.to_le_bytes());
+        assert_eq!(
+            key.verify(&Uuid::from_bytes(forged), 7, 1, 1500),
+            Err(TicketError::InvalidSignature)
+        );
+        let other = TicketKey::new(&[1; 32]).unwrap();
+        assert_eq!(
+            other.verify(&ticket, 7, 1, 999),
+            Err(TicketError::InvalidSignature)
+        );
+
+        assert_eq!(key.derive(b"logins"), key.derive(b"logins"));
+        assert_ne!(key.derive(b"logins"), key.derive(b"other"));
+        assert_ne!(key.derive(b"logins"), other.derive(b"logins"));
+    }
+}
@@ -0,0 +1,21 @@
+//! A minimal realm server.
+//!
+//! Clients connect to the realm server after logging in, get the list of realms,
+//! and log in with their account, proving that they know its password with the
+//! SRP6a exchange of `ws_auth`. They then receive a ticket for the realm they
+//! pick. The ticket is then presented to
+//! the world server of that realm, which checks that the realm server signed it
+//! for the account. Other requests reach the world servers over the
+//! `ws_cluster` bus when a cluster is configured.
+
+mod messages;
+#[cfg(feature = "server")]
+mod realms;
+#[cfg(feature = "server")]
+mod server;
+
+pub use messages::*;
+#[cfg(feature = "server")]
+pub use realms::*;
+#[cfg(feature = "server")]
+pub use server::*;
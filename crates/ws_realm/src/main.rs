@@ -0,0 +1,98 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing_subscriber::EnvFilter;
+use ws_cluster::{ClusterHub, ClusterMessage, ClusterNode, Node};
+use ws_config::Config;
+use ws_db::Database;
+use ws_messages::MessageRegistry;
+use ws_metrics::MetricsServer;
+use ws_net::{RateLimit, RateLimits, Server};
+use ws_protocol::TicketKey;
+use ws_realm::{Realm, RealmHandler, RealmServer, RealmStatus};
+
+/// How long clients can stay silent, they ping every few seconds.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // RUST_LOG=ws_net=debug logs the messages received and sent
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+    // the configuration file can be given as the first argument
+    let path = std::env::args().nth(1).map(PathBuf::from);
+    let config = Config::load(path.as_deref()).expect("Failed to load the configuration");
+
+    let realm = Realm {
+        id: config.realm.id,
+        name: config.realm.name.clone(),
+        status: RealmStatus::Up,
+        world_addr: config.world.public_addr,
+    };
+    let database = Database::connect(&config.database_url)
+        .await
+        .expect("Failed to open the database");
+    let mut state = RealmServer::new(vec![realm]).with_database(database);
+    if config.ticket_key.is_empty() {
+        tracing::warn!("No ticket key is configured, world servers can't check the tickets");
+    } else {
+        let key = TicketKey::from_hex(&config.ticket_key)
+            .expect("The ticket key must be at least 16 bytes in hex");
+        state = state.with_ticket_key(key);
+    }
+    if !config.cluster.addr.is_empty() {
+        let hub = ClusterHub::bind(&config.cluster.addr)
+            .await
+            .expect("Failed to bind the cluster hub");
+        tracing::info!("Cluster hub listening on {}", config.cluster.addr);
+        let node = hub.join(Node::Realm);
+        state = state.with_cluster(node.sender());
+        tokio::spawn(async {
+            if let Err(error) = hub.run().await {
+                tracing::error!("Cluster hub stopped: {error}");
+            }
+        });
+        tokio::spawn(serve_cluster(node));
+    }
+    let state = Arc::new(state);
+
+    let addr = &config.realm.listen;
+    let mut server = Server::bind(addr, MessageRegistry::with_registered())
+        .await
+        .expect("Failed to bind the realm server");
+    server.set_idle_timeout(IDLE_TIMEOUT);
+    // realm sessions only send a few messages
+    let mut limits = RateLimits::new();
+    limits.global(RateLimit::new(20, 5.0).disconnect());
+    server.set_rate_limits(limits);
+    tracing::info!("Realm server listening on {addr}");
+    let metrics = MetricsServer::bind(&config.realm.metrics)
+        .await
+        .expect("Failed to bind the metrics endpoint");
+    tokio::spawn(async {
+        if let Err(error) = metrics.run().await {
+            tracing::error!("Metrics endpoint stopped: {error}");
+        }
+    });
+    if let Err(error) = server.run(Arc::new(RealmHandler::new(state))).await {
+        tracing::error!("Realm server stopped: {error}");
+    }
+}
+
+/// Handles the messages sent to the realm server over the cluster.
+async fn serve_cluster(mut node: ClusterNode) {
+    while let Some(envelope) = node.recv().await {
+        match envelope.message {
+            ClusterMessage::CharacterReleased {
+                character_id,
+                to_realm,
+            } => tracing::info!(
+                "Character {character_id} left {} for realm {to_realm}",
+                envelope.from
+            ),
+            message => tracing::debug!("Ignored {message:?} from {}", envelope.from),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use ws_messages::*;
+
+/// Sent by the client right after connecting.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0002)]
+pub struct ClientHello {
+    pub build_number: u32,
+    pub realm_id: u32,
+    pub realm_group_id: u32,
+    pub realm_group_enum: u32,
+    pub startup_time: u64,
+    pub listen_port: u16,
+    #[packed(5)]
+    pub connection_type: u8,
+    pub network_message_crc: u32,
+    pub process_id: u32,
+    pub process_creation_time: u64,
+}
+
+/// Sent by the client to log in with its account, before picking a realm.
+/// The realm server answers with a [`ServerAuthChallenge`].
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0004)]
+pub struct ClientHelloAuth {
+    pub build_number: u32,
+    pub account_name: String,
+}
+
+/// The start of the SRP6a exchange: the salt of the account's verifier, and
+/// the public key B of the server.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0005)]
+pub struct ServerAuthChallenge {
+    #[length_of(salt)]
+    pub salt_length: u32,
+    #[bytes]
+    #[length(salt_length)]
+    pub salt: Vec<u8>,
+    #[length_of(public_key)]
+    pub public_key_length: u32,
+    #[bytes]
+    #[length(public_key_length)]
+    pub public_key: Vec<u8>,
+}
+
+/// The public key A of the client, and its proof M1 that it knows the
+/// password.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0006)]
+pub struct ClientAuthProof {
+    #[length_of(public_key)]
+    pub public_key_length: u32,
+    #[bytes]
+    #[length(public_key_length)]
+    pub public_key: Vec<u8>,
+    #[length_of(proof)]
+    pub proof_length: u32,
+    #[bytes]
+    #[length(proof_length)]
+    pub proof: Vec<u8>,
+}
+
+/// Sent when the proof of the client matches, with the proof M2 of the
+/// server. The client can then pick a realm.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0007)]
+pub struct ServerAuthAccepted {
+    pub account_id: u32,
+    #[length_of(server_proof)]
+    pub server_proof_length: u32,
+    #[bytes]
+    #[length(server_proof_length)]
+    pub server_proof: Vec<u8>,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AuthDeniedReason {
+    /// The account doesn't exist or the password is wrong, which aren't told
+    /// apart.
+    InvalidCredentials = 0,
+    /// The login couldn't be checked, such as when the database is
+    /// unavailable.
+    Failed = 1,
+    /// The account is banned, which is only told once the client proved it
+    /// knows the password.
+    Banned = 2,
+}
+
+/// Sent when the login is refused, before the realm server closes the
+/// session.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0008)]
+pub struct ServerAuthDenied {
+    #[packed(3)]
+    pub reason: AuthDeniedReason,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum RealmStatus {
+    Offline = 0,
+    Up = 1,
+    Locked = 2,
+}
+
+#[derive(MessageStruct, Debug, Clone, PartialEq)]
+pub struct RealmEntry {
+    pub realm_id: u32,
+    pub name: String,
+    #[packed(3)]
+    pub status: RealmStatus,
+    pub population: u32,
+}
+
+/// The realms the client can pick from.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x0761)]
+pub struct ServerRealmList {
+    #[length_of(realms)]
+    pub count: u32,
+    #[length(count)]
+    pub realms: Vec<RealmEntry>,
+}
+
+/// Sent by the client to pick a realm from the list.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x07a4)]
+pub struct ClientSelectRealm {
+    pub realm_id: u32,
+}
+
+/// Tells the client where to connect for the realm it picked, and with which
+/// ticket.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x03db)]
+pub struct ServerRealmTicket {
+    pub realm_id: u32,
+    pub ticket: uuid::Uuid,
+    /// The IPv4 address of the world server, in host byte order.
+    pub address: u32,
+    pub port: u16,
+}
+
+/// Sent when the client picks a realm that it can't join.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq)]
+#[message_id(0x03dc)]
+pub struct ServerRealmUnavailable {
+    pub realm_id: u32,
+}
@@ -0,0 +1,24 @@
+use std::net::SocketAddrV4;
+
+use crate::{RealmEntry, RealmStatus};
+
+/// A realm as configured on the realm server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Realm {
+    pub id: u32,
+    pub name: String,
+    pub status: RealmStatus,
+    /// The address of the realm's world server.
+    pub world_addr: SocketAddrV4,
+}
+
+impl Realm {
+    pub fn entry(&self) -> RealmEntry {
+        RealmEntry {
+            realm_id: self.id,
+            name: self.name.clone(),
+            status: self.status,
+            population: 0,
+        }
+    }
+}
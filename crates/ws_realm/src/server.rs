@@ -0,0 +1,564 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use uuid::Uuid;
+use ws_auth::{normalize_login, Srp6Server, Srp6Verifier};
+use ws_cluster::{ClusterError, ClusterMessage, ClusterResult, ClusterSender, Node};
+use ws_db::Database;
+use ws_messages::AnyMessage;
+use ws_net::{Handler, HandlerMap, NetError, NetResult, Session, SessionId};
+use ws_protocol::{TicketClaims, TicketKey};
+
+use crate::*;
+
+/// How long the tickets issued can be presented to a world server for.
+pub const TICKET_LIFETIME: Duration = Duration::from_secs(60);
+
+/// An SRP6a exchange started by a session, waiting for its proof.
+#[derive(Debug)]
+struct Login {
+    /// The account logging in, `None` for a login without an account.
+    account_id: Option<u32>,
+    /// Only told to the client once it proved it knows the password.
+    banned: bool,
+    srp: Srp6Server,
+}
+
+/// The state shared by all sessions of a realm server.
+#[derive(Debug)]
+pub struct RealmServer {
+    pub realms: Vec<Realm>,
+    ticket_key: TicketKey,
+    /// The accounts are checked against the database, no login is accepted
+    /// without it.
+    database: Option<Database>,
+    /// The SRP6a exchanges started by the sessions.
+    logins: Mutex<HashMap<SessionId, Login>>,
+    /// The accounts that the sessions logged in with.
+    accounts: Mutex<HashMap<SessionId, u32>>,
+    cluster: Option<ClusterSender>,
+}
+
+impl RealmServer {
+    /// Creates a realm server signing its tickets with a random key, which
+    /// only suits world servers that don't check them.
+    pub fn new(realms: Vec<Realm>) -> Self {
+        let key = [Uuid::new_v4().into_bytes(), Uuid::new_v4().into_bytes()].concat();
+        Self {
+            realms,
+            ticket_key: TicketKey::new(&key).unwrap(),
+            database: None,
+            logins: Default::default(),
+            accounts: Default::default(),
+            cluster: None,
+        }
+    }
+
+    /// Signs the tickets with the key that the world servers check them with.
+    pub fn with_ticket_key(mut self, ticket_key: TicketKey) -> Self {
+        self.ticket_key = ticket_key;
+        self
+    }
+
+    /// Checks the logins against the accounts of the database.
+    pub fn with_database(mut self, database: Database) -> Self {
+        self.database = Some(database);
+        self
+    }
+
+    /// Hands the tickets issued to the world servers of the realms over the
+    /// cluster, and sends them requests such as character transfers.
+    pub fn with_cluster(mut self, cluster: ClusterSender) -> Self {
+        self.cluster = Some(cluster);
+        self
+    }
+
+    pub fn database(&self) -> Option<&Database> {
+        self.database.as_ref()
+    }
+
+    pub fn ticket_key(&self) -> &TicketKey {
+        &self.ticket_key
+    }
+
+    /// Asks the world server of a realm to let go of a character, so that
+    /// it can move to another realm. The world server answers with
+    /// [`ClusterMessage::CharacterReleased`].
+    pub fn transfer_character(
+        &self,
+        character_id: u64,
+        from_realm: u32,
+        to_realm: u32,
+    ) -> ClusterResult {
+        let cluster = self.cluster.as_ref().ok_or(ClusterError::Closed)?;
+        cluster.send_to(
+            Node::World(from_realm),
+            ClusterMessage::CharacterTransfer {
+                character_id,
+                to_realm,
+            },
+        )
+    }
+
+    fn hello(&self, session: &Session, _hello: ClientHello) -> NetResult {
+        session.send(&ServerRealmList {
+            count: self.realms.len() as u32,
+            realms: self.realms.iter().map(Realm::entry).collect(),
+        })
+    }
+
+    async fn hello_auth(&self, session: &Session, hello: ClientHelloAuth) -> NetResult {
+        let database = match &self.database {
+            Some(database) => database,
+            None => return deny(session, AuthDeniedReason::Failed),
+        };
+        let account = match database.accounts().find_by_name(&hello.account_name).await {
+            Ok(account) => account,
+            Err(error) => {
+                tracing::error!("Failed to find account {}: {error}", hello.account_name);
+                return deny(session, AuthDeniedReason::Failed);
+            }
+        };
+        let login = match account {
+            Some(account) => {
+                let account_id = match u32::try_from(account.id) {
+                    Ok(account_id) => account_id,
+                    Err(_) => return deny(session, AuthDeniedReason::Failed),
+                };
+                let verifier = Srp6Verifier {
+                    salt: account.salt,
+                    verifier: account.verifier,
+                };
+                Login {
+                    account_id: Some(account_id),
+                    banned: account.banned,
+                    srp: Srp6Server::new(&account.name, &verifier),
+                }
+            }
+            None => {
+                // unknown logins get a challenge like any account, and are
+                // refused at the proof like a wrong password
+                let login = normalize_login(&hello.account_name);
+                let secret = self.ticket_key.derive(b"unknown logins");
+                let verifier = Srp6Verifier::fake(&login, &secret);
+                Login {
+                    account_id: None,
+                    banned: false,
+                    srp: Srp6Server::new(&login, &verifier),
+                }
+            }
+        };
+        let srp = &login.srp;
+        let challenge = ServerAuthChallenge {
+            salt_length: srp.salt().len() as u32,
+            salt: srp.salt().to_vec(),
+            public_key_length: srp.public_key().len() as u32,
+            public_key: srp.public_key(),
+        };
+        self.logins.lock().unwrap().insert(session.id(), login);
+        session.send(&challenge)
+    }
+
+    fn auth_proof(&self, session: &Session, proof: ClientAuthProof) -> NetResult {
+        let login = match self.logins.lock().unwrap().remove(&session.id()) {
+            Some(login) => login,
+            None => {
+                // the proof has to answer a challenge
+                session.close();
+                return Ok(());
+            }
+        };
+        let keys = match login.srp.verify(&proof.public_key, &proof.proof) {
+            Ok(keys) => keys,
+            Err(error) => {
+                tracing::debug!("Login of account {:?} refused: {error}", login.account_id);
+                return deny(session, AuthDeniedReason::InvalidCredentials);
+            }
+        };
+        let account_id = match login.account_id {
+            Some(account_id) => account_id,
+            // no password matches the verifier of an unknown login
+            None => return deny(session, AuthDeniedReason::InvalidCredentials),
+        };
+        // checked once the proof is, so that the ban doesn't tell that the
+        // account exists
+        if login.banned {
+            return deny(session, AuthDeniedReason::Banned);
+        }
+        self.accounts
+            .lock()
+            .unwrap()
+            .insert(session.id(), account_id);
+        session.send(&ServerAuthAccepted {
+            account_id,
+            server_proof_length: keys.server_proof.len() as u32,
+            server_proof: keys.server_proof,
+        })
+    }
+
+    fn select_realm(&self, session: &Session, select: ClientSelectRealm) -> NetResult {
+        let account_id = match self.accounts.lock().unwrap().get(&session.id()) {
+            Some(account_id) => *account_id,
+            None => {
+                // tickets are only issued to accounts
+                session.close();
+                return Ok(());
+            }
+        };
+        let realm = self
+            .realms
+            .iter()
+            .find(|realm| realm.id == select.realm_id && realm.status == RealmStatus::Up);
+        let realm = match realm {
+            Some(realm) => realm,
+            None => {
+                return session.send(&ServerRealmUnavailable {
+                    realm_id: select.realm_id,
+                })
+            }
+        };
+
+        let expires_at = unix_now() + TICKET_LIFETIME.as_secs();
+        let ticket = self.ticket_key.issue(&TicketClaims {
+            account_id,
+            realm_id: realm.id,
+            expires_at: u32::try_from(expires_at).unwrap_or(u32::MAX),
+        });
+        // the world server only lets in the tickets handed over the cluster,
+        // on top of checking their signature
+        if let Some(cluster) = &self.cluster {
+            let handed = cluster.send_to(
+                Node::World(realm.id),
+                ClusterMessage::SessionTicket {
+                    ticket,
+                    realm_id: realm.id,
+                },
+            );
+            if let Err(error) = handed {
+                tracing::error!("Failed to hand a ticket to the world server: {error}");
+                return session.send(&ServerRealmUnavailable { realm_id: realm.id });
+            }
+        }
+        session.send(&ServerRealmTicket {
+            realm_id: realm.id,
+            ticket,
+            address: u32::from(*realm.world_addr.ip()),
+            port: realm.world_addr.port(),
+        })
+    }
+}
+
+/// Refuses the login of a session and closes it.
+fn deny(session: &Session, reason: AuthDeniedReason) -> NetResult {
+    session.send(&ServerAuthDenied { reason })?;
+    session.close();
+    Ok(())
+}
+
+/// Returns the seconds since the Unix epoch, which tickets expire by.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Routes the messages of every session to the realm server.
+pub struct RealmHandler {
+    server: Arc<RealmServer>,
+    handlers: HandlerMap,
+}
+
+impl RealmHandler {
+    pub fn new(server: Arc<RealmServer>) -> Self {
+        let mut handlers = HandlerMap::new();
+        let state = server.clone();
+        handlers.register(move |session, hello| {
+            let state = state.clone();
+            async move { state.hello(&session, hello) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, hello| {
+            let state = state.clone();
+            async move { state.hello_auth(&session, hello).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, proof| {
+            let state = state.clone();
+            async move { state.auth_proof(&session, proof) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, select| {
+            let state = state.clone();
+            async move { state.select_realm(&session, select) }
+        });
+        Self { server, handlers }
+    }
+}
+
+impl Handler for RealmHandler {
+    async fn message(&self, session: &Session, message: Box<dyn AnyMessage>) -> NetResult {
+        self.handlers.dispatch(session, message).await
+    }
+
+    async fn disconnected(&self, session: &Session, _error: Option<NetError>) {
+        self.server.logins.lock().unwrap().remove(&session.id());
+        self.server.accounts.lock().unwrap().remove(&session.id());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use ws_auth::Srp6Client;
+    use ws_bitpack::{ReadValue, WriteValue};
+    use ws_cluster::ClusterHub;
+    use ws_messages::{Message, MessageRegistry};
+    use ws_net::Server;
+    use ws_protocol::{encode_message, FrameDecoder, TicketError};
+
+    use super::*;
+
+    async fn send<T: Message + WriteValue>(client: &mut TcpStream, message: &T) {
+        let frame = encode_message(message).unwrap();
+        client.write_all(&frame).await.unwrap();
+    }
+
+    async fn receive<T: Message + ReadValue>(
+        client: &mut TcpStream,
+        decoder: &mut FrameDecoder,
+    ) -> T {
+        loop {
+            if let Some(frame) = decoder.next_frame().unwrap() {
+                assert_eq!(frame.opcode as u32, T::id());
+                return frame.reader().read().unwrap();
+            }
+            let mut buf = [0u8; 1024];
+            let read = client.read(&mut buf).await.unwrap();
+            assert!(read > 0);
+            decoder.extend(&buf[..read]);
+        }
+    }
+
+    /// Starts a realm server with the account "test" and the password
+    /// "password".
+    async fn start() -> (Arc<RealmServer>, std::net::SocketAddr, u32) {
+        start_with(|state| state).await
+    }
+
+    /// Starts a realm server like [`start`], configured by `configure`.
+    async fn start_with(
+        configure: impl FnOnce(RealmServer) -> RealmServer,
+    ) -> (Arc<RealmServer>, std::net::SocketAddr, u32) {
+        let realm = Realm {
+            id: 1,
+            name: "Sandbox".to_string(),
+            status: RealmStatus::Up,
+            world_addr: "127.0.0.1:24000".parse().unwrap(),
+        };
+        let database = Database::in_memory().await.unwrap();
+        let verifier = Srp6Verifier::generate("test", "password");
+        let account = database
+            .accounts()
+            .create("test", &verifier.salt, &verifier.verifier)
+            .await
+            .unwrap();
+        let state = Arc::new(configure(
+            RealmServer::new(vec![realm]).with_database(database),
+        ));
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run(Arc::new(RealmHandler::new(state.clone()))));
+        (state, addr, account.id as u32)
+    }
+
+    /// Runs the client side of the SRP6a exchange, up to sending the proof,
+    /// with the client normalising the login. Returns the challenge of the
+    /// server.
+    async fn log_in(
+        client: &mut TcpStream,
+        decoder: &mut FrameDecoder,
+        account_name: &str,
+        password: &str,
+    ) -> ServerAuthChallenge {
+        let login = ClientHelloAuth {
+            build_number: 16042,
+            account_name: account_name.to_string(),
+        };
+        send(client, &login).await;
+        let challenge: ServerAuthChallenge = receive(client, decoder).await;
+        let srp = Srp6Client::new();
+        let (proof, _keys) = srp
+            .process(
+                &normalize_login(account_name),
+                password,
+                &challenge.salt,
+                &challenge.public_key,
+            )
+            .unwrap();
+        let public_key = srp.public_key();
+        let proof = ClientAuthProof {
+            public_key_length: public_key.len() as u32,
+            public_key,
+            proof_length: proof.len() as u32,
+            proof,
+        };
+        send(client, &proof).await;
+        challenge
+    }
+
+    #[tokio::test]
+    async fn test_realm_flow() {
+        let (state, addr, account_id) = start().await;
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let hello = ClientHello {
+            build_number: 16042,
+            realm_id: 0,
+            realm_group_id: 0,
+            realm_group_enum: 0,
+            startup_time: 0,
+            listen_port: 0,
+            connection_type: 3,
+            network_message_crc: 0,
+            process_id: 0,
+            process_creation_time: 0,
+        };
+        send(&mut client, &hello).await;
+        let list: ServerRealmList = receive(&mut client, &mut decoder).await;
+        assert_eq!(list.count, 1);
+        assert_eq!(list.realms[0].name, "Sandbox");
+
+        // account names are case-insensitive, the client normalises the login
+        log_in(&mut client, &mut decoder, "Test", "password").await;
+        let accepted: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        assert_eq!(accepted.account_id, account_id);
+        assert_eq!(accepted.server_proof.len(), 32);
+
+        send(&mut client, &ClientSelectRealm { realm_id: 2 }).await;
+        let unavailable: ServerRealmUnavailable = receive(&mut client, &mut decoder).await;
+        assert_eq!(unavailable.realm_id, 2);
+
+        send(&mut client, &ClientSelectRealm { realm_id: 1 }).await;
+        let ticket: ServerRealmTicket = receive(&mut client, &mut decoder).await;
+        assert_eq!(ticket.address, 0x7f000001);
+        assert_eq!(ticket.port, 24000);
+        // the ticket is signed for the account and the realm
+        let key = state.ticket_key();
+        let claims = key
+            .verify(&ticket.ticket, account_id, 1, unix_now())
+            .unwrap();
+        assert!(u64::from(claims.expires_at) > unix_now());
+        assert_eq!(
+            key.verify(&ticket.ticket, account_id + 1, 1, unix_now()),
+            Err(TicketError::InvalidSignature)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_login_denied() {
+        let (state, addr, account_id) = start().await;
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        log_in(&mut client, &mut decoder, "test", "wrong").await;
+        let denied: ServerAuthDenied = receive(&mut client, &mut decoder).await;
+        assert_eq!(denied.reason, AuthDeniedReason::InvalidCredentials);
+        // the session is closed
+        assert_eq!(client.read(&mut [0; 16]).await.unwrap(), 0);
+
+        let accounts = state.database().unwrap().accounts();
+        assert!(accounts.set_banned(account_id.into(), true).await.unwrap());
+        // a banned account is told so only with the right password
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        log_in(&mut client, &mut decoder, "test", "wrong").await;
+        let denied: ServerAuthDenied = receive(&mut client, &mut decoder).await;
+        assert_eq!(denied.reason, AuthDeniedReason::InvalidCredentials);
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        log_in(&mut client, &mut decoder, "test", "password").await;
+        let denied: ServerAuthDenied = receive(&mut client, &mut decoder).await;
+        assert_eq!(denied.reason, AuthDeniedReason::Banned);
+        assert_eq!(client.read(&mut [0; 16]).await.unwrap(), 0);
+
+        // without logging in, no ticket is issued
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        send(&mut client, &ClientSelectRealm { realm_id: 1 }).await;
+        assert_eq!(client.read(&mut [0; 16]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_login() {
+        let (_state, addr, _) = start().await;
+        // the challenge and the denial of a login
+        let attempt = |account_name: &'static str| async move {
+            let mut client = TcpStream::connect(addr).await.unwrap();
+            let mut decoder = FrameDecoder::new();
+            let challenge = log_in(&mut client, &mut decoder, account_name, "wrong").await;
+            let denied: ServerAuthDenied = receive(&mut client, &mut decoder).await;
+            assert_eq!(client.read(&mut [0; 16]).await.unwrap(), 0);
+            (challenge, denied)
+        };
+
+        let (known, known_denied) = attempt("test").await;
+        let (unknown, unknown_denied) = attempt("nobody").await;
+        // an unknown login can't be told from a wrong password
+        assert_eq!(unknown.salt_length, known.salt_length);
+        assert_ne!(unknown.salt, known.salt);
+        assert_eq!(unknown_denied, known_denied);
+        assert_eq!(unknown_denied.reason, AuthDeniedReason::InvalidCredentials);
+        // and its salt is the same at every attempt, like the one of an account
+        assert_eq!(attempt("test").await.0.salt, known.salt);
+        assert_eq!(attempt("Nobody").await.0.salt, unknown.salt);
+    }
+
+    #[tokio::test]
+    async fn test_tickets_over_cluster() {
+        let hub = ClusterHub::bind("127.0.0.1:0").await.unwrap();
+        let mut world = hub.join(Node::World(1));
+        let cluster = hub.join(Node::Realm).sender();
+        let (_state, addr, _) = start_with(|state| state.with_cluster(cluster)).await;
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        log_in(&mut client, &mut decoder, "test", "password").await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        send(&mut client, &ClientSelectRealm { realm_id: 1 }).await;
+        let ticket: ServerRealmTicket = receive(&mut client, &mut decoder).await;
+        // the world server of the realm got the signed ticket
+        let envelope = world.recv().await.unwrap();
+        assert_eq!(envelope.from, Node::Realm);
+        assert_eq!(
+            envelope.message,
+            ClusterMessage::SessionTicket {
+                ticket: ticket.ticket,
+                realm_id: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_transfer_over_cluster() {
+        let hub = ClusterHub::bind("127.0.0.1:0").await.unwrap();
+        let mut world = hub.join(Node::World(1));
+        let state = RealmServer::new(Vec::new());
+        assert!(state.transfer_character(5, 1, 2).is_err());
+
+        let state = state.with_cluster(hub.join(Node::Realm).sender());
+        state.transfer_character(5, 1, 2).unwrap();
+        let envelope = world.recv().await.unwrap();
+        assert_eq!(envelope.from, Node::Realm);
+        assert_eq!(
+            envelope.message,
+            ClusterMessage::CharacterTransfer {
+                character_id: 5,
+                to_realm: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,69 @@
+//! The STS protocol used by the login server before the game connection.
+//!
+//! STS is a text protocol similar to HTTP/1.1 that runs over TLS. The client
+//! authenticates with a SRP6a exchange split across the `/Auth/LoginStart` and
+//! `/Auth/KeyData` requests, which produces the session key needed by the realm
+//! connection.
+
+mod message;
+
+pub use message::*;
+pub use ws_auth::{SessionKeys, Srp6Client, Srp6Server, Srp6Verifier};
+
+use std::fmt;
+
+use ws_auth::AuthError;
+
+#[derive(Debug)]
+pub enum StsError {
+    /// The first line of a message is neither a request nor a response line.
+    InvalidStartLine(String),
+    /// A header line isn't a `name:value` pair.
+    InvalidHeader(String),
+    /// A header is missing or has an invalid value.
+    MissingHeader(&'static str),
+    /// A message is larger than the maximum allowed size.
+    MessageTooLarge { size: usize, max: usize },
+    /// The head of a message isn't valid UTF-8.
+    InvalidUtf8,
+    /// Key data sent by the other side can't be parsed.
+    InvalidKeyData,
+    /// The SRP6a exchange failed.
+    Auth(AuthError),
+}
+
+impl fmt::Display for StsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidStartLine(line) => write!(f, "invalid start line {line:?}"),
+            Self::InvalidHeader(line) => write!(f, "invalid header line {line:?}"),
+            Self::MissingHeader(name) => write!(f, "missing or invalid header {name:?}"),
+            Self::MessageTooLarge { size, max } => {
+                write!(
+                    f,
+                    "message of {size} bytes is larger than the maximum of {max}"
+                )
+            }
+            Self::InvalidUtf8 => write!(f, "message head isn't valid UTF-8"),
+            Self::InvalidKeyData => write!(f, "invalid key data"),
+            Self::Auth(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for StsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Auth(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<AuthError> for StsError {
+    fn from(error: AuthError) -> Self {
+        StsError::Auth(error)
+    }
+}
+
+pub type StsResult<T = ()> = Result<T, StsError>;
@@ -0,0 +1,286 @@
+use crate::{StsError, StsResult};
+
+/// The protocol name and version found in every start line.
+pub const STS_VERSION: &str = "STS/1.0";
+
+/// The first line of a message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StartLine {
+    /// For example `POST /Auth/LoginStart STS/1.0`.
+    Request { method: String, uri: String },
+    /// For example `STS/1.0 200 OK`.
+    Response { status: u16, reason: String },
+}
+
+/// A request or response, with its headers and body.
+///
+/// The body length is written in the `l` header and the sequence number of the
+/// request in the `s` header. Responses use the sequence number of their request
+/// followed by an `R`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StsMessage {
+    pub start_line: StartLine,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl StsMessage {
+    pub const LENGTH_HEADER: &'static str = "l";
+    pub const SEQUENCE_HEADER: &'static str = "s";
+
+    pub fn request(method: &str, uri: &str, sequence: u32) -> Self {
+        Self {
+            start_line: StartLine::Request {
+                method: method.to_string(),
+                uri: uri.to_string(),
+            },
+            headers: vec![(Self::SEQUENCE_HEADER.to_string(), sequence.to_string())],
+            body: Vec::new(),
+        }
+    }
+
+    /// Creates the response to the request with the given sequence number.
+    pub fn response(status: u16, reason: &str, sequence: u32) -> Self {
+        Self {
+            start_line: StartLine::Response {
+                status,
+                reason: reason.to_string(),
+            },
+            headers: vec![(Self::SEQUENCE_HEADER.to_string(), format!("{sequence}R"))],
+            body: Vec::new(),
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Returns the value of the first header with the given name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the sequence number, without the `R` suffix of responses.
+    pub fn sequence(&self) -> Option<u32> {
+        let value = self.header(Self::SEQUENCE_HEADER)?;
+        value.strip_suffix('R').unwrap_or(value).parse().ok()
+    }
+
+    /// Serializes the message, adding the length header.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut head = match &self.start_line {
+            StartLine::Request { method, uri } => format!("{method} {uri} {STS_VERSION}\r\n"),
+            StartLine::Response { status, reason } => {
+                format!("{STS_VERSION} {status} {reason}\r\n")
+            }
+        };
+        head += &format!("{}:{}\r\n", Self::LENGTH_HEADER, self.body.len());
+        for (name, value) in &self.headers {
+            if name != Self::LENGTH_HEADER {
+                head += &format!("{name}:{value}\r\n");
+            }
+        }
+        head += "\r\n";
+
+        let mut bytes = head.into_bytes();
+        bytes.extend_from_slice(&self.body);
+        bytes
+    }
+
+    fn parse_head(head: &str) -> StsResult<(StartLine, Vec<(String, String)>)> {
+        let mut lines = head.split("\r\n");
+        let line = lines.next().unwrap_or_default();
+        let parts = line.splitn(3, ' ').collect::<Vec<_>>();
+        let invalid_start_line = || StsError::InvalidStartLine(line.to_string());
+        let start_line = match parts[..] {
+            [STS_VERSION, status, reason] => StartLine::Response {
+                status: status.parse().map_err(|_| invalid_start_line())?,
+                reason: reason.to_string(),
+            },
+            [method, uri, STS_VERSION] => StartLine::Request {
+                method: method.to_string(),
+                uri: uri.to_string(),
+            },
+            _ => return Err(invalid_start_line()),
+        };
+
+        let headers = lines
+            .map(|line| match line.split_once(':') {
+                Some((name, value)) => Ok((name.to_string(), value.trim().to_string())),
+                None => Err(StsError::InvalidHeader(line.to_string())),
+            })
+            .collect::<StsResult<Vec<_>>>()?;
+        Ok((start_line, headers))
+    }
+}
+
+/// Splits a byte stream into STS messages.
+///
+/// Like the game protocol's frame decoder, bytes can be fed in chunks of any size
+/// and incomplete messages are kept until the rest of their data arrives.
+#[derive(Debug)]
+pub struct StsDecoder {
+    buffer: Vec<u8>,
+    max_message_size: usize,
+}
+
+impl StsDecoder {
+    /// The default maximum size of a message, head included.
+    pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 64 * 1024;
+
+    pub fn new() -> Self {
+        Self::with_max_message_size(Self::DEFAULT_MAX_MESSAGE_SIZE)
+    }
+
+    pub fn with_max_message_size(max_message_size: usize) -> Self {
+        Self {
+            buffer: Vec::new(),
+            max_message_size,
+        }
+    }
+
+    /// Appends received bytes to the stream.
+    pub fn extend(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+    }
+
+    /// Returns the number of buffered bytes that aren't part of a returned message.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Extracts the next complete message, or returns `None` if more data is needed.
+    ///
+    /// An error means that the stream is corrupted and that the connection should
+    /// be closed.
+    pub fn next_message(&mut self) -> StsResult<Option<StsMessage>> {
+        let head_end = match self.buffer.windows(4).position(|w| w == b"\r\n\r\n") {
+            Some(position) => position,
+            None => {
+                return match self.buffer.len() > self.max_message_size {
+                    true => Err(self.too_large(self.buffer.len())),
+                    false => Ok(None),
+                }
+            }
+        };
+
+        let head =
+            std::str::from_utf8(&self.buffer[..head_end]).map_err(|_| StsError::InvalidUtf8)?;
+        let (start_line, headers) = StsMessage::parse_head(head)?;
+        let length = headers
+            .iter()
+            .find(|(name, _)| name == StsMessage::LENGTH_HEADER)
+            .and_then(|(_, value)| value.parse::<usize>().ok())
+            .ok_or(StsError::MissingHeader(StsMessage::LENGTH_HEADER))?;
+
+        let size = (head_end + 4).saturating_add(length);
+        if size > self.max_message_size {
+            return Err(self.too_large(size));
+        }
+        if self.buffer.len() < size {
+            return Ok(None);
+        }
+
+        let rest = self.buffer.split_off(size);
+        let data = std::mem::replace(&mut self.buffer, rest);
+        Ok(Some(StsMessage {
+            start_line,
+            headers,
+            body: data[head_end + 4..].to_vec(),
+        }))
+    }
+
+    fn too_large(&self, size: usize) -> StsError {
+        StsError::MessageTooLarge {
+            size,
+            max: self.max_message_size,
+        }
+    }
+}
+
+impl Default for StsDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Iterator for StsDecoder {
+    type Item = StsResult<StsMessage>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_message().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_round_trip() {
+        let request = StsMessage::request("POST", "/Auth/LoginStart", 1)
+            .with_body("<Request><LoginName>user</LoginName></Request>");
+        let bytes = request.to_bytes();
+        assert!(bytes.starts_with(b"POST /Auth/LoginStart STS/1.0\r\nl:46\r\ns:1\r\n\r\n<Request>"));
+
+        let response = StsMessage::response(200, "OK", 1).with_body("<Reply/>");
+        let stream = [bytes, response.to_bytes()].concat();
+
+        for chunk_size in [1, 7, stream.len()] {
+            let mut decoder = StsDecoder::new();
+            let mut messages = Vec::new();
+            for chunk in stream.chunks(chunk_size) {
+                decoder.extend(chunk);
+                while let Some(message) = decoder.next_message().unwrap() {
+                    messages.push(message);
+                }
+            }
+
+            assert_eq!(decoder.buffered(), 0);
+            assert_eq!(messages.len(), 2);
+            assert_eq!(messages[0].body, request.body);
+            assert_eq!(messages[0].sequence(), Some(1));
+            assert_eq!(messages[1].start_line, response.start_line);
+            assert_eq!(messages[1].header("s"), Some("1R"));
+            assert_eq!(messages[1].sequence(), Some(1));
+        }
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let mut decoder = StsDecoder::new();
+        decoder.extend(b"HELLO\r\n\r\n");
+        assert!(matches!(
+            decoder.next_message(),
+            Err(StsError::InvalidStartLine(_))
+        ));
+
+        let mut decoder = StsDecoder::new();
+        decoder.extend(b"STS/1.0 200 OK\r\ns:1R\r\n\r\n");
+        assert!(matches!(
+            decoder.next_message(),
+            Err(StsError::MissingHeader("l"))
+        ));
+
+        let mut decoder = StsDecoder::with_max_message_size(32);
+        decoder.extend(b"STS/1.0 200 OK\r\nl:100\r\n\r\n");
+        let error = decoder.next_message().unwrap_err();
+        assert!(matches!(
+            error,
+            StsError::MessageTooLarge { size: 125, max: 32 }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "message of 125 bytes is larger than the maximum of 32"
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
+
+/// Implements `TblRecord` for a struct with named fields, reading each field
+/// from the column with the same name, or the one given with
+/// `#[tbl(column = "...")]`.
+#[proc_macro_derive(TblRecord, attributes(tbl))]
+pub fn derive_tbl_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(expanded) => expanded.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "TblRecord can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "TblRecord can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut columns = Vec::new();
+    let mut reads = Vec::new();
+    for (index, field) in fields.iter().enumerate() {
+        let ident = field.ident.as_ref().expect("fields are named");
+        let column = match column_attribute(field)? {
+            Some(column) => column,
+            None => ident.to_string(),
+        };
+        reads.push(quote! {
+            #ident: ws_tbl::read_column(row_, columns_[#index], #column)?
+        });
+        columns.push(column);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ws_tbl::TblRecord for #ident #type_generics #where_clause {
+            const COLUMNS: &'static [&'static str] = &[#(#columns),*];
+
+            fn from_row(
+                row_: &[ws_tbl::TblValue],
+                columns_: &[usize],
+            ) -> ws_tbl::TblResult<Self> {
+                Ok(Self {
+                    #(#reads,)*
+                })
+            }
+        }
+    })
+}
+
+/// Returns the column of `#[tbl(column = "...")]`, if the field has one.
+fn column_attribute(field: &syn::Field) -> syn::Result<Option<String>> {
+    let mut column = None;
+    for attr in field.attrs.iter().filter(|attr| attr.path.is_ident("tbl")) {
+        let list = match attr.parse_meta()? {
+            Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "Expected #[tbl(column = \"...\")]",
+                ))
+            }
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(value)) if value.path.is_ident("column") => {
+                    match value.lit {
+                        Lit::Str(name) if column.is_none() => column = Some(name.value()),
+                        Lit::Str(name) => {
+                            return Err(syn::Error::new_spanned(name, "Only one column is allowed"))
+                        }
+                        lit => return Err(syn::Error::new_spanned(lit, "Expected a string")),
+                    }
+                }
+                nested => {
+                    return Err(syn::Error::new_spanned(
+                        nested,
+                        "Expected #[tbl(column = \"...\")]",
+                    ))
+                }
+            }
+        }
+    }
+    Ok(column)
+}
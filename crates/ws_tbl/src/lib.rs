@@ -0,0 +1,94 @@
+//! Reads the client data tables, the `.tbl` files holding the items,
+//! creatures, spells and most other game data of WildStar.
+//!
+//! A [`Table`] holds the records of a file as loosely typed values, which
+//! convert into structs deriving [`TblRecord`]:
+//!
+//! ```no_run
+//! use ws_tbl::{Table, TblRecord};
+//!
+//! #[derive(TblRecord)]
+//! struct Item {
+//!     id: u32,
+//!     #[tbl(column = "localizedTextIdName")]
+//!     name_text_id: u32,
+//! }
+//!
+//! let table = Table::open("Item2.tbl").unwrap();
+//! let items: Vec<Item> = table.records().unwrap();
+//! ```
+
+// Lets the derive refer to this crate as `ws_tbl` from within it.
+extern crate self as ws_tbl;
+
+mod record;
+mod table;
+
+pub use record::*;
+pub use table::*;
+pub use ws_tbl_macros::*;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum TblError {
+    Io(std::io::Error),
+    /// The data doesn't start with [`MAGIC`].
+    InvalidMagic([u8; 4]),
+    /// The data ended before a value read at this offset.
+    UnexpectedEnd {
+        offset: usize,
+    },
+    UnknownDataType {
+        column: String,
+        data_type: u16,
+    },
+    /// The string at this offset isn't valid UTF-16.
+    InvalidString {
+        offset: usize,
+    },
+    /// A record type needs a column that the table doesn't have.
+    MissingColumn(&'static str),
+    /// A value can't convert into the type of its field.
+    InvalidValue {
+        column: &'static str,
+        expected: &'static str,
+    },
+}
+
+impl fmt::Display for TblError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::InvalidMagic(magic) => write!(f, "invalid table magic {magic:02x?}"),
+            Self::UnexpectedEnd { offset } => {
+                write!(f, "the table ended before the value at {offset:#x}")
+            }
+            Self::UnknownDataType { column, data_type } => {
+                write!(f, "column {column} has the unknown type {data_type}")
+            }
+            Self::InvalidString { offset } => write!(f, "invalid string at {offset:#x}"),
+            Self::MissingColumn(column) => write!(f, "the table has no column {column}"),
+            Self::InvalidValue { column, expected } => {
+                write!(f, "column {column} doesn't hold {expected}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for TblError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for TblError {
+    fn from(error: std::io::Error) -> Self {
+        TblError::Io(error)
+    }
+}
+
+pub type TblResult<T = ()> = Result<T, TblError>;
@@ -0,0 +1,170 @@
+use crate::*;
+
+/// A type that records of a table convert into, usually derived with
+/// `#[derive(TblRecord)]`.
+///
+/// The derive reads each field from the column with the same name, ignoring
+/// case and underscores, or from the column given with
+/// `#[tbl(column = "...")]`.
+pub trait TblRecord: Sized {
+    /// The columns the fields are read from, in order.
+    const COLUMNS: &'static [&'static str];
+
+    /// Converts a record, where `columns` holds the index of each of
+    /// [`TblRecord::COLUMNS`] in the record.
+    fn from_row(row: &[TblValue], columns: &[usize]) -> TblResult<Self>;
+}
+
+/// A type that values of a table convert into.
+pub trait FromTblValue: Sized {
+    /// The type of values that convert, for errors.
+    const EXPECTED: &'static str;
+
+    fn from_value(value: &TblValue) -> Option<Self>;
+}
+
+impl FromTblValue for u32 {
+    const EXPECTED: &'static str = "an unsigned integer";
+
+    fn from_value(value: &TblValue) -> Option<Self> {
+        match value {
+            TblValue::UInt(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromTblValue for i32 {
+    const EXPECTED: &'static str = "an integer";
+
+    fn from_value(value: &TblValue) -> Option<Self> {
+        // signed values are stored in unsigned columns
+        match value {
+            TblValue::UInt(value) => Some(*value as i32),
+            _ => None,
+        }
+    }
+}
+
+impl FromTblValue for u64 {
+    const EXPECTED: &'static str = "an unsigned integer";
+
+    fn from_value(value: &TblValue) -> Option<Self> {
+        match value {
+            TblValue::UInt(value) => Some(*value as u64),
+            TblValue::ULong(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromTblValue for f32 {
+    const EXPECTED: &'static str = "a float";
+
+    fn from_value(value: &TblValue) -> Option<Self> {
+        match value {
+            TblValue::Float(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromTblValue for bool {
+    const EXPECTED: &'static str = "a bool";
+
+    fn from_value(value: &TblValue) -> Option<Self> {
+        match value {
+            TblValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+}
+
+impl FromTblValue for String {
+    const EXPECTED: &'static str = "a string";
+
+    fn from_value(value: &TblValue) -> Option<Self> {
+        match value {
+            TblValue::String(value) => Some(value.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the value at `index` of a record, for the derive.
+#[doc(hidden)]
+pub fn read_column<T: FromTblValue>(
+    row: &[TblValue],
+    index: usize,
+    column: &'static str,
+) -> TblResult<T> {
+    row.get(index)
+        .and_then(T::from_value)
+        .ok_or(TblError::InvalidValue {
+            column,
+            expected: T::EXPECTED,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::table::tests::build_table;
+    use crate::*;
+
+    #[derive(TblRecord, Debug, PartialEq)]
+    struct Creature {
+        id: u32,
+        #[tbl(column = "localizedTextIdName")]
+        name: String,
+        model_scale: f32,
+    }
+
+    #[test]
+    fn test_derive_records() {
+        let data = build_table(
+            "Creature2",
+            &[
+                ("ID", DataType::UInt),
+                ("modelScale", DataType::Float),
+                ("localizedTextIdName", DataType::String),
+            ],
+            &[vec![
+                TblValue::UInt(20),
+                TblValue::Float(1.25),
+                TblValue::String("Boulderback".to_string()),
+            ]],
+        );
+        let table = Table::parse(&data).unwrap();
+        let expected = Creature {
+            id: 20,
+            name: "Boulderback".to_string(),
+            model_scale: 1.25,
+        };
+        assert_eq!(table.records::<Creature>().unwrap(), [expected]);
+        assert!(table.record::<Creature>(21).unwrap().is_none());
+
+        #[derive(TblRecord, Debug)]
+        #[allow(dead_code)]
+        struct WrongType {
+            #[tbl(column = "modelScale")]
+            scale: u32,
+        }
+        assert!(matches!(
+            table.records::<WrongType>(),
+            Err(TblError::InvalidValue {
+                column: "modelScale",
+                expected: "an unsigned integer",
+            })
+        ));
+
+        #[derive(TblRecord, Debug)]
+        #[allow(dead_code)]
+        struct Missing {
+            display_group: u32,
+        }
+        assert!(matches!(
+            table.records::<Missing>(),
+            Err(TblError::MissingColumn("display_group"))
+        ));
+    }
+}
@@ -0,0 +1,464 @@
+use std::path::Path;
+
+use crate::*;
+
+/// The size of the header, which every offset of the file is relative to the
+/// end of.
+pub const HEADER_SIZE: usize = 0x60;
+
+/// The first bytes of a table, `DTBL` read as a little endian integer.
+pub const MAGIC: [u8; 4] = *b"LBTD";
+
+const COLUMN_SIZE: usize = 0x18;
+
+/// The type of the values of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataType {
+    UInt,
+    Float,
+    /// Stored on 4 bytes.
+    Bool,
+    ULong,
+    /// An offset to a null terminated UTF-16 string.
+    String,
+}
+
+impl DataType {
+    fn from_id(id: u16) -> Option<Self> {
+        match id {
+            3 => Some(Self::UInt),
+            4 => Some(Self::Float),
+            11 => Some(Self::Bool),
+            20 => Some(Self::ULong),
+            130 => Some(Self::String),
+            _ => None,
+        }
+    }
+
+    /// Returns the size of the values in a record, which are aligned on it.
+    fn size(self) -> usize {
+        match self {
+            Self::UInt | Self::Float | Self::Bool => 4,
+            Self::ULong | Self::String => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Column {
+    pub name: String,
+    pub data_type: DataType,
+}
+
+/// A value of a record.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TblValue {
+    UInt(u32),
+    Float(f32),
+    Bool(bool),
+    ULong(u64),
+    String(String),
+}
+
+/// A client data table, read from a `.tbl` file.
+///
+/// The file starts with a header of [`HEADER_SIZE`] bytes holding the offsets
+/// of the other parts, relative to the end of the header: the table name, the
+/// columns and their names, the fixed size records followed by their strings,
+/// and a lookup from ids to record indices. Numbers are little endian.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    name: String,
+    columns: Vec<Column>,
+    rows: Vec<Vec<TblValue>>,
+    /// The index of the record of each id, if any.
+    lookup: Vec<Option<usize>>,
+}
+
+impl Table {
+    pub fn open(path: impl AsRef<Path>) -> TblResult<Self> {
+        Self::parse(&std::fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> TblResult<Self> {
+        let magic: [u8; 4] = bytes_at(data, 0, 4)?.try_into().unwrap();
+        if magic != MAGIC {
+            return Err(TblError::InvalidMagic(magic));
+        }
+        let header = |offset| u64_at(data, offset).map(|value| value as usize);
+        let name_length = header(0x08)?;
+        let record_size = header(0x18)?;
+        let column_count = header(0x20)?;
+        let column_offset = HEADER_SIZE + header(0x28)?;
+        let record_count = header(0x30)?;
+        let record_offset = HEADER_SIZE + header(0x40)?;
+        let max_id = header(0x48)?;
+        let lookup_offset = HEADER_SIZE + header(0x50)?;
+
+        let name = utf16_at(data, HEADER_SIZE, Some(name_length))?;
+
+        // the names follow the columns, aligned on 16 bytes
+        let names_offset = column_offset + (column_count * COLUMN_SIZE).next_multiple_of(16);
+        let columns = (0..column_count)
+            .map(|index| {
+                let offset = column_offset + index * COLUMN_SIZE;
+                let name_length = u32_at(data, offset)? as usize;
+                let name_offset = names_offset + u64_at(data, offset + 0x08)? as usize;
+                let name = utf16_at(data, name_offset, Some(name_length))?;
+                let data_type = u16_at(data, offset + 0x10)?;
+                let data_type =
+                    DataType::from_id(data_type).ok_or_else(|| TblError::UnknownDataType {
+                        column: name.clone(),
+                        data_type,
+                    })?;
+                Ok(Column { name, data_type })
+            })
+            .collect::<TblResult<Vec<_>>>()?;
+
+        let rows = (0..record_count)
+            .map(|index| read_record(data, &columns, record_offset, index * record_size))
+            .collect::<TblResult<Vec<_>>>()?;
+
+        let lookup = (0..max_id)
+            .map(|id| {
+                let index = u32_at(data, lookup_offset + id * 4)? as i32;
+                Ok(usize::try_from(index)
+                    .ok()
+                    .filter(|index| *index < rows.len()))
+            })
+            .collect::<TblResult<Vec<_>>>()?;
+
+        Ok(Self {
+            name,
+            columns,
+            rows,
+            lookup,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn columns(&self) -> &[Column] {
+        &self.columns
+    }
+
+    pub fn rows(&self) -> &[Vec<TblValue>] {
+        &self.rows
+    }
+
+    pub fn len(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    /// Returns the record with an id, using the lookup of the table.
+    pub fn get(&self, id: u32) -> Option<&[TblValue]> {
+        let index = (*self.lookup.get(id as usize)?)?;
+        Some(&self.rows[index])
+    }
+
+    /// Finds a column by name, ignoring case and underscores so that `item_id`
+    /// matches `itemId`.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        let name = normalize_column(name);
+        self.columns
+            .iter()
+            .position(|column| normalize_column(&column.name) == name)
+    }
+
+    /// Converts every record into a `T`.
+    pub fn records<T: TblRecord>(&self) -> TblResult<Vec<T>> {
+        let columns = self.record_columns::<T>()?;
+        self.rows
+            .iter()
+            .map(|row| T::from_row(row, &columns))
+            .collect()
+    }
+
+    /// Converts the record with an id into a `T`.
+    pub fn record<T: TblRecord>(&self, id: u32) -> TblResult<Option<T>> {
+        let columns = self.record_columns::<T>()?;
+        self.get(id)
+            .map(|row| T::from_row(row, &columns))
+            .transpose()
+    }
+
+    /// Returns the index of each column that a `T` is read from.
+    fn record_columns<T: TblRecord>(&self) -> TblResult<Vec<usize>> {
+        T::COLUMNS
+            .iter()
+            .map(|name| self.column_index(name).ok_or(TblError::MissingColumn(name)))
+            .collect()
+    }
+}
+
+fn normalize_column(name: &str) -> String {
+    name.chars()
+        .filter(|c| *c != '_')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+fn read_record(
+    data: &[u8],
+    columns: &[Column],
+    record_offset: usize,
+    start: usize,
+) -> TblResult<Vec<TblValue>> {
+    let mut position = start;
+    columns
+        .iter()
+        .map(|column| {
+            let size = column.data_type.size();
+            position = position.next_multiple_of(size);
+            let offset = record_offset + position;
+            position += size;
+            Ok(match column.data_type {
+                DataType::UInt => TblValue::UInt(u32_at(data, offset)?),
+                DataType::Float => TblValue::Float(f32::from_bits(u32_at(data, offset)?)),
+                DataType::Bool => TblValue::Bool(u32_at(data, offset)? != 0),
+                DataType::ULong => TblValue::ULong(u64_at(data, offset)?),
+                DataType::String => {
+                    // either offset can be set, both are relative to the records
+                    let first = u32_at(data, offset)?;
+                    let second = u32_at(data, offset + 4)?;
+                    let string_offset = if first != 0 { first } else { second };
+                    TblValue::String(utf16_at(
+                        data,
+                        record_offset + string_offset as usize,
+                        None,
+                    )?)
+                }
+            })
+        })
+        .collect()
+}
+
+fn bytes_at(data: &[u8], offset: usize, len: usize) -> TblResult<&[u8]> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(TblError::UnexpectedEnd { offset })
+}
+
+fn u16_at(data: &[u8], offset: usize) -> TblResult<u16> {
+    Ok(u16::from_le_bytes(
+        bytes_at(data, offset, 2)?.try_into().unwrap(),
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> TblResult<u32> {
+    Ok(u32::from_le_bytes(
+        bytes_at(data, offset, 4)?.try_into().unwrap(),
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> TblResult<u64> {
+    Ok(u64::from_le_bytes(
+        bytes_at(data, offset, 8)?.try_into().unwrap(),
+    ))
+}
+
+/// Reads a UTF-16 string of `length` units, or up to its null terminator if
+/// the length isn't known. Lengths count the terminator, which isn't returned.
+fn utf16_at(data: &[u8], offset: usize, length: Option<usize>) -> TblResult<String> {
+    let mut units = Vec::new();
+    loop {
+        if length.is_some_and(|length| units.len() >= length) {
+            break;
+        }
+        let unit = u16_at(data, offset + units.len() * 2)?;
+        if unit == 0 && length.is_none() {
+            break;
+        }
+        units.push(unit);
+    }
+    while units.last() == Some(&0) {
+        units.pop();
+    }
+    String::from_utf16(&units).map_err(|_| TblError::InvalidString { offset })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    fn utf16(text: &str) -> Vec<u8> {
+        text.encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_le_bytes)
+            .collect()
+    }
+
+    fn pad(data: &mut Vec<u8>, alignment: usize) {
+        data.resize(data.len().next_multiple_of(alignment), 0);
+    }
+
+    /// Writes a table the way the client files are laid out, with a lookup
+    /// from the ids in the first column.
+    pub(crate) fn build_table(
+        name: &str,
+        columns: &[(&str, DataType)],
+        rows: &[Vec<TblValue>],
+    ) -> Vec<u8> {
+        let mut body = utf16(name);
+        pad(&mut body, 16);
+
+        let column_offset = body.len();
+        let mut names = Vec::new();
+        for (name, data_type) in columns {
+            let id: u16 = match data_type {
+                DataType::UInt => 3,
+                DataType::Float => 4,
+                DataType::Bool => 11,
+                DataType::ULong => 20,
+                DataType::String => 130,
+            };
+            body.extend((name.encode_utf16().count() as u32 + 1).to_le_bytes());
+            body.extend(0u32.to_le_bytes());
+            body.extend((names.len() as u64).to_le_bytes());
+            body.extend(id.to_le_bytes());
+            body.extend([0; 6]);
+            names.extend(utf16(name));
+        }
+        pad(&mut body, 16);
+        body.extend(names);
+        pad(&mut body, 16);
+
+        let record_offset = body.len();
+        let mut records = Vec::new();
+        let mut strings = Vec::new();
+        let string_start = rows.len() * 8 * columns.len();
+        for row in rows {
+            let start = records.len();
+            for value in row {
+                let size = match value {
+                    TblValue::ULong(_) | TblValue::String(_) => 8,
+                    _ => 4,
+                };
+                records.resize(start + (records.len() - start).next_multiple_of(size), 0);
+                match value {
+                    TblValue::UInt(value) => records.extend(value.to_le_bytes()),
+                    TblValue::Float(value) => records.extend(value.to_le_bytes()),
+                    TblValue::Bool(value) => records.extend((*value as u32).to_le_bytes()),
+                    TblValue::ULong(value) => records.extend(value.to_le_bytes()),
+                    TblValue::String(value) => {
+                        let offset = (string_start + strings.len()) as u32;
+                        records.extend(offset.to_le_bytes());
+                        records.extend(0u32.to_le_bytes());
+                        strings.extend(utf16(value));
+                    }
+                }
+            }
+            records.resize(start + 8 * columns.len(), 0);
+        }
+        body.extend(records);
+        body.extend(strings);
+        pad(&mut body, 16);
+
+        let lookup_offset = body.len();
+        let ids: Vec<_> = rows
+            .iter()
+            .map(|row| match row[0] {
+                TblValue::UInt(id) => id as usize,
+                _ => panic!("The first column must be the id"),
+            })
+            .collect();
+        let max_id = ids.iter().max().map_or(0, |id| id + 1);
+        for id in 0..max_id {
+            let index = ids.iter().position(|other| *other == id);
+            body.extend(index.map_or(-1, |index| index as i32).to_le_bytes());
+        }
+
+        let mut data = Vec::from(MAGIC);
+        for value in [
+            0,
+            name.encode_utf16().count() as u64 + 1,
+            0,
+            8 * columns.len() as u64,
+            columns.len() as u64,
+            column_offset as u64,
+            rows.len() as u64,
+            (8 * columns.len() * rows.len()) as u64,
+            record_offset as u64,
+            max_id as u64,
+            lookup_offset as u64,
+            0,
+        ] {
+            data.extend(value.to_le_bytes());
+        }
+        // the version is only 4 bytes
+        data.drain(4..8);
+        assert_eq!(data.len(), HEADER_SIZE);
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn test_parse_table() {
+        let data = build_table(
+            "Item2",
+            &[
+                ("ID", DataType::UInt),
+                ("localizedName", DataType::String),
+                ("powerLevel", DataType::Float),
+                ("bindOnEquip", DataType::Bool),
+                ("flags", DataType::ULong),
+            ],
+            &[
+                vec![
+                    TblValue::UInt(3),
+                    TblValue::String("Sword".to_string()),
+                    TblValue::Float(1.5),
+                    TblValue::Bool(true),
+                    TblValue::ULong(1 << 40),
+                ],
+                vec![
+                    TblValue::UInt(1),
+                    TblValue::String("Ëxile shield".to_string()),
+                    TblValue::Float(0.0),
+                    TblValue::Bool(false),
+                    TblValue::ULong(0),
+                ],
+            ],
+        );
+
+        let table = Table::parse(&data).unwrap();
+        assert_eq!(table.name(), "Item2");
+        assert_eq!(table.columns().len(), 5);
+        assert_eq!(table.columns()[1].name, "localizedName");
+        assert_eq!(table.columns()[4].data_type, DataType::ULong);
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get(3).unwrap(),
+            [
+                TblValue::UInt(3),
+                TblValue::String("Sword".to_string()),
+                TblValue::Float(1.5),
+                TblValue::Bool(true),
+                TblValue::ULong(1 << 40),
+            ]
+        );
+        assert_eq!(
+            table.get(1).unwrap()[1],
+            TblValue::String("Ëxile shield".to_string())
+        );
+        assert!(table.get(2).is_none());
+        assert!(table.get(100).is_none());
+        assert_eq!(table.column_index("power_level"), Some(2));
+
+        assert!(matches!(
+            Table::parse(&data[..0x40]),
+            Err(TblError::UnexpectedEnd { .. })
+        ));
+        assert!(matches!(
+            Table::parse(b"DTBL"),
+            Err(TblError::InvalidMagic(_))
+        ));
+    }
+}
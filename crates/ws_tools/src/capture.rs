@@ -0,0 +1,216 @@
+use crate::{ToolError, ToolResult};
+
+/// The link type of Ethernet captures.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+
+/// A packet read from a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    /// The link-layer header type, as found in the capture file.
+    pub link_type: u32,
+    pub data: Vec<u8>,
+}
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+/// Reads the packets of a pcap or pcapng file.
+pub fn read_capture(bytes: &[u8]) -> ToolResult<Vec<CapturedPacket>> {
+    let mut input = Input::new(bytes, false);
+    let magic = input.u32()?;
+    if magic == PCAPNG_SECTION_HEADER {
+        return read_pcapng(bytes);
+    }
+
+    let big_endian = match magic {
+        PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS => false,
+        _ if magic.swap_bytes() == PCAP_MAGIC_MICROS => true,
+        _ if magic.swap_bytes() == PCAP_MAGIC_NANOS => true,
+        _ => return Err(ToolError::InvalidCapture("Unknown capture format")),
+    };
+    input.big_endian = big_endian;
+    // version, time zone, accuracy and snapshot length
+    input.take(16)?;
+    let link_type = input.u32()?;
+
+    let mut packets = Vec::new();
+    while !input.is_empty() {
+        // timestamp
+        input.take(8)?;
+        let captured_length = input.u32()? as usize;
+        let _original_length = input.u32()?;
+        let data = input.take(captured_length)?.to_vec();
+        packets.push(CapturedPacket { link_type, data });
+    }
+    Ok(packets)
+}
+
+fn read_pcapng(bytes: &[u8]) -> ToolResult<Vec<CapturedPacket>> {
+    let mut input = Input::new(bytes, false);
+    let mut interfaces = Vec::new();
+    let mut packets = Vec::new();
+
+    while !input.is_empty() {
+        let block_type = input.u32()?;
+        if block_type == PCAPNG_SECTION_HEADER {
+            // the byte order can change with each section
+            let mut peek = Input::new(input.rest(), false);
+            peek.take(4)?;
+            input.big_endian = match peek.u32()? {
+                PCAPNG_BYTE_ORDER_MAGIC => false,
+                magic if magic.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+                _ => return Err(ToolError::InvalidCapture("Invalid pcapng byte order")),
+            };
+            interfaces.clear();
+        }
+
+        let total_length = input.u32()? as usize;
+        if total_length < 12 || !total_length.is_multiple_of(4) {
+            return Err(ToolError::InvalidCapture("Invalid pcapng block length"));
+        }
+        let mut body = Input::new(input.take(total_length - 12)?, input.big_endian);
+        input.take(4)?;
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => interfaces.push(body.u16()? as u32),
+            PCAPNG_ENHANCED_PACKET => {
+                let interface = body.u32()? as usize;
+                // timestamp
+                body.take(8)?;
+                let captured_length = body.u32()? as usize;
+                let _original_length = body.u32()?;
+                let link_type = *interfaces
+                    .get(interface)
+                    .ok_or(ToolError::InvalidCapture("Packet of an unknown interface"))?;
+                let data = body.take(captured_length)?.to_vec();
+                packets.push(CapturedPacket { link_type, data });
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                let original_length = body.u32()? as usize;
+                let link_type = *interfaces
+                    .first()
+                    .ok_or(ToolError::InvalidCapture("Packet of an unknown interface"))?;
+                let length = original_length.min(body.rest().len());
+                let data = body.take(length)?.to_vec();
+                packets.push(CapturedPacket { link_type, data });
+            }
+            _ => {}
+        }
+    }
+    Ok(packets)
+}
+
+/// Reads the integers of a capture file, in its byte order.
+struct Input<'a> {
+    bytes: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Input<'a> {
+    fn new(bytes: &'a [u8], big_endian: bool) -> Self {
+        Self { bytes, big_endian }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn rest(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    fn take(&mut self, length: usize) -> ToolResult<&'a [u8]> {
+        if length > self.bytes.len() {
+            return Err(ToolError::InvalidCapture("Truncated capture"));
+        }
+        let (taken, rest) = self.bytes.split_at(length);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> ToolResult<u16> {
+        let bytes = self.take(2)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&mut self) -> ToolResult<u32> {
+        let bytes = self.take(4)?.try_into().unwrap();
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_pcap() {
+        let mut file = Vec::new();
+        file.extend(PCAP_MAGIC_MICROS.to_be_bytes());
+        file.extend([0, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff]);
+        file.extend(LINKTYPE_ETHERNET.to_be_bytes());
+        file.extend([0; 8]);
+        file.extend(3u32.to_be_bytes());
+        file.extend(3u32.to_be_bytes());
+        file.extend([1, 2, 3]);
+
+        let packets = read_capture(&file).unwrap();
+        assert_eq!(
+            packets,
+            [CapturedPacket {
+                link_type: LINKTYPE_ETHERNET,
+                data: vec![1, 2, 3],
+            }]
+        );
+
+        let error = read_capture(&file[..file.len() - 1]).unwrap_err();
+        assert_eq!(error.to_string(), "Truncated capture");
+    }
+
+    #[test]
+    fn test_read_pcapng() {
+        let block = |block_type: u32, body: &[u8]| {
+            let length = (12 + body.len()) as u32;
+            let mut block = block_type.to_le_bytes().to_vec();
+            block.extend(length.to_le_bytes());
+            block.extend(body);
+            block.extend(length.to_le_bytes());
+            block
+        };
+
+        let mut section = PCAPNG_BYTE_ORDER_MAGIC.to_le_bytes().to_vec();
+        section.extend([1, 0, 0, 0]);
+        section.extend(u64::MAX.to_le_bytes());
+        let mut packet = vec![0; 12];
+        packet.extend(2u32.to_le_bytes());
+        packet.extend(2u32.to_le_bytes());
+        packet.extend([7, 8, 0, 0]);
+
+        let mut file = block(PCAPNG_SECTION_HEADER, &section);
+        file.extend(block(
+            PCAPNG_INTERFACE_DESCRIPTION,
+            &[1, 0, 0, 0, 0, 0, 0, 0],
+        ));
+        file.extend(block(PCAPNG_ENHANCED_PACKET, &packet));
+
+        let packets = read_capture(&file).unwrap();
+        assert_eq!(
+            packets,
+            [CapturedPacket {
+                link_type: LINKTYPE_ETHERNET,
+                data: vec![7, 8],
+            }]
+        );
+    }
+}
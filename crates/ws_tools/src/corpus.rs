@@ -0,0 +1,631 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use uuid::Uuid;
+use ws_bitpack::{BitPackReader, BitPackWriter, CStringWide};
+use ws_messages::{
+    find_schema, FieldDescriptor, FieldKind, MessageRegistration, MessageRegistry, TypeShape,
+    VariantDescriptor,
+};
+
+use crate::dissector::RustType;
+use crate::ToolResult;
+
+/// How many instances of a message are generated before giving up on it, when
+/// none of them decodes.
+const ATTEMPTS: usize = 256;
+
+/// The most items of the arrays and characters of the strings generated,
+/// which keeps the seeds small for the fuzzer to mutate.
+const MAX_ITEMS: i64 = 3;
+
+/// Nested types deeper than this aren't generated, for recursive types.
+const MAX_DEPTH: usize = 16;
+
+/// The messages that a corpus was written for.
+#[derive(Debug, Default)]
+pub struct Corpus {
+    /// The opcodes of the seeds written.
+    pub seeds: Vec<u32>,
+    /// The names of the messages without a seed, because their type has no
+    /// schema or no instance generated from it decoded.
+    pub skipped: Vec<&'static str>,
+}
+
+/// Writes a seed for every message of `registry` in `dir`, in the input format
+/// of the `messages` fuzz target: the index of the message among the
+/// registered ones sorted by opcode, as a little-endian `u16`, followed by the
+/// body. Files are named after the opcode, like `0x0004`.
+///
+/// The indices depend on the messages linked in, so the corpus has to be
+/// written again when messages are added.
+pub fn write_corpus(registry: &MessageRegistry, dir: &Path, seed: u64) -> ToolResult<Corpus> {
+    std::fs::create_dir_all(dir)?;
+    let mut registrations: Vec<_> = registry.iter().collect();
+    registrations.sort_by_key(|registration| registration.id);
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut corpus = Corpus::default();
+    for (index, registration) in registrations.into_iter().enumerate() {
+        let body = match generate_body(registration, &mut rng) {
+            Some(body) => body,
+            None => {
+                corpus.skipped.push(registration.name);
+                continue;
+            }
+        };
+        let mut input = (index as u16).to_le_bytes().to_vec();
+        input.extend_from_slice(&body);
+        std::fs::write(dir.join(format!("{:#06x}", registration.id)), input)?;
+        corpus.seeds.push(registration.id);
+    }
+    Ok(corpus)
+}
+
+/// Generates a random body for a message from the schema of its type, which
+/// is only returned once it decodes to the end with the registration.
+pub fn generate_body(registration: &MessageRegistration, rng: &mut StdRng) -> Option<Vec<u8>> {
+    let schema = find_schema(registration.name)?;
+    let fields = match schema.shape {
+        TypeShape::Struct(fields) => fields,
+        _ => return None,
+    };
+    (0..ATTEMPTS).find_map(|_| {
+        let mut generator = Generator {
+            rng: &mut *rng,
+            writer: BitPackWriter::with_capacity(64),
+            depth: 0,
+        };
+        generator.fields(fields)?;
+        let body = generator.writer.finish().ok()?;
+        let mut reader = BitPackReader::new(&body);
+        (registration.read)(&mut reader).ok()?;
+        reader.finish().ok()?;
+        Some(body)
+    })
+}
+
+/// What was generated for a field, kept for the expressions of the fields
+/// after it.
+enum Generated {
+    Scalar(i64),
+    Struct(HashMap<String, i64>),
+    Other,
+}
+
+struct Generator<'a> {
+    rng: &'a mut StdRng,
+    writer: BitPackWriter<'static>,
+    depth: usize,
+}
+
+impl Generator<'_> {
+    /// Generates the fields of a struct or union variant, returning the
+    /// values of its scalar fields by path.
+    fn fields(&mut self, fields: &[FieldDescriptor]) -> Option<HashMap<String, i64>> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+        // counts are kept small, and selectors pick a variant of their union
+        let mut forced = HashMap::new();
+        for field in fields {
+            for expr in [field.length, field.present].into_iter().flatten() {
+                for name in identifiers(expr) {
+                    let value = self.rng.gen_range(0..=MAX_ITEMS);
+                    forced.entry(name).or_insert(value);
+                }
+            }
+        }
+        let mut variants = HashMap::new();
+        for field in fields {
+            if let (FieldKind::Union, Some(selector)) = (field.kind, field.variant) {
+                let variant = self.pick_variant(&RustType::parse(field.ty))?;
+                forced.insert(selector.to_string(), variant.index as i64);
+                variants.insert(field.name, variant);
+            }
+        }
+
+        let mut scope = HashMap::new();
+        for field in fields {
+            if field.aligned {
+                self.writer.align().ok()?;
+            }
+            let mut ty = RustType::parse(field.ty);
+            if let Some(present) = field.present {
+                let present = match evaluate(present, &scope) {
+                    Some(value) => value != 0,
+                    None => self.rng.gen(),
+                };
+                if !present {
+                    continue;
+                }
+                ty = unwrap_option(ty);
+            }
+            let forced = forced.get(field.name).copied();
+            let generated = match field.kind {
+                FieldKind::Value => self.value(&ty, forced)?,
+                FieldKind::Packed { bits } => Generated::Scalar(self.packed(&ty, bits, forced)?),
+                FieldKind::Array => {
+                    let element = ty.element();
+                    for _ in 0..self.length(field, &scope) {
+                        self.value(&element, None)?;
+                    }
+                    Generated::Other
+                }
+                FieldKind::PackedArray { bits } => {
+                    let element = ty.element();
+                    for _ in 0..self.length(field, &scope) {
+                        self.packed(&element, bits, None)?;
+                    }
+                    Generated::Other
+                }
+                FieldKind::Union => {
+                    let variant = variants.get(field.name)?;
+                    self.fields(variant.fields)?;
+                    Generated::Other
+                }
+                FieldKind::InlineUnion { bits } => {
+                    self.inline_union(&unwrap_option(ty), bits)?;
+                    Generated::Other
+                }
+                FieldKind::InlineUnionArray { bits } => {
+                    let (element, length) = match &ty {
+                        RustType::Array(item, count) => ((**item).clone(), count.parse().ok()?),
+                        _ => (ty.element(), self.length(field, &scope)),
+                    };
+                    for _ in 0..length {
+                        self.inline_union(&element, bits)?;
+                    }
+                    Generated::Other
+                }
+                FieldKind::Ascii => {
+                    let text = self.text();
+                    self.writer.write_ascii(&text).ok()?;
+                    Generated::Other
+                }
+                FieldKind::Bytes => {
+                    for _ in 0..self.length(field, &scope) {
+                        self.writer.write_u64(self.rng.gen::<u8>().into(), 8).ok()?;
+                    }
+                    Generated::Other
+                }
+                FieldKind::WideFixed { units } => {
+                    self.wide_fixed(units)?;
+                    Generated::Other
+                }
+                // undefined bits may be refused, so no flag is set
+                FieldKind::Flags { bits, .. } => {
+                    self.writer.write_u64(0, bits).ok()?;
+                    Generated::Scalar(0)
+                }
+            };
+            match generated {
+                Generated::Scalar(value) => {
+                    scope.insert(field.name.to_string(), value);
+                }
+                Generated::Struct(fields) => {
+                    for (path, value) in fields {
+                        scope.insert(format!("{}.{path}", field.name), value);
+                    }
+                }
+                Generated::Other => (),
+            }
+        }
+        self.depth -= 1;
+        Some(scope)
+    }
+
+    /// Generates a value read with the `ReadValue` impl of its type.
+    fn value(&mut self, ty: &RustType, forced: Option<i64>) -> Option<Generated> {
+        let (name, args) = match ty {
+            RustType::Named(name, args) => (name.as_str(), args.as_slice()),
+            RustType::Array(item, count) => {
+                for _ in 0..count.parse::<usize>().ok()? {
+                    self.value(item, None)?;
+                }
+                return Some(Generated::Other);
+            }
+            RustType::Tuple(items) => {
+                for item in items {
+                    self.value(item, None)?;
+                }
+                return Some(Generated::Other);
+            }
+            RustType::Other(_) => return None,
+        };
+        if let Some(bits) = integer_bits(name) {
+            let value = forced.unwrap_or_else(|| self.rng.gen());
+            self.writer.write_u64(mask(value, bits), bits).ok()?;
+            return Some(Generated::Scalar(value));
+        }
+        let writer = &mut self.writer;
+        match (name, args) {
+            ("Option", [inner]) => {
+                // an optional value without a condition ends the data
+                if self.rng.gen() {
+                    self.value(inner, forced)?;
+                }
+            }
+            ("Box" | "Rc" | "Arc", [inner]) => return self.value(inner, forced),
+            ("bool", []) => {
+                let value = forced.map_or_else(|| self.rng.gen(), |value| value != 0);
+                writer.write_bit(value).ok()?;
+                return Some(Generated::Scalar(value.into()));
+            }
+            ("f32", []) => writer.write(&self.rng.gen_range(-1000.0f32..1000.0)).ok()?,
+            ("f64", []) => writer.write(&self.rng.gen_range(-1000.0f64..1000.0)).ok()?,
+            ("char", []) => writer.write(&self.rng.gen_range('a'..='z')).ok()?,
+            ("String", []) => {
+                let text = self.text();
+                self.writer.write(&text).ok()?;
+            }
+            ("AsciiString", []) => {
+                let text = self.text();
+                self.writer.write_ascii(&text).ok()?;
+            }
+            ("CStringWide", []) => {
+                let text = CStringWide(self.text());
+                self.writer.write(&text).ok()?;
+            }
+            ("Uuid", []) => writer.write(&Uuid::from_bytes(self.rng.gen())).ok()?,
+            ("Guid", []) => writer.write_u64(self.rng.gen(), 64).ok()?,
+            ("Packed", [inner, RustType::Other(bits)]) => {
+                let value = self.packed(inner, bits.parse().ok()?, forced)?;
+                return Some(Generated::Scalar(value));
+            }
+            ("Blob", [RustType::Other(bits), ..]) => {
+                let length = self.rng.gen_range(0..=MAX_ITEMS) as u64;
+                self.writer.write_u64(length, bits.parse().ok()?).ok()?;
+                for _ in 0..length {
+                    self.writer.write_u64(self.rng.gen::<u8>().into(), 8).ok()?;
+                }
+            }
+            ("WideStringFixed", [RustType::Other(units)]) => {
+                self.wide_fixed(units.parse().ok()?)?
+            }
+            (name, []) => match find_schema(name)?.shape {
+                TypeShape::Struct(fields) => return self.fields(fields).map(Generated::Struct),
+                TypeShape::Enum { repr, values } => {
+                    let value = self.enum_value(values, forced, 64);
+                    let bits = integer_bits(repr)?;
+                    self.writer.write_u64(mask(value, bits), bits).ok()?;
+                    return Some(Generated::Scalar(value));
+                }
+                // unions are only read through their selector
+                TypeShape::Union(_) => return None,
+            },
+            _ => return None,
+        }
+        Some(Generated::Other)
+    }
+
+    /// Generates an integer, bool or enum on `bits` bits.
+    fn packed(&mut self, ty: &RustType, bits: usize, forced: Option<i64>) -> Option<i64> {
+        let name = match ty {
+            RustType::Named(name, args) if args.is_empty() => name.as_str(),
+            _ => return None,
+        };
+        let value = match find_schema(name).map(|schema| schema.shape) {
+            Some(TypeShape::Enum { values, .. }) => self.enum_value(values, forced, bits),
+            Some(_) => return None,
+            None => forced.unwrap_or_else(|| self.rng.gen()),
+        };
+        self.writer.write_u64(mask(value, bits), bits).ok()?;
+        Some(value)
+    }
+
+    /// Picks the forced value of an enum, or one of its values fitting in
+    /// `bits` bits.
+    fn enum_value(&mut self, values: &[(&str, i64)], forced: Option<i64>, bits: usize) -> i64 {
+        if let Some(value) = forced {
+            return value;
+        }
+        let fitting: Vec<i64> = values
+            .iter()
+            .map(|(_, value)| *value)
+            .filter(|value| bits >= 64 || mask(*value, bits) == *value as u64)
+            .collect();
+        match fitting.is_empty() {
+            true => 0,
+            false => fitting[self.rng.gen_range(0..fitting.len())],
+        }
+    }
+
+    fn pick_variant(&mut self, ty: &RustType) -> Option<&'static VariantDescriptor> {
+        let name = match unwrap_option(ty.clone()) {
+            RustType::Named(name, args) if args.is_empty() => name,
+            _ => return None,
+        };
+        match find_schema(&name)?.shape {
+            TypeShape::Union(variants) if !variants.is_empty() => {
+                Some(&variants[self.rng.gen_range(0..variants.len())])
+            }
+            _ => None,
+        }
+    }
+
+    fn inline_union(&mut self, ty: &RustType, bits: usize) -> Option<()> {
+        let variant = self.pick_variant(ty)?;
+        self.writer.write_u64(variant.index as u64, bits).ok()?;
+        self.fields(variant.fields)?;
+        Some(())
+    }
+
+    fn wide_fixed(&mut self, units: usize) -> Option<()> {
+        let length = self.rng.gen_range(0..=units);
+        for unit in 0..units {
+            let unit = match unit < length {
+                true => self.rng.gen_range(b'a'..=b'z').into(),
+                false => 0,
+            };
+            self.writer.write_u64(unit, 16).ok()?;
+        }
+        Some(())
+    }
+
+    /// Returns the number of items of an array field, from its length
+    /// expression when it can be evaluated.
+    fn length(&mut self, field: &FieldDescriptor, scope: &HashMap<String, i64>) -> usize {
+        let length = field.length.and_then(|length| evaluate(length, scope));
+        let length = length.unwrap_or_else(|| self.rng.gen_range(0..=MAX_ITEMS));
+        length.clamp(0, 4 * MAX_ITEMS) as usize
+    }
+
+    fn text(&mut self) -> String {
+        let length = self.rng.gen_range(0..=MAX_ITEMS);
+        (0..length).map(|_| self.rng.gen_range('a'..='z')).collect()
+    }
+}
+
+fn integer_bits(name: &str) -> Option<usize> {
+    match name {
+        "u8" | "i8" => Some(8),
+        "u16" | "i16" => Some(16),
+        "u32" | "i32" => Some(32),
+        "u64" | "i64" | "usize" | "isize" => Some(64),
+        _ => None,
+    }
+}
+
+/// Keeps the low `bits` bits of a value, two's complement for negative ones.
+fn mask(value: i64, bits: usize) -> u64 {
+    match bits {
+        64 => value as u64,
+        _ => value as u64 & ((1 << bits) - 1),
+    }
+}
+
+fn unwrap_option(ty: RustType) -> RustType {
+    match ty {
+        RustType::Named(name, mut args) if name == "Option" && args.len() == 1 => args.remove(0),
+        ty => ty,
+    }
+}
+
+/// A token of a length expression or presence condition.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    /// A field path, like `header.count`.
+    Path(String),
+    Operator(&'static str),
+    Open,
+    Close,
+}
+
+const OPERATORS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "<<", ">>", "<", ">", "&", "|", "^", "+", "-", "*", "/",
+    "%", "!",
+];
+
+fn tokenize(expr: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let c = rest.chars().next()?;
+        let length = if c.is_ascii_digit() {
+            let length = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let number = rest[..length].replace('_', "");
+            let number = match number.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            tokens.push(Token::Number(number));
+            length
+        } else if c.is_alphabetic() || c == '_' {
+            let length = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '.')
+                .unwrap_or(rest.len());
+            match &rest[..length] {
+                "true" => tokens.push(Token::Number(1)),
+                "false" => tokens.push(Token::Number(0)),
+                // casts don't change the small values generated
+                "as" => tokens.push(Token::Operator("as")),
+                path => tokens.push(Token::Path(path.to_string())),
+            }
+            length
+        } else if c == '(' || c == ')' {
+            tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            1
+        } else {
+            let operator = OPERATORS.iter().find(|op| rest.starts_with(**op))?;
+            tokens.push(Token::Operator(operator));
+            operator.len()
+        };
+        rest = rest[length..].trim_start();
+    }
+    Some(tokens)
+}
+
+/// Returns the field paths used by an expression.
+fn identifiers(expr: &str) -> Vec<String> {
+    let tokens = tokenize(expr).unwrap_or_default();
+    let mut paths = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        // the type of a cast isn't a field
+        let cast = index > 0 && tokens[index - 1] == Token::Operator("as");
+        if let (Token::Path(path), false) = (token, cast) {
+            paths.push(path.clone());
+        }
+    }
+    paths
+}
+
+/// Evaluates a length expression or presence condition with the values of
+/// the previous fields, or returns `None` if it uses anything else.
+fn evaluate(expr: &str, scope: &HashMap<String, i64>) -> Option<i64> {
+    let tokens = tokenize(expr)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        position: 0,
+        scope,
+    };
+    let value = parser.binary(0)?;
+    (parser.position == tokens.len()).then_some(value)
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    scope: &'a HashMap<String, i64>,
+}
+
+impl Parser<'_> {
+    fn binary(&mut self, min_precedence: u8) -> Option<i64> {
+        let mut left = self.unary()?;
+        while let Some(Token::Operator(op)) = self.tokens.get(self.position) {
+            let precedence = precedence(op)?;
+            if precedence < min_precedence {
+                break;
+            }
+            self.position += 1;
+            let right = self.binary(precedence + 1)?;
+            left = match *op {
+                "||" => ((left != 0) || (right != 0)).into(),
+                "&&" => ((left != 0) && (right != 0)).into(),
+                "==" => (left == right).into(),
+                "!=" => (left != right).into(),
+                "<" => (left < right).into(),
+                ">" => (left > right).into(),
+                "<=" => (left <= right).into(),
+                ">=" => (left >= right).into(),
+                "|" => left | right,
+                "^" => left ^ right,
+                "&" => left & right,
+                "<<" => left.checked_shl(right.try_into().ok()?)?,
+                ">>" => left.checked_shr(right.try_into().ok()?)?,
+                "+" => left.checked_add(right)?,
+                "-" => left.checked_sub(right)?,
+                "*" => left.checked_mul(right)?,
+                "/" => left.checked_div(right)?,
+                "%" => left.checked_rem(right)?,
+                _ => return None,
+            };
+        }
+        Some(left)
+    }
+
+    fn unary(&mut self) -> Option<i64> {
+        let token = self.tokens.get(self.position)?.clone();
+        self.position += 1;
+        let value = match token {
+            Token::Number(value) => value,
+            Token::Path(path) => *self.scope.get(&path)?,
+            Token::Operator("!") => (self.unary()? == 0).into(),
+            Token::Operator("-") => -self.unary()?,
+            Token::Open => {
+                let value = self.binary(0)?;
+                match self.tokens.get(self.position) {
+                    Some(Token::Close) => self.position += 1,
+                    _ => return None,
+                }
+                value
+            }
+            _ => return None,
+        };
+        // casts bind tighter than the binary operators
+        while self.tokens.get(self.position) == Some(&Token::Operator("as")) {
+            match self.tokens.get(self.position + 1) {
+                Some(Token::Path(_)) => self.position += 2,
+                _ => return None,
+            }
+        }
+        Some(value)
+    }
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "==" | "!=" | "<" | ">" | "<=" | ">=" => 3,
+        "|" => 4,
+        "^" => 5,
+        "&" => 6,
+        "<<" | ">>" => 7,
+        "+" | "-" => 8,
+        "*" | "/" | "%" => 9,
+        // casts are handled with their operand
+        "as" => return None,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evaluate() {
+        let scope = HashMap::from([
+            ("count".to_string(), 3),
+            ("flags".to_string(), 0b101),
+            ("header.count".to_string(), 2),
+        ]);
+        assert_eq!(evaluate("count", &scope), Some(3));
+        assert_eq!(evaluate("8", &scope), Some(8));
+        assert_eq!(evaluate("extra as usize + 1", &scope), None);
+        assert_eq!(evaluate("count as usize + 1", &scope), Some(4));
+        assert_eq!(evaluate("flags & 0x4 != 0", &scope), Some(1));
+        assert_eq!(evaluate("flags & 0x2 != 0", &scope), Some(0));
+        assert_eq!(evaluate("header.count * (1 + 1)", &scope), Some(4));
+        assert_eq!(evaluate("count >", &scope), None);
+        assert_eq!(
+            identifiers("extra_count as usize + header.count"),
+            ["extra_count", "header.count"]
+        );
+    }
+
+    #[test]
+    fn test_write_corpus() {
+        let dir = std::env::temp_dir().join(format!("ws_corpus_{}", std::process::id()));
+        let registry = MessageRegistry::with_registered();
+        let corpus = write_corpus(&registry, &dir, 7).unwrap();
+        assert_eq!(corpus.seeds.len() + corpus.skipped.len(), registry.len());
+        // the login messages have strings, byte arrays and packed enums
+        for name in ["ClientHelloAuth", "ServerAuthChallenge", "ServerRealmList"] {
+            let registration = registry.iter().find(|r| r.name == name);
+            let id = registration.unwrap().id;
+            assert!(corpus.seeds.contains(&id), "no seed for {name}");
+        }
+        // most messages get one
+        assert!(corpus.seeds.len() > registry.len() * 3 / 4, "{corpus:?}");
+
+        let mut ids: Vec<u32> = registry.iter().map(|r| r.id).collect();
+        ids.sort();
+        for id in &corpus.seeds {
+            let input = std::fs::read(dir.join(format!("{id:#06x}"))).unwrap();
+            let index = u16::from_le_bytes([input[0], input[1]]) as usize;
+            assert_eq!(ids[index], *id);
+            let registration = registry.get(*id).unwrap();
+            let mut reader = BitPackReader::new(&input[2..]);
+            assert!((registration.read)(&mut reader).is_ok());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
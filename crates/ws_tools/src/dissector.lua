@@ -0,0 +1,367 @@
+-- The part of the dissector that doesn't depend on the messages. It reads the
+-- `messages` and `types` tables generated above it.
+
+local proto = Proto("wildstar", "WildStar Protocol")
+local pf_size = ProtoField.uint32("wildstar.size", "Size", base.DEC)
+local pf_opcode = ProtoField.uint16("wildstar.opcode", "Opcode", base.HEX, messages)
+proto.fields = { pf_size, pf_opcode }
+
+local ef_failed = ProtoExpert.new("wildstar.failed", "Message not fully dissected",
+    expert.group.MALFORMED, expert.severity.WARN)
+proto.experts = { ef_failed }
+
+local SIZE_BYTES = 3
+local HEADER_BYTES = 5
+local SIZE_BITS = 24
+local OPCODE_BITS = 11
+
+-- Reads bits starting from the least significant bit of each byte, like
+-- ws_bitpack.
+local Reader = {}
+Reader.__index = Reader
+
+function Reader.new(tvb)
+    return setmetatable({ tvb = tvb, pos = 0, limit = tvb:len() * 8 }, Reader)
+end
+
+function Reader:read(bits)
+    if self.pos + bits > self.limit then
+        error(string.format("out of bounds at bit %d, %d bits needed but %d available",
+            self.pos, bits, self.limit - self.pos), 0)
+    end
+    local value, shift = 0, 0
+    while shift < bits do
+        local offset = self.pos % 8
+        local take = math.min(8 - offset, bits - shift)
+        local byte = self.tvb(self.pos // 8, 1):uint()
+        value = value | (((byte >> offset) & ((1 << take) - 1)) << shift)
+        self.pos = self.pos + take
+        shift = shift + take
+    end
+    return value
+end
+
+function Reader:align()
+    self.pos = (self.pos + 7) // 8 * 8
+end
+
+function Reader:at_end()
+    return (self.pos + 7) // 8 >= self.limit // 8
+end
+
+-- Empty ranges start at 0, a range can't start at the end of the packet.
+function Reader:bytes(first, last)
+    if last <= first then
+        return self.tvb(0, 0)
+    end
+    return self.tvb(first, last - first)
+end
+
+-- The bytes touched by the bits read since `start`.
+function Reader:range(start)
+    return self:bytes(start // 8, (self.pos + 7) // 8)
+end
+
+-- The bytes left, for trees whose length is only known once read.
+function Reader:rest()
+    return self:bytes(self.pos // 8, self.limit // 8)
+end
+
+local function signed(value, bits)
+    if bits < 64 and value & (1 << (bits - 1)) ~= 0 then
+        return value - (1 << bits)
+    end
+    return value
+end
+
+local function read_length(r)
+    local extended = r:read(1) == 1
+    return r:read(extended and 15 or 7)
+end
+
+-- Decodes UTF-16 code units, including surrogate pairs.
+local function utf16_string(units)
+    local length = #units
+    local chars, i = {}, 1
+    while i <= length do
+        local unit = units[i]
+        if unit >= 0xd800 and unit < 0xdc00 and i < length then
+            unit = 0x10000 + ((unit - 0xd800) << 10) + (units[i + 1] - 0xdc00)
+            i = i + 1
+        end
+        chars[#chars + 1] = utf8.char(unit)
+        i = i + 1
+    end
+    return table.concat(chars)
+end
+
+local function read_string(r)
+    local units = {}
+    for i = 1, read_length(r) do
+        units[i] = r:read(16)
+    end
+    return utf16_string(units)
+end
+
+-- Fixed-width strings are padded with zeros, which aren't part of the string.
+local function read_wide_fixed(r, count)
+    local units, ended = {}, false
+    for _ = 1, count do
+        local unit = r:read(16)
+        ended = ended or unit == 0
+        if not ended then
+            units[#units + 1] = unit
+        end
+    end
+    return utf16_string(units)
+end
+
+local function read_cstring_wide(r)
+    local units = {}
+    while true do
+        local unit = r:read(16)
+        if unit == 0 then
+            return utf16_string(units)
+        end
+        units[#units + 1] = unit
+    end
+end
+
+local function read_ascii(r)
+    local chars = {}
+    for i = 1, read_length(r) do
+        chars[i] = string.char(r:read(8))
+    end
+    return table.concat(chars)
+end
+
+-- GUIDs use the mixed-endian layout of .NET's `Guid.ToByteArray()`.
+local function read_uuid(r)
+    -- GUIDs align themselves
+    r:align()
+    local b = {}
+    for i = 1, 16 do
+        b[i] = r:read(8)
+    end
+    return string.format("%02x%02x%02x%02x-%02x%02x-%02x%02x-%02x%02x-%02x%02x%02x%02x%02x%02x",
+        b[4], b[3], b[2], b[1], b[6], b[5], b[8], b[7],
+        b[9], b[10], b[11], b[12], b[13], b[14], b[15], b[16])
+end
+
+local primitives = {
+    u8 = function(r) return r:read(8) end,
+    u16 = function(r) return r:read(16) end,
+    u32 = function(r) return r:read(32) end,
+    u64 = function(r) return r:read(64) end,
+    i8 = function(r) return signed(r:read(8), 8) end,
+    i16 = function(r) return signed(r:read(16), 16) end,
+    i32 = function(r) return signed(r:read(32), 32) end,
+    i64 = function(r) return r:read(64) end,
+    bool = function(r) return r:read(1) == 1 end,
+    f32 = function(r) return (string.unpack("<f", string.pack("<I4", r:read(32)))) end,
+    f64 = function(r) return (string.unpack("<d", string.pack("<i8", r:read(64)))) end,
+    char = function(r) return utf8.char(r:read(16)) end,
+    String = read_string,
+    ascii = read_ascii,
+    uuid = read_uuid,
+    cstring_wide = read_cstring_wide,
+}
+primitives.usize = primitives.u64
+primitives.isize = primitives.i64
+
+local function format_value(value)
+    if type(value) == "string" then
+        return '"' .. value .. '"'
+    end
+    return tostring(value)
+end
+
+local function enum_text(enum, value)
+    return string.format("%s (%d)", enum.values[value] or "Unknown", value)
+end
+
+local function add_item(tree, r, start, label, text)
+    tree:add(proto, r:range(start), label .. ": " .. text)
+end
+
+local read_type, read_fields
+
+local function read_bytes(r, count, tree, label)
+    local start = r.pos
+    for _ = 1, count do
+        r:read(8)
+    end
+    add_item(tree, r, start, label, count .. " bytes")
+    return count
+end
+
+local function read_list(r, item, count, tree, label)
+    local start = r.pos
+    local sub = tree:add(proto, r:rest(), string.format("%s (%d)", label, count))
+    local values = {}
+    for i = 1, count do
+        values[i] = read_type(r, item, sub, "[" .. (i - 1) .. "]")
+    end
+    sub:set_len(r:range(start):len())
+    return values
+end
+
+local function read_packed(r, ty, tree, label)
+    local start = r.pos
+    local value = r:read(ty.bits)
+    local def = types[ty.packed]
+    local text
+    if def and def.enum then
+        if def.enum.repr:sub(1, 1) == "i" then
+            value = signed(value, ty.bits)
+        end
+        text = enum_text(def.enum, value)
+    else
+        if ty.packed:sub(1, 1) == "i" then
+            value = signed(value, ty.bits)
+        end
+        text = tostring(value)
+    end
+    add_item(tree, r, start, label, text)
+    return value
+end
+
+read_type = function(r, ty, tree, label, selector)
+    local start = r.pos
+    if type(ty) == "table" then
+        if ty.packed then
+            return read_packed(r, ty, tree, label)
+        elseif ty.inline_union then
+            local selector = r:read(ty.bits)
+            return read_type(r, ty.inline_union, tree, label, selector)
+        elseif ty.wide_fixed then
+            local value = read_wide_fixed(r, ty.wide_fixed)
+            add_item(tree, r, start, label, format_value(value))
+            return value
+        elseif ty.blob then
+            return read_bytes(r, r:read(ty.blob), tree, label)
+        elseif ty.array then
+            return read_list(r, ty.array, ty.count, tree, label)
+        elseif ty.tuple then
+            local sub = tree:add(proto, r:rest(), label)
+            local values = {}
+            for i, item in ipairs(ty.tuple) do
+                values[i] = read_type(r, item, sub, "[" .. (i - 1) .. "]")
+            end
+            sub:set_len(r:range(start):len())
+            return values
+        end
+        error(label .. " has a type that can't be dissected: " .. ty.unknown, 0)
+    end
+
+    local primitive = primitives[ty]
+    if primitive then
+        local value = primitive(r)
+        add_item(tree, r, start, label, format_value(value))
+        return value
+    end
+
+    local def = types[ty]
+    if def == nil then
+        error(label .. " has a type that can't be dissected: " .. ty, 0)
+    end
+    if def.enum then
+        local value = primitives[def.enum.repr](r)
+        add_item(tree, r, start, label, enum_text(def.enum, value))
+        return value
+    end
+
+    local sub = tree:add(proto, r:rest(), label)
+    local fields = def.struct
+    if def.union then
+        if type(selector) == "boolean" then
+            selector = selector and 1 or 0
+        end
+        local variant = def.union[selector]
+        if variant == nil then
+            error(string.format("%s has no variant %s", ty, tostring(selector)), 0)
+        end
+        sub:append_text(": " .. variant.name)
+        fields = variant.fields
+    end
+    local value = read_fields(r, fields, sub)
+    sub:set_len(r:range(start):len())
+    return value
+end
+
+-- Reads the fields of a struct or union variant. Length expressions and
+-- presence conditions get the values read so far.
+read_fields = function(r, fields, tree)
+    local v = {}
+    for _, field in ipairs(fields) do
+        if field.aligned then
+            r:align()
+        end
+        if field.trailing and r:at_end() then
+            break
+        end
+        if field.present == nil or field.present(v) then
+            if field.bytes then
+                v[field.name] = read_bytes(r, field.length(v), tree, field.name)
+            elseif field.length then
+                v[field.name] = read_list(r, field.item, field.length(v), tree, field.name)
+            else
+                local selector = field.variant and v[field.variant]
+                v[field.name] = read_type(r, field.ty, tree, field.name, selector)
+            end
+        end
+    end
+    return v
+end
+
+local info_frame = nil
+
+local function set_info(pinfo, text)
+    if info_frame == pinfo.number then
+        pinfo.cols.info:append(", " .. text)
+    else
+        pinfo.cols.info:set(text)
+        info_frame = pinfo.number
+    end
+end
+
+local function dissect_packet(tvb, pinfo, tree)
+    pinfo.cols.protocol = "WildStar"
+    local subtree = tree:add(proto, tvb())
+    subtree:add_le(pf_size, tvb(0, SIZE_BYTES))
+
+    local r = Reader.new(tvb)
+    r.pos = SIZE_BITS
+    local opcode = r:read(OPCODE_BITS)
+    subtree:add(pf_opcode, tvb(SIZE_BYTES, HEADER_BYTES - SIZE_BYTES), opcode)
+
+    local name = messages[opcode]
+    if name == nil then
+        set_info(pinfo, string.format("Unknown (0x%04x)", opcode))
+        return tvb:len()
+    end
+    set_info(pinfo, name)
+    subtree:append_text(", " .. name)
+
+    local ok, message = pcall(read_type, r, name, subtree, name)
+    if not ok then
+        subtree:add_proto_expert_info(ef_failed, message)
+    elseif not r:at_end() then
+        subtree:add_proto_expert_info(ef_failed,
+            string.format("%d bits left after the message", r.limit - r.pos))
+    end
+    return tvb:len()
+end
+
+-- The size in the header includes the header itself.
+local function packet_length(tvb, pinfo, offset)
+    return math.max(tvb(offset, SIZE_BYTES):le_uint(), HEADER_BYTES)
+end
+
+function proto.dissector(tvb, pinfo, tree)
+    dissect_tcp_pdus(tvb, tree, SIZE_BYTES, packet_length, dissect_packet)
+end
+
+local tcp_port = DissectorTable.get("tcp.port")
+tcp_port:add(23115, proto)
+tcp_port:add(24000, proto)
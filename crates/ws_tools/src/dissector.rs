@@ -0,0 +1,476 @@
+use std::fmt::Write;
+
+use ws_messages::{
+    registered_schemas, FieldDescriptor, FieldKind, MessageRegistry, TypeSchema, TypeShape,
+};
+
+/// The part of the dissector that reads the generated tables.
+const RUNTIME: &str = include_str!("dissector.lua");
+
+/// Types with a hand-written `ReadValue` impl that reads like a primitive, so
+/// they have no schema.
+const PRIMITIVE_ALIASES: &[(&str, &str)] = &[
+    ("Guid", "u64"),
+    ("AsciiString", "ascii"),
+    ("Uuid", "uuid"),
+    ("CStringWide", "cstring_wide"),
+];
+
+/// Generates a Wireshark dissector in Lua for the messages of `registry`, from
+/// the schemas of the derived types.
+///
+/// The dissector decodes plaintext packets on the ports of the realm and world
+/// servers. Encrypted packets and fields whose type has no schema, like
+/// messages with a hand-written `ReadValue` impl, are shown up to where they
+/// can't be read anymore. It needs Wireshark 4.4 or later, for the integers
+/// and bitwise operators of Lua 5.4.
+pub fn generate_dissector(registry: &MessageRegistry) -> String {
+    let mut out = String::from(
+        "-- WildStar dissector generated by `ws_tools dissector` from the message\n\
+         -- definitions. Regenerate it rather than editing it.\n\n\
+         local function unsupported(expr)\n    \
+             return function() error(\"can't evaluate `\" .. expr .. \"`\", 0) end\n\
+         end\n\n",
+    );
+
+    let mut messages: Vec<_> = registry.iter().collect();
+    messages.sort_by_key(|registration| registration.id);
+    out.push_str("local messages = {\n");
+    for registration in messages {
+        let name = registration.name;
+        writeln!(
+            out,
+            "    [{:#06x}] = {},",
+            registration.id,
+            lua_string(name)
+        )
+        .unwrap();
+    }
+    out.push_str("}\n\n");
+
+    let mut schemas: Vec<&TypeSchema> = registered_schemas().collect();
+    schemas.sort_by_key(|schema| schema.name);
+    // the first schema of a name wins, like `find_schema`
+    schemas.dedup_by_key(|schema| schema.name);
+    out.push_str("local types = {}\n");
+    for schema in schemas {
+        write!(out, "types[{}] = ", lua_string(schema.name)).unwrap();
+        write_shape(&mut out, &schema.shape);
+        out.push('\n');
+    }
+    out.push('\n');
+
+    out.push_str(RUNTIME);
+    out
+}
+
+fn write_shape(out: &mut String, shape: &TypeShape) {
+    match shape {
+        TypeShape::Struct(fields) => {
+            out.push_str("{ struct = ");
+            write_fields(out, fields, 0);
+            out.push_str(" }");
+        }
+        TypeShape::Union(variants) => {
+            out.push_str("{ union = {\n");
+            for variant in *variants {
+                for selector in variant.selectors {
+                    write!(
+                        out,
+                        "    [{}] = {{ name = {}, fields = ",
+                        selector,
+                        lua_string(variant.name)
+                    )
+                    .unwrap();
+                    write_fields(out, variant.fields, 1);
+                    out.push_str(" },\n");
+                }
+            }
+            out.push_str("} }");
+        }
+        TypeShape::Enum { repr, values } => {
+            write!(out, "{{ enum = {{ repr = {}, values = {{", lua_string(repr)).unwrap();
+            for (name, value) in *values {
+                write!(out, " [{value}] = {},", lua_string(name)).unwrap();
+            }
+            out.push_str(" } } }");
+        }
+    }
+}
+
+fn write_fields(out: &mut String, fields: &[FieldDescriptor], depth: usize) {
+    let indent = "    ".repeat(depth);
+    out.push_str("{\n");
+    for field in fields {
+        write!(out, "{indent}    {{ name = {}", lua_string(field.name)).unwrap();
+        let ty = RustType::parse(field.ty);
+        match field.kind {
+            FieldKind::Value | FieldKind::Union => write!(out, ", ty = {}", lua_type(&ty)).unwrap(),
+            FieldKind::Packed { bits } => write!(out, ", ty = {}", packed_type(&ty, bits)).unwrap(),
+            FieldKind::Array => write!(out, ", item = {}", lua_type(&ty.element())).unwrap(),
+            FieldKind::PackedArray { bits } => {
+                write!(out, ", item = {}", packed_type(&ty.element(), bits)).unwrap()
+            }
+            FieldKind::InlineUnion { bits } => {
+                write!(out, ", ty = {}", inline_union_type(&ty, bits)).unwrap()
+            }
+            FieldKind::InlineUnionArray { bits } => {
+                write!(out, ", item = {}", inline_union_type(&ty.element(), bits)).unwrap()
+            }
+            FieldKind::Ascii => out.push_str(", ty = \"ascii\""),
+            FieldKind::Bytes => out.push_str(", bytes = true"),
+            FieldKind::WideFixed { units } => {
+                write!(out, ", ty = {{ wide_fixed = {units} }}").unwrap()
+            }
+            FieldKind::Flags { bits, .. } => {
+                write!(out, ", ty = {}", packed_type(&RustType::named("u64"), bits)).unwrap()
+            }
+        }
+        if let Some(length) = field.length {
+            write!(out, ", length = {}", lua_function(length)).unwrap();
+        }
+        if let Some(variant) = field.variant {
+            write!(out, ", variant = {}", lua_string(variant)).unwrap();
+        }
+        if let Some(present) = field.present {
+            write!(out, ", present = {}", lua_function(present)).unwrap();
+        }
+        if field.aligned {
+            out.push_str(", aligned = true");
+        }
+        if field.trailing {
+            out.push_str(", trailing = true");
+        }
+        out.push_str(" },\n");
+    }
+    write!(out, "{indent}}}").unwrap();
+}
+
+/// A type from a field definition, parsed just enough to find what to read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RustType {
+    /// A type name without its path, with its generic arguments.
+    Named(String, Vec<RustType>),
+    Array(Box<RustType>, String),
+    Tuple(Vec<RustType>),
+    /// A const generic argument, or anything that couldn't be parsed.
+    Other(String),
+}
+
+impl RustType {
+    fn named(name: &str) -> Self {
+        Self::Named(name.to_string(), Vec::new())
+    }
+
+    pub(crate) fn parse(ty: &str) -> Self {
+        let mut chars = ty.chars().peekable();
+        let parsed = Self::parse_next(&mut chars);
+        match chars.next() {
+            None => parsed,
+            Some(_) => Self::Other(ty.to_string()),
+        }
+    }
+
+    fn parse_next(chars: &mut std::iter::Peekable<std::str::Chars>) -> Self {
+        match chars.peek() {
+            Some('[') => {
+                chars.next();
+                let item = Self::parse_next(chars);
+                let count = Self::take_until(chars, &[']']);
+                chars.next();
+                match count.strip_prefix(';') {
+                    Some(count) => Self::Array(Box::new(item), count.to_string()),
+                    None => Self::Other(format!("[{count}]")),
+                }
+            }
+            Some('(') => {
+                chars.next();
+                let items = Self::parse_list(chars, ')');
+                Self::Tuple(items)
+            }
+            Some(c) if c.is_alphabetic() || *c == '_' => {
+                let path = Self::take_until(chars, &['<', ',', '>', ']', ')', ';']);
+                let name = strip_path(&path).to_string();
+                match chars.peek() {
+                    Some('<') => {
+                        chars.next();
+                        Self::Named(name, Self::parse_list(chars, '>'))
+                    }
+                    _ => Self::Named(name, Vec::new()),
+                }
+            }
+            _ => {
+                let other = Self::take_until(chars, &[',', '>', ']', ')', ';']);
+                if other.is_empty() {
+                    // a stray closing bracket, skip it to keep going
+                    chars.next();
+                }
+                Self::Other(other)
+            }
+        }
+    }
+
+    /// Parses comma-separated types up to `end`, which is consumed.
+    fn parse_list(chars: &mut std::iter::Peekable<std::str::Chars>, end: char) -> Vec<Self> {
+        let mut items = Vec::new();
+        loop {
+            match chars.peek() {
+                Some(c) if *c == end => {
+                    chars.next();
+                    return items;
+                }
+                Some(',') => {
+                    chars.next();
+                }
+                Some(_) => items.push(Self::parse_next(chars)),
+                None => return items,
+            }
+        }
+    }
+
+    /// Takes characters up to one of `ends` outside of brackets.
+    fn take_until(chars: &mut std::iter::Peekable<std::str::Chars>, ends: &[char]) -> String {
+        let mut taken = String::new();
+        let mut depth = 0;
+        while let Some(&c) = chars.peek() {
+            match c {
+                '<' | '(' | '[' | '{' if depth > 0 || !ends.contains(&c) => depth += 1,
+                '>' | ')' | ']' | '}' if depth > 0 => depth -= 1,
+                _ if ends.contains(&c) => break,
+                _ => (),
+            }
+            taken.push(c);
+            chars.next();
+        }
+        taken
+    }
+
+    /// Returns the type of the items of an array field.
+    pub(crate) fn element(&self) -> Self {
+        match self {
+            Self::Named(name, args) if name == "Vec" && args.len() == 1 => args[0].clone(),
+            Self::Named(name, args) if name.ends_with("Map") && args.len() == 2 => {
+                Self::Tuple(args.clone())
+            }
+            _ => self.clone(),
+        }
+    }
+}
+
+/// Returns the Lua description of a type for the runtime's `read_type`.
+fn lua_type(ty: &RustType) -> String {
+    match ty {
+        RustType::Named(name, args) => match (name.as_str(), args.as_slice()) {
+            ("Option" | "Box" | "Rc" | "Arc", [inner]) => lua_type(inner),
+            ("Packed", [inner, RustType::Other(bits)]) => packed_type(inner, bits),
+            ("Blob", [RustType::Other(bits), ..]) => format!("{{ blob = {bits} }}"),
+            ("WideStringFixed", [RustType::Other(units)]) => format!("{{ wide_fixed = {units} }}"),
+            (name, []) => {
+                let name = PRIMITIVE_ALIASES
+                    .iter()
+                    .find(|(alias, _)| *alias == name)
+                    .map_or(name, |(_, primitive)| primitive);
+                lua_string(name)
+            }
+            _ => unknown_type(ty),
+        },
+        RustType::Array(item, count) if count.parse::<usize>().is_ok() => {
+            format!("{{ array = {}, count = {count} }}", lua_type(item))
+        }
+        RustType::Tuple(items) => {
+            let items: Vec<_> = items.iter().map(lua_type).collect();
+            format!("{{ tuple = {{ {} }} }}", items.join(", "))
+        }
+        _ => unknown_type(ty),
+    }
+}
+
+fn packed_type(ty: &RustType, bits: impl std::fmt::Display) -> String {
+    match ty {
+        RustType::Named(name, args) if args.is_empty() => {
+            format!("{{ packed = {}, bits = {bits} }}", lua_string(name))
+        }
+        _ => unknown_type(ty),
+    }
+}
+
+/// Returns the Lua description of a union preceded by its variant, or of a
+/// fixed array of them.
+fn inline_union_type(ty: &RustType, bits: usize) -> String {
+    match ty {
+        RustType::Array(item, count) if count.parse::<usize>().is_ok() => {
+            format!(
+                "{{ array = {}, count = {count} }}",
+                inline_union_type(item, bits)
+            )
+        }
+        _ => format!("{{ inline_union = {}, bits = {bits} }}", lua_type(ty)),
+    }
+}
+
+fn unknown_type(ty: &RustType) -> String {
+    format!("{{ unknown = {} }}", lua_string(&format!("{ty:?}")))
+}
+
+/// Returns a Lua function evaluating a length or presence expression with the
+/// fields read so far, or one that fails if it can't be translated.
+fn lua_function(expr: &str) -> String {
+    match lua_expr(expr) {
+        Some(lua) => format!("function(v) return {lua} end"),
+        None => format!("unsupported({})", lua_string(expr)),
+    }
+}
+
+/// Translates a Rust expression using previous fields to Lua. Only field paths,
+/// integer and boolean literals, casts and operators are supported, which is
+/// what length expressions and presence conditions are made of.
+fn lua_expr(expr: &str) -> Option<String> {
+    let mut lua = String::new();
+    let mut chars = expr.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' => continue,
+            'a'..='z' | 'A'..='Z' | '_' => {
+                let mut path = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '.') {
+                        break;
+                    }
+                    path.push(c);
+                    chars.next();
+                }
+                match path.as_str() {
+                    "as" => {
+                        // skip the type, values have no type in Lua
+                        while chars.peek() == Some(&' ') {
+                            chars.next();
+                        }
+                        while chars
+                            .peek()
+                            .is_some_and(|c| c.is_alphanumeric() || *c == '_')
+                        {
+                            chars.next();
+                        }
+                        continue;
+                    }
+                    "true" | "false" => lua.push_str(&path),
+                    _ if chars.peek() == Some(&'(') => return None,
+                    _ => write!(lua, "v.{path}").unwrap(),
+                }
+            }
+            '0'..='9' => {
+                let mut number = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if !c.is_ascii_alphanumeric() && c != '_' {
+                        break;
+                    }
+                    number.push(c);
+                    chars.next();
+                }
+                lua.push_str(&lua_integer(&number)?);
+            }
+            '(' | ')' => lua.push(c),
+            _ => {
+                let next = chars.peek().copied();
+                let op = match (c, next) {
+                    ('<', Some('<')) => "<<",
+                    ('>', Some('>')) => ">>",
+                    ('<', Some('=')) => "<=",
+                    ('>', Some('=')) => ">=",
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "~=",
+                    ('&', Some('&')) => "and",
+                    ('|', Some('|')) => "or",
+                    ('!', _) => {
+                        lua.push_str("not ");
+                        continue;
+                    }
+                    ('^', _) => "~",
+                    ('/', _) => "//",
+                    ('+', _) => "+",
+                    ('-', _) => "-",
+                    ('*', _) => "*",
+                    ('%', _) => "%",
+                    ('&', _) => "&",
+                    ('|', _) => "|",
+                    ('<', _) => "<",
+                    ('>', _) => ">",
+                    _ => return None,
+                };
+                if matches!(op, "<<" | ">>" | "<=" | ">=" | "==" | "~=" | "and" | "or") {
+                    chars.next();
+                }
+                write!(lua, " {op} ").unwrap();
+            }
+        }
+    }
+    Some(lua)
+}
+
+/// Translates an integer literal, which can have a type suffix like `8u32`.
+fn lua_integer(literal: &str) -> Option<String> {
+    let literal = literal.replace('_', "");
+    let (digits, radix) = match literal.strip_prefix("0x") {
+        Some(hex) => (hex, 16),
+        None => (literal.as_str(), 10),
+    };
+    let end = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let value = u64::from_str_radix(&digits[..end], radix).ok()?;
+    Some(value.to_string())
+}
+
+fn strip_path(name: &str) -> &str {
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+fn lua_string(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the messages of the servers are only registered if their crate is linked
+    extern crate ws_realm;
+    extern crate ws_world;
+
+    #[test]
+    fn test_lua_expr() {
+        assert_eq!(lua_expr("count").as_deref(), Some("v.count"));
+        assert_eq!(
+            lua_expr("header.count as usize * 2").as_deref(),
+            Some("v.header.count * 2")
+        );
+        assert_eq!(
+            lua_expr("!(flags & 0x10 != 0)").as_deref(),
+            Some("not (v.flags & 16 ~= 0)")
+        );
+        assert_eq!(
+            lua_expr("a >= 3 && b / 2 == 1u8").as_deref(),
+            Some("v.a >= 3 and v.b // 2 == 1")
+        );
+        assert_eq!(lua_expr("items.len()"), None);
+        assert_eq!(lua_function("items.len()"), "unsupported(\"items.len()\")");
+    }
+
+    #[test]
+    fn test_generate_dissector() {
+        let registry = MessageRegistry::with_registered();
+        let dissector = generate_dissector(&registry);
+        assert!(dissector.contains("[0x0591] = \"ServerAuthAccepted\","));
+        assert!(
+            dissector.contains("{ name = \"faction\", ty = { packed = \"Faction\", bits = 14 } },")
+        );
+        assert!(dissector.contains(
+            "{ name = \"characters\", item = \"CharacterEntry\", length = function(v) return v.count end },"
+        ));
+        assert!(dissector.contains(
+            "types[\"Faction\"] = { enum = { repr = \"u16\", values = { [166] = \"Exile\", [167] = \"Dominion\", } } }"
+        ));
+        assert!(dissector.contains("{ name = \"session_guid\", ty = \"uuid\" },"));
+        assert!(dissector.ends_with(RUNTIME));
+    }
+}
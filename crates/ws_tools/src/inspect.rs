@@ -0,0 +1,105 @@
+use ws_bitpack::{BitPackError, BitPackReader, TraceSpan};
+use ws_messages::{AnyMessage, MessageRegistration, MessageRegistry};
+
+/// A message body decoded with the bits of each field recorded.
+#[derive(Debug)]
+pub struct Inspection {
+    pub registration: MessageRegistration,
+    /// The decoded message, or why it couldn't be decoded.
+    pub result: Result<Box<dyn AnyMessage>, BitPackError>,
+    /// The fields read, in the order they were read in. Fields that failed to
+    /// read have no end.
+    pub spans: Vec<TraceSpan>,
+    /// The bits left after the message.
+    pub remaining_bits: usize,
+}
+
+impl Inspection {
+    /// Returns the bits of a field as an integer, if it spans 64 bits or less.
+    pub fn span_value(&self, data: &[u8], span: &TraceSpan) -> Option<u64> {
+        let bits = span.end? - span.offset;
+        if bits > 64 {
+            return None;
+        }
+        BitPackReader::with_position(data, span.offset)
+            .read_u64(bits)
+            .ok()
+    }
+}
+
+/// Decodes the body of the message with opcode `id` while recording which bits
+/// each field was read from.
+///
+/// Returns `None` if no message is registered for `id`.
+pub fn inspect(registry: &MessageRegistry, id: u32, data: &[u8]) -> Option<Inspection> {
+    let registration = *registry.get(id)?;
+    let mut reader = BitPackReader::new(data);
+    reader.start_trace();
+    let result = (registration.read)(&mut reader);
+    let spans = reader
+        .take_trace()
+        .map(|trace| trace.spans().to_vec())
+        .unwrap_or_default();
+    Some(Inspection {
+        registration,
+        result,
+        spans,
+        remaining_bits: reader.remaining_bits(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_world::{CharacterEntry, Faction, ServerCharacterList};
+
+    use super::*;
+
+    #[test]
+    fn test_inspect() {
+        let mut registry = MessageRegistry::new();
+        registry.register::<ServerCharacterList>();
+        let list = ServerCharacterList {
+            count: 1,
+            characters: vec![CharacterEntry {
+                character_id: 1,
+                name: "A".to_string(),
+                faction: Faction::Exile,
+                race: 1,
+                class: 2,
+                level: 3,
+                world_id: 870,
+            }],
+        };
+        let mut writer = ws_bitpack::BitPackWriter::growable();
+        writer.write(&list).unwrap();
+        let data = writer.finish().unwrap();
+
+        let inspection = inspect(&registry, 0x0117, &data).unwrap();
+        assert!(inspection.result.is_ok());
+        let labels = inspection
+            .spans
+            .iter()
+            .map(|span| (span.depth, span.label))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            labels[..4],
+            [
+                (0, "count"),
+                (0, "characters"),
+                (1, "character_id"),
+                (1, "name")
+            ]
+        );
+        assert_eq!(labels.last(), Some(&(1, "world_id")));
+
+        let count = &inspection.spans[0];
+        assert_eq!((count.offset, count.end), (0, Some(32)));
+        assert_eq!(inspection.span_value(&data, count), Some(1));
+
+        // the last byte is missing
+        let inspection = inspect(&registry, 0x0117, &data[..data.len() - 1]).unwrap();
+        assert!(inspection.result.is_err());
+        let world_id = inspection.spans.last().unwrap();
+        assert_eq!((world_id.label, world_id.end), ("world_id", None));
+    }
+}
@@ -0,0 +1,56 @@
+//! Tools to work on the messages of the protocol.
+//!
+//! The `ws_tools` binary reads captures of WildStar traffic, rebuilds the TCP
+//! streams, and decodes every frame with the message registry, to check the
+//! derived message definitions against real traffic. It can also show which
+//! bits of a message body each field was read from, and generate a Wireshark
+//! dissector and fuzz corpus seeds from the message definitions.
+
+mod capture;
+mod corpus;
+mod dissector;
+mod inspect;
+mod replay;
+mod tcp;
+
+pub use capture::*;
+pub use corpus::*;
+pub use dissector::*;
+pub use inspect::*;
+pub use replay::*;
+pub use tcp::*;
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum ToolError {
+    Io(std::io::Error),
+    /// The capture file is truncated or isn't a pcap or pcapng file.
+    InvalidCapture(&'static str),
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::InvalidCapture(reason) => f.write_str(reason),
+        }
+    }
+}
+
+impl std::error::Error for ToolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            Self::InvalidCapture(_) => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for ToolError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+pub type ToolResult<T = ()> = Result<T, ToolError>;
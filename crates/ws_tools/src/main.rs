@@ -0,0 +1,254 @@
+use std::process::ExitCode;
+
+use ws_messages::MessageRegistry;
+use ws_tools::*;
+
+// the messages of the servers are only registered if their crate is linked
+extern crate ws_realm;
+extern crate ws_world;
+
+const USAGE: &str = "Usage:
+  ws_tools replay <capture.pcap|capture.pcapng> [--port <port>]... [--pretty]
+  ws_tools inspect <opcode> <hex body>
+  ws_tools dissector [output.lua]
+  ws_tools corpus <directory> [--seed <n>]";
+
+#[derive(Debug)]
+enum Command {
+    Replay(ReplayOptions),
+    Inspect { id: u32, data: Vec<u8> },
+    Dissector { output: Option<String> },
+    Corpus { dir: String, seed: u64 },
+}
+
+#[derive(Debug, Default)]
+struct ReplayOptions {
+    path: String,
+    ports: Vec<u16>,
+    pretty: bool,
+}
+
+fn parse_replay_options(args: impl Iterator<Item = String>) -> Option<ReplayOptions> {
+    let mut options = ReplayOptions::default();
+    let mut args = args.peekable();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => options.ports.push(args.next()?.parse().ok()?),
+            "--pretty" => options.pretty = true,
+            _ if options.path.is_empty() => options.path = arg,
+            _ => return None,
+        }
+    }
+    (!options.path.is_empty()).then_some(options)
+}
+
+fn parse_inspect(mut args: impl Iterator<Item = String>) -> Option<Command> {
+    let id = args.next()?;
+    let id = match id.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+        None => id.parse().ok()?,
+    };
+    // the body can be split in several arguments
+    let hex = args.collect::<String>().replace(char::is_whitespace, "");
+    let data = hex::decode(hex).ok()?;
+    Some(Command::Inspect { id, data })
+}
+
+fn parse_corpus(mut args: impl Iterator<Item = String>) -> Option<Command> {
+    let dir = args.next()?;
+    let seed = match (args.next().as_deref(), args.next()) {
+        (Some("--seed"), Some(seed)) => seed.parse().ok()?,
+        (None, _) => 0,
+        _ => return None,
+    };
+    args.next()
+        .is_none()
+        .then_some(Command::Corpus { dir, seed })
+}
+
+fn main() -> ExitCode {
+    let mut args = std::env::args().skip(1);
+    let command = match args.next().as_deref() {
+        Some("replay") => parse_replay_options(args).map(Command::Replay),
+        Some("inspect") => parse_inspect(args),
+        Some("corpus") => parse_corpus(args),
+        Some("dissector") => match (args.next(), args.next()) {
+            (output, None) => Some(Command::Dissector { output }),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    match command {
+        Some(Command::Replay(options)) => match replay(&options) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                eprintln!("Failed to replay {}: {error}", options.path);
+                ExitCode::FAILURE
+            }
+        },
+        Some(Command::Inspect { id, data }) => print_inspection(id, &data),
+        Some(Command::Dissector { output }) => write_dissector(output.as_deref()),
+        Some(Command::Corpus { dir, seed }) => print_corpus(&dir, seed),
+        None => {
+            eprintln!("{USAGE}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_inspection(id: u32, data: &[u8]) -> ExitCode {
+    let registry = MessageRegistry::with_registered();
+    let inspection = match inspect(&registry, id, data) {
+        Some(inspection) => inspection,
+        None => {
+            eprintln!("No message is registered for {id:#06x}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!(
+        "{} ({id:#06x}), {} bits",
+        inspection.registration.name,
+        data.len() * 8
+    );
+    println!("{:>6} {:>5}  field", "offset", "bits");
+    for span in &inspection.spans {
+        let bits = match span.end {
+            Some(end) => (end - span.offset).to_string(),
+            None => "?".to_string(),
+        };
+        let value = match inspection.span_value(data, span) {
+            Some(value) => format!(" = {value:#x}"),
+            None => String::new(),
+        };
+        let indent = "  ".repeat(span.depth);
+        println!(
+            "{:>6} {bits:>5}  {indent}{}{value}",
+            span.offset, span.label
+        );
+    }
+
+    match &inspection.result {
+        Ok(message) => {
+            let message = inspection.registration.debug_message(&**message);
+            println!("{message:#?}");
+            if inspection.remaining_bits >= 8 {
+                println!("{} bits left after the message", inspection.remaining_bits);
+            }
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            println!("FAILED: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn write_dissector(output: Option<&str>) -> ExitCode {
+    let dissector = generate_dissector(&MessageRegistry::with_registered());
+    let path = match output {
+        Some(path) => path,
+        None => {
+            print!("{dissector}");
+            return ExitCode::SUCCESS;
+        }
+    };
+    match std::fs::write(path, dissector) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Failed to write {path}: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn print_corpus(dir: &str, seed: u64) -> ExitCode {
+    let corpus = match write_corpus(&MessageRegistry::with_registered(), dir.as_ref(), seed) {
+        Ok(corpus) => corpus,
+        Err(error) => {
+            eprintln!("Failed to write the corpus to {dir}: {error}");
+            return ExitCode::FAILURE;
+        }
+    };
+    println!("{} seeds written to {dir}", corpus.seeds.len());
+    if !corpus.skipped.is_empty() {
+        println!("No seed for {}", corpus.skipped.join(", "));
+    }
+    ExitCode::SUCCESS
+}
+
+fn replay(options: &ReplayOptions) -> ToolResult {
+    let registry = MessageRegistry::with_registered();
+
+    let capture = std::fs::read(&options.path)?;
+    let mut reassembler = TcpReassembler::new();
+    for packet in read_capture(&capture)? {
+        let segment = match TcpSegment::parse(packet.link_type, &packet.data) {
+            Some(segment) => segment,
+            None => continue,
+        };
+        let ports = [segment.src.port(), segment.dst.port()];
+        if options.ports.is_empty() || options.ports.iter().any(|port| ports.contains(port)) {
+            reassembler.push(&segment);
+        }
+    }
+
+    let (mut decoded, mut unknown, mut failed) = (0, 0, 0);
+    for stream in reassembler.finish() {
+        if stream.data.is_empty() {
+            continue;
+        }
+        println!(
+            "{} -> {}, {} bytes",
+            stream.src,
+            stream.dst,
+            stream.data.len()
+        );
+        if stream.missing_segments > 0 {
+            println!(
+                "  {} segments missing from the capture",
+                stream.missing_segments
+            );
+        }
+
+        let replay = replay_stream(&registry, &stream.data);
+        for frame in &replay.frames {
+            let name = frame.registration.map_or("unknown", |r| r.name);
+            let message = frame.debug_message().map(|message| match options.pretty {
+                true => format!("{message:#?}"),
+                false => format!("{message:?}"),
+            });
+            print!("  {:#06x} ", frame.opcode);
+            match &frame.status {
+                FrameStatus::Decoded(_) => {
+                    decoded += 1;
+                    println!("{}", message.unwrap_or_default());
+                }
+                FrameStatus::TrailingBits { bits, .. } => {
+                    failed += 1;
+                    println!("{} FAILED: {bits} bits left", message.unwrap_or_default());
+                }
+                FrameStatus::Failed(error) => {
+                    failed += 1;
+                    println!("{name} FAILED: {error}");
+                }
+                FrameStatus::Unknown => {
+                    unknown += 1;
+                    println!("{name} ({} bytes)", frame.size);
+                }
+            }
+        }
+        if let Some(error) = &replay.error {
+            println!("  framing stopped: {error}");
+        } else if replay.leftover > 0 {
+            println!("  {} bytes of an incomplete frame", replay.leftover);
+        }
+    }
+
+    println!(
+        "{} frames: {decoded} decoded, {unknown} unknown, {failed} failed",
+        decoded + unknown + failed
+    );
+    Ok(())
+}
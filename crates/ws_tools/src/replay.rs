@@ -0,0 +1,139 @@
+use std::fmt;
+
+use ws_bitpack::BitPackError;
+use ws_messages::{AnyMessage, MessageRegistration, MessageRegistry};
+use ws_protocol::{FrameDecoder, ProtocolError};
+
+/// What came of decoding a frame.
+#[derive(Debug)]
+pub enum FrameStatus {
+    Decoded(Box<dyn AnyMessage>),
+    /// The message was decoded but didn't use the whole frame, which usually
+    /// means that fields are missing from its definition.
+    TrailingBits {
+        message: Box<dyn AnyMessage>,
+        bits: usize,
+    },
+    Failed(BitPackError),
+    /// No message is registered for the opcode.
+    Unknown,
+}
+
+#[derive(Debug)]
+pub struct ReplayedFrame {
+    pub opcode: u16,
+    /// The size of the whole frame, in bytes.
+    pub size: usize,
+    /// The message registered for the opcode, if any.
+    pub registration: Option<MessageRegistration>,
+    pub status: FrameStatus,
+}
+
+impl ReplayedFrame {
+    /// Returns the decoded message, if any, to be formatted with the `Debug`
+    /// impl of its type.
+    pub fn debug_message(&self) -> Option<impl fmt::Debug + '_> {
+        let message = match &self.status {
+            FrameStatus::Decoded(message) => message,
+            FrameStatus::TrailingBits { message, .. } => message,
+            _ => return None,
+        };
+        Some(self.registration?.debug_message(&**message))
+    }
+}
+
+/// The frames found in a stream.
+#[derive(Debug, Default)]
+pub struct StreamReplay {
+    pub frames: Vec<ReplayedFrame>,
+    /// The error that stopped the framing, if any.
+    pub error: Option<ProtocolError>,
+    /// The bytes left after the last complete frame.
+    pub leftover: usize,
+}
+
+/// Splits the data of a stream into frames and decodes their message.
+///
+/// Encrypted streams can't be decoded, their frames show up as unknown or
+/// failed messages.
+pub fn replay_stream(registry: &MessageRegistry, data: &[u8]) -> StreamReplay {
+    let mut decoder = FrameDecoder::new();
+    decoder.extend(data);
+
+    let mut replay = StreamReplay::default();
+    loop {
+        let frame = match decoder.next_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(error) => {
+                replay.error = Some(error);
+                break;
+            }
+        };
+
+        let registration = registry.get(frame.opcode as u32).copied();
+        let status = match registration {
+            None => FrameStatus::Unknown,
+            Some(registration) => {
+                let mut reader = frame.reader();
+                match (registration.read)(&mut reader) {
+                    // the last byte is padded
+                    Ok(message) if reader.remaining_bits() < 8 => FrameStatus::Decoded(message),
+                    Ok(message) => FrameStatus::TrailingBits {
+                        message,
+                        bits: reader.remaining_bits(),
+                    },
+                    Err(error) => FrameStatus::Failed(error),
+                }
+            }
+        };
+        replay.frames.push(ReplayedFrame {
+            opcode: frame.opcode,
+            size: frame.data.len(),
+            registration,
+            status,
+        });
+    }
+    replay.leftover = decoder.buffered();
+    replay
+}
+
+#[cfg(test)]
+mod tests {
+    use ws_protocol::FrameEncoder;
+    use ws_world::{ServerAuthAccepted, ServerCharacterSelectFailed};
+
+    use super::*;
+
+    #[test]
+    fn test_replay_stream() {
+        let mut registry = MessageRegistry::new();
+        registry.register::<ServerAuthAccepted>();
+
+        let mut encoder = FrameEncoder::new();
+        let accepted = ServerAuthAccepted { account_id: 7 };
+        let mut data = encoder.encode_value(0x0591, &accepted).unwrap();
+        let failed = ServerCharacterSelectFailed { character_id: 2 };
+        data.extend(encoder.encode_value(0x00ae, &failed).unwrap());
+        // the body of a ServerAuthAccepted is too short for its account id
+        data.extend(encoder.encode(0x0591, |_| Ok(())).unwrap());
+        data.extend(encoder.encode_value(0x0591, &7u64).unwrap());
+        data.push(0);
+
+        let replay = replay_stream(&registry, &data);
+        assert_eq!(replay.frames.len(), 4);
+        assert!(matches!(replay.frames[0].status, FrameStatus::Decoded(_)));
+        assert_eq!(
+            format!("{:?}", replay.frames[0].debug_message().unwrap()),
+            "ServerAuthAccepted { account_id: 7 }"
+        );
+        assert!(matches!(replay.frames[1].status, FrameStatus::Unknown));
+        assert!(matches!(replay.frames[2].status, FrameStatus::Failed(_)));
+        assert!(matches!(
+            replay.frames[3].status,
+            FrameStatus::TrailingBits { .. }
+        ));
+        assert_eq!(replay.leftover, 1);
+        assert!(replay.error.is_none());
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IP_PROTOCOL_TCP: u8 = 6;
+
+/// The TCP part of a captured packet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpSegment<'a> {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub seq: u32,
+    pub syn: bool,
+    pub payload: &'a [u8],
+}
+
+impl<'a> TcpSegment<'a> {
+    /// Extracts the TCP segment of a packet, if it has one.
+    ///
+    /// Supports Ethernet, loopback, Linux cooked and raw IP captures. IP
+    /// fragments and IPv6 extension headers are not supported.
+    pub fn parse(link_type: u32, data: &'a [u8]) -> Option<Self> {
+        let (ethertype, ip) = match link_type {
+            // BSD loopback, with the address family in host byte order
+            0 => {
+                let family = u32::from_le_bytes(data.get(..4)?.try_into().ok()?);
+                let family = match family > 0xffff {
+                    true => family.swap_bytes(),
+                    false => family,
+                };
+                match family {
+                    2 => (ETHERTYPE_IPV4, &data[4..]),
+                    24 | 28 | 30 => (ETHERTYPE_IPV6, &data[4..]),
+                    _ => return None,
+                }
+            }
+            // Ethernet
+            1 => {
+                let mut ethertype = u16_at(data, 12)?;
+                let mut offset = 14;
+                if ethertype == ETHERTYPE_VLAN {
+                    ethertype = u16_at(data, 16)?;
+                    offset = 18;
+                }
+                (ethertype, data.get(offset..)?)
+            }
+            // raw IP
+            101 | 228 | 229 => match data.first()? >> 4 {
+                4 => (ETHERTYPE_IPV4, data),
+                6 => (ETHERTYPE_IPV6, data),
+                _ => return None,
+            },
+            // Linux cooked captures, v1 and v2
+            113 => (u16_at(data, 14)?, data.get(16..)?),
+            276 => (u16_at(data, 0)?, data.get(20..)?),
+            _ => return None,
+        };
+
+        match ethertype {
+            ETHERTYPE_IPV4 => Self::parse_ipv4(ip),
+            ETHERTYPE_IPV6 => Self::parse_ipv6(ip),
+            _ => None,
+        }
+    }
+
+    fn parse_ipv4(data: &'a [u8]) -> Option<Self> {
+        let header_length = (*data.first()? & 0x0f) as usize * 4;
+        let total_length = u16_at(data, 2)? as usize;
+        let fragment = u16_at(data, 6)?;
+        // more fragments, or a fragment offset
+        if fragment & 0x3fff != 0 || *data.get(9)? != IP_PROTOCOL_TCP {
+            return None;
+        }
+        let src: [u8; 4] = data.get(12..16)?.try_into().ok()?;
+        let dst: [u8; 4] = data.get(16..20)?.try_into().ok()?;
+        // captures can have padding after the packet
+        let tcp = data.get(header_length..total_length.min(data.len()))?;
+        Self::parse_tcp(Ipv4Addr::from(src).into(), Ipv4Addr::from(dst).into(), tcp)
+    }
+
+    fn parse_ipv6(data: &'a [u8]) -> Option<Self> {
+        let payload_length = u16_at(data, 4)? as usize;
+        if *data.get(6)? != IP_PROTOCOL_TCP {
+            return None;
+        }
+        let src: [u8; 16] = data.get(8..24)?.try_into().ok()?;
+        let dst: [u8; 16] = data.get(24..40)?.try_into().ok()?;
+        let tcp = data.get(40..(40 + payload_length).min(data.len()))?;
+        Self::parse_tcp(Ipv6Addr::from(src).into(), Ipv6Addr::from(dst).into(), tcp)
+    }
+
+    fn parse_tcp(src: IpAddr, dst: IpAddr, data: &'a [u8]) -> Option<Self> {
+        let header_length = (*data.get(12)? >> 4) as usize * 4;
+        Some(Self {
+            src: SocketAddr::new(src, u16_at(data, 0)?),
+            dst: SocketAddr::new(dst, u16_at(data, 2)?),
+            seq: u32::from_be_bytes(data.get(4..8)?.try_into().ok()?),
+            syn: *data.get(13)? & 0x02 != 0,
+            payload: data.get(header_length..)?,
+        })
+    }
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+/// The data sent in one direction of a TCP connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpStream {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    /// The data received in order, up to the first missing segment.
+    pub data: Vec<u8>,
+    /// The number of segments that came after a gap in the capture.
+    pub missing_segments: usize,
+}
+
+#[derive(Debug)]
+struct StreamState {
+    stream: TcpStream,
+    /// The sequence number of the first byte of data.
+    base: Option<u32>,
+    /// Segments received ahead of the data, by offset.
+    pending: BTreeMap<usize, Vec<u8>>,
+}
+
+impl StreamState {
+    fn push(&mut self, segment: &TcpSegment) {
+        if segment.syn {
+            self.base = Some(segment.seq.wrapping_add(1));
+        }
+        if segment.payload.is_empty() {
+            return;
+        }
+        let base = *self.base.get_or_insert(segment.seq);
+        let offset = segment.seq.wrapping_sub(base);
+        // data from before the start of the capture
+        if offset > i32::MAX as u32 {
+            return;
+        }
+        self.pending
+            .entry(offset as usize)
+            .and_modify(|payload| {
+                if payload.len() < segment.payload.len() {
+                    *payload = segment.payload.to_vec();
+                }
+            })
+            .or_insert_with(|| segment.payload.to_vec());
+
+        let data = &mut self.stream.data;
+        while let Some(entry) = self.pending.first_entry() {
+            let offset = *entry.key();
+            if offset > data.len() {
+                break;
+            }
+            let payload = entry.remove();
+            // retransmissions overlap with the data received so far
+            let overlap = data.len() - offset;
+            if overlap < payload.len() {
+                data.extend(&payload[overlap..]);
+            }
+        }
+    }
+}
+
+/// Puts TCP segments back in order to rebuild the streams of a capture.
+///
+/// Both directions of a connection are separate streams.
+#[derive(Debug, Default)]
+pub struct TcpReassembler {
+    streams: Vec<StreamState>,
+    indices: HashMap<(SocketAddr, SocketAddr), usize>,
+}
+
+impl TcpReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, segment: &TcpSegment) {
+        let index = *self
+            .indices
+            .entry((segment.src, segment.dst))
+            .or_insert_with(|| {
+                self.streams.push(StreamState {
+                    stream: TcpStream {
+                        src: segment.src,
+                        dst: segment.dst,
+                        data: Vec::new(),
+                        missing_segments: 0,
+                    },
+                    base: None,
+                    pending: BTreeMap::new(),
+                });
+                self.streams.len() - 1
+            });
+        self.streams[index].push(segment);
+    }
+
+    /// Returns the streams in the order they started in.
+    pub fn finish(self) -> Vec<TcpStream> {
+        self.streams
+            .into_iter()
+            .map(|state| TcpStream {
+                missing_segments: state.pending.len(),
+                ..state.stream
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(seq: u32, syn: bool, payload: &[u8]) -> TcpSegment<'_> {
+        TcpSegment {
+            src: "10.0.0.1:50000".parse().unwrap(),
+            dst: "10.0.0.2:24000".parse().unwrap(),
+            seq,
+            syn,
+            payload,
+        }
+    }
+
+    #[test]
+    fn test_parse_ethernet() {
+        let packet = hex::decode(concat!(
+            "0200000000020200000000010800",
+            "4500002900004000400600000a0000010a000002",
+            "c3505dc0000003e8000000005010000000000000",
+            "ff",
+        ))
+        .unwrap();
+        let segment = TcpSegment::parse(1, &packet).unwrap();
+        assert_eq!(segment.src, "10.0.0.1:50000".parse().unwrap());
+        assert_eq!(segment.dst, "10.0.0.2:24000".parse().unwrap());
+        assert_eq!(segment.seq, 1000);
+        assert_eq!(segment.payload, [0xff]);
+    }
+
+    #[test]
+    fn test_reassembly() {
+        let mut reassembler = TcpReassembler::new();
+        reassembler.push(&segment(99, true, &[]));
+        reassembler.push(&segment(105, false, b"world"));
+        reassembler.push(&segment(100, false, b"hel"));
+        // retransmission
+        reassembler.push(&segment(100, false, b"hello"));
+        reassembler.push(&segment(120, false, b"lost"));
+
+        let streams = reassembler.finish();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].data, b"helloworld");
+        assert_eq!(streams[0].missing_segments, 1);
+    }
+}
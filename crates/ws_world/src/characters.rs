@@ -0,0 +1,231 @@
+use std::fmt;
+
+use crate::{Appearance, CharacterEntry, Customization, Faction, Position};
+
+/// The highest level a character can reach.
+pub const MAX_LEVEL: u32 = 50;
+
+/// The number of characters an account can have.
+pub const MAX_CHARACTERS: u32 = 12;
+
+pub const MIN_NAME_LENGTH: usize = 3;
+pub const MAX_NAME_LENGTH: usize = 24;
+
+/// The number of customizations the character creator can send.
+pub const MAX_CUSTOMIZATIONS: usize = 100;
+
+/// A character, along with where it is in the world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Character {
+    pub id: u64,
+    pub account_id: u32,
+    pub name: String,
+    pub faction: Faction,
+    pub race: u8,
+    pub class: u8,
+    pub sex: u8,
+    pub path: u8,
+    pub appearance: Appearance,
+    pub level: u32,
+    pub world_id: u32,
+    pub position: Position,
+    pub yaw: f32,
+}
+
+impl Character {
+    /// The character given to every account until characters can be created.
+    pub fn sandbox(account_id: u32) -> Self {
+        Self {
+            id: 1,
+            account_id,
+            name: "Sandbox".to_string(),
+            faction: Faction::Exile,
+            race: 1,
+            class: 1,
+            sex: 0,
+            path: 0,
+            appearance: Appearance::default(),
+            level: 1,
+            world_id: Self::START_WORLD_ID,
+            position: Self::START_POSITION,
+            yaw: 0.0,
+        }
+    }
+
+    /// Where new characters start, in Thayd, until starting zones are in the
+    /// data tables.
+    pub const START_WORLD_ID: u32 = 870;
+    pub const START_POSITION: Position = Position {
+        x: -3835.0,
+        y: -980.0,
+        z: -6050.0,
+    };
+
+    pub fn entry(&self) -> CharacterEntry {
+        CharacterEntry {
+            character_id: self.id,
+            name: self.name.clone(),
+            faction: self.faction,
+            race: self.race,
+            class: self.class,
+            level: self.level,
+            world_id: self.world_id,
+        }
+    }
+}
+
+/// Why a character name was refused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NameError {
+    TooShort,
+    TooLong,
+    InvalidCharacter(char),
+    /// An apostrophe, a hyphen or a space isn't between two letters.
+    MisplacedSeparator,
+    /// Names are a first name, and optionally a last name.
+    TooManyWords,
+    /// The name contains a blocked word.
+    Blocked(String),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooShort => write!(f, "names need at least {MIN_NAME_LENGTH} characters"),
+            Self::TooLong => write!(f, "names can't have more than {MAX_NAME_LENGTH} characters"),
+            Self::InvalidCharacter(c) => write!(f, "names can't contain {c:?}"),
+            Self::MisplacedSeparator => write!(f, "separators must be between letters"),
+            Self::TooManyWords => write!(f, "names can't have more than two words"),
+            Self::Blocked(word) => write!(f, "names can't contain {word:?}"),
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+/// Checks a character name: a first name and an optional last name made of
+/// ASCII letters, with apostrophes and hyphens allowed between letters, like
+/// `Deadeye Brightland` or `Ka'tal`.
+///
+/// Names containing any of the `blocked` words are refused, whatever their
+/// case and separators.
+pub fn validate_name(name: &str, blocked: &[String]) -> Result<(), NameError> {
+    let length = name.chars().count();
+    if length < MIN_NAME_LENGTH {
+        return Err(NameError::TooShort);
+    }
+    if length > MAX_NAME_LENGTH {
+        return Err(NameError::TooLong);
+    }
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphabetic() && !matches!(c, '\'' | '-' | ' '))
+    {
+        return Err(NameError::InvalidCharacter(c));
+    }
+    let bytes = name.as_bytes();
+    for (i, byte) in bytes.iter().enumerate() {
+        let is_letter = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_alphabetic);
+        if !byte.is_ascii_alphabetic() && (i == 0 || !is_letter(i - 1) || !is_letter(i + 1)) {
+            return Err(NameError::MisplacedSeparator);
+        }
+    }
+    if name.split(' ').count() > 2 {
+        return Err(NameError::TooManyWords);
+    }
+    check_blocked(name, blocked)
+}
+
+/// Refuses names containing any of the `blocked` words, whatever their case
+/// and separators.
+pub(crate) fn check_blocked(name: &str, blocked: &[String]) -> Result<(), NameError> {
+    let letters: String = name
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    match blocked
+        .iter()
+        .find(|word| !word.is_empty() && letters.contains(&word.to_ascii_lowercase()))
+    {
+        Some(word) => Err(NameError::Blocked(word.clone())),
+        None => Ok(()),
+    }
+}
+
+impl Appearance {
+    /// Returns whether the client could have sent this appearance: bones
+    /// are set once each, within their range, and labels once each.
+    pub fn is_valid(&self) -> bool {
+        if self.customizations.len() > MAX_CUSTOMIZATIONS {
+            return false;
+        }
+        let mut labels = Vec::new();
+        let mut bones = Vec::new();
+        self.customizations
+            .iter()
+            .all(|customization| match *customization {
+                Customization::Option { label, .. } => {
+                    let unique = !labels.contains(&label);
+                    labels.push(label);
+                    unique
+                }
+                Customization::Bone { index, offset } => {
+                    let unique = !bones.contains(&index);
+                    bones.push(index);
+                    unique && (-1.0..=1.0).contains(&offset)
+                }
+            })
+    }
+}
+
+impl Faction {
+    pub fn from_id(id: u16) -> Option<Self> {
+        [Self::Exile, Self::Dominion]
+            .into_iter()
+            .find(|faction| *faction as u16 == id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_name() {
+        let blocked = ["darn".to_string()];
+        for name in ["Clamoune", "Deadeye Brightland", "Ka'tal", "Mon-Ra Ix"] {
+            assert_eq!(validate_name(name, &blocked), Ok(()), "{name}");
+        }
+        for (name, error) in [
+            ("Al", NameError::TooShort),
+            ("Abcdefghijklm Nopqrstuvwxyz", NameError::TooLong),
+            ("Clam0une", NameError::InvalidCharacter('0')),
+            ("Clamoune!", NameError::InvalidCharacter('!')),
+            ("Élodie", NameError::InvalidCharacter('É')),
+            ("'Clamoune", NameError::MisplacedSeparator),
+            ("Clam--oune", NameError::MisplacedSeparator),
+            ("Clamoune ", NameError::MisplacedSeparator),
+            ("Clam  Oune", NameError::MisplacedSeparator),
+            ("Big Bad Wolf", NameError::TooManyWords),
+            ("Da-Rn It", NameError::Blocked("darn".to_string())),
+        ] {
+            assert_eq!(validate_name(name, &blocked), Err(error), "{name}");
+        }
+    }
+
+    #[test]
+    fn test_appearance_is_valid() {
+        let option = |label| Customization::Option { label, value: 1 };
+        let bone = |index, offset| Customization::Bone { index, offset };
+        let appearance = |customizations: Vec<_>| Appearance {
+            count: customizations.len() as u8,
+            customizations,
+        };
+        assert!(appearance(vec![option(1), option(2), bone(0, 0.5), bone(1, -1.0)]).is_valid());
+        assert!(!appearance(vec![option(1), option(1)]).is_valid());
+        assert!(!appearance(vec![bone(3, 0.1), bone(3, 0.2)]).is_valid());
+        assert!(!appearance(vec![bone(3, 1.5)]).is_valid());
+        assert!(!appearance(vec![bone(3, f32::NAN)]).is_valid());
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use uuid::Uuid;
+use ws_cluster::{ClusterMessage, ClusterNode};
+
+use crate::*;
+
+/// How long a ticket handed by the realm server can be presented for.
+pub const TICKET_LIFETIME: Duration = Duration::from_secs(60);
+
+/// The tickets that the realm server issued for the realm of a world server,
+/// which the clients present when logging in.
+///
+/// Each ticket can only be redeemed once.
+#[derive(Debug, Default)]
+pub struct PendingTickets {
+    tickets: Mutex<HashMap<Uuid, Instant>>,
+}
+
+impl PendingTickets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, ticket: Uuid) {
+        let mut tickets = self.tickets.lock().unwrap();
+        tickets.retain(|_, issued| issued.elapsed() < TICKET_LIFETIME);
+        tickets.insert(ticket, Instant::now());
+    }
+
+    /// Removes a ticket, returning whether it was issued and didn't expire.
+    pub fn redeem(&self, ticket: &Uuid) -> bool {
+        let issued = self.tickets.lock().unwrap().remove(ticket);
+        issued.is_some_and(|issued| issued.elapsed() < TICKET_LIFETIME)
+    }
+}
+
+/// Handles the messages sent to a world server over the cluster, until it is
+/// disconnected from the hub.
+pub async fn serve_cluster(world: Arc<WorldServer>, mut node: ClusterNode) {
+    let realm_id = u32::from(world.settings().realm_id);
+    while let Some(envelope) = node.recv().await {
+        match envelope.message {
+            ClusterMessage::SessionTicket {
+                ticket,
+                realm_id: ticket_realm,
+            } => match world.tickets() {
+                Some(tickets) if ticket_realm == realm_id => tickets.insert(ticket),
+                _ => tracing::debug!("Ignored a ticket for realm {ticket_realm}"),
+            },
+            ClusterMessage::CharacterTransfer {
+                character_id,
+                to_realm,
+            } => {
+                // it leaves the world once its session is closed
+                if world.kick(character_id) {
+                    tracing::info!("Released character {character_id} for realm {to_realm}");
+                }
+                let released = ClusterMessage::CharacterReleased {
+                    character_id,
+                    to_realm,
+                };
+                if let Err(error) = node.send_to(envelope.from, released) {
+                    tracing::warn!("Failed to answer a transfer: {error}");
+                }
+            }
+            ClusterMessage::CloseSession { key } => {
+                // every world server is asked, only the one holding it answers
+                if world.close_session(&key) {
+                    tracing::info!("Closed session {key} for {}", envelope.from);
+                    let closed = ClusterMessage::SessionClosed { key };
+                    if let Err(error) = node.send_to(envelope.from, closed) {
+                        tracing::warn!("Failed to answer a session close: {error}");
+                    }
+                }
+            }
+            message => tracing::debug!("Ignored {message:?} from {}", envelope.from),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_tickets() {
+        let tickets = PendingTickets::new();
+        let ticket = Uuid::from_u128(1);
+        assert!(!tickets.redeem(&ticket));
+        tickets.insert(ticket);
+        assert!(tickets.redeem(&ticket));
+        assert!(!tickets.redeem(&ticket));
+    }
+}
@@ -0,0 +1,520 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use ws_net::{NetError, Session};
+
+use crate::*;
+
+/// The characters that start a command in the chat box.
+pub const COMMAND_PREFIXES: [char; 2] = ['!', '.'];
+
+/// The most creatures `!spawn` spawns at once.
+const MAX_SPAWN_COUNT: u16 = 100;
+
+#[derive(Debug)]
+pub enum CommandError {
+    UnknownCommand(String),
+    MissingArgument(&'static str),
+    InvalidArgument {
+        name: &'static str,
+        value: String,
+    },
+    TooManyArguments,
+    /// The command needs a character in the world.
+    NotInWorld,
+    Failed(Text),
+    Net(NetError),
+}
+
+impl CommandError {
+    /// Returns the text telling the user what went wrong.
+    pub fn text(&self) -> Text {
+        match self {
+            Self::UnknownCommand(name) => Text::new(TextId::UnknownCommand).param(name),
+            Self::MissingArgument(name) => Text::new(TextId::MissingArgument).param(name),
+            Self::InvalidArgument { name, value } => {
+                Text::new(TextId::InvalidArgument).param(name).param(value)
+            }
+            Self::TooManyArguments => TextId::TooManyArguments.into(),
+            Self::NotInWorld => TextId::NotInWorld.into(),
+            Self::Failed(text) => text.clone(),
+            Self::Net(error) => Text::new(TextId::NetworkError).param(error),
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.text().fmt(f)
+    }
+}
+
+impl From<NetError> for CommandError {
+    fn from(error: NetError) -> Self {
+        Self::Net(error)
+    }
+}
+
+/// The reply to show to the user on success.
+pub type CommandResult<T = String> = Result<T, CommandError>;
+
+/// What a command is executed for.
+pub struct CommandContext<'a> {
+    pub server: &'a WorldServer,
+    pub session: &'a Session,
+}
+
+impl CommandContext<'_> {
+    /// Writes a text in the locale of the user.
+    pub fn text(&self, text: impl Into<Text>) -> String {
+        self.server.text(self.session, &text.into())
+    }
+}
+
+/// The arguments following a command name, parsed one at a time.
+#[derive(Debug, Clone)]
+pub struct CommandArgs<'a> {
+    rest: &'a str,
+}
+
+impl<'a> CommandArgs<'a> {
+    pub fn new(args: &'a str) -> Self {
+        Self { rest: args.trim() }
+    }
+
+    fn next_token(&mut self) -> Option<&'a str> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let (token, rest) = self
+            .rest
+            .split_once(char::is_whitespace)
+            .unwrap_or((self.rest, ""));
+        self.rest = rest.trim_start();
+        Some(token)
+    }
+
+    pub fn required<T: FromStr>(&mut self, name: &'static str) -> CommandResult<T> {
+        self.optional(name)?
+            .ok_or(CommandError::MissingArgument(name))
+    }
+
+    pub fn optional<T: FromStr>(&mut self, name: &'static str) -> CommandResult<Option<T>> {
+        match self.next_token() {
+            Some(token) => token
+                .parse()
+                .map(Some)
+                .map_err(|_| CommandError::InvalidArgument {
+                    name,
+                    value: token.to_string(),
+                }),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the rest of the line as is, for arguments that can have spaces.
+    pub fn rest(&mut self) -> &'a str {
+        std::mem::take(&mut self.rest)
+    }
+
+    /// Fails if there are arguments left.
+    pub fn finish(self) -> CommandResult<()> {
+        match self.rest.is_empty() {
+            true => Ok(()),
+            false => Err(CommandError::TooManyArguments),
+        }
+    }
+}
+
+type CommandHandler =
+    Box<dyn Fn(&CommandContext, CommandArgs) -> CommandResult + Send + Sync + 'static>;
+
+/// A command that can be executed from the chat box, built with
+/// [`Command::new`] and the other builder methods.
+pub struct Command {
+    name: &'static str,
+    aliases: Vec<&'static str>,
+    usage: &'static str,
+    description: &'static str,
+    handler: CommandHandler,
+}
+
+impl Command {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            aliases: Vec::new(),
+            usage: "",
+            description: "",
+            handler: Box::new(|_, args| args.finish().map(|_| String::new())),
+        }
+    }
+
+    pub fn alias(mut self, alias: &'static str) -> Self {
+        self.aliases.push(alias);
+        self
+    }
+
+    /// Describes the arguments, like `<x> <y> <z> [world]`.
+    pub fn usage(mut self, usage: &'static str) -> Self {
+        self.usage = usage;
+        self
+    }
+
+    pub fn description(mut self, description: &'static str) -> Self {
+        self.description = description;
+        self
+    }
+
+    pub fn handler<F>(mut self, handler: F) -> Self
+    where
+        F: Fn(&CommandContext, CommandArgs) -> CommandResult + Send + Sync + 'static,
+    {
+        self.handler = Box::new(handler);
+        self
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn help(&self) -> String {
+        let mut help = format!("!{}", self.name);
+        if !self.usage.is_empty() {
+            help.push(' ');
+            help.push_str(self.usage);
+        }
+        if !self.description.is_empty() {
+            help.push_str(" - ");
+            help.push_str(self.description);
+        }
+        help
+    }
+}
+
+impl fmt::Debug for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Command")
+            .field("name", &self.name)
+            .field("aliases", &self.aliases)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The commands available from the chat box, by name.
+///
+/// `help` is always available and lists the other commands.
+#[derive(Debug, Default)]
+pub struct CommandRegistry {
+    commands: Vec<Command>,
+    names: BTreeMap<&'static str, usize>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a registry with the commands that come with the sandbox.
+    pub fn with_builtin() -> Self {
+        let mut registry = Self::new();
+        registry.register(teleport());
+        registry.register(spawn());
+        registry.register(set_level());
+        registry.register(add_item());
+        registry.register(reload());
+        registry.register(language());
+        registry
+    }
+
+    /// Registers a command. Panics if its name or an alias is already taken.
+    pub fn register(&mut self, command: Command) {
+        let index = self.commands.len();
+        for name in std::iter::once(command.name).chain(command.aliases.iter().copied()) {
+            let previous = self.names.insert(name, index);
+            assert!(previous.is_none(), "Command {name} is already registered");
+        }
+        self.commands.push(command);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Command> {
+        let index = self.names.get(name.to_ascii_lowercase().as_str())?;
+        Some(&self.commands[*index])
+    }
+
+    /// Splits a chat line into a command name and its arguments, if it is a
+    /// command.
+    pub fn parse(line: &str) -> Option<(&str, CommandArgs<'_>)> {
+        let line = line.trim_start().strip_prefix(COMMAND_PREFIXES)?;
+        let (name, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match name.is_empty() {
+            true => None,
+            false => Some((name, CommandArgs::new(args))),
+        }
+    }
+
+    /// Executes a chat line if it is a command, and returns the result.
+    pub fn execute(&self, context: &CommandContext, line: &str) -> Option<CommandResult> {
+        let (name, args) = Self::parse(line)?;
+        if name.eq_ignore_ascii_case("help") {
+            return Some(Ok(context.text(TextId::Commands) + self.help().as_str()));
+        }
+        Some(match self.get(name) {
+            Some(command) => (command.handler)(context, args),
+            None => Err(CommandError::UnknownCommand(name.to_string())),
+        })
+    }
+
+    /// Lists the commands, a line each.
+    fn help(&self) -> String {
+        let mut help = String::new();
+        for command in &self.commands {
+            help.push('\n');
+            help.push_str(&command.help());
+        }
+        help
+    }
+}
+
+fn teleport() -> Command {
+    Command::new("teleport")
+        .alias("tp")
+        .usage("<x> <y> <z> [world]")
+        .description("Moves your character")
+        .handler(|context, mut args| {
+            let position = Position {
+                x: args.required("x")?,
+                y: args.required("y")?,
+                z: args.required("z")?,
+            };
+            let world_id = args.optional("world")?;
+            args.finish()?;
+
+            let current = context
+                .server
+                .player(context.session)
+                .ok_or(CommandError::NotInWorld)?;
+            let world_id = world_id.unwrap_or(current.character.world_id);
+            let position = place_on_terrain(context.server, world_id, position)?;
+            let player = context
+                .server
+                .update_player(context.session, |player| {
+                    player.character.position = position;
+                    player.character.world_id = world_id;
+                    player.clone()
+                })
+                .ok_or(CommandError::NotInWorld)?;
+            context.server.queue(WorldEvent::Teleport {
+                guid: player.guid,
+                world_id,
+                position,
+            });
+            context.session.send(&ServerChangeWorld {
+                world_id: player.character.world_id,
+                position,
+                yaw: player.character.yaw,
+            })?;
+            Ok(context.text(
+                Text::new(TextId::Teleported)
+                    .param(position.x)
+                    .param(position.y)
+                    .param(position.z)
+                    .param(player.character.world_id),
+            ))
+        })
+}
+
+/// Checks that a character can stand at a position, and lifts it out of the
+/// ground if it is below. Positions without terrain are left as is.
+fn place_on_terrain(
+    server: &WorldServer,
+    world_id: u32,
+    position: Position,
+) -> CommandResult<Position> {
+    let tables = server.data().tables();
+    let terrain = match tables.terrain.get(&world_id) {
+        Some(terrain) => terrain,
+        None => return Ok(position),
+    };
+    let height = match terrain.get_height(position.x, position.z) {
+        Some(height) => height,
+        None => return Ok(position),
+    };
+    if !terrain.is_walkable(position.x, position.z) {
+        return Err(CommandError::Failed(TextId::GroundTooSteep.into()));
+    }
+    Ok(Position {
+        y: position.y.max(height),
+        ..position
+    })
+}
+
+fn spawn() -> Command {
+    Command::new("spawn")
+        .usage("[count]")
+        .description("Spawns creatures at your position")
+        .handler(|context, mut args| {
+            let count: u16 = args.optional("count")?.unwrap_or(1);
+            args.finish()?;
+            if !(1..=MAX_SPAWN_COUNT).contains(&count) {
+                let text = Text::new(TextId::SpawnCountOutOfRange).param(MAX_SPAWN_COUNT);
+                return Err(CommandError::Failed(text));
+            }
+
+            let player = context
+                .server
+                .player(context.session)
+                .ok_or(CommandError::NotInWorld)?;
+            for _ in 0..count {
+                context.server.queue(WorldEvent::Spawn {
+                    guid: context.server.guids().allocate(EntityType::Creature),
+                    world_id: player.character.world_id,
+                    position: player.character.position,
+                    yaw: player.character.yaw,
+                    near: Some(player.guid),
+                });
+            }
+            Ok(context.text(Text::new(TextId::Spawned).param(count)))
+        })
+}
+
+fn set_level() -> Command {
+    Command::new("setlevel")
+        .alias("level")
+        .usage("<level>")
+        .description("Sets the level of your character")
+        .handler(|context, mut args| {
+            let level: u32 = args.required("level")?;
+            args.finish()?;
+            if !(1..=MAX_LEVEL).contains(&level) {
+                let text = Text::new(TextId::LevelOutOfRange).param(MAX_LEVEL);
+                return Err(CommandError::Failed(text));
+            }
+
+            context
+                .server
+                .update_player(context.session, |player| {
+                    player.character.level = level;
+                })
+                .ok_or(CommandError::NotInWorld)?;
+            Ok(context.text(Text::new(TextId::LevelSet).param(level)))
+        })
+}
+
+fn add_item() -> Command {
+    Command::new("additem")
+        .alias("item")
+        .usage("<item id> [count]")
+        .description("Adds items to your inventory")
+        .handler(|context, mut args| {
+            let item_id: u32 = args.required("item id")?;
+            let count: u32 = args.optional("count")?.unwrap_or(1);
+            args.finish()?;
+
+            let tables = context.server.data().tables();
+            let template = tables.items.get(&item_id).ok_or_else(|| {
+                CommandError::Failed(Text::new(TextId::UnknownItem).param(item_id))
+            })?;
+            let updates = context
+                .server
+                .update_player(context.session, |player| {
+                    let guids = context.server.guids();
+                    player
+                        .inventory
+                        .add(template, count, ItemAddReason::Gm, guids)
+                })
+                .ok_or(CommandError::NotInWorld)?
+                .map_err(|error| CommandError::Failed(error.text()))?;
+            context.session.send(&ServerItemUpdates::new(updates))?;
+            Ok(context.text(
+                Text::new(TextId::ItemsAdded)
+                    .param(count)
+                    .param(&template.name),
+            ))
+        })
+}
+
+fn reload() -> Command {
+    Command::new("reload")
+        .description("Reloads the spawn points and item templates")
+        .handler(|context, args| {
+            args.finish()?;
+            let tables = context.server.data().reload().map_err(|error| {
+                CommandError::Failed(Text::new(TextId::ReloadFailed).param(error))
+            })?;
+            Ok(context.text(
+                Text::new(TextId::Reloaded)
+                    .param(tables.spawns.len())
+                    .param(tables.items.len()),
+            ))
+        })
+}
+
+fn language() -> Command {
+    Command::new("language")
+        .alias("locale")
+        .usage("[locale]")
+        .description("Shows or changes the language of the server texts")
+        .handler(|context, mut args| {
+            let name: Option<String> = args.optional("locale")?;
+            args.finish()?;
+
+            let tables = context.server.data().tables();
+            let locales = tables.texts.locales().join(", ");
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    let locale = context.server.locale(context.session);
+                    let text = Text::new(TextId::Language).param(locale).param(locales);
+                    return Ok(context.text(text));
+                }
+            };
+            let locale = tables.texts.find_locale(&name).ok_or_else(|| {
+                CommandError::Failed(
+                    Text::new(TextId::UnknownLanguage)
+                        .param(&name)
+                        .param(&locales),
+                )
+            })?;
+            context.server.set_locale(context.session, locale);
+            Ok(context.text(Text::new(TextId::LanguageSet).param(locale)))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_args() {
+        let (name, mut args) = CommandRegistry::parse("!tp 1.5 -2  3").unwrap();
+        assert_eq!(name, "tp");
+        assert_eq!(args.required::<f32>("x").unwrap(), 1.5);
+        assert_eq!(args.required::<f32>("y").unwrap(), -2.0);
+        assert_eq!(args.optional::<f32>("z").unwrap(), Some(3.0));
+        assert_eq!(args.optional::<u32>("world").unwrap(), None);
+        args.finish().unwrap();
+
+        let (name, mut args) = CommandRegistry::parse(".setlevel ten more").unwrap();
+        assert_eq!(name, "setlevel");
+        assert!(matches!(
+            args.required::<u32>("level"),
+            Err(CommandError::InvalidArgument { name: "level", .. })
+        ));
+        assert_eq!(args.rest(), "more");
+        assert!(args.finish().is_ok());
+
+        assert!(CommandRegistry::parse("hello !tp").is_none());
+        assert!(CommandRegistry::parse("!").is_none());
+    }
+
+    #[test]
+    fn test_registry() {
+        let registry = CommandRegistry::with_builtin();
+        assert_eq!(registry.get("TP").unwrap().name(), "teleport");
+        assert_eq!(registry.get("level").unwrap().name(), "setlevel");
+        assert!(registry.get("nothing").is_none());
+        assert!(registry.help().contains("!teleport <x> <y> <z> [world] - "));
+    }
+}
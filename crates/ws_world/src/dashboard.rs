@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use bevy_ecs::prelude::*;
+use serde::Serialize;
+
+use crate::ecs::{Despawn, EntityGuid, Viewer};
+use crate::*;
+
+/// How often the world loop publishes a [`WorldSnapshot`].
+pub const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The path of the world snapshots on the debugging bridge.
+pub const DASHBOARD_PATH: &str = "/world";
+
+/// The state of the world, published by the [`WorldLoop`] every
+/// [`SNAPSHOT_INTERVAL`] for a live map or dashboard.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct WorldSnapshot {
+    /// The number of ticks run so far.
+    pub tick: u64,
+    pub players_online: usize,
+    pub instances: Vec<InstanceSnapshot>,
+    pub timings: TickTimings,
+}
+
+/// The entities of an instance of a map.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstanceSnapshot {
+    pub id: u32,
+    pub world_id: u32,
+    /// The number of entities, players included.
+    pub entities: usize,
+    pub players: Vec<PlayerPosition>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct PlayerPosition {
+    pub guid: Guid,
+    pub position: Position,
+}
+
+/// How long the ticks since the previous snapshot took.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct TickTimings {
+    pub ticks: u32,
+    pub average_ms: f64,
+    pub max_ms: f64,
+    /// The ticks that took longer than the tick duration since the server
+    /// started.
+    pub overruns: u64,
+}
+
+impl TickTimings {
+    /// Adds the duration of a tick.
+    pub fn record(&mut self, elapsed: Duration) {
+        let elapsed = elapsed.as_secs_f64() * 1000.0;
+        let total = self.average_ms * self.ticks as f64 + elapsed;
+        self.ticks += 1;
+        self.average_ms = total / self.ticks as f64;
+        self.max_ms = self.max_ms.max(elapsed);
+    }
+}
+
+impl InstanceSnapshot {
+    pub fn new(instance: &mut Instance) -> Self {
+        let id = instance.id().0;
+        let world_id = instance.world_id();
+        let world = instance.world_mut();
+        let entities = world
+            .query_filtered::<(), (With<EntityGuid>, Without<Despawn>)>()
+            .iter(world)
+            .count();
+        let mut players: Vec<PlayerPosition> = world
+            .query_filtered::<(&EntityGuid, &Position), (With<Viewer>, Without<Despawn>)>()
+            .iter(world)
+            .map(|(guid, position)| PlayerPosition {
+                guid: guid.0,
+                position: *position,
+            })
+            .collect();
+        players.sort_by_key(|player| player.guid);
+        Self {
+            id,
+            world_id,
+            entities,
+            players,
+        }
+    }
+}
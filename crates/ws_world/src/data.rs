@@ -0,0 +1,512 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use ws_tbl::{Table, TblError, TblRecord};
+
+use crate::{Position, SpellEffect, Terrain, TerrainError, TextTables};
+
+/// Where a creature is spawned.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpawnPoint {
+    pub creature_id: u32,
+    pub world_id: u32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    #[serde(default)]
+    pub yaw: f32,
+}
+
+impl SpawnPoint {
+    pub fn position(&self) -> Position {
+        Position {
+            x: self.x,
+            y: self.y,
+            z: self.z,
+        }
+    }
+}
+
+/// What every item with the same id has in common.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ItemTemplate {
+    pub id: u32,
+    pub name: String,
+    #[serde(default)]
+    pub quality: u8,
+    #[serde(default = "default_max_stack")]
+    pub max_stack: u32,
+}
+
+fn default_max_stack() -> u32 {
+    1
+}
+
+/// The columns of the client `Item2.tbl` that item templates are made of.
+#[derive(TblRecord)]
+struct ItemRecord {
+    id: u32,
+    #[tbl(column = "itemQualityId")]
+    quality: u32,
+    max_stack_count: u32,
+}
+
+impl From<ItemRecord> for ItemTemplate {
+    fn from(record: ItemRecord) -> Self {
+        Self {
+            id: record.id,
+            // the names are in the client language files, which aren't read
+            name: format!("Item {}", record.id),
+            quality: record.quality as u8,
+            max_stack: record.max_stack_count.max(1),
+        }
+    }
+}
+
+/// What a spell does, and how it is cast.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct SpellTemplate {
+    pub id: u32,
+    /// In milliseconds, 0 for instant spells.
+    #[serde(default)]
+    pub cast_time: u32,
+    /// In milliseconds.
+    #[serde(default)]
+    pub cooldown: u32,
+    #[serde(default = "default_spell_range")]
+    pub range: f32,
+    pub effect: SpellEffect,
+    pub amount: f32,
+}
+
+fn default_spell_range() -> f32 {
+    30.0
+}
+
+/// The columns of the client `Spell4.tbl` that spell templates are made of.
+#[derive(TblRecord)]
+struct SpellRecord {
+    id: u32,
+    cast_time: u32,
+    #[tbl(column = "spellCoolDown")]
+    cooldown: u32,
+    #[tbl(column = "targetMaxRange")]
+    range: f32,
+}
+
+/// The columns of the client `Spell4Effects.tbl`, which holds the effects of
+/// the spells.
+#[derive(TblRecord)]
+struct SpellEffectRecord {
+    spell_id: u32,
+    effect_type: u32,
+    #[tbl(column = "dataBits00")]
+    amount: u32,
+}
+
+impl SpellEffectRecord {
+    const DAMAGE: u32 = 0;
+    const HEAL: u32 = 10;
+
+    fn effect(&self) -> Option<SpellEffect> {
+        match self.effect_type {
+            Self::DAMAGE => Some(SpellEffect::Damage),
+            Self::HEAL => Some(SpellEffect::Heal),
+            _ => None,
+        }
+    }
+}
+
+/// Makes the templates of the client spells that have an effect the server
+/// knows, using their first one.
+fn spells_from_tbl(
+    spells: Vec<SpellRecord>,
+    effects: Vec<SpellEffectRecord>,
+) -> HashMap<u32, SpellTemplate> {
+    let mut spell_effects = HashMap::new();
+    for record in effects {
+        if let Some(effect) = record.effect() {
+            spell_effects
+                .entry(record.spell_id)
+                .or_insert((effect, record.amount as f32));
+        }
+    }
+    spells
+        .into_iter()
+        .filter_map(|spell| {
+            let (effect, amount) = *spell_effects.get(&spell.id)?;
+            let template = SpellTemplate {
+                id: spell.id,
+                cast_time: spell.cast_time,
+                cooldown: spell.cooldown,
+                range: spell.range,
+                effect,
+                amount,
+            };
+            Some((spell.id, template))
+        })
+        .collect()
+}
+
+/// A word that character names can't contain.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct BlockedName {
+    word: String,
+}
+
+/// A world that every group gets its own instance of.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Dungeon {
+    world_id: u32,
+}
+
+/// The game data loaded at once from a data directory.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DataTables {
+    pub spawns: Vec<SpawnPoint>,
+    pub items: HashMap<u32, ItemTemplate>,
+    pub spells: HashMap<u32, SpellTemplate>,
+    /// The terrain of the worlds that have one, by world id.
+    pub terrain: HashMap<u32, Terrain>,
+    /// The words that character names can't contain.
+    pub blocked_names: Vec<String>,
+    /// The worlds that every group gets its own instance of, by world id.
+    pub dungeons: HashSet<u32>,
+    /// The texts written for the players in other languages than English.
+    pub texts: TextTables,
+}
+
+impl DataTables {
+    /// Loads the tables of a data directory.
+    ///
+    /// Each table is read from `<name>.json`, an array of records, or from
+    /// `<name>.csv`, with a header naming the fields. Tables without a file
+    /// are empty, so that a sandbox runs without any data.
+    ///
+    /// The item and spell templates are read from the client `Item2.tbl`,
+    /// `Spell4.tbl` and `Spell4Effects.tbl` if there are, and the `items` and
+    /// `spells` tables add to them or replace them.
+    ///
+    /// The terrain of a world is read from the `.area` files of
+    /// `maps/<world id>`, and the texts of a locale from `text/<locale>`.
+    pub fn load(dir: &Path) -> DataResult<Self> {
+        let spawns = load_table(dir, "spawns")?;
+        let mut items: HashMap<_, _> = load_tbl::<ItemRecord>(dir, "Item2")?
+            .into_iter()
+            .map(|record| (record.id, ItemTemplate::from(record)))
+            .collect();
+        let mut overridden = HashSet::new();
+        for item in load_table::<ItemTemplate>(dir, "items")? {
+            if !overridden.insert(item.id) {
+                return Err(DataError::DuplicateItem(item.id));
+            }
+            items.insert(item.id, item);
+        }
+        let mut spells = spells_from_tbl(load_tbl(dir, "Spell4")?, load_tbl(dir, "Spell4Effects")?);
+        let mut overridden = HashSet::new();
+        for spell in load_table::<SpellTemplate>(dir, "spells")? {
+            if !overridden.insert(spell.id) {
+                return Err(DataError::DuplicateSpell(spell.id));
+            }
+            spells.insert(spell.id, spell);
+        }
+        let blocked_names = load_table::<BlockedName>(dir, "blocked_names")?
+            .into_iter()
+            .map(|blocked| blocked.word)
+            .collect();
+        let dungeons = load_table::<Dungeon>(dir, "dungeons")?
+            .into_iter()
+            .map(|dungeon| dungeon.world_id)
+            .collect();
+        Ok(Self {
+            spawns,
+            items,
+            spells,
+            terrain: load_terrain(&dir.join("maps"))?,
+            blocked_names,
+            dungeons,
+            texts: TextTables::load(&dir.join("text"))?,
+        })
+    }
+
+    /// Returns the spawn points of a world.
+    pub fn spawns_in(&self, world_id: u32) -> impl Iterator<Item = &SpawnPoint> {
+        self.spawns
+            .iter()
+            .filter(move |spawn| spawn.world_id == world_id)
+    }
+
+    /// Returns a position moved to the height of the ground, or as is if the
+    /// terrain there isn't known.
+    pub fn on_ground(&self, world_id: u32, position: Position) -> Position {
+        let height = self
+            .terrain
+            .get(&world_id)
+            .and_then(|terrain| terrain.get_height(position.x, position.z));
+        Position {
+            y: height.unwrap_or(position.y),
+            ..position
+        }
+    }
+}
+
+fn load_terrain(dir: &Path) -> DataResult<HashMap<u32, Terrain>> {
+    let mut terrain = HashMap::new();
+    if !dir.exists() {
+        return Ok(terrain);
+    }
+    let entries = std::fs::read_dir(dir).map_err(|error| DataError::Io {
+        path: dir.to_path_buf(),
+        error,
+    })?;
+    for entry in entries {
+        let path = entry
+            .map_err(|error| DataError::Io {
+                path: dir.to_path_buf(),
+                error,
+            })?
+            .path();
+        let world_id = path
+            .file_name()
+            .and_then(|name| name.to_str()?.parse().ok());
+        if let (true, Some(world_id)) = (path.is_dir(), world_id) {
+            terrain.insert(world_id, Terrain::load_dir(&path)?);
+        }
+    }
+    Ok(terrain)
+}
+
+pub(crate) fn load_table<T: DeserializeOwned>(dir: &Path, name: &str) -> DataResult<Vec<T>> {
+    let path = dir.join(format!("{name}.json"));
+    if path.exists() {
+        let text = read(&path)?;
+        return serde_json::from_str(&text).map_err(|error| DataError::Json { path, error });
+    }
+    let path = dir.join(format!("{name}.csv"));
+    if path.exists() {
+        let text = read(&path)?;
+        return csv::Reader::from_reader(text.as_bytes())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .map_err(|error| DataError::Csv { path, error });
+    }
+    Ok(Vec::new())
+}
+
+fn load_tbl<T: TblRecord>(dir: &Path, name: &str) -> DataResult<Vec<T>> {
+    let path = dir.join(format!("{name}.tbl"));
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    Table::open(&path)
+        .and_then(|table| table.records())
+        .map_err(|error| DataError::Tbl { path, error })
+}
+
+fn read(path: &Path) -> DataResult<String> {
+    std::fs::read_to_string(path).map_err(|error| DataError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// The data tables of a world server, which can be reloaded while it runs.
+///
+/// Readers get a snapshot of the tables, so a reload never changes the data
+/// in the middle of a handler.
+#[derive(Debug, Default)]
+pub struct DataStore {
+    dir: Option<PathBuf>,
+    tables: RwLock<Arc<DataTables>>,
+}
+
+impl DataStore {
+    /// Returns a store with empty tables, which reloads nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the tables of a data directory.
+    pub fn load(dir: impl Into<PathBuf>) -> DataResult<Self> {
+        let dir = dir.into();
+        let tables = DataTables::load(&dir)?;
+        Ok(Self {
+            dir: Some(dir),
+            tables: RwLock::new(Arc::new(tables)),
+        })
+    }
+
+    pub fn tables(&self) -> Arc<DataTables> {
+        self.tables.read().unwrap().clone()
+    }
+
+    /// Loads the tables again from the data directory, and returns them.
+    ///
+    /// The current tables are kept if any of them fails to load.
+    pub fn reload(&self) -> DataResult<Arc<DataTables>> {
+        let tables = match &self.dir {
+            Some(dir) => Arc::new(DataTables::load(dir)?),
+            None => Arc::default(),
+        };
+        *self.tables.write().unwrap() = tables.clone();
+        Ok(tables)
+    }
+}
+
+#[derive(Debug)]
+pub enum DataError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Json {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    Csv {
+        path: PathBuf,
+        error: csv::Error,
+    },
+    Tbl {
+        path: PathBuf,
+        error: TblError,
+    },
+    /// Two item templates of the `items` table have the same id.
+    DuplicateItem(u32),
+    /// Two spell templates of the `spells` table have the same id.
+    DuplicateSpell(u32),
+    Terrain(TerrainError),
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "failed to read {}: {error}", path.display()),
+            Self::Json { path, error } => write!(f, "invalid {}: {error}", path.display()),
+            Self::Csv { path, error } => write!(f, "invalid {}: {error}", path.display()),
+            Self::Tbl { path, error } => write!(f, "invalid {}: {error}", path.display()),
+            Self::DuplicateItem(id) => write!(f, "item {id} is defined twice"),
+            Self::DuplicateSpell(id) => write!(f, "spell {id} is defined twice"),
+            Self::Terrain(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DataError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::Json { error, .. } => Some(error),
+            Self::Csv { error, .. } => Some(error),
+            Self::Tbl { error, .. } => Some(error),
+            Self::DuplicateItem(_) | Self::DuplicateSpell(_) => None,
+            Self::Terrain(error) => Some(error),
+        }
+    }
+}
+
+impl From<TerrainError> for DataError {
+    fn from(error: TerrainError) -> Self {
+        DataError::Terrain(error)
+    }
+}
+
+pub type DataResult<T = ()> = Result<T, DataError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_reload() {
+        let dir = std::env::temp_dir().join(format!("ws_world_data_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("spawns.csv"),
+            "creature_id,world_id,x,y,z\n\
+             20,870,1,2,3\n\
+             21,51,4,5,6\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("items.json"),
+            r#"[{ "id": 7, "name": "Sword", "quality": 2 }]"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("blocked_names.csv"), "word\ndarn\n").unwrap();
+        std::fs::write(
+            dir.join("spells.csv"),
+            "id,cast_time,effect,amount\n\
+             435,1500,Damage,25\n",
+        )
+        .unwrap();
+
+        let store = DataStore::load(&dir).unwrap();
+        let tables = store.tables();
+        assert_eq!(tables.spawns.len(), 2);
+        let spawn = tables.spawns_in(870).next().unwrap();
+        assert_eq!(spawn.creature_id, 20);
+        assert_eq!(spawn.yaw, 0.0);
+        assert_eq!(
+            spawn.position(),
+            Position {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0
+            }
+        );
+        assert_eq!(tables.items[&7].name, "Sword");
+        assert_eq!(tables.items[&7].max_stack, 1);
+        assert!(tables.terrain.is_empty());
+        assert_eq!(tables.blocked_names, ["darn"]);
+        assert_eq!(tables.spells[&435].effect, SpellEffect::Damage);
+        assert_eq!(tables.spells[&435].range, 30.0);
+
+        // a broken file keeps the tables loaded before
+        std::fs::write(dir.join("items.json"), "[{ \"id\": 7 }]").unwrap();
+        assert!(matches!(store.reload(), Err(DataError::Json { .. })));
+        assert_eq!(store.tables(), tables);
+
+        std::fs::remove_file(dir.join("items.json")).unwrap();
+        let reloaded = store.reload().unwrap();
+        assert!(reloaded.items.is_empty());
+        assert_eq!(reloaded.spawns, tables.spawns);
+        // snapshots taken before the reload don't change
+        assert_eq!(tables.items.len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_terrain() {
+        let dir = std::env::temp_dir().join(format!("ws_world_terrain_{}", std::process::id()));
+        let maps = dir.join("maps").join("870");
+        std::fs::create_dir_all(&maps).unwrap();
+        std::fs::write(
+            maps.join("Sandbox.4040.area"),
+            crate::terrain::build_area(0, |_, _| -980.0),
+        )
+        .unwrap();
+
+        let tables = DataTables::load(&dir).unwrap();
+        let position = Position {
+            x: 10.0,
+            y: 0.0,
+            z: 10.0,
+        };
+        assert_eq!(tables.on_ground(870, position).y, -980.0);
+        assert_eq!(tables.on_ground(51, position), position);
+
+        std::fs::write(maps.join("Sandbox.area"), []).unwrap();
+        assert!(matches!(
+            DataTables::load(&dir),
+            Err(DataError::Terrain(TerrainError::InvalidFileName(_)))
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
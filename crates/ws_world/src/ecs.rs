@@ -0,0 +1,942 @@
+//! The components of the world entities, and the systems that the
+//! [`WorldLoop`](crate::WorldLoop) runs every tick.
+//!
+//! The systems that simulate the world only change components. The changes
+//! are then turned into updates by [`update_visibility`], which tracks who
+//! sees what in the [`Grids`], and by [`update_properties`], then sent by
+//! [`send_updates`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use bevy_ecs::component::{Mutable, StorageType};
+use bevy_ecs::prelude::*;
+use ws_bitpack::WriteValue;
+use ws_messages::Message;
+use ws_net::Session;
+
+use crate::{
+    EntityProperties, Guid, InstanceKey, Position, Property, PropertyValue, Relevance,
+    ServerPropertyUpdates, ServerSpellGo, SpellEffect, SpellTargetResult, SpellTemplate,
+    UpdateBuilder, UpdateConfig, VisibilityChange, VisibilityGrid, WorldEvent,
+};
+
+/// The size of the visibility cells, and how many cells around their own
+/// players see.
+pub const VIEW_CELL_SIZE: f32 = 64.0;
+pub const VIEW_RADIUS: u32 = 2;
+
+impl Component for Position {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+    type Mutability = Mutable;
+}
+
+/// The guid the clients know an entity by.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EntityGuid(pub Guid);
+
+/// The id of the creature an entity is, for the creatures of the spawn
+/// points.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Creature(pub u32);
+
+/// The time since the `on_update` handler of the script of a creature last ran,
+/// added once its `on_spawn` handler ran.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ScriptTimer(pub Duration);
+
+/// The direction an entity faces, in radians.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Yaw(pub f32);
+
+/// Walks an entity to a destination, and is removed once it arrived.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Movement {
+    pub destination: Position,
+    /// In units per second.
+    pub speed: f32,
+}
+
+/// Whether a [`StatModifier`] adds to a property, or multiplies it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifierKind {
+    Flat,
+    /// Adds a percentage of the value, once the flat modifiers are added.
+    Percent,
+}
+
+/// Changes a property of an entity until its source, like a buff or an
+/// equipped item, removes it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatModifier {
+    pub source: u32,
+    pub property: Property,
+    pub kind: ModifierKind,
+    pub value: f32,
+}
+
+/// The properties of an entity, like its health or its armor.
+///
+/// Each property is computed from a base value and the modifiers of the
+/// property. Pools, like `Health`, aren't modified and are kept between 0
+/// and their max. The properties that changed are tracked until they are
+/// taken by [`update_properties`], which sends them to the players once per
+/// tick.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct Stats {
+    base: [f32; Property::ALL.len()],
+    values: [f32; Property::ALL.len()],
+    modifiers: Vec<StatModifier>,
+    /// The properties changed since they were last taken, one bit each.
+    changed: u32,
+}
+
+impl Stats {
+    /// Returns stats where every property is 0.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns stats with full health, and no other property.
+    pub fn with_health(max_health: f32, regeneration: f32) -> Self {
+        let mut stats = Self::new();
+        stats.set_base(Property::MaxHealth, max_health);
+        stats.set_base(Property::Health, max_health);
+        stats.set_base(Property::HealthRegeneration, regeneration);
+        stats
+    }
+
+    pub fn get(&self, property: Property) -> f32 {
+        self.values[property as usize]
+    }
+
+    pub fn base(&self, property: Property) -> f32 {
+        self.base[property as usize]
+    }
+
+    pub fn is_alive(&self) -> bool {
+        self.get(Property::Health) > 0.0
+    }
+
+    /// Sets the value of a property before its modifiers. Pools are set to
+    /// the value capped by their max.
+    pub fn set_base(&mut self, property: Property, value: f32) {
+        self.base[property as usize] = value;
+        self.compute(property);
+    }
+
+    /// Adds to the value of a pool, like healing or spending focus, and
+    /// returns what it changed by once capped.
+    pub fn add_to_pool(&mut self, pool: Property, amount: f32) -> f32 {
+        let previous = self.get(pool);
+        self.set_base(pool, previous + amount);
+        self.get(pool) - previous
+    }
+
+    /// Damages the entity, its shield first, and returns the health it lost.
+    pub fn damage(&mut self, amount: f32) -> f32 {
+        let absorbed = -self.add_to_pool(Property::Shield, -amount);
+        -self.add_to_pool(Property::Health, absorbed - amount)
+    }
+
+    pub fn add_modifier(&mut self, modifier: StatModifier) {
+        self.modifiers.push(modifier);
+        self.compute(modifier.property);
+    }
+
+    /// Removes the modifiers of a source.
+    pub fn remove_modifiers(&mut self, source: u32) {
+        let mut properties = Vec::new();
+        self.modifiers.retain(|modifier| {
+            let keep = modifier.source != source;
+            if !keep {
+                properties.push(modifier.property);
+            }
+            keep
+        });
+        for property in properties {
+            self.compute(property);
+        }
+    }
+
+    pub fn has_changes(&self) -> bool {
+        self.changed != 0
+    }
+
+    /// Returns the properties that changed since the last call.
+    pub fn take_changes(&mut self) -> Vec<PropertyValue> {
+        let changed = std::mem::take(&mut self.changed);
+        Property::ALL
+            .into_iter()
+            .filter(|property| changed & (1 << *property as u32) != 0)
+            .map(|property| self.value(property))
+            .collect()
+    }
+
+    /// Returns every property, for players who start seeing the entity.
+    pub fn snapshot(&self) -> Vec<PropertyValue> {
+        Property::ALL
+            .into_iter()
+            .map(|property| self.value(property))
+            .collect()
+    }
+
+    fn value(&self, property: Property) -> PropertyValue {
+        PropertyValue {
+            property,
+            value: self.get(property),
+        }
+    }
+
+    fn compute(&mut self, property: Property) {
+        let index = property as usize;
+        let value = match property.max() {
+            Some(max) => {
+                // what is lost when the max goes down doesn't come back
+                let value = self.base[index].clamp(0.0, self.get(max));
+                self.base[index] = value;
+                value
+            }
+            None => {
+                let (flat, percent) = self
+                    .modifiers
+                    .iter()
+                    .filter(|modifier| modifier.property == property)
+                    .fold((0.0, 0.0), |(flat, percent), modifier| {
+                        match modifier.kind {
+                            ModifierKind::Flat => (flat + modifier.value, percent),
+                            ModifierKind::Percent => (flat, percent + modifier.value),
+                        }
+                    });
+                (self.base[index] + flat) * (1.0 + percent / 100.0)
+            }
+        };
+        if value != self.values[index] {
+            self.values[index] = value;
+            self.changed |= 1 << index;
+        }
+        if let Some(pool) = property.pool() {
+            self.compute(pool);
+        }
+    }
+}
+
+/// Makes an entity visible to the players around it in a world.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Visibility {
+    pub world_id: u32,
+}
+
+/// The number of entities whose properties are sent in a message, which
+/// keeps the messages of snapshots in a frame.
+pub const MAX_PROPERTY_ENTITIES: usize = 100;
+
+/// A player, who sees the entities around it, and the updates it will be
+/// sent at the end of the tick.
+#[derive(Component, Debug)]
+pub struct Viewer {
+    pub session: Session,
+    pub updates: UpdateBuilder,
+    pub properties: Vec<EntityProperties>,
+    /// The number of ticks the player was sent its updates at, which sets
+    /// whose are due, see [`Relevance`].
+    pub ticks: u64,
+}
+
+impl Viewer {
+    pub fn new(session: Session) -> Self {
+        Self {
+            session,
+            updates: UpdateBuilder::new(UpdateConfig::default()),
+            properties: Vec::new(),
+            ticks: 0,
+        }
+    }
+
+    /// Destroys an entity for the player, with the updates held back for it.
+    fn forget(&mut self, guid: Guid) {
+        self.updates.destroy(guid);
+        self.properties.retain(|properties| properties.guid != guid);
+    }
+
+    /// Forgets the updates of the tick, when the player changes worlds.
+    fn reset(&mut self) {
+        self.updates = UpdateBuilder::new(UpdateConfig::default());
+        self.properties.clear();
+    }
+}
+
+/// The entity a player last cast a spell on, whose updates it gets every
+/// tick.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Target(pub Guid);
+
+/// The group of a player, whose members get each other's updates every tick.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grouped(pub u64);
+
+/// A spell being cast, which takes effect once `remaining` reaches 0.
+#[derive(Component, Debug, Clone, PartialEq)]
+pub struct Casting {
+    pub cast_id: u32,
+    pub spell: SpellTemplate,
+    pub target: Guid,
+    /// In seconds.
+    pub remaining: f32,
+}
+
+/// The spells an entity can't cast again yet, with the seconds left.
+#[derive(Component, Debug, Clone, Default, PartialEq)]
+pub struct Cooldowns(pub HashMap<u32, f32>);
+
+/// Removes an entity from the world at the end of the tick, after the
+/// players who saw it were told.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Despawn;
+
+/// Moves an entity to the instance of another world at the end of the tick,
+/// see [`leave_instance`].
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct Leaving {
+    pub key: InstanceKey,
+    pub world_id: u32,
+    pub position: Position,
+}
+
+/// An entity on its way to another instance, with what it keeps of the one
+/// it left.
+#[derive(Debug)]
+pub struct Traveler {
+    pub guid: Guid,
+    pub key: InstanceKey,
+    pub world_id: u32,
+    pub position: Position,
+    pub yaw: f32,
+    pub stats: Option<Stats>,
+    pub cooldowns: Option<Cooldowns>,
+    pub group: Option<Grouped>,
+    /// The session of the player, who sees the entities of the new instance.
+    pub session: Option<Session>,
+}
+
+/// The world whose grid an entity is in, which differs from its
+/// [`Visibility`] until [`update_visibility`] moves it.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tracked(u32);
+
+/// The entities by guid.
+#[derive(Resource, Debug, Default)]
+pub struct GuidIndex(pub HashMap<Guid, Entity>);
+
+/// The visibility grid of each world.
+#[derive(Resource, Debug, Default)]
+pub struct Grids(pub HashMap<u32, VisibilityGrid<()>>);
+
+impl Grids {
+    fn grid(&mut self, world_id: u32) -> &mut VisibilityGrid<()> {
+        self.0
+            .entry(world_id)
+            .or_insert_with(|| VisibilityGrid::new(VIEW_CELL_SIZE, VIEW_RADIUS))
+    }
+}
+
+/// The events that the systems of an instance send to the rest of the world,
+/// like teleporting a player to another map. Instances tick in parallel and
+/// can't change each other, so the world loop applies these at the start of
+/// the next tick, in the order of the instances.
+#[derive(Resource, Debug, Default)]
+pub struct Outbox(pub Vec<WorldEvent>);
+
+/// The time simulated by the current tick, in seconds.
+#[derive(Resource, Debug, Clone, Copy, Default)]
+pub struct TickTime(pub f32);
+
+/// Walks the entities that have a destination.
+pub fn walk(
+    time: Res<TickTime>,
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut Position, &mut Yaw, &Movement)>,
+) {
+    for (entity, mut position, mut yaw, movement) in &mut query {
+        let destination = movement.destination;
+        let (dx, dy, dz) = (
+            destination.x - position.x,
+            destination.y - position.y,
+            destination.z - position.z,
+        );
+        let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+        let step = movement.speed * time.0;
+        if distance <= step {
+            *position = destination;
+            commands.entity(entity).remove::<Movement>();
+        } else {
+            let ratio = step / distance;
+            position.x += dx * ratio;
+            position.y += dy * ratio;
+            position.z += dz * ratio;
+            yaw.0 = dx.atan2(dz);
+        }
+    }
+}
+
+/// Finishes the casts whose time is up, applying their effect to their
+/// target, and counts down the cooldowns.
+pub fn cast_spells(world: &mut World) {
+    let elapsed = world.resource::<TickTime>().0;
+    for mut cooldowns in world.query::<&mut Cooldowns>().iter_mut(world) {
+        cooldowns.0.retain(|_, remaining| {
+            *remaining -= elapsed;
+            *remaining > 0.0
+        });
+    }
+
+    let mut finished = Vec::new();
+    for (entity, guid, mut casting) in world
+        .query::<(Entity, &EntityGuid, &mut Casting)>()
+        .iter_mut(world)
+    {
+        casting.remaining -= elapsed;
+        if casting.remaining <= 0.0 {
+            finished.push((entity, guid.0));
+        }
+    }
+
+    for (entity, caster) in finished {
+        let casting = match world.entity_mut(entity).take::<Casting>() {
+            Some(casting) => casting,
+            None => continue,
+        };
+        let spell = &casting.spell;
+        if spell.cooldown > 0 {
+            let cooldown = spell.cooldown as f32 / 1000.0;
+            world
+                .entity_mut(entity)
+                .entry::<Cooldowns>()
+                .or_default()
+                .get_mut()
+                .0
+                .insert(spell.id, cooldown);
+        }
+
+        let target = world
+            .resource::<GuidIndex>()
+            .0
+            .get(&casting.target)
+            .copied();
+        let mut targets = Vec::new();
+        // targets that died or left while the spell was cast are spared
+        if let Some(mut stats) = target.and_then(|target| world.get_mut::<Stats>(target)) {
+            if stats.is_alive() {
+                let amount = match spell.effect {
+                    SpellEffect::Damage => stats.damage(spell.amount),
+                    SpellEffect::Heal => stats.add_to_pool(Property::Health, spell.amount),
+                };
+                targets.push(SpellTargetResult {
+                    target: casting.target,
+                    effect: spell.effect,
+                    amount,
+                });
+            }
+        }
+        let go = ServerSpellGo {
+            cast_id: casting.cast_id,
+            caster,
+            spell_id: spell.id,
+            count: targets.len() as u8,
+            targets,
+        };
+        broadcast(world, caster, &go);
+    }
+}
+
+/// Sends a message at once to the players who see an entity, and to the
+/// entity itself if it is a player.
+pub fn broadcast<T>(world: &World, guid: Guid, message: &T)
+where
+    T: Message + WriteValue + fmt::Debug,
+{
+    let index = world.resource::<GuidIndex>();
+    let entity = match index.0.get(&guid) {
+        Some(entity) => *entity,
+        None => return,
+    };
+    let observers: Vec<_> = world
+        .get::<Tracked>(entity)
+        .and_then(|tracked| world.resource::<Grids>().0.get(&tracked.0))
+        .map(|grid| grid.observers_of(guid).map(|(guid, _)| guid).collect())
+        .unwrap_or_default();
+    let entities = std::iter::once(entity).chain(
+        observers
+            .into_iter()
+            .filter_map(|observer| index.0.get(&observer).copied()),
+    );
+    for entity in entities {
+        if let Some(viewer) = world.get::<Viewer>(entity) {
+            // sessions that closed are removed when disconnecting
+            let _ = viewer.session.send(message);
+        }
+    }
+}
+
+/// Regenerates the health and shield of the living entities.
+pub fn regenerate(time: Res<TickTime>, mut query: Query<&mut Stats>) {
+    const POOLS: [(Property, Property); 2] = [
+        (Property::Health, Property::HealthRegeneration),
+        (Property::Shield, Property::ShieldRegeneration),
+    ];
+    for mut stats in &mut query {
+        if !stats.is_alive() {
+            continue;
+        }
+        for (pool, regeneration) in POOLS {
+            // only the stats that aren't full are changed
+            let max = pool.max().expect("regenerated properties are pools");
+            if stats.get(pool) < stats.get(max) && stats.get(regeneration) > 0.0 {
+                let amount = stats.get(regeneration) * time.0;
+                stats.add_to_pool(pool, amount);
+            }
+        }
+    }
+}
+
+/// Sends the entities [`Leaving`] their instance to the world loop through
+/// the [`Outbox`], with their stats, cooldowns and group. The casts in
+/// progress and the target are lost. The entity leaves like it was removed,
+/// without telling the player who already changed worlds.
+#[allow(clippy::type_complexity)]
+pub fn leave_instance(
+    mut commands: Commands,
+    mut outbox: ResMut<Outbox>,
+    leaving: Query<
+        (
+            Entity,
+            &EntityGuid,
+            &Leaving,
+            Option<&Yaw>,
+            Option<&Stats>,
+            Option<&Cooldowns>,
+            Option<&Grouped>,
+            Option<&Viewer>,
+        ),
+        Without<Despawn>,
+    >,
+) {
+    for (entity, guid, leaving, yaw, stats, cooldowns, group, viewer) in &leaving {
+        outbox.0.push(WorldEvent::Arrive(Box::new(Traveler {
+            guid: guid.0,
+            key: leaving.key,
+            world_id: leaving.world_id,
+            position: leaving.position,
+            yaw: yaw.copied().unwrap_or_default().0,
+            stats: stats.cloned(),
+            cooldowns: cooldowns.cloned(),
+            group: group.copied(),
+            session: viewer.map(|viewer| viewer.session.clone()),
+        })));
+        commands
+            .entity(entity)
+            .remove::<(Leaving, Viewer)>()
+            .insert(Despawn);
+    }
+}
+
+/// Moves the entities that moved or changed worlds in the visibility grids,
+/// and adds the creates, moves and destroys seen by the players to their
+/// updates. Despawned entities are removed.
+#[allow(clippy::type_complexity)]
+pub fn update_visibility(
+    mut commands: Commands,
+    mut grids: ResMut<Grids>,
+    mut index: ResMut<GuidIndex>,
+    despawned: Query<(Entity, &EntityGuid, Option<&Tracked>), With<Despawn>>,
+    mut changed: Query<
+        (
+            Entity,
+            &EntityGuid,
+            &Position,
+            &Visibility,
+            Option<&mut Tracked>,
+            Has<Viewer>,
+        ),
+        (
+            Or<(Changed<Position>, Changed<Visibility>)>,
+            Without<Despawn>,
+        ),
+    >,
+    entities: Query<(&'static Position, &'static Yaw, Option<&'static Stats>)>,
+    mut viewers: Query<&'static mut Viewer>,
+) {
+    let mut notifier = Notifier {
+        index: &index,
+        entities: &entities,
+        viewers: &mut viewers,
+    };
+
+    for (entity, guid, tracked) in &despawned {
+        if let Some(Tracked(world_id)) = tracked {
+            let change = grids.grid(*world_id).remove(guid.0);
+            notifier.notify(guid.0, &change);
+        }
+        commands.entity(entity).despawn();
+    }
+
+    for (entity, guid, position, visibility, tracked, is_viewer) in &mut changed {
+        let guid = guid.0;
+        match tracked {
+            Some(tracked) if tracked.0 == visibility.world_id => {
+                let grid = grids.grid(visibility.world_id);
+                let change = grid.move_entity(guid, position);
+                let observers: Vec<_> = grid.observers_of(guid).map(|(guid, _)| guid).collect();
+                notifier.notify(guid, &change);
+                for observer in observers {
+                    notifier.update(observer, |viewer| viewer.updates.move_to(guid, *position));
+                }
+            }
+            tracked => {
+                if let Some(Tracked(world_id)) = tracked.as_deref() {
+                    let change = grids.grid(*world_id).remove(guid);
+                    notifier.notify(guid, &change);
+                    // the client forgets everything it saw in the previous world
+                    notifier.update(guid, Viewer::reset);
+                }
+                let grid = grids.grid(visibility.world_id);
+                let change = match is_viewer {
+                    true => grid.insert_observer(guid, position, ()),
+                    false => grid.insert(guid, position),
+                };
+                notifier.notify(guid, &change);
+                match tracked {
+                    Some(mut tracked) => tracked.0 = visibility.world_id,
+                    None => {
+                        commands.entity(entity).insert(Tracked(visibility.world_id));
+                    }
+                }
+            }
+        }
+    }
+
+    for (_, guid, _) in &despawned {
+        index.0.remove(&guid.0);
+    }
+}
+
+/// Adds the visibility changes of an entity to the updates of the players.
+struct Notifier<'a, 'w, 's, 'vw, 'vs> {
+    index: &'a GuidIndex,
+    entities: &'a Query<'w, 's, (&'static Position, &'static Yaw, Option<&'static Stats>)>,
+    viewers: &'a mut Query<'vw, 'vs, &'static mut Viewer>,
+}
+
+impl Notifier<'_, '_, '_, '_, '_> {
+    fn update(&mut self, guid: Guid, f: impl FnOnce(&mut Viewer)) {
+        let entity = match self.index.0.get(&guid) {
+            Some(entity) => *entity,
+            None => return,
+        };
+        if let Ok(mut viewer) = self.viewers.get_mut(entity) {
+            f(&mut viewer);
+        }
+    }
+
+    /// Creates an entity for a player, along with its properties.
+    fn create(&mut self, observer: Guid, guid: Guid) {
+        let entity = match self.index.0.get(&guid) {
+            Some(entity) => *entity,
+            None => return,
+        };
+        let Ok((position, yaw, stats)) = self.entities.get(entity) else {
+            return;
+        };
+        self.update(observer, |viewer| {
+            viewer.updates.create(guid, *position, yaw.0);
+            if let Some(stats) = stats {
+                let properties = EntityProperties::new(guid, stats.snapshot());
+                viewer.properties.push(properties);
+            }
+        });
+    }
+
+    fn notify(&mut self, guid: Guid, change: &VisibilityChange) {
+        for observer in &change.seen_by {
+            self.create(*observer, guid);
+        }
+        for observer in &change.unseen_by {
+            self.update(*observer, |viewer| viewer.forget(guid));
+        }
+        for other in &change.appeared {
+            self.create(guid, *other);
+        }
+        for other in &change.disappeared {
+            self.update(guid, |viewer| viewer.forget(*other));
+        }
+    }
+}
+
+/// Adds the properties that changed during the tick to the updates of the
+/// players who see the entities. Players are sent their own properties, all
+/// of them when they enter the world.
+pub fn update_properties(
+    grids: Res<Grids>,
+    index: Res<GuidIndex>,
+    mut changed: Query<(Entity, &EntityGuid, &mut Stats, Option<&Tracked>), Changed<Stats>>,
+    mut viewers: Query<&mut Viewer>,
+) {
+    for (entity, guid, mut stats, tracked) in &mut changed {
+        let added = stats.is_added();
+        let stats = stats.bypass_change_detection();
+        let values = stats.take_changes();
+        if let Ok(mut viewer) = viewers.get_mut(entity) {
+            let values = match added {
+                true => stats.snapshot(),
+                false => values.clone(),
+            };
+            if !values.is_empty() {
+                viewer
+                    .properties
+                    .push(EntityProperties::new(guid.0, values));
+            }
+        }
+        // the players who see a new entity got its properties with it
+        if added || values.is_empty() {
+            continue;
+        }
+
+        let grid = match tracked.and_then(|tracked| grids.0.get(&tracked.0)) {
+            Some(grid) => grid,
+            None => continue,
+        };
+        for (observer, _) in grid.observers_of(guid.0) {
+            let viewer = index
+                .0
+                .get(&observer)
+                .and_then(|entity| viewers.get_mut(*entity).ok());
+            if let Some(mut viewer) = viewer {
+                let properties = EntityProperties::new(guid.0, values.clone());
+                viewer.properties.push(properties);
+            }
+        }
+    }
+}
+
+/// Sends the updates of the tick to the players, the world updates first so
+/// that the clients know the entities whose properties follow.
+///
+/// The updates of an entity are only sent when they are due for its
+/// [`Relevance`] to the player, and held back otherwise. The updates of a
+/// player whose session is backed up are all held back. Either way, they are
+/// merged with the ones of the next ticks, so that only the last position of
+/// an entity and the last value of a property are sent once they go out.
+#[allow(clippy::type_complexity)]
+pub fn send_updates(
+    index: Res<GuidIndex>,
+    mut viewers: Query<(
+        &mut Viewer,
+        &EntityGuid,
+        &Position,
+        Option<&Target>,
+        Option<&Grouped>,
+    )>,
+    entities: Query<(&Position, Option<&Grouped>)>,
+) {
+    for (mut viewer, guid, position, target, group) in &mut viewers {
+        let viewer = &mut *viewer;
+        merge_properties(&mut viewer.properties);
+        if viewer.session.is_backed_up() {
+            continue;
+        }
+        let tick = viewer.ticks;
+        viewer.ticks += 1;
+        let due = |other: Guid| {
+            let entity = index.0.get(&other).and_then(|e| entities.get(*e).ok());
+            // the player itself, and entities that are gone
+            let (other_position, other_group) = match entity {
+                Some(entity) if other != guid.0 => entity,
+                _ => return true,
+            };
+            let targeted = target.is_some_and(|target| target.0 == other);
+            let grouped = group.is_some() && group == other_group;
+            let distance = distance(position, other_position);
+            Relevance::of(distance, targeted, grouped).is_due(tick, other)
+        };
+
+        let (properties, held) = std::mem::take(&mut viewer.properties)
+            .into_iter()
+            // the properties of new entities come with them
+            .partition(|properties| {
+                viewer.updates.is_created(properties.guid) || due(properties.guid)
+            });
+        viewer.properties = held;
+        let sent = viewer
+            .updates
+            .build_due(due)
+            .iter()
+            .try_for_each(|update| viewer.session.send(update));
+        // sessions that closed are removed when disconnecting
+        if sent.is_err() {
+            continue;
+        }
+        for entities in properties.chunks(MAX_PROPERTY_ENTITIES) {
+            let update = ServerPropertyUpdates::new(entities.to_vec());
+            if viewer.session.send(&update).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+fn distance(a: &Position, b: &Position) -> f32 {
+    let (dx, dy, dz) = (b.x - a.x, b.y - a.y, b.z - a.z);
+    (dx * dx + dy * dy + dz * dz).sqrt()
+}
+
+/// Merges the properties of the same entity, keeping the last value of each.
+fn merge_properties(properties: &mut Vec<EntityProperties>) {
+    let mut merged: Vec<EntityProperties> = Vec::with_capacity(properties.len());
+    let mut index = HashMap::new();
+    for entity in properties.drain(..) {
+        let merged_entity = match index.get(&entity.guid) {
+            Some(&i) => &mut merged[i],
+            None => {
+                index.insert(entity.guid, merged.len());
+                merged.push(entity);
+                continue;
+            }
+        };
+        let mut values = std::mem::take(&mut merged_entity.values);
+        for value in entity.values {
+            match values.iter_mut().find(|v| v.property == value.property) {
+                Some(merged_value) => merged_value.value = value.value,
+                None => values.push(value),
+            }
+        }
+        *merged_entity = EntityProperties::new(entity.guid, values);
+    }
+    *properties = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EntityType;
+
+    #[test]
+    fn test_walk_and_regenerate() {
+        let mut world = World::new();
+        world.insert_resource(TickTime(2.0));
+        let mut schedule = Schedule::default();
+        schedule.add_systems((walk, regenerate));
+
+        let mut wounded = Stats::with_health(20.0, 1.5);
+        wounded.set_base(Property::Health, 10.0);
+        let mut dead = wounded.clone();
+        dead.set_base(Property::Health, 0.0);
+        let destination = Position {
+            x: 0.0,
+            y: 0.0,
+            z: 5.0,
+        };
+        let walker = world
+            .spawn((
+                Position::default(),
+                Yaw(1.0),
+                Movement {
+                    destination,
+                    speed: 2.0,
+                },
+                wounded,
+            ))
+            .id();
+        let corpse = world.spawn(dead).id();
+
+        schedule.run(&mut world);
+        assert_eq!(world.get::<Position>(walker).unwrap().z, 4.0);
+        assert_eq!(world.get::<Yaw>(walker).unwrap().0, 0.0);
+        let health = |entity| world.get::<Stats>(entity).unwrap().get(Property::Health);
+        assert_eq!(health(walker), 13.0);
+        assert_eq!(health(corpse), 0.0);
+
+        schedule.run(&mut world);
+        assert_eq!(*world.get::<Position>(walker).unwrap(), destination);
+        assert!(world.get::<Movement>(walker).is_none());
+    }
+
+    #[test]
+    fn test_stats() {
+        let mut stats = Stats::with_health(100.0, 0.0);
+        assert_eq!(stats.take_changes().len(), 2);
+        assert!(!stats.has_changes());
+
+        let modifier = |kind, value| StatModifier {
+            source: 12,
+            property: Property::MaxHealth,
+            kind,
+            value,
+        };
+        stats.add_modifier(modifier(ModifierKind::Flat, 20.0));
+        stats.add_modifier(modifier(ModifierKind::Percent, 50.0));
+        assert_eq!(stats.get(Property::MaxHealth), 180.0);
+        assert_eq!(stats.base(Property::MaxHealth), 100.0);
+        assert_eq!(stats.add_to_pool(Property::Health, 500.0), 80.0);
+        assert_eq!(stats.add_to_pool(Property::Health, -30.0), -30.0);
+        assert_eq!(
+            stats.take_changes(),
+            [
+                PropertyValue {
+                    property: Property::Health,
+                    value: 150.0
+                },
+                PropertyValue {
+                    property: Property::MaxHealth,
+                    value: 180.0
+                },
+            ]
+        );
+
+        // the pool is capped by its new max, and stays there
+        stats.remove_modifiers(12);
+        assert_eq!(stats.get(Property::Health), 100.0);
+        stats.add_modifier(modifier(ModifierKind::Flat, 50.0));
+        assert_eq!(stats.get(Property::Health), 100.0);
+        assert_eq!(stats.take_changes().len(), 2);
+
+        // setting a property to its value isn't a change
+        stats.set_base(Property::Armor, 0.0);
+        assert!(!stats.has_changes());
+        assert_eq!(stats.snapshot().len(), Property::ALL.len());
+        stats.add_to_pool(Property::Health, -1000.0);
+        assert!(!stats.is_alive());
+    }
+
+    #[test]
+    fn test_merge_properties() {
+        let value = |property, value| PropertyValue { property, value };
+        let first = Guid::new(EntityType::Player, 1, 1);
+        let second = Guid::new(EntityType::Creature, 1, 2);
+        let mut properties = vec![
+            EntityProperties::new(first, vec![value(Property::Health, 10.0)]),
+            EntityProperties::new(second, vec![value(Property::Health, 50.0)]),
+            EntityProperties::new(
+                first,
+                vec![
+                    value(Property::MaxHealth, 120.0),
+                    value(Property::Health, 20.0),
+                ],
+            ),
+        ];
+        merge_properties(&mut properties);
+        assert_eq!(
+            properties,
+            [
+                EntityProperties::new(
+                    first,
+                    vec![
+                        value(Property::Health, 20.0),
+                        value(Property::MaxHealth, 120.0),
+                    ],
+                ),
+                EntityProperties::new(second, vec![value(Property::Health, 50.0)]),
+            ]
+        );
+    }
+}
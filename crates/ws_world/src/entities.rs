@@ -0,0 +1,169 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use serde::{Deserialize, Serialize};
+use ws_bitpack::*;
+
+/// The kind of entity a [`Guid`] refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum EntityType {
+    Player = 1,
+    Creature = 2,
+    WorldObject = 3,
+    Item = 4,
+}
+
+impl EntityType {
+    pub fn from_raw(raw: u8) -> Option<Self> {
+        match raw {
+            1 => Some(EntityType::Player),
+            2 => Some(EntityType::Creature),
+            3 => Some(EntityType::WorldObject),
+            4 => Some(EntityType::Item),
+            _ => None,
+        }
+    }
+}
+
+/// A 64-bit entity identifier.
+///
+/// From the highest to the lowest bits, a GUID packs the entity type on 8 bits,
+/// the id of the realm that created it on 12 bits, and a counter on 44 bits.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize,
+)]
+pub struct Guid(u64);
+
+impl Guid {
+    pub const TYPE_BITS: usize = 8;
+    pub const REALM_BITS: usize = 12;
+    pub const COUNTER_BITS: usize = 44;
+    pub const MAX_REALM: u16 = (1 << Self::REALM_BITS) - 1;
+    pub const MAX_COUNTER: u64 = (1 << Self::COUNTER_BITS) - 1;
+
+    /// The GUID that refers to no entity.
+    pub const NONE: Guid = Guid(0);
+
+    /// Packs a GUID. Panics if the realm or counter doesn't fit in its bits.
+    pub fn new(entity_type: EntityType, realm: u16, counter: u64) -> Self {
+        assert!(realm <= Self::MAX_REALM, "GUID realm {realm} is too large");
+        assert!(
+            counter <= Self::MAX_COUNTER,
+            "GUID counter {counter} is too large"
+        );
+        Self(
+            (entity_type as u64) << (Self::REALM_BITS + Self::COUNTER_BITS)
+                | (realm as u64) << Self::COUNTER_BITS
+                | counter,
+        )
+    }
+
+    pub fn from_raw(raw: u64) -> Self {
+        Self(raw)
+    }
+
+    pub fn raw(self) -> u64 {
+        self.0
+    }
+
+    pub fn is_none(self) -> bool {
+        self == Self::NONE
+    }
+
+    /// Returns the entity type, or `None` if the type bits are unknown.
+    pub fn entity_type(self) -> Option<EntityType> {
+        EntityType::from_raw((self.0 >> (Self::REALM_BITS + Self::COUNTER_BITS)) as u8)
+    }
+
+    pub fn realm(self) -> u16 {
+        ((self.0 >> Self::COUNTER_BITS) & Self::MAX_REALM as u64) as u16
+    }
+
+    pub fn counter(self) -> u64 {
+        self.0 & Self::MAX_COUNTER
+    }
+}
+
+impl std::fmt::Display for Guid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+impl ReadValue for Guid {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        reader.read_u64(64).map(Self)
+    }
+}
+
+impl WriteValue for Guid {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        writer.write_u64(self.0, 64)
+    }
+
+    fn bits(&self) -> usize {
+        64
+    }
+}
+
+/// Hands out unique GUIDs for the entities spawned by a server.
+///
+/// Each entity type has its own counter, starting at 1 so that no GUID is
+/// [`Guid::NONE`].
+#[derive(Debug)]
+pub struct GuidAllocator {
+    realm: u16,
+    counters: [AtomicU64; 4],
+}
+
+impl GuidAllocator {
+    pub fn new(realm: u16) -> Self {
+        assert!(realm <= Guid::MAX_REALM, "GUID realm {realm} is too large");
+        Self {
+            realm,
+            counters: Default::default(),
+        }
+    }
+
+    /// Returns the next GUID for an entity type. Panics if its counter overflows.
+    pub fn allocate(&self, entity_type: EntityType) -> Guid {
+        let counter = &self.counters[entity_type as usize - 1];
+        let value = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        Guid::new(entity_type, self.realm, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_guid_layout() {
+        let guid = Guid::new(EntityType::Creature, 0x123, 0xabcdef);
+        assert_eq!(guid.raw(), 0x0212_3000_00ab_cdef);
+        assert_eq!(guid.entity_type(), Some(EntityType::Creature));
+        assert_eq!(guid.realm(), 0x123);
+        assert_eq!(guid.counter(), 0xabcdef);
+        assert_eq!(guid.to_string(), "0212300000abcdef");
+
+        let mut writer = BitPackWriter::growable();
+        writer.write(&guid).unwrap();
+        let buffer = writer.finish().unwrap();
+        assert_eq!(BitPackReader::new(&buffer).read::<Guid>().unwrap(), guid);
+    }
+
+    #[test]
+    fn test_guid_allocator() {
+        let allocator = GuidAllocator::new(7);
+        let first = allocator.allocate(EntityType::Player);
+        let second = allocator.allocate(EntityType::Player);
+        let creature = allocator.allocate(EntityType::Creature);
+
+        assert_eq!(first.counter(), 1);
+        assert_eq!(second.counter(), 2);
+        assert_eq!(creature.counter(), 1);
+        assert_eq!(creature.realm(), 7);
+        assert_ne!(first, creature);
+        assert!(!first.is_none());
+    }
+}
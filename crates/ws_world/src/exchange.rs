@@ -0,0 +1,461 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use ws_db::{Database, DbError, NewCommodityOrder, NewMail};
+
+use crate::*;
+
+/// How often the [`WorldLoop`] matches the orders of the commodity exchange.
+pub const ORDER_MATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum ExchangeError {
+    /// The request was refused, the client is told why.
+    Refused(CommodityResult),
+    Db(DbError),
+}
+
+impl fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refused(result) => write!(f, "exchange request refused: {result:?}"),
+            Self::Db(error) => write!(f, "exchange database error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for ExchangeError {}
+
+impl From<CommodityResult> for ExchangeError {
+    fn from(result: CommodityResult) -> Self {
+        Self::Refused(result)
+    }
+}
+
+impl From<DbError> for ExchangeError {
+    fn from(error: DbError) -> Self {
+        Self::Db(error)
+    }
+}
+
+/// An open order of the commodity exchange.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+    pub id: u64,
+    pub character_id: u64,
+    pub side: CommoditySide,
+    pub template_id: u32,
+    /// The items left to buy or sell.
+    pub count: u32,
+    pub unit_price: u64,
+}
+
+impl Order {
+    pub fn data(&self) -> CommodityOrderData {
+        CommodityOrderData {
+            order_id: self.id,
+            side: self.side,
+            template_id: self.template_id,
+            count: self.count,
+            unit_price: self.unit_price,
+        }
+    }
+}
+
+fn order_from_db(order: ws_db::CommodityOrder) -> Order {
+    Order {
+        id: order.id as u64,
+        character_id: order.character_id as u64,
+        side: if order.side == CommoditySide::Sell as u8 {
+            CommoditySide::Sell
+        } else {
+            CommoditySide::Buy
+        },
+        template_id: order.template_id,
+        count: order.count,
+        unit_price: order.unit_price as u64,
+    }
+}
+
+/// Items changing hands when a buy order meets a sell order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trade {
+    pub template_id: u32,
+    pub buyer: u64,
+    pub seller: u64,
+    pub buy_order: u64,
+    pub sell_order: u64,
+    pub count: u32,
+    pub unit_price: u64,
+}
+
+/// The open orders of the commodity exchange, by id, which is also the order
+/// they were placed in.
+#[derive(Debug, Clone, Default)]
+pub struct OrderBook {
+    orders: BTreeMap<u64, Order>,
+}
+
+impl OrderBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, order: Order) {
+        self.orders.insert(order.id, order);
+    }
+
+    pub fn get(&self, order_id: u64) -> Option<&Order> {
+        self.orders.get(&order_id)
+    }
+
+    pub fn remove(&mut self, order_id: u64) -> Option<Order> {
+        self.orders.remove(&order_id)
+    }
+
+    /// Returns the orders of a character, oldest first.
+    pub fn orders_of(&self, character_id: u64) -> Vec<Order> {
+        self.orders
+            .values()
+            .filter(|order| order.character_id == character_id)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the id of the best order on a side for a commodity: the
+    /// highest buy or the lowest sell, the oldest first at the same price.
+    pub fn best(&self, template_id: u32, side: CommoditySide) -> Option<u64> {
+        let orders = self
+            .orders
+            .values()
+            .filter(|order| order.template_id == template_id && order.side == side);
+        let best = match side {
+            CommoditySide::Buy => {
+                orders.min_by_key(|order| (u64::MAX - order.unit_price, order.id))
+            }
+            CommoditySide::Sell => orders.min_by_key(|order| (order.unit_price, order.id)),
+        };
+        best.map(|order| order.id)
+    }
+
+    /// Returns how much of a commodity is bought and sold, and at the best
+    /// prices.
+    pub fn info(&self, template_id: u32) -> ServerCommodityInfo {
+        let price = |side| {
+            self.best(template_id, side)
+                .map_or(0, |id| self.orders[&id].unit_price)
+        };
+        let count = |side| {
+            self.orders
+                .values()
+                .filter(|order| order.template_id == template_id && order.side == side)
+                .map(|order| order.count)
+                .fold(0u32, u32::saturating_add)
+        };
+        ServerCommodityInfo {
+            template_id,
+            buy_count: count(CommoditySide::Buy),
+            best_buy: price(CommoditySide::Buy),
+            sell_count: count(CommoditySide::Sell),
+            best_sell: price(CommoditySide::Sell),
+        }
+    }
+
+    /// Fills the buy orders that meet sell orders, at the price of the older
+    /// of the two, and returns the trades. The orders filled are removed and
+    /// the others keep what is left of them.
+    pub fn match_orders(&mut self) -> Vec<Trade> {
+        let template_ids: BTreeSet<_> = self
+            .orders
+            .values()
+            .map(|order| order.template_id)
+            .collect();
+        let mut trades = Vec::new();
+        for template_id in template_ids {
+            while let (Some(buy_id), Some(sell_id)) = (
+                self.best(template_id, CommoditySide::Buy),
+                self.best(template_id, CommoditySide::Sell),
+            ) {
+                let (buy, sell) = (&self.orders[&buy_id], &self.orders[&sell_id]);
+                if buy.unit_price < sell.unit_price {
+                    break;
+                }
+                let trade = Trade {
+                    template_id,
+                    buyer: buy.character_id,
+                    seller: sell.character_id,
+                    buy_order: buy_id,
+                    sell_order: sell_id,
+                    count: buy.count.min(sell.count),
+                    unit_price: if buy_id < sell_id {
+                        buy.unit_price
+                    } else {
+                        sell.unit_price
+                    },
+                };
+                for id in [buy_id, sell_id] {
+                    let order = self.orders.get_mut(&id).unwrap();
+                    order.count -= trade.count;
+                    if order.count == 0 {
+                        self.orders.remove(&id);
+                    }
+                }
+                trades.push(trade);
+            }
+        }
+        trades
+    }
+}
+
+/// The commodity exchange of a world server, whose orders are kept in its
+/// database and matched by the [`WorldLoop`].
+///
+/// This is a stub until characters have money: buy orders reserve nothing,
+/// and the sellers are only told what they sold. The items sold leave the
+/// inventory when the order is placed, and the buyers receive theirs by mail.
+#[derive(Debug)]
+pub struct CommodityExchange {
+    database: Database,
+    book: Mutex<OrderBook>,
+}
+
+impl CommodityExchange {
+    /// Loads the open orders from the database.
+    pub async fn load(database: Database) -> Result<Self, DbError> {
+        let mut book = OrderBook::new();
+        for order in database.commodity_orders().list().await? {
+            book.insert(order_from_db(order));
+        }
+        Ok(Self {
+            database,
+            book: Mutex::new(book),
+        })
+    }
+
+    pub fn info(&self, template_id: u32) -> ServerCommodityInfo {
+        self.book.lock().unwrap().info(template_id)
+    }
+
+    pub fn orders_of(&self, character_id: u64) -> Vec<Order> {
+        self.book.lock().unwrap().orders_of(character_id)
+    }
+
+    /// Places an order for a character. Whether it has the items to sell is
+    /// up to the caller.
+    pub async fn post(
+        &self,
+        character_id: u64,
+        post: &ClientCommodityOrderPost,
+    ) -> Result<Order, ExchangeError> {
+        if post.count == 0 || post.unit_price == 0 || post.unit_price > i64::MAX as u64 {
+            return Err(CommodityResult::InvalidOrder.into());
+        }
+        let order = self
+            .database
+            .commodity_orders()
+            .create(&NewCommodityOrder {
+                character_id: character_id as i64,
+                side: post.side as u8,
+                template_id: post.template_id,
+                count: post.count,
+                unit_price: post.unit_price as i64,
+            })
+            .await?;
+        let order = order_from_db(order);
+        self.book.lock().unwrap().insert(order.clone());
+        Ok(order)
+    }
+
+    /// Cancels an order of a character, and returns what was left of it.
+    pub async fn cancel(&self, character_id: u64, order_id: u64) -> Result<Order, ExchangeError> {
+        // taken out of the book first so that it can't be matched meanwhile
+        let order = {
+            let mut book = self.book.lock().unwrap();
+            match book.get(order_id) {
+                Some(order) if order.character_id == character_id => book.remove(order_id),
+                _ => None,
+            }
+        };
+        let order = order.ok_or(CommodityResult::OrderNotFound)?;
+        if let Err(error) = self
+            .database
+            .commodity_orders()
+            .delete(order_id as i64)
+            .await
+        {
+            self.book.lock().unwrap().insert(order);
+            return Err(error.into());
+        }
+        Ok(order)
+    }
+
+    /// Matches the orders, and keeps what is left of them in the database
+    /// along with the mail that `mail` writes for each trade, all or nothing.
+    /// The orders are put back in the book if the database fails, to be
+    /// matched again.
+    pub async fn match_orders(
+        &self,
+        mail: impl Fn(&Trade) -> Vec<NewMail>,
+    ) -> Result<Vec<ws_db::Mail>, DbError> {
+        let (matched, changed, trades) = {
+            let mut book = self.book.lock().unwrap();
+            let before = book.clone();
+            let trades = book.match_orders();
+            let ids: BTreeSet<_> = trades
+                .iter()
+                .flat_map(|trade| [trade.buy_order, trade.sell_order])
+                .collect();
+            let matched: Vec<_> = ids
+                .iter()
+                .filter_map(|id| before.get(*id).cloned())
+                .collect();
+            let changed: Vec<_> = ids
+                .iter()
+                .map(|id| (*id as i64, book.get(*id).map(|order| order.count)))
+                .collect();
+            (matched, changed, trades)
+        };
+        if trades.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mail: Vec<_> = trades.iter().flat_map(mail).collect();
+        let result = self
+            .database
+            .commodity_orders()
+            .settle(&changed, &mail)
+            .await;
+        if result.is_err() {
+            let mut book = self.book.lock().unwrap();
+            for order in matched {
+                book.insert(order);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: u64, side: CommoditySide, count: u32, unit_price: u64) -> Order {
+        Order {
+            id,
+            character_id: id * 10,
+            side,
+            template_id: 30,
+            count,
+            unit_price,
+        }
+    }
+
+    #[test]
+    fn test_order_book() {
+        let mut book = OrderBook::new();
+        book.insert(order(1, CommoditySide::Sell, 10, 150));
+        book.insert(order(2, CommoditySide::Sell, 10, 120));
+        book.insert(order(3, CommoditySide::Buy, 5, 100));
+        book.insert(order(4, CommoditySide::Buy, 5, 110));
+        assert_eq!(book.match_orders(), []);
+        assert_eq!(
+            book.info(30),
+            ServerCommodityInfo {
+                template_id: 30,
+                buy_count: 10,
+                best_buy: 110,
+                sell_count: 20,
+                best_sell: 120,
+            }
+        );
+
+        // the cheapest sell is filled first, at the price of the older order
+        book.insert(order(5, CommoditySide::Buy, 15, 200));
+        assert_eq!(
+            book.match_orders(),
+            [
+                Trade {
+                    template_id: 30,
+                    buyer: 50,
+                    seller: 20,
+                    buy_order: 5,
+                    sell_order: 2,
+                    count: 10,
+                    unit_price: 120,
+                },
+                Trade {
+                    template_id: 30,
+                    buyer: 50,
+                    seller: 10,
+                    buy_order: 5,
+                    sell_order: 1,
+                    count: 5,
+                    unit_price: 150,
+                },
+            ]
+        );
+        assert_eq!(book.get(1).unwrap().count, 5);
+        assert_eq!(book.get(2), None);
+        assert_eq!(book.get(5), None);
+
+        // a newer sell order is filled at the price of the buy
+        book.insert(order(6, CommoditySide::Sell, 3, 90));
+        let trades = book.match_orders();
+        assert_eq!(trades.len(), 1);
+        assert_eq!((trades[0].buy_order, trades[0].unit_price), (4, 110));
+        assert_eq!(book.orders_of(40)[0].count, 2);
+    }
+
+    #[tokio::test]
+    async fn test_match_orders_fails() {
+        let database = Database::in_memory().await.unwrap();
+        let account = database
+            .accounts()
+            .create("clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let character = ws_db::NewCharacter {
+            account_id: account.id,
+            name: "Trader".to_string(),
+            faction: Faction::Exile as u16,
+            race: 1,
+            class: 1,
+            world_id: 870,
+            position: (0.0, 0.0, 0.0),
+            yaw: 0.0,
+            sex: 0,
+            path: 0,
+            customization: Vec::new(),
+        };
+        let character_id = database.characters().create(&character).await.unwrap().id as u64;
+        let exchange = CommodityExchange::load(database.clone()).await.unwrap();
+        for (side, count) in [(CommoditySide::Sell, 5), (CommoditySide::Buy, 3)] {
+            let post = ClientCommodityOrderPost {
+                side,
+                template_id: 30,
+                count,
+                unit_price: 100,
+            };
+            exchange.post(character_id, &post).await.unwrap();
+        }
+        let mail_to = |recipient_id| {
+            move |_: &Trade| vec![system_mail("Exchange", recipient_id, "", "", Vec::new())]
+        };
+
+        // the orders are left as they were when the mail can't be sent
+        assert!(exchange.match_orders(mail_to(404)).await.is_err());
+        let info = exchange.info(30);
+        assert_eq!((info.sell_count, info.buy_count), (5, 3));
+        assert_eq!(database.commodity_orders().list().await.unwrap().len(), 2);
+
+        let sent = exchange.match_orders(mail_to(character_id)).await.unwrap();
+        assert_eq!(sent.len(), 1);
+        let info = exchange.info(30);
+        assert_eq!((info.sell_count, info.buy_count), (2, 0));
+        let orders = database.commodity_orders().list().await.unwrap();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].count, 2);
+    }
+}
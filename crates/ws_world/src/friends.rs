@@ -0,0 +1,219 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use ws_db::{Database, DbError};
+use ws_net::Session;
+
+use crate::*;
+
+pub const MAX_FRIENDS: u32 = 100;
+pub const MAX_FRIEND_NOTE_LENGTH: usize = 64;
+
+#[derive(Debug)]
+pub enum FriendError {
+    /// The request was refused, the client is told why.
+    Refused(FriendResult),
+    Db(DbError),
+}
+
+impl fmt::Display for FriendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refused(result) => write!(f, "friend request refused: {result:?}"),
+            Self::Db(error) => write!(f, "friend database error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for FriendError {}
+
+impl From<FriendResult> for FriendError {
+    fn from(result: FriendResult) -> Self {
+        Self::Refused(result)
+    }
+}
+
+impl From<DbError> for FriendError {
+    fn from(error: DbError) -> Self {
+        Self::Db(error)
+    }
+}
+
+#[derive(Debug)]
+struct OnlineCharacter {
+    session: Session,
+    account_id: u32,
+    /// The characters that the account of the character is friends with.
+    friends: HashSet<u64>,
+}
+
+/// The friends of the accounts of a world server, kept in its database.
+///
+/// Friends are kept per account, so that every character of an account
+/// shares them. The manager knows the friends of the characters in the
+/// world, to tell them when their friends enter or leave it.
+#[derive(Debug)]
+pub struct FriendManager {
+    database: Database,
+    online: Mutex<HashMap<u64, OnlineCharacter>>,
+}
+
+impl FriendManager {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            online: Default::default(),
+        }
+    }
+
+    /// Adds a character who entered the world, sends the friends of its
+    /// account and tells the characters it is a friend of.
+    pub async fn enter(&self, session: Session, character: &Character) -> Result<(), DbError> {
+        let friends = self
+            .database
+            .friends()
+            .list(character.account_id.into())
+            .await?;
+        let mut online = self.online.lock().unwrap();
+        let list: Vec<_> = friends
+            .into_iter()
+            .map(|friend| {
+                let is_online = online.contains_key(&(friend.character_id as u64));
+                friend_data(friend, is_online)
+            })
+            .collect();
+        let friends = list.iter().map(|friend| friend.character_id).collect();
+        // the session was closed if sending fails, it is removed once handled
+        let _ = session.send(&ServerFriendList {
+            count: list.len() as u8,
+            friends: list,
+        });
+        online.insert(
+            character.id,
+            OnlineCharacter {
+                session,
+                account_id: character.account_id,
+                friends,
+            },
+        );
+        send_presence(&online, character.id, true);
+        Ok(())
+    }
+
+    /// Removes a character who left the world, and tells the characters it
+    /// is a friend of.
+    pub fn exit(&self, character_id: u64) {
+        let mut online = self.online.lock().unwrap();
+        if online.remove(&character_id).is_some() {
+            send_presence(&online, character_id, false);
+        }
+    }
+
+    /// Adds a character to the friends of the account of another, by name.
+    pub async fn add(&self, character_id: u64, name: &str) -> Result<(), FriendError> {
+        let account_id = self.account_of(character_id)?;
+        let friend = self
+            .database
+            .characters()
+            .find_by_name(name)
+            .await?
+            .ok_or(FriendResult::PlayerNotFound)?;
+        if friend.account_id == i64::from(account_id) {
+            return Err(FriendResult::OwnCharacter.into());
+        }
+        let friends = self.database.friends();
+        if friends.count(account_id.into()).await? >= MAX_FRIENDS {
+            return Err(FriendResult::ListFull.into());
+        }
+        if !friends.add(account_id.into(), friend.id).await? {
+            return Err(FriendResult::AlreadyFriend.into());
+        }
+        let friend = friends
+            .find(account_id.into(), friend.id)
+            .await?
+            .ok_or(FriendResult::PlayerNotFound)?;
+
+        let mut online = self.online.lock().unwrap();
+        let is_online = online.contains_key(&(friend.character_id as u64));
+        let friend = friend_data(friend, is_online);
+        if let Some(character) = online.get_mut(&character_id) {
+            character.friends.insert(friend.character_id);
+            let _ = character.session.send(&ServerFriendAdded { friend });
+        }
+        Ok(())
+    }
+
+    /// Removes a friend of the account of a character.
+    pub async fn remove(&self, character_id: u64, friend_id: u64) -> Result<(), FriendError> {
+        let account_id = self.account_of(character_id)?;
+        if !self
+            .database
+            .friends()
+            .remove(account_id.into(), friend_id as i64)
+            .await?
+        {
+            return Err(FriendResult::NotFriend.into());
+        }
+        if let Some(character) = self.online.lock().unwrap().get_mut(&character_id) {
+            character.friends.remove(&friend_id);
+            let _ = character.session.send(&ServerFriendRemoved {
+                character_id: friend_id,
+            });
+        }
+        Ok(())
+    }
+
+    /// Changes the note of a friend of the account of a character.
+    pub async fn set_note(
+        &self,
+        character_id: u64,
+        friend_id: u64,
+        note: &str,
+    ) -> Result<(), FriendError> {
+        if note.chars().count() > MAX_FRIEND_NOTE_LENGTH {
+            return Err(FriendResult::InvalidNote.into());
+        }
+        let account_id = self.account_of(character_id)?;
+        if !self
+            .database
+            .friends()
+            .set_note(account_id.into(), friend_id as i64, note)
+            .await?
+        {
+            return Err(FriendResult::NotFriend.into());
+        }
+        Ok(())
+    }
+
+    fn account_of(&self, character_id: u64) -> Result<u32, FriendResult> {
+        let online = self.online.lock().unwrap();
+        let character = online.get(&character_id).ok_or(FriendResult::Failed)?;
+        Ok(character.account_id)
+    }
+}
+
+fn friend_data(friend: ws_db::Friend, online: bool) -> FriendData {
+    FriendData {
+        character_id: friend.character_id as u64,
+        name: friend.name,
+        class: friend.class,
+        level: friend.level,
+        online,
+        note: friend.note,
+    }
+}
+
+/// Tells the characters in the world who are friends with a character that
+/// it entered or left it.
+fn send_presence(online: &HashMap<u64, OnlineCharacter>, character_id: u64, is_online: bool) {
+    let presence = ServerFriendPresence {
+        character_id,
+        online: is_online,
+    };
+    for character in online.values() {
+        if character.friends.contains(&character_id) {
+            let _ = character.session.send(&presence);
+        }
+    }
+}
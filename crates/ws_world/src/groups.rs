@@ -0,0 +1,362 @@
+use std::collections::HashMap;
+
+use ws_net::Session;
+
+use crate::*;
+
+/// The number of players a group can have, its leader included.
+pub const MAX_GROUP_SIZE: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    pub id: u64,
+    pub leader: Guid,
+    /// The members in the order they joined, the leader included.
+    pub members: Vec<Guid>,
+}
+
+/// What became of a group once a member left it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupLeave {
+    /// The other members stay in the group.
+    Left(Group),
+    /// The group was left with a single member and was disbanded. The
+    /// members are the ones it had, the one who left included.
+    Disbanded { group_id: u64, members: Vec<Guid> },
+}
+
+#[derive(Debug)]
+struct OnlinePlayer<S> {
+    handle: S,
+    member: GroupMember,
+    group: Option<u64>,
+}
+
+/// The groups of the players of a world server, and the invites between
+/// them.
+///
+/// Players are known by the guid they entered the world with, and come with
+/// a handle to reach them, which is a [`Session`] on a server. The manager
+/// only keeps track of the groups: telling the players about the changes is
+/// left to its caller.
+#[derive(Debug)]
+pub struct GroupManager<S = Session> {
+    players: HashMap<Guid, OnlinePlayer<S>>,
+    groups: HashMap<u64, Group>,
+    /// The pending invites, from the invited player to its inviter.
+    invites: HashMap<Guid, Guid>,
+    last_group_id: u64,
+}
+
+impl<S> Default for GroupManager<S> {
+    fn default() -> Self {
+        Self {
+            players: HashMap::new(),
+            groups: HashMap::new(),
+            invites: HashMap::new(),
+            last_group_id: 0,
+        }
+    }
+}
+
+impl<S> GroupManager<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a player who entered the world.
+    pub fn enter(&mut self, handle: S, member: GroupMember) {
+        let player = OnlinePlayer {
+            handle,
+            member,
+            group: None,
+        };
+        self.players.insert(player.member.guid, player);
+    }
+
+    pub fn handle(&self, guid: Guid) -> Option<&S> {
+        self.players.get(&guid).map(|player| &player.handle)
+    }
+
+    pub fn member(&self, guid: Guid) -> Option<&GroupMember> {
+        self.players.get(&guid).map(|player| &player.member)
+    }
+
+    pub fn group_of(&self, guid: Guid) -> Option<&Group> {
+        self.groups.get(&self.players.get(&guid)?.group?)
+    }
+
+    /// Returns the members of a group, in the order they joined.
+    pub fn members(&self, group: &Group) -> Vec<GroupMember> {
+        group
+            .members
+            .iter()
+            .filter_map(|guid| self.member(*guid).cloned())
+            .collect()
+    }
+
+    /// Invites the player with a name to the group of the inviter, and
+    /// returns who was invited.
+    ///
+    /// Only the leader of a group can invite players to it. A player can only
+    /// consider one invite at a time.
+    pub fn invite(&mut self, inviter: Guid, name: &str) -> Result<Guid, GroupInviteResult> {
+        let inviter_group = self
+            .players
+            .get(&inviter)
+            .ok_or(GroupInviteResult::PlayerNotFound)?
+            .group;
+        if let Some(group) = inviter_group.and_then(|id| self.groups.get(&id)) {
+            if group.leader != inviter {
+                return Err(GroupInviteResult::NotLeader);
+            }
+            if group.members.len() >= MAX_GROUP_SIZE {
+                return Err(GroupInviteResult::GroupFull);
+            }
+        }
+        // several sandbox characters can share a name, the inviter isn't one
+        // of them
+        let invitee = self
+            .players
+            .values()
+            .find(|player| {
+                player.member.guid != inviter && player.member.name.eq_ignore_ascii_case(name)
+            })
+            .ok_or(GroupInviteResult::PlayerNotFound)?;
+        if invitee.group.is_some() {
+            return Err(GroupInviteResult::AlreadyGrouped);
+        }
+        let invitee = invitee.member.guid;
+        if self.invites.contains_key(&invitee) {
+            return Err(GroupInviteResult::Busy);
+        }
+        self.invites.insert(invitee, inviter);
+        Ok(invitee)
+    }
+
+    /// Answers the pending invite of a player, if any, and returns the
+    /// inviter along with the id of the group joined.
+    ///
+    /// The group is created when its leader gets its first member.
+    pub fn respond(
+        &mut self,
+        invitee: Guid,
+        accept: bool,
+    ) -> Option<(Guid, Result<u64, GroupInviteResult>)> {
+        let inviter = self.invites.remove(&invitee)?;
+        Some((inviter, self.join(inviter, invitee, accept)))
+    }
+
+    fn join(
+        &mut self,
+        inviter: Guid,
+        invitee: Guid,
+        accept: bool,
+    ) -> Result<u64, GroupInviteResult> {
+        if !accept {
+            return Err(GroupInviteResult::Declined);
+        }
+        let inviter_group = self
+            .players
+            .get(&inviter)
+            .ok_or(GroupInviteResult::PlayerNotFound)?
+            .group;
+        if self
+            .players
+            .get(&invitee)
+            .and_then(|player| player.group)
+            .is_some()
+        {
+            return Err(GroupInviteResult::AlreadyGrouped);
+        }
+
+        let group_id = match inviter_group {
+            Some(group_id) => {
+                let group = &self.groups[&group_id];
+                // things changed since the invite was sent
+                if group.leader != inviter {
+                    return Err(GroupInviteResult::NotLeader);
+                }
+                if group.members.len() >= MAX_GROUP_SIZE {
+                    return Err(GroupInviteResult::GroupFull);
+                }
+                group_id
+            }
+            None => {
+                self.last_group_id += 1;
+                let group = Group {
+                    id: self.last_group_id,
+                    leader: inviter,
+                    members: vec![inviter],
+                };
+                self.groups.insert(group.id, group);
+                self.set_group(inviter, Some(self.last_group_id));
+                self.last_group_id
+            }
+        };
+        let group = self
+            .groups
+            .get_mut(&group_id)
+            .expect("group was just found");
+        group.members.push(invitee);
+        self.set_group(invitee, Some(group_id));
+        Ok(group_id)
+    }
+
+    /// Removes a player from its group, if it is in one. The first member to
+    /// have joined becomes the leader if the leader leaves.
+    pub fn leave(&mut self, guid: Guid) -> Option<GroupLeave> {
+        let group_id = self.players.get(&guid)?.group?;
+        self.set_group(guid, None);
+        let group = self
+            .groups
+            .get_mut(&group_id)
+            .expect("players are in existing groups");
+        group.members.retain(|member| *member != guid);
+        if group.members.len() > 1 {
+            if group.leader == guid {
+                group.leader = group.members[0];
+            }
+            return Some(GroupLeave::Left(group.clone()));
+        }
+
+        let group = self.groups.remove(&group_id).expect("group was just found");
+        for member in &group.members {
+            self.set_group(*member, None);
+        }
+        let mut members = group.members;
+        members.push(guid);
+        Some(GroupLeave::Disbanded { group_id, members })
+    }
+
+    /// Removes a player who left the world, along with its invites, and
+    /// returns what became of its group.
+    pub fn exit(&mut self, guid: Guid) -> Option<GroupLeave> {
+        let leave = self.leave(guid);
+        self.players.remove(&guid);
+        self.invites
+            .retain(|invitee, inviter| *invitee != guid && *inviter != guid);
+        leave
+    }
+
+    /// Replaces the state of a player, and returns its group if it changed,
+    /// so that the other members can be told.
+    pub fn update(&mut self, member: GroupMember) -> Option<Group> {
+        let player = self.players.get_mut(&member.guid)?;
+        if player.member == member {
+            return None;
+        }
+        player.member = member;
+        let group_id = player.group?;
+        self.groups.get(&group_id).cloned()
+    }
+
+    fn set_group(&mut self, guid: Guid, group: Option<u64>) {
+        if let Some(player) = self.players.get_mut(&guid) {
+            player.group = group;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enter(groups: &mut GroupManager<()>, guids: &GuidAllocator, name: &str) -> Guid {
+        let guid = guids.allocate(EntityType::Player);
+        let member = GroupMember {
+            guid,
+            name: name.to_string(),
+            class: 1,
+            level: 1,
+            world_id: 870,
+        };
+        groups.enter((), member);
+        guid
+    }
+
+    #[test]
+    fn test_invite_and_leave() {
+        let guids = GuidAllocator::new(1);
+        let mut groups = GroupManager::new();
+        let leader = enter(&mut groups, &guids, "Deadeye");
+        let first = enter(&mut groups, &guids, "Ka'tal");
+        let second = enter(&mut groups, &guids, "Drusera");
+
+        assert_eq!(groups.invite(leader, "ka'TAL"), Ok(first));
+        assert_eq!(
+            groups.invite(second, "Ka'tal"),
+            Err(GroupInviteResult::Busy)
+        );
+        assert_eq!(
+            groups.invite(leader, "Nobody"),
+            Err(GroupInviteResult::PlayerNotFound)
+        );
+        let (inviter, joined) = groups.respond(first, true).unwrap();
+        assert_eq!(inviter, leader);
+        let group_id = joined.unwrap();
+        assert_eq!(groups.respond(first, true), None);
+
+        assert_eq!(
+            groups.invite(first, "Drusera"),
+            Err(GroupInviteResult::NotLeader)
+        );
+        assert_eq!(
+            groups.invite(second, "Deadeye"),
+            Err(GroupInviteResult::AlreadyGrouped)
+        );
+        groups.invite(leader, "Drusera").unwrap();
+        assert_eq!(
+            groups.respond(second, false),
+            Some((leader, Err(GroupInviteResult::Declined)))
+        );
+        groups.invite(leader, "Drusera").unwrap();
+        groups.respond(second, true).unwrap().1.unwrap();
+        let group = groups.group_of(second).unwrap();
+        assert_eq!(group.members, [leader, first, second]);
+        assert_eq!(groups.members(group)[2].name, "Drusera");
+
+        // the next member leads once the leader leaves
+        let leave = groups.exit(leader).unwrap();
+        assert_eq!(
+            leave,
+            GroupLeave::Left(Group {
+                id: group_id,
+                leader: first,
+                members: vec![first, second],
+            })
+        );
+        let mut member = groups.member(second).unwrap().clone();
+        member.level = 2;
+        assert_eq!(groups.update(member.clone()).unwrap().id, group_id);
+        assert_eq!(groups.update(member), None);
+
+        assert_eq!(
+            groups.leave(second),
+            Some(GroupLeave::Disbanded {
+                group_id,
+                members: vec![first, second],
+            })
+        );
+        assert_eq!(groups.group_of(first), None);
+        assert_eq!(groups.leave(first), None);
+    }
+
+    #[test]
+    fn test_group_full() {
+        let guids = GuidAllocator::new(1);
+        let mut groups = GroupManager::new();
+        let leader = enter(&mut groups, &guids, "Leader");
+        for i in 1..MAX_GROUP_SIZE {
+            let name = format!("Member{i}");
+            let member = enter(&mut groups, &guids, &name);
+            groups.invite(leader, &name).unwrap();
+            assert!(groups.respond(member, true).unwrap().1.is_ok());
+        }
+        enter(&mut groups, &guids, "Late");
+        assert_eq!(
+            groups.invite(leader, "Late"),
+            Err(GroupInviteResult::GroupFull)
+        );
+    }
+}
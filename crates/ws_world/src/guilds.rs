@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use ws_bitpack::WriteValue;
+use ws_db::{Database, DbError};
+use ws_messages::Message;
+use ws_net::Session;
+
+use crate::*;
+
+pub const MAX_GUILD_NAME_LENGTH: usize = 30;
+pub const MAX_RANK_NAME_LENGTH: usize = 16;
+
+/// The ranks of a new guild, from the guild master's down. Invited players
+/// join at the lowest one.
+pub const DEFAULT_GUILD_RANKS: [(&str, GuildPermissions); 3] = [
+    ("Guild Master", GuildPermissions::all()),
+    (
+        "Officer",
+        GuildPermissions::INVITE
+            .union(GuildPermissions::KICK)
+            .union(GuildPermissions::SET_RANK),
+    ),
+    ("Member", GuildPermissions::empty()),
+];
+
+#[derive(Debug)]
+pub enum GuildError {
+    /// The request was refused, the client is told why.
+    Refused(GuildResult),
+    Db(DbError),
+}
+
+impl fmt::Display for GuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refused(result) => write!(f, "guild request refused: {result:?}"),
+            Self::Db(error) => write!(f, "guild database error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for GuildError {}
+
+impl From<GuildResult> for GuildError {
+    fn from(result: GuildResult) -> Self {
+        Self::Refused(result)
+    }
+}
+
+impl From<DbError> for GuildError {
+    fn from(error: DbError) -> Self {
+        Self::Db(error)
+    }
+}
+
+/// Checks a guild name: words of ASCII letters separated by single spaces,
+/// like `Sandbox Testers`. Names containing any of the `blocked` words are
+/// refused like character names are.
+pub fn validate_guild_name(name: &str, blocked: &[String]) -> Result<(), NameError> {
+    let length = name.chars().count();
+    if length < MIN_NAME_LENGTH {
+        return Err(NameError::TooShort);
+    }
+    if length > MAX_GUILD_NAME_LENGTH {
+        return Err(NameError::TooLong);
+    }
+    if let Some(c) = name.chars().find(|c| !c.is_ascii_alphabetic() && *c != ' ') {
+        return Err(NameError::InvalidCharacter(c));
+    }
+    if name.split(' ').any(str::is_empty) {
+        return Err(NameError::MisplacedSeparator);
+    }
+    check_blocked(name, blocked)
+}
+
+#[derive(Debug)]
+struct OnlineCharacter {
+    session: Session,
+    name: String,
+    guild_id: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct GuildInvite {
+    guild_id: i64,
+    inviter: u64,
+}
+
+/// The guilds of a world server, kept in its database.
+///
+/// The manager knows the characters in the world and the guild they are in,
+/// to send the events of each guild to its online members. Characters are
+/// known by their id, and the requests they make either succeed or return why
+/// they were refused.
+#[derive(Debug)]
+pub struct GuildManager {
+    database: Database,
+    online: Mutex<HashMap<u64, OnlineCharacter>>,
+    /// The pending invites, by invited character.
+    invites: Mutex<HashMap<u64, GuildInvite>>,
+}
+
+impl GuildManager {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            online: Default::default(),
+            invites: Default::default(),
+        }
+    }
+
+    /// Adds a character who entered the world, and tells its guild.
+    pub async fn enter(&self, session: Session, character: &Character) -> Result<(), DbError> {
+        let member = self.database.guilds().member(character.id as i64).await?;
+        let guild_id = member.map(|member| member.guild_id);
+        self.online.lock().unwrap().insert(
+            character.id,
+            OnlineCharacter {
+                session,
+                name: character.name.clone(),
+                guild_id,
+            },
+        );
+        if let Some(guild_id) = guild_id {
+            self.broadcast(
+                guild_id,
+                GuildEvent::MemberOnline {
+                    character_id: character.id,
+                    online: true,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns true if a character entered the world and didn't leave it.
+    pub fn is_online(&self, character_id: u64) -> bool {
+        self.online.lock().unwrap().contains_key(&character_id)
+    }
+
+    /// Removes a character who left the world along with its invites, and
+    /// tells its guild.
+    pub fn exit(&self, character_id: u64) {
+        let character = self.online.lock().unwrap().remove(&character_id);
+        self.invites
+            .lock()
+            .unwrap()
+            .retain(|invitee, invite| *invitee != character_id && invite.inviter != character_id);
+        if let Some(guild_id) = character.and_then(|character| character.guild_id) {
+            self.broadcast(
+                guild_id,
+                GuildEvent::MemberOnline {
+                    character_id,
+                    online: false,
+                },
+            );
+        }
+    }
+
+    /// Creates a guild with the default ranks, led by a character.
+    pub async fn create(
+        &self,
+        character_id: u64,
+        name: &str,
+        blocked: &[String],
+    ) -> Result<(), GuildError> {
+        if let Err(error) = validate_guild_name(name, blocked) {
+            tracing::debug!("Refused the guild name {name:?}: {error}");
+            return Err(GuildResult::InvalidName.into());
+        }
+        let guilds = self.database.guilds();
+        if guilds.member(character_id as i64).await?.is_some() {
+            return Err(GuildResult::AlreadyInGuild.into());
+        }
+        if guilds.find_by_name(name).await?.is_some() {
+            return Err(GuildResult::NameTaken.into());
+        }
+
+        let ranks = DEFAULT_GUILD_RANKS.map(|(name, permissions)| (name, permissions.bits()));
+        let guild = guilds.create(name, character_id as i64, &ranks).await?;
+        self.set_guild(character_id, Some(guild.id));
+        Ok(())
+    }
+
+    /// Returns the ranks and members of the guild of a character.
+    pub async fn roster(&self, character_id: u64) -> Result<ServerGuildRoster, GuildError> {
+        let member = self.membership(character_id).await?;
+        let guilds = self.database.guilds();
+        let guild = guilds
+            .find(member.guild_id)
+            .await?
+            .ok_or(GuildResult::NotInGuild)?;
+        let ranks: Vec<_> = guilds
+            .ranks(guild.id)
+            .await?
+            .into_iter()
+            .map(|rank| GuildRankInfo {
+                rank: rank.rank,
+                name: rank.name,
+                permissions: GuildPermissions::from_bits_truncate(rank.permissions),
+            })
+            .collect();
+        let members: Vec<_> = guilds
+            .members(guild.id)
+            .await?
+            .into_iter()
+            .map(|member| self.roster_member(member))
+            .collect();
+        Ok(ServerGuildRoster {
+            guild_id: guild.id as u64,
+            name: guild.name,
+            rank_count: ranks.len() as u8,
+            ranks,
+            member_count: members.len() as u16,
+            members,
+        })
+    }
+
+    /// Invites the character with a name, who must be in the world, to the
+    /// guild of the inviter.
+    pub async fn invite(&self, inviter_id: u64, name: &str) -> Result<(), GuildError> {
+        let inviter = self.membership(inviter_id).await?;
+        self.check_permission(&inviter, GuildPermissions::INVITE)
+            .await?;
+        let invitee = self
+            .online
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(id, character)| **id != inviter_id && character.name.eq_ignore_ascii_case(name))
+            .map(|(id, character)| (*id, character.guild_id));
+        let invitee = match invitee {
+            Some((_, Some(_))) => return Err(GuildResult::AlreadyInGuild.into()),
+            Some((invitee, None)) => invitee,
+            None => return Err(GuildResult::PlayerNotFound.into()),
+        };
+        let guild = self
+            .database
+            .guilds()
+            .find(inviter.guild_id)
+            .await?
+            .ok_or(GuildResult::NotInGuild)?;
+
+        {
+            let mut invites = self.invites.lock().unwrap();
+            if invites.contains_key(&invitee) {
+                return Err(GuildResult::Busy.into());
+            }
+            invites.insert(
+                invitee,
+                GuildInvite {
+                    guild_id: guild.id,
+                    inviter: inviter_id,
+                },
+            );
+        }
+        self.send(
+            invitee,
+            &ServerGuildInvite {
+                guild_id: guild.id as u64,
+                guild_name: guild.name,
+                inviter_name: inviter.name,
+            },
+        );
+        Ok(())
+    }
+
+    /// Answers the pending invite of a character, if any. The inviter is told
+    /// when it is declined, the guild when it is accepted.
+    pub async fn respond(&self, invitee_id: u64, accept: bool) -> Result<(), GuildError> {
+        let invite = self.invites.lock().unwrap().remove(&invitee_id);
+        let invite = match invite {
+            Some(invite) => invite,
+            // the invite was cancelled when the inviter left the world
+            None => return Ok(()),
+        };
+        if !accept {
+            self.send(
+                invite.inviter,
+                &ServerGuildResult {
+                    result: GuildResult::Declined,
+                },
+            );
+            return Ok(());
+        }
+
+        let guilds = self.database.guilds();
+        if guilds.member(invitee_id as i64).await?.is_some() {
+            return Err(GuildResult::AlreadyInGuild.into());
+        }
+        // the guild is gone if it was disbanded in the meantime
+        let lowest_rank = guilds
+            .ranks(invite.guild_id)
+            .await?
+            .last()
+            .map(|rank| rank.rank)
+            .ok_or(GuildResult::NotInGuild)?;
+        guilds
+            .add_member(invite.guild_id, invitee_id as i64, lowest_rank)
+            .await?;
+        self.set_guild(invitee_id, Some(invite.guild_id));
+
+        let member = self.membership(invitee_id).await?;
+        let member = self.roster_member(member);
+        self.broadcast(invite.guild_id, GuildEvent::MemberJoined(member));
+        Ok(())
+    }
+
+    /// Removes a character from its guild. The guild is disbanded when its
+    /// master leaves it last.
+    pub async fn leave(&self, character_id: u64) -> Result<(), GuildError> {
+        let member = self.membership(character_id).await?;
+        let guilds = self.database.guilds();
+        if member.rank == 0 {
+            if guilds.members(member.guild_id).await?.len() > 1 {
+                return Err(GuildResult::MasterCantLeave.into());
+            }
+            guilds.delete(member.guild_id).await?;
+            self.broadcast(member.guild_id, GuildEvent::Disbanded);
+        } else {
+            guilds
+                .remove_member(member.guild_id, member.character_id)
+                .await?;
+            self.broadcast(member.guild_id, GuildEvent::MemberLeft { character_id });
+        }
+        self.set_guild(character_id, None);
+        Ok(())
+    }
+
+    /// Removes a member of a lower rank from the guild of a character.
+    pub async fn kick(&self, character_id: u64, name: &str) -> Result<(), GuildError> {
+        let member = self.membership(character_id).await?;
+        self.check_permission(&member, GuildPermissions::KICK)
+            .await?;
+        let target = self.member_named(&member, name).await?;
+        if target.rank <= member.rank {
+            return Err(GuildResult::NotPermitted.into());
+        }
+
+        self.database
+            .guilds()
+            .remove_member(member.guild_id, target.character_id)
+            .await?;
+        let target_id = target.character_id as u64;
+        // the kicked member is told along with the others
+        self.broadcast(
+            member.guild_id,
+            GuildEvent::MemberKicked {
+                character_id: target_id,
+            },
+        );
+        self.set_guild(target_id, None);
+        Ok(())
+    }
+
+    /// Changes the rank of a member of a lower rank, to a rank below the one
+    /// of the character.
+    pub async fn set_rank(
+        &self,
+        character_id: u64,
+        name: &str,
+        rank: u8,
+    ) -> Result<(), GuildError> {
+        let member = self.membership(character_id).await?;
+        self.check_permission(&member, GuildPermissions::SET_RANK)
+            .await?;
+        let target = self.member_named(&member, name).await?;
+        if target.rank <= member.rank || rank <= member.rank {
+            return Err(GuildResult::NotPermitted.into());
+        }
+        let guilds = self.database.guilds();
+        let ranks = guilds.ranks(member.guild_id).await?;
+        if !ranks.iter().any(|guild_rank| guild_rank.rank == rank) {
+            return Err(GuildResult::InvalidRank.into());
+        }
+
+        guilds
+            .set_rank(member.guild_id, target.character_id, rank)
+            .await?;
+        self.broadcast(
+            member.guild_id,
+            GuildEvent::RankChanged {
+                character_id: target.character_id as u64,
+                rank,
+            },
+        );
+        Ok(())
+    }
+
+    /// Renames a rank below the one of the character, or any rank for the
+    /// guild master.
+    pub async fn rename_rank(
+        &self,
+        character_id: u64,
+        rank: u8,
+        name: &str,
+    ) -> Result<(), GuildError> {
+        let member = self.membership(character_id).await?;
+        self.check_permission(&member, GuildPermissions::RENAME_RANK)
+            .await?;
+        if member.rank != 0 && rank <= member.rank {
+            return Err(GuildResult::NotPermitted.into());
+        }
+        let length = name.chars().count();
+        if name.trim() != name || length == 0 || length > MAX_RANK_NAME_LENGTH {
+            return Err(GuildResult::InvalidName.into());
+        }
+
+        if !self
+            .database
+            .guilds()
+            .rename_rank(member.guild_id, rank, name)
+            .await?
+        {
+            return Err(GuildResult::InvalidRank.into());
+        }
+        self.broadcast(
+            member.guild_id,
+            GuildEvent::RankRenamed {
+                rank,
+                name: name.to_string(),
+            },
+        );
+        Ok(())
+    }
+
+    async fn membership(&self, character_id: u64) -> Result<ws_db::GuildMember, GuildError> {
+        let member = self.database.guilds().member(character_id as i64).await?;
+        Ok(member.ok_or(GuildResult::NotInGuild)?)
+    }
+
+    /// Finds a member of the guild of `member` by name.
+    async fn member_named(
+        &self,
+        member: &ws_db::GuildMember,
+        name: &str,
+    ) -> Result<ws_db::GuildMember, GuildError> {
+        let character = self
+            .database
+            .characters()
+            .find_by_name(name)
+            .await?
+            .ok_or(GuildResult::PlayerNotFound)?;
+        match self.database.guilds().member(character.id).await? {
+            Some(target) if target.guild_id == member.guild_id => Ok(target),
+            _ => Err(GuildResult::PlayerNotFound.into()),
+        }
+    }
+
+    async fn check_permission(
+        &self,
+        member: &ws_db::GuildMember,
+        permission: GuildPermissions,
+    ) -> Result<(), GuildError> {
+        if member.rank == 0 {
+            return Ok(());
+        }
+        let ranks = self.database.guilds().ranks(member.guild_id).await?;
+        let permissions = ranks
+            .iter()
+            .find(|rank| rank.rank == member.rank)
+            .map(|rank| GuildPermissions::from_bits_truncate(rank.permissions))
+            .unwrap_or_else(GuildPermissions::empty);
+        if !permissions.contains(permission) {
+            return Err(GuildResult::NotPermitted.into());
+        }
+        Ok(())
+    }
+
+    fn roster_member(&self, member: ws_db::GuildMember) -> GuildRosterMember {
+        let character_id = member.character_id as u64;
+        GuildRosterMember {
+            character_id,
+            name: member.name,
+            rank: member.rank,
+            class: member.class,
+            level: member.level,
+            online: self.is_online(character_id),
+        }
+    }
+
+    fn set_guild(&self, character_id: u64, guild_id: Option<i64>) {
+        if let Some(character) = self.online.lock().unwrap().get_mut(&character_id) {
+            character.guild_id = guild_id;
+        }
+    }
+
+    /// Sends a message to a character in the world. Characters whose session
+    /// just closed are skipped, they are removed once it is handled.
+    fn send<T: Message + WriteValue + fmt::Debug>(&self, character_id: u64, message: &T) {
+        if let Some(character) = self.online.lock().unwrap().get(&character_id) {
+            let _ = character.session.send(message);
+        }
+    }
+
+    /// Sends an event to the online members of a guild.
+    fn broadcast(&self, guild_id: i64, event: GuildEvent) {
+        let message = ServerGuildEvent {
+            guild_id: guild_id as u64,
+            event,
+        };
+        let online = self.online.lock().unwrap();
+        for character in online.values() {
+            if character.guild_id == Some(guild_id) {
+                let _ = character.session.send(&message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_guild_name() {
+        let blocked = vec!["darn".to_string()];
+        assert_eq!(validate_guild_name("Sandbox Testers", &blocked), Ok(()));
+        assert_eq!(validate_guild_name("The Big Guild", &blocked), Ok(()));
+        assert_eq!(
+            validate_guild_name("Ab", &blocked),
+            Err(NameError::TooShort)
+        );
+        assert_eq!(
+            validate_guild_name("Sandbox  Testers", &blocked),
+            Err(NameError::MisplacedSeparator)
+        );
+        assert_eq!(
+            validate_guild_name(" Sandbox", &blocked),
+            Err(NameError::MisplacedSeparator)
+        );
+        assert_eq!(
+            validate_guild_name("Ka'tal", &blocked),
+            Err(NameError::InvalidCharacter('\''))
+        );
+        assert_eq!(
+            validate_guild_name("Da Rn Guild", &blocked),
+            Err(NameError::Blocked("darn".to_string()))
+        );
+    }
+}
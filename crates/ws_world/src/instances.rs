@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::Duration;
+
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::ScheduleSystem;
+use rayon::prelude::*;
+use rayon::ThreadPool;
+
+use crate::ecs::{self, Despawn, GuidIndex, Outbox, Viewer};
+use crate::*;
+
+/// How long an instance without players is kept before it shuts down.
+pub const INSTANCE_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InstanceId(pub u32);
+
+impl fmt::Display for InstanceId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Which instance of a map the entities joining it are routed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstanceKey {
+    /// The instance of an open world map, shared by everyone on it.
+    Shared { world_id: u32 },
+    /// The instance of a dungeon that the members of a group share.
+    Group { world_id: u32, group_id: u64 },
+    /// The instance of a dungeon of a player without a group.
+    Solo { world_id: u32, guid: Guid },
+}
+
+impl InstanceKey {
+    pub fn world_id(&self) -> u32 {
+        match *self {
+            Self::Shared { world_id }
+            | Self::Group { world_id, .. }
+            | Self::Solo { world_id, .. } => world_id,
+        }
+    }
+}
+
+type SystemAdder = Box<dyn Fn(&mut Schedule) + Send + Sync>;
+
+/// The entities of a map instance, in their own ECS [`World`] with the
+/// systems that advance them.
+pub struct Instance {
+    id: InstanceId,
+    key: InstanceKey,
+    world: World,
+    schedule: Schedule,
+    /// The creatures of the spawn points of the map.
+    pub(crate) spawned: Vec<Guid>,
+    /// The time since the last player left, while there is none.
+    idle: Duration,
+}
+
+impl Instance {
+    fn new(id: InstanceId, key: InstanceKey, systems: &[SystemAdder]) -> Self {
+        let mut world = World::new();
+        world.init_resource::<GuidIndex>();
+        world.init_resource::<ecs::Grids>();
+        world.init_resource::<ecs::TickTime>();
+        world.init_resource::<Outbox>();
+        let mut schedule = Schedule::default();
+        schedule.add_systems(
+            (
+                ecs::walk,
+                ecs::regenerate,
+                ecs::cast_spells,
+                ecs::leave_instance,
+                ecs::update_visibility,
+                ecs::update_properties,
+                ecs::send_updates,
+            )
+                .chain(),
+        );
+        for add_system in systems {
+            add_system(&mut schedule);
+        }
+        Self {
+            id,
+            key,
+            world,
+            schedule,
+            spawned: Vec::new(),
+            idle: Duration::ZERO,
+        }
+    }
+
+    pub fn id(&self) -> InstanceId {
+        self.id
+    }
+
+    pub fn key(&self) -> InstanceKey {
+        self.key
+    }
+
+    pub fn world_id(&self) -> u32 {
+        self.key.world_id()
+    }
+
+    pub fn world(&self) -> &World {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.world
+    }
+
+    /// Returns the ECS entity of a guid, unless it isn't in the instance or
+    /// is leaving it.
+    pub fn entity(&self, guid: Guid) -> Option<Entity> {
+        let entity = *self.world.resource::<GuidIndex>().0.get(&guid)?;
+        match self.world.get::<Despawn>(entity) {
+            Some(_) => None,
+            None => Some(entity),
+        }
+    }
+
+    /// Returns the number of players in the instance.
+    pub fn player_count(&mut self) -> usize {
+        self.world
+            .query_filtered::<(), (With<Viewer>, Without<Despawn>)>()
+            .iter(&self.world)
+            .count()
+    }
+
+    /// Advances the entities by `elapsed`, and sends the updates of the
+    /// tick to the players.
+    pub fn tick(&mut self, elapsed: Duration) {
+        self.world.resource_mut::<ecs::TickTime>().0 = elapsed.as_secs_f32();
+        self.schedule.run(&mut self.world);
+    }
+}
+
+impl fmt::Debug for Instance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Instance")
+            .field("id", &self.id)
+            .field("key", &self.key)
+            .field("entities", &self.world.entities().len())
+            .field("idle", &self.idle)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The instances of the maps of a world server.
+///
+/// Every instance has its own entities, so that the players only see and
+/// hear the ones of the instance they are in. Instances are started when an
+/// entity is routed to one that isn't running, and shut down once they had
+/// no players for the idle timeout. They tick in parallel on a thread pool,
+/// and send what concerns other instances through their [`Outbox`].
+pub struct InstanceManager {
+    instances: BTreeMap<InstanceId, Instance>,
+    routes: HashMap<InstanceKey, InstanceId>,
+    last_id: u32,
+    idle_timeout: Duration,
+    systems: Vec<SystemAdder>,
+    /// The pool the instances tick on, rayon's global one by default.
+    pool: Option<ThreadPool>,
+    /// The events sent by the instances during the last tick.
+    events: Vec<WorldEvent>,
+}
+
+impl Default for InstanceManager {
+    fn default() -> Self {
+        Self {
+            instances: BTreeMap::new(),
+            routes: HashMap::new(),
+            last_id: 0,
+            idle_timeout: INSTANCE_IDLE_TIMEOUT,
+            systems: Vec::new(),
+            pool: None,
+            events: Vec::new(),
+        }
+    }
+}
+
+impl InstanceManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the default idle timeout of [`INSTANCE_IDLE_TIMEOUT`].
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Ticks the instances on a pool of `threads` threads, instead of the
+    /// global one which has a thread per core.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|index| format!("instances-{index}"))
+            .build()
+            .expect("Failed to start the threads of the instances");
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Adds a system run every tick by every instance, after the built-in
+    /// systems moved the entities and finished the casts, and before the
+    /// visibility changes are sent.
+    pub fn add_system<M>(
+        &mut self,
+        system: impl IntoScheduleConfigs<ScheduleSystem, M> + Clone + Send + Sync + 'static,
+    ) {
+        let add_system: SystemAdder = Box::new(move |schedule| {
+            schedule.add_systems(
+                system
+                    .clone()
+                    .after(ecs::cast_spells)
+                    .before(ecs::update_visibility),
+            );
+        });
+        for instance in self.instances.values_mut() {
+            add_system(&mut instance.schedule);
+        }
+        self.systems.push(add_system);
+    }
+
+    pub fn len(&self) -> usize {
+        self.instances.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instances.is_empty()
+    }
+
+    pub fn get(&self, id: InstanceId) -> Option<&Instance> {
+        self.instances.get(&id)
+    }
+
+    pub fn get_mut(&mut self, id: InstanceId) -> Option<&mut Instance> {
+        self.instances.get_mut(&id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Instance> {
+        self.instances.values()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Instance> {
+        self.instances.values_mut()
+    }
+
+    /// Returns the running instance of a key.
+    pub fn route(&self, key: InstanceKey) -> Option<InstanceId> {
+        self.routes.get(&key).copied()
+    }
+
+    /// Starts an instance for a key, which entities are routed to until it
+    /// shuts down.
+    pub fn start(&mut self, key: InstanceKey) -> &mut Instance {
+        self.last_id += 1;
+        let id = InstanceId(self.last_id);
+        tracing::debug!("Starting instance {id} of {key:?}");
+        self.routes.insert(key, id);
+        self.instances
+            .entry(id)
+            .or_insert_with(|| Instance::new(id, key, &self.systems))
+    }
+
+    /// Returns the instance that an entity is in.
+    pub fn instance_of(&self, guid: Guid) -> Option<InstanceId> {
+        self.instances
+            .values()
+            .find(|instance| instance.entity(guid).is_some())
+            .map(Instance::id)
+    }
+
+    /// Ticks every instance in parallel, collects the events they sent,
+    /// then shuts down the ones that had no players for the idle timeout,
+    /// and returns their ids.
+    pub fn tick(&mut self, elapsed: Duration) -> Vec<InstanceId> {
+        let mut instances: Vec<&mut Instance> = self.instances.values_mut().collect();
+        let tick = |instance: &mut &mut Instance| instance.tick(elapsed);
+        match &self.pool {
+            Some(pool) => pool.install(|| instances.par_iter_mut().for_each(tick)),
+            None => instances.par_iter_mut().for_each(tick),
+        }
+
+        let mut idle = Vec::new();
+        for instance in self.instances.values_mut() {
+            let outbox = &mut instance.world.resource_mut::<Outbox>().0;
+            self.events.append(outbox);
+            if instance.player_count() > 0 {
+                instance.idle = Duration::ZERO;
+                continue;
+            }
+            instance.idle += elapsed;
+            if instance.idle >= self.idle_timeout {
+                idle.push(instance.id);
+            }
+        }
+        for id in &idle {
+            if let Some(instance) = self.instances.remove(id) {
+                tracing::debug!("Shutting down the idle instance {id} of {:?}", instance.key);
+                self.routes.remove(&instance.key);
+            }
+        }
+        idle
+    }
+
+    /// Returns the events that the instances sent during the ticks since
+    /// the last call, in the order of the instances.
+    pub fn take_events(&mut self) -> Vec<WorldEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+impl fmt::Debug for InstanceManager {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InstanceManager")
+            .field("instances", &self.instances)
+            .field("idle_timeout", &self.idle_timeout)
+            .field(
+                "threads",
+                &self.pool.as_ref().map(ThreadPool::current_num_threads),
+            )
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_routes() {
+        let mut instances = InstanceManager::new().with_idle_timeout(Duration::from_secs(1));
+        let open_world = InstanceKey::Shared { world_id: 870 };
+        let dungeon = InstanceKey::Group {
+            world_id: 1000,
+            group_id: 3,
+        };
+        assert_eq!(instances.route(open_world), None);
+        let first = instances.start(open_world).id();
+        let second = instances.start(dungeon).id();
+        assert_ne!(first, second);
+        assert_eq!(instances.route(open_world), Some(first));
+        assert_eq!(instances.get(second).unwrap().world_id(), 1000);
+
+        assert_eq!(instances.tick(Duration::from_millis(500)), []);
+        assert_eq!(instances.tick(Duration::from_millis(500)), [first, second]);
+        assert_eq!(instances.route(dungeon), None);
+        // a new instance starts for the key
+        assert_ne!(instances.start(dungeon).id(), second);
+    }
+
+    /// Sends the removal of every entity of the instance to the world loop.
+    fn remove_all(mut outbox: ResMut<Outbox>, query: Query<&ecs::EntityGuid>) {
+        for guid in &query {
+            outbox.0.push(WorldEvent::Remove { guid: guid.0 });
+        }
+    }
+
+    #[test]
+    fn test_outbox() {
+        let mut instances = InstanceManager::new().with_threads(2);
+        instances.add_system(remove_all);
+        let guids = [870, 1000].map(|world_id| {
+            let guid = Guid::new(EntityType::Creature, 1, world_id as u64);
+            let instance = instances.start(InstanceKey::Shared { world_id });
+            instance.world_mut().spawn(ecs::EntityGuid(guid));
+            guid
+        });
+
+        instances.tick(Duration::from_millis(100));
+        let events = instances.take_events();
+        let removed: Vec<Guid> = events
+            .iter()
+            .map(|event| match event {
+                WorldEvent::Remove { guid } => *guid,
+                event => panic!("unexpected event {event:?}"),
+            })
+            .collect();
+        assert_eq!(removed, guids);
+        assert!(instances.take_events().is_empty());
+    }
+}
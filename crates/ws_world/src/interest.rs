@@ -0,0 +1,78 @@
+use crate::*;
+
+/// The distance under which the entities a player sees are sent every tick.
+pub const NEAR_DISTANCE: f32 = 40.0;
+/// The distance under which the entities a player sees are sent every
+/// [`Relevance::Medium`] interval, and past which they are
+/// [`Relevance::Low`].
+pub const MEDIUM_DISTANCE: f32 = 100.0;
+
+/// How much an entity matters to a player who sees it, which sets how often
+/// the player is sent its movements and properties.
+///
+/// The updates of an entity that isn't due at a tick are held back and
+/// merged with the next ones, so the player gets its last position and
+/// property values once it is. Positions are still sent as deltas from what
+/// the client knows, see [`UpdateBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Relevance {
+    Low,
+    Medium,
+    High,
+}
+
+impl Relevance {
+    /// Scores an entity at `distance` units from a player. The target of the
+    /// player and the members of its group matter however far they are.
+    pub fn of(distance: f32, targeted: bool, grouped: bool) -> Self {
+        if targeted || grouped || distance <= NEAR_DISTANCE {
+            Self::High
+        } else if distance <= MEDIUM_DISTANCE {
+            Self::Medium
+        } else {
+            Self::Low
+        }
+    }
+
+    /// Returns the number of ticks between two updates of an entity.
+    pub fn interval(self) -> u64 {
+        match self {
+            Self::Low => 10,
+            Self::Medium => 3,
+            Self::High => 1,
+        }
+    }
+
+    /// Returns true if the updates of an entity are sent at a tick. Entities
+    /// of the same relevance take turns by guid, so that they aren't all sent
+    /// at the same tick.
+    pub fn is_due(self, tick: u64, guid: Guid) -> bool {
+        tick.wrapping_add(guid.raw())
+            .is_multiple_of(self.interval())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_relevance() {
+        assert_eq!(Relevance::of(10.0, false, false), Relevance::High);
+        assert_eq!(Relevance::of(60.0, false, false), Relevance::Medium);
+        assert_eq!(Relevance::of(150.0, false, false), Relevance::Low);
+        assert_eq!(Relevance::of(150.0, true, false), Relevance::High);
+        assert_eq!(Relevance::of(150.0, false, true), Relevance::High);
+
+        let guid = Guid::from_raw(7);
+        let due =
+            |relevance: Relevance| (0..30).filter(|tick| relevance.is_due(*tick, guid)).count();
+        assert_eq!(due(Relevance::High), 30);
+        assert_eq!(due(Relevance::Medium), 10);
+        assert_eq!(due(Relevance::Low), 3);
+        // entities take turns
+        let other = Guid::from_raw(8);
+        let tick = (0..10).find(|tick| Relevance::Low.is_due(*tick, guid));
+        assert!(!Relevance::Low.is_due(tick.unwrap(), other));
+    }
+}
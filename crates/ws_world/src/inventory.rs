@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::*;
+
+/// The number of slots of the bags, until bags can be equipped.
+pub const INVENTORY_SLOTS: u32 = 32;
+pub const BANK_SLOTS: u32 = 16;
+
+/// An item owned by a character.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub guid: Guid,
+    pub template_id: u32,
+    pub count: u32,
+    pub slot: ItemSlot,
+    pub properties: Vec<ItemProperty>,
+}
+
+impl Item {
+    pub fn data(&self) -> ItemData {
+        ItemData {
+            guid: self.guid,
+            template_id: self.template_id,
+            slot: self.slot,
+            count: self.count,
+            property_count: self.properties.len() as u8,
+            properties: self.properties.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InventoryError {
+    InvalidCount,
+    /// There isn't enough room in the inventory for the items.
+    Full,
+    InvalidSlot(ItemSlot),
+    ItemNotFound(Guid),
+    /// The inventory has fewer items of the template than asked for.
+    NotEnough {
+        template_id: u32,
+    },
+}
+
+impl InventoryError {
+    /// Returns the text telling the player what went wrong.
+    pub fn text(&self) -> Text {
+        match self {
+            Self::InvalidCount => TextId::InvalidCount.into(),
+            Self::Full => TextId::InventoryFull.into(),
+            Self::InvalidSlot(slot) => Text::new(TextId::InvalidSlot)
+                .param(slot.bag_index)
+                .param(format_args!("{:?}", slot.location)),
+            Self::ItemNotFound(guid) => Text::new(TextId::ItemNotFound).param(guid),
+            Self::NotEnough { template_id } => Text::new(TextId::NotEnoughItems).param(template_id),
+        }
+    }
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.text().fmt(f)
+    }
+}
+
+impl std::error::Error for InventoryError {}
+
+pub type InventoryResult<T = ()> = Result<T, InventoryError>;
+
+/// The items of a character, by slot.
+///
+/// Every change returns the updates that tell the client about it, to send
+/// with [`ServerItemUpdates`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Inventory {
+    items: BTreeMap<ItemSlot, Item>,
+}
+
+impl Inventory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of slots of a bag.
+    pub fn capacity(location: ItemLocation) -> u32 {
+        match location {
+            ItemLocation::Inventory => INVENTORY_SLOTS,
+            ItemLocation::Bank => BANK_SLOTS,
+        }
+    }
+
+    pub fn get(&self, guid: Guid) -> Option<&Item> {
+        self.items.values().find(|item| item.guid == guid)
+    }
+
+    pub fn at(&self, slot: ItemSlot) -> Option<&Item> {
+        self.items.get(&slot)
+    }
+
+    /// Returns the items, ordered by bag and slot.
+    pub fn items(&self) -> impl Iterator<Item = &Item> {
+        self.items.values()
+    }
+
+    /// Returns the number of items of a template, in all bags.
+    pub fn count(&self, template_id: u32) -> u32 {
+        self.items()
+            .filter(|item| item.template_id == template_id)
+            .map(|item| item.count)
+            .sum()
+    }
+
+    /// Adds items to the inventory bag, filling the stacks of the same
+    /// template before taking free slots.
+    ///
+    /// Nothing is added if they don't all fit.
+    pub fn add(
+        &mut self,
+        template: &ItemTemplate,
+        count: u32,
+        reason: ItemAddReason,
+        guids: &GuidAllocator,
+    ) -> InventoryResult<Vec<ItemUpdate>> {
+        if count == 0 {
+            return Err(InventoryError::InvalidCount);
+        }
+        let max_stack = template.max_stack.max(1);
+        let stacks: Vec<_> = self
+            .items
+            .values()
+            .filter(|item| {
+                item.slot.location == ItemLocation::Inventory
+                    && item.template_id == template.id
+                    && item.count < max_stack
+            })
+            .map(|item| item.slot)
+            .collect();
+        let free = self.free_slots(ItemLocation::Inventory);
+        let room = stacks
+            .iter()
+            .map(|slot| (max_stack - self.items[slot].count) as u64)
+            .sum::<u64>()
+            + free.len() as u64 * max_stack as u64;
+        if room < count as u64 {
+            return Err(InventoryError::Full);
+        }
+
+        let mut updates = Vec::new();
+        let mut left = count;
+        for slot in stacks {
+            let item = self.items.get_mut(&slot).expect("stack was just found");
+            let added = left.min(max_stack - item.count);
+            item.count += added;
+            left -= added;
+            updates.push(ItemUpdate::StackCount {
+                guid: item.guid,
+                count: item.count,
+            });
+            if left == 0 {
+                return Ok(updates);
+            }
+        }
+        for slot in free {
+            let item = Item {
+                guid: guids.allocate(EntityType::Item),
+                template_id: template.id,
+                count: left.min(max_stack),
+                slot,
+                properties: Vec::new(),
+            };
+            left -= item.count;
+            updates.push(ItemUpdate::Add {
+                reason,
+                item: item.data(),
+            });
+            self.items.insert(slot, item);
+            if left == 0 {
+                break;
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Moves an item to a slot, swapping it with the item already there.
+    pub fn move_item(&mut self, guid: Guid, to: ItemSlot) -> InventoryResult<Vec<ItemUpdate>> {
+        if to.bag_index >= Self::capacity(to.location) {
+            return Err(InventoryError::InvalidSlot(to));
+        }
+        let from = self
+            .get(guid)
+            .ok_or(InventoryError::ItemNotFound(guid))?
+            .slot;
+        if from == to {
+            return Ok(Vec::new());
+        }
+
+        let mut updates = Vec::new();
+        let mut item = self.items.remove(&from).expect("item was just found");
+        if let Some(mut other) = self.items.remove(&to) {
+            other.slot = from;
+            updates.push(ItemUpdate::Move {
+                guid: other.guid,
+                slot: from,
+            });
+            self.items.insert(from, other);
+        }
+        item.slot = to;
+        updates.push(ItemUpdate::Move { guid, slot: to });
+        self.items.insert(to, item);
+        Ok(updates)
+    }
+
+    /// Removes items of a template from the inventory bag, emptying its
+    /// stacks in slot order.
+    ///
+    /// Nothing is removed if there aren't enough of them.
+    pub fn take(&mut self, template_id: u32, count: u32) -> InventoryResult<Vec<ItemUpdate>> {
+        if count == 0 {
+            return Err(InventoryError::InvalidCount);
+        }
+        let stacks: Vec<_> = self
+            .items
+            .values()
+            .filter(|item| {
+                item.slot.location == ItemLocation::Inventory && item.template_id == template_id
+            })
+            .map(|item| item.slot)
+            .collect();
+        let available: u64 = stacks
+            .iter()
+            .map(|slot| self.items[slot].count as u64)
+            .sum();
+        if available < count as u64 {
+            return Err(InventoryError::NotEnough { template_id });
+        }
+
+        let mut updates = Vec::new();
+        let mut left = count;
+        for slot in stacks {
+            let item = self.items.get_mut(&slot).expect("stack was just found");
+            let taken = left.min(item.count);
+            item.count -= taken;
+            left -= taken;
+            let guid = item.guid;
+            if item.count == 0 {
+                self.items.remove(&slot);
+                updates.push(ItemUpdate::Delete { guid });
+            } else {
+                updates.push(ItemUpdate::StackCount {
+                    guid,
+                    count: item.count,
+                });
+            }
+            if left == 0 {
+                break;
+            }
+        }
+        Ok(updates)
+    }
+
+    /// Destroys an item, whatever its count.
+    pub fn remove(&mut self, guid: Guid) -> InventoryResult<ItemUpdate> {
+        let slot = self
+            .get(guid)
+            .ok_or(InventoryError::ItemNotFound(guid))?
+            .slot;
+        self.items.remove(&slot);
+        Ok(ItemUpdate::Delete { guid })
+    }
+
+    fn free_slots(&self, location: ItemLocation) -> Vec<ItemSlot> {
+        (0..Self::capacity(location))
+            .map(|bag_index| ItemSlot {
+                location,
+                bag_index,
+            })
+            .filter(|slot| !self.items.contains_key(slot))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(id: u32, max_stack: u32) -> ItemTemplate {
+        ItemTemplate {
+            id,
+            name: "Potion".to_string(),
+            quality: 1,
+            max_stack,
+        }
+    }
+
+    fn slot(location: ItemLocation, bag_index: u32) -> ItemSlot {
+        ItemSlot {
+            location,
+            bag_index,
+        }
+    }
+
+    #[test]
+    fn test_add_stacks_then_fills_free_slots() {
+        let guids = GuidAllocator::new(1);
+        let mut inventory = Inventory::new();
+        let potion = template(30, 20);
+
+        let updates = inventory
+            .add(&potion, 15, ItemAddReason::Gm, &guids)
+            .unwrap();
+        assert!(matches!(&updates[..], [ItemUpdate::Add { item, .. }] if item.count == 15));
+        let first = inventory.at(slot(ItemLocation::Inventory, 0)).unwrap().guid;
+
+        let updates = inventory
+            .add(&potion, 30, ItemAddReason::Loot, &guids)
+            .unwrap();
+        assert_eq!(
+            updates[0],
+            ItemUpdate::StackCount {
+                guid: first,
+                count: 20
+            }
+        );
+        assert!(
+            matches!(&updates[1..], [ItemUpdate::Add { item, .. }, ItemUpdate::Add { .. }]
+            if item.slot == slot(ItemLocation::Inventory, 1) && item.count == 20)
+        );
+        assert_eq!(inventory.count(30), 45);
+
+        // nothing is added when the items don't fit
+        let sword = template(7, 1);
+        assert_eq!(
+            inventory.add(&sword, INVENTORY_SLOTS, ItemAddReason::Gm, &guids),
+            Err(InventoryError::Full)
+        );
+        assert_eq!(inventory.count(7), 0);
+        assert_eq!(
+            inventory.add(&sword, 0, ItemAddReason::Gm, &guids),
+            Err(InventoryError::InvalidCount)
+        );
+
+        // taking empties the stacks in slot order
+        assert_eq!(
+            inventory.take(30, 46),
+            Err(InventoryError::NotEnough { template_id: 30 })
+        );
+        let updates = inventory.take(30, 25).unwrap();
+        assert_eq!(updates[0], ItemUpdate::Delete { guid: first });
+        assert!(matches!(
+            updates[1],
+            ItemUpdate::StackCount { count: 15, .. }
+        ));
+        assert_eq!(inventory.count(30), 20);
+    }
+
+    #[test]
+    fn test_move_and_remove() {
+        let guids = GuidAllocator::new(1);
+        let mut inventory = Inventory::new();
+        inventory
+            .add(&template(7, 1), 2, ItemAddReason::Gm, &guids)
+            .unwrap();
+        let first = inventory.at(slot(ItemLocation::Inventory, 0)).unwrap().guid;
+        let second = inventory.at(slot(ItemLocation::Inventory, 1)).unwrap().guid;
+
+        let bank = slot(ItemLocation::Bank, 3);
+        let updates = inventory.move_item(first, bank).unwrap();
+        assert_eq!(
+            updates,
+            [ItemUpdate::Move {
+                guid: first,
+                slot: bank
+            }]
+        );
+
+        // moving onto an item swaps them
+        let updates = inventory.move_item(second, bank).unwrap();
+        assert_eq!(
+            updates,
+            [
+                ItemUpdate::Move {
+                    guid: first,
+                    slot: slot(ItemLocation::Inventory, 1)
+                },
+                ItemUpdate::Move {
+                    guid: second,
+                    slot: bank
+                },
+            ]
+        );
+        assert_eq!(inventory.at(bank).unwrap().guid, second);
+
+        let outside = slot(ItemLocation::Bank, BANK_SLOTS);
+        assert_eq!(
+            inventory.move_item(first, outside),
+            Err(InventoryError::InvalidSlot(outside))
+        );
+        assert_eq!(
+            inventory.remove(first),
+            Ok(ItemUpdate::Delete { guid: first })
+        );
+        assert_eq!(
+            inventory.remove(first),
+            Err(InventoryError::ItemNotFound(first))
+        );
+        assert_eq!(inventory.items().count(), 1);
+    }
+}
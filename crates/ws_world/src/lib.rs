@@ -0,0 +1,104 @@
+//! The world server, which clients connect to with the ticket given by the realm
+//! server.
+//!
+//! This currently covers the flow from the account login to the character
+//! selection, the character creation, the inventory of the characters, spell
+//! casts, groups, guilds, mail, friends, the commodity exchange, and the GM
+//! commands typed in the chat box. The entities of the world are simulated by
+//! the [`WorldLoop`] in an instance of their map, which sends the world
+//! updates to the players around them, and the creatures run the handlers of
+//! their scripts, which are reloaded as they change.
+
+mod characters;
+#[cfg(feature = "server")]
+mod cluster;
+#[cfg(feature = "server")]
+mod commands;
+#[cfg(feature = "server")]
+mod dashboard;
+#[cfg(feature = "server")]
+mod data;
+#[cfg(feature = "server")]
+pub mod ecs;
+mod entities;
+#[cfg(feature = "server")]
+mod exchange;
+#[cfg(feature = "server")]
+mod friends;
+#[cfg(feature = "server")]
+mod groups;
+#[cfg(feature = "server")]
+mod guilds;
+#[cfg(feature = "server")]
+mod instances;
+#[cfg(feature = "server")]
+mod interest;
+#[cfg(feature = "server")]
+mod inventory;
+#[cfg(feature = "server")]
+mod mail;
+mod messages;
+#[cfg(feature = "server")]
+mod recording;
+#[cfg(feature = "server")]
+mod scripts;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+mod spells;
+mod terrain;
+#[cfg(feature = "server")]
+mod text;
+#[cfg(feature = "server")]
+mod tickets;
+mod updates;
+#[cfg(feature = "server")]
+mod visibility;
+#[cfg(feature = "server")]
+mod world_loop;
+
+pub use characters::*;
+#[cfg(feature = "server")]
+pub use cluster::*;
+#[cfg(feature = "server")]
+pub use commands::*;
+#[cfg(feature = "server")]
+pub use dashboard::*;
+#[cfg(feature = "server")]
+pub use data::*;
+pub use entities::*;
+#[cfg(feature = "server")]
+pub use exchange::*;
+#[cfg(feature = "server")]
+pub use friends::*;
+#[cfg(feature = "server")]
+pub use groups::*;
+#[cfg(feature = "server")]
+pub use guilds::*;
+#[cfg(feature = "server")]
+pub use instances::*;
+#[cfg(feature = "server")]
+pub use interest::*;
+#[cfg(feature = "server")]
+pub use inventory::*;
+#[cfg(feature = "server")]
+pub use mail::*;
+pub use messages::*;
+#[cfg(feature = "server")]
+pub use recording::*;
+#[cfg(feature = "server")]
+pub use scripts::*;
+#[cfg(feature = "server")]
+pub use server::*;
+#[cfg(feature = "server")]
+pub use spells::*;
+pub use terrain::*;
+#[cfg(feature = "server")]
+pub use text::*;
+#[cfg(feature = "server")]
+pub use tickets::*;
+pub use updates::*;
+#[cfg(feature = "server")]
+pub use visibility::*;
+#[cfg(feature = "server")]
+pub use world_loop::*;
@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ws_bitpack::WriteValue;
+use ws_db::{Database, DbError, NewMail};
+use ws_messages::Message;
+use ws_net::Session;
+
+use crate::*;
+
+/// How long mail is kept before it expires, attachments included.
+pub const MAIL_EXPIRY: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// How often the [`WorldLoop`] deletes the mail that expired.
+pub const MAIL_EXPIRY_INTERVAL: Duration = Duration::from_secs(60);
+pub const MAX_MAIL_SUBJECT_LENGTH: usize = 64;
+pub const MAX_MAIL_BODY_LENGTH: usize = 2000;
+pub const MAX_MAIL_ATTACHMENTS: usize = 10;
+
+#[derive(Debug)]
+pub enum MailError {
+    /// The request was refused, the client is told why.
+    Refused(MailResult),
+    Db(DbError),
+}
+
+impl fmt::Display for MailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Refused(result) => write!(f, "mail request refused: {result:?}"),
+            Self::Db(error) => write!(f, "mail database error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for MailError {}
+
+impl From<MailResult> for MailError {
+    fn from(result: MailResult) -> Self {
+        Self::Refused(result)
+    }
+}
+
+impl From<DbError> for MailError {
+    fn from(error: DbError) -> Self {
+        Self::Db(error)
+    }
+}
+
+/// Returns the seconds since the Unix epoch, which mail expires by.
+pub fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64
+}
+
+/// Returns a mail from the server itself, which expires like the others.
+pub fn system_mail(
+    sender_name: &str,
+    recipient_id: u64,
+    subject: &str,
+    body: &str,
+    attachments: Vec<(u32, u32)>,
+) -> NewMail {
+    NewMail {
+        sender_id: None,
+        sender_name: sender_name.to_string(),
+        recipient_id: recipient_id as i64,
+        subject: subject.to_string(),
+        body: body.to_string(),
+        attachments,
+        expires_at: unix_now() + MAIL_EXPIRY.as_secs() as i64,
+    }
+}
+
+/// The mail of a world server, kept in its database.
+///
+/// Mail sent to characters outside of the world waits in the database, and
+/// their mailbox is sent to them when they enter it. The items attached to a
+/// mail are kept by template and count until the recipient takes them.
+#[derive(Debug)]
+pub struct MailManager {
+    database: Database,
+    /// The sessions of the characters in the world.
+    online: Mutex<HashMap<u64, Session>>,
+}
+
+impl MailManager {
+    pub fn new(database: Database) -> Self {
+        Self {
+            database,
+            online: Default::default(),
+        }
+    }
+
+    /// Adds a character who entered the world, and sends its mailbox.
+    pub async fn enter(&self, session: Session, character_id: u64) -> Result<(), DbError> {
+        let mut list = Vec::new();
+        for mail in self.database.mail().list(character_id as i64).await? {
+            list.push(self.mail_data(mail).await?);
+        }
+        // the session was closed if sending fails, it is removed once handled
+        let _ = session.send(&ServerMailList {
+            count: list.len() as u16,
+            mail: list,
+        });
+        self.online.lock().unwrap().insert(character_id, session);
+        Ok(())
+    }
+
+    /// Returns true if a character entered the world and didn't leave it.
+    pub fn is_online(&self, character_id: u64) -> bool {
+        self.online.lock().unwrap().contains_key(&character_id)
+    }
+
+    /// Removes a character who left the world.
+    pub fn exit(&self, character_id: u64) {
+        self.online.lock().unwrap().remove(&character_id);
+    }
+
+    /// Sends a mail from a character, with the template and count of the
+    /// items it attached. The recipient gets it at once if it is in the
+    /// world.
+    pub async fn send(
+        &self,
+        sender_id: u64,
+        sender_name: &str,
+        mail: &ClientMailSend,
+        attachments: Vec<(u32, u32)>,
+    ) -> Result<(), MailError> {
+        if mail.subject.chars().count() > MAX_MAIL_SUBJECT_LENGTH
+            || mail.body.chars().count() > MAX_MAIL_BODY_LENGTH
+            || attachments.len() > MAX_MAIL_ATTACHMENTS
+        {
+            return Err(MailResult::InvalidMail.into());
+        }
+        let recipient = self
+            .database
+            .characters()
+            .find_by_name(&mail.recipient)
+            .await?
+            .ok_or(MailResult::RecipientNotFound)?;
+
+        self.deliver(NewMail {
+            sender_id: Some(sender_id as i64),
+            sender_name: sender_name.to_string(),
+            recipient_id: recipient.id,
+            subject: mail.subject.clone(),
+            body: mail.body.clone(),
+            attachments,
+            expires_at: unix_now() + MAIL_EXPIRY.as_secs() as i64,
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Sends a mail from the server itself, such as the items of a cancelled
+    /// commodity order.
+    pub async fn send_system(
+        &self,
+        sender_name: &str,
+        recipient_id: u64,
+        subject: &str,
+        body: &str,
+        attachments: Vec<(u32, u32)>,
+    ) -> Result<(), DbError> {
+        let mail = system_mail(sender_name, recipient_id, subject, body, attachments);
+        self.deliver(mail).await
+    }
+
+    /// Tells the recipient of a mail sent outside of the manager about it,
+    /// if it is in the world.
+    pub async fn delivered(&self, mail: ws_db::Mail) -> Result<(), DbError> {
+        let recipient_id = mail.recipient_id as u64;
+        if self.online.lock().unwrap().contains_key(&recipient_id) {
+            let mail = self.mail_data(mail).await?;
+            self.send_to(recipient_id, &ServerMailReceived { mail });
+        }
+        Ok(())
+    }
+
+    /// Marks a mail of a character as read.
+    pub async fn read(&self, character_id: u64, mail_id: u64) -> Result<(), MailError> {
+        if !self
+            .database
+            .mail()
+            .mark_read(character_id as i64, mail_id as i64)
+            .await?
+        {
+            return Err(MailResult::MailNotFound.into());
+        }
+        Ok(())
+    }
+
+    /// Returns the attachments of a mail of a character, left in the mail
+    /// until [`remove_attachments`](Self::remove_attachments) is called.
+    pub async fn attachments(
+        &self,
+        character_id: u64,
+        mail_id: u64,
+    ) -> Result<Vec<ws_db::MailAttachment>, MailError> {
+        let mail = self.find(character_id, mail_id).await?;
+        Ok(self.database.mail().attachments(mail.id).await?)
+    }
+
+    /// Removes the attachments of a mail once they were added to the
+    /// inventory of the recipient.
+    pub async fn remove_attachments(&self, mail_id: u64) -> Result<(), DbError> {
+        self.database
+            .mail()
+            .remove_attachments(mail_id as i64)
+            .await?;
+        Ok(())
+    }
+
+    /// Deletes a mail of a character, whose attachments were taken.
+    pub async fn delete(&self, character_id: u64, mail_id: u64) -> Result<(), MailError> {
+        let mail = self.find(character_id, mail_id).await?;
+        let mailboxes = self.database.mail();
+        if !mailboxes.attachments(mail.id).await?.is_empty() {
+            return Err(MailResult::HasAttachments.into());
+        }
+        mailboxes.delete(mail.recipient_id, mail.id).await?;
+        self.send_to(character_id, &ServerMailRemoved { mail_id });
+        Ok(())
+    }
+
+    /// Deletes the mail expiring at `now` or before, in seconds since the
+    /// Unix epoch, and returns how much there was. The recipients in the
+    /// world see it leave their mailbox.
+    pub async fn expire(&self, now: i64) -> Result<usize, DbError> {
+        let expired = self.database.mail().delete_expired(now).await?;
+        for mail in &expired {
+            self.send_to(
+                mail.recipient_id as u64,
+                &ServerMailRemoved {
+                    mail_id: mail.id as u64,
+                },
+            );
+        }
+        Ok(expired.len())
+    }
+
+    /// Keeps a mail in the database, and sends it to its recipient if it is
+    /// in the world.
+    async fn deliver(&self, mail: NewMail) -> Result<(), DbError> {
+        let sent = self.database.mail().send(&mail).await?;
+        self.delivered(sent).await
+    }
+
+    async fn find(&self, character_id: u64, mail_id: u64) -> Result<ws_db::Mail, MailError> {
+        let mail = self
+            .database
+            .mail()
+            .find(character_id as i64, mail_id as i64)
+            .await?;
+        Ok(mail.ok_or(MailResult::MailNotFound)?)
+    }
+
+    async fn mail_data(&self, mail: ws_db::Mail) -> Result<MailData, DbError> {
+        let attachments: Vec<_> = self
+            .database
+            .mail()
+            .attachments(mail.id)
+            .await?
+            .into_iter()
+            .map(|attachment| MailAttachmentData {
+                template_id: attachment.template_id,
+                count: attachment.count,
+            })
+            .collect();
+        Ok(MailData {
+            mail_id: mail.id as u64,
+            sender_name: mail.sender_name,
+            subject: mail.subject,
+            body: mail.body,
+            is_read: mail.is_read,
+            expires_in: (mail.expires_at - unix_now()).clamp(0, u32::MAX as i64) as u32,
+            attachment_count: attachments.len() as u8,
+            attachments,
+        })
+    }
+
+    /// Sends a message to a character if it is in the world.
+    fn send_to<T: Message + WriteValue + fmt::Debug>(&self, character_id: u64, message: &T) {
+        if let Some(session) = self.online.lock().unwrap().get(&character_id) {
+            let _ = session.send(message);
+        }
+    }
+}
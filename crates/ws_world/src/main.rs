@@ -0,0 +1,218 @@
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing_subscriber::EnvFilter;
+use ws_cluster::{ClusterNode, Node};
+use ws_config::Config;
+use ws_db::Database;
+use ws_messages::MessageRegistry;
+use ws_metrics::MetricsServer;
+use ws_net::{DebugBridge, PacketTap, Server};
+use ws_protocol::TicketKey;
+use ws_world::{
+    json_codecs, rate_limits, serve_cluster, session_policy, traffic_filter, CommodityExchange,
+    DataStore, InstanceManager, Recorder, Replay, ScriptStore, WorldHandler, WorldLoop,
+    WorldServer, WorldSettings, DASHBOARD_PATH, SCRIPT_POLL_INTERVAL,
+};
+
+/// How long clients can stay silent, they ping every few seconds.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// How many messages the debugging bridge keeps for a client reading slowly.
+const BRIDGE_BACKLOG: usize = 1024;
+/// How long to wait before connecting to the cluster hub again.
+const CLUSTER_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    // RUST_LOG=ws_net=debug logs the messages received and sent
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .init();
+    // the configuration file can be given as the first argument
+    let mut args = std::env::args().skip(1);
+    let path = args.next();
+    if path.as_deref() == Some("replay") {
+        let recording = args
+            .next()
+            .expect("Usage: ws_world replay <recording> [config]");
+        let config = load_config(args.next());
+        return replay(recording, config).await;
+    }
+    let config = load_config(path);
+
+    let addr = &config.world.listen;
+    let mut server = Server::bind(addr, MessageRegistry::with_registered())
+        .await
+        .expect("Failed to bind the world server");
+    server.set_policy(session_policy());
+    server.set_idle_timeout(IDLE_TIMEOUT);
+    server.set_rate_limits(rate_limits());
+    server.set_traffic_filter(traffic_filter());
+    tracing::info!("World server listening on {addr}");
+    let metrics = MetricsServer::bind(&config.world.metrics)
+        .await
+        .expect("Failed to bind the metrics endpoint");
+    tokio::spawn(async {
+        if let Err(error) = metrics.run().await {
+            tracing::error!("Metrics endpoint stopped: {error}");
+        }
+    });
+
+    let mut world = open_world(&config).await;
+    if !config.cluster.addr.is_empty() {
+        world = world.with_tickets();
+    }
+    if config.ticket_key.is_empty() {
+        tracing::warn!("No ticket key is configured, tickets aren't checked for their account");
+    } else {
+        let key = TicketKey::from_hex(&config.ticket_key)
+            .expect("The ticket key must be at least 16 bytes in hex");
+        world = world.with_ticket_key(key);
+    }
+    if !config.world.record.is_empty() {
+        let recorder =
+            Recorder::create(&config.world.record).expect("Failed to create the recording");
+        tracing::info!("Recording the world to {}", config.world.record);
+        world = world.with_recorder(recorder);
+    }
+    let world = Arc::new(world);
+    let mut world_loop = WorldLoop::new(world.clone());
+    if config.world.tick_threads > 0 {
+        let instances = InstanceManager::new().with_threads(config.world.tick_threads);
+        world_loop = world_loop.with_instances(instances);
+    }
+    if !config.world.bridge.is_empty() {
+        let tap = PacketTap::new(BRIDGE_BACKLOG);
+        server.set_packet_tap(tap.clone());
+        let addr = &config.world.bridge;
+        let registry = MessageRegistry::with_registered();
+        let mut bridge = DebugBridge::bind(addr, tap, registry, json_codecs())
+            .await
+            .expect("Failed to bind the debugging bridge");
+        bridge.add_feed(DASHBOARD_PATH, world_loop.snapshots());
+        tracing::warn!("Debugging bridge listening on {addr}, it can inject messages");
+        tokio::spawn(async {
+            if let Err(error) = bridge.run().await {
+                tracing::error!("Debugging bridge stopped: {error}");
+            }
+        });
+    }
+    tokio::spawn(world_loop.run());
+    if !config.cluster.addr.is_empty() {
+        let node = Node::World(config.realm.id);
+        tokio::spawn(join_cluster(world.clone(), config.cluster.addr, node));
+    }
+    tokio::spawn(watch_scripts(world.clone()));
+    #[cfg(unix)]
+    tokio::spawn(reload_on_hangup(world.clone()));
+    let handler = WorldHandler::new(world);
+    if let Err(error) = server.run(Arc::new(handler)).await {
+        tracing::error!("World server stopped: {error}");
+    }
+}
+
+fn load_config(path: Option<String>) -> Config {
+    let path = path.map(PathBuf::from);
+    Config::load(path.as_deref()).expect("Failed to load the configuration")
+}
+
+/// Opens the world with the data tables and the database of the configuration.
+async fn open_world(config: &Config) -> WorldServer {
+    let settings = WorldSettings {
+        realm_id: u16::try_from(config.realm.id).expect("The realm id is too large"),
+        encryption: config.encryption,
+        motd: config.motd.clone(),
+        locale: config.world.locale.clone(),
+    };
+    let data = DataStore::load(&config.world.data_dir).expect("Failed to load the data tables");
+    let scripts = ScriptStore::load(config.world.data_dir.join("scripts"))
+        .expect("Failed to load the scripts");
+    let database = Database::connect(&config.database_url)
+        .await
+        .expect("Failed to open the database");
+    let exchange = CommodityExchange::load(database.clone())
+        .await
+        .expect("Failed to load the commodity orders");
+    WorldServer::with_settings(settings)
+        .with_data(data)
+        .with_scripts(scripts)
+        .with_database(database)
+        .with_exchange(exchange)
+}
+
+/// Replays a recording against a fresh world. It changes the database like
+/// the recorded sessions did, so it should be a copy of the one the server
+/// started with.
+async fn replay(recording: String, config: Config) {
+    let file = std::fs::File::open(&recording).expect("Failed to open the recording");
+    let replay = Replay::read(BufReader::new(file)).expect("Failed to read the recording");
+    let world = Arc::new(open_world(&config).await);
+    let mut world_loop = WorldLoop::new(world.clone()).with_deterministic();
+    let handler = WorldHandler::new(world);
+    let registry = MessageRegistry::with_registered();
+    match replay.run(&mut world_loop, &handler, &registry).await {
+        Ok(report) => {
+            println!(
+                "Replayed {} message(s) over {} tick(s)",
+                report.messages, report.ticks
+            );
+            for (session, frames) in &report.sent {
+                println!("Session {session}: sent {} frame(s)", frames.len());
+            }
+        }
+        Err(error) => tracing::error!("Failed to replay {recording}: {error}"),
+    }
+}
+
+/// Connects to the cluster hub and handles what it sends, connecting again
+/// whenever the connection is lost.
+async fn join_cluster(world: Arc<WorldServer>, addr: String, node: Node) {
+    loop {
+        match ClusterNode::connect(&addr, node.clone()).await {
+            Ok(cluster) => {
+                tracing::info!("Joined the cluster at {addr} as {node}");
+                serve_cluster(world.clone(), cluster).await;
+                tracing::warn!("Lost the connection to the cluster hub");
+            }
+            Err(error) => tracing::warn!("Failed to join the cluster at {addr}: {error}"),
+        }
+        tokio::time::sleep(CLUSTER_RETRY_DELAY).await;
+    }
+}
+
+/// Reloads the scripts whenever their files change. The world loop rebinds
+/// the handlers of the creatures at the start of the next tick, so nobody
+/// has to leave the sandbox.
+async fn watch_scripts(world: Arc<WorldServer>) {
+    let mut interval = tokio::time::interval(SCRIPT_POLL_INTERVAL);
+    loop {
+        interval.tick().await;
+        if !world.scripts().changed() {
+            continue;
+        }
+        match world.scripts().reload() {
+            Ok(scripts) => tracing::info!("Reloaded the scripts of {} creature(s)", scripts.len()),
+            Err(error) => tracing::error!("Failed to reload the scripts: {error}"),
+        }
+    }
+}
+
+/// Reloads the data tables every time the process gets a SIGHUP.
+#[cfg(unix)]
+async fn reload_on_hangup(world: Arc<WorldServer>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+    while hangups.recv().await.is_some() {
+        match world.data().reload() {
+            Ok(tables) => tracing::info!(
+                "Reloaded {} spawn point(s) and {} item template(s)",
+                tables.spawns.len(),
+                tables.items.len()
+            ),
+            Err(error) => tracing::error!("Failed to reload the data tables: {error}"),
+        }
+    }
+}
@@ -0,0 +1,1081 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use ws_bitpack::{BitPackReader, BitPackWriter};
+use ws_messages::*;
+
+use crate::Guid;
+
+/// Sent by the client right after connecting, with the ticket from the realm
+/// server.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x02ee)]
+pub struct ClientHelloRealm {
+    pub account_id: u32,
+    pub session_guid: uuid::Uuid,
+    pub account_name: String,
+}
+
+/// Accepts the login of the account.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0591)]
+pub struct ServerAuthAccepted {
+    pub account_id: u32,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u16)]
+pub enum Faction {
+    Exile = 166,
+    Dominion = 167,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x07e0)]
+pub struct ClientCharacterListRequest {}
+
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharacterEntry {
+    pub character_id: u64,
+    pub name: String,
+    #[packed(14)]
+    pub faction: Faction,
+    #[packed(5)]
+    pub race: u8,
+    #[packed(5)]
+    pub class: u8,
+    pub level: u32,
+    pub world_id: u32,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0117)]
+pub struct ServerCharacterList {
+    #[length_of(characters)]
+    pub count: u32,
+    #[length(count)]
+    pub characters: Vec<CharacterEntry>,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x07dd)]
+pub struct ClientCharacterSelect {
+    pub character_id: u64,
+}
+
+#[derive(MessageStruct, Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+/// Sends the selected character into the world.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x00ad)]
+pub struct ServerChangeWorld {
+    pub world_id: u32,
+    pub position: Position,
+    pub yaw: f32,
+}
+
+/// Sent when the selected character doesn't belong to the account.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x00ae)]
+pub struct ServerCharacterSelectFailed {
+    pub character_id: u64,
+}
+
+/// A choice made in the character creator.
+#[derive(MessageUnion, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Customization {
+    /// The value of a customization label, like a hair style or a skin color.
+    Option { label: u32, value: u32 },
+    /// The offset of a face bone, set with the sliders.
+    Bone {
+        #[packed(7)]
+        index: u8,
+        offset: f32,
+    },
+}
+
+/// Everything chosen in the character creator apart from the race and class.
+#[derive(MessageStruct, Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Appearance {
+    #[length_of(customizations)]
+    #[packed(7)]
+    pub count: u8,
+    #[length(count)]
+    #[variant(inline, bits = 2)]
+    pub customizations: Vec<Customization>,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x025b)]
+pub struct ClientCharacterCreate {
+    pub name: String,
+    #[packed(14)]
+    pub faction: Faction,
+    #[packed(5)]
+    pub race: u8,
+    #[packed(5)]
+    pub class: u8,
+    #[packed(2)]
+    pub sex: u8,
+    #[packed(3)]
+    pub path: u8,
+    pub appearance: Appearance,
+}
+
+/// The results of a character creation that the client shows a message for.
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum CharacterCreateResult {
+    Ok = 3,
+    Failed = 4,
+    NameTaken = 6,
+    AccountFull = 10,
+    InvalidName = 14,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x00dc)]
+pub struct ServerCharacterCreate {
+    #[packed(5)]
+    pub result: CharacterCreateResult,
+    /// The id of the new character, or 0 if it wasn't created.
+    pub character_id: u64,
+    pub world_id: u32,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u16)]
+pub enum ChatChannel {
+    System = 2,
+    Debug = 3,
+    Say = 4,
+}
+
+/// A line typed in the chat box.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x01c3)]
+pub struct ClientChat {
+    #[packed(14)]
+    pub channel: ChatChannel,
+    pub message: String,
+}
+
+/// A line to show in the chat box.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x01c8)]
+pub struct ServerChat {
+    #[packed(14)]
+    pub channel: ChatChannel,
+    pub sender: String,
+    pub message: String,
+}
+
+/// The bags that hold the items of a character.
+#[derive(
+    MessageEnum, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[repr(u16)]
+pub enum ItemLocation {
+    Inventory = 1,
+    Bank = 2,
+}
+
+/// Where an item is, as a bag and an index in that bag.
+#[derive(
+    MessageStruct, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub struct ItemSlot {
+    #[packed(9)]
+    pub location: ItemLocation,
+    pub bag_index: u32,
+}
+
+/// A property rolled on an item, on top of what its template gives.
+#[derive(MessageUnion, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ItemProperty {
+    /// A bonus to a stat of the character wearing the item.
+    Stat {
+        #[packed(7)]
+        stat: u8,
+        value: f32,
+    },
+    Durability(f32),
+    Charges(u32),
+}
+
+/// An item, as the client renders it in its bags.
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ItemData {
+    pub guid: Guid,
+    #[packed(18)]
+    pub template_id: u32,
+    pub slot: ItemSlot,
+    pub count: u32,
+    #[length_of(properties)]
+    #[packed(5)]
+    pub property_count: u8,
+    #[length(property_count)]
+    #[variant(inline, bits = 2)]
+    pub properties: Vec<ItemProperty>,
+}
+
+/// Why an item was added, which picks the message the client shows.
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum ItemAddReason {
+    None = 0,
+    Loot = 1,
+    Vendor = 2,
+    Gm = 3,
+    Mail = 4,
+}
+
+/// A change to the items of a character.
+#[derive(MessageUnion, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ItemUpdate {
+    Add {
+        #[packed(6)]
+        reason: ItemAddReason,
+        item: ItemData,
+    },
+    /// Items were added to or removed from a stack.
+    StackCount {
+        guid: Guid,
+        count: u32,
+    },
+    Move {
+        guid: Guid,
+        slot: ItemSlot,
+    },
+    Delete {
+        guid: Guid,
+    },
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0788)]
+pub struct ServerItemUpdates {
+    #[length_of(updates)]
+    #[packed(10)]
+    pub count: u16,
+    #[length(count)]
+    #[variant(inline, bits = 2)]
+    pub updates: Vec<ItemUpdate>,
+}
+
+impl ServerItemUpdates {
+    pub fn new(updates: Vec<ItemUpdate>) -> Self {
+        Self {
+            count: updates.len() as u16,
+            updates,
+        }
+    }
+}
+
+/// Moves an item to another slot, swapping it with the item there if any.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0235)]
+pub struct ClientItemMove {
+    pub guid: Guid,
+    pub slot: ItemSlot,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0239)]
+pub struct ClientItemDelete {
+    pub guid: Guid,
+}
+
+/// A value of an entity, sent to the players who see it.
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum Property {
+    Health = 0,
+    MaxHealth = 1,
+    /// The health regenerated per second.
+    HealthRegeneration = 2,
+    Shield = 3,
+    MaxShield = 4,
+    ShieldRegeneration = 5,
+    /// The resource spent by the abilities of most classes.
+    Focus = 6,
+    MaxFocus = 7,
+    AssaultPower = 8,
+    SupportPower = 9,
+    Armor = 10,
+    /// In units per second.
+    MoveSpeed = 11,
+}
+
+impl Property {
+    pub const ALL: [Property; 12] = [
+        Property::Health,
+        Property::MaxHealth,
+        Property::HealthRegeneration,
+        Property::Shield,
+        Property::MaxShield,
+        Property::ShieldRegeneration,
+        Property::Focus,
+        Property::MaxFocus,
+        Property::AssaultPower,
+        Property::SupportPower,
+        Property::Armor,
+        Property::MoveSpeed,
+    ];
+
+    /// Returns the property that caps a pool, like `MaxHealth` for `Health`.
+    pub fn max(self) -> Option<Property> {
+        match self {
+            Property::Health => Some(Property::MaxHealth),
+            Property::Shield => Some(Property::MaxShield),
+            Property::Focus => Some(Property::MaxFocus),
+            _ => None,
+        }
+    }
+
+    /// Returns the pool that a property caps, like `Health` for `MaxHealth`.
+    pub fn pool(self) -> Option<Property> {
+        match self {
+            Property::MaxHealth => Some(Property::Health),
+            Property::MaxShield => Some(Property::Shield),
+            Property::MaxFocus => Some(Property::Focus),
+            _ => None,
+        }
+    }
+}
+
+#[derive(MessageStruct, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PropertyValue {
+    #[packed(8)]
+    pub property: Property,
+    pub value: f32,
+}
+
+/// The properties of an entity that changed.
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntityProperties {
+    pub guid: Guid,
+    #[length_of(values)]
+    #[packed(8)]
+    pub count: u8,
+    #[length(count)]
+    pub values: Vec<PropertyValue>,
+}
+
+impl EntityProperties {
+    pub fn new(guid: Guid, values: Vec<PropertyValue>) -> Self {
+        Self {
+            guid,
+            count: values.len() as u8,
+            values,
+        }
+    }
+}
+
+/// The properties that changed during a tick, of the entities a player sees.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[message_id(0x0262)]
+pub struct ServerPropertyUpdates {
+    #[length_of(entities)]
+    #[packed(16)]
+    pub count: u16,
+    #[length(count)]
+    pub entities: Vec<EntityProperties>,
+}
+
+impl ServerPropertyUpdates {
+    pub fn new(entities: Vec<EntityProperties>) -> Self {
+        Self {
+            count: entities.len() as u16,
+            entities,
+        }
+    }
+}
+
+/// What a spell does to its target.
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum SpellEffect {
+    Damage = 0,
+    Heal = 1,
+}
+
+/// Casts a spell on a target, or on the caster without one.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0650)]
+pub struct ClientCastSpell {
+    #[packed(18)]
+    pub spell_id: u32,
+    pub target: Guid,
+}
+
+/// Why a spell couldn't be cast.
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum CastResult {
+    UnknownSpell = 1,
+    CasterDead = 2,
+    AlreadyCasting = 3,
+    Cooldown = 4,
+    InvalidTarget = 5,
+    OutOfRange = 6,
+}
+
+impl fmt::Display for CastResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::UnknownSpell => "unknown spell",
+            Self::CasterDead => "the caster is dead",
+            Self::AlreadyCasting => "the caster is already casting",
+            Self::Cooldown => "the spell is on cooldown",
+            Self::InvalidTarget => "invalid target",
+            Self::OutOfRange => "the target is out of range",
+        })
+    }
+}
+
+/// Sent to the caster of a spell that was refused.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x07f2)]
+pub struct ServerSpellCastFailed {
+    #[packed(18)]
+    pub spell_id: u32,
+    #[packed(5)]
+    pub result: CastResult,
+}
+
+/// A spell started being cast, sent to the players around the caster.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x07f0)]
+pub struct ServerSpellStart {
+    pub cast_id: u32,
+    pub caster: Guid,
+    #[packed(18)]
+    pub spell_id: u32,
+    pub target: Guid,
+    /// In milliseconds.
+    pub cast_time: u32,
+}
+
+#[derive(MessageStruct, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SpellTargetResult {
+    pub target: Guid,
+    #[packed(2)]
+    pub effect: SpellEffect,
+    /// The health taken or given, once capped.
+    pub amount: f32,
+}
+
+/// A cast finished, sent to the players around the caster.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x07f1)]
+pub struct ServerSpellGo {
+    pub cast_id: u32,
+    pub caster: Guid,
+    #[packed(18)]
+    pub spell_id: u32,
+    #[length_of(targets)]
+    #[packed(8)]
+    pub count: u8,
+    #[length(count)]
+    pub targets: Vec<SpellTargetResult>,
+}
+
+/// A member of a group, as shown in the group frames.
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupMember {
+    pub guid: Guid,
+    pub name: String,
+    #[packed(5)]
+    pub class: u8,
+    pub level: u32,
+    pub world_id: u32,
+}
+
+/// Invites a player to the group of the sender, or to a new group.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0502)]
+pub struct ClientGroupInvite {
+    pub name: String,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum GroupInviteResult {
+    Sent = 0,
+    PlayerNotFound = 1,
+    AlreadyGrouped = 2,
+    GroupFull = 3,
+    NotLeader = 4,
+    Declined = 5,
+    /// The player is already considering another invite.
+    Busy = 6,
+}
+
+/// Tells the sender of an invite what became of it.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0503)]
+pub struct ServerGroupInviteResult {
+    pub name: String,
+    #[packed(4)]
+    pub result: GroupInviteResult,
+}
+
+/// Asks a player to join a group.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0504)]
+pub struct ServerGroupInvite {
+    pub inviter: Guid,
+    pub inviter_name: String,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0505)]
+pub struct ClientGroupInviteResponse {
+    pub accept: bool,
+}
+
+/// The members of a group, sent to all of them when a player joins.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0506)]
+pub struct ServerGroupJoined {
+    pub group_id: u64,
+    pub leader: Guid,
+    #[length_of(members)]
+    #[packed(3)]
+    pub count: u8,
+    #[length(count)]
+    pub members: Vec<GroupMember>,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0507)]
+pub struct ClientGroupLeave {}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum GroupLeaveReason {
+    Left = 0,
+    Disconnected = 1,
+}
+
+/// A member left the group, sent to the ones who stay.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0508)]
+pub struct ServerGroupMemberLeft {
+    pub group_id: u64,
+    pub member: Guid,
+    /// The leader once the member left, who changes if it was the leader.
+    pub leader: Guid,
+    #[packed(2)]
+    pub reason: GroupLeaveReason,
+}
+
+/// The group is no more, sent to its last members and to the ones leaving.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0509)]
+pub struct ServerGroupDisbanded {
+    pub group_id: u64,
+}
+
+/// The state of a member changed, like its level or its world.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x050a)]
+pub struct ServerGroupMemberUpdate {
+    pub group_id: u64,
+    pub member: GroupMember,
+}
+
+/// Creates a guild led by the sender.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0510)]
+pub struct ClientGuildCreate {
+    pub name: String,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum GuildResult {
+    Ok = 0,
+    InvalidName = 1,
+    NameTaken = 2,
+    AlreadyInGuild = 3,
+    NotInGuild = 4,
+    PlayerNotFound = 5,
+    /// The rank of the sender doesn't allow it, or the target's rank isn't
+    /// below it.
+    NotPermitted = 6,
+    InvalidRank = 7,
+    /// The player is already considering another invite.
+    Busy = 8,
+    Declined = 9,
+    /// The guild master can't leave a guild that has other members.
+    MasterCantLeave = 10,
+    /// The server keeps no guilds, or failed to.
+    Failed = 11,
+}
+
+/// Tells the sender of a guild request whether it was done.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0511)]
+pub struct ServerGuildResult {
+    #[packed(4)]
+    pub result: GuildResult,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0512)]
+pub struct ClientGuildRosterRequest {}
+
+bitflags::bitflags! {
+    /// What the members of a guild rank may do. The guild master may do
+    /// anything.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    pub struct GuildPermissions: u32 {
+        const INVITE = 1 << 0;
+        const KICK = 1 << 1;
+        const SET_RANK = 1 << 2;
+        const RENAME_RANK = 1 << 3;
+    }
+}
+
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuildRankInfo {
+    #[packed(4)]
+    pub rank: u8,
+    pub name: String,
+    #[flags(8)]
+    pub permissions: GuildPermissions,
+}
+
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GuildRosterMember {
+    pub character_id: u64,
+    pub name: String,
+    #[packed(4)]
+    pub rank: u8,
+    #[packed(5)]
+    pub class: u8,
+    pub level: u32,
+    pub online: bool,
+}
+
+/// The ranks and members of the guild of the player.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0513)]
+pub struct ServerGuildRoster {
+    pub guild_id: u64,
+    pub name: String,
+    #[length_of(ranks)]
+    #[packed(4)]
+    pub rank_count: u8,
+    #[length(rank_count)]
+    pub ranks: Vec<GuildRankInfo>,
+    #[length_of(members)]
+    #[packed(10)]
+    pub member_count: u16,
+    #[length(member_count)]
+    pub members: Vec<GuildRosterMember>,
+}
+
+/// Invites a player to the guild of the sender.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0514)]
+pub struct ClientGuildInvite {
+    pub name: String,
+}
+
+/// Asks a player to join a guild.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0515)]
+pub struct ServerGuildInvite {
+    pub guild_id: u64,
+    pub guild_name: String,
+    pub inviter_name: String,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0516)]
+pub struct ClientGuildInviteResponse {
+    pub accept: bool,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0517)]
+pub struct ClientGuildLeave {}
+
+/// Removes a member of a lower rank from the guild of the sender.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0518)]
+pub struct ClientGuildKick {
+    pub name: String,
+}
+
+/// Promotes or demotes a member of a lower rank, to a rank below the one of
+/// the sender.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0519)]
+pub struct ClientGuildSetRank {
+    pub name: String,
+    #[packed(4)]
+    pub rank: u8,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x051a)]
+pub struct ClientGuildRenameRank {
+    #[packed(4)]
+    pub rank: u8,
+    pub name: String,
+}
+
+/// Something that happened to a guild, as shown in its chat and roster.
+#[derive(MessageUnion, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum GuildEvent {
+    MemberJoined(GuildRosterMember),
+    MemberLeft {
+        character_id: u64,
+    },
+    MemberKicked {
+        character_id: u64,
+    },
+    RankChanged {
+        character_id: u64,
+        #[packed(4)]
+        rank: u8,
+    },
+    RankRenamed {
+        #[packed(4)]
+        rank: u8,
+        name: String,
+    },
+    MemberOnline {
+        character_id: u64,
+        online: bool,
+    },
+    Disbanded,
+}
+
+/// Sent to the online members of a guild when something happens to it.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x051b)]
+pub struct ServerGuildEvent {
+    pub guild_id: u64,
+    #[variant(inline, bits = 3)]
+    pub event: GuildEvent,
+}
+
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailAttachmentData {
+    #[packed(18)]
+    pub template_id: u32,
+    pub count: u32,
+}
+
+/// A mail, as listed in the mailbox.
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MailData {
+    pub mail_id: u64,
+    pub sender_name: String,
+    pub subject: String,
+    pub body: String,
+    pub is_read: bool,
+    /// The seconds left before the mail is deleted.
+    pub expires_in: u32,
+    #[length_of(attachments)]
+    #[packed(4)]
+    pub attachment_count: u8,
+    #[length(attachment_count)]
+    pub attachments: Vec<MailAttachmentData>,
+}
+
+/// Sends a mail to a character, online or not, along with items of the
+/// inventory of the sender.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0520)]
+pub struct ClientMailSend {
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    #[length_of(items)]
+    #[packed(4)]
+    pub item_count: u8,
+    #[length(item_count)]
+    pub items: Vec<Guid>,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum MailResult {
+    Ok = 0,
+    RecipientNotFound = 1,
+    /// The subject or the body is too long, or there are too many
+    /// attachments.
+    InvalidMail = 2,
+    ItemNotFound = 3,
+    MailNotFound = 4,
+    InventoryFull = 5,
+    /// Mail can't be deleted before its attachments are taken.
+    HasAttachments = 6,
+    /// The server keeps no mail, or failed to.
+    Failed = 7,
+}
+
+/// Tells the sender of a mail request whether it was done.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0521)]
+pub struct ServerMailResult {
+    #[packed(4)]
+    pub result: MailResult,
+}
+
+/// The mailbox of the player, sent when entering the world with the mail
+/// received while offline.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0522)]
+pub struct ServerMailList {
+    #[length_of(mail)]
+    #[packed(10)]
+    pub count: u16,
+    #[length(count)]
+    pub mail: Vec<MailData>,
+}
+
+/// A mail arrived while the player is in the world.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0523)]
+pub struct ServerMailReceived {
+    pub mail: MailData,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0524)]
+pub struct ClientMailRead {
+    pub mail_id: u64,
+}
+
+/// Moves the attachments of a mail to the inventory, all of them or none.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0525)]
+pub struct ClientMailTakeAttachments {
+    pub mail_id: u64,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0526)]
+pub struct ClientMailDelete {
+    pub mail_id: u64,
+}
+
+/// A mail left the mailbox, because it was deleted or it expired.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0527)]
+pub struct ServerMailRemoved {
+    pub mail_id: u64,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum CommoditySide {
+    Buy = 0,
+    Sell = 1,
+}
+
+/// An order of the player on the commodity exchange.
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CommodityOrderData {
+    pub order_id: u64,
+    #[packed(1)]
+    pub side: CommoditySide,
+    #[packed(18)]
+    pub template_id: u32,
+    /// The items left to buy or sell.
+    pub count: u32,
+    pub unit_price: u64,
+}
+
+/// Asks for the state of the exchange for a commodity, sent when opening its
+/// page.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0530)]
+pub struct ClientCommodityInfoRequest {
+    #[packed(18)]
+    pub template_id: u32,
+}
+
+/// How much of a commodity is bought and sold, and at the best prices. The
+/// prices are 0 when there is no order.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0531)]
+pub struct ServerCommodityInfo {
+    #[packed(18)]
+    pub template_id: u32,
+    pub buy_count: u32,
+    pub best_buy: u64,
+    pub sell_count: u32,
+    pub best_sell: u64,
+}
+
+/// Places an order on the commodity exchange. The items sold leave the
+/// inventory until the order is filled or cancelled.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0532)]
+pub struct ClientCommodityOrderPost {
+    #[packed(1)]
+    pub side: CommoditySide,
+    #[packed(18)]
+    pub template_id: u32,
+    pub count: u32,
+    pub unit_price: u64,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum CommodityResult {
+    Ok = 0,
+    /// The count or the price is 0, or the item isn't known.
+    InvalidOrder = 1,
+    NotEnoughItems = 2,
+    OrderNotFound = 3,
+    /// The server has no exchange, or failed to keep the order.
+    Failed = 4,
+}
+
+/// Tells the player whether an order was placed or cancelled.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0533)]
+pub struct ServerCommodityResult {
+    #[packed(3)]
+    pub result: CommodityResult,
+    /// The order placed or cancelled, 0 if there is none.
+    pub order_id: u64,
+}
+
+/// Cancels an order of the player, the items left of a sell order are
+/// mailed back.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0534)]
+pub struct ClientCommodityOrderCancel {
+    pub order_id: u64,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0535)]
+pub struct ClientCommodityOrdersRequest {}
+
+/// The open orders of the player.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0536)]
+pub struct ServerCommodityOrders {
+    #[length_of(orders)]
+    #[packed(10)]
+    pub count: u16,
+    #[length(count)]
+    pub orders: Vec<CommodityOrderData>,
+}
+
+/// A friend of the account, as listed in the social window.
+#[derive(MessageStruct, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FriendData {
+    pub character_id: u64,
+    pub name: String,
+    #[packed(5)]
+    pub class: u8,
+    pub level: u32,
+    pub online: bool,
+    pub note: String,
+}
+
+/// Adds a character to the friends of the account, by name.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0540)]
+pub struct ClientFriendAdd {
+    pub name: String,
+}
+
+#[derive(MessageEnum, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum FriendResult {
+    Ok = 0,
+    PlayerNotFound = 1,
+    AlreadyFriend = 2,
+    NotFriend = 3,
+    /// The characters of the account can't be its friends.
+    OwnCharacter = 4,
+    ListFull = 5,
+    /// The note is too long.
+    InvalidNote = 6,
+    /// The server keeps no friends, or failed to.
+    Failed = 7,
+}
+
+/// Tells the player whether a friend request was done.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0541)]
+pub struct ServerFriendResult {
+    #[packed(4)]
+    pub result: FriendResult,
+}
+
+/// The friends of the account, sent when entering the world.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0542)]
+pub struct ServerFriendList {
+    #[length_of(friends)]
+    #[packed(8)]
+    pub count: u8,
+    #[length(count)]
+    pub friends: Vec<FriendData>,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0543)]
+pub struct ServerFriendAdded {
+    pub friend: FriendData,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0544)]
+pub struct ClientFriendRemove {
+    pub character_id: u64,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0545)]
+pub struct ServerFriendRemoved {
+    pub character_id: u64,
+}
+
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0546)]
+pub struct ClientFriendSetNote {
+    pub character_id: u64,
+    pub note: String,
+}
+
+/// A friend of the account entered or left the world.
+#[derive(MessageStruct, Message, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[message_id(0x0547)]
+pub struct ServerFriendPresence {
+    pub character_id: u64,
+    pub online: bool,
+}
+
+// the messages are registered for their JSON form by the server
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use ws_messages::GoldenTests;
+
+    /// Checks the fixtures against the messages the debugging bridge knows,
+    /// which needs a fixture for each of them.
+    #[test]
+    fn test_golden_fixtures() {
+        let tests = GoldenTests::with_codecs(crate::json_codecs());
+        tests.assert_fixtures(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{BufRead, BufWriter, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use ws_bitpack::{BitPackReader, BitPackWriter};
+use ws_messages::{AnyMessage, MessageRegistry};
+use ws_net::{Handler, Session, SessionId};
+
+use crate::*;
+
+/// An input of the world server, recorded in the order it was handled.
+///
+/// Recordings are written one event per line in JSON, like:
+///
+/// ```json
+/// {"event":"message","tick":42,"session":1,"opcode":2016,"body":"..."}
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Connected {
+        /// The number of ticks run before the event.
+        tick: u64,
+        session: SessionId,
+        peer_addr: SocketAddr,
+    },
+    Message {
+        tick: u64,
+        session: SessionId,
+        opcode: u32,
+        /// The body of the message in hex.
+        body: String,
+    },
+    Disconnected {
+        tick: u64,
+        session: SessionId,
+    },
+    /// A tick of the [`WorldLoop`], numbered from 1.
+    Tick {
+        tick: u64,
+        /// How long the tick advanced the entities by, in microseconds.
+        elapsed_us: u64,
+    },
+}
+
+/// Records the messages handled by a world server and the ticks of its
+/// world loop, so that a [`Replay`] can run them again against a fresh world.
+pub struct Recorder {
+    state: Mutex<RecorderState>,
+}
+
+struct RecorderState {
+    writer: Box<dyn Write + Send>,
+    ticks: u64,
+    /// Set once writing failed, which stops the recording.
+    failed: bool,
+}
+
+impl Recorder {
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            state: Mutex::new(RecorderState {
+                writer: Box::new(writer),
+                ticks: 0,
+                failed: false,
+            }),
+        }
+    }
+
+    /// Records to a new file at `path`, replacing any existing one.
+    pub fn create(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = std::fs::File::create(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+
+    pub(crate) fn connected(&self, session: &Session) {
+        self.record(|tick| RecordedEvent::Connected {
+            tick,
+            session: session.id(),
+            peer_addr: session.peer_addr(),
+        });
+    }
+
+    pub(crate) fn message(&self, session: &Session, message: &dyn AnyMessage) {
+        let mut writer = BitPackWriter::with_capacity(message.message_bits().div_ceil(8));
+        let body = match message
+            .write_message(&mut writer)
+            .and_then(|_| writer.finish())
+        {
+            Ok(body) => body,
+            Err(error) => {
+                tracing::error!("Failed to record {}: {error}", message.message_name());
+                return;
+            }
+        };
+        self.record(|tick| RecordedEvent::Message {
+            tick,
+            session: session.id(),
+            opcode: message.message_id(),
+            body: body.iter().map(|byte| format!("{byte:02x}")).collect(),
+        });
+    }
+
+    pub(crate) fn disconnected(&self, session: &Session) {
+        self.record(|tick| RecordedEvent::Disconnected {
+            tick,
+            session: session.id(),
+        });
+    }
+
+    /// Records a tick, flushing the events recorded so far so that a
+    /// recording is complete up to the last tick if the server crashes.
+    pub(crate) fn tick(&self, elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.ticks += 1;
+        let event = RecordedEvent::Tick {
+            tick: state.ticks,
+            elapsed_us: elapsed.as_micros() as u64,
+        };
+        state.write(&event);
+        if let Err(error) = state.writer.flush() {
+            state.fail(error);
+        }
+    }
+
+    fn record(&self, event: impl FnOnce(u64) -> RecordedEvent) {
+        let mut state = self.state.lock().unwrap();
+        let event = event(state.ticks);
+        state.write(&event);
+    }
+}
+
+impl RecorderState {
+    fn write(&mut self, event: &RecordedEvent) {
+        if self.failed {
+            return;
+        }
+        let mut line = serde_json::to_string(event).expect("events serialize to JSON");
+        line.push('\n');
+        if let Err(error) = self.writer.write_all(line.as_bytes()) {
+            self.fail(error);
+        }
+    }
+
+    fn fail(&mut self, error: std::io::Error) {
+        if !self.failed {
+            tracing::error!("Stopped recording, writing failed: {error}");
+            self.failed = true;
+        }
+    }
+}
+
+impl fmt::Debug for Recorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recorder").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    /// An event of the recording is invalid, or refers to a session or
+    /// message that doesn't exist. Lines are numbered from 1.
+    InvalidEvent {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(error) => error.fmt(f),
+            Self::InvalidEvent { line, reason } => write!(f, "line {line}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(error: std::io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+pub type ReplayResult<T> = Result<T, ReplayError>;
+
+/// What a replay did.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    pub ticks: u64,
+    pub messages: usize,
+    /// The frames sent to each session, in order.
+    pub sent: BTreeMap<SessionId, Vec<Vec<u8>>>,
+}
+
+/// A recording of a world server, to reproduce what happened on it.
+///
+/// Replaying handles every message to completion, then runs the ticks with
+/// the recorded durations, in the order they were recorded. With a
+/// [deterministic](WorldLoop::with_deterministic) world loop and a copy of
+/// the database the server started with, the same recording gives the same
+/// world every time. Tickets aren't checked again, as they expired since.
+#[derive(Debug, Clone, Default)]
+pub struct Replay {
+    events: Vec<RecordedEvent>,
+}
+
+impl Replay {
+    pub fn new(events: Vec<RecordedEvent>) -> Self {
+        Self { events }
+    }
+
+    /// Reads a recording written by a [`Recorder`]. A last line cut short
+    /// by a crash is ignored.
+    pub fn read(reader: impl BufRead) -> ReplayResult<Self> {
+        let lines = reader.lines().collect::<std::io::Result<Vec<_>>>()?;
+        let mut events = Vec::with_capacity(lines.len());
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(event) => events.push(event),
+                Err(_) if index + 1 == lines.len() => break,
+                Err(error) => {
+                    return Err(ReplayError::InvalidEvent {
+                        line: index + 1,
+                        reason: error.to_string(),
+                    })
+                }
+            }
+        }
+        Ok(Self { events })
+    }
+
+    pub fn events(&self) -> &[RecordedEvent] {
+        &self.events
+    }
+
+    /// Replays the recording against the world of `world_loop`, sending the
+    /// messages to `handler` on sessions without a client.
+    pub async fn run(
+        &self,
+        world_loop: &mut WorldLoop,
+        handler: &WorldHandler,
+        registry: &MessageRegistry,
+    ) -> ReplayResult<ReplayReport> {
+        let mut report = ReplayReport::default();
+        let mut sessions = BTreeMap::new();
+        for (index, event) in self.events.iter().enumerate() {
+            let invalid = |reason: String| ReplayError::InvalidEvent {
+                line: index + 1,
+                reason,
+            };
+            match event {
+                RecordedEvent::Connected {
+                    session, peer_addr, ..
+                } => {
+                    let (session, frames) = Session::detached(*session, *peer_addr);
+                    if let Err(error) = handler.connected(&session).await {
+                        tracing::warn!("Session {} refused: {error}", session.id());
+                    }
+                    sessions.insert(session.id(), (session, frames));
+                }
+                RecordedEvent::Message {
+                    session,
+                    opcode,
+                    body,
+                    ..
+                } => {
+                    let (session, _) = sessions
+                        .get(session)
+                        .ok_or_else(|| invalid(format!("unknown session {session}")))?;
+                    let registration = registry
+                        .get(*opcode)
+                        .ok_or_else(|| invalid(format!("unknown message 0x{opcode:04x}")))?;
+                    let body = parse_hex(body).ok_or_else(|| invalid("invalid body".into()))?;
+                    let message = (registration.read)(&mut BitPackReader::new(&body))
+                        .map_err(|error| invalid(format!("{}: {error}", registration.name)))?;
+                    // the session was closed here, and is disconnected next
+                    if let Err(error) = handler.message(session, message).await {
+                        tracing::warn!("Session {} failed: {error}", session.id());
+                        session.close();
+                    }
+                    report.messages += 1;
+                }
+                RecordedEvent::Disconnected { session, .. } => {
+                    let (session, mut frames) = sessions
+                        .remove(session)
+                        .ok_or_else(|| invalid(format!("unknown session {session}")))?;
+                    session.close();
+                    handler.disconnected(&session, None).await;
+                    let sent = report.sent.entry(session.id()).or_default();
+                    while let Ok(frame) = frames.try_recv() {
+                        sent.push(frame);
+                    }
+                }
+                RecordedEvent::Tick { elapsed_us, .. } => {
+                    world_loop.tick(Duration::from_micros(*elapsed_us));
+                    world_loop.settle().await;
+                    report.ticks += 1;
+                }
+            }
+        }
+        // sessions still open when the recording stopped
+        for (id, (_, mut frames)) in sessions {
+            let sent = report.sent.entry(id).or_default();
+            while let Ok(frame) = frames.try_recv() {
+                sent.push(frame);
+            }
+        }
+        Ok(report)
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,506 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use bevy_ecs::prelude::*;
+use rhai::{CallFnOptions, Dynamic, Engine, EvalAltResult, ParseError, Scope, AST, FLOAT, INT};
+
+use crate::ecs::{self, Creature, EntityGuid, Movement, ScriptTimer};
+use crate::{ChatChannel, Guid, Position, ServerChat};
+
+/// How often the script directory is checked for changed files.
+pub const SCRIPT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the `on_update` handler of the creatures runs.
+pub const SCRIPT_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+/// The speed of the creatures walking with `move_to`, unless the script
+/// gives one, in units per second.
+const WALK_SPEED: f32 = 2.5;
+/// How many operations a handler can run before it is stopped, so that a
+/// script looping forever doesn't hold the tick up.
+const MAX_OPERATIONS: u64 = 100_000;
+
+/// The functions of a creature script that the world loop calls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NpcHandler {
+    /// `fn on_spawn()`, when the creature spawns, and again when its script is
+    /// reloaded.
+    Spawn,
+    /// `fn on_update()`, every [`SCRIPT_UPDATE_INTERVAL`].
+    Update,
+}
+
+impl NpcHandler {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Spawn => "on_spawn",
+            Self::Update => "on_update",
+        }
+    }
+}
+
+/// What a handler asked its creature to do, once it returned.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NpcAction {
+    /// Says a line to the players around.
+    Say(String),
+    /// Walks to a destination, at `speed` units per second.
+    MoveTo { destination: Position, speed: f32 },
+}
+
+/// The creature a handler runs for, which the scripts know as `this`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Npc {
+    pub creature_id: u32,
+    pub position: Position,
+    actions: Vec<NpcAction>,
+}
+
+impl Npc {
+    pub fn new(creature_id: u32, position: Position) -> Self {
+        Self {
+            creature_id,
+            position,
+            actions: Vec::new(),
+        }
+    }
+}
+
+/// Returns the engine running the scripts, with the API of the creatures.
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.on_print(|text| tracing::info!("Script: {text}"));
+    engine
+        .register_type_with_name::<Npc>("Npc")
+        .register_get("creature_id", |npc: &mut Npc| INT::from(npc.creature_id))
+        .register_get("x", |npc: &mut Npc| FLOAT::from(npc.position.x))
+        .register_get("y", |npc: &mut Npc| FLOAT::from(npc.position.y))
+        .register_get("z", |npc: &mut Npc| FLOAT::from(npc.position.z))
+        .register_fn("say", |npc: &mut Npc, text: &str| {
+            npc.actions.push(NpcAction::Say(text.to_string()));
+        })
+        .register_fn("move_to", |npc: &mut Npc, x: FLOAT, y: FLOAT, z: FLOAT| {
+            npc.actions.push(move_to(x, y, z, WALK_SPEED));
+        })
+        .register_fn(
+            "move_to",
+            |npc: &mut Npc, x: FLOAT, y: FLOAT, z: FLOAT, speed: FLOAT| {
+                npc.actions.push(move_to(x, y, z, speed as f32));
+            },
+        );
+    engine
+}
+
+fn move_to(x: FLOAT, y: FLOAT, z: FLOAT, speed: f32) -> NpcAction {
+    NpcAction::MoveTo {
+        destination: Position {
+            x: x as f32,
+            y: y as f32,
+            z: z as f32,
+        },
+        speed,
+    }
+}
+
+/// The scripts loaded at once from a script directory.
+#[derive(Debug)]
+pub struct Scripts {
+    engine: Engine,
+    /// The script of each creature, by creature id.
+    creatures: HashMap<u32, AST>,
+}
+
+impl Default for Scripts {
+    fn default() -> Self {
+        Self {
+            engine: engine(),
+            creatures: HashMap::new(),
+        }
+    }
+}
+
+impl Scripts {
+    /// Compiles the scripts of a script directory.
+    ///
+    /// The handlers of a creature are the functions of
+    /// `creatures/<creature id>.rhai`, in [Rhai](https://rhai.rs). They take
+    /// no arguments, and tell the creature what to do through `this`, like
+    /// `this.say("Hello")` or `this.move_to(x, y, z)`. Only the handlers are
+    /// run, never the statements outside of them.
+    pub fn load(dir: &Path) -> ScriptResult<Self> {
+        let mut scripts = Self::default();
+        for file in list_files(dir)? {
+            let creature_id = file
+                .path
+                .file_stem()
+                .and_then(|stem| stem.to_str()?.parse().ok())
+                .ok_or_else(|| ScriptError::InvalidFileName(file.path.clone()))?;
+            let text = std::fs::read_to_string(&file.path).map_err(|error| ScriptError::Io {
+                path: file.path.clone(),
+                error,
+            })?;
+            let ast = scripts
+                .engine
+                .compile(text)
+                .map_err(|error| ScriptError::Parse {
+                    path: file.path,
+                    error,
+                })?;
+            scripts.creatures.insert(creature_id, ast);
+        }
+        Ok(scripts)
+    }
+
+    /// Returns the number of creatures that have a script.
+    pub fn len(&self) -> usize {
+        self.creatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.creatures.is_empty()
+    }
+
+    pub fn has_creature(&self, creature_id: u32) -> bool {
+        self.creatures.contains_key(&creature_id)
+    }
+
+    /// Runs a handler of the script of a creature, and returns what it asked
+    /// the creature to do. Creatures without a script or without the handler
+    /// do nothing.
+    pub fn run(&self, handler: NpcHandler, npc: Npc) -> ScriptResult<Vec<NpcAction>> {
+        let creature_id = npc.creature_id;
+        let ast = match self.creatures.get(&creature_id) {
+            Some(ast) => ast,
+            None => return Ok(Vec::new()),
+        };
+        let name = handler.name();
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == name && function.params.is_empty())
+        {
+            return Ok(Vec::new());
+        }
+        let mut this = Dynamic::from(npc);
+        let options = CallFnOptions::new()
+            .eval_ast(false)
+            .bind_this_ptr(&mut this);
+        // what the handlers return is ignored
+        let _: Dynamic = self
+            .engine
+            .call_fn_with_options(options, &mut Scope::new(), ast, name, ())
+            .map_err(|error| ScriptError::Run {
+                creature_id,
+                handler,
+                error,
+            })?;
+        // the handlers can assign something else to `this`
+        Ok(this
+            .try_cast::<Npc>()
+            .map(|npc| npc.actions)
+            .unwrap_or_default())
+    }
+
+    /// Runs the handlers due in a world: `on_spawn` for the creatures that
+    /// haven't run it yet, and `on_update` for those whose
+    /// [`SCRIPT_UPDATE_INTERVAL`] elapsed. The failures are logged.
+    pub fn run_creatures(&self, world: &mut World, elapsed: Duration) {
+        let mut runs = Vec::new();
+        let mut query = world.query_filtered::<(
+            Entity,
+            &EntityGuid,
+            &Creature,
+            &Position,
+            Option<&mut ScriptTimer>,
+        ), Without<ecs::Despawn>>();
+        for (entity, guid, creature, position, timer) in query.iter_mut(world) {
+            if !self.has_creature(creature.0) {
+                continue;
+            }
+            let handler = match timer {
+                Some(mut timer) => {
+                    timer.0 += elapsed;
+                    if timer.0 < SCRIPT_UPDATE_INTERVAL {
+                        continue;
+                    }
+                    timer.0 = Duration::ZERO;
+                    NpcHandler::Update
+                }
+                None => NpcHandler::Spawn,
+            };
+            runs.push((entity, guid.0, handler, Npc::new(creature.0, *position)));
+        }
+        for (entity, guid, handler, npc) in runs {
+            if handler == NpcHandler::Spawn {
+                world.entity_mut(entity).insert(ScriptTimer::default());
+            }
+            match self.run(handler, npc) {
+                Ok(actions) => act(world, entity, guid, actions),
+                Err(error) => tracing::warn!("{error}"),
+            }
+        }
+    }
+}
+
+/// Does what a handler asked a creature to do.
+fn act(world: &mut World, entity: Entity, guid: Guid, actions: Vec<NpcAction>) {
+    let creature_id = world
+        .get::<Creature>(entity)
+        .map_or(0, |creature| creature.0);
+    for action in actions {
+        match action {
+            NpcAction::Say(message) => {
+                let chat = ServerChat {
+                    channel: ChatChannel::Say,
+                    // the names are in the client language files, which
+                    // aren't read
+                    sender: format!("Creature {creature_id}"),
+                    message,
+                };
+                ecs::broadcast(world, guid, &chat);
+            }
+            NpcAction::MoveTo { destination, speed } => {
+                world
+                    .entity_mut(entity)
+                    .insert(Movement { destination, speed });
+            }
+        }
+    }
+}
+
+/// A script file, and what tells when it changed.
+#[derive(Debug, Clone, PartialEq)]
+struct ScriptFile {
+    path: PathBuf,
+    modified: SystemTime,
+    len: u64,
+}
+
+/// Lists the scripts of a script directory, sorted by path. There are none
+/// if the directory doesn't exist, so that a sandbox runs without scripts.
+fn list_files(dir: &Path) -> ScriptResult<Vec<ScriptFile>> {
+    let dir = dir.join("creatures");
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |error| ScriptError::Io { path, error }
+    };
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(&dir).map_err(io_error(&dir))? {
+        let path = entry.map_err(io_error(&dir))?.path();
+        if path.extension().is_none_or(|extension| extension != "rhai") {
+            continue;
+        }
+        let metadata = std::fs::metadata(&path).map_err(io_error(&path))?;
+        let modified = metadata.modified().map_err(io_error(&path))?;
+        files.push(ScriptFile {
+            path,
+            modified,
+            len: metadata.len(),
+        });
+    }
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(files)
+}
+
+/// The scripts of a world server, which are reloaded when their files
+/// change.
+///
+/// Like the [`DataStore`](crate::DataStore), readers get a snapshot of the
+/// scripts. The [`WorldLoop`](crate::WorldLoop) takes the reloaded ones at
+/// the start of a tick, so a handler never runs half of a change.
+#[derive(Debug, Default)]
+pub struct ScriptStore {
+    dir: Option<PathBuf>,
+    scripts: RwLock<Arc<Scripts>>,
+    /// The files the scripts were last loaded from, or failed to.
+    files: Mutex<Option<Vec<ScriptFile>>>,
+}
+
+impl ScriptStore {
+    /// Returns a store without scripts, which reloads nothing.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the scripts of a script directory.
+    pub fn load(dir: impl Into<PathBuf>) -> ScriptResult<Self> {
+        let dir = dir.into();
+        let files = list_files(&dir)?;
+        let scripts = Scripts::load(&dir)?;
+        Ok(Self {
+            dir: Some(dir),
+            scripts: RwLock::new(Arc::new(scripts)),
+            files: Mutex::new(Some(files)),
+        })
+    }
+
+    pub fn scripts(&self) -> Arc<Scripts> {
+        self.scripts.read().unwrap().clone()
+    }
+
+    /// Returns whether a script file was added, changed or removed since the
+    /// scripts were last reloaded.
+    pub fn changed(&self) -> bool {
+        let files = self.dir.as_deref().and_then(|dir| list_files(dir).ok());
+        files.is_some() && *self.files.lock().unwrap() != files
+    }
+
+    /// Compiles the scripts again from the script directory, and returns
+    /// them.
+    ///
+    /// The current scripts are kept if any of them fails to compile. Either
+    /// way, [`ScriptStore::changed`] is false until the files change again.
+    pub fn reload(&self) -> ScriptResult<Arc<Scripts>> {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return Ok(self.scripts()),
+        };
+        // listed first, so that files written while compiling are reloaded
+        // again
+        *self.files.lock().unwrap() = list_files(dir).ok();
+        let scripts = Arc::new(Scripts::load(dir)?);
+        *self.scripts.write().unwrap() = scripts.clone();
+        Ok(scripts)
+    }
+}
+
+#[derive(Debug)]
+pub enum ScriptError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// A script file isn't named by the id of its creature.
+    InvalidFileName(PathBuf),
+    Parse {
+        path: PathBuf,
+        error: ParseError,
+    },
+    /// A handler failed, or ran too many operations.
+    Run {
+        creature_id: u32,
+        handler: NpcHandler,
+        error: Box<EvalAltResult>,
+    },
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "failed to read {}: {error}", path.display()),
+            Self::InvalidFileName(path) => {
+                write!(f, "{} isn't named by a creature id", path.display())
+            }
+            Self::Parse { path, error } => write!(f, "invalid {}: {error}", path.display()),
+            Self::Run {
+                creature_id,
+                handler,
+                error,
+            } => write!(
+                f,
+                "the {} handler of creature {creature_id} failed: {error}",
+                handler.name()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            Self::InvalidFileName(_) => None,
+            Self::Parse { error, .. } => Some(error),
+            Self::Run { error, .. } => Some(error),
+        }
+    }
+}
+
+pub type ScriptResult<T = ()> = Result<T, ScriptError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_and_reload() {
+        let dir = std::env::temp_dir().join(format!("ws_world_scripts_{}", std::process::id()));
+        let creatures = dir.join("creatures");
+        std::fs::create_dir_all(&creatures).unwrap();
+        std::fs::write(
+            creatures.join("20.rhai"),
+            r#"
+                fn on_spawn() {
+                    this.say("Hello");
+                }
+
+                fn on_update() {
+                    this.move_to(this.x + 1.0, this.y, this.z);
+                }
+            "#,
+        )
+        .unwrap();
+
+        let store = ScriptStore::load(&dir).unwrap();
+        assert!(!store.changed());
+        let scripts = store.scripts();
+        assert_eq!(scripts.len(), 1);
+        let npc = Npc::new(20, Position::default());
+        assert_eq!(
+            scripts.run(NpcHandler::Spawn, npc.clone()).unwrap(),
+            [NpcAction::Say("Hello".to_string())]
+        );
+        assert_eq!(
+            scripts.run(NpcHandler::Update, npc.clone()).unwrap(),
+            [NpcAction::MoveTo {
+                destination: Position {
+                    x: 1.0,
+                    y: 0.0,
+                    z: 0.0
+                },
+                speed: WALK_SPEED,
+            }]
+        );
+        // creatures without a script do nothing
+        let stranger = Npc::new(21, Position::default());
+        assert_eq!(scripts.run(NpcHandler::Spawn, stranger).unwrap(), []);
+
+        // a broken script keeps the scripts loaded before, until it changes
+        std::fs::write(creatures.join("20.rhai"), "fn on_spawn() {").unwrap();
+        assert!(store.changed());
+        assert!(matches!(store.reload(), Err(ScriptError::Parse { .. })));
+        assert!(!store.changed());
+        assert!(Arc::ptr_eq(&store.scripts(), &scripts));
+
+        // as do scripts that aren't named by a creature id
+        std::fs::write(creatures.join("20.rhai"), "fn on_update() { loop {} }").unwrap();
+        std::fs::write(creatures.join("guard.rhai"), "").unwrap();
+        assert!(store.changed());
+        assert!(matches!(
+            store.reload(),
+            Err(ScriptError::InvalidFileName(_))
+        ));
+
+        // handlers running for too long are stopped
+        std::fs::remove_file(creatures.join("guard.rhai")).unwrap();
+        assert!(store.changed());
+        let reloaded = store.reload().unwrap();
+        assert_eq!(reloaded.run(NpcHandler::Spawn, npc.clone()).unwrap(), []);
+        assert!(matches!(
+            reloaded.run(NpcHandler::Update, npc),
+            Err(ScriptError::Run {
+                creature_id: 20,
+                handler: NpcHandler::Update,
+                ..
+            })
+        ));
+        // snapshots taken before the reload don't change
+        assert!(scripts.has_creature(20));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,40 @@
+use super::*;
+
+impl WorldServer {
+    pub(super) fn chat(&self, session: &Session, chat: ClientChat) -> NetResult {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return not_logged_in(session),
+        };
+
+        let context = CommandContext {
+            server: self,
+            session,
+        };
+        let reply = match self.commands.execute(&context, &chat.message) {
+            Some(Ok(reply)) => reply,
+            Some(Err(CommandError::Net(error))) => return Err(error),
+            Some(Err(error)) => self.text(session, &error.text()),
+            None => {
+                // the world loop knows who is around to hear it
+                self.queue(WorldEvent::Say {
+                    guid: player.guid,
+                    chat: ServerChat {
+                        channel: chat.channel,
+                        sender: player.character.name,
+                        message: chat.message,
+                    },
+                });
+                return Ok(());
+            }
+        };
+        if reply.is_empty() {
+            return Ok(());
+        }
+        session.send(&ServerChat {
+            channel: ChatChannel::System,
+            sender: String::new(),
+            message: reply,
+        })
+    }
+}
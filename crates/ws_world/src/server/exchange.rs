@@ -0,0 +1,165 @@
+use super::*;
+
+impl WorldServer {
+    /// Returns the commodity exchange of the server along with the player of
+    /// the session. Sessions outside of the world are closed, and servers
+    /// without an exchange refuse its requests.
+    fn trader(&self, session: &Session) -> Result<(&CommodityExchange, Player), NetResult> {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return Err(not_logged_in(session)),
+        };
+        match &self.exchange {
+            Some(exchange) => Ok((exchange, player)),
+            None => Err(send_commodity_result(
+                session,
+                Err(CommodityResult::Failed.into()),
+            )),
+        }
+    }
+
+    /// Answers even without an exchange, so that its window opens empty.
+    pub(super) fn commodity_info(
+        &self,
+        session: &Session,
+        request: ClientCommodityInfoRequest,
+    ) -> NetResult {
+        if self.player(session).is_none() {
+            return not_logged_in(session);
+        }
+        let info = match &self.exchange {
+            Some(exchange) => exchange.info(request.template_id),
+            None => OrderBook::new().info(request.template_id),
+        };
+        session.send(&info)
+    }
+
+    pub(super) async fn commodity_order_post(
+        &self,
+        session: &Session,
+        post: ClientCommodityOrderPost,
+    ) -> NetResult {
+        let (exchange, player) = match self.trader(session) {
+            Ok(trader) => trader,
+            Err(reply) => return reply,
+        };
+        let template = match self.data.tables().items.get(&post.template_id) {
+            Some(template) => template.clone(),
+            None => {
+                return send_commodity_result(session, Err(CommodityResult::InvalidOrder.into()))
+            }
+        };
+
+        // the items sold leave the inventory before the order is placed, so
+        // that they can't be sold twice
+        if post.side == CommoditySide::Sell {
+            let result = self.update_player(session, |player| {
+                player.inventory.take(post.template_id, post.count)
+            });
+            match result {
+                Some(Ok(updates)) => session.send(&ServerItemUpdates::new(updates))?,
+                Some(Err(InventoryError::NotEnough { .. })) => {
+                    return send_commodity_result(
+                        session,
+                        Err(CommodityResult::NotEnoughItems.into()),
+                    )
+                }
+                Some(Err(_)) => {
+                    return send_commodity_result(
+                        session,
+                        Err(CommodityResult::InvalidOrder.into()),
+                    )
+                }
+                None => return not_logged_in(session),
+            }
+        }
+
+        match exchange.post(player.character.id, &post).await {
+            Ok(order) => send_commodity_result(session, Ok(order.id)),
+            Err(error) => {
+                if post.side == CommoditySide::Sell {
+                    let result = self.update_player(session, |player| {
+                        player.inventory.add(
+                            &template,
+                            post.count,
+                            ItemAddReason::None,
+                            &self.guids,
+                        )
+                    });
+                    if let Some(Ok(updates)) = &result {
+                        session.send(&ServerItemUpdates::new(updates.clone()))?;
+                    } else {
+                        tracing::error!(
+                            "Failed to give back {} of item {} to {}",
+                            post.count,
+                            post.template_id,
+                            player.character.name
+                        );
+                    }
+                }
+                send_commodity_result(session, Err(error))
+            }
+        }
+    }
+
+    pub(super) async fn commodity_order_cancel(
+        &self,
+        session: &Session,
+        cancel: ClientCommodityOrderCancel,
+    ) -> NetResult {
+        let (exchange, player) = match self.trader(session) {
+            Ok(trader) => trader,
+            Err(reply) => return reply,
+        };
+        let order = match exchange.cancel(player.character.id, cancel.order_id).await {
+            Ok(order) => order,
+            Err(error) => return send_commodity_result(session, Err(error)),
+        };
+        // the inventory may be full by now, the items left are mailed back
+        if let (CommoditySide::Sell, Some(mail)) = (order.side, &self.mail) {
+            let text = |id: TextId| self.character_text(order.character_id, &id.into());
+            let result = mail
+                .send_system(
+                    &text(TextId::ExchangeSender),
+                    order.character_id,
+                    &text(TextId::OrderCancelled),
+                    &text(TextId::OrderItemsAttached),
+                    vec![(order.template_id, order.count)],
+                )
+                .await;
+            if let Err(error) = result {
+                tracing::error!("Failed to mail back the items of order {order:?}: {error}");
+            }
+        }
+        send_commodity_result(session, Ok(order.id))
+    }
+
+    pub(super) fn commodity_orders(&self, session: &Session) -> NetResult {
+        let (exchange, player) = match self.trader(session) {
+            Ok(trader) => trader,
+            Err(reply) => return reply,
+        };
+        let orders: Vec<_> = exchange
+            .orders_of(player.character.id)
+            .iter()
+            .map(Order::data)
+            .collect();
+        session.send(&ServerCommodityOrders {
+            count: orders.len() as u16,
+            orders,
+        })
+    }
+}
+
+/// Tells the player whether an order was placed or cancelled.
+fn send_commodity_result(session: &Session, result: Result<u64, ExchangeError>) -> NetResult {
+    let (result, order_id) = match result {
+        Ok(order_id) => (CommodityResult::Ok, order_id),
+        Err(ExchangeError::Refused(result)) => (result, 0),
+        Err(ExchangeError::Db(error)) => {
+            tracing::error!("Failed to update the commodity orders: {error}");
+            (CommodityResult::Failed, 0)
+        }
+    };
+    session.send(&ServerCommodityResult { result, order_id })
+}
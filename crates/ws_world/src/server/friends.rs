@@ -0,0 +1,70 @@
+use super::*;
+
+impl WorldServer {
+    /// Returns the friends of the server along with the character of the
+    /// session. Sessions outside of the world are closed, and servers without
+    /// a database refuse friend requests.
+    fn friend_list(&self, session: &Session) -> Result<(&FriendManager, u64), NetResult> {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return Err(not_logged_in(session)),
+        };
+        match &self.friends {
+            Some(friends) => Ok((friends, player.character.id)),
+            None => Err(send_friend_result(
+                session,
+                Err(FriendResult::Failed.into()),
+            )),
+        }
+    }
+
+    pub(super) async fn friend_add(&self, session: &Session, add: ClientFriendAdd) -> NetResult {
+        let (friends, character_id) = match self.friend_list(session) {
+            Ok(friends) => friends,
+            Err(reply) => return reply,
+        };
+        let result = friends.add(character_id, &add.name).await;
+        send_friend_result(session, result)
+    }
+
+    pub(super) async fn friend_remove(
+        &self,
+        session: &Session,
+        remove: ClientFriendRemove,
+    ) -> NetResult {
+        let (friends, character_id) = match self.friend_list(session) {
+            Ok(friends) => friends,
+            Err(reply) => return reply,
+        };
+        let result = friends.remove(character_id, remove.character_id).await;
+        send_friend_result(session, result)
+    }
+
+    pub(super) async fn friend_set_note(
+        &self,
+        session: &Session,
+        set_note: ClientFriendSetNote,
+    ) -> NetResult {
+        let (friends, character_id) = match self.friend_list(session) {
+            Ok(friends) => friends,
+            Err(reply) => return reply,
+        };
+        let result = friends
+            .set_note(character_id, set_note.character_id, &set_note.note)
+            .await;
+        send_friend_result(session, result)
+    }
+}
+
+/// Tells the player whether a friend request was done.
+fn send_friend_result(session: &Session, result: Result<(), FriendError>) -> NetResult {
+    let result = match result {
+        Ok(()) => FriendResult::Ok,
+        Err(FriendError::Refused(result)) => result,
+        Err(FriendError::Db(error)) => {
+            tracing::error!("Failed to update the friends: {error}");
+            FriendResult::Failed
+        }
+    };
+    session.send(&ServerFriendResult { result })
+}
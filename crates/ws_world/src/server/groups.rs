@@ -0,0 +1,141 @@
+use super::*;
+
+impl WorldServer {
+    /// Tells the world loop the group the players are in now.
+    fn queue_group(&self, guids: &[Guid], group_id: Option<u64>) {
+        for guid in guids {
+            self.queue(WorldEvent::SetGroup {
+                guid: *guid,
+                group_id,
+            });
+        }
+    }
+
+    pub(super) fn queue_group_leave(&self, guid: Guid, leave: &GroupLeave) {
+        match leave {
+            GroupLeave::Left(_) => self.queue_group(&[guid], None),
+            GroupLeave::Disbanded { members, .. } => self.queue_group(members, None),
+        }
+    }
+
+    pub(super) fn group_invite(&self, session: &Session, invite: ClientGroupInvite) -> NetResult {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return not_logged_in(session),
+        };
+
+        let mut groups = self.groups.lock().unwrap();
+        let result = match groups.invite(player.guid, &invite.name) {
+            Ok(invitee) => {
+                send_to_member(
+                    &groups,
+                    invitee,
+                    &ServerGroupInvite {
+                        inviter: player.guid,
+                        inviter_name: player.character.name,
+                    },
+                );
+                GroupInviteResult::Sent
+            }
+            Err(result) => result,
+        };
+        session.send(&ServerGroupInviteResult {
+            name: invite.name,
+            result,
+        })
+    }
+
+    pub(super) fn group_invite_response(
+        &self,
+        session: &Session,
+        response: ClientGroupInviteResponse,
+    ) -> NetResult {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return not_logged_in(session),
+        };
+
+        let mut groups = self.groups.lock().unwrap();
+        match groups.respond(player.guid, response.accept) {
+            Some((_, Ok(group_id))) => {
+                let group = groups.group_of(player.guid).expect("the group was joined");
+                let joined = ServerGroupJoined {
+                    group_id,
+                    leader: group.leader,
+                    count: group.members.len() as u8,
+                    members: groups.members(group),
+                };
+                for guid in &group.members {
+                    send_to_member(&groups, *guid, &joined);
+                }
+                self.queue_group(&group.members, Some(group_id));
+            }
+            Some((inviter, Err(result))) => send_to_member(
+                &groups,
+                inviter,
+                &ServerGroupInviteResult {
+                    name: player.character.name,
+                    result,
+                },
+            ),
+            // the invite was cancelled when the inviter left the world
+            None => {}
+        }
+        Ok(())
+    }
+
+    pub(super) fn group_leave(&self, session: &Session) -> NetResult {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return not_logged_in(session),
+        };
+
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(leave) = groups.leave(player.guid) {
+            self.queue_group_leave(player.guid, &leave);
+            send_group_leave(&groups, player.guid, leave, GroupLeaveReason::Left);
+        }
+        Ok(())
+    }
+}
+
+/// Sends a message to a player in the world. Players whose session just closed
+/// are skipped, they are removed from the groups once it is handled.
+pub(super) fn send_to_member<T: Message + WriteValue + fmt::Debug>(
+    groups: &GroupManager,
+    guid: Guid,
+    message: &T,
+) {
+    if let Some(session) = groups.handle(guid) {
+        let _ = session.send(message);
+    }
+}
+
+/// Tells the members of a group that a player left it.
+pub(super) fn send_group_leave(
+    groups: &GroupManager,
+    guid: Guid,
+    leave: GroupLeave,
+    reason: GroupLeaveReason,
+) {
+    match leave {
+        GroupLeave::Left(group) => {
+            let left = ServerGroupMemberLeft {
+                group_id: group.id,
+                member: guid,
+                leader: group.leader,
+                reason,
+            };
+            for member in &group.members {
+                send_to_member(groups, *member, &left);
+            }
+            // the group is gone for the player who left
+            send_to_member(groups, guid, &ServerGroupDisbanded { group_id: group.id });
+        }
+        GroupLeave::Disbanded { group_id, members } => {
+            for member in &members {
+                send_to_member(groups, *member, &ServerGroupDisbanded { group_id });
+            }
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use super::*;
+
+impl WorldServer {
+    /// Returns the guilds of the server along with the character of the
+    /// session. Sessions outside of the world are closed, and servers without
+    /// a database refuse guild requests.
+    fn guild_member(&self, session: &Session) -> Result<(&GuildManager, u64), NetResult> {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return Err(not_logged_in(session)),
+        };
+        match &self.guilds {
+            Some(guilds) => Ok((guilds, player.character.id)),
+            None => Err(send_guild_result(session, Err(GuildResult::Failed.into()))),
+        }
+    }
+
+    pub(super) async fn guild_create(
+        &self,
+        session: &Session,
+        create: ClientGuildCreate,
+    ) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        let tables = self.data.tables();
+        let result = guilds
+            .create(character_id, &create.name, &tables.blocked_names)
+            .await;
+        send_guild_result(session, result)
+    }
+
+    pub(super) async fn guild_roster(&self, session: &Session) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        match guilds.roster(character_id).await {
+            Ok(roster) => session.send(&roster),
+            Err(error) => send_guild_result(session, Err(error)),
+        }
+    }
+
+    pub(super) async fn guild_invite(
+        &self,
+        session: &Session,
+        invite: ClientGuildInvite,
+    ) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        let result = guilds.invite(character_id, &invite.name).await;
+        send_guild_result(session, result)
+    }
+
+    pub(super) async fn guild_invite_response(
+        &self,
+        session: &Session,
+        response: ClientGuildInviteResponse,
+    ) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        match guilds.respond(character_id, response.accept).await {
+            // the new member already heard of it along with the guild
+            Ok(()) => Ok(()),
+            Err(error) => send_guild_result(session, Err(error)),
+        }
+    }
+
+    pub(super) async fn guild_leave(&self, session: &Session) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        let result = guilds.leave(character_id).await;
+        send_guild_result(session, result)
+    }
+
+    pub(super) async fn guild_kick(&self, session: &Session, kick: ClientGuildKick) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        let result = guilds.kick(character_id, &kick.name).await;
+        send_guild_result(session, result)
+    }
+
+    pub(super) async fn guild_set_rank(
+        &self,
+        session: &Session,
+        set_rank: ClientGuildSetRank,
+    ) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        let result = guilds
+            .set_rank(character_id, &set_rank.name, set_rank.rank)
+            .await;
+        send_guild_result(session, result)
+    }
+
+    pub(super) async fn guild_rename_rank(
+        &self,
+        session: &Session,
+        rename: ClientGuildRenameRank,
+    ) -> NetResult {
+        let (guilds, character_id) = match self.guild_member(session) {
+            Ok(member) => member,
+            Err(reply) => return reply,
+        };
+        let result = guilds
+            .rename_rank(character_id, rename.rank, &rename.name)
+            .await;
+        send_guild_result(session, result)
+    }
+}
+
+/// Tells the sender of a guild request whether it was done.
+fn send_guild_result(session: &Session, result: Result<(), GuildError>) -> NetResult {
+    let result = match result {
+        Ok(()) => GuildResult::Ok,
+        Err(GuildError::Refused(result)) => result,
+        Err(GuildError::Db(error)) => {
+            tracing::error!("Failed to update a guild: {error}");
+            GuildResult::Failed
+        }
+    };
+    session.send(&ServerGuildResult { result })
+}
@@ -0,0 +1,158 @@
+use super::*;
+
+impl WorldServer {
+    /// Returns the mail of the server along with the player of the session.
+    /// Sessions outside of the world are closed, and servers without a
+    /// database refuse mail requests.
+    fn mailbox(&self, session: &Session) -> Result<(&MailManager, Player), NetResult> {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return Err(not_logged_in(session)),
+        };
+        match &self.mail {
+            Some(mail) => Ok((mail, player)),
+            None => Err(send_mail_result(session, Err(MailResult::Failed.into()))),
+        }
+    }
+
+    pub(super) async fn mail_send(&self, session: &Session, send: ClientMailSend) -> NetResult {
+        let (mail, player) = match self.mailbox(session) {
+            Ok(mailbox) => mailbox,
+            Err(reply) => return reply,
+        };
+        let unique: HashSet<_> = send.items.iter().collect();
+        if unique.len() != send.items.len() {
+            return send_mail_result(session, Err(MailResult::InvalidMail.into()));
+        }
+        let attachments: Option<Vec<_>> = send
+            .items
+            .iter()
+            .map(|guid| {
+                let item = player.inventory.get(*guid)?;
+                Some((item.template_id, item.count))
+            })
+            .collect();
+        let attachments = match attachments {
+            Some(attachments) => attachments,
+            None => return send_mail_result(session, Err(MailResult::ItemNotFound.into())),
+        };
+
+        let result = mail
+            .send(
+                player.character.id,
+                &player.character.name,
+                &send,
+                attachments,
+            )
+            .await;
+        // the attached items leave the inventory once they are in the mail
+        if result.is_ok() && !send.items.is_empty() {
+            let updates = self.update_player(session, |player| {
+                send.items
+                    .iter()
+                    .filter_map(|guid| player.inventory.remove(*guid).ok())
+                    .collect()
+            });
+            send_item_updates(session, updates.map(Ok))?;
+        }
+        send_mail_result(session, result)
+    }
+
+    pub(super) async fn mail_read(&self, session: &Session, read: ClientMailRead) -> NetResult {
+        let (mail, player) = match self.mailbox(session) {
+            Ok(mailbox) => mailbox,
+            Err(reply) => return reply,
+        };
+        match mail.read(player.character.id, read.mail_id).await {
+            Ok(()) => Ok(()),
+            Err(error) => send_mail_result(session, Err(error)),
+        }
+    }
+
+    pub(super) async fn mail_take_attachments(
+        &self,
+        session: &Session,
+        take: ClientMailTakeAttachments,
+    ) -> NetResult {
+        let (mail, player) = match self.mailbox(session) {
+            Ok(mailbox) => mailbox,
+            Err(reply) => return reply,
+        };
+        let attachments = match mail.attachments(player.character.id, take.mail_id).await {
+            Ok(attachments) => attachments,
+            Err(error) => return send_mail_result(session, Err(error)),
+        };
+        let tables = self.data.tables();
+        let templates: Option<Vec<_>> = attachments
+            .iter()
+            .map(|attachment| {
+                let template = tables.items.get(&attachment.template_id)?;
+                Some((template, attachment.count))
+            })
+            .collect();
+        let templates = match templates {
+            Some(templates) => templates,
+            None => {
+                tracing::error!("Mail {} has items that don't exist anymore", take.mail_id);
+                return send_mail_result(session, Err(MailResult::Failed.into()));
+            }
+        };
+
+        // the items are added all at once, or not at all
+        let result = self.update_player(session, |player| {
+            let mut inventory = player.inventory.clone();
+            let mut updates = Vec::new();
+            for (template, count) in templates {
+                updates.extend(inventory.add(template, count, ItemAddReason::Mail, &self.guids)?);
+            }
+            player.inventory = inventory;
+            Ok(updates)
+        });
+        match result {
+            Some(Ok(updates)) => {
+                if let Err(error) = mail.remove_attachments(take.mail_id).await {
+                    return send_mail_result(session, Err(error.into()));
+                }
+                session.send(&ServerItemUpdates::new(updates))?;
+                send_mail_result(session, Ok(()))
+            }
+            Some(Err(InventoryError::Full)) => {
+                send_mail_result(session, Err(MailResult::InventoryFull.into()))
+            }
+            Some(Err(error)) => {
+                tracing::error!("Failed to add the attachments of a mail: {error}");
+                send_mail_result(session, Err(MailResult::Failed.into()))
+            }
+            None => not_logged_in(session),
+        }
+    }
+
+    pub(super) async fn mail_delete(
+        &self,
+        session: &Session,
+        delete: ClientMailDelete,
+    ) -> NetResult {
+        let (mail, player) = match self.mailbox(session) {
+            Ok(mailbox) => mailbox,
+            Err(reply) => return reply,
+        };
+        match mail.delete(player.character.id, delete.mail_id).await {
+            // the mail leaving the mailbox tells it was deleted
+            Ok(()) => Ok(()),
+            Err(error) => send_mail_result(session, Err(error)),
+        }
+    }
+}
+
+/// Tells the sender of a mail request whether it was done.
+fn send_mail_result(session: &Session, result: Result<(), MailError>) -> NetResult {
+    let result = match result {
+        Ok(()) => MailResult::Ok,
+        Err(MailError::Refused(result)) => result,
+        Err(MailError::Db(error)) => {
+            tracing::error!("Failed to update the mail: {error}");
+            MailResult::Failed
+        }
+    };
+    session.send(&ServerMailResult { result })
+}
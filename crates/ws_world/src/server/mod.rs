@@ -0,0 +1,2383 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use uuid::Uuid;
+use ws_bitpack::{BitPackReader, BitPackWriter, WriteValue};
+use ws_db::{Database, DbError, NewCharacter};
+use ws_messages::{AnyMessage, JsonCodecs, Message};
+use ws_net::{
+    phase, Handler, HandlerMap, NetError, NetResult, RateLimit, RateLimits, Session, SessionId,
+    SessionPolicy, SessionState, TrafficFilter,
+};
+use ws_protocol::{Arc4Encryption, TicketKey};
+
+use crate::*;
+
+mod chat;
+mod exchange;
+mod friends;
+mod groups;
+mod guilds;
+mod mail;
+
+use groups::{send_group_leave, send_to_member};
+
+/// The account a session logged in with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub id: u32,
+    pub name: String,
+}
+
+/// A character that a session entered the world with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Player {
+    pub guid: Guid,
+    pub character: Character,
+    pub inventory: Inventory,
+}
+
+impl Player {
+    /// Returns the player as shown to the other members of its group.
+    pub fn group_member(&self) -> GroupMember {
+        GroupMember {
+            guid: self.guid,
+            name: self.character.name.clone(),
+            class: self.character.class,
+            level: self.character.level,
+            world_id: self.character.world_id,
+        }
+    }
+}
+
+/// How a world server behaves, set from the configuration by the binary.
+#[derive(Debug, Clone, Default)]
+pub struct WorldSettings {
+    /// The realm of the GUIDs allocated by the server.
+    pub realm_id: u16,
+    /// Whether the frames following the login are encrypted, keyed with the
+    /// session GUID of the client.
+    pub encryption: bool,
+    /// Shown in the chat box when entering the world, unless empty.
+    pub motd: String,
+    /// The locale of the texts of the players who didn't choose one,
+    /// [`DEFAULT_LOCALE`] if empty.
+    pub locale: String,
+}
+
+/// The state shared by all sessions of a world server.
+#[derive(Debug)]
+pub struct WorldServer {
+    settings: WorldSettings,
+    data: DataStore,
+    scripts: ScriptStore,
+    database: Option<Database>,
+    sessions: Mutex<HashMap<SessionId, Session>>,
+    /// The keys of the sessions recorded in the database, which the
+    /// administration tools list and close them by.
+    session_keys: Mutex<HashMap<SessionId, Uuid>>,
+    accounts: Mutex<HashMap<SessionId, Account>>,
+    /// The locales that players chose for the server texts.
+    locales: Mutex<HashMap<SessionId, String>>,
+    players: Mutex<HashMap<SessionId, Player>>,
+    guids: GuidAllocator,
+    commands: CommandRegistry,
+    events: Mutex<Vec<WorldEvent>>,
+    groups: Mutex<GroupManager>,
+    guilds: Option<GuildManager>,
+    mail: Option<MailManager>,
+    friends: Option<FriendManager>,
+    exchange: Option<CommodityExchange>,
+    tickets: Option<PendingTickets>,
+    ticket_checker: Option<TicketChecker>,
+    recorder: Option<Recorder>,
+}
+
+impl Default for WorldServer {
+    fn default() -> Self {
+        Self::with_settings(WorldSettings::default())
+    }
+}
+
+impl WorldServer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_settings(settings: WorldSettings) -> Self {
+        Self {
+            guids: GuidAllocator::new(settings.realm_id),
+            settings,
+            data: DataStore::new(),
+            scripts: ScriptStore::new(),
+            database: None,
+            sessions: Default::default(),
+            session_keys: Default::default(),
+            accounts: Default::default(),
+            locales: Default::default(),
+            players: Default::default(),
+            commands: CommandRegistry::with_builtin(),
+            events: Default::default(),
+            groups: Default::default(),
+            guilds: None,
+            mail: None,
+            friends: None,
+            exchange: None,
+            tickets: None,
+            ticket_checker: None,
+            recorder: None,
+        }
+    }
+
+    /// Replaces the empty data tables the server starts with.
+    pub fn with_data(mut self, data: DataStore) -> Self {
+        self.data = data;
+        self
+    }
+
+    /// Replaces the empty scripts the server starts with.
+    pub fn with_scripts(mut self, scripts: ScriptStore) -> Self {
+        self.scripts = scripts;
+        self
+    }
+
+    /// Keeps the characters, the guilds, the mail and the friends in a
+    /// database, instead of giving every account the sandbox character.
+    pub fn with_database(mut self, database: Database) -> Self {
+        self.guilds = Some(GuildManager::new(database.clone()));
+        self.mail = Some(MailManager::new(database.clone()));
+        self.friends = Some(FriendManager::new(database.clone()));
+        self.database = Some(database);
+        self
+    }
+
+    /// Opens the commodity exchange, whose orders are loaded from the
+    /// database beforehand.
+    pub fn with_exchange(mut self, exchange: CommodityExchange) -> Self {
+        self.exchange = Some(exchange);
+        self
+    }
+
+    /// Only lets in the clients presenting a ticket that the realm server
+    /// handed over the cluster.
+    pub fn with_tickets(mut self) -> Self {
+        self.tickets = Some(PendingTickets::new());
+        self
+    }
+
+    /// Only lets in the clients presenting a ticket that the realm server
+    /// signed with the key, for their account and the realm of the server.
+    pub fn with_ticket_key(mut self, key: TicketKey) -> Self {
+        let realm_id = u32::from(self.settings.realm_id);
+        self.ticket_checker = Some(TicketChecker::new(key, realm_id));
+        self
+    }
+
+    /// Records the messages of the clients and the ticks of the world loop,
+    /// to replay them with a [`Replay`].
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    pub fn settings(&self) -> &WorldSettings {
+        &self.settings
+    }
+
+    pub fn data(&self) -> &DataStore {
+        &self.data
+    }
+
+    pub fn scripts(&self) -> &ScriptStore {
+        &self.scripts
+    }
+
+    pub fn database(&self) -> Option<&Database> {
+        self.database.as_ref()
+    }
+
+    pub fn exchange(&self) -> Option<&CommodityExchange> {
+        self.exchange.as_ref()
+    }
+
+    pub fn tickets(&self) -> Option<&PendingTickets> {
+        self.tickets.as_ref()
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    pub fn guids(&self) -> &GuidAllocator {
+        &self.guids
+    }
+
+    /// Returns the account that the session logged in with, if any.
+    pub fn account(&self, session: &Session) -> Option<Account> {
+        self.accounts.lock().unwrap().get(&session.id()).cloned()
+    }
+
+    /// Returns the locale of the texts of a session, the one chosen by the
+    /// player or the default one of the server.
+    pub fn locale(&self, session: &Session) -> String {
+        match self.locales.lock().unwrap().get(&session.id()) {
+            Some(locale) => locale.clone(),
+            None => self.default_locale().to_string(),
+        }
+    }
+
+    pub fn set_locale(&self, session: &Session, locale: &str) {
+        let mut locales = self.locales.lock().unwrap();
+        locales.insert(session.id(), locale.to_string());
+    }
+
+    fn default_locale(&self) -> &str {
+        match self.settings.locale.as_str() {
+            "" => DEFAULT_LOCALE,
+            locale => locale,
+        }
+    }
+
+    /// Writes a text in the locale of a session.
+    pub fn text(&self, session: &Session, text: &Text) -> String {
+        let locale = self.locale(session);
+        self.data.tables().texts.render(&locale, text)
+    }
+
+    /// Writes a text in the locale of a character, or the default one of the
+    /// server if it isn't in the world, like for the mail it receives.
+    fn character_text(&self, character_id: u64, text: &Text) -> String {
+        let session_id = self
+            .players
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, player)| player.character.id == character_id)
+            .map(|(session_id, _)| *session_id);
+        let locale = session_id.and_then(|id| self.locales.lock().unwrap().get(&id).cloned());
+        let locale = locale.as_deref().unwrap_or(self.default_locale());
+        self.data.tables().texts.render(locale, text)
+    }
+
+    /// Returns the player of the session, if it entered the world.
+    pub fn player(&self, session: &Session) -> Option<Player> {
+        self.players.lock().unwrap().get(&session.id()).cloned()
+    }
+
+    /// Closes the session of a character, returning whether it was in the
+    /// world.
+    pub fn kick(&self, character_id: u64) -> bool {
+        let session_id = self
+            .players
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, player)| player.character.id == character_id)
+            .map(|(session_id, _)| *session_id);
+        session_id.is_some_and(|session_id| self.close_connected(session_id))
+    }
+
+    /// Closes the session recorded in the database with a key, returning
+    /// whether it was on this server.
+    pub fn close_session(&self, key: &Uuid) -> bool {
+        let session_id = self
+            .session_keys
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, session_key)| *session_key == key)
+            .map(|(session_id, _)| *session_id);
+        session_id.is_some_and(|session_id| self.close_connected(session_id))
+    }
+
+    /// Closes a session if it is still connected, returning whether it was.
+    fn close_connected(&self, session_id: SessionId) -> bool {
+        let session = self.sessions.lock().unwrap().get(&session_id).cloned();
+        match session {
+            Some(session) => {
+                session.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns the id of the group of a player, if it is in one.
+    pub fn group_id(&self, guid: Guid) -> Option<u64> {
+        let groups = self.groups.lock().unwrap();
+        groups.group_of(guid).map(|group| group.id)
+    }
+
+    /// Changes the player of the session, if it entered the world. The other
+    /// members of its group are told when their group frames should change.
+    pub fn update_player<R>(
+        &self,
+        session: &Session,
+        f: impl FnOnce(&mut Player) -> R,
+    ) -> Option<R> {
+        let (result, member) = {
+            let mut players = self.players.lock().unwrap();
+            let player = players.get_mut(&session.id())?;
+            let result = f(player);
+            (result, player.group_member())
+        };
+
+        let mut groups = self.groups.lock().unwrap();
+        if let Some(group) = groups.update(member.clone()) {
+            let update = ServerGroupMemberUpdate {
+                group_id: group.id,
+                member,
+            };
+            for guid in &group.members {
+                send_to_member(&groups, *guid, &update);
+            }
+        }
+        Some(result)
+    }
+
+    /// Queues a change to the world, applied by the [`WorldLoop`] at its next
+    /// tick.
+    pub fn queue(&self, event: WorldEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+
+    pub(crate) fn take_events(&self) -> Vec<WorldEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    /// Deletes the mail that expired, if the server keeps mail.
+    pub async fn expire_mail(&self) {
+        let mail = match &self.mail {
+            Some(mail) => mail,
+            None => return,
+        };
+        match mail.expire(unix_now()).await {
+            Ok(0) => {}
+            Ok(count) => tracing::debug!("Deleted {count} expired mail"),
+            Err(error) => tracing::error!("Failed to delete the expired mail: {error}"),
+        }
+    }
+
+    /// Matches the orders of the commodity exchange, if the server has one.
+    /// The buyers receive their items by mail, and the sellers are told what
+    /// they sold.
+    pub async fn match_orders(&self) {
+        let (exchange, mail) = match (&self.exchange, &self.mail) {
+            (Some(exchange), Some(mail)) => (exchange, mail),
+            _ => return,
+        };
+        let tables = self.data.tables();
+        // each trader gets a mail in its own locale
+        let trade_mail = |trade: &Trade| {
+            let item = match tables.items.get(&trade.template_id) {
+                Some(template) => template.name.clone(),
+                None => format!("item {}", trade.template_id),
+            };
+            let text = |character_id, id: TextId| {
+                let text = Text::new(id)
+                    .param(trade.count)
+                    .param(&item)
+                    .param(trade.unit_price);
+                let sender = self.character_text(character_id, &TextId::ExchangeSender.into());
+                let subject = self.character_text(character_id, &TextId::OrderFilled.into());
+                let body = self.character_text(character_id, &text);
+                (sender, subject, body)
+            };
+            let (sender, subject, body) = text(trade.buyer, TextId::Bought);
+            let attachments = vec![(trade.template_id, trade.count)];
+            let bought = system_mail(&sender, trade.buyer, &subject, &body, attachments);
+            let (sender, subject, body) = text(trade.seller, TextId::Sold);
+            let sold = system_mail(&sender, trade.seller, &subject, &body, Vec::new());
+            vec![bought, sold]
+        };
+        // the orders are matched again next time if this fails
+        let sent = match exchange.match_orders(trade_mail).await {
+            Ok(sent) => sent,
+            Err(error) => {
+                tracing::error!("Failed to match the commodity orders: {error}");
+                return;
+            }
+        };
+        for sent in sent {
+            if let Err(error) = mail.delivered(sent).await {
+                tracing::error!("Failed to tell about a commodity trade mail: {error}");
+            }
+        }
+    }
+
+    /// Returns the characters of an account, or the sandbox character without
+    /// a database.
+    pub async fn characters(&self, account_id: u32) -> Result<Vec<Character>, DbError> {
+        let database = match &self.database {
+            Some(database) => database,
+            None => return Ok(vec![Character::sandbox(account_id)]),
+        };
+        let characters = database.characters().list(account_id.into()).await?;
+        Ok(characters.into_iter().map(character_from_db).collect())
+    }
+
+    /// Creates a character for an account, or returns why it can't be.
+    pub async fn create_character(
+        &self,
+        account_id: u32,
+        create: ClientCharacterCreate,
+    ) -> Result<Character, CharacterCreateResult> {
+        let database = self
+            .database
+            .as_ref()
+            .ok_or(CharacterCreateResult::Failed)?;
+        let tables = self.data.tables();
+        if let Err(error) = validate_name(&create.name, &tables.blocked_names) {
+            tracing::debug!("Refused the character name {:?}: {error}", create.name);
+            return Err(CharacterCreateResult::InvalidName);
+        }
+        if !create.appearance.is_valid() {
+            return Err(CharacterCreateResult::Failed);
+        }
+
+        let failed = |error: DbError| {
+            tracing::error!("Failed to create a character for account {account_id}: {error}");
+            CharacterCreateResult::Failed
+        };
+        let characters = database.characters();
+        if characters.count(account_id.into()).await.map_err(failed)? >= MAX_CHARACTERS {
+            return Err(CharacterCreateResult::AccountFull);
+        }
+        if characters
+            .find_by_name(&create.name)
+            .await
+            .map_err(failed)?
+            .is_some()
+        {
+            return Err(CharacterCreateResult::NameTaken);
+        }
+
+        let mut writer = BitPackWriter::growable();
+        let customization = writer
+            .write(&create.appearance)
+            .and_then(|_| writer.finish())
+            .map_err(|_| CharacterCreateResult::Failed)?;
+        let world_id = Character::START_WORLD_ID;
+        let position = tables.on_ground(world_id, Character::START_POSITION);
+        let character = characters
+            .create(&NewCharacter {
+                account_id: account_id.into(),
+                name: create.name,
+                faction: create.faction as u16,
+                race: create.race,
+                class: create.class,
+                world_id,
+                position: (position.x, position.y, position.z),
+                yaw: 0.0,
+                sex: create.sex,
+                path: create.path,
+                customization,
+            })
+            .await
+            .map_err(failed)?;
+        Ok(character_from_db(character))
+    }
+
+    async fn hello(&self, session: &Session, hello: ClientHelloRealm) -> NetResult {
+        let connected = session
+            .in_state::<phase::Connected>()
+            .ok_or_else(|| not_allowed::<ClientHelloRealm>(session))?;
+        // the signature is checked first, so that presenting a stolen ticket
+        // with another account doesn't use up the one handed over the cluster
+        if let Some(checker) = &self.ticket_checker {
+            let now = unix_now() as u64;
+            if let Err(error) = checker.redeem(&hello.session_guid, hello.account_id, now) {
+                tracing::debug!("Refused account {}: {error}", hello.account_id);
+                session.close();
+                return Ok(());
+            }
+        }
+        if let Some(tickets) = &self.tickets {
+            if !tickets.redeem(&hello.session_guid) {
+                tracing::debug!("Refused account {} without a ticket", hello.account_id);
+                session.close();
+                return Ok(());
+            }
+        }
+        let account = Account {
+            id: hello.account_id,
+            name: hello.account_name,
+        };
+        self.accounts.lock().unwrap().insert(session.id(), account);
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(session.id(), session.clone());
+        session.send(&ServerAuthAccepted {
+            account_id: hello.account_id,
+        })?;
+        if self.settings.encryption {
+            let encryption = Arc4Encryption::new(hello.session_guid.as_bytes());
+            connected.set_encryption(encryption)?.authenticate()?;
+        } else {
+            connected.authenticate()?;
+        }
+        if let Some(database) = &self.database {
+            // the session can be played without, it just can't be listed
+            match database.sessions().create(hello.account_id.into()).await {
+                Ok(record) => {
+                    let mut keys = self.session_keys.lock().unwrap();
+                    keys.insert(session.id(), record.key);
+                }
+                Err(error) => tracing::error!(
+                    "Failed to record the session of account {}: {error}",
+                    hello.account_id
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    async fn character_list(&self, session: &Session) -> NetResult {
+        let account = match self.account(session) {
+            Some(account) => account,
+            None => return not_logged_in(session),
+        };
+
+        let characters = match self.characters(account.id).await {
+            Ok(characters) => characters,
+            Err(error) => return database_failed(session, error),
+        };
+        session.send(&ServerCharacterList {
+            count: characters.len() as u32,
+            characters: characters.iter().map(Character::entry).collect(),
+        })
+    }
+
+    async fn character_select(
+        &self,
+        session: &Session,
+        select: ClientCharacterSelect,
+    ) -> NetResult {
+        let account = match self.account(session) {
+            Some(account) => account,
+            None => return not_logged_in(session),
+        };
+        let authed = session
+            .in_state::<phase::Authed>()
+            .ok_or_else(|| not_allowed::<ClientCharacterSelect>(session))?;
+
+        let characters = match self.characters(account.id).await {
+            Ok(characters) => characters,
+            Err(error) => return database_failed(session, error),
+        };
+        let character = characters
+            .into_iter()
+            .find(|character| character.id == select.character_id);
+        match character {
+            Some(character) => {
+                session.send(&ServerChangeWorld {
+                    world_id: character.world_id,
+                    position: character.position,
+                    yaw: character.yaw,
+                })?;
+                // the character only enters the world once nothing can fail
+                if let Err(error) = self.enter_social(session, &character).await {
+                    self.exit_social(character.id);
+                    return database_failed(session, error);
+                }
+                let player = Player {
+                    guid: self.guids.allocate(EntityType::Player),
+                    character,
+                    inventory: Inventory::new(),
+                };
+                self.queue(WorldEvent::Enter {
+                    session: session.clone(),
+                    guid: player.guid,
+                    world_id: player.character.world_id,
+                    position: player.character.position,
+                    yaw: player.character.yaw,
+                });
+                self.groups
+                    .lock()
+                    .unwrap()
+                    .enter(session.clone(), player.group_member());
+                self.players.lock().unwrap().insert(session.id(), player);
+                authed.enter_world()?;
+                if self.settings.motd.is_empty() {
+                    return Ok(());
+                }
+                session.send(&ServerChat {
+                    channel: ChatChannel::System,
+                    sender: String::new(),
+                    message: self.settings.motd.clone(),
+                })
+            }
+            None => session.send(&ServerCharacterSelectFailed {
+                character_id: select.character_id,
+            }),
+        }
+    }
+
+    /// Tells the guild, the mail and the friends of a character that it
+    /// entered the world, which loads them from the database.
+    async fn enter_social(&self, session: &Session, character: &Character) -> Result<(), DbError> {
+        if let Some(guilds) = &self.guilds {
+            guilds.enter(session.clone(), character).await?;
+        }
+        if let Some(mail) = &self.mail {
+            mail.enter(session.clone(), character.id).await?;
+        }
+        if let Some(friends) = &self.friends {
+            friends.enter(session.clone(), character).await?;
+        }
+        Ok(())
+    }
+
+    /// Tells the guild, the mail and the friends of a character that it left
+    /// the world, or that it didn't enter it after all.
+    fn exit_social(&self, character_id: u64) {
+        if let Some(guilds) = &self.guilds {
+            guilds.exit(character_id);
+        }
+        if let Some(mail) = &self.mail {
+            mail.exit(character_id);
+        }
+        if let Some(friends) = &self.friends {
+            friends.exit(character_id);
+        }
+    }
+
+    async fn character_create(
+        &self,
+        session: &Session,
+        create: ClientCharacterCreate,
+    ) -> NetResult {
+        let account = match self.account(session) {
+            Some(account) => account,
+            None => return not_logged_in(session),
+        };
+
+        let reply = match self.create_character(account.id, create).await {
+            Ok(character) => ServerCharacterCreate {
+                result: CharacterCreateResult::Ok,
+                character_id: character.id,
+                world_id: character.world_id,
+            },
+            Err(result) => ServerCharacterCreate {
+                result,
+                character_id: 0,
+                world_id: 0,
+            },
+        };
+        session.send(&reply)
+    }
+
+    fn cast_spell(&self, session: &Session, cast: ClientCastSpell) -> NetResult {
+        let player = match self.player(session) {
+            Some(player) => player,
+            None => return not_logged_in(session),
+        };
+        self.queue(WorldEvent::CastSpell {
+            guid: player.guid,
+            spell_id: cast.spell_id,
+            target: cast.target,
+        });
+        Ok(())
+    }
+
+    fn item_move(&self, session: &Session, item_move: ClientItemMove) -> NetResult {
+        let result = self.update_player(session, |player| {
+            player.inventory.move_item(item_move.guid, item_move.slot)
+        });
+        send_item_updates(session, result)
+    }
+
+    fn item_delete(&self, session: &Session, delete: ClientItemDelete) -> NetResult {
+        let result = self.update_player(session, |player| {
+            player
+                .inventory
+                .remove(delete.guid)
+                .map(|update| vec![update])
+        });
+        send_item_updates(session, result)
+    }
+}
+
+/// Sends the updates of an inventory change. Changes the client shouldn't
+/// have asked for are ignored, it still shows the items as they are.
+fn send_item_updates(
+    session: &Session,
+    result: Option<InventoryResult<Vec<ItemUpdate>>>,
+) -> NetResult {
+    match result {
+        Some(Ok(updates)) => session.send(&ServerItemUpdates::new(updates)),
+        Some(Err(error)) => {
+            tracing::debug!("Refused an item change: {error}");
+            Ok(())
+        }
+        None => not_logged_in(session),
+    }
+}
+
+/// Closes sessions that send messages before logging in.
+fn not_logged_in(session: &Session) -> NetResult {
+    session.close();
+    Ok(())
+}
+
+/// Refuses a message sent in a state the session can't leave with it, for
+/// the servers that don't enforce a [`SessionPolicy`].
+fn not_allowed<T: Message>(session: &Session) -> NetError {
+    NetError::MessageNotAllowed {
+        opcode: T::id(),
+        state: session.state(),
+    }
+}
+
+/// Closes sessions whose characters couldn't be loaded, the client has no
+/// way to retry.
+fn database_failed(session: &Session, error: DbError) -> NetResult {
+    tracing::error!("Failed to load the characters: {error}");
+    session.close();
+    Ok(())
+}
+
+fn character_from_db(character: ws_db::Character) -> Character {
+    Character {
+        id: character.id as u64,
+        account_id: character.account_id as u32,
+        name: character.name,
+        // the characters are only created with a valid faction
+        faction: Faction::from_id(character.faction).unwrap_or(Faction::Exile),
+        race: character.race,
+        class: character.class,
+        sex: character.sex,
+        path: character.path,
+        appearance: BitPackReader::new(&character.customization)
+            .read_to_end()
+            .unwrap_or_default(),
+        level: character.level,
+        world_id: character.world_id,
+        position: Position {
+            x: character.x,
+            y: character.y,
+            z: character.z,
+        },
+        yaw: character.yaw,
+    }
+}
+
+/// Returns the messages that clients may send in each state of their session.
+pub fn session_policy() -> SessionPolicy {
+    use SessionState::*;
+
+    let mut policy = SessionPolicy::new();
+    policy
+        .allow::<ClientHelloRealm>(&[Connected])
+        .allow::<ClientCharacterListRequest>(&[Authed])
+        .allow::<ClientCharacterSelect>(&[Authed])
+        .allow::<ClientCharacterCreate>(&[Authed])
+        .allow::<ClientChat>(&[InWorld])
+        .allow::<ClientItemMove>(&[InWorld])
+        .allow::<ClientItemDelete>(&[InWorld])
+        .allow::<ClientCastSpell>(&[InWorld])
+        .allow::<ClientGroupInvite>(&[InWorld])
+        .allow::<ClientGroupInviteResponse>(&[InWorld])
+        .allow::<ClientGroupLeave>(&[InWorld])
+        .allow::<ClientGuildCreate>(&[InWorld])
+        .allow::<ClientGuildRosterRequest>(&[InWorld])
+        .allow::<ClientGuildInvite>(&[InWorld])
+        .allow::<ClientGuildInviteResponse>(&[InWorld])
+        .allow::<ClientGuildLeave>(&[InWorld])
+        .allow::<ClientGuildKick>(&[InWorld])
+        .allow::<ClientGuildSetRank>(&[InWorld])
+        .allow::<ClientGuildRenameRank>(&[InWorld])
+        .allow::<ClientMailSend>(&[InWorld])
+        .allow::<ClientMailRead>(&[InWorld])
+        .allow::<ClientMailTakeAttachments>(&[InWorld])
+        .allow::<ClientMailDelete>(&[InWorld])
+        .allow::<ClientFriendAdd>(&[InWorld])
+        .allow::<ClientFriendRemove>(&[InWorld])
+        .allow::<ClientFriendSetNote>(&[InWorld])
+        .allow::<ClientCommodityInfoRequest>(&[InWorld])
+        .allow::<ClientCommodityOrderPost>(&[InWorld])
+        .allow::<ClientCommodityOrderCancel>(&[InWorld])
+        .allow::<ClientCommodityOrdersRequest>(&[InWorld]);
+    policy
+}
+
+/// Returns the rate limits of world sessions: chat, character creation, mail
+/// and commodity orders are throttled, and clients flooding the server are
+/// disconnected.
+pub fn rate_limits() -> RateLimits {
+    let mut limits = RateLimits::new();
+    limits
+        .global(RateLimit::new(200, 100.0).disconnect())
+        .message::<ClientChat>(RateLimit::new(5, 1.0))
+        .message::<ClientCharacterCreate>(RateLimit::new(3, 0.5))
+        .message::<ClientMailSend>(RateLimit::new(5, 0.5))
+        .message::<ClientCommodityOrderPost>(RateLimit::new(5, 0.5));
+    limits
+}
+
+/// Returns the messages whose traffic isn't logged: world and property
+/// updates are sent to every player several times per second.
+pub fn traffic_filter() -> TrafficFilter {
+    let mut filter = TrafficFilter::new();
+    filter
+        .mute::<ServerWorldUpdate>()
+        .mute::<ServerPropertyUpdates>();
+    filter
+}
+
+/// Returns the JSON forms of the messages of the world server, which the
+/// debugging bridge streams and injects messages with.
+pub fn json_codecs() -> JsonCodecs {
+    let mut codecs = JsonCodecs::new();
+    codecs
+        .register::<ClientHelloRealm>()
+        .register::<ServerAuthAccepted>()
+        .register::<ClientCharacterListRequest>()
+        .register::<ServerCharacterList>()
+        .register::<ClientCharacterSelect>()
+        .register::<ServerChangeWorld>()
+        .register::<ServerCharacterSelectFailed>()
+        .register::<ClientCharacterCreate>()
+        .register::<ServerCharacterCreate>()
+        .register::<ClientChat>()
+        .register::<ServerChat>()
+        .register::<ServerItemUpdates>()
+        .register::<ClientItemMove>()
+        .register::<ClientItemDelete>()
+        .register::<ServerPropertyUpdates>()
+        .register::<ClientCastSpell>()
+        .register::<ServerSpellCastFailed>()
+        .register::<ServerSpellStart>()
+        .register::<ServerSpellGo>()
+        .register::<ClientGroupInvite>()
+        .register::<ServerGroupInviteResult>()
+        .register::<ServerGroupInvite>()
+        .register::<ClientGroupInviteResponse>()
+        .register::<ServerGroupJoined>()
+        .register::<ClientGroupLeave>()
+        .register::<ServerGroupMemberLeft>()
+        .register::<ServerGroupDisbanded>()
+        .register::<ServerGroupMemberUpdate>()
+        .register::<ClientGuildCreate>()
+        .register::<ServerGuildResult>()
+        .register::<ClientGuildRosterRequest>()
+        .register::<ServerGuildRoster>()
+        .register::<ClientGuildInvite>()
+        .register::<ServerGuildInvite>()
+        .register::<ClientGuildInviteResponse>()
+        .register::<ClientGuildLeave>()
+        .register::<ClientGuildKick>()
+        .register::<ClientGuildSetRank>()
+        .register::<ClientGuildRenameRank>()
+        .register::<ServerGuildEvent>()
+        .register::<ClientMailSend>()
+        .register::<ServerMailResult>()
+        .register::<ServerMailList>()
+        .register::<ServerMailReceived>()
+        .register::<ClientMailRead>()
+        .register::<ClientMailTakeAttachments>()
+        .register::<ClientMailDelete>()
+        .register::<ServerMailRemoved>()
+        .register::<ClientCommodityInfoRequest>()
+        .register::<ServerCommodityInfo>()
+        .register::<ClientCommodityOrderPost>()
+        .register::<ServerCommodityResult>()
+        .register::<ClientCommodityOrderCancel>()
+        .register::<ClientCommodityOrdersRequest>()
+        .register::<ServerCommodityOrders>()
+        .register::<ClientFriendAdd>()
+        .register::<ServerFriendResult>()
+        .register::<ServerFriendList>()
+        .register::<ServerFriendAdded>()
+        .register::<ClientFriendRemove>()
+        .register::<ServerFriendRemoved>()
+        .register::<ClientFriendSetNote>()
+        .register::<ServerFriendPresence>()
+        .register::<ServerWorldUpdate>();
+    codecs
+}
+
+/// Routes the messages of every session to the world server.
+pub struct WorldHandler {
+    server: Arc<WorldServer>,
+    handlers: HandlerMap,
+}
+
+impl WorldHandler {
+    pub fn new(server: Arc<WorldServer>) -> Self {
+        let mut handlers = HandlerMap::new();
+        let state = server.clone();
+        handlers.register(move |session, hello| {
+            let state = state.clone();
+            async move { state.hello(&session, hello).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, _: ClientCharacterListRequest| {
+            let state = state.clone();
+            async move { state.character_list(&session).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, select| {
+            let state = state.clone();
+            async move { state.character_select(&session, select).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, create| {
+            let state = state.clone();
+            async move { state.character_create(&session, create).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, chat| {
+            let state = state.clone();
+            async move { state.chat(&session, chat) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, cast| {
+            let state = state.clone();
+            async move { state.cast_spell(&session, cast) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, item_move| {
+            let state = state.clone();
+            async move { state.item_move(&session, item_move) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, delete| {
+            let state = state.clone();
+            async move { state.item_delete(&session, delete) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, invite| {
+            let state = state.clone();
+            async move { state.group_invite(&session, invite) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, response| {
+            let state = state.clone();
+            async move { state.group_invite_response(&session, response) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, _: ClientGroupLeave| {
+            let state = state.clone();
+            async move { state.group_leave(&session) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, create| {
+            let state = state.clone();
+            async move { state.guild_create(&session, create).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, _: ClientGuildRosterRequest| {
+            let state = state.clone();
+            async move { state.guild_roster(&session).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, invite| {
+            let state = state.clone();
+            async move { state.guild_invite(&session, invite).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, response| {
+            let state = state.clone();
+            async move { state.guild_invite_response(&session, response).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, _: ClientGuildLeave| {
+            let state = state.clone();
+            async move { state.guild_leave(&session).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, kick| {
+            let state = state.clone();
+            async move { state.guild_kick(&session, kick).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, set_rank| {
+            let state = state.clone();
+            async move { state.guild_set_rank(&session, set_rank).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, rename| {
+            let state = state.clone();
+            async move { state.guild_rename_rank(&session, rename).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, send| {
+            let state = state.clone();
+            async move { state.mail_send(&session, send).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, read| {
+            let state = state.clone();
+            async move { state.mail_read(&session, read).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, take| {
+            let state = state.clone();
+            async move { state.mail_take_attachments(&session, take).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, delete| {
+            let state = state.clone();
+            async move { state.mail_delete(&session, delete).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, add| {
+            let state = state.clone();
+            async move { state.friend_add(&session, add).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, remove| {
+            let state = state.clone();
+            async move { state.friend_remove(&session, remove).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, set_note| {
+            let state = state.clone();
+            async move { state.friend_set_note(&session, set_note).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, request| {
+            let state = state.clone();
+            async move { state.commodity_info(&session, request) }
+        });
+        let state = server.clone();
+        handlers.register(move |session, post| {
+            let state = state.clone();
+            async move { state.commodity_order_post(&session, post).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, cancel| {
+            let state = state.clone();
+            async move { state.commodity_order_cancel(&session, cancel).await }
+        });
+        let state = server.clone();
+        handlers.register(move |session, _: ClientCommodityOrdersRequest| {
+            let state = state.clone();
+            async move { state.commodity_orders(&session) }
+        });
+
+        Self { server, handlers }
+    }
+}
+
+impl Handler for WorldHandler {
+    async fn connected(&self, session: &Session) -> NetResult {
+        if let Some(recorder) = &self.server.recorder {
+            recorder.connected(session);
+        }
+        Ok(())
+    }
+
+    async fn message(&self, session: &Session, message: Box<dyn AnyMessage>) -> NetResult {
+        if let Some(recorder) = &self.server.recorder {
+            recorder.message(session, message.as_ref());
+        }
+        self.handlers.dispatch(session, message).await
+    }
+
+    async fn disconnected(&self, session: &Session, _error: Option<NetError>) {
+        if let Some(recorder) = &self.server.recorder {
+            recorder.disconnected(session);
+        }
+        self.server.accounts.lock().unwrap().remove(&session.id());
+        self.server.locales.lock().unwrap().remove(&session.id());
+        self.server.sessions.lock().unwrap().remove(&session.id());
+        let key = self
+            .server
+            .session_keys
+            .lock()
+            .unwrap()
+            .remove(&session.id());
+        if let (Some(key), Some(database)) = (key, &self.server.database) {
+            if let Err(error) = database.sessions().delete(&key).await {
+                tracing::error!("Failed to delete session {key}: {error}");
+            }
+        }
+        let player = self.server.players.lock().unwrap().remove(&session.id());
+        if let Some(player) = player {
+            let mut groups = self.server.groups.lock().unwrap();
+            if let Some(leave) = groups.exit(player.guid) {
+                self.server.queue_group_leave(player.guid, &leave);
+                send_group_leave(&groups, player.guid, leave, GroupLeaveReason::Disconnected);
+            }
+            drop(groups);
+            self.server.exit_social(player.character.id);
+            self.server.queue(WorldEvent::Remove { guid: player.guid });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use ws_bitpack::ReadValue;
+    use ws_messages::MessageRegistry;
+    use ws_net::Server;
+    use ws_protocol::{encode_message, FrameDecoder, FrameEncoder};
+
+    use super::*;
+
+    async fn send<T: Message + WriteValue>(client: &mut TcpStream, message: &T) {
+        let frame = encode_message(message).unwrap();
+        client.write_all(&frame).await.unwrap();
+    }
+
+    async fn receive<T: Message + ReadValue>(
+        client: &mut TcpStream,
+        decoder: &mut FrameDecoder,
+    ) -> T {
+        loop {
+            if let Some(frame) = decoder.next_frame().unwrap() {
+                assert_eq!(frame.opcode as u32, T::id());
+                return frame.reader().read().unwrap();
+            }
+            let mut buf = [0u8; 1024];
+            let read = client.read(&mut buf).await.unwrap();
+            assert!(read > 0);
+            decoder.extend(&buf[..read]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_login_to_character_select() {
+        let mut server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        server.set_policy(session_policy());
+        let addr = server.local_addr().unwrap();
+        let handler = WorldHandler::new(Arc::new(WorldServer::new()));
+        tokio::spawn(server.run(Arc::new(handler)));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let hello = ClientHelloRealm {
+            account_id: 430,
+            session_guid: uuid::Uuid::nil(),
+            account_name: "clamoune".to_string(),
+        };
+        send(&mut client, &hello).await;
+        let accepted: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        assert_eq!(accepted.account_id, 430);
+
+        send(&mut client, &ClientCharacterListRequest {}).await;
+        let list: ServerCharacterList = receive(&mut client, &mut decoder).await;
+        assert_eq!(list.characters, [Character::sandbox(430).entry()]);
+
+        let select = ClientCharacterSelect { character_id: 2 };
+        send(&mut client, &select).await;
+        let failed: ServerCharacterSelectFailed = receive(&mut client, &mut decoder).await;
+        assert_eq!(failed.character_id, 2);
+
+        let select = ClientCharacterSelect { character_id: 1 };
+        send(&mut client, &select).await;
+        let change: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+        assert_eq!(change.world_id, 870);
+
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!tp 1 2 3 51".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let change: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+        assert_eq!(change.world_id, 51);
+        assert_eq!(
+            change.position,
+            Position {
+                x: 1.0,
+                y: 2.0,
+                z: 3.0
+            }
+        );
+        let reply: ServerChat = receive(&mut client, &mut decoder).await;
+        assert_eq!(reply.channel, ChatChannel::System);
+
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: ".setlevel 99".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let reply: ServerChat = receive(&mut client, &mut decoder).await;
+        assert_eq!(reply.message, "Level must be between 1 and 50");
+    }
+
+    #[tokio::test]
+    async fn test_encryption_and_motd() {
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let settings = WorldSettings {
+            realm_id: 2,
+            encryption: true,
+            motd: "Welcome!".to_string(),
+            ..Default::default()
+        };
+        let handler = WorldHandler::new(Arc::new(WorldServer::with_settings(settings)));
+        tokio::spawn(server.run(Arc::new(handler)));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let session_guid = uuid::Uuid::from_u128(0x1234);
+        let hello = ClientHelloRealm {
+            account_id: 430,
+            session_guid,
+            account_name: "clamoune".to_string(),
+        };
+        send(&mut client, &hello).await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+
+        // everything after the login is encrypted
+        let encryption = Arc4Encryption::new(session_guid.as_bytes());
+        decoder.set_encryption(encryption.clone());
+        let mut encoder = FrameEncoder::with_encryption(encryption);
+        let frame = encoder
+            .encode_message(&ClientCharacterSelect { character_id: 1 })
+            .unwrap();
+        client.write_all(&frame).await.unwrap();
+        let _: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+        let motd: ServerChat = receive(&mut client, &mut decoder).await;
+        assert_eq!(motd.channel, ChatChannel::System);
+        assert_eq!(motd.message, "Welcome!");
+    }
+
+    #[tokio::test]
+    async fn test_tickets() {
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let settings = WorldSettings {
+            realm_id: 2,
+            ..Default::default()
+        };
+        let key = TicketKey::new(&[7; 16]).unwrap();
+        let world = WorldServer::with_settings(settings).with_ticket_key(key.clone());
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(Arc::new(world)))));
+
+        let ticket = key.issue(&ws_protocol::TicketClaims {
+            account_id: 430,
+            realm_id: 2,
+            expires_at: unix_now() as u32 + 60,
+        });
+        let hello = ClientHelloRealm {
+            account_id: 430,
+            session_guid: ticket,
+            account_name: "clamoune".to_string(),
+        };
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        send(&mut client, &hello).await;
+        let accepted: ServerAuthAccepted = receive(&mut client, &mut FrameDecoder::new()).await;
+        assert_eq!(accepted.account_id, 430);
+
+        // a ticket can't be presented twice
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        send(&mut client, &hello).await;
+        assert_eq!(client.read(&mut [0; 64]).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tickets_over_cluster() {
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let key = TicketKey::new(&[7; 16]).unwrap();
+        let world = Arc::new(
+            WorldServer::new()
+                .with_tickets()
+                .with_ticket_key(key.clone()),
+        );
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world.clone()))));
+
+        let hello = |expires_at: u32| ClientHelloRealm {
+            account_id: 430,
+            session_guid: key.issue(&ws_protocol::TicketClaims {
+                account_id: 430,
+                realm_id: u32::from(world.settings().realm_id),
+                expires_at,
+            }),
+            account_name: "clamoune".to_string(),
+        };
+        // a signed ticket is refused until the realm server hands it over
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        send(&mut client, &hello(unix_now() as u32 + 60)).await;
+        assert_eq!(client.read(&mut [0; 64]).await.unwrap(), 0);
+
+        let announced = hello(unix_now() as u32 + 61);
+        world.tickets().unwrap().insert(announced.session_guid);
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        send(&mut client, &announced).await;
+        let accepted: ServerAuthAccepted = receive(&mut client, &mut FrameDecoder::new()).await;
+        assert_eq!(accepted.account_id, 430);
+    }
+
+    #[tokio::test]
+    async fn test_world_loop_sends_spawned_creatures() {
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new());
+        let mut world_loop = WorldLoop::new(world.clone());
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world))));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let hello = ClientHelloRealm {
+            account_id: 430,
+            session_guid: uuid::Uuid::nil(),
+            account_name: "clamoune".to_string(),
+        };
+        send(&mut client, &hello).await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        send(&mut client, &ClientCharacterSelect { character_id: 1 }).await;
+        let change: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!spawn 2".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let reply: ServerChat = receive(&mut client, &mut decoder).await;
+        assert_eq!(reply.message, "Spawned 2 creature(s)");
+
+        // the creatures are sent by the next tick
+        world_loop.tick(Duration::ZERO);
+        let update: ServerWorldUpdate = receive(&mut client, &mut decoder).await;
+        assert_eq!(update.creates.len(), 2);
+        assert!(update
+            .creates
+            .iter()
+            .all(|create| create.position == change.position));
+
+        // followed by their properties, and the ones of the player
+        let properties: ServerPropertyUpdates = receive(&mut client, &mut decoder).await;
+        assert_eq!(properties.entities.len(), 3);
+        let creature = properties
+            .entities
+            .iter()
+            .find(|entity| entity.guid == update.creates[0].guid)
+            .unwrap();
+        assert_eq!(creature.values.len(), Property::ALL.len());
+        assert_eq!(
+            creature.values[0],
+            PropertyValue {
+                property: Property::Health,
+                value: 100.0
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_items() {
+        let dir = std::env::temp_dir().join(format!("ws_world_items_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("items.json"),
+            r#"[{ "id": 30, "name": "Potion", "max_stack": 20 }]"#,
+        )
+        .unwrap();
+        let data = DataStore::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = WorldServer::new().with_data(data);
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(Arc::new(world)))));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let hello = ClientHelloRealm {
+            account_id: 430,
+            session_guid: uuid::Uuid::nil(),
+            account_name: "clamoune".to_string(),
+        };
+        send(&mut client, &hello).await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        send(&mut client, &ClientCharacterSelect { character_id: 1 }).await;
+        let _: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!additem 30 25".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let added: ServerItemUpdates = receive(&mut client, &mut decoder).await;
+        let reply: ServerChat = receive(&mut client, &mut decoder).await;
+        assert_eq!(reply.message, "Added 25 x Potion");
+        let items: Vec<_> = added
+            .updates
+            .iter()
+            .map(|update| match update {
+                ItemUpdate::Add { reason, item } => {
+                    assert_eq!(*reason, ItemAddReason::Gm);
+                    item.clone()
+                }
+                update => panic!("unexpected update {update:?}"),
+            })
+            .collect();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].count, 20);
+        assert_eq!(items[1].count, 5);
+
+        let bank = ItemSlot {
+            location: ItemLocation::Bank,
+            bag_index: 0,
+        };
+        let item_move = ClientItemMove {
+            guid: items[1].guid,
+            slot: bank,
+        };
+        send(&mut client, &item_move).await;
+        let moved: ServerItemUpdates = receive(&mut client, &mut decoder).await;
+        assert_eq!(
+            moved.updates,
+            [ItemUpdate::Move {
+                guid: items[1].guid,
+                slot: bank
+            }]
+        );
+
+        let delete = ClientItemDelete {
+            guid: items[0].guid,
+        };
+        send(&mut client, &delete).await;
+        let deleted: ServerItemUpdates = receive(&mut client, &mut decoder).await;
+        assert_eq!(
+            deleted.updates,
+            [ItemUpdate::Delete {
+                guid: items[0].guid
+            }]
+        );
+
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!item 7".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let reply: ServerChat = receive(&mut client, &mut decoder).await;
+        assert_eq!(reply.message, "Unknown item 7");
+    }
+
+    #[tokio::test]
+    async fn test_spell_cast() {
+        let dir = std::env::temp_dir().join(format!("ws_world_spells_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("spells.json"),
+            r#"[{ "id": 435, "cast_time": 500, "cooldown": 10000, "effect": "Damage", "amount": 30 }]"#,
+        )
+        .unwrap();
+        let data = DataStore::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new().with_data(data));
+        let mut world_loop = WorldLoop::new(world.clone());
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world))));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let hello = ClientHelloRealm {
+            account_id: 430,
+            session_guid: uuid::Uuid::nil(),
+            account_name: "clamoune".to_string(),
+        };
+        send(&mut client, &hello).await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        send(&mut client, &ClientCharacterSelect { character_id: 1 }).await;
+        let _: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!spawn".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let _: ServerChat = receive(&mut client, &mut decoder).await;
+        world_loop.tick(Duration::ZERO);
+        let update: ServerWorldUpdate = receive(&mut client, &mut decoder).await;
+        let _: ServerPropertyUpdates = receive(&mut client, &mut decoder).await;
+        let creature = update.creates[0].guid;
+
+        let cast = ClientCastSpell {
+            spell_id: 435,
+            target: creature,
+        };
+        send(&mut client, &cast).await;
+        // the handlers queue the cast for the next tick
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        world_loop.tick(Duration::ZERO);
+        let start: ServerSpellStart = receive(&mut client, &mut decoder).await;
+        assert_eq!(start.target, creature);
+        assert_eq!(start.cast_time, 500);
+
+        world_loop.tick(Duration::from_millis(600));
+        let go: ServerSpellGo = receive(&mut client, &mut decoder).await;
+        assert_eq!(go.cast_id, start.cast_id);
+        assert_eq!(
+            go.targets,
+            [SpellTargetResult {
+                target: creature,
+                effect: SpellEffect::Damage,
+                amount: 30.0
+            }]
+        );
+        let properties: ServerPropertyUpdates = receive(&mut client, &mut decoder).await;
+        assert_eq!(
+            properties.entities[0].values,
+            [PropertyValue {
+                property: Property::Health,
+                value: 70.0
+            }]
+        );
+
+        send(&mut client, &cast).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        world_loop.tick(Duration::ZERO);
+        let failed: ServerSpellCastFailed = receive(&mut client, &mut decoder).await;
+        assert_eq!(failed.result, CastResult::Cooldown);
+    }
+
+    #[tokio::test]
+    async fn test_world_snapshot() {
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new());
+        let mut world_loop = WorldLoop::new(world.clone());
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world))));
+
+        let (mut client, mut decoder) = enter_world(addr, 430, 1).await;
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!spawn 2".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let _: ServerChat = receive(&mut client, &mut decoder).await;
+        world_loop.tick(Duration::ZERO);
+        let update: ServerWorldUpdate = receive(&mut client, &mut decoder).await;
+
+        let snapshot = world_loop.snapshot();
+        assert_eq!(snapshot.tick, 1);
+        assert_eq!(snapshot.players_online, 1);
+        assert_eq!(snapshot.instances.len(), 1);
+        let instance = &snapshot.instances[0];
+        assert_eq!(instance.entities, 3);
+        assert_eq!(instance.players.len(), 1);
+        assert_eq!(instance.players[0].position, update.creates[0].position);
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        let x = update.creates[0].position.x;
+        assert_eq!(json["instances"][0]["players"][0]["position"]["x"], x);
+        assert_eq!(json["timings"]["overruns"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_language() {
+        let dir = std::env::temp_dir().join(format!("ws_world_language_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("text")).unwrap();
+        std::fs::write(
+            dir.join("text").join("fr-FR.csv"),
+            "key,text\nlevel_set,Niveau {0}\nlanguage_set,Langue : {0}\n",
+        )
+        .unwrap();
+        let data = DataStore::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = WorldServer::new().with_data(data);
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(Arc::new(world)))));
+        let (mut client, mut decoder) = enter_world(addr, 430, 1).await;
+        let mut command = async |message: &str| {
+            let chat = ClientChat {
+                channel: ChatChannel::Say,
+                message: message.to_string(),
+            };
+            send(&mut client, &chat).await;
+            let reply: ServerChat = receive(&mut client, &mut decoder).await;
+            reply.message
+        };
+
+        assert_eq!(command("!setlevel 12").await, "Level set to 12");
+        assert_eq!(
+            command("!language").await,
+            "Your language is en-US, the others are: en-US, fr-FR"
+        );
+        assert_eq!(
+            command("!language de-DE").await,
+            "Unknown language de-DE, the languages are: en-US, fr-FR"
+        );
+        assert_eq!(command("!language FR-fr").await, "Langue : fr-FR");
+        assert_eq!(command("!setlevel 13").await, "Niveau 13");
+        // the texts without a translation stay in English
+        assert_eq!(
+            command("!setlevel 0").await,
+            "Level must be between 1 and 50"
+        );
+    }
+
+    /// A recording shared with the test while the recorder writes it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let recording = SharedBuffer::default();
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = WorldServer::new().with_recorder(Recorder::new(recording.clone()));
+        let world = Arc::new(world);
+        let mut world_loop = WorldLoop::new(world.clone());
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world))));
+
+        let (mut client, mut decoder) = enter_world(addr, 430, 1).await;
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!spawn 2".to_string(),
+        };
+        send(&mut client, &chat).await;
+        let reply: ServerChat = receive(&mut client, &mut decoder).await;
+        world_loop.tick(Duration::from_millis(40));
+        let update: ServerWorldUpdate = receive(&mut client, &mut decoder).await;
+        drop(client);
+        let recorded = |recording: &SharedBuffer| {
+            String::from_utf8(recording.0.lock().unwrap().clone()).unwrap()
+        };
+        while !recorded(&recording).contains("\"disconnected\"") {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let replay = Replay::read(recorded(&recording).as_bytes()).unwrap();
+        let events = replay.events();
+        assert_eq!(events.len(), 6);
+        assert!(matches!(
+            events[0],
+            RecordedEvent::Connected { tick: 0, .. }
+        ));
+        assert!(matches!(events[3], RecordedEvent::Message { tick: 0, .. }));
+        assert_eq!(
+            events[4],
+            RecordedEvent::Tick {
+                tick: 1,
+                elapsed_us: 40_000
+            }
+        );
+        assert!(matches!(
+            events[5],
+            RecordedEvent::Disconnected { tick: 1, .. }
+        ));
+
+        let world = Arc::new(WorldServer::new());
+        let mut world_loop = WorldLoop::new(world.clone()).with_deterministic();
+        let handler = WorldHandler::new(world);
+        let registry = MessageRegistry::with_registered();
+        let report = replay
+            .run(&mut world_loop, &handler, &registry)
+            .await
+            .unwrap();
+        assert_eq!(report.messages, 3);
+        assert_eq!(report.ticks, 1);
+        assert_eq!(report.sent.len(), 1);
+        let mut decoder = FrameDecoder::new();
+        for frame in report.sent.values().next().unwrap() {
+            decoder.extend(frame);
+        }
+        let mut replayed_reply = None;
+        let mut replayed_update = None;
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            match frame.opcode as u32 {
+                id if id == ServerChat::id() => replayed_reply = frame.reader().read().ok(),
+                id if id == ServerWorldUpdate::id() => replayed_update = frame.reader().read().ok(),
+                _ => {}
+            }
+        }
+        assert_eq!(replayed_reply, Some(reply));
+        assert_eq!(replayed_update, Some(update));
+    }
+
+    async fn enter_world(
+        addr: std::net::SocketAddr,
+        account_id: u32,
+        character_id: u64,
+    ) -> (TcpStream, FrameDecoder) {
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let hello = ClientHelloRealm {
+            account_id,
+            session_guid: uuid::Uuid::nil(),
+            account_name: format!("account{account_id}"),
+        };
+        send(&mut client, &hello).await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        send(&mut client, &ClientCharacterSelect { character_id }).await;
+        let _: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+        (client, decoder)
+    }
+
+    /// Creates a character named after each name, each on its own account,
+    /// and returns their account and character ids.
+    async fn create_characters(database: &Database, names: &[&str]) -> Vec<(u32, u64)> {
+        let mut ids = Vec::new();
+        for name in names {
+            let account = database
+                .accounts()
+                .create(name, &[1; 16], &[2; 128])
+                .await
+                .unwrap();
+            let character = NewCharacter {
+                account_id: account.id,
+                name: name.to_string(),
+                faction: Faction::Exile as u16,
+                race: 1,
+                class: 2,
+                world_id: 870,
+                position: (0.0, 0.0, 0.0),
+                yaw: 0.0,
+                sex: 0,
+                path: 0,
+                customization: Vec::new(),
+            };
+            let character = database.characters().create(&character).await.unwrap();
+            ids.push((account.id as u32, character.id as u64));
+        }
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_groups() {
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new());
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world))));
+
+        let (mut leader, mut leader_decoder) = enter_world(addr, 1, 1).await;
+        let (mut member, mut member_decoder) = enter_world(addr, 2, 1).await;
+
+        // both sandbox characters share a name, the inviter can't invite
+        // itself
+        let invite = ClientGroupInvite {
+            name: "sandbox".to_string(),
+        };
+        send(&mut leader, &invite).await;
+        let result: ServerGroupInviteResult = receive(&mut leader, &mut leader_decoder).await;
+        assert_eq!(result.result, GroupInviteResult::Sent);
+        let invite: ServerGroupInvite = receive(&mut member, &mut member_decoder).await;
+        assert_eq!(invite.inviter_name, "Sandbox");
+
+        send(&mut member, &ClientGroupInviteResponse { accept: true }).await;
+        let joined: ServerGroupJoined = receive(&mut leader, &mut leader_decoder).await;
+        assert_eq!(joined.leader, invite.inviter);
+        assert_eq!(joined.members.len(), 2);
+        let member_guid = joined.members[1].guid;
+        let _: ServerGroupJoined = receive(&mut member, &mut member_decoder).await;
+
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!setlevel 12".to_string(),
+        };
+        send(&mut member, &chat).await;
+        let update: ServerGroupMemberUpdate = receive(&mut leader, &mut leader_decoder).await;
+        assert_eq!(update.group_id, joined.group_id);
+        assert_eq!(update.member.guid, member_guid);
+        assert_eq!(update.member.level, 12);
+
+        // a group of one is no group
+        send(&mut leader, &ClientGroupLeave {}).await;
+        let disbanded: ServerGroupDisbanded = receive(&mut leader, &mut leader_decoder).await;
+        assert_eq!(disbanded.group_id, joined.group_id);
+        let _: ServerGroupMemberUpdate = receive(&mut member, &mut member_decoder).await;
+        let _: ServerChat = receive(&mut member, &mut member_decoder).await;
+        let disbanded: ServerGroupDisbanded = receive(&mut member, &mut member_decoder).await;
+        assert_eq!(disbanded.group_id, joined.group_id);
+    }
+
+    #[tokio::test]
+    async fn test_guilds() {
+        let database = ws_db::Database::in_memory().await.unwrap();
+        let ids = create_characters(&database, &["Master", "Recruit"]).await;
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = WorldServer::new().with_database(database);
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(Arc::new(world)))));
+
+        let (mut master, mut master_decoder) = enter_world(addr, ids[0].0, ids[0].1).await;
+        let _: ServerMailList = receive(&mut master, &mut master_decoder).await;
+        let _: ServerFriendList = receive(&mut master, &mut master_decoder).await;
+        let (mut recruit, mut recruit_decoder) = enter_world(addr, ids[1].0, ids[1].1).await;
+        let _: ServerMailList = receive(&mut recruit, &mut recruit_decoder).await;
+        let _: ServerFriendList = receive(&mut recruit, &mut recruit_decoder).await;
+        let recruit_id = ids[1].1;
+
+        let create = ClientGuildCreate {
+            name: "Sandbox  Testers".to_string(),
+        };
+        send(&mut master, &create).await;
+        let result: ServerGuildResult = receive(&mut master, &mut master_decoder).await;
+        assert_eq!(result.result, GuildResult::InvalidName);
+        let create = ClientGuildCreate {
+            name: "Sandbox Testers".to_string(),
+        };
+        send(&mut master, &create).await;
+        let result: ServerGuildResult = receive(&mut master, &mut master_decoder).await;
+        assert_eq!(result.result, GuildResult::Ok);
+
+        let invite = ClientGuildInvite {
+            name: "recruit".to_string(),
+        };
+        send(&mut master, &invite).await;
+        let result: ServerGuildResult = receive(&mut master, &mut master_decoder).await;
+        assert_eq!(result.result, GuildResult::Ok);
+        let invite: ServerGuildInvite = receive(&mut recruit, &mut recruit_decoder).await;
+        assert_eq!(invite.guild_name, "Sandbox Testers");
+        assert_eq!(invite.inviter_name, "Master");
+
+        send(&mut recruit, &ClientGuildInviteResponse { accept: true }).await;
+        // both members hear of the new one
+        for (client, decoder) in [
+            (&mut master, &mut master_decoder),
+            (&mut recruit, &mut recruit_decoder),
+        ] {
+            let event: ServerGuildEvent = receive(client, decoder).await;
+            match event.event {
+                GuildEvent::MemberJoined(member) => {
+                    assert_eq!(member.character_id, recruit_id);
+                    assert_eq!(member.rank, 2);
+                    assert!(member.online);
+                }
+                event => panic!("unexpected event {event:?}"),
+            }
+        }
+
+        // members can't promote others without the permission
+        let set_rank = ClientGuildSetRank {
+            name: "Master".to_string(),
+            rank: 2,
+        };
+        send(&mut recruit, &set_rank).await;
+        let result: ServerGuildResult = receive(&mut recruit, &mut recruit_decoder).await;
+        assert_eq!(result.result, GuildResult::NotPermitted);
+        let set_rank = ClientGuildSetRank {
+            name: "Recruit".to_string(),
+            rank: 1,
+        };
+        send(&mut master, &set_rank).await;
+        let event: ServerGuildEvent = receive(&mut master, &mut master_decoder).await;
+        assert_eq!(
+            event.event,
+            GuildEvent::RankChanged {
+                character_id: recruit_id,
+                rank: 1
+            }
+        );
+        let _: ServerGuildResult = receive(&mut master, &mut master_decoder).await;
+        let _: ServerGuildEvent = receive(&mut recruit, &mut recruit_decoder).await;
+
+        send(&mut recruit, &ClientGuildRosterRequest {}).await;
+        let roster: ServerGuildRoster = receive(&mut recruit, &mut recruit_decoder).await;
+        assert_eq!(roster.name, "Sandbox Testers");
+        assert_eq!(roster.ranks[1].name, "Officer");
+        assert!(roster.ranks[1].permissions.contains(GuildPermissions::KICK));
+        let members: Vec<_> = roster
+            .members
+            .iter()
+            .map(|member| (member.name.as_str(), member.rank))
+            .collect();
+        assert_eq!(members, [("Master", 0), ("Recruit", 1)]);
+
+        send(&mut master, &ClientGuildLeave {}).await;
+        let result: ServerGuildResult = receive(&mut master, &mut master_decoder).await;
+        assert_eq!(result.result, GuildResult::MasterCantLeave);
+        let kick = ClientGuildKick {
+            name: "Recruit".to_string(),
+        };
+        send(&mut master, &kick).await;
+        let event: ServerGuildEvent = receive(&mut recruit, &mut recruit_decoder).await;
+        assert_eq!(
+            event.event,
+            GuildEvent::MemberKicked {
+                character_id: recruit_id
+            }
+        );
+        send(&mut recruit, &ClientGuildRosterRequest {}).await;
+        let result: ServerGuildResult = receive(&mut recruit, &mut recruit_decoder).await;
+        assert_eq!(result.result, GuildResult::NotInGuild);
+    }
+
+    #[tokio::test]
+    async fn test_mail() {
+        let dir = std::env::temp_dir().join(format!("ws_world_mail_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("items.json"),
+            r#"[{ "id": 30, "name": "Potion", "max_stack": 20 }]"#,
+        )
+        .unwrap();
+        let data = DataStore::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let database = ws_db::Database::in_memory().await.unwrap();
+        let ids = create_characters(&database, &["Sender", "Recipient"]).await;
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new().with_data(data).with_database(database));
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world.clone()))));
+
+        let (mut sender, mut sender_decoder) = enter_world(addr, ids[0].0, ids[0].1).await;
+        let list: ServerMailList = receive(&mut sender, &mut sender_decoder).await;
+        let _: ServerFriendList = receive(&mut sender, &mut sender_decoder).await;
+        assert!(list.mail.is_empty());
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!additem 30 5".to_string(),
+        };
+        send(&mut sender, &chat).await;
+        let added: ServerItemUpdates = receive(&mut sender, &mut sender_decoder).await;
+        let _: ServerChat = receive(&mut sender, &mut sender_decoder).await;
+        let potions = match &added.updates[0] {
+            ItemUpdate::Add { item, .. } => item.guid,
+            update => panic!("unexpected update {update:?}"),
+        };
+
+        let mut mail = ClientMailSend {
+            recipient: "Nobody".to_string(),
+            subject: "Potions".to_string(),
+            body: "For the raid".to_string(),
+            item_count: 1,
+            items: vec![potions],
+        };
+        send(&mut sender, &mail).await;
+        let result: ServerMailResult = receive(&mut sender, &mut sender_decoder).await;
+        assert_eq!(result.result, MailResult::RecipientNotFound);
+        // the recipient isn't in the world, it gets the mail when it enters
+        mail.recipient = "recipient".to_string();
+        send(&mut sender, &mail).await;
+        let removed: ServerItemUpdates = receive(&mut sender, &mut sender_decoder).await;
+        assert_eq!(removed.updates, [ItemUpdate::Delete { guid: potions }]);
+        let result: ServerMailResult = receive(&mut sender, &mut sender_decoder).await;
+        assert_eq!(result.result, MailResult::Ok);
+        send(&mut sender, &mail).await;
+        let result: ServerMailResult = receive(&mut sender, &mut sender_decoder).await;
+        assert_eq!(result.result, MailResult::ItemNotFound);
+
+        let (mut recipient, mut recipient_decoder) = enter_world(addr, ids[1].0, ids[1].1).await;
+        let list: ServerMailList = receive(&mut recipient, &mut recipient_decoder).await;
+        let _: ServerFriendList = receive(&mut recipient, &mut recipient_decoder).await;
+        assert_eq!(list.mail.len(), 1);
+        let received = &list.mail[0];
+        assert_eq!(received.sender_name, "Sender");
+        assert_eq!(received.subject, "Potions");
+        assert!(!received.is_read);
+        assert_eq!(
+            received.attachments,
+            [MailAttachmentData {
+                template_id: 30,
+                count: 5
+            }]
+        );
+        let mail_id = received.mail_id;
+
+        let delete = ClientMailDelete { mail_id };
+        send(&mut recipient, &delete).await;
+        let result: ServerMailResult = receive(&mut recipient, &mut recipient_decoder).await;
+        assert_eq!(result.result, MailResult::HasAttachments);
+        send(&mut recipient, &ClientMailTakeAttachments { mail_id }).await;
+        let added: ServerItemUpdates = receive(&mut recipient, &mut recipient_decoder).await;
+        match &added.updates[0] {
+            ItemUpdate::Add { reason, item } => {
+                assert_eq!(*reason, ItemAddReason::Mail);
+                assert_eq!((item.template_id, item.count), (30, 5));
+            }
+            update => panic!("unexpected update {update:?}"),
+        }
+        let result: ServerMailResult = receive(&mut recipient, &mut recipient_decoder).await;
+        assert_eq!(result.result, MailResult::Ok);
+        send(&mut recipient, &delete).await;
+        let removed: ServerMailRemoved = receive(&mut recipient, &mut recipient_decoder).await;
+        assert_eq!(removed.mail_id, mail_id);
+
+        // mail reaches the recipients in the world at once
+        let mail = ClientMailSend {
+            recipient: "Recipient".to_string(),
+            subject: "Hello".to_string(),
+            body: String::new(),
+            item_count: 0,
+            items: Vec::new(),
+        };
+        send(&mut sender, &mail).await;
+        let received: ServerMailReceived = receive(&mut recipient, &mut recipient_decoder).await;
+        assert_eq!(received.mail.subject, "Hello");
+        assert!(received.mail.expires_in > 0);
+
+        let later = unix_now() + MAIL_EXPIRY.as_secs() as i64;
+        let expired = world.mail.as_ref().unwrap().expire(later).await.unwrap();
+        assert_eq!(expired, 1);
+        let removed: ServerMailRemoved = receive(&mut recipient, &mut recipient_decoder).await;
+        assert_eq!(removed.mail_id, received.mail.mail_id);
+    }
+
+    #[tokio::test]
+    async fn test_close_session() {
+        let database = ws_db::Database::in_memory().await.unwrap();
+        let accounts = database.accounts();
+        let account = accounts
+            .create("clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new().with_database(database.clone()));
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world.clone()))));
+
+        let hello = ClientHelloRealm {
+            account_id: account.id as u32,
+            session_guid: Uuid::nil(),
+            account_name: account.name,
+        };
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        send(&mut client, &hello).await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut FrameDecoder::new()).await;
+        // the session is recorded in the database while it lasts
+        let sessions = database.sessions().list().await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions[0].account_id, account.id);
+
+        assert!(!world.close_session(&Uuid::nil()));
+        assert!(world.close_session(&sessions[0].key));
+        assert_eq!(client.read(&mut [0; 64]).await.unwrap(), 0);
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while !database.sessions().list().await.unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_enter_fails() {
+        let database = ws_db::Database::in_memory().await.unwrap();
+        let ids = create_characters(&database, &["Alice"]).await;
+        // the friends are loaded last, once the guild and the mail were
+        sqlx::query("DROP TABLE friends")
+            .execute(database.pool())
+            .await
+            .unwrap();
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new().with_database(database));
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world.clone()))));
+
+        let (mut alice, mut decoder) = enter_world(addr, ids[0].0, ids[0].1).await;
+        let _: ServerMailList = receive(&mut alice, &mut decoder).await;
+        assert_eq!(alice.read(&mut [0; 64]).await.unwrap(), 0);
+        // the character was left out of the world and the guild and mail
+        assert!(world.events.lock().unwrap().is_empty());
+        assert!(world.players.lock().unwrap().is_empty());
+        assert!(!world.guilds.as_ref().unwrap().is_online(ids[0].1));
+        assert!(!world.mail.as_ref().unwrap().is_online(ids[0].1));
+    }
+
+    #[tokio::test]
+    async fn test_friends() {
+        let database = ws_db::Database::in_memory().await.unwrap();
+        let ids = create_characters(&database, &["Alice", "Bob"]).await;
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(WorldServer::new().with_database(database));
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world))));
+
+        let (mut alice, mut alice_decoder) = enter_world(addr, ids[0].0, ids[0].1).await;
+        let _: ServerMailList = receive(&mut alice, &mut alice_decoder).await;
+        let list: ServerFriendList = receive(&mut alice, &mut alice_decoder).await;
+        assert!(list.friends.is_empty());
+
+        for (name, expected) in [
+            ("Nobody", FriendResult::PlayerNotFound),
+            ("alice", FriendResult::OwnCharacter),
+        ] {
+            send(&mut alice, &ClientFriendAdd { name: name.into() }).await;
+            let result: ServerFriendResult = receive(&mut alice, &mut alice_decoder).await;
+            assert_eq!(result.result, expected);
+        }
+        let add = ClientFriendAdd { name: "bob".into() };
+        send(&mut alice, &add).await;
+        let added: ServerFriendAdded = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(added.friend.name, "Bob");
+        assert!(!added.friend.online);
+        let result: ServerFriendResult = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(result.result, FriendResult::Ok);
+        send(&mut alice, &add).await;
+        let result: ServerFriendResult = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(result.result, FriendResult::AlreadyFriend);
+        let set_note = ClientFriendSetNote {
+            character_id: ids[1].1,
+            note: "Tank".into(),
+        };
+        send(&mut alice, &set_note).await;
+        let result: ServerFriendResult = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(result.result, FriendResult::Ok);
+
+        // friends are told when the other enters and leaves the world
+        let (mut bob, mut bob_decoder) = enter_world(addr, ids[1].0, ids[1].1).await;
+        let _: ServerMailList = receive(&mut bob, &mut bob_decoder).await;
+        let _: ServerFriendList = receive(&mut bob, &mut bob_decoder).await;
+        let presence: ServerFriendPresence = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!((presence.character_id, presence.online), (ids[1].1, true));
+        drop(bob);
+        let presence: ServerFriendPresence = receive(&mut alice, &mut alice_decoder).await;
+        assert!(!presence.online);
+
+        // the friends are kept with the account
+        drop(alice);
+        let (mut alice, mut alice_decoder) = enter_world(addr, ids[0].0, ids[0].1).await;
+        let _: ServerMailList = receive(&mut alice, &mut alice_decoder).await;
+        let list: ServerFriendList = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(list.friends.len(), 1);
+        assert_eq!(list.friends[0].note, "Tank");
+        let remove = ClientFriendRemove {
+            character_id: ids[1].1,
+        };
+        send(&mut alice, &remove).await;
+        let removed: ServerFriendRemoved = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(removed.character_id, ids[1].1);
+        let result: ServerFriendResult = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(result.result, FriendResult::Ok);
+        send(&mut alice, &remove).await;
+        let result: ServerFriendResult = receive(&mut alice, &mut alice_decoder).await;
+        assert_eq!(result.result, FriendResult::NotFriend);
+    }
+
+    #[tokio::test]
+    async fn test_commodity_exchange() {
+        let dir = std::env::temp_dir().join(format!("ws_world_exchange_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("items.json"),
+            r#"[{ "id": 30, "name": "Potion", "max_stack": 20 }]"#,
+        )
+        .unwrap();
+        let data = DataStore::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let database = ws_db::Database::in_memory().await.unwrap();
+        let ids = create_characters(&database, &["Seller", "Buyer"]).await;
+        let exchange = CommodityExchange::load(database.clone()).await.unwrap();
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = Arc::new(
+            WorldServer::new()
+                .with_data(data)
+                .with_database(database)
+                .with_exchange(exchange),
+        );
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(world.clone()))));
+
+        let (mut seller, mut seller_decoder) = enter_world(addr, ids[0].0, ids[0].1).await;
+        let _: ServerMailList = receive(&mut seller, &mut seller_decoder).await;
+        let _: ServerFriendList = receive(&mut seller, &mut seller_decoder).await;
+        let (mut buyer, mut buyer_decoder) = enter_world(addr, ids[1].0, ids[1].1).await;
+        let _: ServerMailList = receive(&mut buyer, &mut buyer_decoder).await;
+        let _: ServerFriendList = receive(&mut buyer, &mut buyer_decoder).await;
+        let chat = ClientChat {
+            channel: ChatChannel::Say,
+            message: "!additem 30 5".to_string(),
+        };
+        send(&mut seller, &chat).await;
+        let _: ServerItemUpdates = receive(&mut seller, &mut seller_decoder).await;
+        let _: ServerChat = receive(&mut seller, &mut seller_decoder).await;
+
+        let mut sell = ClientCommodityOrderPost {
+            side: CommoditySide::Sell,
+            template_id: 30,
+            count: 6,
+            unit_price: 100,
+        };
+        send(&mut seller, &sell).await;
+        let result: ServerCommodityResult = receive(&mut seller, &mut seller_decoder).await;
+        assert_eq!(result.result, CommodityResult::NotEnoughItems);
+        sell.count = 5;
+        send(&mut seller, &sell).await;
+        let taken: ServerItemUpdates = receive(&mut seller, &mut seller_decoder).await;
+        assert!(matches!(taken.updates[..], [ItemUpdate::Delete { .. }]));
+        let result: ServerCommodityResult = receive(&mut seller, &mut seller_decoder).await;
+        assert_eq!(result.result, CommodityResult::Ok);
+        let sell_order = result.order_id;
+
+        let buy = ClientCommodityOrderPost {
+            side: CommoditySide::Buy,
+            template_id: 30,
+            count: 3,
+            unit_price: 120,
+        };
+        send(&mut buyer, &buy).await;
+        let result: ServerCommodityResult = receive(&mut buyer, &mut buyer_decoder).await;
+        assert_eq!(result.result, CommodityResult::Ok);
+
+        // the buyer gets the items at the price of the older sell order
+        world.match_orders().await;
+        let bought: ServerMailReceived = receive(&mut buyer, &mut buyer_decoder).await;
+        assert_eq!(bought.mail.sender_name, TextId::ExchangeSender.english());
+        assert_eq!(bought.mail.body, "Bought 3 Potion for 100 each.");
+        assert_eq!(
+            bought.mail.attachments,
+            [MailAttachmentData {
+                template_id: 30,
+                count: 3
+            }]
+        );
+        let sold: ServerMailReceived = receive(&mut seller, &mut seller_decoder).await;
+        assert!(sold.mail.attachments.is_empty());
+
+        send(&mut buyer, &ClientCommodityInfoRequest { template_id: 30 }).await;
+        let info: ServerCommodityInfo = receive(&mut buyer, &mut buyer_decoder).await;
+        assert_eq!((info.buy_count, info.best_buy), (0, 0));
+        assert_eq!((info.sell_count, info.best_sell), (2, 100));
+
+        // what is left of a cancelled sell order is mailed back
+        send(&mut seller, &ClientCommodityOrdersRequest {}).await;
+        let orders: ServerCommodityOrders = receive(&mut seller, &mut seller_decoder).await;
+        assert_eq!(orders.orders.len(), 1);
+        assert_eq!(orders.orders[0].count, 2);
+        let cancel = ClientCommodityOrderCancel {
+            order_id: sell_order,
+        };
+        send(&mut seller, &cancel).await;
+        let returned: ServerMailReceived = receive(&mut seller, &mut seller_decoder).await;
+        assert_eq!(returned.mail.attachments[0].count, 2);
+        let result: ServerCommodityResult = receive(&mut seller, &mut seller_decoder).await;
+        assert_eq!(result.result, CommodityResult::Ok);
+        send(&mut buyer, &cancel).await;
+        let result: ServerCommodityResult = receive(&mut buyer, &mut buyer_decoder).await;
+        assert_eq!(result.result, CommodityResult::OrderNotFound);
+    }
+
+    #[tokio::test]
+    async fn test_character_create() {
+        let database = ws_db::Database::in_memory().await.unwrap();
+        let account = database
+            .accounts()
+            .create("clamoune", &[1; 16], &[2; 128])
+            .await
+            .unwrap();
+        let server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        let addr = server.local_addr().unwrap();
+        let world = WorldServer::new().with_database(database);
+        tokio::spawn(server.run(Arc::new(WorldHandler::new(Arc::new(world)))));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut decoder = FrameDecoder::new();
+        let hello = ClientHelloRealm {
+            account_id: account.id as u32,
+            session_guid: uuid::Uuid::nil(),
+            account_name: account.name,
+        };
+        send(&mut client, &hello).await;
+        let _: ServerAuthAccepted = receive(&mut client, &mut decoder).await;
+        send(&mut client, &ClientCharacterListRequest {}).await;
+        let list: ServerCharacterList = receive(&mut client, &mut decoder).await;
+        assert!(list.characters.is_empty());
+
+        let appearance = Appearance {
+            count: 1,
+            customizations: vec![Customization::Bone {
+                index: 2,
+                offset: 0.25,
+            }],
+        };
+        let mut create = ClientCharacterCreate {
+            name: "Clam0une".to_string(),
+            faction: Faction::Dominion,
+            race: 3,
+            class: 2,
+            sex: 1,
+            path: 2,
+            appearance: appearance.clone(),
+        };
+        send(&mut client, &create).await;
+        let created: ServerCharacterCreate = receive(&mut client, &mut decoder).await;
+        assert_eq!(created.result, CharacterCreateResult::InvalidName);
+
+        create.name = "Clamoune".to_string();
+        send(&mut client, &create).await;
+        let created: ServerCharacterCreate = receive(&mut client, &mut decoder).await;
+        assert_eq!(created.result, CharacterCreateResult::Ok);
+        assert_eq!(created.world_id, Character::START_WORLD_ID);
+
+        send(&mut client, &create).await;
+        let taken: ServerCharacterCreate = receive(&mut client, &mut decoder).await;
+        assert_eq!(taken.result, CharacterCreateResult::NameTaken);
+
+        send(&mut client, &ClientCharacterListRequest {}).await;
+        let list: ServerCharacterList = receive(&mut client, &mut decoder).await;
+        assert_eq!(list.characters.len(), 1);
+        assert_eq!(list.characters[0].character_id, created.character_id);
+        assert_eq!(list.characters[0].name, "Clamoune");
+        assert_eq!(list.characters[0].faction, Faction::Dominion);
+
+        let select = ClientCharacterSelect {
+            character_id: created.character_id,
+        };
+        send(&mut client, &select).await;
+        let change: ServerChangeWorld = receive(&mut client, &mut decoder).await;
+        assert_eq!(change.position, Character::START_POSITION);
+    }
+
+    #[tokio::test]
+    async fn test_requires_login() {
+        let mut server = Server::bind("127.0.0.1:0", MessageRegistry::with_registered())
+            .await
+            .unwrap();
+        server.set_policy(session_policy());
+        let addr = server.local_addr().unwrap();
+        let handler = WorldHandler::new(Arc::new(WorldServer::new()));
+        tokio::spawn(server.run(Arc::new(handler)));
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        send(&mut client, &ClientCharacterListRequest {}).await;
+        let mut buf = [0u8; 16];
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+    }
+}
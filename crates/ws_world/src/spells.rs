@@ -0,0 +1,139 @@
+use bevy_ecs::prelude::*;
+
+use crate::ecs::{self, Casting, Cooldowns, GuidIndex, Stats};
+use crate::*;
+
+/// Starts the spells cast by the entities of a world.
+///
+/// A cast is checked against the spell templates, the state of its caster
+/// and the distance to its target. Accepted casts are announced to the
+/// players around the caster, and take effect once [`ecs::cast_spells`]
+/// finishes them.
+#[derive(Debug, Default)]
+pub struct SpellManager {
+    last_cast_id: u32,
+}
+
+impl SpellManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts casting a spell, on the caster itself if the target is
+    /// [`Guid::NONE`], and returns the id of the cast.
+    pub fn cast(
+        &mut self,
+        world: &mut World,
+        tables: &DataTables,
+        caster: Guid,
+        spell_id: u32,
+        target: Guid,
+    ) -> Result<u32, CastResult> {
+        let spell = tables
+            .spells
+            .get(&spell_id)
+            .ok_or(CastResult::UnknownSpell)?;
+        let target = match target.is_none() {
+            true => caster,
+            false => target,
+        };
+        let index = world.resource::<GuidIndex>();
+        let caster_entity = *index.0.get(&caster).ok_or(CastResult::CasterDead)?;
+        let target_entity = *index.0.get(&target).ok_or(CastResult::InvalidTarget)?;
+
+        let caster_ref = world.entity(caster_entity);
+        if !caster_ref.get::<Stats>().is_some_and(Stats::is_alive) {
+            return Err(CastResult::CasterDead);
+        }
+        if caster_ref.contains::<Casting>() {
+            return Err(CastResult::AlreadyCasting);
+        }
+        if caster_ref
+            .get::<Cooldowns>()
+            .is_some_and(|cooldowns| cooldowns.0.contains_key(&spell_id))
+        {
+            return Err(CastResult::Cooldown);
+        }
+        let target_ref = world.entity(target_entity);
+        if !target_ref.get::<Stats>().is_some_and(Stats::is_alive) {
+            return Err(CastResult::InvalidTarget);
+        }
+        let (from, to) = match (caster_ref.get::<Position>(), target_ref.get::<Position>()) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return Err(CastResult::InvalidTarget),
+        };
+        let (dx, dy, dz) = (to.x - from.x, to.y - from.y, to.z - from.z);
+        if (dx * dx + dy * dy + dz * dz).sqrt() > spell.range {
+            return Err(CastResult::OutOfRange);
+        }
+
+        self.last_cast_id = self.last_cast_id.wrapping_add(1);
+        let cast_id = self.last_cast_id;
+        world.entity_mut(caster_entity).insert(Casting {
+            cast_id,
+            spell: spell.clone(),
+            target,
+            remaining: spell.cast_time as f32 / 1000.0,
+        });
+        let start = ServerSpellStart {
+            cast_id,
+            caster,
+            spell_id,
+            target,
+            cast_time: spell.cast_time,
+        };
+        ecs::broadcast(world, caster, &start);
+        Ok(cast_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::EntityGuid;
+
+    fn spawn(world: &mut World, guid: Guid, x: f32, stats: Stats) -> Entity {
+        let position = Position {
+            x,
+            ..Default::default()
+        };
+        let entity = world.spawn((EntityGuid(guid), position, stats)).id();
+        world.resource_mut::<GuidIndex>().0.insert(guid, entity);
+        entity
+    }
+
+    #[test]
+    fn test_cast_checks() {
+        let mut tables = DataTables::default();
+        tables.spells.insert(
+            435,
+            SpellTemplate {
+                id: 435,
+                cast_time: 1000,
+                cooldown: 0,
+                range: 25.0,
+                effect: SpellEffect::Damage,
+                amount: 30.0,
+            },
+        );
+        let guids = GuidAllocator::new(1);
+        let [caster, near, far, dead] = [(); 4].map(|_| guids.allocate(EntityType::Creature));
+        let mut world = World::new();
+        world.init_resource::<GuidIndex>();
+        world.init_resource::<ecs::Grids>();
+        spawn(&mut world, caster, 0.0, Stats::with_health(100.0, 0.0));
+        spawn(&mut world, near, 20.0, Stats::with_health(100.0, 0.0));
+        spawn(&mut world, far, 40.0, Stats::with_health(100.0, 0.0));
+        spawn(&mut world, dead, 10.0, Stats::new());
+
+        let mut spells = SpellManager::new();
+        let mut cast = |world: &mut World, spell_id, target| {
+            spells.cast(world, &tables, caster, spell_id, target)
+        };
+        assert_eq!(cast(&mut world, 7, near), Err(CastResult::UnknownSpell));
+        assert_eq!(cast(&mut world, 435, far), Err(CastResult::OutOfRange));
+        assert_eq!(cast(&mut world, 435, dead), Err(CastResult::InvalidTarget));
+        assert_eq!(cast(&mut world, 435, near), Ok(1));
+        assert_eq!(cast(&mut world, 435, near), Err(CastResult::AlreadyCasting));
+    }
+}
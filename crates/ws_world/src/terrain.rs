@@ -0,0 +1,389 @@
+//! The terrain heights of the client's `.area` files.
+//!
+//! A map is a grid of 128 by 128 areas of 512 meters, centered on the origin,
+//! and each area is a grid of 16 by 16 cells of 32 meters. An area file starts
+//! with the `AREA` magic and a version, followed by chunks made of a fourcc,
+//! a size and their data. Only the `CHNK` chunk is read: it holds the cells of
+//! the area, each starting with its index in the top 8 bits of a `u32` and its
+//! size in the lower 24 bits, then flags telling which parts follow. When its
+//! first flag is set, a cell starts with its height map: 19 by 19 `u16`, every
+//! 2 meters from 2 meters before the cell to 2 meters after it.
+//!
+//! Like in [`Position`](crate::Position), `y` is the height and `x` and `z`
+//! are the ground axes.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The fourccs are stored as little endian `u32`, so backwards in the file.
+const MAGIC: [u8; 4] = *b"AERA";
+const CELLS_CHUNK: [u8; 4] = *b"KNHC";
+
+const FLAG_HEIGHT_MAP: u32 = 0x1;
+
+pub const AREA_SIZE: f32 = 512.0;
+pub const CELL_SIZE: f32 = 32.0;
+/// The number of areas on each axis of a map.
+pub const MAP_AREAS: u8 = 128;
+const AREA_CELLS: usize = 16;
+/// The samples of a height map row, with one sample of border on each side.
+const HEIGHT_MAP_SIZE: usize = 19;
+const SAMPLE_SPACING: f32 = 2.0;
+
+/// The steepest slope characters can walk on, as the height climbed per meter.
+pub const MAX_WALKABLE_SLOPE: f32 = 1.0;
+
+/// The heights of a cell, in meters.
+#[derive(Debug, Clone, PartialEq)]
+struct HeightMap {
+    samples: Vec<f32>,
+}
+
+impl HeightMap {
+    /// Returns the sample at a column and row of the cell, from 0 to 16.
+    fn sample(&self, column: usize, row: usize) -> f32 {
+        self.samples[(row + 1) * HEIGHT_MAP_SIZE + column + 1]
+    }
+
+    /// Returns the heights of the corners of the square around a point of
+    /// the cell, in meters from the cell's corner.
+    fn square(&self, x: f32, z: f32) -> ([f32; 4], f32, f32) {
+        // the last square starts at the second to last sample of the cell
+        let max = (HEIGHT_MAP_SIZE - 4) as f32;
+        let column = (x / SAMPLE_SPACING).floor().clamp(0.0, max);
+        let row = (z / SAMPLE_SPACING).floor().clamp(0.0, max);
+        let (c, r) = (column as usize, row as usize);
+        let corners = [
+            self.sample(c, r),
+            self.sample(c + 1, r),
+            self.sample(c, r + 1),
+            self.sample(c + 1, r + 1),
+        ];
+        let fx = (x / SAMPLE_SPACING - column).clamp(0.0, 1.0);
+        let fz = (z / SAMPLE_SPACING - row).clamp(0.0, 1.0);
+        (corners, fx, fz)
+    }
+
+    fn height(&self, x: f32, z: f32) -> f32 {
+        let ([h00, h10, h01, h11], fx, fz) = self.square(x, z);
+        let near = h00 + (h10 - h00) * fx;
+        let far = h01 + (h11 - h01) * fx;
+        near + (far - near) * fz
+    }
+
+    fn slope(&self, x: f32, z: f32) -> f32 {
+        let ([h00, h10, h01, h11], _, _) = self.square(x, z);
+        [h10 - h00, h11 - h01, h01 - h00, h11 - h10]
+            .into_iter()
+            .map(|rise| rise.abs() / SAMPLE_SPACING)
+            .fold(0.0, f32::max)
+    }
+}
+
+/// The terrain of an area of a map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AreaFile {
+    x: u8,
+    z: u8,
+    cells: Vec<Option<HeightMap>>,
+}
+
+impl AreaFile {
+    /// Parses the area at the given position in the grid of its map.
+    pub fn parse(x: u8, z: u8, data: &[u8]) -> TerrainResult<Self> {
+        let mut reader = Reader { data, offset: 0 };
+        if reader.bytes(4)? != MAGIC {
+            return Err(TerrainError::InvalidMagic);
+        }
+        let _version = reader.u32()?;
+
+        let mut cells = vec![None; AREA_CELLS * AREA_CELLS];
+        while reader.offset < data.len() {
+            let fourcc = reader.bytes(4)?;
+            let size = reader.u32()? as usize;
+            let mut chunk = reader.sub(size)?;
+            if fourcc != CELLS_CHUNK {
+                continue;
+            }
+            while chunk.offset < chunk.data.len() {
+                let info = chunk.u32()?;
+                let index = (info >> 24) as usize;
+                let mut cell = chunk.sub((info & 0x00ff_ffff) as usize)?;
+                if cell.u32()? & FLAG_HEIGHT_MAP == 0 {
+                    continue;
+                }
+                let samples = (0..HEIGHT_MAP_SIZE * HEIGHT_MAP_SIZE)
+                    .map(|_| cell.u16().map(decode_height))
+                    .collect::<TerrainResult<_>>()?;
+                cells[index] = Some(HeightMap { samples });
+            }
+        }
+        Ok(Self { x, z, cells })
+    }
+
+    /// Returns the position of the area in the grid of its map.
+    pub fn position(&self) -> (u8, u8) {
+        (self.x, self.z)
+    }
+
+    /// Returns the height map and the position in it of a point of the area,
+    /// in meters from the area's corner.
+    fn cell(&self, x: f32, z: f32) -> Option<(&HeightMap, f32, f32)> {
+        let column = (x / CELL_SIZE) as usize;
+        let row = (z / CELL_SIZE) as usize;
+        let cell = self.cells.get(row * AREA_CELLS + column)?.as_ref()?;
+        Some((
+            cell,
+            x - column as f32 * CELL_SIZE,
+            z - row as f32 * CELL_SIZE,
+        ))
+    }
+}
+
+fn decode_height(raw: u16) -> f32 {
+    (raw & 0x7fff) as f32 / 8.0 - 2048.0
+}
+
+/// The terrain of a map, made of the areas that were loaded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Terrain {
+    areas: HashMap<(u8, u8), AreaFile>,
+}
+
+impl Terrain {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the `.area` files of a directory.
+    ///
+    /// The position of an area is read from its file name, which ends like
+    /// the client's with its column then its row in hex, like
+    /// `Eastern.3f41.area`.
+    pub fn load_dir(dir: &Path) -> TerrainResult<Self> {
+        let mut terrain = Self::new();
+        let entries = std::fs::read_dir(dir).map_err(|error| TerrainError::Io {
+            path: dir.to_path_buf(),
+            error,
+        })?;
+        for entry in entries {
+            let path = entry
+                .map_err(|error| TerrainError::Io {
+                    path: dir.to_path_buf(),
+                    error,
+                })?
+                .path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "area")
+            {
+                let (x, z) = area_position(&path)
+                    .ok_or_else(|| TerrainError::InvalidFileName(path.clone()))?;
+                let data = std::fs::read(&path).map_err(|error| TerrainError::Io {
+                    path: path.clone(),
+                    error,
+                })?;
+                terrain.insert(AreaFile::parse(x, z, &data)?);
+            }
+        }
+        Ok(terrain)
+    }
+
+    pub fn insert(&mut self, area: AreaFile) {
+        self.areas.insert(area.position(), area);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.areas.is_empty()
+    }
+
+    fn height_map(&self, x: f32, z: f32) -> Option<(&HeightMap, f32, f32)> {
+        let half = AREA_SIZE * (MAP_AREAS / 2) as f32;
+        let (x, z) = (x + half, z + half);
+        if !(0.0..AREA_SIZE * MAP_AREAS as f32).contains(&x)
+            || !(0.0..AREA_SIZE * MAP_AREAS as f32).contains(&z)
+        {
+            return None;
+        }
+        let (area_x, area_z) = ((x / AREA_SIZE) as u8, (z / AREA_SIZE) as u8);
+        let area = self.areas.get(&(area_x, area_z))?;
+        area.cell(x - area_x as f32 * AREA_SIZE, z - area_z as f32 * AREA_SIZE)
+    }
+
+    /// Returns the height of the ground at a point, if its cell was loaded.
+    pub fn get_height(&self, x: f32, z: f32) -> Option<f32> {
+        let (cell, x, z) = self.height_map(x, z)?;
+        Some(cell.height(x, z))
+    }
+
+    /// Returns whether characters can stand at a point, which needs its cell
+    /// to be loaded and the ground around it not to be too steep.
+    pub fn is_walkable(&self, x: f32, z: f32) -> bool {
+        self.height_map(x, z)
+            .is_some_and(|(cell, x, z)| cell.slope(x, z) <= MAX_WALKABLE_SLOPE)
+    }
+}
+
+/// Reads the `XXZZ` at the end of the stem of an area file name.
+fn area_position(path: &Path) -> Option<(u8, u8)> {
+    let stem = path.file_stem()?.to_str()?;
+    let position = stem.rsplit('.').next()?;
+    if position.len() != 4 {
+        return None;
+    }
+    let x = u8::from_str_radix(position.get(..2)?, 16).ok()?;
+    let z = u8::from_str_radix(position.get(2..)?, 16).ok()?;
+    (x < MAP_AREAS && z < MAP_AREAS).then_some((x, z))
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> TerrainResult<&'a [u8]> {
+        let bytes =
+            self.data
+                .get(self.offset..self.offset + len)
+                .ok_or(TerrainError::UnexpectedEnd {
+                    offset: self.offset,
+                })?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn sub(&mut self, len: usize) -> TerrainResult<Reader<'a>> {
+        Ok(Reader {
+            data: self.bytes(len)?,
+            offset: 0,
+        })
+    }
+
+    fn u16(&mut self) -> TerrainResult<u16> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> TerrainResult<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+}
+
+#[derive(Debug)]
+pub enum TerrainError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    InvalidMagic,
+    /// The data ended in the middle of a chunk or a cell, at an offset of the
+    /// chunk or the cell.
+    UnexpectedEnd {
+        offset: usize,
+    },
+    /// The name of an area file doesn't end with its position.
+    InvalidFileName(PathBuf),
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "failed to read {}: {error}", path.display()),
+            Self::InvalidMagic => write!(f, "not an area file"),
+            Self::UnexpectedEnd { offset } => write!(f, "unexpected end of data at {offset}"),
+            Self::InvalidFileName(path) => {
+                write!(f, "no area position in the name of {}", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for TerrainError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+pub type TerrainResult<T = ()> = Result<T, TerrainError>;
+
+/// Builds an area file with a single cell, whose heights are given by
+/// `height` for each column and row of its height map.
+#[cfg(test)]
+pub(crate) fn build_area(index: u8, height: impl Fn(usize, usize) -> f32) -> Vec<u8> {
+    let mut cell = FLAG_HEIGHT_MAP.to_le_bytes().to_vec();
+    for row in 0..HEIGHT_MAP_SIZE {
+        for column in 0..HEIGHT_MAP_SIZE {
+            let raw = ((height(column, row) + 2048.0) * 8.0) as u16;
+            cell.extend_from_slice(&raw.to_le_bytes());
+        }
+    }
+    let mut chunk = ((index as u32) << 24 | cell.len() as u32)
+        .to_le_bytes()
+        .to_vec();
+    chunk.extend_from_slice(&cell);
+
+    let mut data = MAGIC.to_vec();
+    data.extend_from_slice(&1u32.to_le_bytes());
+    // chunks that aren't cells are skipped
+    data.extend_from_slice(b"XEDT");
+    data.extend_from_slice(&2u32.to_le_bytes());
+    data.extend_from_slice(&[0, 0]);
+    data.extend_from_slice(&CELLS_CHUNK);
+    data.extend_from_slice(&(chunk.len() as u32).to_le_bytes());
+    data.extend_from_slice(&chunk);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heights_and_walkability() {
+        // a slope rising by half a meter per meter along x, then a wall
+        let data = build_area(0, |column, _| match column {
+            0..=12 => column as f32,
+            _ => 100.0,
+        });
+        let mut terrain = Terrain::new();
+        terrain.insert(AreaFile::parse(64, 64, &data).unwrap());
+
+        // the border sample comes before the cell
+        assert_eq!(terrain.get_height(0.0, 5.0), Some(1.0));
+        assert_eq!(terrain.get_height(3.0, 5.0), Some(2.5));
+        assert!(terrain.is_walkable(3.0, 5.0));
+        assert!(!terrain.is_walkable(23.0, 5.0));
+        // cells and areas that weren't loaded
+        assert_eq!(terrain.get_height(40.0, 5.0), None);
+        assert_eq!(terrain.get_height(-1.0, 5.0), None);
+        assert!(!terrain.is_walkable(40.0, 5.0));
+        assert_eq!(terrain.get_height(1e6, 0.0), None);
+
+        assert!(matches!(
+            AreaFile::parse(0, 0, b"ARE"),
+            Err(TerrainError::UnexpectedEnd { offset: 0 })
+        ));
+        assert!(matches!(
+            AreaFile::parse(0, 0, &data[..data.len() - 1]),
+            Err(TerrainError::UnexpectedEnd { .. })
+        ));
+    }
+
+    #[test]
+    fn test_area_position() {
+        assert_eq!(
+            area_position(Path::new("maps/Eastern.3f41.area")),
+            Some((0x3f, 0x41))
+        );
+        assert_eq!(
+            area_position(Path::new("maps/3f41.area")),
+            Some((0x3f, 0x41))
+        );
+        assert_eq!(area_position(Path::new("maps/Eastern.ff41.area")), None);
+        assert_eq!(area_position(Path::new("maps/Eastern.area")), None);
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::data::load_table;
+use crate::*;
+
+/// The locale of the built-in texts, used for the players who didn't choose
+/// another.
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// A text that the server writes for the players, instead of a hardcoded
+/// string. Each has a built-in English version, and the [`TextTables`] can
+/// translate it in other locales.
+///
+/// None of the messages that the world server sends take a text id, so the
+/// texts are written in the locale of each player before they are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextId {
+    UnknownCommand,
+    MissingArgument,
+    InvalidArgument,
+    TooManyArguments,
+    NotInWorld,
+    NetworkError,
+    Commands,
+    Teleported,
+    GroundTooSteep,
+    SpawnCountOutOfRange,
+    Spawned,
+    LevelOutOfRange,
+    LevelSet,
+    UnknownItem,
+    ItemsAdded,
+    ReloadFailed,
+    Reloaded,
+    Language,
+    LanguageSet,
+    UnknownLanguage,
+    InvalidCount,
+    InventoryFull,
+    InvalidSlot,
+    ItemNotFound,
+    NotEnoughItems,
+    ExchangeSender,
+    OrderFilled,
+    Bought,
+    Sold,
+    OrderCancelled,
+    OrderItemsAttached,
+}
+
+impl TextId {
+    /// Returns the built-in English text, where `{0}`, `{1}`... stand for the
+    /// parameters.
+    pub fn english(self) -> &'static str {
+        match self {
+            Self::UnknownCommand => "Unknown command \"{0}\", try !help",
+            Self::MissingArgument => "Missing argument <{0}>",
+            Self::InvalidArgument => "Invalid value \"{1}\" for <{0}>",
+            Self::TooManyArguments => "Too many arguments",
+            Self::NotInWorld => "This command needs a character in the world",
+            Self::NetworkError => "Network error: {0}",
+            Self::Commands => "Commands:",
+            Self::Teleported => "Teleported to {0} {1} {2} in world {3}",
+            Self::GroundTooSteep => "The ground there is too steep to stand on",
+            Self::SpawnCountOutOfRange => "Count must be between 1 and {0}",
+            Self::Spawned => "Spawned {0} creature(s)",
+            Self::LevelOutOfRange => "Level must be between 1 and {0}",
+            Self::LevelSet => "Level set to {0}",
+            Self::UnknownItem => "Unknown item {0}",
+            Self::ItemsAdded => "Added {0} x {1}",
+            Self::ReloadFailed => "Reload failed: {0}",
+            Self::Reloaded => "Reloaded {0} spawn point(s) and {1} item template(s)",
+            Self::Language => "Your language is {0}, the others are: {1}",
+            Self::LanguageSet => "Language set to {0}",
+            Self::UnknownLanguage => "Unknown language {0}, the languages are: {1}",
+            Self::InvalidCount => "Count must be at least 1",
+            Self::InventoryFull => "The inventory is full",
+            Self::InvalidSlot => "No slot {0} in the {1}",
+            Self::ItemNotFound => "No item {0} in the inventory",
+            Self::NotEnoughItems => "Not enough of item {0} in the inventory",
+            Self::ExchangeSender => "Commodity Exchange",
+            Self::OrderFilled => "Order filled",
+            Self::Bought => "Bought {0} {1} for {2} each.",
+            Self::Sold => "Sold {0} {1} for {2} each.",
+            Self::OrderCancelled => "Order cancelled",
+            Self::OrderItemsAttached => "The items of your order are attached.",
+        }
+    }
+}
+
+/// A text with its parameters, written in English when displayed, or in
+/// the locale of a player with [`TextTables::render`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    pub id: TextId,
+    pub params: Vec<String>,
+}
+
+impl Text {
+    pub fn new(id: TextId) -> Self {
+        Self {
+            id,
+            params: Vec::new(),
+        }
+    }
+
+    /// Adds the next parameter, `{0}` for the first one.
+    pub fn param(mut self, value: impl fmt::Display) -> Self {
+        self.params.push(value.to_string());
+        self
+    }
+}
+
+impl From<TextId> for Text {
+    fn from(id: TextId) -> Self {
+        Self::new(id)
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&substitute(self.id.english(), &self.params))
+    }
+}
+
+/// Replaces the `{n}` of a text by the parameters. Those without a parameter
+/// are left as is.
+fn substitute(text: &str, params: &[String]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let param = rest
+            .find('}')
+            .and_then(|end| Some((rest[1..end].parse::<usize>().ok()?, end)))
+            .and_then(|(index, end)| Some((params.get(index)?, end)));
+        match param {
+            Some((param, end)) => {
+                result.push_str(param);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// A row of a text table.
+#[derive(Debug, Deserialize)]
+struct TextRecord {
+    key: TextId,
+    text: String,
+}
+
+/// The translations of the [`TextId`]s, by locale.
+///
+/// Texts missing from the table of a locale are written in English.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TextTables {
+    locales: BTreeMap<String, HashMap<TextId, String>>,
+}
+
+impl TextTables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Loads the table of each locale from `<locale>.csv` or `<locale>.json`
+    /// in a directory, with a `key` and a `text` field. The keys are the ids
+    /// in snake case, like `level_set`.
+    pub fn load(dir: &Path) -> DataResult<Self> {
+        let mut tables = Self::new();
+        if !dir.exists() {
+            return Ok(tables);
+        }
+        let entries = std::fs::read_dir(dir).map_err(|error| DataError::Io {
+            path: dir.to_path_buf(),
+            error,
+        })?;
+        for entry in entries {
+            let path = entry
+                .map_err(|error| DataError::Io {
+                    path: dir.to_path_buf(),
+                    error,
+                })?
+                .path();
+            let is_table = matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("csv" | "json")
+            );
+            let locale = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(locale) if is_table => locale,
+                _ => continue,
+            };
+            for record in load_table::<TextRecord>(dir, locale)? {
+                tables.insert(locale, record.key, record.text);
+            }
+        }
+        Ok(tables)
+    }
+
+    pub fn insert(&mut self, locale: &str, id: TextId, text: impl Into<String>) {
+        self.locales
+            .entry(locale.to_string())
+            .or_default()
+            .insert(id, text.into());
+    }
+
+    /// Returns the locales that texts can be written in, the default one
+    /// included.
+    pub fn locales(&self) -> Vec<&str> {
+        let mut locales: Vec<&str> = self.locales.keys().map(String::as_str).collect();
+        if !self.locales.contains_key(DEFAULT_LOCALE) {
+            locales.insert(0, DEFAULT_LOCALE);
+        }
+        locales
+    }
+
+    /// Returns the locale named like `name`, ignoring the case.
+    pub fn find_locale(&self, name: &str) -> Option<&str> {
+        self.locales()
+            .into_iter()
+            .find(|locale| locale.eq_ignore_ascii_case(name))
+    }
+
+    /// Writes a text in a locale, in English if the locale doesn't have it.
+    pub fn render(&self, locale: &str, text: &Text) -> String {
+        let translated = self
+            .locales
+            .get(locale)
+            .and_then(|table| table.get(&text.id));
+        match translated {
+            Some(translated) => substitute(translated, &text.params),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut tables = TextTables::new();
+        tables.insert("fr-FR", TextId::ItemsAdded, "{1} ajouté(s) {0} fois");
+        let text = Text::new(TextId::ItemsAdded).param(3).param("Potion");
+        assert_eq!(text.to_string(), "Added 3 x Potion");
+        assert_eq!(tables.render("fr-FR", &text), "Potion ajouté(s) 3 fois");
+        // missing translations and locales fall back to English
+        let text = Text::new(TextId::LevelSet).param(12);
+        assert_eq!(tables.render("fr-FR", &text), "Level set to 12");
+        assert_eq!(tables.render("de-DE", &text), "Level set to 12");
+
+        assert_eq!(tables.locales(), ["en-US", "fr-FR"]);
+        assert_eq!(tables.find_locale("FR-fr"), Some("fr-FR"));
+        assert_eq!(tables.find_locale("de-DE"), None);
+    }
+
+    #[test]
+    fn test_substitute() {
+        let params = ["a".to_string(), "b".to_string()];
+        assert_eq!(substitute("{1}{0} {0}", &params), "ba a");
+        assert_eq!(substitute("{2} {x} {", &params), "{2} {x} {");
+        assert_eq!(substitute("{}", &[]), "{}");
+    }
+
+    #[test]
+    fn test_load() {
+        let dir = std::env::temp_dir().join(format!("ws_world_text_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("fr-FR.csv"),
+            "key,text\nlevel_set,Niveau {0}\nspawned,{0} créature(s)\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a table").unwrap();
+        let tables = TextTables::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let text = Text::new(TextId::LevelSet).param(5);
+        assert_eq!(tables.render("fr-FR", &text), "Niveau 5");
+        assert_eq!(tables.locales(), ["en-US", "fr-FR"]);
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use uuid::Uuid;
+use ws_protocol::{TicketClaims, TicketError, TicketKey};
+
+/// Checks the tickets that the clients log in with, which the realm server
+/// signed for their account and the realm of the world server.
+///
+/// Each ticket can only be redeemed once: the ones redeemed are remembered
+/// until they expire.
+#[derive(Debug)]
+pub struct TicketChecker {
+    key: TicketKey,
+    realm_id: u32,
+    /// The tickets redeemed, with their expiry.
+    redeemed: Mutex<HashMap<Uuid, u32>>,
+}
+
+impl TicketChecker {
+    pub fn new(key: TicketKey, realm_id: u32) -> Self {
+        Self {
+            key,
+            realm_id,
+            redeemed: Default::default(),
+        }
+    }
+
+    /// Redeems the ticket of an account at `now`, in seconds since the Unix
+    /// epoch.
+    pub fn redeem(
+        &self,
+        ticket: &Uuid,
+        account_id: u32,
+        now: u64,
+    ) -> Result<TicketClaims, TicketError> {
+        let claims = self.key.verify(ticket, account_id, self.realm_id, now)?;
+        let mut redeemed = self.redeemed.lock().unwrap();
+        redeemed.retain(|_, expires_at| u64::from(*expires_at) > now);
+        if redeemed.insert(*ticket, claims.expires_at).is_some() {
+            return Err(TicketError::Redeemed);
+        }
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redeem() {
+        let key = TicketKey::new(&[3; 16]).unwrap();
+        let checker = TicketChecker::new(key.clone(), 1);
+        let ticket = key.issue(&TicketClaims {
+            account_id: 430,
+            realm_id: 1,
+            expires_at: 100,
+        });
+        assert_eq!(
+            checker.redeem(&ticket, 431, 50),
+            Err(TicketError::InvalidSignature)
+        );
+        assert!(checker.redeem(&ticket, 430, 50).is_ok());
+        assert_eq!(checker.redeem(&ticket, 430, 60), Err(TicketError::Redeemed));
+        assert_eq!(checker.redeem(&ticket, 430, 100), Err(TicketError::Expired));
+
+        // the tickets of other realms are refused
+        let other_realm = key.issue(&TicketClaims {
+            account_id: 430,
+            realm_id: 2,
+            expires_at: 100,
+        });
+        assert_eq!(
+            checker.redeem(&other_realm, 430, 50),
+            Err(TicketError::InvalidSignature)
+        );
+    }
+}
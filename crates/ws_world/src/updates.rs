@@ -0,0 +1,422 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use ws_bitpack::*;
+use ws_messages::*;
+
+use crate::{Guid, Position};
+
+/// An entity that came into view.
+#[derive(MessageStruct, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EntityCreate {
+    pub guid: Guid,
+    pub position: Position,
+    pub yaw: f32,
+}
+
+/// How an entity moved since the previous update.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Movement {
+    /// The offset from the previous position, in units of
+    /// `1 / UpdateConfig::precision`.
+    Delta { dx: i32, dy: i32, dz: i32 },
+    /// The new position, when the offset doesn't fit in the delta bits.
+    Absolute(Position),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct EntityMove {
+    pub guid: Guid,
+    pub movement: Movement,
+}
+
+/// The changes to the entities around a player during a tick.
+///
+/// Moves are packed with a number of bits given by `delta_bits`, so this message
+/// is written by hand instead of derived.
+#[derive(Message, Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[message_id(0x0640)]
+pub struct ServerWorldUpdate {
+    pub delta_bits: u8,
+    pub creates: Vec<EntityCreate>,
+    pub moves: Vec<EntityMove>,
+    pub destroys: Vec<Guid>,
+}
+
+impl ServerWorldUpdate {
+    const DELTA_BITS_BITS: usize = 6;
+    const COUNT_BITS: usize = 16;
+
+    fn move_bits(&self, entity_move: &EntityMove) -> usize {
+        64 + 1
+            + match entity_move.movement {
+                Movement::Delta { .. } => 3 * self.delta_bits as usize,
+                Movement::Absolute(position) => position.bits(),
+            }
+    }
+}
+
+impl MessageStruct for ServerWorldUpdate {}
+
+impl ReadValue for ServerWorldUpdate {
+    fn read(reader: &mut BitPackReader) -> BitPackResult<Self> {
+        reader.nested(|reader| {
+            let delta_bits: u8 = reader.read_packed(Self::DELTA_BITS_BITS)?;
+            let bits = delta_bits as usize;
+
+            let count: usize = reader.read_packed(Self::COUNT_BITS)?;
+            let creates = reader.read_array(count)?;
+
+            let count: usize = reader.read_packed(Self::COUNT_BITS)?;
+            let mut moves = Vec::with_capacity(count.min(reader.remaining_bits()));
+            for _ in 0..count {
+                let guid = reader.read()?;
+                let movement = match reader.read_bit()? {
+                    true => Movement::Absolute(reader.read()?),
+                    false => Movement::Delta {
+                        dx: reader.read_packed(bits)?,
+                        dy: reader.read_packed(bits)?,
+                        dz: reader.read_packed(bits)?,
+                    },
+                };
+                moves.push(EntityMove { guid, movement });
+            }
+
+            let count: usize = reader.read_packed(Self::COUNT_BITS)?;
+            let destroys = reader.read_array(count)?;
+
+            Ok(Self {
+                delta_bits,
+                creates,
+                moves,
+                destroys,
+            })
+        })
+    }
+}
+
+impl WriteValue for ServerWorldUpdate {
+    fn write(&self, writer: &mut BitPackWriter) -> BitPackResult {
+        let bits = self.delta_bits as usize;
+        writer.write_packed(&self.delta_bits, Self::DELTA_BITS_BITS)?;
+
+        writer.write_packed(&self.creates.len(), Self::COUNT_BITS)?;
+        writer.write_array(&self.creates)?;
+
+        writer.write_packed(&self.moves.len(), Self::COUNT_BITS)?;
+        for entity_move in &self.moves {
+            writer.write(&entity_move.guid)?;
+            match &entity_move.movement {
+                Movement::Absolute(position) => {
+                    writer.write_bit(true)?;
+                    writer.write(position)?;
+                }
+                Movement::Delta { dx, dy, dz } => {
+                    writer.write_bit(false)?;
+                    writer.write_packed(dx, bits)?;
+                    writer.write_packed(dy, bits)?;
+                    writer.write_packed(dz, bits)?;
+                }
+            }
+        }
+
+        writer.write_packed(&self.destroys.len(), Self::COUNT_BITS)?;
+        writer.write_array(&self.destroys)
+    }
+
+    fn bits(&self) -> usize {
+        Self::DELTA_BITS_BITS
+            + 3 * Self::COUNT_BITS
+            + self.creates.bits_array()
+            + self.moves.iter().map(|m| self.move_bits(m)).sum::<usize>()
+            + self.destroys.bits_array()
+    }
+}
+
+/// How [`UpdateBuilder`] packs updates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UpdateConfig {
+    /// The number of bits of each axis of a position delta, sign included.
+    pub delta_bits: u8,
+    /// The number of delta units per world unit.
+    pub precision: f32,
+    /// The largest message body to build, in bits. Larger updates are split.
+    pub max_message_bits: usize,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            delta_bits: 12,
+            precision: 16.0,
+            // leaves room for the frame header in an 8 KiB frame
+            max_message_bits: 8 * 8184,
+        }
+    }
+}
+
+/// Collects the entity changes of a tick and turns them into world updates.
+///
+/// Positions are sent as deltas from the last position sent for the entity, so
+/// the builder keeps track of what the receiving client knows. Use one builder
+/// per receiving session.
+#[derive(Debug, Default)]
+pub struct UpdateBuilder {
+    config: UpdateConfig,
+    /// The positions known by the client, as it reconstructs them.
+    known: HashMap<Guid, Position>,
+    creates: BTreeMap<Guid, EntityCreate>,
+    moves: BTreeMap<Guid, Position>,
+    destroys: Vec<Guid>,
+}
+
+impl UpdateBuilder {
+    pub fn new(config: UpdateConfig) -> Self {
+        assert!(
+            (2..=32).contains(&config.delta_bits),
+            "Delta bits must be between 2 and 32"
+        );
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn create(&mut self, guid: Guid, position: Position, yaw: f32) {
+        self.moves.remove(&guid);
+        self.destroys.retain(|destroyed| *destroyed != guid);
+        self.creates.insert(
+            guid,
+            EntityCreate {
+                guid,
+                position,
+                yaw,
+            },
+        );
+    }
+
+    /// Moves an entity. Only the last move of a tick is sent.
+    pub fn move_to(&mut self, guid: Guid, position: Position) {
+        match self.creates.get_mut(&guid) {
+            Some(create) => create.position = position,
+            None => {
+                self.moves.insert(guid, position);
+            }
+        }
+    }
+
+    pub fn destroy(&mut self, guid: Guid) {
+        self.moves.remove(&guid);
+        // an entity created and destroyed in the same tick was never seen
+        if self.creates.remove(&guid).is_none() && self.known.contains_key(&guid) {
+            self.destroys.push(guid);
+        }
+    }
+
+    /// Returns true if the entity is created by the next build.
+    pub fn is_created(&self, guid: Guid) -> bool {
+        self.creates.contains_key(&guid)
+    }
+
+    /// Returns true if there is nothing to send.
+    pub fn is_empty(&self) -> bool {
+        self.creates.is_empty() && self.moves.is_empty() && self.destroys.is_empty()
+    }
+
+    /// Builds the updates of the tick, split in messages no larger than the
+    /// configured size, and clears the builder for the next tick.
+    pub fn build(&mut self) -> Vec<ServerWorldUpdate> {
+        self.build_due(|_| true)
+    }
+
+    /// Builds the updates of the tick like [`UpdateBuilder::build`], except
+    /// for the moves of the entities that aren't `due`. Those are held back
+    /// until a later build, replaced by the moves that come after them.
+    pub fn build_due(&mut self, mut due: impl FnMut(Guid) -> bool) -> Vec<ServerWorldUpdate> {
+        let mut messages = Vec::new();
+        let new_message = || ServerWorldUpdate {
+            delta_bits: self.config.delta_bits,
+            ..Default::default()
+        };
+        let mut current = new_message();
+        let mut current_bits = current.bits();
+        let max_bits = self.config.max_message_bits;
+
+        let mut push = |current: &mut ServerWorldUpdate, current_bits: &mut usize, bits| {
+            if *current_bits + bits > max_bits && *current != new_message() {
+                messages.push(std::mem::replace(current, new_message()));
+                *current_bits = current.bits();
+            }
+            *current_bits += bits;
+        };
+
+        for create in std::mem::take(&mut self.creates).into_values() {
+            push(&mut current, &mut current_bits, create.bits());
+            self.known.insert(create.guid, create.position);
+            current.creates.push(create);
+        }
+
+        let (moves, held) = std::mem::take(&mut self.moves)
+            .into_iter()
+            .partition(|(guid, _)| due(*guid));
+        self.moves = held;
+        for (guid, position) in moves {
+            let entity_move = match self.known.get_mut(&guid) {
+                Some(known) => Self::movement(&self.config, guid, known, position),
+                None => {
+                    self.known.insert(guid, position);
+                    EntityMove {
+                        guid,
+                        movement: Movement::Absolute(position),
+                    }
+                }
+            };
+            let bits = current.move_bits(&entity_move);
+            push(&mut current, &mut current_bits, bits);
+            current.moves.push(entity_move);
+        }
+
+        for guid in std::mem::take(&mut self.destroys) {
+            push(&mut current, &mut current_bits, guid.bits());
+            self.known.remove(&guid);
+            current.destroys.push(guid);
+        }
+
+        if current != new_message() {
+            messages.push(current);
+        }
+        messages
+    }
+
+    /// Computes the movement from the known position, and updates it to what the
+    /// client will reconstruct.
+    fn movement(
+        config: &UpdateConfig,
+        guid: Guid,
+        known: &mut Position,
+        position: Position,
+    ) -> EntityMove {
+        let max = (1i64 << (config.delta_bits - 1)) - 1;
+        let delta = |from: f32, to: f32| {
+            let delta = ((to - from) * config.precision).round() as i64;
+            (-max..=max).contains(&delta).then_some(delta as i32)
+        };
+
+        let movement = match (
+            delta(known.x, position.x),
+            delta(known.y, position.y),
+            delta(known.z, position.z),
+        ) {
+            (Some(dx), Some(dy), Some(dz)) => {
+                known.x += dx as f32 / config.precision;
+                known.y += dy as f32 / config.precision;
+                known.z += dz as f32 / config.precision;
+                Movement::Delta { dx, dy, dz }
+            }
+            _ => {
+                *known = position;
+                Movement::Absolute(position)
+            }
+        };
+        EntityMove { guid, movement }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityType, GuidAllocator};
+
+    fn position(x: f32, y: f32, z: f32) -> Position {
+        Position { x, y, z }
+    }
+
+    fn round_trip(update: &ServerWorldUpdate) -> ServerWorldUpdate {
+        let mut writer = BitPackWriter::growable();
+        writer.write(update).unwrap();
+        assert_eq!(writer.position(), update.bits());
+        let buffer = writer.finish().unwrap();
+        BitPackReader::new(&buffer).read_to_end().unwrap()
+    }
+
+    #[test]
+    fn test_update_builder() {
+        let guids = GuidAllocator::new(1);
+        let first = guids.allocate(EntityType::Creature);
+        let second = guids.allocate(EntityType::Creature);
+        let mut builder = UpdateBuilder::new(UpdateConfig::default());
+
+        builder.create(first, position(10.0, 0.0, 10.0), 0.0);
+        builder.create(second, position(20.0, 0.0, 20.0), 0.0);
+        builder.move_to(first, position(11.0, 0.0, 10.0));
+        let updates = builder.build();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].creates.len(), 2);
+        assert_eq!(updates[0].creates[0].position, position(11.0, 0.0, 10.0));
+        assert_eq!(round_trip(&updates[0]), updates[0]);
+        assert!(builder.is_empty());
+
+        builder.move_to(first, position(12.5, 0.0, 9.0));
+        builder.move_to(second, position(5000.0, 0.0, 20.0));
+        builder.destroy(second);
+        let updates = builder.build();
+        assert_eq!(
+            updates[0].moves,
+            [EntityMove {
+                guid: first,
+                movement: Movement::Delta {
+                    dx: 24,
+                    dy: 0,
+                    dz: -16
+                },
+            }]
+        );
+        assert_eq!(updates[0].destroys, [second]);
+        assert_eq!(round_trip(&updates[0]), updates[0]);
+
+        // a move too far for the delta bits is sent as an absolute position
+        builder.move_to(first, position(1000.0, 0.0, 9.0));
+        let updates = builder.build();
+        assert_eq!(
+            updates[0].moves[0].movement,
+            Movement::Absolute(position(1000.0, 0.0, 9.0))
+        );
+        assert_eq!(round_trip(&updates[0]), updates[0]);
+
+        // moves that aren't due are held back, and superseded by later ones
+        builder.move_to(first, position(1001.0, 0.0, 9.0));
+        assert!(builder.build_due(|guid| guid != first).is_empty());
+        builder.move_to(first, position(1002.0, 0.0, 9.0));
+        let updates = builder.build();
+        assert_eq!(
+            updates[0].moves[0].movement,
+            Movement::Delta {
+                dx: 32,
+                dy: 0,
+                dz: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_update_builder_split() {
+        let guids = GuidAllocator::new(1);
+        let config = UpdateConfig {
+            max_message_bits: 1000,
+            ..Default::default()
+        };
+        let mut builder = UpdateBuilder::new(config);
+        for _ in 0..20 {
+            builder.create(
+                guids.allocate(EntityType::WorldObject),
+                Position::default(),
+                0.0,
+            );
+        }
+
+        let updates = builder.build();
+        assert!(updates.len() > 1);
+        assert!(updates.iter().all(|update| update.bits() <= 1000));
+        assert_eq!(updates.iter().map(|u| u.creates.len()).sum::<usize>(), 20);
+    }
+}
@@ -0,0 +1,335 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use ws_bitpack::WriteValue;
+use ws_messages::Message;
+use ws_net::Session;
+
+use crate::{Guid, Position};
+
+/// The coordinates of a grid cell on the horizontal plane.
+pub type CellCoord = (i32, i32);
+
+/// Who started or stopped seeing what after an entity was added, moved or
+/// removed.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VisibilityChange {
+    /// The observers that can now see the entity.
+    pub seen_by: Vec<Guid>,
+    /// The observers that can no longer see the entity.
+    pub unseen_by: Vec<Guid>,
+    /// The entities that the entity can now see, if it is an observer.
+    pub appeared: Vec<Guid>,
+    /// The entities that the entity can no longer see, if it is an observer.
+    pub disappeared: Vec<Guid>,
+}
+
+impl VisibilityChange {
+    pub fn is_empty(&self) -> bool {
+        self.seen_by.is_empty()
+            && self.unseen_by.is_empty()
+            && self.appeared.is_empty()
+            && self.disappeared.is_empty()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Cell {
+    entities: HashSet<Guid>,
+    observers: HashSet<Guid>,
+}
+
+#[derive(Debug)]
+struct GridEntity<S> {
+    cell: CellCoord,
+    observer: Option<S>,
+}
+
+/// Tracks which entities of a map can see each other, so that messages about
+/// an entity are only sent to the sessions around it.
+///
+/// The map is split in square cells on the x and z axes. Observers, usually
+/// players, see every entity in the cells within `view_radius` cells of their
+/// own. Observers are entities too, and are seen by each other.
+///
+/// The observer type is a [`Session`] on a server, but anything can be used to
+/// only track visibility.
+#[derive(Debug)]
+pub struct VisibilityGrid<S = Session> {
+    cell_size: f32,
+    view_radius: i32,
+    cells: HashMap<CellCoord, Cell>,
+    entities: HashMap<Guid, GridEntity<S>>,
+}
+
+impl<S> VisibilityGrid<S> {
+    pub fn new(cell_size: f32, view_radius: u32) -> Self {
+        assert!(cell_size > 0.0, "Cell size must be positive");
+        Self {
+            cell_size,
+            view_radius: view_radius as i32,
+            cells: HashMap::new(),
+            entities: HashMap::new(),
+        }
+    }
+
+    pub fn cell_of(&self, position: &Position) -> CellCoord {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.z / self.cell_size).floor() as i32,
+        )
+    }
+
+    pub fn contains(&self, guid: Guid) -> bool {
+        self.entities.contains_key(&guid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    /// Adds an entity that doesn't observe anything, like a creature.
+    pub fn insert(&mut self, guid: Guid, position: &Position) -> VisibilityChange {
+        self.insert_entity(guid, position, None)
+    }
+
+    /// Adds an entity that sees the entities around it, like a player.
+    pub fn insert_observer(
+        &mut self,
+        guid: Guid,
+        position: &Position,
+        observer: S,
+    ) -> VisibilityChange {
+        self.insert_entity(guid, position, Some(observer))
+    }
+
+    fn insert_entity(
+        &mut self,
+        guid: Guid,
+        position: &Position,
+        observer: Option<S>,
+    ) -> VisibilityChange {
+        self.remove(guid);
+        let cell = self.cell_of(position);
+        let is_observer = observer.is_some();
+        self.entities.insert(guid, GridEntity { cell, observer });
+        self.add_to_cell(guid, cell, is_observer);
+
+        let mut change = VisibilityChange::default();
+        for coord in self.cells_around(cell) {
+            self.collect_cell(
+                guid,
+                coord,
+                is_observer,
+                &mut change.seen_by,
+                &mut change.appeared,
+            );
+        }
+        change
+    }
+
+    /// Moves an entity, and returns the visibility changes if it changed cells.
+    pub fn move_entity(&mut self, guid: Guid, position: &Position) -> VisibilityChange {
+        let mut change = VisibilityChange::default();
+        let cell = self.cell_of(position);
+        let (old_cell, is_observer) = match self.entities.get_mut(&guid) {
+            Some(entity) if entity.cell != cell => (
+                std::mem::replace(&mut entity.cell, cell),
+                entity.observer.is_some(),
+            ),
+            _ => return change,
+        };
+
+        self.remove_from_cell(guid, old_cell, is_observer);
+        self.add_to_cell(guid, cell, is_observer);
+
+        for coord in self.cells_around(cell) {
+            if !self.is_around(old_cell, coord) {
+                self.collect_cell(
+                    guid,
+                    coord,
+                    is_observer,
+                    &mut change.seen_by,
+                    &mut change.appeared,
+                );
+            }
+        }
+        for coord in self.cells_around(old_cell) {
+            if !self.is_around(cell, coord) {
+                self.collect_cell(
+                    guid,
+                    coord,
+                    is_observer,
+                    &mut change.unseen_by,
+                    &mut change.disappeared,
+                );
+            }
+        }
+        change
+    }
+
+    /// Removes an entity. The returned change only lists who stopped seeing it.
+    pub fn remove(&mut self, guid: Guid) -> VisibilityChange {
+        let mut change = VisibilityChange::default();
+        if let Some(entity) = self.entities.remove(&guid) {
+            self.remove_from_cell(guid, entity.cell, entity.observer.is_some());
+            for coord in self.cells_around(entity.cell) {
+                self.collect_cell(guid, coord, false, &mut change.unseen_by, &mut Vec::new());
+            }
+        }
+        change
+    }
+
+    /// Returns the observers that can see an entity, itself excluded.
+    pub fn observers_of(&self, guid: Guid) -> impl Iterator<Item = (Guid, &S)> + '_ {
+        let cells = match self.entities.get(&guid) {
+            Some(entity) => self.cells_around(entity.cell).collect(),
+            None => Vec::new(),
+        };
+        cells
+            .into_iter()
+            .filter_map(|coord| self.cells.get(&coord))
+            .flat_map(|cell| cell.observers.iter())
+            .filter(move |observer| **observer != guid)
+            .filter_map(|observer| {
+                let entity = self.entities.get(observer)?;
+                Some((*observer, entity.observer.as_ref()?))
+            })
+    }
+
+    /// Returns the entities that an observer can see, itself excluded.
+    pub fn visible_to(&self, observer: Guid) -> Vec<Guid> {
+        let entity = match self.entities.get(&observer) {
+            Some(entity) if entity.observer.is_some() => entity,
+            _ => return Vec::new(),
+        };
+        self.cells_around(entity.cell)
+            .filter_map(|coord| self.cells.get(&coord))
+            .flat_map(|cell| cell.entities.iter().copied())
+            .filter(|guid| *guid != observer)
+            .collect()
+    }
+
+    fn cells_around(&self, cell: CellCoord) -> impl Iterator<Item = CellCoord> {
+        let radius = self.view_radius;
+        (-radius..=radius)
+            .flat_map(move |dx| (-radius..=radius).map(move |dz| (cell.0 + dx, cell.1 + dz)))
+    }
+
+    fn is_around(&self, center: CellCoord, cell: CellCoord) -> bool {
+        (center.0 - cell.0).abs() <= self.view_radius
+            && (center.1 - cell.1).abs() <= self.view_radius
+    }
+
+    fn add_to_cell(&mut self, guid: Guid, cell: CellCoord, is_observer: bool) {
+        let cell = self.cells.entry(cell).or_default();
+        cell.entities.insert(guid);
+        if is_observer {
+            cell.observers.insert(guid);
+        }
+    }
+
+    fn remove_from_cell(&mut self, guid: Guid, coord: CellCoord, is_observer: bool) {
+        if let Some(cell) = self.cells.get_mut(&coord) {
+            cell.entities.remove(&guid);
+            if is_observer {
+                cell.observers.remove(&guid);
+            }
+            if cell.entities.is_empty() {
+                self.cells.remove(&coord);
+            }
+        }
+    }
+
+    /// Adds the observers of a cell to `observers`, and its entities to
+    /// `entities` if the entity is an observer itself.
+    fn collect_cell(
+        &self,
+        guid: Guid,
+        coord: CellCoord,
+        is_observer: bool,
+        observers: &mut Vec<Guid>,
+        entities: &mut Vec<Guid>,
+    ) {
+        let cell = match self.cells.get(&coord) {
+            Some(cell) => cell,
+            None => return,
+        };
+        observers.extend(cell.observers.iter().filter(|other| **other != guid));
+        if is_observer {
+            entities.extend(cell.entities.iter().filter(|other| **other != guid));
+        }
+    }
+}
+
+impl VisibilityGrid<Session> {
+    /// Sends a message to the sessions that can see an entity, and returns how
+    /// many it was sent to.
+    ///
+    /// The session of the entity itself doesn't get the message. Sessions that
+    /// closed are skipped, they are removed from the grid when disconnecting.
+    pub fn broadcast_to_visible<T>(&self, guid: Guid, message: &T) -> usize
+    where
+        T: Message + WriteValue + fmt::Debug,
+    {
+        self.observers_of(guid)
+            .filter(|(_, session)| session.send(message).is_ok())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{EntityType, GuidAllocator};
+
+    fn at(x: f32, z: f32) -> Position {
+        Position { x, y: 0.0, z }
+    }
+
+    fn sorted(mut guids: Vec<Guid>) -> Vec<Guid> {
+        guids.sort();
+        guids
+    }
+
+    #[test]
+    fn test_visibility_grid() {
+        let guids = GuidAllocator::new(1);
+        let player = guids.allocate(EntityType::Player);
+        let other = guids.allocate(EntityType::Player);
+        let creature = guids.allocate(EntityType::Creature);
+        let mut grid = VisibilityGrid::<&str>::new(64.0, 1);
+
+        grid.insert_observer(player, &at(10.0, 10.0), "player");
+        let change = grid.insert(creature, &at(100.0, 10.0));
+        assert_eq!(change.seen_by, [player]);
+        let change = grid.insert_observer(other, &at(1000.0, 10.0), "other");
+        assert!(change.is_empty());
+        assert_eq!(sorted(grid.visible_to(player)), [creature]);
+
+        let change = grid.move_entity(other, &at(120.0, 10.0));
+        assert_eq!(change.seen_by, [player]);
+        assert_eq!(sorted(change.appeared), sorted(vec![player, creature]));
+        assert_eq!(grid.observers_of(creature).count(), 2);
+
+        // moving within a cell doesn't change anything
+        assert!(grid.move_entity(other, &at(125.0, 20.0)).is_empty());
+
+        let change = grid.move_entity(player, &at(-200.0, 10.0));
+        assert_eq!(change.unseen_by, [other]);
+        assert_eq!(sorted(change.disappeared), sorted(vec![other, creature]));
+        assert_eq!(
+            grid.observers_of(creature).collect::<Vec<_>>(),
+            [(other, &"other")]
+        );
+
+        let change = grid.remove(creature);
+        assert_eq!(change.unseen_by, [other]);
+        assert!(grid.visible_to(other).is_empty());
+        assert_eq!(grid.len(), 2);
+    }
+}
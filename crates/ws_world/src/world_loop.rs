@@ -0,0 +1,908 @@
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use bevy_ecs::prelude::*;
+use bevy_ecs::system::ScheduleSystem;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio::time::MissedTickBehavior;
+use ws_metrics::metrics;
+use ws_net::Session;
+
+use crate::ecs::{
+    self, Creature, EntityGuid, GuidIndex, Movement, ScriptTimer, Stats, Viewer, Visibility, Yaw,
+};
+use crate::*;
+
+/// The number of ticks per second.
+pub const TICK_RATE: u32 = 30;
+
+/// The health of every entity, until creatures and classes have their own.
+const BASE_HEALTH: f32 = 100.0;
+const BASE_REGENERATION: f32 = 1.0;
+
+/// A change to the world, queued by the handlers with [`WorldServer::queue`]
+/// and applied at the start of the next tick.
+#[derive(Debug)]
+pub enum WorldEvent {
+    /// A player entered the world, and sees the entities around it.
+    Enter {
+        session: Session,
+        guid: Guid,
+        world_id: u32,
+        position: Position,
+        yaw: f32,
+    },
+    /// Adds an entity that doesn't see anything, like a creature.
+    Spawn {
+        guid: Guid,
+        world_id: u32,
+        position: Position,
+        yaw: f32,
+        /// The entity whose instance of the world it is added to, for the
+        /// worlds that have several. The shared instance otherwise.
+        near: Option<Guid>,
+    },
+    /// Moves an entity at once, possibly to another world or instance.
+    Teleport {
+        guid: Guid,
+        world_id: u32,
+        position: Position,
+    },
+    /// Adds an entity that left the instance of another world, sent by
+    /// [`ecs::leave_instance`] through the outbox of that instance.
+    Arrive(Box<ecs::Traveler>),
+    /// Walks an entity to a destination, at `speed` units per second.
+    MoveTo {
+        guid: Guid,
+        destination: Position,
+        speed: f32,
+    },
+    Remove {
+        guid: Guid,
+    },
+    /// Casts a spell, or tells the caster why it can't.
+    CastSpell {
+        guid: Guid,
+        spell_id: u32,
+        target: Guid,
+    },
+    /// Changes the group of a player, whose members see each other's updates
+    /// every tick.
+    SetGroup {
+        guid: Guid,
+        group_id: Option<u64>,
+    },
+    /// Sends a line said by a player to the players who see it, and to the
+    /// player itself.
+    Say {
+        guid: Guid,
+        chat: ServerChat,
+    },
+}
+
+/// Runs the simulation of a world server at a fixed tick.
+///
+/// The entities are kept in the ECS [`World`] of the instance of the map they
+/// are in, with the components of the [`ecs`] module. Every tick applies the
+/// events queued by the handlers since the previous one, routing them to the
+/// instances of the [`InstanceManager`], and the handlers of the creature
+/// [`Scripts`] that are due, then runs the systems of every instance in
+/// parallel, which advance the entities and send the world updates of the
+/// tick to the players who can see them. What an instance does to another,
+/// like moving a player there, goes through its [`Outbox`](ecs::Outbox) and
+/// is applied with the events of the next tick.
+/// Ticks taking longer than the tick duration are counted as overruns, and
+/// the ticks they delay are skipped.
+pub struct WorldLoop {
+    server: Arc<WorldServer>,
+    tick_duration: Duration,
+    instances: InstanceManager,
+    /// The tables the creatures of the spawn points were spawned from, to
+    /// spawn them again when they are reloaded.
+    tables: Option<Arc<DataTables>>,
+    /// The scripts whose handlers the creatures run.
+    scripts: Option<Arc<Scripts>>,
+    spells: SpellManager,
+    /// The time since the expired mail was last deleted.
+    since_mail_expiry: Duration,
+    /// The time since the commodity orders were last matched.
+    since_order_match: Duration,
+    overruns: u64,
+    ticks: u64,
+    /// The timings of the ticks since the last snapshot.
+    timings: TickTimings,
+    since_snapshot: Duration,
+    /// The last snapshot in JSON, empty until the first one.
+    snapshots: watch::Sender<String>,
+    /// Keeps the tasks spawned by the ticks until [`WorldLoop::settle`], in
+    /// deterministic mode.
+    deterministic: bool,
+    background: Vec<JoinHandle<()>>,
+}
+
+impl WorldLoop {
+    pub fn new(server: Arc<WorldServer>) -> Self {
+        Self {
+            server,
+            tick_duration: Duration::from_secs(1) / TICK_RATE,
+            instances: InstanceManager::new(),
+            tables: None,
+            scripts: None,
+            spells: SpellManager::new(),
+            since_mail_expiry: Duration::ZERO,
+            since_order_match: Duration::ZERO,
+            overruns: 0,
+            ticks: 0,
+            timings: TickTimings::default(),
+            since_snapshot: Duration::ZERO,
+            snapshots: watch::channel(String::new()).0,
+            deterministic: false,
+            background: Vec::new(),
+        }
+    }
+
+    /// Replaces the default tick duration of `1 / TICK_RATE` seconds.
+    pub fn with_tick_duration(mut self, tick_duration: Duration) -> Self {
+        assert!(!tick_duration.is_zero(), "Tick duration must be positive");
+        self.tick_duration = tick_duration;
+        self
+    }
+
+    /// Replaces the instance manager, to change how long idle instances are
+    /// kept.
+    pub fn with_instances(mut self, instances: InstanceManager) -> Self {
+        self.instances = instances;
+        self
+    }
+
+    /// Keeps the tasks spawned by the ticks, like the mail expiry, for
+    /// [`WorldLoop::settle`] to wait for them, so that a [`Replay`] gives the
+    /// same world every time.
+    pub fn with_deterministic(mut self) -> Self {
+        self.deterministic = true;
+        self
+    }
+
+    /// Adds a system run every tick by every instance, see
+    /// [`InstanceManager::add_system`].
+    pub fn add_system<M>(
+        &mut self,
+        system: impl IntoScheduleConfigs<ScheduleSystem, M> + Clone + Send + Sync + 'static,
+    ) {
+        self.instances.add_system(system);
+    }
+
+    pub fn tick_duration(&self) -> Duration {
+        self.tick_duration
+    }
+
+    /// Returns the number of ticks that took longer than the tick duration.
+    pub fn overruns(&self) -> u64 {
+        self.overruns
+    }
+
+    /// Receives the [`WorldSnapshot`]s in JSON, published every
+    /// [`SNAPSHOT_INTERVAL`] while anyone is subscribed.
+    pub fn snapshots(&self) -> watch::Receiver<String> {
+        self.snapshots.subscribe()
+    }
+
+    /// Returns the state of the world, with the timings of the ticks since
+    /// the previous snapshot.
+    pub fn snapshot(&mut self) -> WorldSnapshot {
+        let mut instances: Vec<_> = self
+            .instances
+            .iter_mut()
+            .map(InstanceSnapshot::new)
+            .collect();
+        instances.sort_by_key(|instance| instance.id);
+        let timings = TickTimings {
+            overruns: self.overruns,
+            ..std::mem::take(&mut self.timings)
+        };
+        WorldSnapshot {
+            tick: self.ticks,
+            players_online: instances.iter().map(|i| i.players.len()).sum(),
+            instances,
+            timings,
+        }
+    }
+
+    pub fn instances(&self) -> &InstanceManager {
+        &self.instances
+    }
+
+    /// Returns the position of an entity of the world, if it exists.
+    pub fn position(&self, guid: Guid) -> Option<Position> {
+        let instance = self.instances.get(self.instances.instance_of(guid)?)?;
+        let entity = instance.entity(guid)?;
+        instance.world().get::<Position>(entity).copied()
+    }
+
+    /// Ticks forever.
+    pub async fn run(mut self) {
+        let mut interval = tokio::time::interval(self.tick_duration);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        loop {
+            interval.tick().await;
+            let start = Instant::now();
+            self.tick(self.tick_duration);
+            let elapsed = start.elapsed();
+            metrics().observe_tick(elapsed);
+            if elapsed > self.tick_duration {
+                self.overruns += 1;
+                metrics().tick_overrun();
+                tracing::warn!("Tick took {elapsed:?}, more than {:?}", self.tick_duration);
+            }
+            self.timings.record(elapsed);
+            self.publish_snapshot();
+        }
+    }
+
+    /// Publishes a snapshot every [`SNAPSHOT_INTERVAL`], unless nobody
+    /// watches them.
+    fn publish_snapshot(&mut self) {
+        self.since_snapshot += self.tick_duration;
+        if self.since_snapshot < SNAPSHOT_INTERVAL || self.snapshots.receiver_count() == 0 {
+            return;
+        }
+        self.since_snapshot = Duration::ZERO;
+        let snapshot = self.snapshot();
+        match serde_json::to_string(&snapshot) {
+            Ok(json) => {
+                self.snapshots.send_replace(json);
+            }
+            Err(error) => tracing::error!("Failed to serialize a world snapshot: {error}"),
+        }
+    }
+
+    /// Runs a tick, advancing the entities by `elapsed`.
+    pub fn tick(&mut self, elapsed: Duration) {
+        self.ticks += 1;
+        if let Some(recorder) = self.server.recorder() {
+            recorder.tick(elapsed);
+        }
+        self.respawn_if_reloaded();
+        self.rebind_if_reloaded();
+        for event in self.server.take_events() {
+            self.apply(event);
+        }
+        if let Some(scripts) = &self.scripts {
+            for instance in self.instances.iter_mut() {
+                scripts.run_creatures(instance.world_mut(), elapsed);
+            }
+        }
+        self.instances.tick(elapsed);
+        for event in self.instances.take_events() {
+            self.server.queue(event);
+        }
+        self.expire_mail(elapsed);
+        self.match_orders(elapsed);
+    }
+
+    /// Deletes the expired mail every [`MAIL_EXPIRY_INTERVAL`], in a task so
+    /// that the database doesn't hold the tick up.
+    fn expire_mail(&mut self, elapsed: Duration) {
+        self.since_mail_expiry += elapsed;
+        if self.since_mail_expiry < MAIL_EXPIRY_INTERVAL || self.server.database().is_none() {
+            return;
+        }
+        self.since_mail_expiry = Duration::ZERO;
+        let server = self.server.clone();
+        self.spawn(async move { server.expire_mail().await });
+    }
+
+    /// Matches the commodity orders every [`ORDER_MATCH_INTERVAL`], in a
+    /// task as well.
+    fn match_orders(&mut self, elapsed: Duration) {
+        self.since_order_match += elapsed;
+        if self.since_order_match < ORDER_MATCH_INTERVAL || self.server.exchange().is_none() {
+            return;
+        }
+        self.since_order_match = Duration::ZERO;
+        let server = self.server.clone();
+        self.spawn(async move { server.match_orders().await });
+    }
+
+    fn spawn(&mut self, task: impl Future<Output = ()> + Send + 'static) {
+        let task = tokio::spawn(task);
+        if self.deterministic {
+            self.background.push(task);
+        }
+    }
+
+    /// Waits for the tasks spawned by the ticks so far, in deterministic mode.
+    pub async fn settle(&mut self) {
+        for task in std::mem::take(&mut self.background) {
+            if let Err(error) = task.await {
+                tracing::error!("A task of the world loop failed: {error}");
+            }
+        }
+    }
+
+    /// Spawns the creatures of the spawn points again in every instance when
+    /// the tables are reloaded.
+    fn respawn_if_reloaded(&mut self) {
+        let tables = self.server.data().tables();
+        if self
+            .tables
+            .as_ref()
+            .is_some_and(|spawned| Arc::ptr_eq(spawned, &tables))
+        {
+            return;
+        }
+        for instance in self.instances.iter_mut() {
+            for guid in std::mem::take(&mut instance.spawned) {
+                if let Some(entity) = instance.entity(guid) {
+                    instance.world_mut().entity_mut(entity).insert(ecs::Despawn);
+                }
+            }
+            spawn_points(instance, &tables, self.server.guids());
+        }
+        self.tables = Some(tables);
+    }
+
+    /// Takes the scripts when they are reloaded, and has every creature run
+    /// the `on_spawn` handler of its new script.
+    fn rebind_if_reloaded(&mut self) {
+        let scripts = self.server.scripts().scripts();
+        if self
+            .scripts
+            .as_ref()
+            .is_some_and(|bound| Arc::ptr_eq(bound, &scripts))
+        {
+            return;
+        }
+        for instance in self.instances.iter_mut() {
+            let world = instance.world_mut();
+            let scripted: Vec<_> = world
+                .query_filtered::<Entity, With<ScriptTimer>>()
+                .iter(world)
+                .collect();
+            for entity in scripted {
+                world.entity_mut(entity).remove::<ScriptTimer>();
+            }
+        }
+        self.scripts = Some(scripts);
+    }
+
+    /// Returns the instance of a world that an entity goes to: the one of its
+    /// group or its own for the dungeons, the shared one otherwise.
+    fn key(&self, guid: Guid, world_id: u32) -> InstanceKey {
+        if !self.server.data().tables().dungeons.contains(&world_id) {
+            return InstanceKey::Shared { world_id };
+        }
+        match self.server.group_id(guid) {
+            Some(group_id) => InstanceKey::Group { world_id, group_id },
+            None => InstanceKey::Solo { world_id, guid },
+        }
+    }
+
+    /// Returns the running instance of a key, or starts it with the
+    /// creatures of the spawn points of its world.
+    fn join(&mut self, key: InstanceKey) -> &mut Instance {
+        let id = match self.instances.route(key) {
+            Some(id) => id,
+            None => {
+                let tables = self.server.data().tables();
+                let instance = self.instances.start(key);
+                spawn_points(instance, &tables, self.server.guids());
+                instance.id()
+            }
+        };
+        self.instances
+            .get_mut(id)
+            .expect("routes lead to running instances")
+    }
+
+    /// Returns the instance of an entity, and the entity in it.
+    fn find(&mut self, guid: Guid) -> Option<(&mut Instance, Entity)> {
+        let instance = self.instances.get_mut(self.instances.instance_of(guid)?)?;
+        let entity = instance.entity(guid)?;
+        Some((instance, entity))
+    }
+
+    fn apply(&mut self, event: WorldEvent) {
+        match event {
+            WorldEvent::Enter {
+                session,
+                guid,
+                world_id,
+                position,
+                yaw,
+            } => {
+                let key = self.key(guid, world_id);
+                let world = self.join(key).world_mut();
+                let entity = spawn(world, guid, world_id, position, yaw);
+                world.entity_mut(entity).insert(Viewer::new(session));
+            }
+            WorldEvent::Spawn {
+                guid,
+                world_id,
+                position,
+                yaw,
+                near,
+            } => {
+                let key = match near {
+                    Some(near) => self.key(near, world_id),
+                    None => InstanceKey::Shared { world_id },
+                };
+                spawn(self.join(key).world_mut(), guid, world_id, position, yaw);
+            }
+            WorldEvent::Teleport {
+                guid,
+                world_id,
+                position,
+            } => self.teleport(guid, world_id, position),
+            WorldEvent::Arrive(traveler) => self.arrive(*traveler),
+            WorldEvent::MoveTo {
+                guid,
+                destination,
+                speed,
+            } => {
+                if let Some((instance, entity)) = self.find(guid) {
+                    instance
+                        .world_mut()
+                        .entity_mut(entity)
+                        .insert(Movement { destination, speed });
+                }
+            }
+            WorldEvent::Say { guid, chat } => {
+                if let Some((instance, _)) = self.find(guid) {
+                    ecs::broadcast(instance.world(), guid, &chat);
+                }
+            }
+            WorldEvent::Remove { guid } => {
+                if let Some((instance, entity)) = self.find(guid) {
+                    instance.world_mut().entity_mut(entity).insert(ecs::Despawn);
+                }
+            }
+            WorldEvent::CastSpell {
+                guid,
+                spell_id,
+                target,
+            } => {
+                let tables = self.server.data().tables();
+                let instance = self
+                    .instances
+                    .instance_of(guid)
+                    .and_then(|id| self.instances.get_mut(id));
+                let world = match instance {
+                    Some(instance) => instance.world_mut(),
+                    None => return,
+                };
+                let result = self.spells.cast(world, &tables, guid, spell_id, target);
+                let entity = match world.resource::<GuidIndex>().0.get(&guid) {
+                    Some(entity) => *entity,
+                    None => return,
+                };
+                match result {
+                    Ok(_) if !target.is_none() && target != guid => {
+                        world.entity_mut(entity).insert(ecs::Target(target));
+                    }
+                    Ok(_) => {}
+                    Err(result) => {
+                        if let Some(viewer) = world.get::<Viewer>(entity) {
+                            // sessions that closed are removed when disconnecting
+                            let _ = viewer
+                                .session
+                                .send(&ServerSpellCastFailed { spell_id, result });
+                        }
+                    }
+                }
+            }
+            WorldEvent::SetGroup { guid, group_id } => {
+                if let Some((instance, entity)) = self.find(guid) {
+                    let mut entity = instance.world_mut().entity_mut(entity);
+                    match group_id {
+                        Some(group_id) => entity.insert(ecs::Grouped(group_id)),
+                        None => entity.remove::<ecs::Grouped>(),
+                    };
+                }
+            }
+        }
+    }
+
+    /// Moves an entity in its instance, or has it leave for the instance of
+    /// another world, which it joins at the next tick.
+    fn teleport(&mut self, guid: Guid, world_id: u32, position: Position) {
+        let key = self.key(guid, world_id);
+        let (instance, entity) = match self.find(guid) {
+            Some(found) => found,
+            None => return,
+        };
+        let same_instance = instance.key() == key;
+        let mut entity = instance.world_mut().entity_mut(entity);
+        if same_instance {
+            entity.remove::<Movement>();
+            entity.insert((position, Visibility { world_id }));
+        } else {
+            entity.insert(ecs::Leaving {
+                key,
+                world_id,
+                position,
+            });
+        }
+    }
+
+    /// Adds an entity that left its instance to the one it was going to,
+    /// with what it kept.
+    fn arrive(&mut self, traveler: ecs::Traveler) {
+        let world = self.join(traveler.key).world_mut();
+        let entity = spawn(
+            world,
+            traveler.guid,
+            traveler.world_id,
+            traveler.position,
+            traveler.yaw,
+        );
+        let mut entity = world.entity_mut(entity);
+        if let Some(stats) = traveler.stats {
+            entity.insert(stats);
+        }
+        if let Some(cooldowns) = traveler.cooldowns {
+            entity.insert(cooldowns);
+        }
+        if let Some(group) = traveler.group {
+            entity.insert(group);
+        }
+        if let Some(session) = traveler.session {
+            entity.insert(Viewer::new(session));
+        }
+    }
+}
+
+fn spawn(world: &mut World, guid: Guid, world_id: u32, position: Position, yaw: f32) -> Entity {
+    let entity = world
+        .spawn((
+            EntityGuid(guid),
+            position,
+            Yaw(yaw),
+            Stats::with_health(BASE_HEALTH, BASE_REGENERATION),
+            Visibility { world_id },
+        ))
+        .id();
+    world.resource_mut::<GuidIndex>().0.insert(guid, entity);
+    entity
+}
+
+/// Spawns the creatures of the spawn points of the world of an instance.
+fn spawn_points(instance: &mut Instance, tables: &DataTables, guids: &GuidAllocator) {
+    let world_id = instance.world_id();
+    for spawn_point in tables.spawns_in(world_id) {
+        let guid = guids.allocate(EntityType::Creature);
+        let position = tables.on_ground(world_id, spawn_point.position());
+        let world = instance.world_mut();
+        let entity = spawn(world, guid, world_id, position, spawn_point.yaw);
+        world
+            .entity_mut(entity)
+            .insert(Creature(spawn_point.creature_id));
+        instance.spawned.push(guid);
+    }
+}
+
+impl fmt::Debug for WorldLoop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WorldLoop")
+            .field("tick_duration", &self.tick_duration)
+            .field("instances", &self.instances.len())
+            .field("overruns", &self.overruns)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ws_messages::Message;
+
+    use super::*;
+
+    #[test]
+    fn test_instances() {
+        let dir = std::env::temp_dir().join(format!("ws_world_instances_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("spawns.json"),
+            r#"[{ "creature_id": 1, "world_id": 870, "x": 0, "y": 0, "z": 0 }]"#,
+        )
+        .unwrap();
+        std::fs::write(dir.join("dungeons.json"), r#"[{ "world_id": 1000 }]"#).unwrap();
+        let data = DataStore::load(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        let server = Arc::new(WorldServer::new().with_data(data));
+        let instances = InstanceManager::new().with_idle_timeout(Duration::from_secs(5));
+        let mut world = WorldLoop::new(server.clone()).with_instances(instances);
+
+        let spawn = |world_id, near| {
+            let guid = server.guids().allocate(EntityType::Creature);
+            server.queue(WorldEvent::Spawn {
+                guid,
+                world_id,
+                position: Position::default(),
+                yaw: 0.0,
+                near,
+            });
+            guid
+        };
+        // the open world instance starts with the creatures of its spawn
+        // points
+        let walker = spawn(870, None);
+        world.tick(Duration::ZERO);
+        assert_eq!(world.instances().len(), 1);
+        let open_world = world.instances().instance_of(walker).unwrap();
+        let instance = world.instances().get(open_world).unwrap();
+        assert_eq!(instance.key(), InstanceKey::Shared { world_id: 870 });
+        assert_eq!(instance.world().entities().len(), 2);
+
+        // entities without a group get their own dungeon instance
+        let destination = Position {
+            x: 5.0,
+            y: 0.0,
+            z: 5.0,
+        };
+        server.queue(WorldEvent::Teleport {
+            guid: walker,
+            world_id: 1000,
+            position: destination,
+        });
+        world.tick(Duration::ZERO);
+        // the walker left the open world, and joins the dungeon at the next
+        // tick
+        assert_eq!(world.position(walker), None);
+        let instance = world.instances().get(open_world).unwrap();
+        assert_eq!(instance.world().entities().len(), 1);
+        world.tick(Duration::ZERO);
+        assert_eq!(world.position(walker), Some(destination));
+        let follower = spawn(1000, Some(walker));
+        let stranger = spawn(1000, None);
+        world.tick(Duration::ZERO);
+        let dungeon = world.instances().instance_of(walker).unwrap();
+        assert_eq!(
+            world.instances().get(dungeon).unwrap().key(),
+            InstanceKey::Solo {
+                world_id: 1000,
+                guid: walker
+            }
+        );
+        assert_eq!(world.instances().instance_of(follower), Some(dungeon));
+        let shared = world.instances().instance_of(stranger).unwrap();
+        assert_ne!(shared, dungeon);
+
+        // instances without players shut down once idle
+        world.tick(Duration::from_secs(5));
+        assert!(world.instances().is_empty());
+        assert_eq!(world.position(walker), None);
+    }
+
+    #[test]
+    fn test_scripts_reload() {
+        let dir =
+            std::env::temp_dir().join(format!("ws_world_loop_scripts_{}", std::process::id()));
+        let creatures = dir.join("scripts").join("creatures");
+        std::fs::create_dir_all(&creatures).unwrap();
+        std::fs::write(
+            dir.join("spawns.json"),
+            r#"[{ "creature_id": 1, "world_id": 870, "x": 0, "y": 0, "z": 0 }]"#,
+        )
+        .unwrap();
+        std::fs::write(
+            creatures.join("1.rhai"),
+            "fn on_spawn() { this.move_to(10.0, 0.0, 0.0, 10.0); }",
+        )
+        .unwrap();
+        let data = DataStore::load(&dir).unwrap();
+        let scripts = ScriptStore::load(dir.join("scripts")).unwrap();
+        let server = Arc::new(WorldServer::new().with_data(data).with_scripts(scripts));
+        let mut world = WorldLoop::new(server.clone());
+
+        // the creature of the spawn point runs its spawn handler as the
+        // instance starts
+        server.queue(WorldEvent::Spawn {
+            guid: server.guids().allocate(EntityType::Creature),
+            world_id: 870,
+            position: Position::default(),
+            yaw: 0.0,
+            near: None,
+        });
+        world.tick(Duration::ZERO);
+        let id = world.instances().iter().next().unwrap().id();
+        let creature = world.instances().get(id).unwrap().spawned[0];
+        world.tick(Duration::from_secs(1));
+        let position = |x| Some(Position { x, y: 0.0, z: 0.0 });
+        assert_eq!(world.position(creature), position(10.0));
+
+        // the reloaded handlers take over the creature at the next tick
+        std::fs::write(
+            creatures.join("1.rhai"),
+            "fn on_update() { this.move_to(this.x - 5.0, 0.0, 0.0, 5.0); }",
+        )
+        .unwrap();
+        assert!(server.scripts().changed());
+        server.scripts().reload().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        world.tick(Duration::ZERO);
+        world.tick(Duration::from_secs(1));
+        assert_eq!(world.position(creature), position(5.0));
+        // and run every second
+        world.tick(Duration::from_millis(500));
+        assert_eq!(world.position(creature), position(5.0));
+        world.tick(Duration::from_millis(500));
+        assert_eq!(world.position(creature), position(2.5));
+    }
+
+    #[test]
+    fn test_entities_walk_to_their_destination() {
+        let server = Arc::new(WorldServer::new());
+        let mut world = WorldLoop::new(server.clone());
+        assert_eq!(world.tick_duration(), Duration::from_secs(1) / 30);
+
+        let guid = server.guids().allocate(EntityType::Creature);
+        let start = Position::default();
+        let destination = Position {
+            x: 3.0,
+            y: 0.0,
+            z: 4.0,
+        };
+        server.queue(WorldEvent::Spawn {
+            guid,
+            world_id: 870,
+            position: start,
+            yaw: 0.0,
+            near: None,
+        });
+        server.queue(WorldEvent::MoveTo {
+            guid,
+            destination,
+            speed: 2.5,
+        });
+        world.tick(Duration::from_secs(1));
+        assert_eq!(
+            world.position(guid),
+            Some(Position {
+                x: 1.5,
+                y: 0.0,
+                z: 2.0
+            })
+        );
+        // the last step stops at the destination
+        world.tick(Duration::from_secs(1));
+        assert_eq!(world.position(guid), Some(destination));
+        world.tick(Duration::from_secs(1));
+        assert_eq!(world.position(guid), Some(destination));
+
+        server.queue(WorldEvent::Remove { guid });
+        world.tick(Duration::from_secs(1));
+        assert_eq!(world.position(guid), None);
+        assert_eq!(world.overruns(), 0);
+    }
+
+    #[test]
+    fn test_interest() {
+        let server = Arc::new(WorldServer::new());
+        let mut world = WorldLoop::new(server.clone());
+        let at = |x| Position { x, y: 0.0, z: 0.0 };
+        let enter = |x| {
+            let guid = server.guids().allocate(EntityType::Player);
+            let addr = "127.0.0.1:1".parse().unwrap();
+            let (session, frames) = Session::detached(guid.raw(), addr);
+            server.queue(WorldEvent::Enter {
+                session,
+                guid,
+                world_id: 870,
+                position: at(x),
+                yaw: 0.0,
+            });
+            (guid, frames)
+        };
+        let spawn = |x| {
+            let guid = server.guids().allocate(EntityType::Creature);
+            server.queue(WorldEvent::Spawn {
+                guid,
+                world_id: 870,
+                position: at(x),
+                yaw: 0.0,
+                near: None,
+            });
+            guid
+        };
+        let (player, mut frames) = enter(0.0);
+        let (member, _) = enter(150.0);
+        let near = spawn(10.0);
+        let medium = spawn(60.0);
+        let far = spawn(150.0);
+        for guid in [player, member] {
+            server.queue(WorldEvent::SetGroup {
+                guid,
+                group_id: Some(1),
+            });
+        }
+        world.tick(Duration::ZERO);
+
+        for step in 1..=30 {
+            for (guid, x) in [(near, 10.0), (medium, 60.0), (far, 150.0), (member, 150.0)] {
+                server.queue(WorldEvent::Teleport {
+                    guid,
+                    world_id: 870,
+                    position: at(x + step as f32),
+                });
+            }
+            world.tick(Duration::ZERO);
+        }
+
+        let mut moves = HashMap::new();
+        let mut decoder = ws_protocol::FrameDecoder::new();
+        while let Ok(frame) = frames.try_recv() {
+            decoder.extend(&frame);
+        }
+        while let Some(frame) = decoder.next_frame().unwrap() {
+            if frame.opcode as u32 != ServerWorldUpdate::id() {
+                continue;
+            }
+            let update: ServerWorldUpdate = frame.reader().read().unwrap();
+            for entity_move in update.moves {
+                *moves.entry(entity_move.guid).or_insert(0) += 1;
+            }
+        }
+        // the group members are sent every tick however far they are
+        assert_eq!(moves[&near], 30);
+        assert_eq!(moves[&member], 30);
+        assert_eq!(moves[&medium], 10);
+        assert_eq!(moves[&far], 3);
+    }
+
+    #[test]
+    fn test_local_chat() {
+        let server = Arc::new(WorldServer::new());
+        let mut world = WorldLoop::new(server.clone());
+        let enter = |x| {
+            let guid = server.guids().allocate(EntityType::Player);
+            let addr = "127.0.0.1:1".parse().unwrap();
+            let (session, frames) = Session::detached(guid.raw(), addr);
+            server.queue(WorldEvent::Enter {
+                session,
+                guid,
+                world_id: 870,
+                position: Position { x, y: 0.0, z: 0.0 },
+                yaw: 0.0,
+            });
+            (guid, frames)
+        };
+        let (speaker, mut speaker_frames) = enter(0.0);
+        let (_, mut near_frames) = enter(10.0);
+        let (_, mut far_frames) = enter(1000.0);
+        world.tick(Duration::ZERO);
+
+        let chat = ServerChat {
+            channel: ChatChannel::Say,
+            sender: "Deadeye".to_string(),
+            message: "Hello there".to_string(),
+        };
+        server.queue(WorldEvent::Say {
+            guid: speaker,
+            chat: chat.clone(),
+        });
+        world.tick(Duration::ZERO);
+
+        let heard = |frames: &mut tokio::sync::mpsc::UnboundedReceiver<Vec<u8>>| {
+            let mut decoder = ws_protocol::FrameDecoder::new();
+            while let Ok(frame) = frames.try_recv() {
+                decoder.extend(&frame);
+            }
+            let mut lines = vec![];
+            while let Some(frame) = decoder.next_frame().unwrap() {
+                if frame.opcode as u32 == ServerChat::id() {
+                    lines.push(frame.reader().read::<ServerChat>().unwrap());
+                }
+            }
+            lines
+        };
+        assert_eq!(heard(&mut speaker_frames), std::slice::from_ref(&chat));
+        assert_eq!(heard(&mut near_frames), [chat]);
+        assert!(heard(&mut far_frames).is_empty());
+    }
+}
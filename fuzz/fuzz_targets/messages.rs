@@ -0,0 +1,48 @@
+//! Decodes arbitrary bytes as the body of each registered message.
+//!
+//! The first two bytes pick the message, the rest is its body. Decoding may
+//! fail but must never panic or allocate more than the input can describe.
+
+#![no_main]
+
+// messages are registered by the crates that define them, so they have to be
+// linked even though nothing is used from them directly
+extern crate ws_realm;
+extern crate ws_world;
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+use ws_bitpack::BitPackReader;
+use ws_fuzz::LimitedAllocator;
+use ws_messages::{MessageRegistration, MessageRegistry};
+
+#[global_allocator]
+static ALLOCATOR: LimitedAllocator = LimitedAllocator;
+
+fn registrations() -> &'static [MessageRegistration] {
+    static REGISTRATIONS: OnceLock<Vec<MessageRegistration>> = OnceLock::new();
+    REGISTRATIONS.get_or_init(|| {
+        let mut registrations = MessageRegistry::with_registered()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        registrations.sort_by_key(|registration| registration.id);
+        registrations
+    })
+}
+
+fuzz_target!(|data: &[u8]| {
+    let (selector, body) = match data {
+        [a, b, body @ ..] => (u16::from_le_bytes([*a, *b]) as usize, body),
+        _ => return,
+    };
+    let registrations = registrations();
+    let registration = &registrations[selector % registrations.len()];
+
+    let mut reader = BitPackReader::new(body);
+    if let Ok(message) = (registration.read)(&mut reader) {
+        // formatting goes through every decoded field
+        let _ = format!("{:?}", registration.debug_message(&*message));
+    }
+});
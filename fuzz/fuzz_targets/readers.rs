@@ -0,0 +1,50 @@
+//! Goes through the string, array and map readers with arbitrary bytes.
+//!
+//! The lengths read from the data are used as is, so any reader that trusts
+//! them before checking the remaining bits shows up as a crash.
+
+#![no_main]
+
+use std::collections::HashMap;
+
+use libfuzzer_sys::fuzz_target;
+use ws_bitpack::{AsciiString, BitPackReader};
+use ws_fuzz::LimitedAllocator;
+
+#[global_allocator]
+static ALLOCATOR: LimitedAllocator = LimitedAllocator;
+
+fuzz_target!(|data: &[u8]| {
+    let mut reader = BitPackReader::new(data);
+    while reader.remaining_bits() > 0 {
+        let result = match reader.read_u64(3) {
+            Ok(0) => reader.read::<String>().map(drop),
+            Ok(1) => reader.read::<AsciiString>().map(drop),
+            Ok(2) => read_length(&mut reader)
+                .and_then(|length| reader.read_array::<Vec<u32>>(length))
+                .map(drop),
+            Ok(3) => read_length(&mut reader)
+                .and_then(|length| reader.read_packed_array::<Vec<u16>>(length, 5))
+                .map(drop),
+            Ok(4) => read_length(&mut reader)
+                .and_then(|length| reader.read_array::<Vec<String>>(length))
+                .map(drop),
+            Ok(5) => read_length(&mut reader)
+                .and_then(|length| reader.read_array::<HashMap<u8, u16>>(length))
+                .map(drop),
+            Ok(6) => read_length(&mut reader)
+                .and_then(|length| reader.read_byte_vec(length))
+                .map(drop),
+            Ok(_) => reader.read_blob(16, usize::MAX).map(drop),
+            Err(_) => return,
+        };
+        if result.is_err() {
+            return;
+        }
+    }
+});
+
+/// Reads a length the way a hostile count field would give it.
+fn read_length(reader: &mut BitPackReader) -> ws_bitpack::BitPackResult<usize> {
+    reader.read_u64(64).map(|length| length as usize)
+}
@@ -0,0 +1,49 @@
+//! Helpers shared by the fuzz targets.
+//!
+//! Run a target with `cargo +nightly fuzz run <target>` from the repository
+//! root. The targets are `messages`, which decodes every registered message,
+//! and `readers`, which goes through the string, array and map readers.
+//!
+//! `cargo run -p ws_tools -- corpus fuzz/corpus/messages` seeds the corpus of
+//! `messages` with a valid body for each message, generated from its schema.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+
+/// The largest allocation a single read may make. Inputs are a few kilobytes
+/// at most, so anything above this comes from trusting a length read from the
+/// data.
+pub const MAX_ALLOCATION: usize = 16 * 1024 * 1024;
+
+/// An allocator that panics on allocations larger than [`MAX_ALLOCATION`], so
+/// that the fuzzer reports unbounded allocations as crashes instead of running
+/// out of memory.
+pub struct LimitedAllocator;
+
+unsafe impl GlobalAlloc for LimitedAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        check_size(layout.size());
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        check_size(layout.size());
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        check_size(new_size);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+fn check_size(size: usize) {
+    if size > MAX_ALLOCATION {
+        // aborting rather than panicking, a panic would allocate its message
+        eprintln!("Unbounded allocation of {size} bytes");
+        std::process::abort();
+    }
+}